@@ -0,0 +1,15 @@
+-- Per-user token usage for model calls
+CREATE TABLE IF NOT EXISTS usage (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    model TEXT NOT NULL,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    total_tokens BIGINT NOT NULL DEFAULT 0,
+    estimated BOOLEAN NOT NULL DEFAULT FALSE,  -- True when counts were estimated from streamed content
+    created_at BIGINT NOT NULL  -- Unix timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_user_created_at ON usage(user_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_usage_created_at ON usage(created_at);
+CREATE INDEX IF NOT EXISTS idx_usage_model ON usage(model);
@@ -0,0 +1,2 @@
+-- Keyset pagination of a user's chats orders by (created_at, id)
+CREATE INDEX IF NOT EXISTS idx_chat_user_created_at_id ON chat(user_id, created_at DESC, id DESC);
@@ -0,0 +1,8 @@
+-- Short-lived auth flow state (e.g. OAuth login state) when SESSION_STORE=db
+CREATE TABLE IF NOT EXISTS session_store (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    expires_at BIGINT NOT NULL  -- Unix timestamp
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_store_expires_at ON session_store(expires_at);
@@ -0,0 +1,17 @@
+-- Record of who did what to which resource
+CREATE TABLE IF NOT EXISTS audit_log (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    action TEXT NOT NULL,  -- Dotted event name, e.g. knowledge.created
+    target_type TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    data JSONB,
+    created_at BIGINT NOT NULL  -- Unix timestamp
+);
+
+-- Listing pages by (created_at, id); the other filters narrow within that order
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at_id ON audit_log(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_created_at ON audit_log(actor_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_target ON audit_log(target_type, target_id);
+-- text_pattern_ops lets prefix globs such as knowledge.* use the index
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log(action text_pattern_ops);
@@ -0,0 +1,2 @@
+-- Retention purges scan chats by last activity
+CREATE INDEX IF NOT EXISTS idx_chat_updated_at ON chat(updated_at);
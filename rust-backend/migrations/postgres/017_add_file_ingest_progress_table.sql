@@ -0,0 +1,15 @@
+-- How far embedding one file into one knowledge base got, so an interrupted ingest
+-- resumes from the last completed chunk
+CREATE TABLE IF NOT EXISTS file_ingest_progress (
+    file_id TEXT NOT NULL,
+    knowledge_id TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,  -- Content hash and embedding config the chunks came from
+    chunks_total BIGINT NOT NULL,
+    chunks_done BIGINT NOT NULL,
+    status TEXT NOT NULL,  -- running, completed, failed or interrupted
+    error TEXT,
+    updated_at BIGINT NOT NULL,  -- Unix timestamp
+    PRIMARY KEY (file_id, knowledge_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_file_ingest_progress_status ON file_ingest_progress(status);
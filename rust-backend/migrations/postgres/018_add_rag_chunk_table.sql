@@ -0,0 +1,12 @@
+-- Chunk text kept alongside the vectors, so citations don't depend on the vector DB
+-- returning payload text
+CREATE TABLE IF NOT EXISTS rag_chunk (
+    collection TEXT NOT NULL,  -- Knowledge base (base collection) the chunk was indexed into
+    file_id TEXT NOT NULL,
+    chunk_index BIGINT NOT NULL,
+    text TEXT NOT NULL,
+    created_at BIGINT NOT NULL,  -- Unix timestamp
+    PRIMARY KEY (collection, file_id, chunk_index)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rag_chunk_file_id ON rag_chunk(file_id);
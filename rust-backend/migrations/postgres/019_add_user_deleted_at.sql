@@ -0,0 +1,2 @@
+-- Accounts deleted by their owner are kept, anonymized, with the time of deletion
+ALTER TABLE "user" ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
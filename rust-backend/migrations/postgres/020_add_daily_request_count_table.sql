@@ -0,0 +1,8 @@
+-- Chat requests per user per UTC day, counted against DAILY_REQUEST_LIMIT quotas
+CREATE TABLE IF NOT EXISTS daily_request_count (
+    user_id TEXT NOT NULL,
+    counter TEXT NOT NULL,  -- "all", or "model:<id>" for a per-model limit
+    day BIGINT NOT NULL,  -- Unix timestamp of the UTC midnight starting the day
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, counter, day)
+);
@@ -0,0 +1,4 @@
+-- Concurrent OAuth callbacks for one identity insert with ON CONFLICT (oauth_sub), which
+-- needs a unique index. Named like the constraint from 001, so this only adds it to
+-- databases created without one
+CREATE UNIQUE INDEX IF NOT EXISTS user_oauth_sub_key ON "user"(oauth_sub);
@@ -0,0 +1,10 @@
+-- TOTP multi-factor sign-in, one row per user who started setting it up
+CREATE TABLE IF NOT EXISTS user_mfa (
+    user_id TEXT PRIMARY KEY,
+    secret TEXT NOT NULL,  -- Fernet-encrypted base32 TOTP secret
+    enabled BOOLEAN NOT NULL DEFAULT FALSE,  -- Set once a code from the secret is confirmed
+    recovery_codes TEXT NOT NULL DEFAULT '[]',  -- JSON array of SHA-256 hashes of unused codes
+    last_used_step BIGINT,  -- Time step of the last accepted code, so codes can't be replayed
+    created_at BIGINT NOT NULL,  -- Unix timestamp
+    updated_at BIGINT NOT NULL  -- Unix timestamp
+);
@@ -4,6 +4,7 @@
 //! the application for consistent caching behavior.
 
 use crate::utils::cache::*;
+use crate::utils::redis_guard::RedisGuard;
 use deadpool_redis::Pool as RedisPool;
 use once_cell::sync::OnceCell;
 use std::sync::Arc;
@@ -32,7 +33,12 @@ pub struct CacheManager {
 
 impl CacheManager {
     /// Initialize the cache manager with optional Redis support
-    pub fn init(redis_pool: Option<RedisPool>) -> &'static CacheManager {
+    ///
+    /// `redis_guard` is shared with the other Redis users so they degrade together.
+    pub fn init(
+        redis_pool: Option<RedisPool>,
+        redis_guard: Arc<RedisGuard>,
+    ) -> &'static CacheManager {
         CACHE_MANAGER.get_or_init(|| {
             // App cache configuration
             let app_config = CacheConfig {
@@ -67,10 +73,18 @@ impl CacheManager {
             };
 
             CacheManager {
-                app_cache: Arc::new(MultiTierCache::new(app_config, redis_pool.clone())),
+                app_cache: Arc::new(MultiTierCache::new(
+                    app_config,
+                    redis_pool.clone(),
+                    redis_guard.clone(),
+                )),
                 session_cache: Arc::new(MemoryCache::new(session_config)),
-                model_cache: Arc::new(MultiTierCache::new(model_config, redis_pool.clone())),
-                api_cache: Arc::new(MultiTierCache::new(api_config, redis_pool)),
+                model_cache: Arc::new(MultiTierCache::new(
+                    model_config,
+                    redis_pool.clone(),
+                    redis_guard.clone(),
+                )),
+                api_cache: Arc::new(MultiTierCache::new(api_config, redis_pool, redis_guard)),
                 stampede_guard: Arc::new(StampedeGuard::new()),
             }
         })
@@ -83,7 +97,7 @@ impl CacheManager {
 
     /// Get or create the global cache manager (uses memory-only if not initialized)
     pub fn get_or_init() -> &'static CacheManager {
-        Self::init(None)
+        Self::init(None, Arc::new(RedisGuard::default()))
     }
 
     /// Start background cleanup tasks
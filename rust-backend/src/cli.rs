@@ -0,0 +1,137 @@
+/// Command-line interface: maintenance subcommands that run and exit
+use clap::{Parser, Subcommand};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::services::{oauth_session::OAuthSessionService, user::UserService, AuthService};
+
+#[derive(Debug, Parser)]
+#[command(name = "open-webui-rust", version, about = "Open WebUI Rust backend")]
+pub struct Cli {
+    /// Starts the server when omitted
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Apply pending database migrations
+    Migrate,
+    /// Create an admin account
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        /// Prefer the env var so the password stays out of the process list
+        #[arg(long, env = "ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+        /// Defaults to the local part of the email
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Delete expired OAuth sessions
+    CleanupSessions,
+    /// Re-encrypt OAuth sessions under the primary OAUTH_SESSION_TOKEN_ENCRYPTION_KEY
+    RotateKey {
+        #[arg(long, default_value_t = 500, value_parser = clap::value_parser!(i64).range(1..))]
+        batch_size: i64,
+    },
+}
+
+/// Run a maintenance subcommand against a migrated database
+pub async fn run(command: Command, db: &Database, config: &Config) -> anyhow::Result<()> {
+    match command {
+        // Migrations already ran during startup
+        Command::Migrate => info!("Database is up to date"),
+        Command::CreateAdmin {
+            email,
+            password,
+            name,
+        } => {
+            let email = email.trim().to_lowercase();
+            let user_service = UserService::new(db);
+            if user_service.get_user_by_email(&email).await?.is_some() {
+                anyhow::bail!("User {} already exists", email);
+            }
+
+            let user_id = uuid::Uuid::new_v4().to_string();
+            let name =
+                name.unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_string());
+            user_service
+                .create_user(&user_id, &name, &email, "admin", "/user.png")
+                .await?;
+            AuthService::new(db)
+                .create_auth(&user_id, &email, &password)
+                .await?;
+            info!("Created admin user {}", email);
+        }
+        Command::CleanupSessions => {
+            let deleted = oauth_sessions(db, config)?
+                .cleanup_expired_sessions()
+                .await?;
+            info!("Deleted {} expired OAuth sessions", deleted);
+        }
+        Command::RotateKey { batch_size } => {
+            let report = oauth_sessions(db, config)?
+                .rotate_encryption_key(batch_size)
+                .await?;
+            info!(
+                "Re-encrypted {} OAuth sessions ({} keys configured)",
+                report.migrated, report.key_count
+            );
+            if report.failed > 0 {
+                warn!(
+                    "{} sessions could not be decrypted with any configured key: {}",
+                    report.failed,
+                    report.failed_session_ids.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn oauth_sessions(db: &Database, config: &Config) -> anyhow::Result<OAuthSessionService> {
+    Ok(OAuthSessionService::new(
+        db.clone(),
+        &config.oauth_session_keys(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_subcommand_starts_server() {
+        let cli = Cli::try_parse_from(["open-webui-rust"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_parse_subcommands() {
+        let cli = Cli::try_parse_from([
+            "open-webui-rust",
+            "create-admin",
+            "--email",
+            "ops@example.com",
+            "--password",
+            "hunter22",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CreateAdmin { ref email, name: None, .. }) if email == "ops@example.com"
+        ));
+
+        let cli = Cli::try_parse_from(["open-webui-rust", "rotate-key"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::RotateKey { batch_size: 500 })
+        ));
+        assert!(
+            Cli::try_parse_from(["open-webui-rust", "rotate-key", "--batch-size", "0"]).is_err()
+        );
+    }
+}
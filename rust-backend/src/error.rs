@@ -1,15 +1,17 @@
 use actix_web::{
     cookie::{Cookie, SameSite},
     http::{header, StatusCode},
-    HttpResponse, ResponseError,
+    HttpResponse, HttpResponseBuilder, ResponseError,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(sqlx::Error),
 
     #[error("Redis error: {0}")]
     Redis(String),
@@ -20,6 +22,9 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     Validation(String),
 
+    #[error("Validation error: {0}")]
+    ValidationError(FieldErrors),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
@@ -70,41 +75,174 @@ pub enum AppError {
 
     #[error("Too many requests: {0}")]
     TooManyRequests(String),
+
+    #[error("Request headers too large: {0}")]
+    HeadersTooLarge(String),
+
+    /// Throttled request the client may retry after `retry_after` seconds
+    #[error("Too many requests: {message}")]
+    RateLimited { message: String, retry_after: u64 },
+
+    #[error("Service unavailable: {message}")]
+    ServiceUnavailable { message: String, retry_after: u64 },
+
+    /// External service error fast-failed by an open upstream circuit
+    #[error("External service error: {message}")]
+    CircuitOpen { message: String, retry_after: u64 },
 }
 
+/// Media type of RFC 7807 error bodies
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
 }
 
-impl ResponseError for AppError {
-    fn error_response(&self) -> HttpResponse {
-        let (status, error_message) = match self {
-            AppError::Database(ref e) => {
-                tracing::error!("Database error: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Database error".to_string(),
-                )
+/// RFC 7807 error body, sent to clients that accept `application/problem+json`
+#[derive(Serialize)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub instance: String,
+    /// Extension member carrying per-field validation messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<FieldErrors>,
+}
+
+/// Validation messages keyed by field path (e.g. `email`, `items[0].name`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldErrors(pub BTreeMap<String, Vec<String>>);
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|(field, messages)| format!("{}: {}", field, messages.join(", ")))
+            .collect();
+        write!(f, "{}", parts.join("; "))
+    }
+}
+
+impl From<validator::ValidationErrors> for FieldErrors {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields = BTreeMap::new();
+        collect_field_errors(&errors, "", &mut fields);
+        FieldErrors(fields)
+    }
+}
+
+fn collect_field_errors(
+    errors: &validator::ValidationErrors,
+    prefix: &str,
+    fields: &mut BTreeMap<String, Vec<String>>,
+) {
+    use validator::ValidationErrorsKind;
+
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                fields
+                    .entry(path)
+                    .or_default()
+                    .extend(errors.iter().map(validation_message));
             }
-            AppError::Redis(ref e) => {
-                tracing::error!("Redis error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Redis error".to_string())
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(nested, &format!("{}[{}]", path, index), fields);
+                }
             }
+        }
+    }
+}
+
+/// Human-readable message for a single validator error
+fn validation_message(error: &validator::ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(|v| v.to_string());
+
+    match error.code.as_ref() {
+        "email" => "Invalid email address".to_string(),
+        "url" => "Invalid URL".to_string(),
+        "required" => "This field is required".to_string(),
+        "length" => match (param("min"), param("max"), param("equal")) {
+            (_, _, Some(equal)) => format!("Must be exactly {} characters", equal),
+            (Some(min), Some(max), _) => format!("Must be between {} and {} characters", min, max),
+            (Some(min), None, _) => format!("Must be at least {} characters", min),
+            (None, Some(max), _) => format!("Must be at most {} characters", max),
+            _ => "Invalid length".to_string(),
+        },
+        "range" => match (param("min"), param("max")) {
+            (Some(min), Some(max)) => format!("Must be between {} and {}", min, max),
+            (Some(min), None) => format!("Must be at least {}", min),
+            (None, Some(max)) => format!("Must be at most {}", max),
+            _ => "Out of range".to_string(),
+        },
+        code => format!("Invalid value ({})", code),
+    }
+}
+
+impl From<validator::ValidationErrors> for AppError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        AppError::ValidationError(errors.into())
+    }
+}
+
+impl AppError {
+    /// Log failures whose details the client doesn't see
+    pub(crate) fn log(&self) {
+        match self {
+            AppError::Database(ref e) => tracing::error!("Database error: {:?}", e),
+            AppError::Redis(ref e) => tracing::error!("Redis error: {:?}", e),
+            AppError::InternalServerError(ref e) => {
+                tracing::error!("Internal server error: {:?}", e)
+            }
+            AppError::Jwt(ref e) => tracing::error!("JWT error: {:?}", e),
+            AppError::Io(ref e) => tracing::error!("IO error: {:?}", e),
+            AppError::ExternalServiceError(ref e) => {
+                tracing::error!("External service error: {:?}", e)
+            }
+            AppError::Internal(ref e) => tracing::error!("Internal error: {:?}", e),
+            AppError::Http(ref e) => tracing::error!("HTTP error: {:?}", e),
+            AppError::RedisPool(ref e) => tracing::error!("Redis pool error: {:?}", e),
+            AppError::Timeout(ref e) => tracing::error!("Request timeout: {:?}", e),
+            _ => {}
+        }
+    }
+
+    /// Status and client-facing message
+    fn status_and_detail(&self) -> (StatusCode, String) {
+        match self {
+            AppError::Database(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Database error".to_string(),
+            ),
+            AppError::Redis(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Redis error".to_string()),
             AppError::Auth(ref e) => (StatusCode::UNAUTHORIZED, e.clone()),
             AppError::Validation(ref e) => (StatusCode::BAD_REQUEST, e.clone()),
+            AppError::ValidationError(ref e) => (StatusCode::BAD_REQUEST, e.to_string()),
             AppError::NotFound(ref e) => (StatusCode::NOT_FOUND, e.clone()),
             AppError::Unauthorized(ref e) => (StatusCode::UNAUTHORIZED, e.clone()),
             AppError::Forbidden(ref e) => (StatusCode::FORBIDDEN, e.clone()),
             AppError::BadRequest(ref e) => (StatusCode::BAD_REQUEST, e.clone()),
-            AppError::InternalServerError(ref e) => {
-                tracing::error!("Internal server error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, e.clone())
-            }
-            AppError::Jwt(ref e) => {
-                tracing::error!("JWT error: {:?}", e);
-                (StatusCode::UNAUTHORIZED, "Invalid token".to_string())
-            }
+            AppError::InternalServerError(ref e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone()),
+            AppError::Jwt(_) => (StatusCode::UNAUTHORIZED, "Invalid token".to_string()),
             AppError::InvalidCredentials => {
                 (StatusCode::UNAUTHORIZED, "Invalid credentials".to_string())
             }
@@ -112,41 +250,93 @@ impl ResponseError for AppError {
                 (StatusCode::BAD_REQUEST, "User already exists".to_string())
             }
             AppError::Conflict(ref e) => (StatusCode::CONFLICT, e.clone()),
-            AppError::Io(ref e) => {
-                tracing::error!("IO error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "IO error".to_string())
-            }
+            AppError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "IO error".to_string()),
             AppError::NotImplemented(ref e) => (StatusCode::NOT_IMPLEMENTED, e.clone()),
-            AppError::ExternalServiceError(ref e) => {
-                tracing::error!("External service error: {:?}", e);
-                (StatusCode::BAD_GATEWAY, e.clone())
+            AppError::ExternalServiceError(ref e) => (StatusCode::BAD_GATEWAY, e.clone()),
+            AppError::Internal(ref e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone()),
+            AppError::Http(_) => (StatusCode::BAD_GATEWAY, "HTTP request failed".to_string()),
+            AppError::RedisPool(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Redis pool error".to_string(),
+            ),
+            AppError::Timeout(ref e) => (StatusCode::GATEWAY_TIMEOUT, e.clone()),
+            AppError::TooManyRequests(ref e) => (StatusCode::TOO_MANY_REQUESTS, e.clone()),
+            AppError::HeadersTooLarge(ref e) => {
+                (StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, e.clone())
+            }
+            AppError::RateLimited { ref message, .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, message.clone())
             }
-            AppError::Internal(ref e) => {
-                tracing::error!("Internal error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, e.clone())
+            AppError::ServiceUnavailable { ref message, .. } => {
+                (StatusCode::SERVICE_UNAVAILABLE, message.clone())
             }
-            AppError::Http(ref e) => {
-                tracing::error!("HTTP error: {:?}", e);
-                (StatusCode::BAD_GATEWAY, "HTTP request failed".to_string())
+            AppError::CircuitOpen { ref message, .. } => (StatusCode::BAD_GATEWAY, message.clone()),
+        }
+    }
+
+    /// Stable RFC 7807 problem type for this error
+    pub fn problem_type(&self) -> &'static str {
+        match self {
+            AppError::Database(_)
+            | AppError::Redis(_)
+            | AppError::RedisPool(_)
+            | AppError::Io(_)
+            | AppError::InternalServerError(_)
+            | AppError::Internal(_) => "urn:open-webui:problem:internal-error",
+            AppError::Auth(_)
+            | AppError::Unauthorized(_)
+            | AppError::Jwt(_)
+            | AppError::InvalidCredentials => "urn:open-webui:problem:unauthorized",
+            AppError::Validation(_) | AppError::ValidationError(_) => {
+                "urn:open-webui:problem:validation-error"
             }
-            AppError::RedisPool(ref e) => {
-                tracing::error!("Redis pool error: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Redis pool error".to_string(),
-                )
+            AppError::NotFound(_) => "urn:open-webui:problem:not-found",
+            AppError::Forbidden(_) => "urn:open-webui:problem:forbidden",
+            AppError::BadRequest(_) => "urn:open-webui:problem:bad-request",
+            AppError::UserAlreadyExists | AppError::Conflict(_) => {
+                "urn:open-webui:problem:conflict"
             }
-            AppError::Timeout(ref e) => {
-                tracing::error!("Request timeout: {:?}", e);
-                (StatusCode::GATEWAY_TIMEOUT, e.clone())
+            AppError::NotImplemented(_) => "urn:open-webui:problem:not-implemented",
+            AppError::ExternalServiceError(_) | AppError::Http(_) => {
+                "urn:open-webui:problem:upstream-error"
             }
-            AppError::TooManyRequests(ref e) => (StatusCode::TOO_MANY_REQUESTS, e.clone()),
-        };
+            AppError::CircuitOpen { .. } => "urn:open-webui:problem:upstream-unavailable",
+            AppError::Timeout(_) => "urn:open-webui:problem:timeout",
+            AppError::TooManyRequests(_) | AppError::RateLimited { .. } => {
+                "urn:open-webui:problem:rate-limited"
+            }
+            AppError::ServiceUnavailable { .. } => "urn:open-webui:problem:service-unavailable",
+            AppError::HeadersTooLarge(_) => "urn:open-webui:problem:headers-too-large",
+        }
+    }
 
-        let body = ErrorResponse {
-            detail: error_message,
+    /// The error as an RFC 7807 `application/problem+json` response
+    ///
+    /// `instance` is the request path. Headers match [`ResponseError::error_response`],
+    /// which has already logged the error.
+    pub fn problem_response(&self, instance: &str) -> HttpResponse {
+        let (status, detail) = self.status_and_detail();
+        let problem = ProblemDetails {
+            problem_type: self.problem_type(),
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            instance: instance.to_string(),
+            errors: self.field_errors(),
         };
+        self.response_builder(status)
+            .content_type(PROBLEM_JSON)
+            .body(serde_json::to_string(&problem).unwrap_or_default())
+    }
+
+    fn field_errors(&self) -> Option<FieldErrors> {
+        match self {
+            AppError::ValidationError(ref e) => Some(e.clone()),
+            _ => None,
+        }
+    }
 
+    fn response_builder(&self, status: StatusCode) -> HttpResponseBuilder {
         // Build response with CORS headers to ensure they're always present
         // even when errors occur in middleware before CORS middleware processes the response
         let mut response_builder = HttpResponse::build(status);
@@ -180,7 +370,26 @@ impl ResponseError for AppError {
             response_builder.insert_header((header::SET_COOKIE, token_cookie.to_string()));
         }
 
-        response_builder.json(body)
+        if let AppError::ServiceUnavailable { retry_after, .. }
+        | AppError::CircuitOpen { retry_after, .. }
+        | AppError::RateLimited { retry_after, .. } = self
+        {
+            response_builder.insert_header((header::RETRY_AFTER, retry_after.to_string()));
+        }
+
+        response_builder
+    }
+}
+
+impl ResponseError for AppError {
+    fn error_response(&self) -> HttpResponse {
+        self.log();
+        let (status, detail) = self.status_and_detail();
+        let body = ErrorResponse {
+            detail,
+            errors: self.field_errors(),
+        };
+        self.response_builder(status).json(body)
     }
 
     fn status_code(&self) -> StatusCode {
@@ -189,6 +398,7 @@ impl ResponseError for AppError {
             AppError::Redis(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Auth(_) => StatusCode::UNAUTHORIZED,
             AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
             AppError::NotFound(_) => StatusCode::NOT_FOUND,
             AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
             AppError::Forbidden(_) => StatusCode::FORBIDDEN,
@@ -206,15 +416,125 @@ impl ResponseError for AppError {
             AppError::RedisPool(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::HeadersTooLarge(_) => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::ServiceUnavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::CircuitOpen { .. } => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// SQLSTATE for a query cancelled by `statement_timeout`
+const QUERY_CANCELED: &str = "57014";
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref db_error)
+                if db_error.code().as_deref() == Some(QUERY_CANCELED) =>
+            {
+                AppError::Timeout("Database query timed out".to_string())
+            }
+            e => AppError::Database(e),
         }
     }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
 
+/// Render malformed JSON bodies in the standard JSON error shape
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    use actix_web::error::JsonPayloadError;
+
+    let message = match err {
+        JsonPayloadError::ContentType => "Content-Type must be application/json".to_string(),
+        JsonPayloadError::Deserialize(e) => format!("Invalid JSON body: {}", e),
+        e => format!("Invalid request body: {}", e),
+    };
+    AppError::BadRequest(message).into()
+}
+
+/// Render unparseable path parameters in the standard JSON error shape
+pub fn path_error_handler(
+    err: actix_web::error::PathError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    AppError::BadRequest(format!("Invalid path parameter: {}", err)).into()
+}
+
+/// Render unparseable query strings in the standard JSON error shape
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _req: &actix_web::HttpRequest,
+) -> actix_web::Error {
+    AppError::BadRequest(format!("Invalid query parameters: {}", err)).into()
+}
+
 // Implement From for redis pool errors
 impl From<deadpool_redis::PoolError> for AppError {
     fn from(err: deadpool_redis::PoolError) -> Self {
         AppError::RedisPool(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{SigninRequest, SignupRequest};
+    use actix_web::test::{call_service, init_service, read_body_json, TestRequest};
+    use actix_web::{web, App};
+    use validator::Validate;
+
+    #[actix_web::test]
+    async fn test_malformed_json_returns_error_body() {
+        let app = init_service(
+            App::new()
+                .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+                .route(
+                    "/signin",
+                    web::post()
+                        .to(|_: web::Json<SigninRequest>| async { HttpResponse::Ok().finish() }),
+                ),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/signin")
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload("{\"email\": ")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let body: serde_json::Value = read_body_json(resp).await;
+        assert!(body["detail"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid JSON body"));
+    }
+
+    #[test]
+    fn test_validation_errors_are_per_field() {
+        let req = SignupRequest {
+            name: "Test".to_string(),
+            email: "not-an-email".to_string(),
+            password: "short".to_string(),
+            password_confirmation: None,
+            captcha_token: None,
+        };
+
+        let AppError::ValidationError(fields) = AppError::from(req.validate().unwrap_err()) else {
+            panic!("expected structured validation error");
+        };
+
+        assert_eq!(fields.0.len(), 2);
+        assert_eq!(fields.0["email"], vec!["Invalid email address".to_string()]);
+        assert_eq!(
+            fields.0["password"],
+            vec!["Must be at least 8 characters".to_string()]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 mod cache_manager;
+mod cli;
 mod config;
 mod db;
 mod error;
@@ -9,6 +10,8 @@ mod routes;
 mod services;
 mod socket;
 mod socketio;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 mod websocket_chat;
 
@@ -16,12 +19,12 @@ use actix_cors::Cors;
 use actix_files::Files;
 use actix_web::{
     http::header,
-    middleware::{Compress, Logger, NormalizePath},
+    middleware::{Logger, NormalizePath},
     web, App, HttpRequest, HttpResponse, HttpServer,
 };
+use clap::Parser;
 use std::net::SocketAddr;
 use tracing::{info, warn, Level};
-use tracing_subscriber::FmtSubscriber;
 
 use crate::config::{Config, MutableConfig};
 use crate::db::Database;
@@ -34,8 +37,19 @@ pub struct AppState {
     pub db: Database,
     pub config: MutableConfig,
     pub redis: Option<deadpool_redis::Pool>,
-    // Model cache: model_id -> model info (with urlIdx, etc.)
-    pub models_cache: Arc<RwLock<std::collections::HashMap<String, serde_json::Value>>>,
+    // Circuit breaker over Redis calls; degraded while Redis is unreachable
+    pub redis_guard: Arc<utils::redis_guard::RedisGuard>,
+    // Model cache: merged model list plus (prefixed) model_id -> connection routes
+    pub models_cache: Arc<RwLock<utils::models_cache::ModelsCache>>,
+    // Shares one upstream model-list refresh among concurrent identical requests
+    pub model_list_flight: Arc<
+        utils::single_flight::SingleFlight<
+            String,
+            Vec<(serde_json::Value, utils::models_cache::ModelRoute)>,
+        >,
+    >,
+    // Last upstream lists for /api/models, served while a refresh is in progress
+    pub upstream_models: Arc<services::models::UpstreamModelsCache>,
     // Socket state for tracking sessions and users (Socket.IO-like functionality)
     pub socket_state: Option<socket::SocketState>,
     // Socket.IO event handler (native Rust implementation)
@@ -52,6 +66,36 @@ pub struct AppState {
     pub oauth_session_service: Arc<services::oauth_session::OAuthSessionService>,
     // OAuth manager for coordinating OAuth providers
     pub oauth_manager: Arc<services::oauth_manager::OAuthManager>,
+    // Concurrency limiters for expensive routes (embeddings, upstream model calls)
+    pub concurrency_limits: middleware::ConcurrencyLimits,
+    // Per-connection circuit breakers for failing upstream providers
+    pub circuit_breakers: Arc<utils::circuit_breaker::CircuitBreakers>,
+    // Open streaming completions per user (MAX_CONCURRENT_STREAMS_PER_USER)
+    pub stream_limits: Arc<utils::stream_limits::StreamLimits>,
+    pub guest_access: middleware::GuestAccess,
+    // TTL cache for proxied remote profile pictures (OAUTH_PROXY_PICTURES)
+    pub avatar_cache: Arc<services::avatar::AvatarCache>,
+    // Verifier for IdP-issued bearer tokens (EXTERNAL_JWT_JWKS_URL), None when disabled
+    pub external_jwt: Option<Arc<services::external_jwt::ExternalJwtVerifier>>,
+    // Background refresh of OAuth tokens about to expire
+    pub oauth_refresher: Arc<services::oauth_refresh::OAuthRefresher>,
+    // Request/response body size histograms, recorded by the PayloadSize middleware
+    pub payload_metrics: Arc<middleware::PayloadMetrics>,
+    // Progressive delay for repeated failed sign-ins
+    pub signin_throttle: Arc<services::signin_throttle::SigninThrottle>,
+    pub mfa_attempts: Arc<services::mfa::MfaAttempts>,
+    // Rate-limited admin impersonation of other users
+    pub impersonation: Arc<services::impersonation::Impersonation>,
+    // Rate-limited, audit-logged "export my data" downloads
+    pub data_export: Arc<services::data_export::DataExport>,
+    // Rate-limited sample webhook deliveries for admins
+    pub webhook_tester: Arc<utils::webhook::WebhookTester>,
+    // Per-model throttling gates (MODEL_RATE_LIMITS and model meta)
+    pub model_queues: Arc<utils::model_queue::ModelQueues>,
+    // One ingestion job per knowledge base (KNOWLEDGE_INGEST_LOCK_TIMEOUT)
+    pub ingest_locks: utils::ingest_lock::IngestLocks,
+    // Startup tasks still running, reported by /health/ready
+    pub readiness: Arc<utils::readiness::Readiness>,
 }
 
 #[actix_web::main]
@@ -64,15 +108,11 @@ async fn main() -> anyhow::Result<()> {
         .parse()
         .unwrap_or(Level::INFO);
 
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .finish();
+    // Falls back to the plain fmt subscriber unless OTLP export is enabled
+    utils::telemetry::init_tracing(log_level)?;
 
-    tracing::subscriber::set_global_default(subscriber)?;
+    // Maintenance subcommands run after startup migrations and exit; none starts the server
+    let cli = cli::Cli::parse();
 
     info!("Starting Open WebUI Rust Backend");
 
@@ -81,16 +121,83 @@ async fn main() -> anyhow::Result<()> {
     info!("Configuration loaded from environment");
 
     // Initialize database
-    let db = Database::new(&config.database_url).await?;
+    let mut db = Database::new(&config.database_url, db::QueryLimits::from_config(&config)).await?;
     info!("Database connected");
+    if let Some(read_url) = &config.database_read_url {
+        db = db.with_read_replica(read_url).await?;
+        info!("Read replica connected");
+    }
+
+    let readiness = Arc::new(utils::readiness::Readiness::new(&[
+        utils::readiness::MIGRATIONS,
+        utils::readiness::CONFIG,
+        utils::readiness::MODELS_CACHE,
+        utils::readiness::EMBEDDINGS,
+        utils::readiness::VECTOR_DB,
+    ]));
 
     // Run migrations
     db.run_migrations().await?;
     info!("Database migrations completed");
+    readiness.complete(utils::readiness::MIGRATIONS);
+
+    let strict_schema_check = config
+        .strict_schema_check
+        .unwrap_or(config.env == "production");
+    services::schema_check::verify(&db, strict_schema_check).await?;
 
     // Load and merge config from database (PersistentConfig behavior)
     let config = services::ConfigService::load_from_db(&db, config).await?;
     info!("Configuration loaded and merged from database");
+    readiness.complete(utils::readiness::CONFIG);
+
+    utils::http::init(&config)?;
+    utils::password::init(&config)?;
+    utils::geoip::init(&config)?;
+    for warning in config.warnings() {
+        warn!("⚠️  {}", warning);
+    }
+
+    if let Some(command) = cli.command {
+        return cli::run(command, &db, &config).await;
+    }
+
+    // Create the bootstrap admin for declarative deployments
+    if let (Some(email), Some(password)) = (
+        config.initial_admin_email.as_deref(),
+        config.initial_admin_password.as_deref(),
+    ) {
+        let auth_service = services::AuthService::new(&db);
+        if auth_service.bootstrap_admin(email, password).await? {
+            info!("Created bootstrap admin user {}", email);
+        }
+        if matches!(
+            auth_service.authenticate(email, password).await,
+            Ok(Some(_))
+        ) {
+            warn!(
+                "⚠️  Admin {} still uses INITIAL_ADMIN_PASSWORD; change it and unset the variable",
+                email
+            );
+        }
+    }
+
+    if !config.default_model_definitions.is_empty() {
+        if let Err(e) = services::model::seed_default_models(&db, &config).await {
+            warn!("Failed to seed DEFAULT_MODEL_DEFINITIONS: {}", e);
+        }
+    }
+
+    // Ingests cut off by the last shutdown resume when their file is next indexed
+    let interrupted = services::file::FileService::new(&db)
+        .mark_interrupted_ingests()
+        .await?;
+    if interrupted > 0 {
+        info!(
+            "Marked {} unfinished file ingests as interrupted",
+            interrupted
+        );
+    }
 
     // Initialize Redis if enabled
     let redis = if config.enable_redis {
@@ -102,8 +209,11 @@ async fn main() -> anyhow::Result<()> {
         None
     };
 
+    // Shared by the Redis users so they fall back together while Redis is down
+    let redis_guard = Arc::new(utils::redis_guard::RedisGuard::from_config(&config));
+
     // Initialize cache manager
-    let cache_manager = cache_manager::CacheManager::init(redis.clone());
+    let cache_manager = cache_manager::CacheManager::init(redis.clone(), redis_guard.clone());
     cache_manager.start_cleanup_tasks();
     info!("Cache manager initialized and cleanup tasks started");
 
@@ -130,9 +240,13 @@ async fn main() -> anyhow::Result<()> {
         let rate_limit_config = RateLimitConfig::default();
         let rate_limiter = Arc::new(RateLimiter::new(rate_limit_config));
 
-        // Initialize presence manager
+        // Identifies this instance in Redis (pub/sub adapter and presence)
+        let server_id = uuid::Uuid::new_v4().to_string();
+
+        // Initialize presence manager, shared across instances via Redis if available
         let presence_config = PresenceConfig::default();
-        let presence_manager = Arc::new(PresenceManager::new(presence_config));
+        let presence_manager =
+            Arc::new(PresenceManager::new(presence_config).with_redis(redis.clone(), &server_id));
 
         // Initialize recovery manager with Redis if available
         let recovery_config = RecoveryConfig::default();
@@ -143,7 +257,6 @@ async fn main() -> anyhow::Result<()> {
 
         // Initialize Redis adapter if SOCKETIO_REDIS_URL is set
         let redis_adapter = if let Ok(redis_url) = std::env::var("SOCKETIO_REDIS_URL") {
-            let server_id = uuid::Uuid::new_v4().to_string();
             match RedisAdapter::new(&redis_url, server_id.clone()) {
                 Ok(adapter) => {
                     let adapter_arc = Arc::new(adapter);
@@ -191,12 +304,13 @@ async fn main() -> anyhow::Result<()> {
         );
 
         // Spawn background cleanup tasks
-        let manager_cleanup = manager.clone();
+        let handler_cleanup = handler.clone();
         tokio::spawn(async move {
             loop {
                 tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-                // Clean up sessions that haven't pinged in 60 seconds (3x ping interval + timeout)
-                manager_cleanup.cleanup_stale_sessions(60).await;
+                // Clean up sessions that haven't pinged in 60 seconds (3x ping interval + timeout),
+                // clearing their presence as well
+                handler_cleanup.cleanup_stale_sessions(60).await;
             }
         });
 
@@ -216,6 +330,7 @@ async fn main() -> anyhow::Result<()> {
                 tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
                 presence_cleanup.update_away_statuses().await;
                 presence_cleanup.cleanup_typing_indicators().await;
+                presence_cleanup.sync_redis().await;
             }
         });
 
@@ -252,14 +367,17 @@ async fn main() -> anyhow::Result<()> {
     // Create Socket state with native handler
     let socket_state = if config.enable_websocket_support && socketio_handler.is_some() {
         use crate::socket::SocketState;
-        Some(SocketState::new(socketio_handler.as_ref().unwrap().clone()))
+        Some(SocketState::new(
+            socketio_handler.as_ref().unwrap().clone(),
+            Arc::new(socket::ConnectionLimits::from_config(&config)),
+        ))
     } else {
         None
     };
 
     // Create app state
     // Create shared HTTP client with connection pooling and optimized settings
-    let http_client = reqwest::Client::builder()
+    let http_client = utils::http::builder()
         .pool_max_idle_per_host(10) // Reuse connections
         .tcp_nodelay(true) // Disable Nagle's algorithm for real-time streaming
         .timeout(std::time::Duration::from_secs(300)) // 5 min default timeout
@@ -276,10 +394,15 @@ async fn main() -> anyhow::Result<()> {
         == "true";
 
     let vector_db = if vector_db_enabled {
-        match retrieval::VectorDBFactory::from_env().await {
+        // Config::validate already rejected anything else
+        let distance = retrieval::DistanceMetric::parse(&config.rag_distance).unwrap_or_default();
+        match retrieval::VectorDBFactory::from_env(distance).await {
             Ok(db) => {
                 info!("✅ Vector database initialized successfully");
-                Some(db)
+                Some(retrieval::vector::PrefixedVectorDB::wrap(
+                    db,
+                    &config.vector_collection_prefix,
+                ))
             }
             Err(e) => {
                 warn!("⚠️  Failed to initialize vector database: {}", e);
@@ -470,7 +593,120 @@ async fn main() -> anyhow::Result<()> {
         }
     } else {
         None
-    };
+    }
+    .map(|provider| {
+        // The default provider and its fallbacks, routed by health
+        let fallbacks = config
+            .rag_embedding_fallback_models
+            .iter()
+            .filter_map(|fallback| {
+                let (engine, model) = fallback.split_once(':')?;
+                match retrieval::EmbeddingFactory::create(engine, model, &config.rag_openai_api_key)
+                {
+                    Ok(provider) => {
+                        info!("   Embedding fallback: {} ({})", model, engine);
+                        Some(provider)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "⚠️  Failed to initialize embedding fallback {}: {}",
+                            fallback, e
+                        );
+                        None
+                    }
+                }
+            });
+        Arc::new(retrieval::HealthRoutedEmbeddings::new(
+            std::iter::once(provider).chain(fallbacks).collect(),
+            config.rag_embedding_health_error_rate,
+            std::time::Duration::from_secs(config.rag_embedding_health_cooldown),
+        )) as Arc<dyn retrieval::EmbeddingProvider>
+    })
+    .map(|provider| {
+        info!(
+            "   Embedding batch size: {}, rate limit: {}",
+            config.rag_embedding_batch_size,
+            match config.rag_embedding_rpm {
+                0 => "unlimited".to_string(),
+                rpm => format!("{} requests/min", rpm),
+            }
+        );
+        Arc::new(
+            retrieval::BatchedEmbeddings::new(
+                provider,
+                config.rag_embedding_batch_size,
+                config.rag_embedding_rpm,
+            )
+            .with_max_input_tokens(
+                config.rag_embedding_max_input_tokens,
+                config.rag_embedding_truncate_input,
+            ),
+        ) as Arc<dyn retrieval::EmbeddingProvider>
+        })
+    .map(|provider| {
+        if !config.rag_language_detection {
+            return provider;
+        }
+
+        // Per-language models use the default engine; each gets its own batching and pacing
+        let models = config
+            .rag_embedding_language_models
+            .iter()
+            .filter_map(|(language, model)| {
+                match retrieval::EmbeddingFactory::create(
+                    &config.rag_embedding_engine,
+                    model,
+                    &config.rag_openai_api_key,
+                ) {
+                    Ok(language_provider) => {
+                        info!("   Language '{}' embeds with {}", language, model);
+                        Some((
+                            language.clone(),
+                            Arc::new(
+                                retrieval::BatchedEmbeddings::new(
+                                    language_provider,
+                                    config.rag_embedding_batch_size,
+                                    config.rag_embedding_rpm,
+                                )
+                                .with_max_input_tokens(
+                                    config.rag_embedding_max_input_tokens,
+                                    config.rag_embedding_truncate_input,
+                                ),
+                            ) as Arc<dyn retrieval::EmbeddingProvider>,
+                        ))
+                    }
+                    Err(e) => {
+                        warn!(
+                            "⚠️  Failed to initialize embedding model {} for '{}', using the default: {}",
+                            model, language, e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+        info!("✅ Language detection enabled for RAG");
+        Arc::new(retrieval::LanguageEmbeddings::new(provider, models))
+            as Arc<dyn retrieval::EmbeddingProvider>
+    });
+
+    // A strict startup fails here; otherwise the warmup runs once the server is up
+    match &embedding_provider {
+        Some(provider) if config.rag_embedding_warmup && config.rag_strict_startup => {
+            retrieval::embeddings::warmup(provider.as_ref(), config.rag_strict_startup)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Embedding provider failed warmup (RAG_STRICT_STARTUP=true): {}",
+                        e
+                    )
+                })?;
+        }
+        None if vector_db_enabled && config.rag_strict_startup => {
+            anyhow::bail!("No embedding provider could be initialized (RAG_STRICT_STARTUP=true)");
+        }
+        _ => {}
+    }
 
     // Initialize sandbox executor client if enabled
     let sandbox_executor_client = if config.enable_code_execution {
@@ -489,8 +725,8 @@ async fn main() -> anyhow::Result<()> {
 
     // Initialize OAuth session service with encryption
     let oauth_session_service = {
-        let encryption_key = &config.oauth_session_token_encryption_key;
-        match services::oauth_session::OAuthSessionService::new(db.clone(), encryption_key) {
+        let encryption_keys = config.oauth_session_keys();
+        match services::oauth_session::OAuthSessionService::new(db.clone(), &encryption_keys) {
             Ok(service) => {
                 info!("OAuth session service initialized with encryption");
                 Arc::new(service)
@@ -502,7 +738,7 @@ async fn main() -> anyhow::Result<()> {
                 Arc::new(
                     services::oauth_session::OAuthSessionService::new(
                         db.clone(),
-                        &uuid::Uuid::new_v4().to_string(),
+                        &[uuid::Uuid::new_v4().to_string()],
                     )
                     .expect("Failed to create OAuth session service"),
                 )
@@ -510,56 +746,128 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    // Initialize OAuth manager
-    let oauth_manager = {
-        match services::oauth_manager::OAuthManager::new(
+    let session_store = services::session_store::from_config(
+        &config,
+        &db,
+        redis.as_ref(),
+        redis_guard.clone(),
+    );
+    info!("Session store: {}", session_store.backend());
+
+    // Initialize OAuth manager. Providers with built-in endpoints survive a failed
+    // discovery, so an error here means generic OIDC could not be set up
+    let oauth_manager = Arc::new(
+        services::oauth_manager::OAuthManager::new(
             config.clone(),
             oauth_session_service.clone(),
+            http_client.clone(),
+            session_store.clone(),
         )
         .await
-        {
-            Ok(manager) => {
-                info!("OAuth manager initialized with providers");
-                Arc::new(manager)
-            }
-            Err(e) => {
-                warn!("Failed to initialize OAuth manager: {}", e);
-                warn!("OAuth authentication will not be available");
-                // Create a fallback (empty manager)
-                Arc::new(
-                    services::oauth_manager::OAuthManager::new(
-                        config.clone(),
-                        oauth_session_service.clone(),
-                    )
-                    .await
-                    .expect("Failed to create OAuth manager"),
-                )
-            }
-        }
-    };
+        .map_err(|e| anyhow::anyhow!("Failed to initialize OAuth providers: {}", e))?,
+    );
+    info!("OAuth manager initialized with providers");
+
+    let oauth_refresher = Arc::new(services::oauth_refresh::OAuthRefresher::from_config(
+        &config,
+    ));
+    oauth_refresher.spawn(oauth_manager.clone());
 
     let state = web::Data::new(AppState {
         db: db.clone(),
         config: Arc::new(RwLock::new(config.clone())),
         redis: redis.clone(),
-        models_cache: Arc::new(RwLock::new(std::collections::HashMap::new())),
+        redis_guard,
+        models_cache: Arc::new(RwLock::new(utils::models_cache::ModelsCache::new())),
+        model_list_flight: Arc::new(utils::single_flight::SingleFlight::new()),
+        upstream_models: Arc::new(services::models::UpstreamModelsCache::default()),
         socket_state,
         socketio_handler: socketio_handler.clone(),
-        http_client,
+        http_client: http_client.clone(),
         vector_db,
         embedding_provider,
         sandbox_executor_client,
         oauth_session_service,
         oauth_manager,
+        oauth_refresher,
+        payload_metrics: Arc::new(middleware::PayloadMetrics::default()),
+        signin_throttle: Arc::new(services::signin_throttle::SigninThrottle::from_config(
+            &config,
+        )),
+        mfa_attempts: Arc::new(services::mfa::MfaAttempts::from_config(&config)),
+        impersonation: Arc::new(services::impersonation::Impersonation::from_config(&config)),
+        data_export: Arc::new(services::data_export::DataExport::from_config(&config)),
+        webhook_tester: Arc::new(utils::webhook::WebhookTester::from_config(&config)),
+        model_queues: Arc::new(utils::model_queue::ModelQueues::default()),
+        ingest_locks: utils::ingest_lock::IngestLocks::new(std::time::Duration::from_secs(
+            config.knowledge_ingest_lock_timeout,
+        )),
+        concurrency_limits: middleware::ConcurrencyLimits::from_config(&config),
+        circuit_breakers: Arc::new(utils::circuit_breaker::CircuitBreakers::new()),
+        stream_limits: Arc::new(utils::stream_limits::StreamLimits::new()),
+        guest_access: middleware::GuestAccess::from_config(&config),
+        avatar_cache: Arc::new(services::avatar::AvatarCache::new()),
+        external_jwt: services::external_jwt::ExternalJwtVerifier::from_config(
+            &config,
+            http_client.clone(),
+        )
+        .map(Arc::new),
+        readiness: readiness.clone(),
     });
+    services::retention::spawn(db.clone(), state.config.clone());
+
+    // Warm up in the background; /health/ready reports ready once all of it is done
+    if config.enable_openai_api {
+        let (state, config) = (state.clone(), config.clone());
+        readiness.spawn(utils::readiness::MODELS_CACHE, async move {
+            let models = routes::openai::refresh_models_cache(&state, &config).await;
+            info!("Models cache warmed with {} models", models.len());
+        });
+    } else {
+        readiness.complete(utils::readiness::MODELS_CACHE);
+    }
+    match state.embedding_provider.clone() {
+        Some(provider) if config.rag_embedding_warmup && !config.rag_strict_startup => {
+            readiness.spawn(utils::readiness::EMBEDDINGS, async move {
+                let _ = retrieval::embeddings::warmup(provider.as_ref(), false).await;
+            });
+        }
+        _ => readiness.complete(utils::readiness::EMBEDDINGS),
+    }
+    match state.vector_db.clone() {
+        Some(vector_db) => readiness.spawn(utils::readiness::VECTOR_DB, async move {
+            match tokio::time::timeout(std::time::Duration::from_secs(10), vector_db.heartbeat())
+                .await
+            {
+                Ok(Ok(())) => info!("✅ Vector database reachable"),
+                Ok(Err(e)) => warn!("⚠️  Vector database probe failed: {}", e),
+                Err(_) => warn!("⚠️  Vector database probe timed out"),
+            }
+        }),
+        None => readiness.complete(utils::readiness::VECTOR_DB),
+    }
 
     // Start server
     let addr = SocketAddr::from((config.host.parse::<std::net::IpAddr>()?, config.port));
     let cors_allow_origin = config.cors_allow_origin.clone();
+    let compression = middleware::Compression::from_config(&config);
+    let debug_log = middleware::DebugLog::from_config(&config);
+    let payload_size = middleware::PayloadSize::new(state.payload_metrics.clone(), &config);
+    let security_headers = middleware::SecurityHeaders::from_config(&config);
+    let request_timeout = middleware::RequestTimeout::from_config(&config);
+    let route_auth = middleware::RouteAuth::from_config(&config);
+    let server_limits = middleware::ServerLimits::from_config(&config);
+    let header_limit = server_limits.header_limit();
+    if !config.debug_log_routes.is_empty() {
+        info!(
+            "Debug body logging enabled for: {}",
+            config.debug_log_routes.join(", ")
+        );
+    }
 
     info!("🚀 Server running at http://{}", addr);
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         // Create CORS middleware
         // NOTE: When credentials are needed (cookies/auth), we cannot use allow_any_origin()
         // Instead, we need to allow specific origins or use allowed_origin_fn to dynamically allow
@@ -572,7 +880,10 @@ async fn main() -> anyhow::Result<()> {
                 })
                 .allow_any_method()
                 .allow_any_header()
-                .expose_headers(vec![header::SET_COOKIE])
+                .expose_headers(vec![
+                    header::SET_COOKIE,
+                    header::HeaderName::from_static(middleware::request_id::REQUEST_ID_HEADER),
+                ])
                 .supports_credentials()
                 .max_age(3600)
         } else {
@@ -587,25 +898,44 @@ async fn main() -> anyhow::Result<()> {
                     header::AUTHORIZATION,
                     header::ACCEPT,
                     header::COOKIE,
+                    header::HeaderName::from_static("x-openai-key"),
+                ])
+                .expose_headers(vec![
+                    header::SET_COOKIE,
+                    header::HeaderName::from_static(middleware::request_id::REQUEST_ID_HEADER),
                 ])
-                .expose_headers(vec![header::SET_COOKIE])
                 .supports_credentials()
                 .max_age(3600)
         };
 
         App::new()
             .app_data(state.clone())
+            // Extractor rejections use the standard JSON error body
+            .app_data(web::JsonConfig::default().error_handler(error::json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(error::path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error_handler))
+            .wrap(route_auth.clone()) // Innermost, so it sees normalized paths
+            .wrap(middleware::Maintenance)
+            .wrap(middleware::ReadinessGate)
+            .wrap(request_timeout.clone())
+            .wrap(debug_log.clone())
+            .wrap(header_limit)
+            .wrap(middleware::ProblemJson) // Sees errors from every inner layer
             .wrap(cors)
-            .wrap(Compress::default())
+            .wrap(compression.clone())
             .wrap(Logger::default())
             .wrap(NormalizePath::trim())
-            .wrap(middleware::SecurityHeaders) // Security headers middleware
+            .wrap(security_headers.clone()) // Security headers middleware
+            .wrap(payload_size.clone())
+            .wrap(middleware::RequestId) // Outermost, so every layer sees the id
             // Health checks
             .route("/health", web::get().to(health_check))
             .route("/health/db", web::get().to(health_check_db))
+            .route("/health/ready", web::get().to(health_check_ready))
             // Config and version
             .route("/api/config", web::get().to(get_app_config))
             .route("/api/version", web::get().to(get_app_version))
+            .route("/api/capabilities", web::get().to(get_capabilities))
             .route(
                 "/api/version/updates",
                 web::get().to(get_app_latest_version),
@@ -613,13 +943,30 @@ async fn main() -> anyhow::Result<()> {
             // Models list endpoint (OpenAI compatible - returns {"data": [...]})
             .route("/api/models", web::get().to(get_models))
             .route("/api/models/base", web::get().to(get_base_models))
+            .service(
+                web::resource("/api/models/create")
+                    .wrap(middleware::AuthMiddleware)
+                    .route(web::post().to(routes::models::create_model)),
+            )
+            .service(
+                web::resource("/api/models/{id}")
+                    .wrap(middleware::AuthMiddleware)
+                    .route(web::get().to(routes::models::get_model))
+                    .route(web::post().to(routes::models::update_model))
+                    .route(web::delete().to(routes::models::delete_model)),
+            )
             // API routes (nested after specific routes to avoid conflicts)
             .service(web::scope("/api/v1").configure(create_routes))
             // OpenAI compatible API
-            .service(web::scope("/openai").configure(routes::openai::create_routes))
+            .service(
+                web::scope("/openai")
+                    .wrap(middleware::ConcurrencyLimit::Upstream)
+                    .configure(routes::openai::create_routes),
+            )
             // Chat endpoints (legacy routes without /v1 prefix)
             .service(
                 web::resource("/api/chat/completions")
+                    .wrap(middleware::ConcurrencyLimit::Upstream)
                     .wrap(middleware::AuthMiddleware)
                     .route(web::post().to(chat_completions)),
             )
@@ -641,7 +988,22 @@ async fn main() -> anyhow::Result<()> {
                     .route(web::post().to(chat_action)),
             )
             // Embeddings endpoint (legacy route without /v1 prefix)
-            .route("/api/embeddings", web::post().to(embeddings))
+            .service(
+                web::resource("/api/embeddings")
+                    .wrap(middleware::ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(embeddings)),
+            )
+            // Runtime metrics (Prometheus text format)
+            .service(
+                web::resource("/api/metrics")
+                    .wrap(middleware::AdminMiddleware)
+                    .route(web::get().to(routes::metrics::get_metrics)),
+            )
+            .service(
+                web::resource("/api/audit")
+                    .wrap(middleware::AdminMiddleware)
+                    .route(web::get().to(routes::audit::get_audit_logs)),
+            )
             // Task management
             .route("/api/tasks", web::get().to(list_tasks))
             .route("/api/tasks/stop/{task_id}", web::post().to(stop_task))
@@ -673,16 +1035,10 @@ async fn main() -> anyhow::Result<()> {
             .route("/user.png", web::get().to(serve_user_avatar))
             // Serve static files from static directory
             .service(Files::new("/static", "../svelte-frontend/static/static"))
-    })
-    // CRITICAL: Disable keep-alive buffering for real-time streaming
-    .keep_alive(actix_web::http::KeepAlive::Timeout(
-        std::time::Duration::from_secs(75),
-    ))
-    // CRITICAL: Set client timeout high for long-running streams
-    .client_request_timeout(std::time::Duration::from_secs(300))
-    .bind(addr)?
-    .run()
-    .await?;
+    });
+    server_limits.apply(server).bind(addr)?.run().await?;
+
+    utils::telemetry::shutdown();
 
     Ok(())
 }
@@ -709,17 +1065,36 @@ async fn health_check() -> HttpResponse {
 async fn health_check_db(
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, crate::error::AppError> {
-    sqlx::query("SELECT 1")
-        .execute(state.db.pool())
-        .await
-        .map_err(|e| crate::error::AppError::Database(e))?;
+    state
+        .db
+        .timed("SELECT 1", sqlx::query("SELECT 1").execute(state.db.pool()))
+        .await?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({ "status": true })))
 }
 
+/// 200 once startup tasks complete, 503 until then; either way listing each task
+async fn health_check_ready(state: web::Data<AppState>) -> HttpResponse {
+    let report = state.readiness.report();
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
+
 async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
     use serde_json::json;
 
+    // Disabled providers are left out, so login pages don't offer them
+    let oauth_providers: serde_json::Map<String, serde_json::Value> = state
+        .oauth_manager
+        .get_available_providers()
+        .await
+        .into_iter()
+        .map(|name| (name.clone(), json!(name)))
+        .collect();
+
     // Get read lock on config
     let config = state.config.read().unwrap();
 
@@ -753,109 +1128,25 @@ async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpRes
     let user_service = services::user::UserService::new(&state.db);
     let user_count = user_service.get_user_count().await.unwrap_or(0);
 
-    let onboarding = user.is_none() && user_count == 0;
-
-    let mut response = json!({
-        "status": true,
-        "name": config.webui_name,
-        "version": env!("CARGO_PKG_VERSION"),
-        "default_locale": "en-US",
-        "features": {
-            "auth": config.webui_auth,
-            "auth_trusted_header": false,
-            "enable_signup": config.enable_signup,
-            "enable_login_form": config.enable_login_form,
-            "enable_api_key": config.enable_api_key,
-            "enable_ldap": false,
-            "enable_websocket": config.enable_websocket_support,
-            "enable_version_update_check": config.enable_version_update_check,
-            "enable_signup_password_confirmation": false,
-        },
-        "oauth": {
-            "providers": {}
-        }
-    });
-
-    if onboarding {
-        response["onboarding"] = json!(true);
-    }
-
-    // Add authenticated user configuration
-    if user.is_some() {
-        response["features"]["enable_direct_connections"] = json!(config.enable_direct_connections);
-        response["features"]["enable_channels"] = json!(config.enable_channels);
-        response["features"]["enable_notes"] = json!(config.enable_notes);
-        response["features"]["enable_web_search"] = json!(config.enable_web_search);
-        response["features"]["enable_code_execution"] = json!(config.enable_code_execution);
-        response["features"]["enable_code_interpreter"] = json!(config.enable_code_interpreter);
-        response["features"]["enable_image_generation"] = json!(config.enable_image_generation);
-        response["features"]["enable_autocomplete_generation"] =
-            json!(config.enable_autocomplete_generation);
-        response["features"]["enable_community_sharing"] = json!(config.enable_community_sharing);
-        response["features"]["enable_message_rating"] = json!(config.enable_message_rating);
-        response["features"]["enable_user_webhooks"] = json!(config.enable_user_webhooks);
-        response["features"]["enable_admin_export"] = json!(config.enable_admin_export);
-        response["features"]["enable_admin_chat_access"] = json!(config.enable_admin_chat_access);
-        response["features"]["enable_google_drive_integration"] =
-            json!(config.enable_google_drive_integration);
-        response["features"]["enable_onedrive_integration"] =
-            json!(config.enable_onedrive_integration);
-
-        // Convert default_models to comma-separated string (or null if empty) to match Python backend
-        response["default_models"] = if config.default_models.is_empty() {
-            json!(null)
-        } else {
-            json!(&config.default_models)
-        };
-        response["default_prompt_suggestions"] = config.default_prompt_suggestions.clone();
-        response["user_count"] = json!(user_count);
-
-        response["code"] = json!({
-            "engine": if config.enable_code_execution { "python" } else { "" }
-        });
-
-        response["audio"] = json!({
-            "tts": {
-                "engine": &config.tts_engine,
-                "voice": &config.tts_voice,
-                "split_on": &config.tts_split_on
-            },
-            "stt": {
-                "engine": &config.stt_engine,
-            }
-        });
-
-        response["file"] = json!({
-            "max_size": 10485760, // 10MB default
-            "max_count": 10,
-            "image_compression": {
-                "width": 1024,
-                "height": 1024
-            }
-        });
-
-        response["permissions"] = json!({
-            "chat": {
-                "deletion": true,
-                "edit": true,
-                "temporary": true
-            }
-        });
-
-        response["google_drive"] = json!({
-            "client_id": "",
-            "api_key": ""
-        });
-
-        response["onedrive"] = json!({
-            "client_id_personal": "",
-            "client_id_business": "",
-            "sharepoint_url": "",
-            "sharepoint_tenant_id": ""
-        });
+    // Report the features in effect for this user, overrides included
+    let flags = user
+        .as_ref()
+        .map(|user| utils::feature_flags::FeatureFlags::from_request(&config, user, &req))
+        .unwrap_or_default();
+    let config = if flags.is_empty() {
+        std::borrow::Cow::Borrowed(&*config)
+    } else {
+        let mut effective = config.clone();
+        flags.apply(&mut effective);
+        std::borrow::Cow::Owned(effective)
+    };
 
-        response["ui"] = json!({});
-    }
+    let response = utils::app_config::app_config_response(
+        &config,
+        oauth_providers,
+        user.is_some(),
+        user_count,
+    );
 
     // Add cache-control headers to prevent browser caching of config
     // This ensures users always get fresh config data
@@ -869,6 +1160,45 @@ async fn get_app_config(state: web::Data<AppState>, req: HttpRequest) -> HttpRes
         .json(response)
 }
 
+/// What this backend implements, derived from what actually started rather than config flags
+///
+/// The route is public, so the caller is resolved from the token here, as for `/api/config`.
+async fn get_capabilities(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, crate::error::AppError> {
+    let oauth_providers = state.oauth_manager.get_available_providers().await;
+
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer ").map(|s| s.to_string()))
+        .or_else(|| req.cookie("token").map(|c| c.value().to_string()));
+    let signed_in = match token {
+        Some(token) => middleware::authenticate_token(&state, &token).await.is_ok(),
+        None => false,
+    };
+
+    let config = state.config.read().unwrap();
+    utils::capabilities::check_access(&config, signed_in)?;
+
+    let capabilities = utils::capabilities::capabilities(&utils::capabilities::RuntimeState {
+        config: &config,
+        vector_db: state.vector_db.is_some(),
+        embedding_model: state
+            .embedding_provider
+            .as_ref()
+            .map(|provider| provider.model_name()),
+        websocket: state.socket_state.is_some(),
+        socketio: state.socketio_handler.is_some(),
+        code_sandbox: state.sandbox_executor_client.is_some(),
+        oauth_providers,
+    });
+
+    Ok(HttpResponse::Ok().json(capabilities))
+}
+
 async fn get_app_version() -> HttpResponse {
     use serde_json::json;
 
@@ -898,15 +1228,25 @@ async fn get_models(
 
     // Get config for model service
     let config = state.config.read().unwrap().clone();
-    let model_service = crate::services::models::ModelService::new(config.clone());
+    let model_service = crate::services::models::ModelService::new(config.clone())
+        .with_cache(state.upstream_models.clone());
 
-    // Fetch all models
-    match model_service.get_all_models(&state.db).await {
-        Ok(mut models) => {
+    // Fetch all models; connections that timed out or failed are listed separately
+    match model_service.get_all_models_with_status(&state.db).await {
+        Ok((mut models, unavailable)) => {
             // Apply user-based filtering if authenticated
             if let Some(user) = auth_user {
                 models =
                     model_service.filter_models_by_access(models, &user.user.id, &user.user.role);
+                match utils::model_access::ModelAccess::for_user(&state.db, &config, &user.user)
+                    .await
+                {
+                    Ok(access) => models.retain(|model| access.allows(&model.id)),
+                    Err(e) => {
+                        tracing::error!("Failed to resolve model lists: {}", e);
+                        models.clear();
+                    }
+                }
             } else {
                 // For unauthenticated users, only show public models
                 models = model_service.filter_models_by_access(models, "", "guest");
@@ -934,7 +1274,7 @@ async fn get_models(
                 });
             }
 
-            // Process model tags
+            // Process model tags and capabilities
             for model in &mut models {
                 let mut tags = Vec::new();
 
@@ -957,10 +1297,12 @@ async fn get_models(
                 tags.dedup_by(|a, b| a.name == b.name);
 
                 model.tags = if tags.is_empty() { None } else { Some(tags) };
+                model.resolve_capabilities();
             }
 
             HttpResponse::Ok().json(json!({
-                "data": models
+                "data": models,
+                "unavailable_upstreams": unavailable,
             }))
         }
         Err(e) => {
@@ -995,12 +1337,28 @@ async fn get_base_models(state: web::Data<AppState>) -> HttpResponse {
 
 // Chat endpoints
 async fn chat_completions(
+    req: HttpRequest,
     state: web::Data<AppState>,
     payload: web::Json<serde_json::Value>,
     auth_user: middleware::AuthUser,
 ) -> Result<HttpResponse, crate::error::AppError> {
+    let (api_key_override, forwarded_headers) = {
+        let config = state.config.read().unwrap();
+        (
+            routes::openai::byok_key(&req, &config)?,
+            utils::forward_headers::forwarded_headers(&req, &config.forward_headers),
+        )
+    };
+
     // Forward to OpenAI chat completions handler
-    routes::openai::handle_chat_completions(state, auth_user, payload).await
+    routes::openai::handle_chat_completions(
+        state,
+        auth_user,
+        payload,
+        api_key_override,
+        forwarded_headers,
+    )
+    .await
 }
 
 // Configure Socket.IO routes
@@ -1063,10 +1421,29 @@ async fn socketio_connection_get(
         .unwrap_or(false);
 
     if is_websocket_upgrade || transport == Some("websocket") {
+        // Socket.IO authenticates after the upgrade, so only a token already on the
+        // request counts the connection toward its user
+        let connection = match &state.socket_state {
+            Some(socket_state) => {
+                let user_id = match websocket_chat::upgrade_token(&req) {
+                    Ok((token, _)) => middleware::authenticate_token(&state, &token)
+                        .await
+                        .ok()
+                        .map(|(auth_user, _)| auth_user.user.id),
+                    Err(_) => None,
+                };
+                match socket_state.connections.try_acquire(user_id.as_deref()) {
+                    Ok(guard) => Some(guard),
+                    Err(limit) => return socket::reject_upgrade(&req, stream, limit),
+                }
+            }
+            None => None,
+        };
+
         // WebSocket transport
         tracing::info!("Handling WebSocket upgrade for Socket.IO");
         let handler_data = web::Data::new(handler.as_ref().clone());
-        socketio::transport::websocket_handler(req, stream, handler_data).await
+        socketio::transport::websocket_handler(req, stream, handler_data, connection).await
     } else {
         // HTTP polling transport (GET - initial connection or polling for messages)
         tracing::info!("Handling HTTP polling GET for Socket.IO");
@@ -1270,7 +1647,8 @@ async fn embeddings(
         // Prepare embedding request
         let embedding_url = format!("{}/embeddings", base_url);
 
-        let mut request = state.http_client.post(&embedding_url);
+        let mut request =
+            utils::http::upstream_client(&state.http_client, base_url).post(&embedding_url);
 
         if !api_key.is_empty() {
             request = request.header("Authorization", format!("Bearer {}", api_key));
@@ -1,5 +1,7 @@
 use crate::error::AppError;
 use crate::models::User;
+use crate::services::external_jwt::ExternalJwtVerifier;
+use crate::services::mfa::SETUP_ROUTES;
 use crate::services::user::UserService;
 use crate::utils::auth::verify_jwt;
 use crate::AppState;
@@ -15,10 +17,25 @@ use std::rc::Rc;
 #[derive(Clone)]
 pub struct AuthUser {
     pub user: User,
+    /// Admin acting as this user through an impersonation token
+    pub impersonated_by: Option<String>,
+    /// When the session's token was issued, for actions that want a recent sign-in
+    pub issued_at: Option<i64>,
+    /// Signed in with a token that may only set up MFA
+    pub mfa_setup_pending: bool,
 }
 
 #[allow(dead_code)]
 impl AuthUser {
+    pub fn new(user: User) -> Self {
+        Self {
+            user,
+            impersonated_by: None,
+            issued_at: None,
+            mfa_setup_pending: false,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn id(&self) -> &str {
         &self.user.id
@@ -49,6 +66,112 @@ impl actix_web::FromRequest for AuthUser {
     }
 }
 
+/// Resolve a bearer token (API key or JWT) to its user
+///
+/// Also returns the JWT expiry, if any, so long-lived connections can drop at expiry.
+pub async fn authenticate_token(
+    state: &AppState,
+    token: &str,
+) -> Result<(AuthUser, Option<i64>), AppError> {
+    // Check if it's an API key (starts with sk-)
+    if token.starts_with("sk-") {
+        if !state.config.read().unwrap().enable_api_key {
+            return Err(AppError::Forbidden("API keys are disabled".to_string()));
+        }
+
+        let user_service = UserService::new(&state.db);
+        let user = user_service
+            .get_user_by_api_key(token)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+        return Ok((AuthUser::new(user), None));
+    }
+
+    // Asymmetrically signed tokens come from the external IdP, when one is configured
+    if let Some(external) = &state.external_jwt {
+        if ExternalJwtVerifier::handles(token) {
+            let identity = external.verify(token).await?;
+            let config = state.config.read().unwrap().clone();
+            let user = external.resolve_user(&state.db, &identity, &config).await?;
+            let auth_user = AuthUser {
+                issued_at: identity.iat,
+                ..AuthUser::new(user)
+            };
+            return Ok((auth_user, identity.exp));
+        }
+    }
+
+    // Otherwise, verify JWT token
+    let webui_secret_key = state.config.read().unwrap().webui_secret_key.clone();
+
+    let claims = verify_jwt(token, &webui_secret_key).map_err(|e| {
+        // Token verification failed (expired or invalid)
+        tracing::debug!("JWT verification failed: {:?}", e);
+        AppError::Unauthorized("Invalid or expired token".to_string())
+    })?;
+
+    // Check token expiration explicitly
+    if let Some(exp) = claims.exp {
+        let now = chrono::Utc::now().timestamp();
+        if now > exp {
+            tracing::debug!("Token expired at {}, current time {}", exp, now);
+            return Err(AppError::Unauthorized("Token expired".to_string()));
+        }
+    }
+
+    let user_service = UserService::new(&state.db);
+    let user = user_service
+        .get_user_by_id(&claims.sub)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+    Ok((
+        AuthUser {
+            user,
+            impersonated_by: claims.impersonated_by,
+            issued_at: claims.iat,
+            mfa_setup_pending: claims.mfa_setup_pending,
+        },
+        claims.exp,
+    ))
+}
+
+/// Impersonation sessions may look around but not change anything
+pub(crate) fn reject_impersonated_write(
+    req: &ServiceRequest,
+    auth_user: &AuthUser,
+) -> Result<(), AppError> {
+    let Some(admin_id) = &auth_user.impersonated_by else {
+        return Ok(());
+    };
+    if req.method().is_safe() {
+        return Ok(());
+    }
+
+    tracing::warn!(
+        "Blocked {} {} by admin {} impersonating user {}",
+        req.method(),
+        req.path(),
+        admin_id,
+        auth_user.user.id
+    );
+    Err(AppError::Forbidden(
+        "Write actions are not allowed while impersonating a user".to_string(),
+    ))
+}
+
+/// Admins signing in without MFA under REQUIRE_MFA_FOR_ADMINS may only set it up
+pub(crate) fn reject_pending_mfa_setup(
+    req: &ServiceRequest,
+    auth_user: &AuthUser,
+) -> Result<(), AppError> {
+    if !auth_user.mfa_setup_pending || SETUP_ROUTES.contains(&req.path()) {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(
+        "Set up multi-factor authentication to continue".to_string(),
+    ))
+}
+
 // Auth middleware factory
 pub struct AuthMiddleware;
 
@@ -91,6 +214,11 @@ where
         let service = self.service.clone();
 
         Box::pin(async move {
+            // RouteAuth already authenticated the caller
+            if req.extensions().contains::<AuthUser>() {
+                return service.call(req).await;
+            }
+
             // Extract state
             let state = req
                 .app_data::<web::Data<AppState>>()
@@ -108,55 +236,23 @@ where
             };
 
             // If no Authorization header, try to get token from cookie
-            let token = token
-                .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
-                .ok_or_else(|| AppError::Unauthorized("Missing authorization token".to_string()))?;
-
-            // Check if it's an API key (starts with sk-)
-            let user = if token.starts_with("sk-") {
-                let config = state.config.read().unwrap();
-                if !config.enable_api_key {
-                    return Err(AppError::Forbidden("API keys are disabled".to_string()).into());
-                }
-                drop(config); // Release the lock
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_api_key(&token)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?
-            } else {
-                // Otherwise, verify JWT token
-                let config = state.config.read().unwrap();
-                let webui_secret_key = config.webui_secret_key.clone();
-                drop(config); // Release the lock
-
-                let claims = verify_jwt(&token, &webui_secret_key).map_err(|e| {
-                    // Token verification failed (expired or invalid)
-                    tracing::debug!("JWT verification failed: {:?}", e);
-                    AppError::Unauthorized("Invalid or expired token".to_string())
-                })?;
-
-                // Check token expiration explicitly
-                if let Some(exp) = claims.exp {
-                    let now = chrono::Utc::now().timestamp();
-                    if now > exp {
-                        tracing::debug!("Token expired at {}, current time {}", exp, now);
-                        return Err(AppError::Unauthorized("Token expired".to_string()).into());
-                    }
-                }
+            let token = token.or_else(|| req.cookie("token").map(|c| c.value().to_string()));
+
+            // Without a token, admit the request as a guest if guest mode allows this route
+            let Some(token) = token else {
+                let user = state.guest_access.authorize(&req)?;
+                req.extensions_mut().insert(AuthUser::new(user));
 
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_id(&claims.sub)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?
+                let res = service.call(req).await?;
+                return Ok(res);
             };
 
+            let (auth_user, _) = authenticate_token(state, &token).await?;
+            reject_impersonated_write(&req, &auth_user)?;
+            reject_pending_mfa_setup(&req, &auth_user)?;
+
             // Insert user into request extensions
-            req.extensions_mut().insert(AuthUser { user });
+            req.extensions_mut().insert(auth_user);
 
             let res = service.call(req).await?;
             Ok(res)
@@ -223,60 +319,29 @@ where
             };
 
             // If no Authorization header, try to get token from cookie
-            let token = token
-                .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
-                .ok_or_else(|| AppError::Unauthorized("Missing authorization token".to_string()))?;
-
-            // Check if it's an API key (starts with sk-)
-            let user = if token.starts_with("sk-") {
-                let config = state.config.read().unwrap();
-                if !config.enable_api_key {
-                    return Err(AppError::Forbidden("API keys are disabled".to_string()).into());
+            let token = token.or_else(|| req.cookie("token").map(|c| c.value().to_string()));
+
+            // Reuse the caller RouteAuth attached, if any
+            let existing = req.extensions().get::<AuthUser>().cloned();
+            let auth_user = match (existing, token) {
+                (Some(auth_user), _) => auth_user,
+                (None, Some(token)) => authenticate_token(state, &token).await?.0,
+                (None, None) => {
+                    return Err(
+                        AppError::Unauthorized("Missing authorization token".to_string()).into(),
+                    )
                 }
-                drop(config); // Release the lock
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_api_key(&token)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?
-            } else {
-                // Otherwise, verify JWT token
-                let config = state.config.read().unwrap();
-                let webui_secret_key = config.webui_secret_key.clone();
-                drop(config); // Release the lock
-
-                let claims = verify_jwt(&token, &webui_secret_key).map_err(|e| {
-                    // Token verification failed (expired or invalid)
-                    tracing::debug!("JWT verification failed: {:?}", e);
-                    AppError::Unauthorized("Invalid or expired token".to_string())
-                })?;
-
-                // Check token expiration explicitly
-                if let Some(exp) = claims.exp {
-                    let now = chrono::Utc::now().timestamp();
-                    if now > exp {
-                        tracing::debug!("Token expired at {}, current time {}", exp, now);
-                        return Err(AppError::Unauthorized("Token expired".to_string()).into());
-                    }
-                }
-
-                let user_service = UserService::new(&state.db);
-                user_service
-                    .get_user_by_id(&claims.sub)
-                    .await
-                    .map_err(|e| AppError::from(e))?
-                    .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?
             };
 
-            // Check if user is admin
-            if user.role != "admin" {
+            reject_pending_mfa_setup(&req, &auth_user)?;
+
+            // Check if user is admin; impersonation never grants admin access
+            if auth_user.user.role != "admin" || auth_user.impersonated_by.is_some() {
                 return Err(AppError::Forbidden("Admin access required".to_string()).into());
             }
 
             // Insert user into request extensions
-            req.extensions_mut().insert(AuthUser { user });
+            req.extensions_mut().insert(auth_user);
 
             let res = service.call(req).await?;
             Ok(res)
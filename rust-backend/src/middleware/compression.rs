@@ -0,0 +1,197 @@
+use actix_http::encoding::Encoder;
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::header::{self, AcceptEncoding, ContentEncoding, Encoding, Preference, Quality},
+    HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::config::Config;
+
+/// Response compression with a size threshold and server-side algorithm preference
+///
+/// Replaces `actix_web::middleware::Compress`, which compresses every response including
+/// SSE streams, where encoder buffering delays token delivery.
+#[derive(Clone)]
+pub struct Compression {
+    inner: Arc<CompressionPolicy>,
+}
+
+struct CompressionPolicy {
+    min_size: u64,
+    algorithms: Vec<ContentEncoding>,
+}
+
+impl Compression {
+    pub fn from_config(config: &Config) -> Self {
+        let algorithms = config
+            .compression_algorithms
+            .iter()
+            .filter_map(|name| {
+                let encoding = parse_algorithm(name);
+                if encoding.is_none() {
+                    tracing::warn!("Ignoring unsupported compression algorithm: {}", name);
+                }
+                encoding
+            })
+            .collect();
+
+        Self {
+            inner: Arc::new(CompressionPolicy {
+                min_size: config.compression_min_size as u64,
+                algorithms,
+            }),
+        }
+    }
+}
+
+fn parse_algorithm(name: &str) -> Option<ContentEncoding> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "br" | "brotli" => Some(ContentEncoding::Brotli),
+        "zstd" => Some(ContentEncoding::Zstd),
+        "gzip" => Some(ContentEncoding::Gzip),
+        "deflate" => Some(ContentEncoding::Deflate),
+        _ => None,
+    }
+}
+
+impl CompressionPolicy {
+    /// Pick the first configured algorithm the client accepts
+    fn negotiate(&self, accept: Option<&AcceptEncoding>) -> ContentEncoding {
+        let Some(accept) = accept else {
+            return ContentEncoding::Identity;
+        };
+
+        self.algorithms
+            .iter()
+            .copied()
+            .find(|algorithm| {
+                accept.0.iter().any(|item| {
+                    item.quality > Quality::ZERO
+                        && match &item.item {
+                            Preference::Any => true,
+                            Preference::Specific(Encoding::Known(known)) => known == algorithm,
+                            Preference::Specific(_) => false,
+                        }
+                })
+            })
+            .unwrap_or(ContentEncoding::Identity)
+    }
+
+    /// Whether a response is worth compressing
+    fn should_compress(&self, content_type: Option<&str>, size: BodySize) -> bool {
+        if let Some(content_type) = content_type {
+            let mime = content_type.to_ascii_lowercase();
+            // Streams must be flushed per event; media is already compressed
+            if mime.starts_with("text/event-stream")
+                || mime.starts_with("video/")
+                || (mime.starts_with("image/") && !mime.starts_with("image/svg"))
+            {
+                return false;
+            }
+        }
+
+        match size {
+            BodySize::Sized(len) => len >= self.min_size,
+            BodySize::Stream => true,
+            BodySize::None => false,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Compression
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<Encoder<B>>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = CompressionService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CompressionService {
+            service: Rc::new(service),
+            policy: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct CompressionService<S> {
+    service: Rc<S>,
+    policy: Arc<CompressionPolicy>,
+}
+
+impl<S, B> Service<ServiceRequest> for CompressionService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<Encoder<B>>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let policy = self.policy.clone();
+        let encoding = policy.negotiate(req.get_header::<AcceptEncoding>().as_ref());
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+
+            Ok(res.map_body(move |head, body| {
+                let content_type = head
+                    .headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok());
+
+                let encoding = if policy.should_compress(content_type, body.size()) {
+                    encoding
+                } else {
+                    ContentEncoding::Identity
+                };
+
+                Encoder::response(encoding, head, body)
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> CompressionPolicy {
+        CompressionPolicy {
+            min_size: 1024,
+            algorithms: vec![ContentEncoding::Brotli, ContentEncoding::Gzip],
+        }
+    }
+
+    #[test]
+    fn test_prefers_configured_order() {
+        let accept: AcceptEncoding =
+            AcceptEncoding(vec!["gzip".parse().unwrap(), "br;q=0.5".parse().unwrap()]);
+        assert_eq!(policy().negotiate(Some(&accept)), ContentEncoding::Brotli);
+
+        let accept = AcceptEncoding(vec!["deflate".parse().unwrap()]);
+        assert_eq!(policy().negotiate(Some(&accept)), ContentEncoding::Identity);
+    }
+
+    #[test]
+    fn test_skips_streams_and_small_bodies() {
+        let policy = policy();
+        assert!(!policy.should_compress(Some("text/event-stream; charset=utf-8"), BodySize::Stream));
+        assert!(!policy.should_compress(Some("application/json"), BodySize::Sized(100)));
+        assert!(policy.should_compress(Some("application/json"), BodySize::Sized(4096)));
+    }
+}
@@ -0,0 +1,552 @@
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::header::{self, HeaderName},
+    web, HttpMessage,
+};
+use bytes::Bytes;
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+
+use crate::error::AppError;
+use crate::middleware::auth::AuthUser;
+use crate::AppState;
+
+/// How urgently a request needs a slot when a limiter is saturated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Batch and background work: API-key scripts, embeddings, self-declared low
+    Low,
+    /// Interactive traffic and admins
+    High,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::High => "high",
+        }
+    }
+}
+
+/// Waiters in serving order: highest priority first, then arrival
+type WaiterKey = (Reverse<Priority>, u64);
+
+/// Bounded concurrency limiter with a bounded, priority-ordered wait queue
+///
+/// Waiting high-priority requests get freed slots before low-priority ones. With the
+/// queue full, a high-priority request sheds the newest low-priority waiter (which gets
+/// 429) rather than being turned away. A `max_concurrent` of 0 disables limiting entirely.
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    inner: Arc<LimiterInner>,
+}
+
+struct LimiterInner {
+    name: &'static str,
+    max_concurrent: usize,
+    max_queue: usize,
+    in_flight: AtomicUsize,
+    shed: AtomicU64,
+    rejected: AtomicU64,
+    state: Mutex<LimiterState>,
+}
+
+struct LimiterState {
+    available: usize,
+    next_seq: u64,
+    waiters: BTreeMap<WaiterKey, oneshot::Sender<Slot>>,
+}
+
+/// A concurrency slot; dropping it hands the slot to the next waiter
+struct Slot {
+    limiter: Option<Arc<LimiterInner>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let Some(limiter) = self.limiter.take() else {
+            return;
+        };
+        let next = {
+            let mut state = limiter.state.lock().unwrap();
+            loop {
+                match state.waiters.pop_first() {
+                    Some((_, waiter)) if waiter.is_closed() => continue,
+                    Some((_, waiter)) => break Some(waiter),
+                    None => {
+                        state.available += 1;
+                        break None;
+                    }
+                }
+            }
+        };
+        if let Some(waiter) = next {
+            // A waiter that gave up meanwhile drops the slot, which hands it on again
+            let _ = waiter.send(Slot {
+                limiter: Some(limiter),
+            });
+        }
+    }
+}
+
+/// Held for the duration of a limited request
+pub struct ConcurrencyPermit {
+    _slot: Option<Slot>,
+    limiter: Arc<LimiterInner>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(name: &'static str, max_concurrent: usize, max_queue: usize) -> Self {
+        Self {
+            inner: Arc::new(LimiterInner {
+                name,
+                max_concurrent,
+                max_queue,
+                in_flight: AtomicUsize::new(0),
+                shed: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                state: Mutex::new(LimiterState {
+                    available: max_concurrent,
+                    next_seq: 0,
+                    waiters: BTreeMap::new(),
+                }),
+            }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.inner.name
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.inner.max_concurrent
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn queued(&self) -> usize {
+        self.inner.state.lock().unwrap().waiters.len()
+    }
+
+    /// Requests of `priority` waiting for a slot
+    pub fn queued_with(&self, priority: Priority) -> usize {
+        let state = self.inner.state.lock().unwrap();
+        state
+            .waiters
+            .iter()
+            .filter(|((Reverse(p), _), waiter)| *p == priority && !waiter.is_closed())
+            .count()
+    }
+
+    /// Low-priority waiters dropped to make room for high-priority ones
+    pub fn shed(&self) -> u64 {
+        self.inner.shed.load(Ordering::Relaxed)
+    }
+
+    /// Requests turned away by a full queue
+    pub fn rejected(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+
+    fn busy(&self) -> AppError {
+        AppError::TooManyRequests(format!(
+            "Too many concurrent {} requests, please retry later",
+            self.inner.name
+        ))
+    }
+
+    /// Wait for a slot, failing fast with `TooManyRequests` when the queue is full
+    pub async fn acquire(&self, priority: Priority) -> Result<ConcurrencyPermit, AppError> {
+        if self.inner.max_concurrent == 0 {
+            self.inner.in_flight.fetch_add(1, Ordering::Relaxed);
+            return Ok(ConcurrencyPermit {
+                _slot: None,
+                limiter: self.inner.clone(),
+            });
+        }
+
+        let slot = {
+            let mut state = self.inner.state.lock().unwrap();
+            state.waiters.retain(|_, waiter| !waiter.is_closed());
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                None
+            } else {
+                if state.waiters.len() >= self.inner.max_queue {
+                    // The last waiter is the newest of the lowest priority
+                    let shed = match state.waiters.last_key_value() {
+                        Some(((Reverse(lowest), _), _)) if *lowest < priority => {
+                            state.waiters.pop_last()
+                        }
+                        _ => None,
+                    };
+                    if shed.is_none() {
+                        self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(
+                            "Concurrency limit reached for {} ({} in flight, {} queued)",
+                            self.inner.name,
+                            self.in_flight(),
+                            state.waiters.len()
+                        );
+                        return Err(self.busy());
+                    }
+                    self.inner.shed.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(
+                        "Shed a low-priority {} request for a {}-priority one",
+                        self.inner.name,
+                        priority.as_str()
+                    );
+                }
+
+                let key = (Reverse(priority), state.next_seq);
+                state.next_seq += 1;
+                let (tx, rx) = oneshot::channel();
+                state.waiters.insert(key, tx);
+                Some(rx)
+            }
+        };
+
+        let slot = match slot {
+            None => Slot {
+                limiter: Some(self.inner.clone()),
+            },
+            // Only shedding drops a waiter's sender unanswered
+            Some(rx) => rx.await.map_err(|_| self.busy())?,
+        };
+
+        self.inner.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(ConcurrencyPermit {
+            _slot: Some(slot),
+            limiter: self.inner.clone(),
+        })
+    }
+}
+
+/// Limiters shared across the app, sized from configuration
+#[derive(Clone)]
+pub struct ConcurrencyLimits {
+    pub embeddings: ConcurrencyLimiter,
+    pub upstream: ConcurrencyLimiter,
+    /// REQUEST_PRIORITY_HEADER, through which clients can mark their requests low priority
+    priority_header: Option<HeaderName>,
+}
+
+impl ConcurrencyLimits {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self {
+            embeddings: ConcurrencyLimiter::new(
+                "embeddings",
+                config.max_concurrent_embeddings,
+                config.max_concurrent_queue,
+            ),
+            upstream: ConcurrencyLimiter::new(
+                "upstream",
+                config.max_concurrent_upstream,
+                config.max_concurrent_queue,
+            ),
+            priority_header: HeaderName::try_from(config.request_priority_header.as_str()).ok(),
+        }
+    }
+
+    pub fn all(&self) -> [&ConcurrencyLimiter; 2] {
+        [&self.embeddings, &self.upstream]
+    }
+}
+
+/// The priority a request waits with
+///
+/// A `low` REQUEST_PRIORITY_HEADER always applies. Otherwise admins are high, API-key
+/// callers (scripts and batch jobs) are low, and anything else gets the route's default:
+/// embeddings are background work, chat completions are interactive.
+fn request_priority(
+    req: &ServiceRequest,
+    limits: &ConcurrencyLimits,
+    kind: ConcurrencyLimit,
+) -> Priority {
+    let declared = limits
+        .priority_header
+        .as_ref()
+        .and_then(|name| req.headers().get(name))
+        .and_then(|value| value.to_str().ok());
+    if declared.is_some_and(|value| value.trim().eq_ignore_ascii_case("low")) {
+        return Priority::Low;
+    }
+
+    if let Some(auth) = req.extensions().get::<AuthUser>() {
+        if auth.user.role == "admin" {
+            return Priority::High;
+        }
+    }
+
+    let api_key = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token.starts_with("sk-"));
+    if api_key {
+        return Priority::Low;
+    }
+
+    match kind {
+        ConcurrencyLimit::Embeddings => Priority::Low,
+        ConcurrencyLimit::Upstream => Priority::High,
+    }
+}
+
+/// Response body that keeps the request's slot until it has been sent in full
+///
+/// Streamed completions do most of their upstream work after the handler returns, so the
+/// permit is released at the end of the body, or when the client goes away and the body
+/// is dropped, rather than with the handler.
+struct PermitBody {
+    body: BoxBody,
+    permit: Option<ConcurrencyPermit>,
+}
+
+impl MessageBody for PermitBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let next = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(None | Some(Err(_))) = &next {
+            this.permit.take();
+        }
+        next
+    }
+}
+
+// Concurrency limit middleware factory
+#[derive(Clone, Copy)]
+pub enum ConcurrencyLimit {
+    Embeddings,
+    Upstream,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ConcurrencyLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = ConcurrencyLimitService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ConcurrencyLimitService {
+            service: Rc::new(service),
+            kind: *self,
+        }))
+    }
+}
+
+pub struct ConcurrencyLimitService<S> {
+    service: Rc<S>,
+    kind: ConcurrencyLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for ConcurrencyLimitService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let kind = self.kind;
+
+        Box::pin(async move {
+            let state = req
+                .app_data::<web::Data<AppState>>()
+                .ok_or_else(|| AppError::InternalServerError("App state not found".to_string()))?;
+
+            let limiter = match kind {
+                ConcurrencyLimit::Embeddings => state.concurrency_limits.embeddings.clone(),
+                ConcurrencyLimit::Upstream => state.concurrency_limits.upstream.clone(),
+            };
+
+            let priority = request_priority(&req, &state.concurrency_limits, kind);
+            let permit = limiter.acquire(priority).await?;
+            let res = service.call(req).await?;
+            Ok(res.map_body(|_, body| {
+                BoxBody::new(PermitBody {
+                    body: body.boxed(),
+                    permit: Some(permit),
+                })
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn wait_for_queued(limiter: &ConcurrencyLimiter, count: usize) {
+        for _ in 0..100 {
+            if limiter.queued() == count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("expected {} queued requests", count);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_when_queue_full() {
+        let limiter = ConcurrencyLimiter::new("test", 1, 0);
+
+        let permit = limiter.acquire(Priority::High).await.unwrap();
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(matches!(
+            limiter.acquire(Priority::High).await,
+            Err(AppError::TooManyRequests(_))
+        ));
+
+        drop(permit);
+        assert_eq!(limiter.in_flight(), 0);
+        assert!(limiter.acquire(Priority::High).await.is_ok());
+    }
+
+    async fn next_chunk(body: &mut PermitBody) -> Option<Bytes> {
+        std::future::poll_fn(|cx| Pin::new(&mut *body).poll_next(cx))
+            .await
+            .map(|chunk| chunk.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_permit_held_until_body_sent() {
+        let limiter = ConcurrencyLimiter::new("test", 1, 0);
+        let mut body = PermitBody {
+            body: BoxBody::new("streamed"),
+            permit: Some(limiter.acquire(Priority::High).await.unwrap()),
+        };
+
+        // The handler has returned, but the body is still going out
+        assert_eq!(next_chunk(&mut body).await.unwrap(), "streamed");
+        assert_eq!(limiter.in_flight(), 1);
+        assert!(limiter.acquire(Priority::High).await.is_err());
+
+        assert!(next_chunk(&mut body).await.is_none());
+        assert_eq!(limiter.in_flight(), 0);
+
+        // A client going away mid-stream releases the slot too
+        let body = PermitBody {
+            body: BoxBody::new("streamed"),
+            permit: Some(limiter.acquire(Priority::High).await.unwrap()),
+        };
+        drop(body);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unlimited_when_zero() {
+        let limiter = ConcurrencyLimiter::new("test", 0, 0);
+        let _a = limiter.acquire(Priority::High).await.unwrap();
+        let _b = limiter.acquire(Priority::High).await.unwrap();
+        assert_eq!(limiter.in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_low_priority_queues_behind_high_priority() {
+        let limiter = ConcurrencyLimiter::new("test", 1, 3);
+        let running = limiter.acquire(Priority::High).await.unwrap();
+
+        let served = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for (name, priority) in [
+            ("low-1", Priority::Low),
+            ("low-2", Priority::Low),
+            ("high", Priority::High),
+        ] {
+            let (task_limiter, task_served) = (limiter.clone(), served.clone());
+            waiters.push(tokio::spawn(async move {
+                let permit = task_limiter.acquire(priority).await.unwrap();
+                task_served.lock().unwrap().push(name);
+                drop(permit);
+            }));
+            wait_for_queued(&limiter, waiters.len()).await;
+        }
+        assert_eq!(limiter.queued_with(Priority::Low), 2);
+        assert_eq!(limiter.queued_with(Priority::High), 1);
+
+        drop(running);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*served.lock().unwrap(), vec!["high", "low-1", "low-2"]);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_sheds_newest_low_priority_waiter() {
+        let limiter = ConcurrencyLimiter::new("test", 1, 2);
+        let running = limiter.acquire(Priority::High).await.unwrap();
+
+        let mut low = Vec::new();
+        for _ in 0..2 {
+            let task_limiter = limiter.clone();
+            low.push(tokio::spawn(async move {
+                task_limiter.acquire(Priority::Low).await.map(drop)
+            }));
+            wait_for_queued(&limiter, low.len()).await;
+        }
+
+        // A full queue turns away low priority, but makes room for high priority
+        assert!(matches!(
+            limiter.acquire(Priority::Low).await,
+            Err(AppError::TooManyRequests(_))
+        ));
+        let task_limiter = limiter.clone();
+        let high =
+            tokio::spawn(async move { task_limiter.acquire(Priority::High).await.map(drop) });
+        while limiter.queued_with(Priority::High) == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(limiter.queued_with(Priority::Low), 1);
+        assert_eq!(limiter.shed(), 1);
+        assert_eq!(limiter.rejected(), 1);
+
+        drop(running);
+        high.await.unwrap().unwrap();
+        let second = low.pop().unwrap().await.unwrap();
+        assert!(matches!(second, Err(AppError::TooManyRequests(_))));
+        low.pop().unwrap().await.unwrap().unwrap();
+    }
+}
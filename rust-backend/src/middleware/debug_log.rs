@@ -0,0 +1,398 @@
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::header,
+    web::Bytes,
+    HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use futures::StreamExt;
+use serde_json::Value;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::config::Config;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Field name endings that mark a secret, compared without case or `_`/`-`
+const SECRET_SUFFIXES: &[&str] = &[
+    "token",
+    "apikey",
+    "authorization",
+    "cookie",
+    "credential",
+    "credentials",
+    "privatekey",
+    "passwd",
+];
+
+/// Debug logging of request/response bodies for selected routes
+///
+/// Routes are matched by path prefix from DEBUG_LOG_ROUTES. Bodies are only buffered
+/// when debug logging is enabled; headers and query strings are never logged, secret
+/// fields are redacted and streams are left untouched.
+#[derive(Clone)]
+pub struct DebugLog {
+    inner: Arc<DebugLogPolicy>,
+}
+
+struct DebugLogPolicy {
+    routes: Vec<String>,
+    max_body: usize,
+}
+
+impl DebugLog {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            inner: Arc::new(DebugLogPolicy {
+                routes: config.debug_log_routes.clone(),
+                max_body: config.debug_log_max_body,
+            }),
+        }
+    }
+}
+
+impl DebugLogPolicy {
+    fn matches(&self, path: &str) -> bool {
+        self.routes
+            .iter()
+            .any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Loggable form of a body: secrets redacted, capped at `max_body` bytes
+    fn render(&self, content_type: Option<&str>, body: &[u8]) -> String {
+        if body.is_empty() {
+            return "<empty>".to_string();
+        }
+
+        let mime = content_type.unwrap_or("").to_ascii_lowercase();
+        let rendered = if mime.contains("json") {
+            match serde_json::from_slice::<Value>(body) {
+                Ok(mut value) => {
+                    redact_json(&mut value);
+                    value.to_string()
+                }
+                // Unparseable JSON may still hold secrets, so don't echo it
+                Err(_) => format!("<{} bytes of invalid JSON>", body.len()),
+            }
+        } else if mime.starts_with("application/x-www-form-urlencoded") {
+            redact_form(&String::from_utf8_lossy(body))
+        } else {
+            match std::str::from_utf8(body) {
+                Ok(text) => text.to_string(),
+                Err(_) => return format!("<{} bytes binary>", body.len()),
+            }
+        };
+
+        truncate(rendered, self.max_body)
+    }
+}
+
+fn is_secret_field(key: &str) -> bool {
+    let key = key
+        .chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .collect::<String>()
+        .to_ascii_lowercase();
+    key.contains("password")
+        || key.contains("secret")
+        || SECRET_SUFFIXES.iter().any(|suffix| key.ends_with(suffix))
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_field(key) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+fn redact_form(body: &str) -> String {
+    body.split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if is_secret_field(key) => format!("{}={}", key, REDACTED),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn truncate(mut text: String, max: usize) -> String {
+    if text.len() <= max {
+        return text;
+    }
+    let total = text.len();
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    format!("{}... ({} bytes total)", text, total)
+}
+
+fn content_type(headers: &header::HeaderMap) -> Option<String> {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+}
+
+/// Buffer a request body and put it back so the handler can still read it
+///
+/// Multipart uploads are not buffered.
+async fn capture_request_body(
+    req: &mut ServiceRequest,
+    content_type: Option<&str>,
+) -> Result<Option<Bytes>, ActixError> {
+    if content_type.is_some_and(|ct| ct.to_ascii_lowercase().starts_with("multipart/")) {
+        return Ok(None);
+    }
+
+    let mut payload = req.take_payload();
+    let mut body = actix_web::web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+    let body = body.freeze();
+
+    let (_, mut restored) = actix_http::h1::Payload::create(true);
+    restored.unread_data(body.clone());
+    req.set_payload(Payload::from(restored));
+
+    Ok(Some(body))
+}
+
+impl<S, B> Transform<S, ServiceRequest> for DebugLog
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = DebugLogService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(DebugLogService {
+            service: Rc::new(service),
+            policy: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct DebugLogService<S> {
+    service: Rc<S>,
+    policy: Arc<DebugLogPolicy>,
+}
+
+impl<S, B> Service<ServiceRequest> for DebugLogService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            if !policy.matches(req.path()) || !tracing::enabled!(tracing::Level::DEBUG) {
+                return service
+                    .call(req)
+                    .await
+                    .map(ServiceResponse::map_into_boxed_body);
+            }
+
+            let method = req.method().clone();
+            let path = req.path().to_string();
+            let request_type = content_type(req.headers());
+            let request_body = match capture_request_body(&mut req, request_type.as_deref()).await?
+            {
+                Some(body) => policy.render(request_type.as_deref(), &body),
+                None => "<not captured>".to_string(),
+            };
+
+            let started = Instant::now();
+            let res = match service.call(req).await {
+                Ok(res) => res,
+                Err(e) => {
+                    tracing::debug!(
+                        "{} {} -> {} in {}ms request={} response=<error: {}>",
+                        method,
+                        path,
+                        e.as_response_error().status_code().as_u16(),
+                        started.elapsed().as_millis(),
+                        request_body,
+                        e
+                    );
+                    return Err(e);
+                }
+            };
+            let elapsed = started.elapsed().as_millis();
+            let status = res.status().as_u16();
+            let response_type = content_type(res.headers());
+
+            // Streams are logged without their body so delivery isn't held up
+            let is_stream = response_type
+                .as_deref()
+                .is_some_and(|ct| ct.starts_with("text/event-stream"))
+                || !matches!(
+                    res.response().body().size(),
+                    BodySize::Sized(_) | BodySize::None
+                );
+            let (res, response_body) = if is_stream {
+                (res.map_into_boxed_body(), "<stream>".to_string())
+            } else {
+                let (http_req, res) = res.map_into_boxed_body().into_parts();
+                let (res, body) = res.into_parts();
+                let body = actix_web::body::to_bytes(body)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+                let rendered = policy.render(response_type.as_deref(), &body);
+                let res = ServiceResponse::new(http_req, res.set_body(body).map_into_boxed_body());
+                (res, rendered)
+            };
+
+            tracing::debug!(
+                "{} {} -> {} in {}ms request={} response={}",
+                method,
+                path,
+                status,
+                elapsed,
+                request_body,
+                response_body
+            );
+
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_and_read_body_json, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Collects formatted log output
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Capture {
+        type Writer = Capture;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn debug_log(routes: &[&str]) -> DebugLog {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.debug_log_routes = routes.iter().map(|r| r.to_string()).collect();
+        DebugLog::from_config(&config)
+    }
+
+    #[actix_web::test]
+    async fn test_signin_password_not_logged() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(capture.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = init_service(App::new().wrap(debug_log(&["/signin"])).route(
+            "/signin",
+            web::post().to(|body: web::Json<Value>| async move {
+                HttpResponse::Ok().json(json!({
+                    "email": body["email"],
+                    "token": "issued-jwt-value",
+                }))
+            }),
+        ))
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/signin")
+            .insert_header((header::AUTHORIZATION, "Bearer header-secret"))
+            .set_json(json!({ "email": "a@example.com", "password": "hunter2-secret" }))
+            .to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+
+        // The handler still saw the full body
+        assert_eq!(body["email"], "a@example.com");
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("POST /signin -> 200"));
+        assert!(logs.contains("a@example.com"));
+        assert!(logs.contains(REDACTED));
+        assert!(!logs.contains("hunter2-secret"));
+        assert!(!logs.contains("issued-jwt-value"));
+        assert!(!logs.contains("header-secret"));
+    }
+
+    #[test]
+    fn test_render_redacts_and_caps() {
+        let policy = DebugLogPolicy {
+            routes: Vec::new(),
+            max_body: 16,
+        };
+        assert_eq!(
+            policy.render(
+                Some("application/x-www-form-urlencoded"),
+                b"user=a&client_secret=x"
+            ),
+            "user=a&client_se... (31 bytes total)"
+        );
+
+        let mut value = json!({
+            "access_token": "t",
+            "apiKey": "k",
+            "max_tokens": 10,
+            "nested": [{ "newPassword": "p" }],
+        });
+        redact_json(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "access_token": REDACTED,
+                "apiKey": REDACTED,
+                "max_tokens": 10,
+                "nested": [{ "newPassword": REDACTED }],
+            })
+        );
+    }
+}
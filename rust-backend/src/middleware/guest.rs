@@ -0,0 +1,167 @@
+use actix_web::{dev::ServiceRequest, http::Method};
+use governor::{clock::DefaultClock, state::keyed::DefaultKeyedStateStore, Quota, RateLimiter};
+use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::User;
+
+pub const GUEST_ROLE: &str = "guest";
+pub const GUEST_USER_ID: &str = "guest";
+
+type GuestRateLimiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
+
+/// A route guests may reach; routes without an explicit method are read-only
+#[derive(Debug, Clone)]
+struct GuestRoute {
+    method: Option<Method>,
+    prefix: String,
+}
+
+impl GuestRoute {
+    /// Parse `"/path/prefix"` or `"POST /path/prefix"`
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        match entry.split_once(char::is_whitespace) {
+            Some((method, prefix)) => Some(Self {
+                method: Some(Method::from_bytes(method.to_uppercase().as_bytes()).ok()?),
+                prefix: prefix.trim().to_string(),
+            }),
+            None if entry.starts_with('/') => Some(Self {
+                method: None,
+                prefix: entry.to_string(),
+            }),
+            None => None,
+        }
+    }
+
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let method_allowed = match &self.method {
+            Some(m) => m == method,
+            None => method == Method::GET || method == Method::HEAD,
+        };
+        method_allowed && path.starts_with(&self.prefix)
+    }
+}
+
+/// Anonymous guest access, sized from configuration at startup
+#[derive(Clone)]
+pub struct GuestAccess {
+    enabled: bool,
+    routes: Vec<GuestRoute>,
+    limiter: Option<Arc<GuestRateLimiter>>,
+}
+
+impl GuestAccess {
+    pub fn from_config(config: &Config) -> Self {
+        let routes: Vec<GuestRoute> = config
+            .guest_allowed_routes
+            .iter()
+            .filter_map(|entry| {
+                let route = GuestRoute::parse(entry);
+                if route.is_none() {
+                    tracing::warn!("Ignoring invalid GUEST_ALLOWED_ROUTES entry: {}", entry);
+                }
+                route
+            })
+            .collect();
+
+        let limiter = NonZeroU32::new(config.guest_rate_limit_per_minute)
+            .map(|limit| Arc::new(RateLimiter::keyed(Quota::per_minute(limit))));
+
+        Self {
+            enabled: config.guest_mode,
+            routes,
+            limiter,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether an unauthenticated request may proceed as a guest
+    pub fn allows(&self, method: &Method, path: &str) -> bool {
+        // Admin endpoints are never reachable as a guest, whatever the allowlist says
+        if !self.enabled || path.split('/').any(|segment| segment == "admin") {
+            return false;
+        }
+        self.routes.iter().any(|route| route.matches(method, path))
+    }
+
+    /// Admit an unauthenticated request as the guest user, or reject it
+    pub fn authorize(&self, req: &ServiceRequest) -> Result<User, AppError> {
+        if !self.allows(req.method(), req.path()) {
+            return Err(AppError::Unauthorized(
+                "Missing authorization token".to_string(),
+            ));
+        }
+
+        if let Some(limiter) = &self.limiter {
+            let client = req
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or("unknown")
+                .to_string();
+            if limiter.check_key(&client).is_err() {
+                return Err(AppError::TooManyRequests(
+                    "Guest rate limit exceeded, please sign in or retry later".to_string(),
+                ));
+            }
+        }
+
+        Ok(guest_user())
+    }
+}
+
+/// Synthetic identity attached to admitted guest requests
+pub fn guest_user() -> User {
+    let now = chrono::Utc::now().timestamp();
+    User {
+        id: GUEST_USER_ID.to_string(),
+        name: "Guest".to_string(),
+        email: String::new(),
+        username: None,
+        role: GUEST_ROLE.to_string(),
+        profile_image_url: "/user.png".to_string(),
+        bio: None,
+        gender: None,
+        date_of_birth: None,
+        info: None,
+        settings: None,
+        api_key: None,
+        oauth_sub: None,
+        last_active_at: now,
+        updated_at: now,
+        created_at: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(routes: &[&str]) -> GuestAccess {
+        GuestAccess {
+            enabled: true,
+            routes: routes.iter().filter_map(|r| GuestRoute::parse(r)).collect(),
+            limiter: None,
+        }
+    }
+
+    #[test]
+    fn test_routes_default_to_read_only() {
+        let guest = access(&["/api/v1/chats/share/"]);
+        assert!(guest.allows(&Method::GET, "/api/v1/chats/share/abc"));
+        assert!(!guest.allows(&Method::POST, "/api/v1/chats/share/abc"));
+        assert!(!guest.allows(&Method::GET, "/api/v1/chats/abc"));
+    }
+
+    #[test]
+    fn test_explicit_method_and_admin_denied() {
+        let guest = access(&["POST /api/chat/completions", "/api/v1/admin"]);
+        assert!(guest.allows(&Method::POST, "/api/chat/completions"));
+        assert!(!guest.allows(&Method::GET, "/api/v1/admin/usage"));
+    }
+}
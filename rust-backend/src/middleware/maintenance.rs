@@ -0,0 +1,206 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::{header, Method},
+    web,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::middleware::auth::{authenticate_token, AuthUser};
+use crate::AppState;
+
+/// Retry-After sent when the maintenance window has no scheduled end
+const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// Write routes that stay open so admins can sign in during maintenance
+const SIGNIN_PATHS: &[&str] = &[
+    "/api/v1/auths/signin",
+    "/api/v1/auths/ldap",
+    "/api/v1/auths/mfa/challenge",
+];
+
+/// Seconds until maintenance ends if it is currently active
+///
+/// A window whose `maintenance_until` has passed is treated as over, so a forgotten
+/// flag can't block writes indefinitely.
+pub fn maintenance_retry_after(config: &Config, now: i64) -> Option<u64> {
+    if !config.maintenance_mode {
+        return None;
+    }
+    match config.maintenance_until {
+        Some(until) if until <= now => None,
+        Some(until) => Some((until - now) as u64),
+        None => Some(DEFAULT_RETRY_AFTER_SECS),
+    }
+}
+
+/// Whether a request is allowed through regardless of the caller's role
+fn is_exempt(method: &Method, path: &str) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) || SIGNIN_PATHS.contains(&path)
+}
+
+/// Only a fully signed-in admin acting as themselves may write during maintenance
+fn bypasses_maintenance(auth_user: &AuthUser) -> bool {
+    auth_user.user.role == "admin"
+        && auth_user.impersonated_by.is_none()
+        && !auth_user.mfa_setup_pending
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .map(String::from)
+        .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
+}
+
+/// Read-only mode: while maintenance is active, non-admin writes get 503 with Retry-After
+///
+/// Reads and admin requests pass through. The caller is only authenticated for writes
+/// during an active window, so the normal path costs a config read.
+pub struct Maintenance;
+
+impl<S, B> Transform<S, ServiceRequest> for Maintenance
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = MaintenanceMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MaintenanceMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct MaintenanceMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MaintenanceMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+                return service.call(req).await;
+            };
+
+            if is_exempt(req.method(), req.path()) {
+                return service.call(req).await;
+            }
+
+            let active = {
+                let config = state.config.read().unwrap();
+                maintenance_retry_after(&config, chrono::Utc::now().timestamp())
+                    .map(|retry_after| (retry_after, config.maintenance_message.clone()))
+            };
+            let Some((retry_after, message)) = active else {
+                return service.call(req).await;
+            };
+
+            let is_admin = match bearer_token(&req) {
+                Some(token) => authenticate_token(&state, &token)
+                    .await
+                    .map(|(auth_user, _)| bypasses_maintenance(&auth_user))
+                    .unwrap_or(false),
+                None => false,
+            };
+            if is_admin {
+                return service.call(req).await;
+            }
+
+            Err(AppError::ServiceUnavailable {
+                message,
+                retry_after,
+            }
+            .into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(maintenance_mode: bool, maintenance_until: Option<i64>) -> Config {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.maintenance_mode = maintenance_mode;
+        config.maintenance_until = maintenance_until;
+        config
+    }
+
+    #[test]
+    fn test_retry_after_tracks_window_end() {
+        assert_eq!(maintenance_retry_after(&config(false, None), 100), None);
+        assert_eq!(
+            maintenance_retry_after(&config(true, None), 100),
+            Some(DEFAULT_RETRY_AFTER_SECS)
+        );
+        assert_eq!(
+            maintenance_retry_after(&config(true, Some(160)), 100),
+            Some(60)
+        );
+        // Expired windows no longer block writes
+        assert_eq!(maintenance_retry_after(&config(true, Some(100)), 100), None);
+    }
+
+    #[test]
+    fn test_reads_and_signin_are_exempt() {
+        assert!(is_exempt(&Method::GET, "/api/v1/chats/"));
+        assert!(is_exempt(&Method::OPTIONS, "/api/v1/chats/new"));
+        assert!(is_exempt(&Method::POST, "/api/v1/auths/signin"));
+        assert!(is_exempt(&Method::POST, "/api/v1/auths/mfa/challenge"));
+        assert!(!is_exempt(&Method::POST, "/api/v1/chats/new"));
+        assert!(!is_exempt(&Method::POST, "/api/v1/auths/signup"));
+    }
+
+    #[test]
+    fn test_only_full_admin_sessions_bypass() {
+        let user = |role: &str| -> crate::models::User {
+            serde_json::from_value(serde_json::json!({
+                "id": "user-1",
+                "name": "Admin",
+                "email": "admin@example.com",
+                "role": role,
+                "profile_image_url": "/user.png",
+                "last_active_at": 0,
+                "updated_at": 0,
+                "created_at": 0,
+            }))
+            .unwrap()
+        };
+
+        assert!(bypasses_maintenance(&AuthUser::new(user("admin"))));
+        assert!(!bypasses_maintenance(&AuthUser::new(user("user"))));
+
+        let mut pending = AuthUser::new(user("admin"));
+        pending.mfa_setup_pending = true;
+        assert!(!bypasses_maintenance(&pending));
+
+        let mut impersonated = AuthUser::new(user("admin"));
+        impersonated.impersonated_by = Some("admin-2".to_string());
+        assert!(!bypasses_maintenance(&impersonated));
+    }
+}
@@ -1,9 +1,32 @@
 pub mod audit;
 pub mod auth;
 pub mod code_interpreter;
+pub mod compression;
+pub mod concurrency;
+pub mod debug_log;
+pub mod guest;
+pub mod maintenance;
+pub mod payload_size;
+pub mod problem_json;
 pub mod rate_limit;
+pub mod readiness;
 pub mod request_id;
+pub mod route_auth;
 pub mod security_headers;
+pub mod server_limits;
+pub mod timeout;
 
 pub use auth::*;
+pub use compression::Compression;
+pub use concurrency::{ConcurrencyLimit, ConcurrencyLimits};
+pub use debug_log::DebugLog;
+pub use guest::GuestAccess;
+pub use maintenance::Maintenance;
+pub use payload_size::{PayloadMetrics, PayloadSize};
+pub use problem_json::ProblemJson;
+pub use readiness::ReadinessGate;
+pub use request_id::RequestId;
+pub use route_auth::RouteAuth;
 pub use security_headers::SecurityHeaders;
+pub use server_limits::ServerLimits;
+pub use timeout::RequestTimeout;
@@ -0,0 +1,351 @@
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    error::{Error as ActixError, PayloadError},
+    http::header,
+    web::Bytes,
+    HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::config::Config;
+use crate::middleware::request_id::request_id;
+
+/// Upper bounds of the size histogram buckets, in bytes
+const BUCKETS: &[u64] = &[
+    1 << 10,
+    16 << 10,
+    256 << 10,
+    1 << 20,
+    4 << 20,
+    16 << 20,
+    64 << 20,
+];
+
+/// Prometheus-style histogram of body sizes
+#[derive(Default)]
+pub struct SizeHistogram {
+    /// Per-bucket counts, the last one for sizes above every bound
+    counts: [AtomicU64; BUCKETS.len() + 1],
+    sum: AtomicU64,
+}
+
+impl SizeHistogram {
+    pub fn observe(&self, bytes: u64) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| bytes <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Cumulative counts per upper bound; `None` is +Inf
+    pub fn buckets(&self) -> Vec<(Option<u64>, u64)> {
+        let mut total = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count.load(Ordering::Relaxed);
+                (BUCKETS.get(i).copied(), total)
+            })
+            .collect()
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+}
+
+/// Request and response body sizes seen by [`PayloadSize`], exported on /api/metrics
+#[derive(Default)]
+pub struct PayloadMetrics {
+    pub requests: SizeHistogram,
+    pub responses: SizeHistogram,
+}
+
+/// Records request and response body sizes and warns about large ones
+///
+/// Bodies are counted as they stream, so nothing is buffered and streamed responses are
+/// recorded once they finish. Bodies above LARGE_REQUEST_WARN_BYTES or
+/// LARGE_RESPONSE_WARN_BYTES (0 = never) are logged with the request id, giving
+/// visibility into big payloads that are still under the hard body limits.
+#[derive(Clone)]
+pub struct PayloadSize {
+    inner: Arc<PayloadSizePolicy>,
+}
+
+struct PayloadSizePolicy {
+    metrics: Arc<PayloadMetrics>,
+    request_warn_bytes: u64,
+    response_warn_bytes: u64,
+}
+
+impl PayloadSize {
+    pub fn new(metrics: Arc<PayloadMetrics>, config: &Config) -> Self {
+        Self {
+            inner: Arc::new(PayloadSizePolicy {
+                metrics,
+                request_warn_bytes: config.large_request_warn_bytes,
+                response_warn_bytes: config.large_response_warn_bytes,
+            }),
+        }
+    }
+}
+
+/// What a size warning says about the request
+struct RequestLabel {
+    method: String,
+    path: String,
+    request_id: String,
+}
+
+impl PayloadSizePolicy {
+    fn record_request(&self, label: &RequestLabel, bytes: u64) {
+        self.metrics.requests.observe(bytes);
+        if self.request_warn_bytes > 0 && bytes > self.request_warn_bytes {
+            tracing::warn!(
+                "Large request body: {} {} sent {} bytes (warn above {}) request_id={}",
+                label.method,
+                label.path,
+                bytes,
+                self.request_warn_bytes,
+                label.request_id
+            );
+        }
+    }
+
+    fn record_response(&self, label: &RequestLabel, bytes: u64) {
+        self.metrics.responses.observe(bytes);
+        if self.response_warn_bytes > 0 && bytes > self.response_warn_bytes {
+            tracing::warn!(
+                "Large response body: {} {} returned {} bytes (warn above {}) request_id={}",
+                label.method,
+                label.path,
+                bytes,
+                self.response_warn_bytes,
+                label.request_id
+            );
+        }
+    }
+}
+
+/// Response body that records its size once sent or dropped
+struct CountedBody {
+    body: BoxBody,
+    bytes: u64,
+    policy: Arc<PayloadSizePolicy>,
+    label: Option<RequestLabel>,
+}
+
+impl MessageBody for CountedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        let next = Pin::new(&mut this.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &next {
+            this.bytes += chunk.len() as u64;
+        }
+        next
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if let Some(label) = self.label.take() {
+            self.policy.record_response(&label, self.bytes);
+        }
+    }
+}
+
+fn content_length(req: &ServiceRequest) -> u64 {
+    req.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0)
+}
+
+impl<S, B> Transform<S, ServiceRequest> for PayloadSize
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = PayloadSizeService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PayloadSizeService {
+            service: Rc::new(service),
+            policy: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct PayloadSizeService<S> {
+    service: Rc<S>,
+    policy: Arc<PayloadSizePolicy>,
+}
+
+impl<S, B> Service<ServiceRequest> for PayloadSizeService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            let label = RequestLabel {
+                method: req.method().to_string(),
+                path: req.path().to_string(),
+                request_id: request_id(&req).unwrap_or_else(|| "-".to_string()),
+            };
+            let declared = content_length(&req);
+
+            // Count the body as the handler reads it
+            let received = Arc::new(AtomicU64::new(0));
+            let counter = received.clone();
+            let payload = req.take_payload().inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+            });
+            let payload: Pin<Box<dyn Stream<Item = Result<Bytes, PayloadError>>>> =
+                Box::pin(payload);
+            req.set_payload(Payload::from(payload));
+
+            let res = service.call(req).await;
+            // A body rejected before it was read still counts at its declared length
+            policy.record_request(&label, received.load(Ordering::Relaxed).max(declared));
+
+            let res = res?;
+            Ok(res.map_body(move |_, body| {
+                BoxBody::new(CountedBody {
+                    body: body.boxed(),
+                    bytes: 0,
+                    policy,
+                    label: Some(label),
+                })
+            }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::request_id::{RequestId, REQUEST_ID_HEADER};
+    use actix_web::test::{call_and_read_body, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use std::sync::Mutex;
+
+    /// Collects formatted log output
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Capture {
+        type Writer = Capture;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_large_request_is_logged_and_measured() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(capture.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.large_request_warn_bytes = 1024;
+        config.large_response_warn_bytes = 0;
+        let metrics = Arc::new(PayloadMetrics::default());
+        let app = init_service(
+            App::new()
+                .wrap(PayloadSize::new(metrics.clone(), &config))
+                .wrap(RequestId)
+                .route(
+                    "/upload",
+                    web::post().to(|body: Bytes| async move {
+                        HttpResponse::Ok().body(body.len().to_string())
+                    }),
+                ),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/upload")
+            .insert_header((REQUEST_ID_HEADER, "trace-123"))
+            .set_payload(vec![b'x'; 4096])
+            .to_request();
+        let body = call_and_read_body(&app, req).await;
+        // The handler still got the whole body
+        assert_eq!(body, "4096");
+
+        let small = TestRequest::post()
+            .uri("/upload")
+            .set_payload(vec![b'x'; 100])
+            .to_request();
+        call_and_read_body(&app, small).await;
+
+        assert_eq!(metrics.requests.count(), 2);
+        assert_eq!(metrics.requests.sum(), 4196);
+        assert_eq!(metrics.requests.buckets()[0], (Some(1024), 1));
+        assert_eq!(metrics.requests.buckets()[1], (Some(16 << 10), 2));
+        assert_eq!(metrics.responses.count(), 2);
+        assert_eq!(metrics.responses.sum(), 7);
+
+        let logs = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(logs.matches("Large request body").count(), 1);
+        assert!(logs.contains("POST /upload sent 4096 bytes"));
+        assert!(logs.contains("request_id=trace-123"));
+        assert!(!logs.contains("Large response body"));
+    }
+}
@@ -0,0 +1,224 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::{
+        header::{self, Header},
+        StatusCode,
+    },
+    HttpRequest, HttpResponse, ResponseError,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::{AppError, PROBLEM_JSON};
+
+/// Whether the client prefers RFC 7807 errors over the `{detail}` shape
+///
+/// `application/problem+json` has to rank above `application/json` and `*/*`, so
+/// clients that accept anything keep getting the existing shape.
+fn prefers_problem_json(req: &HttpRequest) -> bool {
+    let Ok(accept) = header::Accept::parse(req) else {
+        return false;
+    };
+    for mime in accept.ranked() {
+        match mime.essence_str() {
+            PROBLEM_JSON => return true,
+            "application/json" | "*/*" => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// An [`AppError`] from inner middleware, rendered as a problem
+#[derive(Debug)]
+struct Problem {
+    error: ActixError,
+    instance: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl ResponseError for Problem {
+    fn status_code(&self) -> StatusCode {
+        self.error.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self.error.as_error::<AppError>() {
+            Some(e) => {
+                e.log();
+                e.problem_response(&self.instance)
+            }
+            None => self.error.error_response(),
+        }
+    }
+}
+
+/// Renders [`AppError`]s as `application/problem+json` for clients that ask for it
+///
+/// Errors returned by handlers and by inner middleware are both covered; other
+/// responses, and errors that aren't `AppError`s, pass through unchanged.
+pub struct ProblemJson;
+
+impl<S, B> Transform<S, ServiceRequest> for ProblemJson
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = ProblemJsonMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ProblemJsonMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ProblemJsonMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ProblemJsonMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            if !prefers_problem_json(req.request()) {
+                return service.call(req).await.map(|res| res.map_into_boxed_body());
+            }
+
+            let instance = req.path().to_string();
+            let res = match service.call(req).await {
+                Ok(res) => res,
+                // Rendered at the end of the chain, which would use the `{detail}` shape
+                Err(e) => {
+                    if e.as_error::<AppError>().is_none() {
+                        return Err(e);
+                    }
+                    return Err(Problem { error: e, instance }.into());
+                }
+            };
+
+            let problem = res
+                .response()
+                .error()
+                .and_then(|e| e.as_error::<AppError>())
+                .map(|e| e.problem_response(&instance));
+            Ok(match problem {
+                Some(problem) => res.into_response(problem),
+                None => res.map_into_boxed_body(),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use actix_web::test::{
+        call_service, init_service, read_body_json, try_call_service, TestRequest,
+    };
+    use actix_web::{web, App, HttpResponse};
+
+    async fn missing() -> Result<HttpResponse, AppError> {
+        Err(AppError::NotFound("Chat not found".to_string()))
+    }
+
+    #[actix_web::test]
+    async fn test_error_shape_follows_accept_header() {
+        let app = init_service(
+            App::new()
+                .wrap(ProblemJson)
+                .route("/chats/{id}", web::get().to(missing)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/chats/42")
+            .insert_header((
+                header::ACCEPT,
+                "application/problem+json, application/json;q=0.5",
+            ))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_TYPE).unwrap(),
+            PROBLEM_JSON
+        );
+        let body: serde_json::Value = read_body_json(resp).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "urn:open-webui:problem:not-found",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Chat not found",
+                "instance": "/chats/42",
+            })
+        );
+
+        for accept in ["application/json", "*/*"] {
+            let req = TestRequest::get()
+                .uri("/chats/42")
+                .insert_header((header::ACCEPT, accept))
+                .to_request();
+            let resp = call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            let body: serde_json::Value = read_body_json(resp).await;
+            assert_eq!(body, serde_json::json!({ "detail": "Chat not found" }));
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_inner_middleware_error_becomes_problem() {
+        let app = init_service(
+            App::new().wrap(ProblemJson).service(
+                web::scope("/admin")
+                    .wrap_fn(|_, _| async {
+                        Err::<ServiceResponse, _>(
+                            AppError::Forbidden("Admin access required".to_string()).into(),
+                        )
+                    })
+                    .route("/users", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/admin/users")
+            .insert_header((header::ACCEPT, PROBLEM_JSON))
+            .to_request();
+        let error = try_call_service(&app, req).await.unwrap_err();
+        let resp = error.error_response();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let body = to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:open-webui:problem:forbidden");
+        assert_eq!(body["instance"], "/admin/users");
+    }
+}
@@ -0,0 +1,71 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    web,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use crate::error::AppError;
+use crate::utils::readiness;
+use crate::AppState;
+
+/// With BLOCK_UNTIL_READY, everything but the health checks gets 503 until startup
+/// tasks complete
+pub struct ReadinessGate;
+
+impl<S, B> Transform<S, ServiceRequest> for ReadinessGate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = ReadinessGateMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ReadinessGateMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct ReadinessGateMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ReadinessGateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let blocked = req.app_data::<web::Data<AppState>>().is_some_and(|state| {
+                state.config.read().unwrap().block_until_ready
+                    && !state.readiness.is_ready()
+                    && !readiness::is_exempt(req.path())
+            });
+            if blocked {
+                return Err(AppError::ServiceUnavailable {
+                    message: "The server is starting up. Please try again shortly.".to_string(),
+                    retry_after: readiness::RETRY_AFTER_SECS,
+                }
+                .into());
+            }
+            service.call(req).await
+        })
+    }
+}
@@ -0,0 +1,470 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+    http::header,
+    web, HttpMessage,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::middleware::auth::{
+    authenticate_token, reject_impersonated_write, reject_pending_mfa_setup, AuthUser,
+};
+use crate::AppState;
+
+/// What a caller needs to reach a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthLevel {
+    /// Public; the handler authenticates the caller itself if it needs to
+    None,
+    /// Any signed-in user (or a guest, where guest mode allows the route)
+    User,
+    Admin,
+    /// Signed-in user, and API keys work even with ENABLE_API_KEY_ENDPOINT_RESTRICTIONS
+    ApiKeyAllowed,
+}
+
+impl AuthLevel {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" | "public" => Some(Self::None),
+            "user" => Some(Self::User),
+            "admin" => Some(Self::Admin),
+            "api_key_allowed" => Some(Self::ApiKeyAllowed),
+            _ => None,
+        }
+    }
+}
+
+/// Routes that don't need a plain signed-in user; everything else does
+///
+/// Patterns match whole path segments: `{name}` stands for one segment and a trailing
+/// `/*` for any number. The most specific match wins, so a new route is only public
+/// once it is listed here or in ROUTE_AUTH_OVERRIDES.
+const ROUTE_AUTH: &[(&str, AuthLevel)] = &[
+    ("/health", AuthLevel::None),
+    ("/health/db", AuthLevel::None),
+    ("/api/config", AuthLevel::None),
+    ("/api/version", AuthLevel::None),
+    ("/api/version/updates", AuthLevel::None),
+    ("/api/capabilities", AuthLevel::None),
+    ("/api/v1/auths/signin", AuthLevel::None),
+    ("/api/v1/auths/signup", AuthLevel::None),
+    ("/api/v1/auths/signout", AuthLevel::None),
+    ("/api/v1/auths/ldap", AuthLevel::None),
+    ("/api/v1/auths/mfa/challenge", AuthLevel::None),
+    ("/api/v1/oauth/{provider}/login", AuthLevel::None),
+    ("/api/v1/oauth/{provider}/callback", AuthLevel::None),
+    ("/api/v1/oauth/{provider}/login/callback", AuthLevel::None),
+    // WebSocket upgrades and Socket.IO check the token they are handed
+    ("/api/ws/chat", AuthLevel::None),
+    ("/socket.io", AuthLevel::None),
+    ("/api/socketio/auth", AuthLevel::None),
+    ("/api/socketio/health", AuthLevel::None),
+    ("/manifest.json", AuthLevel::None),
+    ("/opensearch.xml", AuthLevel::None),
+    ("/favicon.png", AuthLevel::None),
+    ("/user.png", AuthLevel::None),
+    ("/static/*", AuthLevel::None),
+    ("/cache/*", AuthLevel::None),
+    ("/api/v1/admin/*", AuthLevel::Admin),
+    ("/api/v1/scim/*", AuthLevel::Admin),
+    ("/api/metrics", AuthLevel::Admin),
+    ("/api/audit", AuthLevel::Admin),
+    ("/api/webhook", AuthLevel::Admin),
+    // Emits to any room or user
+    ("/api/socketio/emit", AuthLevel::Admin),
+    // OpenAI-compatible API, the usual target of API keys
+    ("/api/models", AuthLevel::ApiKeyAllowed),
+    ("/api/chat/completions", AuthLevel::ApiKeyAllowed),
+    ("/api/embeddings", AuthLevel::ApiKeyAllowed),
+    ("/openai/*", AuthLevel::ApiKeyAllowed),
+];
+
+/// Views of shared resources, public unless SHARE_REQUIRE_AUTH
+///
+/// Any signed-in user may view a share either way; access to the resource itself is
+/// never required.
+const SHARE_VIEW_ROUTES: &[&str] = &["/api/v1/chats/share/{share_id}"];
+
+#[derive(Debug, Clone, PartialEq)]
+struct RoutePattern {
+    segments: Vec<String>,
+    /// Ends in `/*`
+    prefix: bool,
+}
+
+impl RoutePattern {
+    fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if !pattern.starts_with('/') {
+            return None;
+        }
+        let (path, prefix) = match pattern.strip_suffix("/*") {
+            Some(path) => (path, true),
+            None => (pattern, false),
+        };
+        Some(Self {
+            segments: segments(path).map(str::to_string).collect(),
+            prefix,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let mut path = segments(path);
+        for segment in &self.segments {
+            match path.next() {
+                Some(part) if segment == part || is_param(segment) => {}
+                _ => return false,
+            }
+        }
+        self.prefix || path.next().is_none()
+    }
+
+    /// Literal segments count most, then length; an exact pattern beats a prefix
+    fn specificity(&self) -> (usize, usize, bool) {
+        let literals = self.segments.iter().filter(|s| !is_param(s)).count();
+        (literals, self.segments.len(), !self.prefix)
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn is_param(segment: &str) -> bool {
+    segment.starts_with('{') && segment.ends_with('}')
+}
+
+/// Parse ROUTE_AUTH_OVERRIDES (`{"/path/*": "none"}`)
+fn parse_overrides(
+    overrides: &BTreeMap<String, String>,
+) -> Result<Vec<(RoutePattern, AuthLevel)>, String> {
+    overrides
+        .iter()
+        .map(|(pattern, level)| {
+            let parsed = RoutePattern::parse(pattern).ok_or_else(|| {
+                format!(
+                    "Invalid ROUTE_AUTH_OVERRIDES path '{}': expected a path starting with /",
+                    pattern
+                )
+            })?;
+            let level = AuthLevel::parse(level).ok_or_else(|| {
+                format!(
+                    "Invalid ROUTE_AUTH_OVERRIDES level '{}' for {}: expected none, user, admin or api_key_allowed",
+                    level, pattern
+                )
+            })?;
+            Ok((parsed, level))
+        })
+        .collect()
+}
+
+/// Check ROUTE_AUTH_OVERRIDES, for `Config::validate`
+pub fn validate_overrides(config: &Config) -> Result<(), String> {
+    parse_overrides(&config.route_auth_overrides).map(|_| ())
+}
+
+struct RouteAuthPolicy {
+    rules: Vec<(RoutePattern, AuthLevel)>,
+    guest_mode: bool,
+}
+
+impl RouteAuthPolicy {
+    fn level(&self, path: &str) -> AuthLevel {
+        // On a tie the later rule, i.e. an override, wins
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| pattern.matches(path))
+            .max_by_key(|(pattern, _)| pattern.specificity())
+            .map(|(_, level)| *level)
+            .unwrap_or(AuthLevel::User)
+    }
+}
+
+/// Whether an API key may be used on `path`
+///
+/// Without ENABLE_API_KEY_ENDPOINT_RESTRICTIONS keys work wherever a session does;
+/// with it only on `api_key_allowed` routes and those in API_KEY_ALLOWED_ENDPOINTS.
+fn api_key_allowed(config: &Config, level: AuthLevel, path: &str) -> bool {
+    level == AuthLevel::ApiKeyAllowed
+        || !config.enable_api_key_endpoint_restrictions
+        || config
+            .api_key_allowed_endpoints
+            .split(',')
+            .filter_map(RoutePattern::parse)
+            .any(|pattern| pattern.matches(path))
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .map(String::from)
+        .or_else(|| req.cookie("token").map(|c| c.value().to_string()))
+}
+
+/// Applies the route auth table to every request
+///
+/// Authenticated callers are attached as `AuthUser`, so handlers and the per-scope
+/// auth middleware don't authenticate them again.
+#[derive(Clone)]
+pub struct RouteAuth {
+    policy: Arc<RouteAuthPolicy>,
+}
+
+impl RouteAuth {
+    pub fn from_config(config: &Config) -> Self {
+        let mut rules: Vec<(RoutePattern, AuthLevel)> = ROUTE_AUTH
+            .iter()
+            .filter_map(|(pattern, level)| Some((RoutePattern::parse(pattern)?, *level)))
+            .collect();
+        if !config.share_require_auth {
+            rules.extend(
+                SHARE_VIEW_ROUTES
+                    .iter()
+                    .filter_map(|pattern| Some((RoutePattern::parse(pattern)?, AuthLevel::None))),
+            );
+        }
+        match parse_overrides(&config.route_auth_overrides) {
+            Ok(overrides) => rules.extend(overrides),
+            Err(e) => tracing::warn!("{}; using the built-in route auth table", e),
+        }
+
+        Self {
+            policy: Arc::new(RouteAuthPolicy {
+                rules,
+                guest_mode: config.guest_mode,
+            }),
+        }
+    }
+
+    /// Auth level required for `path`
+    #[cfg(test)]
+    pub fn level(&self, path: &str) -> AuthLevel {
+        self.policy.level(path)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RouteAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = RouteAuthMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RouteAuthMiddleware {
+            service: Rc::new(service),
+            policy: self.policy.clone(),
+        }))
+    }
+}
+
+pub struct RouteAuthMiddleware<S> {
+    service: Rc<S>,
+    policy: Arc<RouteAuthPolicy>,
+}
+
+impl<S, B> Service<ServiceRequest> for RouteAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let level = self.policy.level(req.path());
+        let guest_mode = self.policy.guest_mode;
+
+        Box::pin(async move {
+            if level == AuthLevel::None {
+                return service.call(req).await;
+            }
+
+            let token = bearer_token(&req);
+            // Guests are only ever admitted to user routes guest mode allows
+            if token.is_none() && (level == AuthLevel::Admin || !guest_mode) {
+                return Err(
+                    AppError::Unauthorized("Missing authorization token".to_string()).into(),
+                );
+            }
+
+            let state = req
+                .app_data::<web::Data<AppState>>()
+                .ok_or_else(|| AppError::InternalServerError("App state not found".to_string()))?;
+
+            let auth_user = match token {
+                Some(token) => {
+                    if token.starts_with("sk-") {
+                        let allowed =
+                            api_key_allowed(&state.config.read().unwrap(), level, req.path());
+                        if !allowed {
+                            return Err(AppError::Forbidden(
+                                "API keys are not allowed on this endpoint".to_string(),
+                            )
+                            .into());
+                        }
+                    }
+                    let (auth_user, _) = authenticate_token(state, &token).await?;
+                    reject_impersonated_write(&req, &auth_user)?;
+                    reject_pending_mfa_setup(&req, &auth_user)?;
+                    auth_user
+                }
+                None => AuthUser::new(state.guest_access.authorize(&req)?),
+            };
+
+            // Impersonation never grants admin access
+            if level == AuthLevel::Admin
+                && (auth_user.user.role != "admin" || auth_user.impersonated_by.is_some())
+            {
+                return Err(AppError::Forbidden("Admin access required".to_string()).into());
+            }
+
+            req.extensions_mut().insert(auth_user);
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::{http::StatusCode, App, HttpResponse};
+
+    fn config(overrides: &str) -> Config {
+        let overrides = overrides.to_string();
+        Config::from_lookup(move |key| match key {
+            "ROUTE_AUTH_OVERRIDES" => Some(overrides.clone()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_unmarked_route_requires_auth_by_default() {
+        let app = init_service(
+            App::new()
+                .wrap(RouteAuth::from_config(&config("{}")))
+                .route(
+                    "/health",
+                    web::get().to(|| async { HttpResponse::Ok().finish() }),
+                )
+                .route(
+                    "/api/v1/reports",
+                    web::get().to(|| async { HttpResponse::Ok().finish() }),
+                ),
+        )
+        .await;
+
+        let err = try_call_service(&app, TestRequest::get().uri("/api/v1/reports").to_request())
+            .await
+            .expect_err("unmarked route should need a token");
+        assert_eq!(err.error_response().status(), StatusCode::UNAUTHORIZED);
+
+        let resp = call_service(&app, TestRequest::get().uri("/health").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_share_links_need_a_session_unless_configured() {
+        for (require_auth, status) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some("false"), StatusCode::OK),
+        ] {
+            let config = Config::from_lookup(move |key| match key {
+                "SHARE_REQUIRE_AUTH" => require_auth.map(String::from),
+                _ => None,
+            })
+            .unwrap();
+            let app = init_service(App::new().wrap(RouteAuth::from_config(&config)).route(
+                "/api/v1/chats/share/{share_id}",
+                web::get().to(|| async { HttpResponse::Ok().finish() }),
+            ))
+            .await;
+
+            let req = TestRequest::get()
+                .uri("/api/v1/chats/share/abc")
+                .to_request();
+            let actual = match try_call_service(&app, req).await {
+                Ok(resp) => resp.status(),
+                Err(e) => e.error_response().status(),
+            };
+            assert_eq!(actual, status, "SHARE_REQUIRE_AUTH={:?}", require_auth);
+
+            // Only the share view opens up
+            let auth = RouteAuth::from_config(&config);
+            assert_eq!(auth.level("/api/v1/chats/abc"), AuthLevel::User);
+        }
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let auth = RouteAuth::from_config(&config(
+            r#"{"/api/v1/chats/share/{id}": "none", "/api/v1/admin/*": "user"}"#,
+        ));
+
+        assert_eq!(auth.level("/api/v1/users"), AuthLevel::User);
+        assert_eq!(auth.level("/api/v1/auths/signin"), AuthLevel::None);
+        // Listed paths don't open up what's below them
+        assert_eq!(auth.level("/api/v1/auths/signin/extra"), AuthLevel::User);
+        assert_eq!(auth.level("/api/v1/oauth/github/login"), AuthLevel::None);
+        assert_eq!(auth.level("/static/css/app.css"), AuthLevel::None);
+        assert_eq!(
+            auth.level("/openai/chat/completions"),
+            AuthLevel::ApiKeyAllowed
+        );
+        assert_eq!(auth.level("/api/v1/chats/share/abc"), AuthLevel::None);
+        assert_eq!(auth.level("/api/v1/chats/share/abc/clone"), AuthLevel::User);
+        // An override of the same pattern replaces the built-in rule
+        assert_eq!(auth.level("/api/v1/admin/usage"), AuthLevel::User);
+        assert_eq!(auth.level("/api/metrics"), AuthLevel::Admin);
+    }
+
+    #[test]
+    fn test_invalid_overrides_rejected() {
+        assert!(config(r#"{"/api/v1/notes/*": "none"}"#).validate().is_ok());
+        assert!(config(r#"{"/api/v1/notes/*": "everyone"}"#)
+            .validate()
+            .is_err());
+        assert!(config(r#"{"api/v1/notes": "none"}"#).validate().is_err());
+    }
+
+    #[test]
+    fn test_api_key_restrictions() {
+        let mut config = config("{}");
+        assert!(api_key_allowed(&config, AuthLevel::User, "/api/v1/chats"));
+
+        config.enable_api_key_endpoint_restrictions = true;
+        config.api_key_allowed_endpoints = "/api/v1/files/*, /api/v1/models".to_string();
+        assert!(!api_key_allowed(&config, AuthLevel::User, "/api/v1/chats"));
+        assert!(api_key_allowed(
+            &config,
+            AuthLevel::User,
+            "/api/v1/files/f1/content"
+        ));
+        assert!(api_key_allowed(&config, AuthLevel::User, "/api/v1/models"));
+        assert!(api_key_allowed(
+            &config,
+            AuthLevel::ApiKeyAllowed,
+            "/api/chat/completions"
+        ));
+    }
+}
@@ -1,22 +1,111 @@
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
     Error,
 };
 use futures_util::future::LocalBoxFuture;
 use std::future::{ready, Ready};
+use std::sync::Arc;
+
+use crate::config::Config;
+
+/// Full policy sent when ENABLE_CSP is set; frame-ancestors is appended from config
+///
+/// Permissive enough for the SPA: inline scripts/styles, images from anywhere (user
+/// uploads) and connections to any LLM provider.
+const CSP_POLICY: &str = "default-src 'self'; \
+     script-src 'self' 'unsafe-inline' 'unsafe-eval'; \
+     style-src 'self' 'unsafe-inline'; \
+     img-src * data: blob:; \
+     font-src 'self' data:; \
+     connect-src *; \
+     media-src 'self' blob:; \
+     object-src 'none'; \
+     base-uri 'self'; \
+     form-action 'self'";
 
 /// Middleware that adds security headers to all responses
 ///
-/// Security headers added:
-/// - X-Content-Type-Options: nosniff (prevent MIME sniffing)
-/// - X-Frame-Options: DENY (prevent clickjacking)
-/// - X-XSS-Protection: 1; mode=block (XSS protection)
-/// - Referrer-Policy: strict-origin-when-cross-origin (control referrer info)
-/// - Permissions-Policy: Restrict browser features
-/// - Content-Security-Policy: Restrict resource loading (if enabled)
+/// Security headers added (each configurable, an empty value leaves it out):
+/// - X-Content-Type-Options (XCONTENT_TYPE, default nosniff)
+/// - X-Frame-Options (XFRAME_OPTIONS, default DENY)
+/// - Content-Security-Policy: frame-ancestors (FRAME_ANCESTORS, default 'none'), or the
+///   full policy when ENABLE_CSP is set
+/// - Referrer-Policy (REFERRER_POLICY, default strict-origin-when-cross-origin)
+/// - Strict-Transport-Security (HSTS), only on HTTPS requests
+/// - X-XSS-Protection and Permissions-Policy, fixed
 ///
 /// Based on Python backend: backend/open_webui/utils/security_headers.py
-pub struct SecurityHeaders;
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    inner: Arc<SecurityHeadersPolicy>,
+}
+
+struct SecurityHeadersPolicy {
+    /// Sent on every response
+    headers: Vec<(HeaderName, HeaderValue)>,
+    hsts: Option<HeaderValue>,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &Config) -> Self {
+        let csp = match (config.enable_csp, config.frame_ancestors.is_empty()) {
+            (true, true) => Some(CSP_POLICY.to_string()),
+            (true, false) => Some(format!(
+                "{}; frame-ancestors {}",
+                CSP_POLICY, config.frame_ancestors
+            )),
+            (false, false) => Some(format!("frame-ancestors {}", config.frame_ancestors)),
+            (false, true) => None,
+        };
+
+        let headers = [
+            ("x-content-type-options", Some(config.xcontent_type.clone())),
+            ("x-frame-options", Some(config.xframe_options.clone())),
+            ("content-security-policy", csp),
+            ("referrer-policy", Some(config.referrer_policy.clone())),
+            ("x-xss-protection", Some("1; mode=block".to_string())),
+            (
+                "permissions-policy",
+                Some("geolocation=(), microphone=(), camera=()".to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((HeaderName::from_static(name), header_value(&value?)?)))
+        .collect();
+
+        Self {
+            inner: Arc::new(SecurityHeadersPolicy {
+                headers,
+                hsts: header_value(&config.hsts),
+            }),
+        }
+    }
+}
+
+/// Configured values are checked at startup, so only an empty one is skipped here
+fn header_value(value: &str) -> Option<HeaderValue> {
+    if value.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(value).ok()
+}
+
+impl SecurityHeadersPolicy {
+    fn apply(&self, headers: &mut HeaderMap, https: bool) {
+        for (name, value) in &self.headers {
+            headers.insert(name.clone(), value.clone());
+        }
+
+        // Browsers ignore HSTS over plain HTTP; sending it there only misleads
+        if let Some(hsts) = self.hsts.as_ref().filter(|_| https) {
+            headers.insert(
+                HeaderName::from_static("strict-transport-security"),
+                hsts.clone(),
+            );
+        }
+    }
+}
 
 impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
 where
@@ -31,12 +120,16 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(SecurityHeadersMiddleware { service }))
+        ready(Ok(SecurityHeadersMiddleware {
+            service,
+            policy: self.inner.clone(),
+        }))
     }
 }
 
 pub struct SecurityHeadersMiddleware<S> {
     service: S,
+    policy: Arc<SecurityHeadersPolicy>,
 }
 
 impl<S, B> Service<ServiceRequest> for SecurityHeadersMiddleware<S>
@@ -52,144 +145,94 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        // Honors X-Forwarded-Proto, so HSTS works behind a TLS-terminating proxy
+        let https = req.connection_info().scheme() == "https";
+        let policy = self.policy.clone();
         let fut = self.service.call(req);
 
         Box::pin(async move {
             let mut res = fut.await?;
 
             // Add security headers to the response
-            let headers = res.headers_mut();
-            add_security_headers(headers);
+            policy.apply(res.headers_mut(), https);
 
             Ok(res)
         })
     }
 }
 
-/// Add security headers to response headers
-pub fn add_security_headers(headers: &mut actix_web::http::header::HeaderMap) {
-    use actix_web::http::header::{HeaderName, HeaderValue};
-
-    // Prevent MIME type sniffing
-    headers.insert(
-        HeaderName::from_static("x-content-type-options"),
-        HeaderValue::from_static("nosniff"),
-    );
-
-    // Prevent clickjacking by not allowing the page to be framed
-    headers.insert(
-        HeaderName::from_static("x-frame-options"),
-        HeaderValue::from_static("DENY"),
-    );
-
-    // Enable XSS protection in legacy browsers
-    headers.insert(
-        HeaderName::from_static("x-xss-protection"),
-        HeaderValue::from_static("1; mode=block"),
-    );
-
-    // Control referrer information
-    headers.insert(
-        HeaderName::from_static("referrer-policy"),
-        HeaderValue::from_static("strict-origin-when-cross-origin"),
-    );
-
-    // Restrict browser features
-    // This helps prevent unauthorized access to camera, microphone, geolocation, etc.
-    headers.insert(
-        HeaderName::from_static("permissions-policy"),
-        HeaderValue::from_static("geolocation=(), microphone=(), camera=()"),
-    );
-
-    // Content Security Policy (CSP)
-    // Note: This is a basic CSP. For production, you may want to customize this based on your needs.
-    // The Python backend has environment-specific CSP configuration.
-    //
-    // For now, we use a permissive policy that allows:
-    // - Same-origin and inline scripts/styles (required for SPA)
-    // - Images from any source (for user-uploaded content)
-    // - Connections to any source (for API calls to various LLM providers)
-    //
-    // TODO: Make this configurable via environment variables or admin settings
-    let csp_enabled = std::env::var("ENABLE_CSP")
-        .unwrap_or_else(|_| "false".to_string())
-        .to_lowercase()
-        == "true";
-
-    if csp_enabled {
-        // Strict CSP
-        headers.insert(
-            HeaderName::from_static("content-security-policy"),
-            HeaderValue::from_static(
-                "default-src 'self'; \
-                 script-src 'self' 'unsafe-inline' 'unsafe-eval'; \
-                 style-src 'self' 'unsafe-inline'; \
-                 img-src * data: blob:; \
-                 font-src 'self' data:; \
-                 connect-src *; \
-                 media-src 'self' blob:; \
-                 object-src 'none'; \
-                 frame-ancestors 'none'; \
-                 base-uri 'self'; \
-                 form-action 'self'",
-            ),
-        );
-
-        // HTTP Strict Transport Security (HSTS)
-        // Only add if HTTPS is enabled
-        let https_enabled = std::env::var("HTTPS_ENABLED")
-            .unwrap_or_else(|_| "false".to_string())
-            .to_lowercase()
-            == "true";
-
-        if https_enabled {
-            // Enforce HTTPS for 1 year, including subdomains
-            headers.insert(
-                HeaderName::from_static("strict-transport-security"),
-                HeaderValue::from_static("max-age=31536000; includeSubDomains; preload"),
-            );
-        }
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use actix_web::{test, web, App, HttpResponse};
 
-    #[actix_web::test]
-    async fn test_security_headers() {
-        let app = test::init_service(App::new().wrap(SecurityHeaders).route(
+    fn config() -> Config {
+        Config::from_lookup(|_| None).unwrap()
+    }
+
+    async fn response_headers(config: &Config, req: test::TestRequest) -> HeaderMap {
+        let app = test::init_service(App::new().wrap(SecurityHeaders::from_config(config)).route(
             "/",
             web::get().to(|| async { HttpResponse::Ok().body("test") }),
         ))
         .await;
 
-        let req = test::TestRequest::get().uri("/").to_request();
-        let resp = test::call_service(&app, req).await;
+        let resp = test::call_service(&app, req.uri("/").to_request()).await;
+        resp.headers().clone()
+    }
 
-        // Check that security headers are present
-        let headers = resp.headers();
+    #[actix_web::test]
+    async fn test_security_headers() {
+        let headers = response_headers(&config(), test::TestRequest::get()).await;
 
-        assert!(headers.contains_key("x-content-type-options"));
+        // Check that security headers are present
         assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
-
-        assert!(headers.contains_key("x-frame-options"));
         assert_eq!(headers.get("x-frame-options").unwrap(), "DENY");
-
-        assert!(headers.contains_key("x-xss-protection"));
+        assert_eq!(
+            headers.get("content-security-policy").unwrap(),
+            "frame-ancestors 'none'"
+        );
         assert_eq!(headers.get("x-xss-protection").unwrap(), "1; mode=block");
-
-        assert!(headers.contains_key("referrer-policy"));
         assert_eq!(
             headers.get("referrer-policy").unwrap(),
             "strict-origin-when-cross-origin"
         );
-
-        assert!(headers.contains_key("permissions-policy"));
         assert_eq!(
             headers.get("permissions-policy").unwrap(),
             "geolocation=(), microphone=(), camera=()"
         );
+
+        // Plain HTTP
+        assert!(!headers.contains_key("strict-transport-security"));
+    }
+
+    #[actix_web::test]
+    async fn test_hsts_only_over_https() {
+        let req = test::TestRequest::get().insert_header(("x-forwarded-proto", "https"));
+        let headers = response_headers(&config(), req).await;
+
+        assert_eq!(
+            headers.get("strict-transport-security").unwrap(),
+            "max-age=31536000; includeSubDomains"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_embedding_configuration() {
+        let mut config = config();
+        config.xframe_options = String::new();
+        config.frame_ancestors = "https://portal.example.com".to_string();
+        config.enable_csp = true;
+
+        let headers = response_headers(&config, test::TestRequest::get()).await;
+
+        assert!(!headers.contains_key("x-frame-options"));
+        let csp = headers
+            .get("content-security-policy")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(csp.starts_with("default-src 'self';"));
+        assert!(csp.ends_with("; frame-ancestors https://portal.example.com"));
     }
 }
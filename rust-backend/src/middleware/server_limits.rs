@@ -0,0 +1,280 @@
+use actix_http::{body::MessageBody, KeepAlive, Request, Response};
+use actix_service::IntoServiceFactory;
+use actix_web::{
+    dev::{
+        forward_ready, AppConfig, Service, ServiceFactory, ServiceRequest, ServiceResponse,
+        Transform,
+    },
+    error::Error as ActixError,
+    HttpServer,
+};
+use futures::future::{ready, Either, Ready};
+use std::fmt;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// actix-http drops a connection whose request head outgrows its 128 KiB read buffer
+/// without answering, so SERVER_MAX_HEADER_BYTES can only be set below that
+pub const MAX_HEADER_BYTES: usize = 128 * 1024;
+
+/// Connection handling for the HTTP listener
+///
+/// SERVER_KEEP_ALIVE is how long a connection may sit idle between requests, for
+/// HTTP/1.1 and HTTP/2 alike; a connection streaming a response (SSE completions,
+/// Socket.IO polling) isn't idle, so long streams are unaffected. The request head limit
+/// is enforced by [`HeaderSizeLimit`], as actix-web has no setting for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerLimits {
+    pub max_connections: usize,
+    pub keep_alive: KeepAlive,
+    pub client_request_timeout: Duration,
+    pub max_header_bytes: usize,
+}
+
+impl ServerLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_connections: config.server_max_connections,
+            keep_alive: match config.server_keep_alive {
+                0 => KeepAlive::Disabled,
+                secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+            },
+            client_request_timeout: Duration::from_secs(config.server_client_request_timeout),
+            max_header_bytes: config.server_max_header_bytes,
+        }
+    }
+
+    /// Apply the connection settings to `server`
+    pub fn apply<F, I, S, B>(&self, server: HttpServer<F, I, S, B>) -> HttpServer<F, I, S, B>
+    where
+        F: Fn() -> I + Send + Clone + 'static,
+        I: IntoServiceFactory<S, Request>,
+        S: ServiceFactory<Request, Config = AppConfig> + 'static,
+        S::Error: Into<ActixError> + 'static,
+        S::InitError: fmt::Debug,
+        S::Response: Into<Response<B>> + 'static,
+        <S::Service as Service<Request>>::Future: 'static,
+        S::Service: 'static,
+        B: MessageBody + 'static,
+    {
+        server
+            .max_connections(self.max_connections)
+            .keep_alive(self.keep_alive)
+            .client_request_timeout(self.client_request_timeout)
+    }
+
+    pub fn header_limit(&self) -> HeaderSizeLimit {
+        HeaderSizeLimit {
+            max_bytes: self.max_header_bytes,
+        }
+    }
+}
+
+/// Size of the request line and headers as sent over HTTP/1.1
+fn head_size(req: &ServiceRequest) -> usize {
+    let target = req.uri().path_and_query().map_or(1, |pq| pq.as_str().len());
+    // "<method> <target> HTTP/1.1\r\n", then "<name>: <value>\r\n" per header
+    let line = req.method().as_str().len() + target + 12;
+    req.headers().iter().fold(line, |size, (name, value)| {
+        size + name.as_str().len() + value.len() + 4
+    })
+}
+
+/// Rejects requests whose line and headers exceed SERVER_MAX_HEADER_BYTES with 431
+#[derive(Clone, Copy)]
+pub struct HeaderSizeLimit {
+    max_bytes: usize,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for HeaderSizeLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = HeaderSizeLimitMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(HeaderSizeLimitMiddleware {
+            service,
+            max_bytes: self.max_bytes,
+        }))
+    }
+}
+
+pub struct HeaderSizeLimitMiddleware<S> {
+    service: S,
+    max_bytes: usize,
+}
+
+impl<S, B> Service<ServiceRequest> for HeaderSizeLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = Either<S::Future, Ready<Result<Self::Response, Self::Error>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let size = head_size(&req);
+        if size <= self.max_bytes {
+            return Either::Left(self.service.call(req));
+        }
+
+        tracing::warn!(
+            "{} {} rejected: request headers are {} bytes (limit {})",
+            req.method(),
+            req.path(),
+            size,
+            self.max_bytes
+        );
+        Either::Right(ready(Err(AppError::HeadersTooLarge(format!(
+            "Request headers exceed {} bytes",
+            self.max_bytes
+        ))
+        .into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::{http::StatusCode, web, App, HttpResponse};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        Config::from_lookup(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.to_string())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_limits_from_config() {
+        let limits = ServerLimits::from_config(&config(&[]));
+        assert_eq!(
+            limits,
+            ServerLimits {
+                max_connections: 25_000,
+                keep_alive: KeepAlive::Timeout(Duration::from_secs(75)),
+                client_request_timeout: Duration::from_secs(300),
+                max_header_bytes: 32 * 1024,
+            }
+        );
+
+        let limits = ServerLimits::from_config(&config(&[("SERVER_KEEP_ALIVE", "0")]));
+        assert_eq!(limits.keep_alive, KeepAlive::Disabled);
+
+        let config = config(&[("SERVER_MAX_HEADER_BYTES", "1048576")]);
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("SERVER_MAX_HEADER_BYTES"));
+    }
+
+    #[actix_web::test]
+    async fn test_oversized_headers_rejected() {
+        let limits = ServerLimits::from_config(&config(&[("SERVER_MAX_HEADER_BYTES", "1024")]));
+        let app = init_service(
+            App::new()
+                .wrap(limits.header_limit())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let resp = call_service(
+            &app,
+            TestRequest::get()
+                .uri("/")
+                .insert_header(("cookie", "a".repeat(512)))
+                .to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let err = try_call_service(
+            &app,
+            TestRequest::get()
+                .uri("/")
+                .insert_header(("cookie", "a".repeat(2048)))
+                .to_request(),
+        )
+        .await
+        .expect_err("oversized headers should be rejected");
+        assert_eq!(
+            err.error_response().status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+    }
+
+    /// Read one response, returning its head
+    fn read_head(stream: &mut TcpStream) -> String {
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n]).to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_limits_applied_to_server() {
+        let limits = ServerLimits::from_config(&config(&[
+            ("SERVER_KEEP_ALIVE", "1"),
+            ("SERVER_CLIENT_REQUEST_TIMEOUT", "1"),
+        ]));
+        let server = limits
+            .apply(HttpServer::new(|| {
+                App::new().route("/", web::get().to(HttpResponse::Ok))
+            }))
+            .workers(1)
+            .disable_signals()
+            .bind("127.0.0.1:0")
+            .unwrap();
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let results = actix_web::rt::task::spawn_blocking(move || {
+            let timeout = Some(Duration::from_secs(5));
+
+            // An idle keep-alive connection is closed after SERVER_KEEP_ALIVE
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(timeout).unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            let response = read_head(&mut stream);
+            let closed = stream.read(&mut [0u8; 64]).unwrap();
+
+            // A client that never finishes its headers gets 408
+            let mut slow = TcpStream::connect(addr).unwrap();
+            slow.set_read_timeout(timeout).unwrap();
+            slow.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+            let timed_out = read_head(&mut slow);
+
+            (response, closed, timed_out)
+        })
+        .await
+        .unwrap();
+        handle.stop(false).await;
+
+        let (response, closed, timed_out) = results;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert_eq!(closed, 0, "idle connection should be closed");
+        assert!(timed_out.starts_with("HTTP/1.1 408"), "{}", timed_out);
+    }
+}
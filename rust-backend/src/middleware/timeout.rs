@@ -0,0 +1,223 @@
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::Error as ActixError,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Budgets in seconds for path prefixes, before REQUEST_TIMEOUT_OVERRIDES; 0 = none
+const DEFAULT_OVERRIDES: &[(&str, u64)] = &[
+    // Completions wait on the model for the first token, or the whole answer when
+    // not streaming
+    ("/api/chat/completions", 0),
+    ("/openai", 0),
+    // Long-lived connections
+    ("/api/ws", 0),
+    ("/socket.io", 0),
+    // Sign-in only touches the database and LDAP; anything slower is stuck
+    ("/api/v1/auths", 30),
+];
+
+/// Overall deadline for producing a response, cancelling the handler with 504 on expiry
+///
+/// REQUEST_TIMEOUT applies unless the longest matching prefix in the built-in or
+/// REQUEST_TIMEOUT_OVERRIDES budgets says otherwise. Only the time until the response
+/// starts counts, so a streamed body isn't cut off once it is flowing.
+#[derive(Clone)]
+pub struct RequestTimeout {
+    inner: Arc<TimeoutPolicy>,
+}
+
+struct TimeoutPolicy {
+    default: Option<Duration>,
+    overrides: BTreeMap<String, Option<Duration>>,
+}
+
+fn budget(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl RequestTimeout {
+    pub fn from_config(config: &Config) -> Self {
+        let overrides = DEFAULT_OVERRIDES
+            .iter()
+            .map(|(prefix, secs)| (prefix.to_string(), *secs))
+            .chain(config.request_timeout_overrides.clone())
+            .map(|(prefix, secs)| (prefix.trim_end_matches('/').to_string(), budget(secs)))
+            .collect();
+
+        Self {
+            inner: Arc::new(TimeoutPolicy {
+                default: budget(config.request_timeout),
+                overrides,
+            }),
+        }
+    }
+}
+
+impl TimeoutPolicy {
+    /// Budget for a path; prefixes match whole segments
+    fn for_path(&self, path: &str) -> Option<Duration> {
+        self.overrides
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, budget)| *budget)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestTimeout
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type InitError = ();
+    type Transform = RequestTimeoutMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestTimeoutMiddleware {
+            service: Rc::new(service),
+            policy: self.inner.clone(),
+        }))
+    }
+}
+
+pub struct RequestTimeoutMiddleware<S> {
+    service: Rc<S>,
+    policy: Arc<TimeoutPolicy>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestTimeoutMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixError> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let Some(budget) = self.policy.for_path(req.path()) else {
+            return Box::pin(self.service.call(req));
+        };
+
+        let method = req.method().clone();
+        let path = req.path().to_string();
+        let response = self.service.call(req);
+
+        Box::pin(async move {
+            match tokio::time::timeout(budget, response).await {
+                Ok(response) => response,
+                Err(_) => {
+                    tracing::warn!(
+                        "{} {} timed out after {}s",
+                        method,
+                        path,
+                        budget.as_secs_f64()
+                    );
+                    Err(AppError::Timeout(format!(
+                        "Request timed out after {} seconds",
+                        budget.as_secs_f64()
+                    ))
+                    .into())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{call_service, init_service, try_call_service, TestRequest};
+    use actix_web::{http::StatusCode, web, App, HttpResponse};
+
+    fn config(request_timeout: u64, overrides: &[(&str, u64)]) -> Config {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.request_timeout = request_timeout;
+        config.request_timeout_overrides = overrides
+            .iter()
+            .map(|(prefix, secs)| (prefix.to_string(), *secs))
+            .collect();
+        config
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let timeout = RequestTimeout::from_config(&config(
+            60,
+            &[("/api/v1/files", 600), ("/api/v1/files/upload/", 0)],
+        ));
+        let policy = &timeout.inner;
+
+        assert_eq!(policy.for_path("/api/v1/chats"), budget(60));
+        assert_eq!(policy.for_path("/api/v1/files/abc"), budget(600));
+        assert_eq!(policy.for_path("/api/v1/files/upload/chunk"), None);
+        // Whole segments only
+        assert_eq!(policy.for_path("/api/v1/filesystem"), budget(60));
+        // Built-in budgets
+        assert_eq!(policy.for_path("/api/chat/completions"), None);
+        assert_eq!(policy.for_path("/api/v1/auths/signin"), budget(30));
+    }
+
+    #[test]
+    fn test_overrides_replace_builtin_budgets() {
+        let timeout = RequestTimeout::from_config(&config(0, &[("/api/v1/auths", 5)]));
+
+        assert_eq!(timeout.inner.for_path("/api/v1/auths/signin"), budget(5));
+        assert_eq!(timeout.inner.for_path("/api/v1/chats"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_slow_handler_times_out() {
+        let mut timeout = RequestTimeout::from_config(&config(60, &[]));
+        // Sub-second budget to keep the test fast
+        Arc::get_mut(&mut timeout.inner)
+            .unwrap()
+            .overrides
+            .insert("/slow".to_string(), Some(Duration::from_millis(50)));
+
+        let app = init_service(
+            App::new()
+                .wrap(timeout)
+                .route(
+                    "/slow",
+                    web::get().to(|| async {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        HttpResponse::Ok().finish()
+                    }),
+                )
+                .route("/fast", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        // Middleware errors are rendered by the server, as for any other AppError
+        let err = try_call_service(&app, TestRequest::get().uri("/slow").to_request())
+            .await
+            .expect_err("slow handler should time out");
+        let resp = err.error_response();
+        assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+        let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["detail"], "Request timed out after 0.05 seconds");
+
+        let resp = call_service(&app, TestRequest::get().uri("/fast").to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
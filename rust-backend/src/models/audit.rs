@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditLog {
+    pub id: String,
+    pub actor_id: String,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub data: Option<serde_json::Value>,
+    pub created_at: i64,
+}
+
+/// Number of matching entries for one action
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuditActionCount {
+    pub action: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<String>,
+    /// Glob over the action name: `*` matches any run of characters, `?` exactly one
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// Unix timestamp (inclusive)
+    pub from: Option<i64>,
+    /// Unix timestamp (inclusive)
+    pub to: Option<i64>,
+    /// Opaque token from a previous page's `next_cursor`
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// One page of audit entries plus per-action counts over the whole filtered set
+#[derive(Debug, Serialize)]
+pub struct AuditLogPage {
+    pub items: Vec<AuditLog>,
+    pub next_cursor: Option<String>,
+    pub summary: Vec<AuditActionCount>,
+}
@@ -19,6 +19,10 @@ pub struct SigninRequest {
 
     #[validate(length(min = 1))]
     pub password: String,
+
+    /// TOTP or recovery code, for users with MFA enabled
+    #[serde(default)]
+    pub mfa_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -34,6 +38,10 @@ pub struct SignupRequest {
 
     #[validate(length(min = 8))]
     pub password_confirmation: Option<String>,
+
+    /// Widget response token, required when SIGNUP_CAPTCHA_PROVIDER is set
+    #[serde(default)]
+    pub captcha_token: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -57,4 +65,10 @@ pub struct Claims {
     pub exp: Option<i64>, // Expiration time (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub iat: Option<i64>, // Issued at (optional)
+    // Admin who minted this token to act as `sub`; only set on impersonation tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<String>,
+    // Admin sign-in that must set up MFA (REQUIRE_MFA_FOR_ADMINS) before anything else
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mfa_setup_pending: bool,
 }
@@ -92,3 +92,39 @@ pub struct FileForm {
     pub access_control: Option<JsonValue>,
     pub hash: Option<String>,
 }
+
+/// How far embedding a file into a knowledge base got
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct IngestProgress {
+    pub file_id: String,
+    pub knowledge_id: String,
+    /// Content hash and embedding config the chunks came from
+    #[serde(skip)]
+    pub fingerprint: String,
+    pub chunks_total: i64,
+    pub chunks_done: i64,
+    /// running, completed, failed or interrupted (the server stopped while running)
+    pub status: String,
+    pub error: Option<String>,
+    pub updated_at: i64,
+}
+
+impl IngestProgress {
+    pub const RUNNING: &'static str = "running";
+    pub const COMPLETED: &'static str = "completed";
+    pub const FAILED: &'static str = "failed";
+    pub const INTERRUPTED: &'static str = "interrupted";
+
+    /// Chunk an ingest of `chunks_total` chunks with `fingerprint` can start from
+    ///
+    /// Only an unfinished ingest of the same content and embedding config is resumed.
+    pub fn resume_point(&self, fingerprint: &str, chunks_total: usize) -> usize {
+        if self.status == Self::COMPLETED
+            || self.fingerprint != fingerprint
+            || self.chunks_total != chunks_total as i64
+        {
+            return 0;
+        }
+        (self.chunks_done.max(0) as usize).min(chunks_total)
+    }
+}
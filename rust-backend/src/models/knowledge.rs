@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::types::JsonValue;
 use sqlx::FromRow;
 
+use crate::models::file::File;
+use crate::retrieval::chunking::count_tokens_approx;
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 #[allow(dead_code)]
 pub struct Knowledge {
@@ -145,3 +148,45 @@ impl KnowledgeFilesResponse {
         }
     }
 }
+
+/// Size of a knowledge base, for `GET /knowledge/{id}/stats`
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct KnowledgeStats {
+    pub file_count: usize,
+    /// Uploaded size, or the extracted text's length for files without one
+    pub total_bytes: u64,
+    /// Tokens in the extracted text, at roughly four bytes per token
+    pub estimated_tokens: usize,
+    /// Vectors across the knowledge base's collections; `None` while RAG is disabled
+    pub chunk_count: Option<usize>,
+    /// When a file last finished indexing
+    pub last_indexed_at: Option<i64>,
+}
+
+impl KnowledgeStats {
+    /// File count, bytes and tokens of `files`, whose JSON fields must be parsed
+    pub fn from_files(files: &[File]) -> Self {
+        let mut stats = Self {
+            file_count: files.len(),
+            ..Self::default()
+        };
+        for file in files {
+            let content = file
+                .data
+                .as_ref()
+                .and_then(|data| data.get("content"))
+                .and_then(|content| content.as_str())
+                .unwrap_or_default();
+            let size = file
+                .meta
+                .as_ref()
+                .and_then(|meta| meta.get("size"))
+                .and_then(|size| size.as_u64());
+            stats.total_bytes += size.unwrap_or(content.len() as u64);
+            if !content.is_empty() {
+                stats.estimated_tokens += count_tokens_approx(content);
+            }
+        }
+        stats
+    }
+}
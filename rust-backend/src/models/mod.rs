@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod auth;
 pub mod channel;
 pub mod chat;
@@ -17,6 +18,7 @@ pub mod prompt;
 pub mod tag;
 pub mod tool;
 pub mod tool_runtime;
+pub mod usage;
 pub mod user;
 
 pub use auth::*;
@@ -43,6 +43,26 @@ pub struct ModelForm {
     pub access_control: Option<JsonValue>,
 }
 
+impl ModelForm {
+    /// Check the shape of a model before it's persisted
+    pub fn validate(&self) -> Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("Model ID is required".to_string());
+        }
+        if self.name.trim().is_empty() {
+            return Err("Model name is required".to_string());
+        }
+        // null is stored as {}
+        if !(self.params.is_object() || self.params.is_null()) {
+            return Err("params must be a JSON object".to_string());
+        }
+        if !(self.meta.is_object() || self.meta.is_null()) {
+            return Err("meta must be a JSON object".to_string());
+        }
+        Ok(())
+    }
+}
+
 fn default_params() -> JsonValue {
     serde_json::json!({})
 }
@@ -20,6 +20,8 @@ pub struct OAuthSessionResponse {
     pub user_id: String,
     pub provider: String,
     pub expires_at: i64,
+    /// False when the session can't be renewed and ends at `expires_at`
+    pub refreshable: bool,
 }
 
 /// Decrypted token data structure
@@ -37,6 +39,21 @@ pub struct OAuthTokenData {
     pub issued_at: i64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub scope: Option<String>,
+    /// Cleared at creation for providers that don't issue refresh tokens; sessions
+    /// stored before the flag existed count as refreshable
+    #[serde(default = "default_refreshable")]
+    pub refreshable: bool,
+}
+
+fn default_refreshable() -> bool {
+    true
+}
+
+impl OAuthTokenData {
+    /// Whether the token can be renewed with a refresh token
+    pub fn can_refresh(&self) -> bool {
+        self.refreshable && self.refresh_token.is_some()
+    }
 }
 
 /// OAuth Session with decrypted token
@@ -51,6 +68,15 @@ pub struct OAuthSessionWithToken {
     pub updated_at: i64,
 }
 
+/// Result of re-encrypting OAuth sessions under the primary key
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeyRotationReport {
+    pub key_count: usize,
+    pub migrated: u64,
+    pub failed: u64,
+    pub failed_session_ids: Vec<String>,
+}
+
 /// Create OAuth Session Request
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateOAuthSession {
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Usage {
+    pub id: String,
+    pub user_id: String,
+    pub model: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+    pub estimated: bool,
+    pub created_at: i64,
+}
+
+/// Aggregated usage row; grouping columns not selected are `None`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UsageSummary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub total_tokens: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGroupBy {
+    User,
+    Model,
+    Date,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UsageQuery {
+    /// Comma-separated list of `user`, `model`, `date`
+    pub group_by: Option<String>,
+    pub user_id: Option<String>,
+    pub model: Option<String>,
+    /// Unix timestamp (inclusive)
+    pub start: Option<i64>,
+    /// Unix timestamp (exclusive)
+    pub end: Option<i64>,
+}
+
+impl UsageQuery {
+    pub fn group_by(&self) -> Result<Vec<UsageGroupBy>, String> {
+        let mut groups = Vec::new();
+        for part in self
+            .group_by
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let group = match part {
+                "user" => UsageGroupBy::User,
+                "model" => UsageGroupBy::Model,
+                "date" => UsageGroupBy::Date,
+                other => return Err(format!("Invalid group_by value: {}", other)),
+            };
+            if !groups.contains(&group) {
+                groups.push(group);
+            }
+        }
+        Ok(groups)
+    }
+}
@@ -1,5 +1,7 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+use super::normalize::TextNormalization;
+
 /// Configuration for text chunking
 #[derive(Debug, Clone)]
 pub struct ChunkingConfig {
@@ -9,6 +11,8 @@ pub struct ChunkingConfig {
     pub chunk_overlap: usize,
     /// Separator to use for splitting text
     pub separator: String,
+    /// Cleanup applied to the text before it is split
+    pub normalization: TextNormalization,
 }
 
 impl Default for ChunkingConfig {
@@ -17,6 +21,7 @@ impl Default for ChunkingConfig {
             chunk_size: 512,
             chunk_overlap: 50,
             separator: "\n\n".to_string(),
+            normalization: TextNormalization::default(),
         }
     }
 }
@@ -38,8 +43,250 @@ impl ChunkingConfig {
             chunk_size,
             chunk_overlap,
             separator: "\n\n".to_string(),
+            normalization: TextNormalization::from_env(),
+        }
+    }
+
+    /// The environment's settings, with the `chunking.chunk_size` and
+    /// `chunking.chunk_overlap` a knowledge base's data may set in their place
+    pub fn for_knowledge(data: Option<&serde_json::Value>) -> Self {
+        let mut config = Self::from_env();
+        let overrides = data.and_then(|data| data.get("chunking"));
+        let setting = |name| {
+            overrides
+                .and_then(|o| o.get(name))
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize)
+        };
+        if let Some(chunk_size) = setting("chunk_size") {
+            config.chunk_size = chunk_size;
+        }
+        if let Some(chunk_overlap) = setting("chunk_overlap") {
+            config.chunk_overlap = chunk_overlap;
+        }
+        config
+    }
+}
+
+/// How a document is split before chunks are packed to size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkStrategy {
+    /// Sections starting at each heading
+    Markdown,
+    /// Top-level definitions, found as blank-line separated blocks starting at column 0
+    Code,
+    /// Groups of rows, each repeating the header line
+    Csv,
+    /// Sentences
+    Prose,
+}
+
+/// Extensions treated as source code
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "js", "jsx", "ts", "tsx", "go", "java", "kt", "c", "h", "cc", "cpp", "hpp", "cs",
+    "rb", "php", "swift", "scala", "sh", "bash", "lua", "sql", "r", "dart", "ex", "exs",
+];
+
+impl ChunkStrategy {
+    /// Pick a strategy from the uploaded file's content type, falling back to its extension
+    pub fn detect(content_type: Option<&str>, filename: &str) -> Self {
+        let content_type = content_type
+            .map(|ct| ct.split(';').next().unwrap_or("").trim().to_lowercase())
+            .unwrap_or_default();
+        let extension = filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+
+        if matches!(content_type.as_str(), "text/markdown" | "text/x-markdown")
+            || matches!(extension.as_str(), "md" | "markdown" | "mdx")
+        {
+            Self::Markdown
+        } else if content_type == "text/csv" || extension == "csv" {
+            Self::Csv
+        } else if CODE_EXTENSIONS.contains(&extension.as_str())
+            || matches!(
+                content_type.as_str(),
+                "application/javascript" | "application/typescript" | "application/x-sh"
+            )
+            || (content_type.starts_with("text/x-") && content_type != "text/x-markdown")
+        {
+            Self::Code
+        } else {
+            Self::Prose
+        }
+    }
+
+    /// Name stored in chunk metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Code => "code",
+            Self::Csv => "csv",
+            Self::Prose => "prose",
+        }
+    }
+}
+
+/// Splits documents with a strategy suited to their type
+#[derive(Debug, Clone, Default)]
+pub struct Chunker {
+    config: ChunkingConfig,
+}
+
+impl Chunker {
+    pub fn new(config: ChunkingConfig) -> Self {
+        Self { config }
+    }
+
+    /// Chunk `text` using `strategy`
+    ///
+    /// Structural strategies keep whole sections together, packing adjacent small ones
+    /// up to `chunk_size`; a section that is too large on its own is split by sentences.
+    /// The text is normalized first, with the steps that suit the strategy.
+    pub fn chunk(&self, text: &str, strategy: ChunkStrategy) -> Vec<String> {
+        let ChunkingConfig {
+            chunk_size,
+            chunk_overlap,
+            normalization,
+            ..
+        } = self.config;
+        let normalization = normalization.for_strategy(strategy);
+        let normalized;
+        let text = if normalization.is_enabled() {
+            normalized = normalization.apply(text);
+            normalized.as_str()
+        } else {
+            text
+        };
+
+        match strategy {
+            ChunkStrategy::Prose => chunk_text(text, chunk_size, chunk_overlap),
+            ChunkStrategy::Markdown => {
+                pack_sections(split_markdown_sections(text), chunk_size, chunk_overlap)
+            }
+            ChunkStrategy::Code => {
+                pack_sections(split_code_blocks(text), chunk_size, chunk_overlap)
+            }
+            ChunkStrategy::Csv => chunk_csv(text, chunk_size),
+        }
+    }
+}
+
+/// Split markdown before every ATX heading, ignoring `#` lines inside fenced code
+fn split_markdown_sections(text: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut current = String::new();
+    let mut in_fence = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+
+        if !in_fence && is_markdown_heading(line) && !current.trim().is_empty() {
+            sections.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.trim().is_empty() {
+        sections.push(current);
+    }
+
+    sections
+}
+
+fn is_markdown_heading(line: &str) -> bool {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&hashes) && line[hashes..].starts_with([' ', '\t'])
+}
+
+/// Split code into blank-line separated blocks, gluing a block onto the previous one
+/// unless it starts at column 0
+///
+/// Indented blocks and closing brackets continue the definition above them, so in most
+/// languages each block ends up holding one top-level item with its leading comments.
+fn split_code_blocks(text: &str) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+
+    for block in text.split("\n\n") {
+        if block.trim().is_empty() {
+            continue;
+        }
+        let first = block.trim_start_matches('\n');
+        let continues = first.starts_with([' ', '\t', '}', ')', ']']);
+
+        match blocks.last_mut() {
+            Some(last) if continues => {
+                last.push_str("\n\n");
+                last.push_str(first);
+            }
+            _ => blocks.push(first.to_string()),
         }
     }
+
+    blocks
+}
+
+/// Greedily pack sections into chunks of at most `chunk_size` characters
+fn pack_sections(sections: Vec<String>, chunk_size: usize, chunk_overlap: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for section in sections {
+        let section = section.trim();
+        if section.is_empty() {
+            continue;
+        }
+
+        if !current.is_empty() && current.len() + 2 + section.len() > chunk_size {
+            chunks.push(std::mem::take(&mut current));
+        }
+
+        if section.len() > chunk_size {
+            chunks.extend(chunk_text(section, chunk_size, chunk_overlap));
+        } else if current.is_empty() {
+            current = section.to_string();
+        } else {
+            current.push_str("\n\n");
+            current.push_str(section);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Split CSV into groups of rows that fit `chunk_size`, each starting with the header
+///
+/// Rows are split on newlines, so quoted fields containing line breaks may be cut.
+fn chunk_csv(text: &str, chunk_size: usize) -> Vec<String> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return vec![];
+    };
+
+    let mut chunks = Vec::new();
+    let mut current = header.to_string();
+    let mut has_rows = false;
+
+    for row in lines {
+        if has_rows && current.len() + 1 + row.len() > chunk_size {
+            chunks.push(std::mem::replace(&mut current, header.to_string()));
+        }
+        current.push('\n');
+        current.push_str(row);
+        has_rows = true;
+    }
+    if has_rows || chunks.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
 }
 
 /// Chunk text into smaller pieces with overlap
@@ -204,6 +451,79 @@ mod tests {
         assert!(tokens < 20); // Reasonable range
     }
 
+    fn chunker(chunk_size: usize) -> Chunker {
+        Chunker::new(ChunkingConfig {
+            chunk_size,
+            chunk_overlap: 0,
+            ..ChunkingConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_detect_strategy() {
+        assert_eq!(
+            ChunkStrategy::detect(Some("text/markdown"), "notes"),
+            ChunkStrategy::Markdown
+        );
+        assert_eq!(
+            ChunkStrategy::detect(None, "README.md"),
+            ChunkStrategy::Markdown
+        );
+        assert_eq!(
+            ChunkStrategy::detect(Some("text/csv"), "data.csv"),
+            ChunkStrategy::Csv
+        );
+        assert_eq!(
+            ChunkStrategy::detect(Some("application/octet-stream"), "main.rs"),
+            ChunkStrategy::Code
+        );
+        assert_eq!(
+            ChunkStrategy::detect(Some("text/plain"), "notes.txt"),
+            ChunkStrategy::Prose
+        );
+    }
+
+    #[test]
+    fn test_markdown_chunks_start_at_headings() {
+        let text = "# Intro\nSome intro text.\n\n## Setup\nInstall it.\n```sh\n# not a heading\nmake\n```\n\n## Usage\nRun it.\n";
+        let chunks = chunker(60).chunk(text, ChunkStrategy::Markdown);
+
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].starts_with("# Intro"));
+        assert!(chunks[1].starts_with("## Setup"));
+        assert!(chunks[1].contains("# not a heading"));
+        assert!(chunks[2].starts_with("## Usage"));
+    }
+
+    #[test]
+    fn test_markdown_small_sections_are_packed() {
+        let text = "# A\none\n# B\ntwo\n";
+        let chunks = chunker(100).chunk(text, ChunkStrategy::Markdown);
+        assert_eq!(chunks, vec!["# A\none\n\n# B\ntwo"]);
+    }
+
+    #[test]
+    fn test_code_chunks_keep_definitions_whole() {
+        let text = "use std::io;\n\n/// Adds\nfn add(a: i32, b: i32) -> i32 {\n    let sum = a + b;\n\n    sum\n}\n\nfn sub(a: i32, b: i32) -> i32 {\n    a - b\n}\n";
+        let chunks = chunker(80).chunk(text, ChunkStrategy::Code);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0], "use std::io;");
+        assert!(chunks[1].starts_with("/// Adds\nfn add"));
+        assert!(chunks[1].ends_with("    sum\n}"));
+        assert!(chunks[2].starts_with("fn sub"));
+    }
+
+    #[test]
+    fn test_csv_chunks_repeat_header() {
+        let text = "name,age\nalice,30\nbob,25\ncarol,41\n";
+        let chunks = chunker(20).chunk(text, ChunkStrategy::Csv);
+
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| chunk.starts_with("name,age\n")));
+        assert_eq!(chunks[2], "name,age\ncarol,41");
+    }
+
     #[test]
     fn test_split_into_sentences() {
         let text = "First sentence. Second sentence! Third sentence?";
@@ -3,16 +3,20 @@ use async_openai::{
     types::{CreateEmbeddingRequest, EmbeddingInput},
     Client,
 };
-use std::sync::Arc;
-use tokio::sync::Semaphore;
-use tracing::{debug, info};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use super::health::ProviderHealth;
+use super::language::LanguageRoute;
+use crate::utils::single_flight::SingleFlight;
 
 #[cfg(feature = "embeddings")]
 use std::path::PathBuf;
 #[cfg(feature = "embeddings")]
 use tokio::sync::RwLock;
-#[cfg(feature = "embeddings")]
-use tracing::warn;
 
 #[cfg(feature = "embeddings")]
 use candle_core::{Device, Tensor};
@@ -23,8 +27,47 @@ use hf_hub::{api::sync::Api, Repo, RepoType};
 #[cfg(feature = "embeddings")]
 use tokenizers::Tokenizer;
 
+/// Texts per upstream request when RAG_EMBEDDING_BATCH_SIZE is unset
+///
+/// OpenAI allows ~8000 tokens per request; at ~100 tokens per chunk 50 is safe.
+const DEFAULT_BATCH_SIZE: usize = 50;
+
+/// Retries for a rate-limited batch before giving up
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Sample embedded by the startup warmup
+const WARMUP_TEXT: &str = "warmup";
+
+/// How long the startup warmup waits for the provider
+const WARMUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Cut `text` to at most `max_tokens` tokens, or `None` if it already fits
+///
+/// Tokens are counted with cl100k, the tokenizer of OpenAI's embedding models; the cut
+/// backs off to a character boundary when a token ends inside one.
+fn truncate_to_tokens(text: &str, max_tokens: usize) -> Option<&str> {
+    // Every token covers at least one byte
+    if text.len() <= max_tokens {
+        return None;
+    }
+    let bpe = tiktoken_rs::cl100k_base_singleton();
+    let tokens = bpe.encode_ordinary(text);
+    if tokens.len() <= max_tokens {
+        return None;
+    }
+
+    let mut end: usize = bpe
+        ._decode_native_and_split(tokens[..max_tokens].to_vec())
+        .map(|token| token.len())
+        .sum();
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(&text[..end])
+}
+
 /// Error types for embedding operations
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum EmbeddingError {
     #[error("API error: {0}")]
     ApiError(String),
@@ -37,6 +80,9 @@ pub enum EmbeddingError {
 
     #[error("Model error: {0}")]
     ModelError(String),
+
+    #[error("Rate limited by embedding API")]
+    RateLimited { retry_after: Option<Duration> },
 }
 
 /// Trait for embedding providers
@@ -63,11 +109,50 @@ pub trait EmbeddingProvider: Send + Sync {
         }
     }
 
+    /// Generate embeddings, reporting `(embedded, total)` as batches complete
+    async fn embed_with_progress(
+        &self,
+        texts: Vec<String>,
+        progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let total = texts.len();
+        let embeddings = self.embed(texts).await?;
+        progress(total, total);
+        Ok(embeddings)
+    }
+
+    /// Embed a short sample to check the provider works, returning the dimension it produced
+    async fn warmup(&self) -> Result<usize, EmbeddingError> {
+        let embeddings = self.embed(vec![WARMUP_TEXT.to_string()]).await?;
+        embeddings
+            .first()
+            .map(Vec::len)
+            .filter(|dimension| *dimension > 0)
+            .ok_or_else(|| EmbeddingError::ApiError("Warmup returned no embedding".to_string()))
+    }
+
     /// Get the dimension of the embeddings
     fn dimension(&self) -> usize;
 
     /// Get the model name
     fn model_name(&self) -> &str;
+
+    /// Language of `text` and, when one is configured for it, the model to embed it with
+    ///
+    /// Providers without language routing detect nothing and embed everything themselves.
+    fn route_language(&self, _text: &str) -> LanguageRoute {
+        LanguageRoute::default()
+    }
+
+    /// Languages with their own model, and so their own sub-collection
+    fn routed_languages(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Health of each provider calls are routed over; empty when there's no routing
+    fn provider_health(&self) -> Vec<ProviderHealth> {
+        Vec::new()
+    }
 }
 
 /// Wrapper for embedding functions with configurable prefixes
@@ -136,6 +221,8 @@ pub struct OpenAIEmbeddings {
     client: Client<OpenAIConfig>,
     model: String,
     dimension: usize,
+    /// Texts sent per upstream request
+    batch_size: usize,
     /// Semaphore to limit concurrent requests
     semaphore: Arc<Semaphore>,
 }
@@ -163,13 +250,19 @@ impl OpenAIEmbeddings {
         };
 
         let config = OpenAIConfig::new().with_api_key(api_key);
-        let client = Client::with_config(config);
+        let http_client = crate::utils::http::client_for(async_openai::config::OPENAI_API_BASE);
+        let client = Client::with_config(config).with_http_client(http_client);
 
         // Limit concurrent requests to avoid rate limits
         let max_concurrent = std::env::var("OPENAI_MAX_CONCURRENT")
             .ok()
             .and_then(|s| s.parse().ok())
             .unwrap_or(10);
+        let batch_size = std::env::var("RAG_EMBEDDING_BATCH_SIZE")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_BATCH_SIZE)
+            .max(1);
 
         info!(
             "Initialized OpenAI embeddings: model={}, dimension={}, max_concurrent={}",
@@ -180,6 +273,7 @@ impl OpenAIEmbeddings {
             client,
             model,
             dimension,
+            batch_size,
             semaphore: Arc::new(Semaphore::new(max_concurrent)),
         })
     }
@@ -241,11 +335,7 @@ impl EmbeddingProvider for OpenAIEmbeddings {
 
         info!("Generating embeddings for {} texts", texts.len());
 
-        // OpenAI has a limit of ~8000 tokens per request, so batch accordingly
-        // Assuming average of 100 tokens per text, batch size of 50 should be safe
-        let batch_size = 50;
-
-        self.embed_batch(texts, batch_size).await
+        self.embed_batch(texts, self.batch_size).await
     }
 
     fn dimension(&self) -> usize {
@@ -264,6 +354,8 @@ pub struct KnoxChatEmbeddings {
     base_url: String,
     model: String,
     dimension: usize,
+    /// Texts sent per upstream request
+    batch_size: usize,
     /// Semaphore to limit concurrent requests
     semaphore: Arc<Semaphore>,
 }
@@ -303,7 +395,7 @@ impl KnoxChatEmbeddings {
             _ => 1024, // Default
         };
 
-        let client = reqwest::Client::builder()
+        let client = crate::utils::http::builder_for(&base_url)
             .timeout(std::time::Duration::from_secs(120))
             .build()
             .map_err(|e| {
@@ -315,6 +407,11 @@ impl KnoxChatEmbeddings {
             .ok()
             .and_then(|s| s.parse().ok())
             .unwrap_or(10);
+        let batch_size = std::env::var("RAG_EMBEDDING_BATCH_SIZE")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_BATCH_SIZE)
+            .max(1);
 
         info!(
             "Initialized Knox Chat embeddings: model={}, dimension={}, max_concurrent={}, base_url={}",
@@ -327,6 +424,7 @@ impl KnoxChatEmbeddings {
             base_url,
             model,
             dimension,
+            batch_size,
             semaphore: Arc::new(Semaphore::new(max_concurrent)),
         })
     }
@@ -361,17 +459,24 @@ impl KnoxChatEmbeddings {
                 "model": self.model,
             });
 
-            let response = self
-                .client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", self.api_key))
-                .header("Content-Type", "application/json")
-                .json(&payload)
-                .send()
-                .await
-                .map_err(|e| {
-                    EmbeddingError::ApiError(format!("Knox Chat API request failed: {}", e))
-                })?;
+            let response = crate::utils::telemetry::send(
+                self.client
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", self.api_key))
+                    .header("Content-Type", "application/json")
+                    .json(&payload),
+                "embeddings",
+            )
+            .await
+            .map_err(|e| {
+                EmbeddingError::ApiError(format!("Knox Chat API request failed: {}", e))
+            })?;
+
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(EmbeddingError::RateLimited {
+                    retry_after: parse_retry_after(response.headers()),
+                });
+            }
 
             if !response.status().is_success() {
                 let status = response.status();
@@ -437,10 +542,7 @@ impl EmbeddingProvider for KnoxChatEmbeddings {
             texts.len()
         );
 
-        // Knox Chat can handle reasonable batch sizes
-        let batch_size = 50;
-
-        self.embed_batch(texts, batch_size).await
+        self.embed_batch(texts, self.batch_size).await
     }
 
     fn dimension(&self) -> usize {
@@ -866,6 +968,243 @@ impl EmbeddingProvider for SentenceTransformerEmbeddings {
     }
 }
 
+/// Seconds from a `Retry-After` header (HTTP-date values are not supported)
+fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Paces calls so that at most `rpm` start per minute
+struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    fn new(rpm: u32) -> Option<Self> {
+        (rpm > 0).then(|| Self {
+            interval: Duration::from_secs(60) / rpm,
+            next_slot: Mutex::new(None),
+        })
+    }
+
+    /// Wait for the next free slot
+    async fn acquire(&self) {
+        let wait_until = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = next_slot.map_or(now, |slot| slot.max(now));
+            *next_slot = Some(slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(wait_until).await;
+    }
+}
+
+/// Wraps a provider to batch inputs, pace requests and retry rate-limited batches
+///
+/// All ingestion shares one wrapper, so RAG_EMBEDDING_RPM holds across concurrent
+/// uploads. A 429 waits for `Retry-After` (or exponential backoff when absent).
+pub struct BatchedEmbeddings {
+    inner: Arc<dyn EmbeddingProvider>,
+    batch_size: usize,
+    limiter: Option<RateLimiter>,
+    max_input_tokens: Option<usize>,
+    truncate_input: bool,
+    /// Identical inputs embedded concurrently (e.g. the same search query) share one call
+    in_flight: SingleFlight<Vec<String>, Result<Vec<Vec<f32>>, EmbeddingError>>,
+    /// Dimension the provider actually produced at warmup, over the one guessed from
+    /// the model name
+    probed_dimension: OnceLock<usize>,
+}
+
+impl BatchedEmbeddings {
+    /// `rpm` of 0 disables pacing
+    pub fn new(inner: Arc<dyn EmbeddingProvider>, batch_size: usize, rpm: u32) -> Self {
+        Self {
+            inner,
+            batch_size: batch_size.max(1),
+            limiter: RateLimiter::new(rpm),
+            max_input_tokens: None,
+            truncate_input: true,
+            in_flight: SingleFlight::new(),
+            probed_dimension: OnceLock::new(),
+        }
+    }
+
+    /// Limit each input to `max_tokens` (0 = no limit), truncating longer ones or, when
+    /// `truncate` is off, failing the call
+    pub fn with_max_input_tokens(mut self, max_tokens: usize, truncate: bool) -> Self {
+        self.max_input_tokens = (max_tokens > 0).then_some(max_tokens);
+        self.truncate_input = truncate;
+        self
+    }
+
+    /// Fit every input within the token limit, so one overlong chunk can't fail a batch
+    fn limit_inputs(&self, texts: Vec<String>) -> Result<Vec<String>, EmbeddingError> {
+        let Some(max_tokens) = self.max_input_tokens else {
+            return Ok(texts);
+        };
+
+        texts
+            .into_iter()
+            .enumerate()
+            .map(
+                |(index, text)| match truncate_to_tokens(&text, max_tokens) {
+                    None => Ok(text),
+                    Some(_) if !self.truncate_input => Err(EmbeddingError::InvalidInput(format!(
+                        "input {} is longer than RAG_EMBEDDING_MAX_INPUT_TOKENS ({} tokens)",
+                        index, max_tokens
+                    ))),
+                    Some(truncated) => {
+                        warn!(
+                            "Truncated embedding input {} from {} to {} bytes to fit {} tokens",
+                            index,
+                            text.len(),
+                            truncated.len(),
+                            max_tokens
+                        );
+                        Ok(truncated.to_string())
+                    }
+                },
+            )
+            .collect()
+    }
+
+    async fn embed_chunk(&self, chunk: &[String]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire().await;
+            }
+
+            match self.inner.embed(chunk.to_vec()).await {
+                Err(EmbeddingError::RateLimited { retry_after })
+                    if attempt < MAX_RATE_LIMIT_RETRIES =>
+                {
+                    let delay = retry_after.unwrap_or(Duration::from_secs(1 << attempt));
+                    warn!(
+                        "Embedding API rate limited, retrying in {:?} (attempt {}/{})",
+                        delay,
+                        attempt + 1,
+                        MAX_RATE_LIMIT_RETRIES
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingProvider for BatchedEmbeddings {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let no_progress = |_: usize, _: usize| {};
+        self.in_flight
+            .run(texts.clone(), || {
+                self.embed_with_progress(texts, &no_progress)
+            })
+            .await
+    }
+
+    async fn embed_with_progress(
+        &self,
+        texts: Vec<String>,
+        progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        let texts = self.limit_inputs(texts)?;
+        let total = texts.len();
+        let mut embeddings = Vec::with_capacity(total);
+
+        for chunk in texts.chunks(self.batch_size) {
+            embeddings.extend(self.embed_chunk(chunk).await?);
+            progress(embeddings.len(), total);
+        }
+
+        Ok(embeddings)
+    }
+
+    async fn warmup(&self) -> Result<usize, EmbeddingError> {
+        let dimension = self
+            .embed_chunk(&[WARMUP_TEXT.to_string()])
+            .await?
+            .first()
+            .map(Vec::len)
+            .filter(|dimension| *dimension > 0)
+            .ok_or_else(|| EmbeddingError::ApiError("Warmup returned no embedding".to_string()))?;
+
+        if dimension != self.inner.dimension() {
+            warn!(
+                "Embedding model {} returned {} dimensions, expected {}; using {}",
+                self.inner.model_name(),
+                dimension,
+                self.inner.dimension(),
+                dimension
+            );
+        }
+        let _ = self.probed_dimension.set(dimension);
+        Ok(dimension)
+    }
+
+    fn dimension(&self) -> usize {
+        self.probed_dimension
+            .get()
+            .copied()
+            .unwrap_or_else(|| self.inner.dimension())
+    }
+
+    fn model_name(&self) -> &str {
+        self.inner.model_name()
+    }
+
+    fn provider_health(&self) -> Vec<ProviderHealth> {
+        self.inner.provider_health()
+    }
+}
+
+/// Check the embedding provider at startup and log the dimension it produces
+///
+/// A provider that fails or doesn't answer within [`WARMUP_TIMEOUT`] is an error when
+/// `strict` (RAG_STRICT_STARTUP); otherwise it is logged and surfaces again on first use.
+pub async fn warmup(provider: &dyn EmbeddingProvider, strict: bool) -> Result<(), EmbeddingError> {
+    let result = match tokio::time::timeout(WARMUP_TIMEOUT, provider.warmup()).await {
+        Ok(result) => result,
+        Err(_) => Err(EmbeddingError::ApiError(format!(
+            "No response within {}s",
+            WARMUP_TIMEOUT.as_secs()
+        ))),
+    };
+
+    match result {
+        Ok(dimension) => {
+            info!(
+                "✅ Embedding provider ready: {} ({} dimensions)",
+                provider.model_name(),
+                dimension
+            );
+            Ok(())
+        }
+        Err(e) if strict => Err(e),
+        Err(e) => {
+            warn!(
+                "⚠️  Embedding provider {} failed warmup: {}",
+                provider.model_name(),
+                e
+            );
+            warn!("   RAG ingestion and search will fail until it is reachable");
+            Ok(())
+        }
+    }
+}
+
 /// Factory for creating embedding providers
 pub struct EmbeddingFactory;
 
@@ -928,6 +1267,45 @@ impl EmbeddingFactory {
         }
     }
 
+    /// Create a provider for `model` on the given RAG_EMBEDDING_ENGINE
+    ///
+    /// Used for the per-language models, which share the default model's engine.
+    pub fn create(
+        engine: &str,
+        model: &str,
+        openai_api_key: &str,
+    ) -> Result<Arc<dyn EmbeddingProvider>, EmbeddingError> {
+        let model = model.to_string();
+        match engine.to_lowercase().as_str() {
+            "openai" => Self::create_openai(
+                Some(openai_api_key.to_string()).filter(|key| !key.is_empty()),
+                Some(model),
+            ),
+            "knoxchat" | "knox" => Self::create_knoxchat(
+                std::env::var("KNOXCHAT_API_KEY").ok(),
+                std::env::var("KNOXCHAT_BASE_URL").ok(),
+                Some(model),
+            ),
+            "" | "local" | "sentence-transformers" => {
+                #[cfg(feature = "embeddings")]
+                {
+                    Self::create_sentence_transformer(model, false)
+                }
+                #[cfg(not(feature = "embeddings"))]
+                {
+                    Err(EmbeddingError::ConfigError(format!(
+                        "Local embeddings support not compiled, can't load {}. Enable the 'embeddings' feature",
+                        model
+                    )))
+                }
+            }
+            other => Err(EmbeddingError::ConfigError(format!(
+                "Unsupported embedding engine: {}",
+                other
+            ))),
+        }
+    }
+
     /// Create an OpenAI embedding provider with custom configuration
     pub fn create_openai(
         api_key: Option<String>,
@@ -986,4 +1364,241 @@ mod tests {
         assert_eq!(provider.dimension(), 1536);
         assert_eq!(provider.model_name(), "text-embedding-3-small");
     }
+
+    /// Records each upstream batch; rate-limits the first `rate_limited` calls
+    struct CountingProvider {
+        batches: std::sync::Mutex<Vec<usize>>,
+        rate_limited: std::sync::atomic::AtomicUsize,
+        latency: Duration,
+    }
+
+    impl CountingProvider {
+        fn new(rate_limited: usize) -> Self {
+            Self {
+                batches: std::sync::Mutex::new(Vec::new()),
+                rate_limited: std::sync::atomic::AtomicUsize::new(rate_limited),
+                latency: Duration::ZERO,
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for CountingProvider {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            use std::sync::atomic::Ordering;
+            if self
+                .rate_limited
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err(EmbeddingError::RateLimited {
+                    retry_after: Some(Duration::ZERO),
+                });
+            }
+            if !self.latency.is_zero() {
+                tokio::time::sleep(self.latency).await;
+            }
+            self.batches.lock().unwrap().push(texts.len());
+            Ok(texts.iter().map(|_| vec![0.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "counting"
+        }
+    }
+
+    fn texts(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("chunk {}", i)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batching_splits_into_upstream_calls() {
+        let inner = Arc::new(CountingProvider::new(0));
+        let provider = BatchedEmbeddings::new(inner.clone(), 16, 0);
+
+        let embeddings = provider.embed(texts(100)).await.unwrap();
+
+        assert_eq!(embeddings.len(), 100);
+        assert_eq!(
+            *inner.batches.lock().unwrap(),
+            vec![16, 16, 16, 16, 16, 16, 4]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_progress_reported_per_batch() {
+        let provider = BatchedEmbeddings::new(Arc::new(CountingProvider::new(0)), 4, 0);
+        let reports = std::sync::Mutex::new(Vec::new());
+
+        provider
+            .embed_with_progress(texts(10), &|done, total| {
+                reports.lock().unwrap().push((done, total))
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(*reports.lock().unwrap(), vec![(4, 10), (8, 10), (10, 10)]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_batch_is_retried() {
+        let inner = Arc::new(CountingProvider::new(2));
+        let provider = BatchedEmbeddings::new(inner.clone(), 8, 0);
+
+        let embeddings = provider.embed(texts(5)).await.unwrap();
+
+        assert_eq!(embeddings.len(), 5);
+        assert_eq!(*inner.batches.lock().unwrap(), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn test_identical_concurrent_queries_share_one_call() {
+        let inner = Arc::new(CountingProvider {
+            latency: Duration::from_millis(50),
+            ..CountingProvider::new(0)
+        });
+        let provider = BatchedEmbeddings::new(inner.clone(), 8, 0);
+        let query = || vec!["what is the refund policy?".to_string()];
+
+        let results = futures::future::join_all((0..8).map(|_| provider.embed(query()))).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(*inner.batches.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_caches_probed_dimension() {
+        struct WideProvider;
+
+        #[async_trait::async_trait]
+        impl EmbeddingProvider for WideProvider {
+            async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+                Ok(texts.iter().map(|_| vec![0.0; 8]).collect())
+            }
+
+            fn dimension(&self) -> usize {
+                1536
+            }
+
+            fn model_name(&self) -> &str {
+                "wide"
+            }
+        }
+
+        let provider = BatchedEmbeddings::new(Arc::new(WideProvider), 8, 0);
+        assert_eq!(provider.dimension(), 1536);
+        assert_eq!(provider.warmup().await.unwrap(), 8);
+        assert_eq!(provider.dimension(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_provider_fails_only_strict_startup() {
+        // A port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let provider = BatchedEmbeddings::new(
+            Arc::new(
+                KnoxChatEmbeddings::new(
+                    Some("test_key".to_string()),
+                    Some(format!("http://127.0.0.1:{}", port)),
+                    None,
+                )
+                .unwrap(),
+            ),
+            8,
+            0,
+        );
+
+        assert!(matches!(
+            warmup(&provider, true).await,
+            Err(EmbeddingError::ApiError(_))
+        ));
+        assert!(warmup(&provider, false).await.is_ok());
+        // Nothing was learned, so the model's nominal dimension stands
+        assert_eq!(provider.dimension(), 1024);
+    }
+
+    /// Records each input it is asked to embed
+    #[derive(Default)]
+    struct RecordingProvider {
+        inputs: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for RecordingProvider {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            let embeddings = texts.iter().map(|_| vec![0.0; 3]).collect();
+            self.inputs.lock().unwrap().extend(texts);
+            Ok(embeddings)
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "recording"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_overlong_input_truncated() {
+        let inner = Arc::new(RecordingProvider::default());
+        let provider = BatchedEmbeddings::new(inner.clone(), 8, 0).with_max_input_tokens(20, true);
+        let long = "The quick brown fox jumps over the lazy dog. ".repeat(50);
+
+        let embeddings = provider
+            .embed(vec!["short".to_string(), long.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings.len(), 2);
+        let inputs = inner.inputs.lock().unwrap();
+        assert_eq!(inputs[0], "short");
+        assert!(long.starts_with(&inputs[1]));
+        assert!(inputs[1].len() < long.len());
+        let tokens = tiktoken_rs::cl100k_base_singleton().encode_ordinary(&inputs[1]);
+        assert!(tokens.len() <= 20);
+    }
+
+    #[tokio::test]
+    async fn test_overlong_input_rejected_without_truncation() {
+        let inner = Arc::new(RecordingProvider::default());
+        let provider = BatchedEmbeddings::new(inner.clone(), 8, 0).with_max_input_tokens(20, false);
+
+        let result = provider.embed(vec!["word ".repeat(100)]).await;
+
+        assert!(matches!(result, Err(EmbeddingError::InvalidInput(_))));
+        assert!(inner.inputs.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncation_keeps_whole_characters() {
+        let text = "日本語のテキスト".repeat(20);
+        let truncated = truncate_to_tokens(&text, 7).unwrap();
+        assert!(text.starts_with(truncated));
+        assert!(truncate_to_tokens("fits", 7).is_none());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(parse_retry_after(&headers), None);
+    }
 }
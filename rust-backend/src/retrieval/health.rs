@@ -0,0 +1,360 @@
+//! Health-gated routing over the embedding provider and its fallbacks
+//!
+//! Every call records its outcome against the provider that served it. A provider whose
+//! error rate over its recent calls passes RAG_EMBEDDING_HEALTH_ERROR_RATE is taken out
+//! of rotation, so calls go straight to the next provider instead of retrying it first.
+//! After RAG_EMBEDDING_HEALTH_COOLDOWN it is probed with one call at a time, and it
+//! rejoins the rotation once enough probes succeed.
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::embeddings::{EmbeddingError, EmbeddingProvider};
+
+/// Recent calls the error rate is measured over
+const HEALTH_WINDOW: usize = 20;
+/// Calls needed before a provider's error rate is trusted, either way
+const MIN_SAMPLES: usize = 5;
+/// Weight of the newest call in the latency average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// A provider's health, as reported on /api/admin/rag/status
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub model: String,
+    /// Whether calls are routed to it
+    pub healthy: bool,
+    /// Share of successful calls among the recent ones
+    pub success_rate: Option<f64>,
+    /// Smoothed latency of successful calls
+    pub latency_ms: Option<f64>,
+    /// Seconds until a provider out of rotation is probed again
+    pub retry_in_secs: Option<u64>,
+}
+
+#[derive(Default)]
+struct HealthState {
+    outcomes: VecDeque<bool>,
+    latency_ms: Option<f64>,
+    /// Out of rotation until probes succeed; probing starts at the instant
+    ejected_until: Option<Instant>,
+    probing: bool,
+}
+
+impl HealthState {
+    fn error_rate(&self) -> f64 {
+        let failures = self.outcomes.iter().filter(|ok| !**ok).count();
+        failures as f64 / self.outcomes.len().max(1) as f64
+    }
+
+    fn push(&mut self, ok: bool) {
+        if self.outcomes.len() == HEALTH_WINDOW {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(ok);
+    }
+}
+
+struct TrackedProvider {
+    provider: Arc<dyn EmbeddingProvider>,
+    state: Mutex<HealthState>,
+}
+
+/// Embeds with the first healthy provider, falling back down the list on errors
+///
+/// Fallbacks must produce vectors in the primary's space (e.g. the same model behind
+/// another endpoint); the primary's name and dimension are the ones reported.
+pub struct HealthRoutedEmbeddings {
+    providers: Vec<TrackedProvider>,
+    max_error_rate: f64,
+    cooldown: Duration,
+}
+
+impl HealthRoutedEmbeddings {
+    /// `providers` in order of preference, the primary first
+    pub fn new(
+        providers: Vec<Arc<dyn EmbeddingProvider>>,
+        max_error_rate: f64,
+        cooldown: Duration,
+    ) -> Self {
+        assert!(!providers.is_empty(), "at least one embedding provider");
+        Self {
+            providers: providers
+                .into_iter()
+                .map(|provider| TrackedProvider {
+                    provider,
+                    state: Mutex::new(HealthState::default()),
+                })
+                .collect(),
+            max_error_rate,
+            cooldown,
+        }
+    }
+
+    /// Claim provider `index` for a call: `Some(false)` while it's in rotation,
+    /// `Some(true)` for the one probe allowed once its cooldown has passed, else `None`
+    fn claim(&self, index: usize) -> Option<bool> {
+        let mut state = self.providers[index].state.lock().unwrap();
+        match state.ejected_until {
+            None => Some(false),
+            Some(until) if until <= Instant::now() && !state.probing => {
+                state.probing = true;
+                Some(true)
+            }
+            Some(_) => None,
+        }
+    }
+
+    fn release_probe(&self, index: usize) {
+        self.providers[index].state.lock().unwrap().probing = false;
+    }
+
+    fn record(&self, index: usize, probe: bool, outcome: Result<Duration, ()>) {
+        let tracked = &self.providers[index];
+        let mut state = tracked.state.lock().unwrap();
+        if probe {
+            state.probing = false;
+        }
+
+        match outcome {
+            Ok(elapsed) => {
+                let ms = elapsed.as_secs_f64() * 1000.0;
+                state.latency_ms = Some(match state.latency_ms {
+                    Some(avg) => avg + LATENCY_SMOOTHING * (ms - avg),
+                    None => ms,
+                });
+                state.push(true);
+                if state.ejected_until.is_some()
+                    && state.outcomes.len() >= MIN_SAMPLES
+                    && state.error_rate() <= self.max_error_rate
+                {
+                    state.ejected_until = None;
+                    info!(
+                        "Embedding provider {} recovered, back in rotation",
+                        tracked.provider.model_name()
+                    );
+                }
+            }
+            Err(()) => {
+                state.push(false);
+                if state.ejected_until.is_some() {
+                    // A failed probe waits out another cooldown
+                    if probe {
+                        state.ejected_until = Some(Instant::now() + self.cooldown);
+                    }
+                } else if state.outcomes.len() >= MIN_SAMPLES
+                    && state.error_rate() > self.max_error_rate
+                {
+                    warn!(
+                        "Embedding provider {} is failing ({:.0}% of recent calls), \
+                         out of rotation for {}s",
+                        tracked.provider.model_name(),
+                        state.error_rate() * 100.0,
+                        self.cooldown.as_secs()
+                    );
+                    // Recovery is judged on the probes alone
+                    state.outcomes.clear();
+                    state.ejected_until = Some(Instant::now() + self.cooldown);
+                }
+            }
+        }
+    }
+
+    pub fn health(&self) -> Vec<ProviderHealth> {
+        let now = Instant::now();
+        self.providers
+            .iter()
+            .map(|tracked| {
+                let state = tracked.state.lock().unwrap();
+                ProviderHealth {
+                    model: tracked.provider.model_name().to_string(),
+                    healthy: state.ejected_until.is_none(),
+                    success_rate: (!state.outcomes.is_empty()).then(|| 1.0 - state.error_rate()),
+                    latency_ms: state.latency_ms,
+                    retry_in_secs: state
+                        .ejected_until
+                        .map(|until| until.saturating_duration_since(now).as_secs()),
+                }
+            })
+            .collect()
+    }
+
+    /// Call provider `index`; an error is final when trying the next provider won't help
+    async fn attempt(
+        &self,
+        index: usize,
+        probe: bool,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>, (EmbeddingError, bool)> {
+        let provider = &self.providers[index].provider;
+        let started = Instant::now();
+        match provider.embed(texts.to_vec()).await {
+            Ok(embeddings) => {
+                self.record(index, probe, Ok(started.elapsed()));
+                Ok(embeddings)
+            }
+            // The same input fails everywhere; not the provider's fault
+            Err(e @ EmbeddingError::InvalidInput(_)) => {
+                if probe {
+                    self.release_probe(index);
+                }
+                Err((e, true))
+            }
+            // Busy rather than broken: try elsewhere without counting it
+            Err(e @ EmbeddingError::RateLimited { .. }) => {
+                if probe {
+                    self.release_probe(index);
+                }
+                Err((e, false))
+            }
+            Err(e) => {
+                warn!("Embedding provider {} failed: {}", provider.model_name(), e);
+                self.record(index, probe, Err(()));
+                Err((e, false))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingProvider for HealthRoutedEmbeddings {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        // Providers in rotation or due a probe first, the rest as a last resort
+        let mut skipped = Vec::new();
+        let mut last_error = None;
+        for index in 0..self.providers.len() {
+            let Some(probe) = self.claim(index) else {
+                skipped.push(index);
+                continue;
+            };
+            match self.attempt(index, probe, &texts).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err((e, true)) => return Err(e),
+                Err((e, false)) => last_error = Some(e),
+            }
+        }
+        for index in skipped {
+            match self.attempt(index, false, &texts).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err((e, true)) => return Err(e),
+                Err((e, false)) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("at least one embedding provider"))
+    }
+
+    fn dimension(&self) -> usize {
+        self.providers[0].provider.dimension()
+    }
+
+    fn model_name(&self) -> &str {
+        self.providers[0].provider.model_name()
+    }
+
+    fn provider_health(&self) -> Vec<ProviderHealth> {
+        self.health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    struct FlakyProvider {
+        name: &'static str,
+        failing: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    impl FlakyProvider {
+        fn new(name: &'static str, failing: bool) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                failing: AtomicBool::new(failing),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for FlakyProvider {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(EmbeddingError::ApiError(
+                    "503 Service Unavailable".to_string(),
+                ));
+            }
+            Ok(texts.iter().map(|_| vec![1.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            self.name
+        }
+    }
+
+    fn text() -> Vec<String> {
+        vec!["hello".to_string()]
+    }
+
+    #[tokio::test]
+    async fn test_failing_provider_leaves_rotation_and_recovers() {
+        let primary = FlakyProvider::new("primary", true);
+        let fallback = FlakyProvider::new("fallback", false);
+        let embeddings = HealthRoutedEmbeddings::new(
+            vec![primary.clone(), fallback.clone()],
+            0.5,
+            Duration::from_millis(100),
+        );
+
+        // Each failure falls back, until the primary is taken out of rotation
+        for _ in 0..MIN_SAMPLES {
+            embeddings.embed(text()).await.unwrap();
+        }
+        assert_eq!(primary.calls(), MIN_SAMPLES);
+        assert_eq!(fallback.calls(), MIN_SAMPLES);
+        let health = embeddings.health();
+        assert!(!health[0].healthy);
+        assert!(health[1].healthy);
+        assert_eq!(health[1].success_rate, Some(1.0));
+
+        // Out of rotation: no more attempts on the primary
+        embeddings.embed(text()).await.unwrap();
+        assert_eq!(primary.calls(), MIN_SAMPLES);
+
+        // Once the cooldown passes, successful probes bring it back
+        primary.failing.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        for _ in 0..MIN_SAMPLES {
+            embeddings.embed(text()).await.unwrap();
+        }
+        assert_eq!(primary.calls(), 2 * MIN_SAMPLES);
+        let health = embeddings.health();
+        assert!(health[0].healthy);
+        assert_eq!(health[0].retry_in_secs, None);
+        assert_eq!(fallback.calls(), MIN_SAMPLES + 1);
+    }
+
+    #[tokio::test]
+    async fn test_everything_unhealthy_still_tries_in_order() {
+        let only = FlakyProvider::new("only", true);
+        let embeddings =
+            HealthRoutedEmbeddings::new(vec![only.clone()], 0.5, Duration::from_secs(60));
+        for _ in 0..MIN_SAMPLES + 2 {
+            assert!(embeddings.embed(text()).await.is_err());
+        }
+        assert_eq!(only.calls(), MIN_SAMPLES + 2);
+        assert!(!embeddings.health()[0].healthy);
+    }
+}
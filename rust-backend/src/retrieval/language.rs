@@ -0,0 +1,229 @@
+//! Language detection and per-language embedding models
+//!
+//! With RAG_LANGUAGE_DETECTION on, each chunk and query is run through a lightweight
+//! detector. Languages mapped in RAG_EMBEDDING_LANGUAGE_MODELS are embedded with their
+//! own model and stored in a sub-collection of the knowledge base, since vectors from
+//! different models can't be compared. Everything else, including text the detector
+//! isn't sure about, uses the default model and collection.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::embeddings::{EmbeddingError, EmbeddingProvider};
+use super::health::ProviderHealth;
+
+/// ISO 639-3 code of `text`'s language, or `None` when detection isn't confident
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code())
+}
+
+/// Where a piece of text should be embedded
+#[derive(Clone, Default)]
+pub struct LanguageRoute {
+    /// Detected language, when detection was confident
+    pub language: Option<&'static str>,
+    /// Model configured for that language; `None` means the default model and collection
+    pub provider: Option<Arc<dyn EmbeddingProvider>>,
+}
+
+impl LanguageRoute {
+    /// Language whose sub-collection the text belongs in, if it has its own model
+    pub fn sub_collection(&self) -> Option<&'static str> {
+        self.provider.as_ref().and(self.language)
+    }
+}
+
+/// Collection holding `language`'s vectors for the `base` collection
+pub fn language_collection(base: &str, language: Option<&str>) -> String {
+    match language {
+        Some(language) => format!("{}_{}", base, language),
+        None => base.to_string(),
+    }
+}
+
+/// `base` and every per-language sub-collection `provider` may have written to it
+pub fn collection_names(provider: &dyn EmbeddingProvider, base: &str) -> Vec<String> {
+    std::iter::once(base.to_string())
+        .chain(
+            provider
+                .routed_languages()
+                .iter()
+                .map(|language| language_collection(base, Some(language))),
+        )
+        .collect()
+}
+
+/// Embeds with the default model and routes detected languages to their own models
+pub struct LanguageEmbeddings {
+    default: Arc<dyn EmbeddingProvider>,
+    models: HashMap<String, Arc<dyn EmbeddingProvider>>,
+    /// Default model plus the language map, so index fingerprints change with either
+    name: String,
+}
+
+impl LanguageEmbeddings {
+    /// `models` maps ISO 639-3 codes (e.g. `deu`, `jpn`) to their providers
+    pub fn new(
+        default: Arc<dyn EmbeddingProvider>,
+        models: HashMap<String, Arc<dyn EmbeddingProvider>>,
+    ) -> Self {
+        let mut routes: Vec<String> = models
+            .iter()
+            .map(|(language, provider)| format!("{}={}", language, provider.model_name()))
+            .collect();
+        routes.sort();
+
+        let name = if routes.is_empty() {
+            default.model_name().to_string()
+        } else {
+            format!("{}+{}", default.model_name(), routes.join(","))
+        };
+
+        Self {
+            default,
+            models,
+            name,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingProvider for LanguageEmbeddings {
+    async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        self.default.embed(texts).await
+    }
+
+    async fn embed_with_progress(
+        &self,
+        texts: Vec<String>,
+        progress: &(dyn Fn(usize, usize) + Send + Sync),
+    ) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        self.default.embed_with_progress(texts, progress).await
+    }
+
+    async fn warmup(&self) -> Result<usize, EmbeddingError> {
+        self.default.warmup().await
+    }
+
+    fn dimension(&self) -> usize {
+        self.default.dimension()
+    }
+
+    fn model_name(&self) -> &str {
+        &self.name
+    }
+
+    fn route_language(&self, text: &str) -> LanguageRoute {
+        let Some(language) = detect_language(text) else {
+            return LanguageRoute::default();
+        };
+        LanguageRoute {
+            language: Some(language),
+            provider: self.models.get(language).cloned(),
+        }
+    }
+
+    fn routed_languages(&self) -> Vec<String> {
+        self.models.keys().cloned().collect()
+    }
+
+    fn provider_health(&self) -> Vec<ProviderHealth> {
+        self.default.provider_health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NamedProvider(&'static str);
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for NamedProvider {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            Ok(texts.iter().map(|_| vec![0.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            self.0
+        }
+    }
+
+    fn embeddings() -> LanguageEmbeddings {
+        let models: HashMap<String, Arc<dyn EmbeddingProvider>> =
+            [("eng", "english-model"), ("deu", "german-model")]
+                .into_iter()
+                .map(|(language, model)| {
+                    (
+                        language.to_string(),
+                        Arc::new(NamedProvider(model)) as Arc<dyn EmbeddingProvider>,
+                    )
+                })
+                .collect();
+        LanguageEmbeddings::new(Arc::new(NamedProvider("default-model")), models)
+    }
+
+    fn routed_model(embeddings: &LanguageEmbeddings, text: &str) -> Option<String> {
+        embeddings
+            .route_language(text)
+            .provider
+            .map(|provider| provider.model_name().to_string())
+    }
+
+    #[test]
+    fn test_languages_route_to_configured_models() {
+        let embeddings = embeddings();
+
+        let english = "The quarterly report shows that revenue grew steadily across all \
+                       regions, while operating costs remained below the forecast.";
+        let german = "Der Quartalsbericht zeigt, dass der Umsatz in allen Regionen stetig \
+                      gewachsen ist, während die Betriebskosten unter der Prognose blieben.";
+        let french = "Le rapport trimestriel montre que le chiffre d'affaires a augmenté \
+                      dans toutes les régions, tandis que les coûts sont restés faibles.";
+
+        assert_eq!(
+            routed_model(&embeddings, english).as_deref(),
+            Some("english-model")
+        );
+        assert_eq!(
+            routed_model(&embeddings, german).as_deref(),
+            Some("german-model")
+        );
+
+        // Detected, but no model configured: default model, language still recorded
+        let route = embeddings.route_language(french);
+        assert_eq!(route.language, Some("fra"));
+        assert!(route.provider.is_none());
+        assert_eq!(route.sub_collection(), None);
+    }
+
+    #[test]
+    fn test_uncertain_detection_uses_default() {
+        let route = embeddings().route_language("ok");
+        assert!(route.language.is_none());
+        assert!(route.provider.is_none());
+    }
+
+    #[test]
+    fn test_collection_names_include_sub_collections() {
+        let mut names = collection_names(&embeddings(), "kb1");
+        names.sort();
+        assert_eq!(names, vec!["kb1", "kb1_deu", "kb1_eng"]);
+
+        let plain = NamedProvider("default-model");
+        assert_eq!(collection_names(&plain, "kb1"), vec!["kb1"]);
+    }
+
+    #[test]
+    fn test_model_name_reflects_language_map() {
+        assert_eq!(
+            embeddings().model_name(),
+            "default-model+deu=german-model,eng=english-model"
+        );
+    }
+}
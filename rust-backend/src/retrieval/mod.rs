@@ -1,7 +1,13 @@
 pub mod chunking;
 pub mod embeddings;
+pub mod health;
+pub mod language;
+pub mod normalize;
+pub mod search;
 pub mod vector;
 
-pub use chunking::{chunk_text, ChunkingConfig};
-pub use embeddings::{EmbeddingError, EmbeddingFactory, EmbeddingFunction, EmbeddingProvider};
-pub use vector::{VectorDB, VectorDBFactory, VectorError};
+pub use chunking::{ChunkStrategy, Chunker, ChunkingConfig};
+pub use embeddings::{BatchedEmbeddings, EmbeddingFactory, EmbeddingProvider};
+pub use health::HealthRoutedEmbeddings;
+pub use language::{LanguageEmbeddings, LanguageRoute};
+pub use vector::{DistanceMetric, MetadataFilter, VectorDB, VectorDBFactory, VectorError};
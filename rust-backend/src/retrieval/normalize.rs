@@ -0,0 +1,318 @@
+//! Text cleanup before chunking (RAG_TEXT_NORMALIZE)
+//!
+//! Text extracted from PDFs and office documents carries layout noise: runs of spaces
+//! from column alignment, control characters, and page headers and footers repeated on
+//! every page. Left in, they end up in chunks and pull embeddings towards each other.
+//! Each cleaning step is enabled on its own; the set in use is part of a file's index
+//! state, so changing it marks existing vectors stale for the next reindex.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+
+use super::chunking::ChunkStrategy;
+
+/// Steps `RAG_TEXT_NORMALIZE=true` enables
+const DEFAULT_STEPS: &str = "nfc,control,whitespace";
+
+/// Longest line considered as a page header or footer, in characters
+const MAX_HEADER_CHARS: usize = 80;
+
+/// Lines at each end of a page checked for headers and footers
+const HEADER_LINES: usize = 2;
+
+/// Unbroken text repeating a line this often treats it as a header or footer
+const MIN_REPEATS: usize = 3;
+
+/// The cleaning steps applied to extracted text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextNormalization {
+    /// Unicode NFC, so composed and decomposed accents embed alike
+    pub unicode_nfc: bool,
+    /// Lines repeated at the top or bottom of pages, such as titles and page numbers
+    pub remove_headers_footers: bool,
+    /// Control characters other than newlines and tabs; page breaks become newlines
+    pub strip_control: bool,
+    /// Runs of spaces within lines, trailing spaces and runs of blank lines
+    pub collapse_whitespace: bool,
+}
+
+impl TextNormalization {
+    /// Parse `true`/`false` or a comma-separated list of steps: `nfc`, `headers`,
+    /// `control` and `whitespace`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim().to_ascii_lowercase();
+        let steps = match value.as_str() {
+            "" | "false" | "none" => return Ok(Self::default()),
+            "true" => DEFAULT_STEPS,
+            "all" => "nfc,headers,control,whitespace",
+            steps => steps,
+        };
+
+        let mut normalization = Self::default();
+        for step in steps.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match step {
+                "nfc" => normalization.unicode_nfc = true,
+                "headers" => normalization.remove_headers_footers = true,
+                "control" => normalization.strip_control = true,
+                "whitespace" => normalization.collapse_whitespace = true,
+                _ => {
+                    return Err(format!(
+                        "unknown step '{}': expected nfc, headers, control or whitespace",
+                        step
+                    ))
+                }
+            }
+        }
+        Ok(normalization)
+    }
+
+    pub fn from_env() -> Self {
+        std::env::var("RAG_TEXT_NORMALIZE")
+            .ok()
+            .and_then(|value| Self::parse(&value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// The steps that suit `strategy`: code and CSV keep their layout, which their
+    /// chunkers rely on
+    pub fn for_strategy(&self, strategy: ChunkStrategy) -> Self {
+        match strategy {
+            ChunkStrategy::Code | ChunkStrategy::Csv => Self {
+                remove_headers_footers: false,
+                collapse_whitespace: false,
+                ..*self
+            },
+            ChunkStrategy::Markdown | ChunkStrategy::Prose => *self,
+        }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = if self.unicode_nfc {
+            text.nfc().collect()
+        } else {
+            text.to_string()
+        };
+        if self.remove_headers_footers {
+            text = remove_headers_footers(&text);
+        }
+        if self.strip_control {
+            text = strip_control(&text);
+        }
+        if self.collapse_whitespace {
+            text = collapse_whitespace(&text);
+        }
+        text
+    }
+}
+
+/// The enabled steps in canonical form, as recorded in the index state
+impl fmt::Display for TextNormalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps: Vec<&str> = [
+            (self.unicode_nfc, "nfc"),
+            (self.remove_headers_footers, "headers"),
+            (self.strip_control, "control"),
+            (self.collapse_whitespace, "whitespace"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect();
+        write!(f, "{}", steps.join(","))
+    }
+}
+
+/// Line compared across pages: page numbers and dates differ, so digits don't count
+fn header_key(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.chars().count() > MAX_HEADER_CHARS {
+        return None;
+    }
+    Some(
+        line.chars()
+            .map(|c| if c.is_ascii_digit() { '#' } else { c })
+            .collect::<String>()
+            .to_lowercase(),
+    )
+}
+
+/// Drop lines repeated at the top or bottom of pages
+///
+/// Pages are separated by form feeds, as most PDF extractors emit them. A line counts as
+/// a header or footer when it is among the first or last lines of at least half the
+/// pages (and at least two). Without page breaks, short lines repeated
+/// [`MIN_REPEATS`] times are dropped instead.
+fn remove_headers_footers(text: &str) -> String {
+    let pages: Vec<&str> = text.split('\x0c').collect();
+    let repeated: HashSet<String> = if pages.len() > 1 {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for page in &pages {
+            let lines: Vec<&str> = page.lines().filter(|l| !l.trim().is_empty()).collect();
+            let edges = lines
+                .iter()
+                .take(HEADER_LINES)
+                .chain(lines.iter().rev().take(HEADER_LINES));
+            let keys: HashSet<String> = edges.filter_map(|line| header_key(line)).collect();
+            for key in keys {
+                *seen.entry(key).or_default() += 1;
+            }
+        }
+        let needed = pages.len().div_ceil(2).max(2);
+        seen.into_iter()
+            .filter(|(_, count)| *count >= needed)
+            .map(|(key, _)| key)
+            .collect()
+    } else {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for key in text.lines().filter_map(header_key) {
+            *seen.entry(key).or_default() += 1;
+        }
+        seen.into_iter()
+            .filter(|(_, count)| *count >= MIN_REPEATS)
+            .map(|(key, _)| key)
+            .collect()
+    };
+    if repeated.is_empty() {
+        return text.to_string();
+    }
+
+    pages
+        .iter()
+        .map(|page| {
+            page.lines()
+                .filter(|line| header_key(line).is_none_or(|key| !repeated.contains(&key)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\x0c")
+}
+
+fn strip_control(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '\n' | '\t' => Some(c),
+            // Page breaks end a line; carriage returns belong to a following newline
+            '\x0c' | '\x0b' => Some('\n'),
+            c if c.is_control() => None,
+            c => Some(c),
+        })
+        .collect()
+}
+
+/// Single spaces within lines, no trailing spaces, at most one blank line in a row;
+/// indentation is kept
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            blank_run += 1;
+            if blank_run == 1 && !out.is_empty() {
+                out.push('\n');
+            }
+            continue;
+        }
+        blank_run = 0;
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        out.push_str(indent);
+        let mut words = trimmed.split_whitespace();
+        if let Some(first) = words.next() {
+            out.push_str(first);
+        }
+        for word in words {
+            out.push(' ');
+            out.push_str(word);
+        }
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two pages of a report as a PDF extractor hands them over
+    const PDF_TEXT: &str = "ACME Corp — Annual Report 2023\n\
+        Revenue   grew  by 12%\tover the year,\n\
+        driven by    new markets.   \n\
+        \n\n\n\
+        Costs were flat.\n\
+        Page 1 of 2\n\
+        \x0c\
+        ACME Corp — Annual Report 2023\n\
+        Outlook\x07 for 2024 is   stable.\r\n\
+        Page 2 of 2\n";
+
+    #[test]
+    fn test_whitespace_and_control_characters_cleaned() {
+        let normalization = TextNormalization::parse("control,whitespace").unwrap();
+        let cleaned = normalization.apply(PDF_TEXT);
+        assert!(cleaned.contains("Revenue grew by 12% over the year,\ndriven by new markets."));
+        assert!(cleaned.contains("new markets.\n\nCosts were flat."));
+        assert!(cleaned.contains("Outlook for 2024 is stable.\nPage 2 of 2"));
+        assert!(!cleaned.contains(['\x0c', '\x07', '\r']));
+
+        // Indentation survives
+        assert_eq!(
+            collapse_whitespace("fn main() {\n    let  x = 1;\n}"),
+            "fn main() {\n    let x = 1;\n}"
+        );
+    }
+
+    #[test]
+    fn test_headers_and_page_numbers_removed() {
+        let cleaned = TextNormalization::parse("all").unwrap().apply(PDF_TEXT);
+        assert!(!cleaned.contains("Annual Report"));
+        assert!(!cleaned.contains("Page 1 of 2"));
+        assert!(!cleaned.contains("Page 2 of 2"));
+        assert!(cleaned.starts_with("Revenue grew by 12%"));
+        assert!(cleaned.ends_with("Outlook for 2024 is stable."));
+
+        // Without page breaks, only lines repeated often enough go
+        let text = "Draft\nIntro text.\nDraft\nMore text.\nDraft\nEnd text.";
+        assert_eq!(
+            remove_headers_footers(text),
+            "Intro text.\nMore text.\nEnd text."
+        );
+        assert_eq!(
+            remove_headers_footers("Draft\nBody\nDraft"),
+            "Draft\nBody\nDraft"
+        );
+    }
+
+    #[test]
+    fn test_steps_parse_and_display() {
+        assert_eq!(
+            TextNormalization::parse("").unwrap(),
+            TextNormalization::default()
+        );
+        assert_eq!(
+            TextNormalization::parse("true").unwrap().to_string(),
+            "nfc,control,whitespace"
+        );
+        assert_eq!(
+            TextNormalization::parse(" Whitespace , headers")
+                .unwrap()
+                .to_string(),
+            "headers,whitespace"
+        );
+        assert!(TextNormalization::parse("whitespace,boilerplate").is_err());
+
+        let all = TextNormalization::parse("all").unwrap();
+        assert_eq!(
+            all.for_strategy(ChunkStrategy::Code).to_string(),
+            "nfc,control"
+        );
+        assert_eq!(
+            TextNormalization::parse("nfc").unwrap().apply("e\u{301}"),
+            "\u{e9}"
+        );
+    }
+}
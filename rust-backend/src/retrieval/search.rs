@@ -0,0 +1,475 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use super::embeddings::EmbeddingProvider;
+use super::language::language_collection;
+use super::vector::filter::MetadataFilter;
+use super::vector::types::{DistanceMetric, SearchResult, VectorDB, VectorError};
+
+/// A retrieved chunk with its relevance scores
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredChunk {
+    pub id: String,
+    pub text: String,
+    pub metadata: serde_json::Value,
+    /// Vector similarity score (0..1, higher is more relevant)
+    pub score: f32,
+    /// Cross-encoder relevance score, when re-ranking ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerank_score: Option<f32>,
+}
+
+/// Options for a collection query
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub k: usize,
+    /// Results scoring below this are dropped (0 keeps everything)
+    pub score_threshold: f32,
+    /// Cross-encoder endpoint; re-ranking is skipped when unset
+    pub rerank_url: Option<String>,
+    /// Number of results kept after re-ranking
+    pub rerank_top_k: usize,
+    /// Only chunks whose payload matches are searched
+    pub filter: Option<MetadataFilter>,
+}
+
+impl SearchOptions {
+    /// Defaults from the RAG settings
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self {
+            k: config.rag_top_k,
+            score_threshold: config.rag_score_threshold,
+            rerank_url: config.rag_rerank_url.clone(),
+            rerank_top_k: config.top_k_reranker.max(1) as usize,
+            filter: None,
+        }
+    }
+}
+
+/// Source of chunk text kept outside the vector DB
+///
+/// Some vector backends don't return payload text reliably; when a store is passed to
+/// [`search_collection`], its text replaces whatever the search returned.
+#[async_trait]
+pub trait ChunkTextStore: Send + Sync {
+    /// Text of the chunks with these vector ids in `collection`, keyed by id
+    async fn chunk_texts(
+        &self,
+        collection: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, String>, VectorError>;
+}
+
+/// Flatten a single-query search result into scored chunks
+pub fn scored_chunks(result: SearchResult, distance: DistanceMetric) -> Vec<ScoredChunk> {
+    let ids = result
+        .ids
+        .and_then(|ids| ids.into_iter().next())
+        .unwrap_or_default();
+    let mut documents = result
+        .documents
+        .and_then(|docs| docs.into_iter().next())
+        .unwrap_or_default()
+        .into_iter();
+    let mut metadatas = result
+        .metadatas
+        .and_then(|metas| metas.into_iter().next())
+        .unwrap_or_default()
+        .into_iter();
+    let mut distances = result
+        .distances
+        .and_then(|dists| dists.into_iter().next())
+        .unwrap_or_default()
+        .into_iter();
+
+    ids.into_iter()
+        .map(|id| ScoredChunk {
+            id,
+            text: documents.next().unwrap_or_default(),
+            metadata: metadatas.next().unwrap_or(serde_json::Value::Null),
+            score: distances.next().map(|d| distance.score(d)).unwrap_or(0.0),
+            rerank_score: None,
+        })
+        .collect()
+}
+
+/// Replace chunk text with the stored text, where there is some
+///
+/// Lookup failures are logged and the vector DB's text kept.
+async fn fill_chunk_texts(
+    store: &dyn ChunkTextStore,
+    collection: &str,
+    chunks: &mut [ScoredChunk],
+) {
+    if chunks.is_empty() {
+        return;
+    }
+    let ids: Vec<String> = chunks.iter().map(|chunk| chunk.id.clone()).collect();
+    match store.chunk_texts(collection, &ids).await {
+        Ok(mut texts) => {
+            for chunk in chunks {
+                if let Some(text) = texts.remove(&chunk.id) {
+                    chunk.text = text;
+                }
+            }
+        }
+        Err(e) => warn!("{}, using vector payload text", e),
+    }
+}
+
+/// Drop chunks scoring below `threshold`
+pub fn filter_by_threshold(chunks: Vec<ScoredChunk>, threshold: f32) -> Vec<ScoredChunk> {
+    chunks
+        .into_iter()
+        .filter(|chunk| chunk.score >= threshold)
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    results: Vec<RerankResult>,
+}
+
+#[derive(Deserialize)]
+struct RerankResult {
+    index: usize,
+    #[serde(alias = "score")]
+    relevance_score: f32,
+}
+
+/// Reorder chunks by relevance using a cross-encoder endpoint
+///
+/// Posts `{query, documents, top_n}` and expects `{results: [{index, relevance_score}]}`
+/// (the Cohere/Jina/TEI rerank shape).
+pub async fn rerank(
+    client: &reqwest::Client,
+    url: &str,
+    query: &str,
+    chunks: Vec<ScoredChunk>,
+    top_n: usize,
+) -> Result<Vec<ScoredChunk>, VectorError> {
+    if chunks.is_empty() {
+        return Ok(chunks);
+    }
+
+    let documents: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+    let response = client
+        .post(url)
+        .timeout(Duration::from_secs(30))
+        .json(&serde_json::json!({
+            "query": query,
+            "documents": documents,
+            "top_n": top_n,
+        }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| VectorError::OperationError(format!("Re-ranking failed: {}", e)))?;
+
+    let ranked: RerankResponse = response
+        .json()
+        .await
+        .map_err(|e| VectorError::SerializationError(format!("Invalid rerank response: {}", e)))?;
+
+    let mut chunks: Vec<Option<ScoredChunk>> = chunks.into_iter().map(Some).collect();
+    let mut reranked: Vec<ScoredChunk> = ranked
+        .results
+        .into_iter()
+        .filter_map(|result| {
+            let mut chunk = chunks.get_mut(result.index)?.take()?;
+            chunk.rerank_score = Some(result.relevance_score);
+            Some(chunk)
+        })
+        .collect();
+
+    reranked.sort_by(|a, b| {
+        b.rerank_score
+            .partial_cmp(&a.rerank_score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    reranked.truncate(top_n);
+    Ok(reranked)
+}
+
+/// Embed `query`, search `collection_name`, apply the score threshold and re-rank
+///
+/// A query in a language with its own embedding model is embedded with that model and
+/// searched in the language's sub-collection, falling back to the default model and
+/// collection when that sub-collection doesn't exist. Re-ranking failures are logged and
+/// the threshold-filtered vector results returned. Chunk text comes from `chunk_store`
+/// when given, keyed by `collection_name` whichever sub-collection was searched.
+pub async fn search_collection(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    client: &reqwest::Client,
+    collection_name: &str,
+    query: &str,
+    options: &SearchOptions,
+    chunk_store: Option<&dyn ChunkTextStore>,
+) -> Result<Vec<ScoredChunk>, VectorError> {
+    let base_collection = collection_name;
+    let route = embedding_provider.route_language(query);
+    let mut embedding_provider = embedding_provider;
+    let mut collection = collection_name.to_string();
+    if let (Some(provider), Some(language)) = (&route.provider, route.sub_collection()) {
+        let sub_collection = language_collection(collection_name, Some(language));
+        if vector_db.has_collection(&sub_collection).await? {
+            embedding_provider = provider;
+            collection = sub_collection;
+        } else {
+            debug!(
+                "No {} sub-collection for {}, using the default model",
+                language, collection_name
+            );
+        }
+    }
+    let collection_name = collection.as_str();
+
+    if !vector_db.has_collection(collection_name).await? {
+        debug!("Collection {} does not exist, no results", collection_name);
+        return Ok(Vec::new());
+    }
+
+    let query_vector = embedding_provider
+        .embed(vec![query.to_string()])
+        .await
+        .map_err(|e| VectorError::OperationError(format!("Failed to embed query: {}", e)))?;
+
+    let result = vector_db
+        .search(
+            collection_name,
+            query_vector,
+            options.k,
+            options.filter.as_ref(),
+        )
+        .await?;
+    let mut chunks = filter_by_threshold(
+        scored_chunks(result, vector_db.distance()),
+        options.score_threshold,
+    );
+    if let Some(store) = chunk_store {
+        fill_chunk_texts(store, base_collection, &mut chunks).await;
+    }
+
+    match options.rerank_url.as_deref() {
+        Some(url) => match rerank(client, url, query, chunks.clone(), options.rerank_top_k).await {
+            Ok(reranked) => Ok(reranked),
+            Err(e) => {
+                warn!("{}, using vector search order", e);
+                Ok(chunks)
+            }
+        },
+        None => Ok(chunks),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_result(distances: Vec<f32>) -> SearchResult {
+        let n = distances.len();
+        SearchResult {
+            ids: Some(vec![(0..n).map(|i| format!("chunk-{}", i)).collect()]),
+            documents: Some(vec![(0..n).map(|i| format!("text {}", i)).collect()]),
+            metadatas: Some(vec![vec![serde_json::json!({}); n]]),
+            distances: Some(vec![distances]),
+        }
+    }
+
+    #[test]
+    fn test_sub_threshold_results_excluded() {
+        // Scores 0.95, 0.6 and 0.2
+        let chunks = scored_chunks(
+            search_result(vec![0.1, 0.8, 1.6]),
+            DistanceMetric::Euclidean,
+        );
+        let kept = filter_by_threshold(chunks, 0.5);
+
+        let ids: Vec<&str> = kept.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, vec!["chunk-0", "chunk-1"]);
+    }
+
+    #[test]
+    fn test_zero_threshold_keeps_everything() {
+        let chunks = scored_chunks(search_result(vec![0.1, 3.5]), DistanceMetric::Euclidean);
+        assert_eq!(filter_by_threshold(chunks, 0.0).len(), 2);
+    }
+
+    struct FakeEmbeddings;
+
+    #[async_trait]
+    impl EmbeddingProvider for FakeEmbeddings {
+        async fn embed(
+            &self,
+            texts: Vec<String>,
+        ) -> Result<Vec<Vec<f32>>, crate::retrieval::embeddings::EmbeddingError> {
+            Ok(texts.iter().map(|_| vec![0.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "fake"
+        }
+    }
+
+    /// Returns ids and distances but no documents, like a backend without payload text
+    struct PayloadlessVectorDb;
+
+    #[async_trait]
+    impl VectorDB for PayloadlessVectorDb {
+        async fn has_collection(&self, _name: &str) -> Result<bool, VectorError> {
+            Ok(true)
+        }
+
+        async fn count(&self, _name: &str) -> Result<usize, VectorError> {
+            Ok(2)
+        }
+
+        async fn delete_collection(&self, _name: &str) -> Result<(), VectorError> {
+            Ok(())
+        }
+
+        async fn insert(
+            &self,
+            _name: &str,
+            _items: Vec<super::super::vector::VectorItem>,
+        ) -> Result<(), VectorError> {
+            Ok(())
+        }
+
+        async fn upsert(
+            &self,
+            _name: &str,
+            _items: Vec<super::super::vector::VectorItem>,
+        ) -> Result<(), VectorError> {
+            Ok(())
+        }
+
+        async fn search(
+            &self,
+            _name: &str,
+            _vectors: Vec<Vec<f32>>,
+            _limit: usize,
+            _filter: Option<&MetadataFilter>,
+        ) -> Result<SearchResult, VectorError> {
+            Ok(SearchResult {
+                ids: Some(vec![vec![
+                    "f1-chunk-0".to_string(),
+                    "f1-chunk-1".to_string(),
+                ]]),
+                documents: None,
+                metadatas: None,
+                distances: Some(vec![vec![0.1, 0.2]]),
+            })
+        }
+
+        async fn query(
+            &self,
+            _name: &str,
+            _filter: serde_json::Value,
+            _limit: Option<usize>,
+        ) -> Result<super::super::vector::GetResult, VectorError> {
+            unimplemented!()
+        }
+
+        async fn get(&self, _name: &str) -> Result<super::super::vector::GetResult, VectorError> {
+            unimplemented!()
+        }
+
+        async fn delete(
+            &self,
+            _name: &str,
+            _ids: Option<Vec<String>>,
+            _filter: Option<serde_json::Value>,
+        ) -> Result<(), VectorError> {
+            Ok(())
+        }
+
+        async fn reset(&self) -> Result<(), VectorError> {
+            Ok(())
+        }
+    }
+
+    struct MemoryChunkStore(HashMap<(String, String), String>);
+
+    #[async_trait]
+    impl ChunkTextStore for MemoryChunkStore {
+        async fn chunk_texts(
+            &self,
+            collection: &str,
+            ids: &[String],
+        ) -> Result<HashMap<String, String>, VectorError> {
+            Ok(ids
+                .iter()
+                .filter_map(|id| {
+                    let text = self.0.get(&(collection.to_string(), id.clone()))?;
+                    Some((id.clone(), text.clone()))
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_results_carry_stored_text_without_payload() {
+        let vector_db: Arc<dyn VectorDB> = Arc::new(PayloadlessVectorDb);
+        let embedding_provider: Arc<dyn EmbeddingProvider> = Arc::new(FakeEmbeddings);
+        let store = MemoryChunkStore(HashMap::from([(
+            ("kb1".to_string(), "f1-chunk-0".to_string()),
+            "Refunds are issued within 14 days.".to_string(),
+        )]));
+        let options = SearchOptions {
+            k: 5,
+            score_threshold: 0.0,
+            rerank_url: None,
+            rerank_top_k: 5,
+            filter: None,
+        };
+        let client = reqwest::Client::new();
+
+        let chunks = search_collection(
+            &vector_db,
+            &embedding_provider,
+            &client,
+            "kb1",
+            "refund policy",
+            &options,
+            Some(&store),
+        )
+        .await
+        .unwrap();
+        assert_eq!(chunks[0].text, "Refunds are issued within 14 days.");
+        // Nothing stored for this chunk, so it keeps the (empty) payload text
+        assert_eq!(chunks[1].text, "");
+
+        let without_store = search_collection(
+            &vector_db,
+            &embedding_provider,
+            &client,
+            "kb1",
+            "refund policy",
+            &options,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(without_store.iter().all(|chunk| chunk.text.is_empty()));
+    }
+
+    #[test]
+    fn test_distance_to_score() {
+        assert_eq!(DistanceMetric::Euclidean.score(0.0), 1.0);
+        assert_eq!(DistanceMetric::Euclidean.score(1.0), 0.5);
+        assert_eq!(DistanceMetric::Euclidean.score(4.0), 0.0);
+        assert_eq!(DistanceMetric::Cosine.score(0.25), 0.75);
+        assert_eq!(DistanceMetric::Cosine.score(1.5), 0.0);
+        assert_eq!(DistanceMetric::Dot.score(-0.5), 1.0);
+    }
+}
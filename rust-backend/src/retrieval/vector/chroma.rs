@@ -1,4 +1,5 @@
-use super::types::{GetResult, SearchResult, VectorDB, VectorError, VectorItem};
+use super::filter::MetadataFilter;
+use super::types::{DistanceMetric, GetResult, SearchResult, VectorDB, VectorError, VectorItem};
 use async_trait::async_trait;
 use chromadb::client::{ChromaAuthMethod, ChromaClient as ChromaDbClient, ChromaClientOptions};
 use chromadb::collection::{ChromaCollection, CollectionEntries, GetOptions, QueryOptions};
@@ -17,6 +18,8 @@ pub struct ChromaConfig {
     pub url: Option<String>,
     pub database: String,
     pub auth: ChromaAuthMethod,
+    /// Metric collections are created with and searches expect
+    pub distance: DistanceMetric,
 }
 
 impl Default for ChromaConfig {
@@ -25,6 +28,7 @@ impl Default for ChromaConfig {
             url: None,
             database: "default_database".to_string(),
             auth: ChromaAuthMethod::None,
+            distance: DistanceMetric::default(),
         }
     }
 }
@@ -72,10 +76,54 @@ impl ChromaConfig {
             url,
             database,
             auth,
+            distance: DistanceMetric::default(),
         })
     }
 }
 
+/// Chroma's name for a metric, as its `hnsw:space` collection setting
+fn chroma_space(distance: DistanceMetric) -> &'static str {
+    match distance {
+        DistanceMetric::Cosine => "cosine",
+        DistanceMetric::Dot => "ip",
+        DistanceMetric::Euclidean => "l2",
+    }
+}
+
+/// Metadata new collections are created with
+fn collection_metadata(distance: DistanceMetric) -> Map<String, Value> {
+    Map::from_iter([(
+        "hnsw:space".to_string(),
+        Value::String(chroma_space(distance).to_string()),
+    )])
+}
+
+/// Refuse to use a collection indexed under a different metric than configured
+///
+/// Its distances would be turned into meaningless scores otherwise. Chroma defaults to
+/// `l2` when no space was set, which is what collections created before RAG_DISTANCE
+/// existed have.
+fn check_distance(
+    collection_name: &str,
+    metadata: Option<&Map<String, Value>>,
+    distance: DistanceMetric,
+) -> Result<(), VectorError> {
+    let space = metadata
+        .and_then(|meta| meta.get("hnsw:space"))
+        .and_then(Value::as_str)
+        .unwrap_or("l2");
+    if space == chroma_space(distance) {
+        return Ok(());
+    }
+    Err(VectorError::ConfigError(format!(
+        "Collection '{}' was indexed with the '{}' distance but RAG_DISTANCE is '{}'; \
+         reindex it with POST /api/v1/knowledge/{{id}}/reindex or set RAG_DISTANCE to match",
+        collection_name,
+        space,
+        distance.as_str()
+    )))
+}
+
 impl ChromaClient {
     /// Create a new ChromaClient with the given configuration
     pub async fn new(config: ChromaConfig) -> Result<Self, VectorError> {
@@ -107,7 +155,10 @@ impl ChromaClient {
         debug!("Getting or creating collection: {}", collection_name);
 
         self.client
-            .get_or_create_collection(collection_name, None)
+            .get_or_create_collection(
+                collection_name,
+                Some(collection_metadata(self.config.distance)),
+            )
             .await
             .map_err(|e| {
                 VectorError::DatabaseError(format!(
@@ -115,6 +166,10 @@ impl ChromaClient {
                     collection_name, e
                 ))
             })
+            .and_then(|collection| {
+                check_distance(collection_name, collection.metadata(), self.config.distance)?;
+                Ok(collection)
+            })
     }
 
     /// Get an existing collection
@@ -158,6 +213,10 @@ impl ChromaClient {
 
 #[async_trait]
 impl VectorDB for ChromaClient {
+    fn distance(&self) -> DistanceMetric {
+        self.config.distance
+    }
+
     async fn has_collection(&self, collection_name: &str) -> Result<bool, VectorError> {
         debug!("Checking if collection exists: {}", collection_name);
 
@@ -173,6 +232,39 @@ impl VectorDB for ChromaClient {
         }
     }
 
+    async fn count(&self, collection_name: &str) -> Result<usize, VectorError> {
+        debug!("Counting vectors in collection: {}", collection_name);
+
+        let collection = self.get_collection(collection_name).await?;
+        collection.count().await.map_err(|e| {
+            VectorError::DatabaseError(format!(
+                "Failed to count collection '{}': {}",
+                collection_name, e
+            ))
+        })
+    }
+
+    async fn heartbeat(&self) -> Result<(), VectorError> {
+        self.client
+            .heartbeat()
+            .await
+            .map(|_| ())
+            .map_err(|e| VectorError::ConnectionError(format!("ChromaDB unreachable: {}", e)))
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>, VectorError> {
+        self.client
+            .list_collections()
+            .await
+            .map(|collections| {
+                collections
+                    .iter()
+                    .map(|collection| collection.name().to_string())
+                    .collect()
+            })
+            .map_err(|e| VectorError::OperationError(format!("Failed to list collections: {}", e)))
+    }
+
     async fn delete_collection(&self, collection_name: &str) -> Result<(), VectorError> {
         info!("Deleting collection: {}", collection_name);
 
@@ -263,21 +355,24 @@ impl VectorDB for ChromaClient {
         collection_name: &str,
         vectors: Vec<Vec<f32>>,
         limit: usize,
+        filter: Option<&MetadataFilter>,
     ) -> Result<SearchResult, VectorError> {
         debug!(
-            "Searching collection '{}' with {} query vectors, limit: {}",
+            "Searching collection '{}' with {} query vectors, limit: {}, filter: {:?}",
             collection_name,
             vectors.len(),
-            limit
+            limit,
+            filter
         );
 
         let collection = self.get_collection(collection_name).await?;
+        check_distance(collection_name, collection.metadata(), self.config.distance)?;
 
         let query_options = QueryOptions {
             query_embeddings: Some(vectors),
             query_texts: None,
             n_results: Some(limit),
-            where_metadata: None,
+            where_metadata: filter.and_then(MetadataFilter::to_chroma),
             where_document: None,
             include: Some(vec!["metadatas", "documents", "distances"]),
         };
@@ -506,6 +601,31 @@ mod tests {
     // Note: These tests require a running Chroma instance
     // Run with: docker run -p 8000:8000 chromadb/chroma
 
+    #[test]
+    fn test_distance_propagated_to_collection_creation() {
+        for (distance, space) in [
+            (DistanceMetric::Cosine, "cosine"),
+            (DistanceMetric::Dot, "ip"),
+            (DistanceMetric::Euclidean, "l2"),
+        ] {
+            let metadata = collection_metadata(distance);
+            assert_eq!(metadata["hnsw:space"], space);
+            assert!(check_distance("kb", Some(&metadata), distance).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_query_against_other_metric_errors() {
+        let euclidean = collection_metadata(DistanceMetric::Euclidean);
+        let err = check_distance("kb", Some(&euclidean), DistanceMetric::Cosine).unwrap_err();
+        assert!(matches!(err, VectorError::ConfigError(_)));
+        assert!(err.to_string().contains("'l2'"), "{}", err);
+
+        // Collections created without a space use Chroma's l2 default
+        assert!(check_distance("kb", None, DistanceMetric::Euclidean).is_ok());
+        assert!(check_distance("kb", None, DistanceMetric::Dot).is_err());
+    }
+
     #[tokio::test]
     #[ignore] // Ignore by default since it requires external service
     async fn test_chroma_connection() {
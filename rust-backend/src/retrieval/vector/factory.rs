@@ -1,5 +1,5 @@
 use super::chroma::{ChromaClient, ChromaConfig};
-use super::types::{VectorDB, VectorError};
+use super::types::{DistanceMetric, VectorDB, VectorError};
 use std::sync::Arc;
 use tracing::info;
 
@@ -28,12 +28,20 @@ pub struct VectorDBFactory;
 
 impl VectorDBFactory {
     /// Create a vector database client based on the specified type
-    pub async fn create(db_type: VectorDBType) -> Result<Arc<dyn VectorDB>, VectorError> {
+    ///
+    /// `distance` is the metric new collections are created with (RAG_DISTANCE).
+    pub async fn create(
+        db_type: VectorDBType,
+        distance: DistanceMetric,
+    ) -> Result<Arc<dyn VectorDB>, VectorError> {
         info!("Creating vector database client: {:?}", db_type);
 
         match db_type {
             VectorDBType::Chroma => {
-                let config = ChromaConfig::from_env()?;
+                let config = ChromaConfig {
+                    distance,
+                    ..ChromaConfig::from_env()?
+                };
                 let client = ChromaClient::new(config).await?;
                 Ok(Arc::new(client))
             }
@@ -42,7 +50,7 @@ impl VectorDBFactory {
 
     /// Create a vector database client from environment variables
     /// Reads VECTOR_DB environment variable (defaults to "chroma")
-    pub async fn from_env() -> Result<Arc<dyn VectorDB>, VectorError> {
+    pub async fn from_env(distance: DistanceMetric) -> Result<Arc<dyn VectorDB>, VectorError> {
         let db_type_str = std::env::var("VECTOR_DB").unwrap_or_else(|_| "chroma".to_string());
         let db_type = VectorDBType::from_str(&db_type_str)?;
 
@@ -51,7 +59,7 @@ impl VectorDBFactory {
             db_type_str
         );
 
-        Self::create(db_type).await
+        Self::create(db_type, distance).await
     }
 
     /// Create a Chroma client with custom configuration
@@ -85,7 +93,7 @@ mod tests {
         std::env::set_var("CHROMA_HTTP_HOST", "localhost");
         std::env::set_var("CHROMA_HTTP_PORT", "8000");
 
-        let client = VectorDBFactory::from_env().await;
+        let client = VectorDBFactory::from_env(DistanceMetric::Cosine).await;
         assert!(client.is_ok());
     }
 }
@@ -0,0 +1,312 @@
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+
+/// Payload key marking a chunk's file as carrying `tag`
+///
+/// Backends like Chroma only store scalar metadata, so each tag gets its own boolean key
+/// instead of a list.
+pub fn tag_key(tag: &str) -> String {
+    format!("tag:{}", tag)
+}
+
+/// Comparison applied to a payload field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,
+    Nin,
+}
+
+impl FilterOp {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.trim_start_matches('$') {
+            "eq" => Self::Eq,
+            "ne" => Self::Ne,
+            "gt" => Self::Gt,
+            "gte" => Self::Gte,
+            "lt" => Self::Lt,
+            "lte" => Self::Lte,
+            "in" => Self::In,
+            "nin" => Self::Nin,
+            _ => return None,
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Gt => "gt",
+            Self::Gte => "gte",
+            Self::Lt => "lt",
+            Self::Lte => "lte",
+            Self::In => "in",
+            Self::Nin => "nin",
+        }
+    }
+}
+
+/// Conditions on vector payload fields, narrowing a similarity search
+///
+/// Parsed from the `filter` object of a query, where each key is a payload field and its
+/// value either a scalar to match exactly or an object of operators, e.g.
+/// `{"mime": "application/pdf", "created_at": {"gte": 1700000000}}`. The `tags` field
+/// takes a tag, a list of tags that must all be present, or `{"in": [...]}` for any of
+/// them. All conditions must hold.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataFilter {
+    Field {
+        field: String,
+        op: FilterOp,
+        value: Value,
+    },
+    And(Vec<MetadataFilter>),
+    Or(Vec<MetadataFilter>),
+}
+
+impl MetadataFilter {
+    /// Errors name the offending field, for returning to the client as-is
+    pub fn parse(filter: &Value) -> Result<Self, String> {
+        let Some(fields) = filter.as_object() else {
+            return Err("expected an object of payload fields".to_string());
+        };
+
+        let mut conditions = Vec::new();
+        for (field, condition) in fields {
+            if field == "tags" {
+                conditions.push(Self::parse_tags(condition)?);
+                continue;
+            }
+            match condition {
+                Value::Object(ops) => {
+                    for (name, value) in ops {
+                        let op = FilterOp::parse(name)
+                            .ok_or_else(|| format!("{}: unknown operator '{}'", field, name))?;
+                        check_operand(op, value)
+                            .map_err(|message| format!("{}: {}", field, message))?;
+                        conditions.push(Self::Field {
+                            field: field.clone(),
+                            op,
+                            value: value.clone(),
+                        });
+                    }
+                }
+                value => {
+                    check_operand(FilterOp::Eq, value)
+                        .map_err(|message| format!("{}: {}", field, message))?;
+                    conditions.push(Self::Field {
+                        field: field.clone(),
+                        op: FilterOp::Eq,
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(Self::And(conditions))
+    }
+
+    fn parse_tags(condition: &Value) -> Result<Self, String> {
+        let has_tag = |tag: &Value| {
+            tag.as_str()
+                .map(|tag| Self::Field {
+                    field: tag_key(tag),
+                    op: FilterOp::Eq,
+                    value: Value::Bool(true),
+                })
+                .ok_or_else(|| "tags: expected tag names as strings".to_string())
+        };
+
+        match condition {
+            Value::String(_) => has_tag(condition),
+            Value::Array(tags) => tags
+                .iter()
+                .map(has_tag)
+                .collect::<Result<_, _>>()
+                .map(Self::And),
+            Value::Object(ops) => match (ops.len(), ops.get("in").or_else(|| ops.get("$in"))) {
+                (1, Some(Value::Array(tags))) => tags
+                    .iter()
+                    .map(has_tag)
+                    .collect::<Result<_, _>>()
+                    .map(Self::Or),
+                _ => Err("tags: only 'in' with a list of tags is supported".to_string()),
+            },
+            _ => Err("tags: expected a tag, a list of tags or {\"in\": [...]}".to_string()),
+        }
+    }
+
+    /// No conditions at all
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Field { .. } => false,
+            Self::And(filters) | Self::Or(filters) => filters.iter().all(Self::is_empty),
+        }
+    }
+
+    /// Chroma `where` clause; `None` when there is nothing to filter on
+    pub fn to_chroma(&self) -> Option<Value> {
+        match self {
+            Self::Field { field, op, value } => {
+                Some(json!({ field: { format!("${}", op.as_str()): value } }))
+            }
+            Self::And(filters) | Self::Or(filters) => {
+                let mut clauses: Vec<Value> = filters.iter().filter_map(Self::to_chroma).collect();
+                match clauses.len() {
+                    0 => None,
+                    // Chroma rejects $and/$or with fewer than two clauses
+                    1 => clauses.pop(),
+                    _ if matches!(self, Self::And(_)) => Some(json!({ "$and": clauses })),
+                    _ => Some(json!({ "$or": clauses })),
+                }
+            }
+        }
+    }
+
+    /// Whether a payload satisfies the filter, with the same semantics as the backends
+    ///
+    /// Range comparisons only hold between numbers, and a missing field fails every
+    /// condition except `ne` and `nin`.
+    pub fn matches(&self, metadata: &Value) -> bool {
+        match self {
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Self::Or(filters) => {
+                filters.is_empty() || filters.iter().any(|filter| filter.matches(metadata))
+            }
+            Self::Field { field, op, value } => {
+                let actual = metadata.get(field);
+                let listed = || {
+                    value
+                        .as_array()
+                        .is_some_and(|values| actual.is_some_and(|actual| values.contains(actual)))
+                };
+                let ordering = || match (actual.and_then(Value::as_f64), value.as_f64()) {
+                    (Some(actual), Some(expected)) => actual.partial_cmp(&expected),
+                    _ => None,
+                };
+                match op {
+                    FilterOp::Eq => actual == Some(value),
+                    FilterOp::Ne => actual != Some(value),
+                    FilterOp::In => listed(),
+                    FilterOp::Nin => !listed(),
+                    FilterOp::Gt => ordering() == Some(Ordering::Greater),
+                    FilterOp::Gte => {
+                        matches!(ordering(), Some(Ordering::Greater | Ordering::Equal))
+                    }
+                    FilterOp::Lt => ordering() == Some(Ordering::Less),
+                    FilterOp::Lte => matches!(ordering(), Some(Ordering::Less | Ordering::Equal)),
+                }
+            }
+        }
+    }
+}
+
+/// Payload values are scalars; ranges need numbers and `in`/`nin` a list of scalars
+fn check_operand(op: FilterOp, value: &Value) -> Result<(), String> {
+    let scalar =
+        |value: &Value| matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_));
+    let valid = match op {
+        FilterOp::Eq | FilterOp::Ne => scalar(value),
+        FilterOp::Gt | FilterOp::Gte | FilterOp::Lt | FilterOp::Lte => value.is_number(),
+        FilterOp::In | FilterOp::Nin => value
+            .as_array()
+            .is_some_and(|values| values.iter().all(scalar)),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid value for '{}'", op.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payloads() -> Vec<Value> {
+        vec![
+            json!({"file_id": "a", "tag:policy": true, "tag:hr": true, "created_at": 1_700_000_000}),
+            json!({"file_id": "b", "tag:policy": true, "created_at": 1_710_000_000}),
+            json!({"file_id": "c", "tag:finance": true, "created_at": 1_720_000_000}),
+        ]
+    }
+
+    fn search(filter: Value) -> Vec<String> {
+        let filter = MetadataFilter::parse(&filter).unwrap();
+        payloads()
+            .iter()
+            .filter(|payload| filter.matches(payload))
+            .map(|payload| payload["file_id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_tag_filtered_search() {
+        assert_eq!(search(json!({"tags": "policy"})), vec!["a", "b"]);
+        assert_eq!(search(json!({"tags": ["policy", "hr"]})), vec!["a"]);
+        assert_eq!(
+            search(json!({"tags": {"in": ["hr", "finance"]}})),
+            vec!["a", "c"]
+        );
+
+        let filter = MetadataFilter::parse(&json!({"tags": "policy"})).unwrap();
+        assert_eq!(
+            filter.to_chroma(),
+            Some(json!({"tag:policy": {"$eq": true}}))
+        );
+        let filter = MetadataFilter::parse(&json!({"tags": {"in": ["hr", "finance"]}})).unwrap();
+        assert_eq!(
+            filter.to_chroma(),
+            Some(json!({"$or": [
+                {"tag:hr": {"$eq": true}},
+                {"tag:finance": {"$eq": true}}
+            ]}))
+        );
+    }
+
+    #[test]
+    fn test_date_range_filtered_search() {
+        let range = json!({"created_at": {"gte": 1_705_000_000, "lt": 1_720_000_000}});
+        assert_eq!(search(range.clone()), vec!["b"]);
+        assert_eq!(
+            search(json!({"tags": "policy", "created_at": {"gt": 1_700_000_000}})),
+            vec!["b"]
+        );
+
+        assert_eq!(
+            MetadataFilter::parse(&range).unwrap().to_chroma(),
+            Some(json!({"$and": [
+                {"created_at": {"$gte": 1_705_000_000}},
+                {"created_at": {"$lt": 1_720_000_000}}
+            ]}))
+        );
+    }
+
+    #[test]
+    fn test_empty_filter_matches_everything() {
+        let filter = MetadataFilter::parse(&json!({})).unwrap();
+        assert!(filter.is_empty());
+        assert_eq!(filter.to_chroma(), None);
+        assert_eq!(search(json!({})), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_invalid_filters_rejected() {
+        for filter in [
+            json!([]),
+            json!({"created_at": {"after": 1}}),
+            json!({"created_at": {"gte": "yesterday"}}),
+            json!({"mime": {"in": "text/plain"}}),
+            json!({"mime": ["text/plain"]}),
+            json!({"tags": 3}),
+            json!({"tags": {"nin": ["hr"]}}),
+        ] {
+            assert!(MetadataFilter::parse(&filter).is_err(), "{}", filter);
+        }
+    }
+}
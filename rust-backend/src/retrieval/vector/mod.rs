@@ -1,7 +1,13 @@
 pub mod chroma;
 pub mod factory;
+pub mod filter;
+pub mod prefixed;
 pub mod types;
 
 pub use chroma::ChromaClient;
 pub use factory::{VectorDBFactory, VectorDBType};
-pub use types::{GetResult, SearchResult, VectorDB, VectorError, VectorItem};
+pub use filter::MetadataFilter;
+pub use prefixed::PrefixedVectorDB;
+pub use types::{DistanceMetric, VectorDB, VectorError};
+#[cfg(test)]
+pub use types::{GetResult, SearchResult, VectorItem};
@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::filter::MetadataFilter;
+use super::types::{DistanceMetric, GetResult, SearchResult, VectorDB, VectorError, VectorItem};
+
+/// Namespaces every collection under VECTOR_COLLECTION_PREFIX
+///
+/// Lets several deployments share one vector database: knowledge (`{id}`) and file
+/// (`file-{id}`) collections of each get a distinct name, while callers keep using the
+/// unprefixed names.
+pub struct PrefixedVectorDB {
+    inner: Arc<dyn VectorDB>,
+    prefix: String,
+}
+
+impl PrefixedVectorDB {
+    /// `inner` with its collections under `prefix`; without a prefix `inner` is returned
+    pub fn wrap(inner: Arc<dyn VectorDB>, prefix: &str) -> Arc<dyn VectorDB> {
+        if prefix.is_empty() {
+            return inner;
+        }
+        Arc::new(PrefixedVectorDB {
+            inner,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    fn name(&self, collection_name: &str) -> String {
+        format!("{}{}", self.prefix, collection_name)
+    }
+}
+
+#[async_trait]
+impl VectorDB for PrefixedVectorDB {
+    async fn has_collection(&self, collection_name: &str) -> Result<bool, VectorError> {
+        self.inner.has_collection(&self.name(collection_name)).await
+    }
+
+    async fn count(&self, collection_name: &str) -> Result<usize, VectorError> {
+        self.inner.count(&self.name(collection_name)).await
+    }
+
+    fn distance(&self) -> DistanceMetric {
+        self.inner.distance()
+    }
+
+    async fn heartbeat(&self) -> Result<(), VectorError> {
+        self.inner.heartbeat().await
+    }
+
+    /// Only this deployment's collections, by their unprefixed names
+    async fn list_collections(&self) -> Result<Vec<String>, VectorError> {
+        Ok(self
+            .inner
+            .list_collections()
+            .await?
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(&self.prefix).map(str::to_string))
+            .collect())
+    }
+
+    async fn delete_collection(&self, collection_name: &str) -> Result<(), VectorError> {
+        self.inner
+            .delete_collection(&self.name(collection_name))
+            .await
+    }
+
+    async fn insert(
+        &self,
+        collection_name: &str,
+        items: Vec<VectorItem>,
+    ) -> Result<(), VectorError> {
+        self.inner.insert(&self.name(collection_name), items).await
+    }
+
+    async fn upsert(
+        &self,
+        collection_name: &str,
+        items: Vec<VectorItem>,
+    ) -> Result<(), VectorError> {
+        self.inner.upsert(&self.name(collection_name), items).await
+    }
+
+    async fn search(
+        &self,
+        collection_name: &str,
+        vectors: Vec<Vec<f32>>,
+        limit: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<SearchResult, VectorError> {
+        self.inner
+            .search(&self.name(collection_name), vectors, limit, filter)
+            .await
+    }
+
+    async fn query(
+        &self,
+        collection_name: &str,
+        filter: serde_json::Value,
+        limit: Option<usize>,
+    ) -> Result<GetResult, VectorError> {
+        self.inner
+            .query(&self.name(collection_name), filter, limit)
+            .await
+    }
+
+    async fn get(&self, collection_name: &str) -> Result<GetResult, VectorError> {
+        self.inner.get(&self.name(collection_name)).await
+    }
+
+    async fn delete(
+        &self,
+        collection_name: &str,
+        ids: Option<Vec<String>>,
+        filter: Option<serde_json::Value>,
+    ) -> Result<(), VectorError> {
+        self.inner
+            .delete(&self.name(collection_name), ids, filter)
+            .await
+    }
+
+    /// Refused: the database would be reset for every prefix sharing it
+    async fn reset(&self) -> Result<(), VectorError> {
+        Err(VectorError::OperationError(format!(
+            "Refusing to reset a vector database shared under VECTOR_COLLECTION_PREFIX '{}'; \
+             delete this deployment's collections instead",
+            self.prefix
+        )))
+    }
+
+    async fn get_collection_metadata(
+        &self,
+        collection_name: &str,
+    ) -> Result<HashMap<String, serde_json::Value>, VectorError> {
+        self.inner
+            .get_collection_metadata(&self.name(collection_name))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Records the collection each call targets
+    #[derive(Default)]
+    struct RecordingVectorDb {
+        names: Mutex<Vec<String>>,
+    }
+
+    impl RecordingVectorDb {
+        fn saw(&self, name: &str) {
+            self.names.lock().unwrap().push(name.to_string());
+        }
+    }
+
+    fn empty() -> GetResult {
+        GetResult {
+            ids: None,
+            documents: None,
+            metadatas: None,
+        }
+    }
+
+    #[async_trait]
+    impl VectorDB for RecordingVectorDb {
+        async fn has_collection(&self, name: &str) -> Result<bool, VectorError> {
+            self.saw(name);
+            Ok(true)
+        }
+
+        async fn count(&self, name: &str) -> Result<usize, VectorError> {
+            self.saw(name);
+            Ok(0)
+        }
+
+        async fn delete_collection(&self, name: &str) -> Result<(), VectorError> {
+            self.saw(name);
+            Ok(())
+        }
+
+        async fn insert(&self, name: &str, _items: Vec<VectorItem>) -> Result<(), VectorError> {
+            self.saw(name);
+            Ok(())
+        }
+
+        async fn upsert(&self, name: &str, _items: Vec<VectorItem>) -> Result<(), VectorError> {
+            self.saw(name);
+            Ok(())
+        }
+
+        async fn search(
+            &self,
+            name: &str,
+            _vectors: Vec<Vec<f32>>,
+            _limit: usize,
+            _filter: Option<&MetadataFilter>,
+        ) -> Result<SearchResult, VectorError> {
+            self.saw(name);
+            Ok(SearchResult {
+                ids: None,
+                documents: None,
+                metadatas: None,
+                distances: None,
+            })
+        }
+
+        async fn query(
+            &self,
+            name: &str,
+            _filter: serde_json::Value,
+            _limit: Option<usize>,
+        ) -> Result<GetResult, VectorError> {
+            self.saw(name);
+            Ok(empty())
+        }
+
+        async fn get(&self, name: &str) -> Result<GetResult, VectorError> {
+            self.saw(name);
+            Ok(empty())
+        }
+
+        async fn delete(
+            &self,
+            name: &str,
+            _ids: Option<Vec<String>>,
+            _filter: Option<serde_json::Value>,
+        ) -> Result<(), VectorError> {
+            self.saw(name);
+            Ok(())
+        }
+
+        async fn reset(&self) -> Result<(), VectorError> {
+            self.saw("*");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_operations_target_prefixed_collection() {
+        let inner = Arc::new(RecordingVectorDb::default());
+        let db = PrefixedVectorDB::wrap(inner.clone(), "tenant-a_");
+
+        db.upsert("file-1", vec![]).await.unwrap();
+        db.insert("kb-1", vec![]).await.unwrap();
+        db.has_collection("kb-1").await.unwrap();
+        db.count("kb-1").await.unwrap();
+        db.search("kb-1", vec![vec![0.1]], 5, None).await.unwrap();
+        db.query("kb-1", serde_json::json!({}), None).await.unwrap();
+        db.get("kb-1").await.unwrap();
+        db.delete("kb-1", Some(vec!["chunk".to_string()]), None)
+            .await
+            .unwrap();
+        db.delete_collection("file-1").await.unwrap();
+        db.get_collection_metadata("kb-1").await.unwrap();
+        assert!(db.reset().await.is_err());
+
+        let names = inner.names.lock().unwrap().clone();
+        assert_eq!(names.len(), 9);
+        assert_eq!(names[0], "tenant-a_file-1");
+        assert!(names.iter().all(|name| name.starts_with("tenant-a_")));
+    }
+
+    #[tokio::test]
+    async fn test_empty_prefix_leaves_names_alone() {
+        let inner = Arc::new(RecordingVectorDb::default());
+        let db = PrefixedVectorDB::wrap(inner.clone(), "");
+        db.upsert("file-1", vec![]).await.unwrap();
+        db.reset().await.unwrap();
+        assert_eq!(*inner.names.lock().unwrap(), vec!["file-1", "*"]);
+    }
+}
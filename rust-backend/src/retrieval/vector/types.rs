@@ -2,6 +2,8 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::filter::MetadataFilter;
+
 /// Represents a single vector item to be stored in the database
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorItem {
@@ -38,6 +40,49 @@ impl From<SearchResult> for GetResult {
     }
 }
 
+/// How vector similarity is measured in a collection, set with RAG_DISTANCE
+///
+/// Fixed when a collection is created; vectors indexed under one metric can't be
+/// searched under another.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    #[default]
+    Cosine,
+    Dot,
+    Euclidean,
+}
+
+impl DistanceMetric {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cosine" => Some(Self::Cosine),
+            "dot" => Some(Self::Dot),
+            "euclidean" => Some(Self::Euclidean),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Cosine => "cosine",
+            Self::Dot => "dot",
+            Self::Euclidean => "euclidean",
+        }
+    }
+
+    /// Similarity score (0..1, higher is more relevant) for a distance under this metric
+    ///
+    /// Cosine and dot distances are `1 - similarity`; Euclidean is squared L2, which over
+    /// normalized embeddings equals `2 - 2 * cosine`.
+    pub fn score(self, distance: f32) -> f32 {
+        match self {
+            Self::Cosine | Self::Dot => 1.0 - distance,
+            Self::Euclidean => 1.0 - distance / 2.0,
+        }
+        .clamp(0.0, 1.0)
+    }
+}
+
 /// Error types for vector database operations
 #[derive(Debug, thiserror::Error)]
 pub enum VectorError {
@@ -67,6 +112,27 @@ pub trait VectorDB: Send + Sync {
     /// Check if a collection exists in the vector database
     async fn has_collection(&self, collection_name: &str) -> Result<bool, VectorError>;
 
+    /// Count the vectors stored in a collection
+    async fn count(&self, collection_name: &str) -> Result<usize, VectorError>;
+
+    /// Metric new collections are created with and searches expect
+    fn distance(&self) -> DistanceMetric {
+        DistanceMetric::default()
+    }
+
+    /// Check that the vector database is reachable
+    async fn heartbeat(&self) -> Result<(), VectorError> {
+        // Default implementation - can be overridden
+        Ok(())
+    }
+
+    /// Names of every collection in the vector database
+    async fn list_collections(&self) -> Result<Vec<String>, VectorError> {
+        Err(VectorError::OperationError(
+            "Listing collections is not supported by this vector database".to_string(),
+        ))
+    }
+
     /// Delete a collection from the vector database
     async fn delete_collection(&self, collection_name: &str) -> Result<(), VectorError>;
 
@@ -84,12 +150,14 @@ pub trait VectorDB: Send + Sync {
         items: Vec<VectorItem>,
     ) -> Result<(), VectorError>;
 
-    /// Search for similar vectors in a collection
+    /// Search for similar vectors in a collection, among those whose payload matches
+    /// `filter` when given
     async fn search(
         &self,
         collection_name: &str,
         vectors: Vec<Vec<f32>>,
         limit: usize,
+        filter: Option<&MetadataFilter>,
     ) -> Result<SearchResult, VectorError>;
 
     /// Query vectors from a collection using metadata filter
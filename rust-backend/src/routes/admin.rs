@@ -0,0 +1,866 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::{
+    config::RESTART_REQUIRED_SETTINGS,
+    error::{AppError, AppResult},
+    middleware::{maintenance::maintenance_retry_after, AdminMiddleware, AuthUser},
+    models::{group::GroupResponse, usage::UsageQuery},
+    retrieval::VectorDB,
+    routes::users,
+    services::{
+        access_report::AccessReportService, group::GroupService, knowledge::KnowledgeService,
+        ownership_transfer::OwnershipTransfer, retention::RetentionService, usage::UsageService,
+        vector_prune::VectorPruneService, ConfigService, UserService,
+    },
+    utils::{
+        dry_run::DryRunQuery,
+        feature_flags,
+        permissions::{default_permissions, permission_errors},
+    },
+    AppState,
+};
+
+pub fn create_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("")
+            .wrap(AdminMiddleware)
+            .route("/usage", web::get().to(get_usage))
+            .route("/config/reload", web::post().to(reload_config))
+            .route("/config/import", web::post().to(import_config))
+            .route("/config/import/schema", web::get().to(get_import_schema))
+            .route("/rag/status", web::get().to(get_rag_status))
+            .route(
+                "/rag/prune-orphans",
+                web::post().to(prune_orphan_collections),
+            )
+            .route("/ws/stats", web::get().to(get_ws_stats))
+            .route("/maintenance", web::get().to(get_maintenance))
+            .route("/maintenance", web::post().to(set_maintenance))
+            .route("/webhook/test", web::post().to(test_webhook))
+            .route("/retention/preview", web::get().to(preview_retention))
+            .route("/retention/purge", web::post().to(purge_retention))
+            .route("/onboarding/complete", web::post().to(complete_onboarding))
+            .route("/users/{id}/impersonate", web::post().to(impersonate_user))
+            .route("/users/{id}/access", web::get().to(get_user_access))
+            .route("/users/{id}/export", web::get().to(export_user_data))
+            .route(
+                "/users/{id}/transfer",
+                web::post().to(transfer_user_resources),
+            )
+            .route(
+                "/transfer/{resource_type}/{id}",
+                web::post().to(transfer_resource),
+            )
+            .route(
+                "/users/{id}/feature-flags",
+                web::post().to(set_user_feature_flags),
+            )
+            .route("/feature-flags/sign", web::post().to(sign_feature_flags))
+            .route(
+                "/permission-templates",
+                web::get().to(get_permission_templates),
+            )
+            .route(
+                "/groups/{id}/apply-template/{template}",
+                web::post().to(apply_permission_template),
+            )
+            .route(
+                "/groups/apply-template/{template}",
+                web::post().to(bulk_apply_permission_template),
+            )
+            .route(
+                "/oauth/providers/{name}/enable",
+                web::post().to(enable_oauth_provider),
+            )
+            .route(
+                "/oauth/providers/{name}/disable",
+                web::post().to(disable_oauth_provider),
+            )
+            .route(
+                "/security/rotate-encryption-key",
+                web::post().to(rotate_encryption_key),
+            ),
+    );
+}
+
+// GET /usage - Aggregated token usage across all users
+async fn get_usage(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<UsageQuery>,
+) -> AppResult<HttpResponse> {
+    let usage_service = UsageService::new(&state.db);
+    let summary = usage_service.get_usage_summary(&query).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[derive(Deserialize)]
+struct RotateKeyQuery {
+    batch_size: Option<i64>,
+}
+
+// POST /security/rotate-encryption-key - Re-encrypt OAuth sessions under the primary key
+async fn rotate_encryption_key(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<RotateKeyQuery>,
+) -> AppResult<HttpResponse> {
+    let batch_size = query.batch_size.unwrap_or(500);
+    if batch_size <= 0 {
+        return Err(AppError::BadRequest(
+            "batch_size must be positive".to_string(),
+        ));
+    }
+
+    let report = state
+        .oauth_session_service
+        .rotate_encryption_key(batch_size)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// POST /config/reload - Re-read env and DB config and swap it in without a restart
+async fn reload_config(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let current = state.config.read().unwrap().clone();
+    let reloaded = ConfigService::reload(&state.db, &current).await?;
+
+    let changed = ConfigService::changed_settings(&current, &reloaded);
+    let requires_restart: Vec<&String> = changed
+        .iter()
+        .filter(|key| RESTART_REQUIRED_SETTINGS.contains(&key.as_str()))
+        .collect();
+
+    state
+        .oauth_manager
+        .set_disabled_providers(&reloaded.oauth_disabled_providers);
+    *state.config.write().unwrap() = reloaded;
+
+    // Cached model lists may point at changed connections
+    state.models_cache.write().unwrap().clear();
+
+    tracing::info!(
+        "Configuration reloaded ({} settings changed, {} require restart)",
+        changed.len(),
+        requires_restart.len()
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "changed": changed,
+        "requires_restart": requires_restart,
+    })))
+}
+
+// GET /config/import/schema - JSON Schema of the settings /config/import applies
+async fn get_import_schema(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let config = state.config.read().unwrap();
+    Ok(HttpResponse::Ok().json(ConfigService::import_schema(&config)))
+}
+
+// POST /config/import - Apply the runtime settings from an exported config snapshot
+async fn import_config(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    snapshot: web::Json<serde_json::Value>,
+) -> AppResult<HttpResponse> {
+    let current = state.config.read().unwrap().clone();
+    let (imported, report) = ConfigService::import(&current, &snapshot)?;
+
+    ConfigService::persist_settings(&state.db, &imported, &report.applied).await?;
+    state
+        .oauth_manager
+        .set_disabled_providers(&imported.oauth_disabled_providers);
+    *state.config.write().unwrap() = imported;
+
+    // Cached model lists may point at changed connections
+    state.models_cache.write().unwrap().clear();
+
+    tracing::info!(
+        "Config imported by {}: {} settings applied, {} skipped",
+        auth_user.user.email,
+        report.applied.len(),
+        report.skipped.len()
+    );
+
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// POST /onboarding/complete - Record that first-run setup is done
+async fn complete_onboarding(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let section = {
+        let mut config = state.config.write().unwrap();
+        config.onboarding_completed = true;
+        ConfigService::onboarding_section(&config)
+    };
+    ConfigService::update_section(&state.db, "onboarding", section.clone()).await?;
+
+    tracing::info!("Onboarding completed by {}", auth_user.user.email);
+    Ok(HttpResponse::Ok().json(section))
+}
+
+#[derive(Deserialize)]
+struct RetentionPreviewQuery {
+    chat_days: Option<u32>,
+    audit_days: Option<u32>,
+}
+
+// GET /retention/preview - Rows a retention purge would delete, for the configured
+// limits or the ones given in the query
+async fn preview_retention(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<RetentionPreviewQuery>,
+) -> AppResult<HttpResponse> {
+    let (chat_days, audit_days) = {
+        let config = state.config.read().unwrap();
+        (
+            query.chat_days.unwrap_or(config.chat_retention_days),
+            query.audit_days.unwrap_or(config.audit_retention_days),
+        )
+    };
+    let counts = RetentionService::new(&state.db)
+        .preview(chat_days, audit_days)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "chat_retention_days": chat_days,
+        "audit_retention_days": audit_days,
+        "would_delete": counts,
+    })))
+}
+
+// POST /retention/purge - Delete rows past the retention limits now
+//
+// Only reports what would be deleted unless called with `?dry_run=false`.
+async fn purge_retention(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<RetentionPreviewQuery>,
+    dry_run: web::Query<DryRunQuery>,
+) -> AppResult<HttpResponse> {
+    let (chat_days, audit_days) = {
+        let config = state.config.read().unwrap();
+        (
+            query.chat_days.unwrap_or(config.chat_retention_days),
+            query.audit_days.unwrap_or(config.audit_retention_days),
+        )
+    };
+    let service = RetentionService::new(&state.db);
+
+    if dry_run.is_dry_run(true) {
+        let counts = service.preview(chat_days, audit_days).await?;
+        return Ok(HttpResponse::Ok().json(json!({
+            "dry_run": true,
+            "chat_retention_days": chat_days,
+            "audit_retention_days": audit_days,
+            "would_delete": counts,
+        })));
+    }
+
+    let counts = service.purge(chat_days, audit_days).await?;
+    tracing::warn!(
+        "Retention purge by {} removed {} chats, {} messages, {} audit entries",
+        auth_user.user.email,
+        counts.chats,
+        counts.messages,
+        counts.audit_logs
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "dry_run": false,
+        "chat_retention_days": chat_days,
+        "audit_retention_days": audit_days,
+        "deleted": counts,
+    })))
+}
+
+#[derive(Deserialize)]
+struct ImpersonateForm {
+    /// The admin's own password, re-entered to confirm
+    password: String,
+}
+
+// POST /users/{id}/impersonate - Short-lived read-only token acting as another user
+async fn impersonate_user(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    user_id: web::Path<String>,
+    form: web::Json<ImpersonateForm>,
+) -> AppResult<HttpResponse> {
+    let secret = state.config.read().unwrap().webui_secret_key.clone();
+    let grant = state
+        .impersonation
+        .start(
+            &state.db,
+            &secret,
+            &auth_user.user,
+            &user_id,
+            &form.password,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(grant))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WebhookTestForm {
+    /// Receiver to test instead of WEBHOOK_URL
+    url: Option<String>,
+}
+
+// POST /webhook/test - Send a sample event to WEBHOOK_URL (or a given URL) and report delivery
+async fn test_webhook(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    form: Option<web::Json<WebhookTestForm>>,
+) -> AppResult<HttpResponse> {
+    let form = form.map(web::Json::into_inner).unwrap_or_default();
+    let config = state.config.read().unwrap().clone();
+    let report = state
+        .webhook_tester
+        .run(&config, &auth_user.user.id, form.url.as_deref())
+        .await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// GET /users/{id}/export - Download everything stored about a user
+async fn export_user_data(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    user_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let (user, stream) = state
+        .data_export
+        .start(&state.db, &auth_user.user, &user_id)
+        .await?;
+    Ok(users::export_download(&user, stream))
+}
+
+#[derive(Deserialize)]
+struct TransferQuery {
+    to: String,
+}
+
+// POST /users/{id}/transfer?to={target_id} - Hand everything a user owns to another user
+async fn transfer_user_resources(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    user_id: web::Path<String>,
+    query: web::Query<TransferQuery>,
+) -> AppResult<HttpResponse> {
+    let transfer = OwnershipTransfer::new(&state.db)
+        .transfer_user(&auth_user.user, &user_id, &query.to)
+        .await?;
+    Ok(HttpResponse::Ok().json(transfer))
+}
+
+// POST /transfer/{resource_type}/{id}?to={target_id} - Hand one resource to another user
+async fn transfer_resource(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    path: web::Path<(String, String)>,
+    query: web::Query<TransferQuery>,
+) -> AppResult<HttpResponse> {
+    let (resource_type, id) = path.into_inner();
+    let transfer = OwnershipTransfer::new(&state.db)
+        .transfer_resource(&auth_user.user, &resource_type, &id, &query.to)
+        .await?;
+    Ok(HttpResponse::Ok().json(transfer))
+}
+
+/// Longest an X-Feature-Flags token may live, in seconds
+const MAX_FEATURE_FLAG_TTL: i64 = 7 * 24 * 3600;
+
+#[derive(Deserialize)]
+struct FeatureFlagsForm {
+    flags: BTreeMap<String, bool>,
+}
+
+// POST /users/{id}/feature-flags - Replace one user's feature flag overrides
+async fn set_user_feature_flags(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    user_id: web::Path<String>,
+    form: web::Json<FeatureFlagsForm>,
+) -> AppResult<HttpResponse> {
+    feature_flags::check_overridable(&state.config.read().unwrap(), &form.flags)?;
+
+    let user_service = UserService::new(&state.db);
+    let user = user_service
+        .get_user_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let mut settings = user.settings.unwrap_or_else(|| json!({}));
+    if !settings.is_object() {
+        settings = json!({});
+    }
+    settings[feature_flags::SETTINGS_KEY] = json!(form.flags);
+    user_service
+        .update_user_settings(&user.id, &settings, None)
+        .await?;
+
+    tracing::info!(
+        "Feature flags for user {} set by {}: {:?}",
+        user.id,
+        auth_user.user.email,
+        form.flags
+    );
+
+    Ok(HttpResponse::Ok().json(json!({ "user_id": user.id, "flags": form.flags })))
+}
+
+#[derive(Deserialize)]
+struct SignFeatureFlagsForm {
+    flags: BTreeMap<String, bool>,
+    /// Restrict the token to this user's requests
+    user_id: Option<String>,
+    /// Token lifetime in seconds
+    #[serde(default = "default_feature_flag_ttl")]
+    ttl: i64,
+}
+
+fn default_feature_flag_ttl() -> i64 {
+    3600
+}
+
+// POST /feature-flags/sign - Token overriding feature flags for requests that send it
+async fn sign_feature_flags(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    form: web::Json<SignFeatureFlagsForm>,
+) -> AppResult<HttpResponse> {
+    if !(1..=MAX_FEATURE_FLAG_TTL).contains(&form.ttl) {
+        return Err(AppError::BadRequest(format!(
+            "ttl must be between 1 and {} seconds",
+            MAX_FEATURE_FLAG_TTL
+        )));
+    }
+
+    let (token, expires_at) = {
+        let config = state.config.read().unwrap();
+        feature_flags::check_overridable(&config, &form.flags)?;
+        feature_flags::sign(
+            &form.flags,
+            form.user_id.as_deref(),
+            &config.webui_secret_key,
+            form.ttl,
+        )?
+    };
+
+    tracing::info!(
+        "Feature flag token signed by {} for {}: {:?}",
+        auth_user.user.email,
+        form.user_id.as_deref().unwrap_or("any user"),
+        form.flags
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "token": token,
+        "header": feature_flags::FEATURE_FLAGS_HEADER,
+        "expires_at": expires_at,
+    })))
+}
+
+// GET /users/{id}/access - Everything the user can read or write, and why
+async fn get_user_access(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    user_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let user = UserService::new(&state.db)
+        .get_user_by_id(&user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let report = AccessReportService::new(&state.db).for_user(&user).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// GET /permission-templates - Configured permission templates and the permission schema
+async fn get_permission_templates(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let config = state.config.read().unwrap();
+    Ok(HttpResponse::Ok().json(json!({
+        "templates": config.permission_templates,
+        "schema": default_permissions(),
+    })))
+}
+
+/// A configured template, rechecked since stored templates may predate schema changes
+fn permission_template(state: &AppState, name: &str) -> AppResult<serde_json::Value> {
+    let template = state
+        .config
+        .read()
+        .unwrap()
+        .permission_templates
+        .get(name)
+        .cloned()
+        .ok_or_else(|| AppError::NotFound(format!("Permission template '{}' not found", name)))?;
+
+    let errors = permission_errors(&template);
+    if !errors.is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "Permission template '{}' is invalid: {}",
+            name,
+            errors.join("; ")
+        )));
+    }
+    Ok(template)
+}
+
+// POST /groups/{id}/apply-template/{template} - Set a template's permissions on a group
+async fn apply_permission_template(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    path: web::Path<(String, String)>,
+) -> AppResult<HttpResponse> {
+    let (group_id, name) = path.into_inner();
+    let template = permission_template(&state, &name)?;
+
+    let group = GroupService::new(&state.db)
+        .apply_permission_template(&group_id, &template)
+        .await?;
+
+    tracing::info!(
+        "Permission template '{}' applied to group {} by {}",
+        name,
+        group_id,
+        auth_user.user.email
+    );
+    Ok(HttpResponse::Ok().json(GroupResponse::from(group)))
+}
+
+#[derive(Deserialize)]
+struct BulkApplyTemplateForm {
+    group_ids: Vec<String>,
+}
+
+// POST /groups/apply-template/{template} - Set a template's permissions on several groups
+async fn bulk_apply_permission_template(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    name: web::Path<String>,
+    form: web::Json<BulkApplyTemplateForm>,
+) -> AppResult<HttpResponse> {
+    let template = permission_template(&state, &name)?;
+    let service = GroupService::new(&state.db);
+
+    // Check every group first so a typo doesn't leave the batch half-applied
+    for group_id in &form.group_ids {
+        if service.get_group_by_id(group_id).await?.is_none() {
+            return Err(AppError::NotFound(format!("Group {} not found", group_id)));
+        }
+    }
+
+    let mut groups = Vec::with_capacity(form.group_ids.len());
+    for group_id in &form.group_ids {
+        let group = service
+            .apply_permission_template(group_id, &template)
+            .await?;
+        groups.push(GroupResponse::from(group));
+    }
+
+    tracing::info!(
+        "Permission template '{}' applied to {} group(s) by {}",
+        name,
+        groups.len(),
+        auth_user.user.email
+    );
+    Ok(HttpResponse::Ok().json(groups))
+}
+
+// POST /oauth/providers/{name}/enable - Allow logins through a configured provider again
+async fn enable_oauth_provider(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    name: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    set_oauth_provider_enabled(&state, &auth_user, &name, true).await
+}
+
+// POST /oauth/providers/{name}/disable - Hide a configured provider and refuse its logins
+async fn disable_oauth_provider(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    name: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    set_oauth_provider_enabled(&state, &auth_user, &name, false).await
+}
+
+async fn set_oauth_provider_enabled(
+    state: &AppState,
+    auth_user: &AuthUser,
+    name: &str,
+    enabled: bool,
+) -> AppResult<HttpResponse> {
+    let disabled = state
+        .oauth_manager
+        .set_provider_enabled(name, enabled)
+        .await?;
+
+    let config = {
+        let mut config = state.config.write().unwrap();
+        config.oauth_disabled_providers = disabled.clone();
+        config.clone()
+    };
+
+    // Persist so the choice survives a restart
+    ConfigService::persist_settings(
+        &state.db,
+        &config,
+        &["oauth_disabled_providers".to_string()],
+    )
+    .await?;
+
+    tracing::warn!(
+        "OAuth provider {} {} by {}",
+        name,
+        if enabled { "enabled" } else { "disabled" },
+        auth_user.user.email
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "provider": name,
+        "enabled": enabled,
+        "disabled_providers": disabled,
+    })))
+}
+
+#[derive(Deserialize)]
+struct MaintenanceForm {
+    enabled: bool,
+    /// Shown to blocked clients; keeps the current message when omitted
+    message: Option<String>,
+    /// Window length in seconds; maintenance ends on its own afterwards
+    duration: Option<u64>,
+}
+
+fn maintenance_status(config: &crate::config::Config) -> serde_json::Value {
+    let now = chrono::Utc::now().timestamp();
+    json!({
+        "enabled": maintenance_retry_after(config, now).is_some(),
+        "message": config.maintenance_message,
+        "until": config.maintenance_until,
+    })
+}
+
+// GET /maintenance - Current maintenance mode state
+async fn get_maintenance(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let config = state.config.read().unwrap();
+    Ok(HttpResponse::Ok().json(maintenance_status(&config)))
+}
+
+// POST /maintenance - Toggle read-only mode for non-admin users
+async fn set_maintenance(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    form: web::Json<MaintenanceForm>,
+) -> AppResult<HttpResponse> {
+    let form = form.into_inner();
+    let until = match form.duration {
+        Some(0) => {
+            return Err(AppError::BadRequest(
+                "duration must be positive".to_string(),
+            ))
+        }
+        Some(duration) if form.enabled => Some(
+            chrono::Utc::now()
+                .timestamp()
+                .saturating_add(i64::try_from(duration).unwrap_or(i64::MAX)),
+        ),
+        _ => None,
+    };
+
+    let section = {
+        let mut config = state.config.write().unwrap();
+        config.maintenance_mode = form.enabled;
+        config.maintenance_until = until;
+        if let Some(message) = form.message.filter(|message| !message.trim().is_empty()) {
+            config.maintenance_message = message;
+        }
+        ConfigService::maintenance_section(&config)
+    };
+
+    // Persist so an active window survives a restart
+    ConfigService::update_section(&state.db, "maintenance", section).await?;
+
+    tracing::warn!(
+        "Maintenance mode {} by {}",
+        if form.enabled { "enabled" } else { "disabled" },
+        auth_user.user.email
+    );
+
+    let config = state.config.read().unwrap();
+    Ok(HttpResponse::Ok().json(maintenance_status(&config)))
+}
+
+// GET /ws/stats - Open WebSocket connections, overall and per user
+async fn get_ws_stats(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let Some(socket_state) = &state.socket_state else {
+        return Ok(HttpResponse::Ok().json(json!({ "enabled": false })));
+    };
+
+    let counts = socket_state.connections.counts();
+    Ok(HttpResponse::Ok().json(json!({
+        "enabled": true,
+        "connections": counts,
+    })))
+}
+
+// GET /rag/status - Vector DB reachability and per-knowledge-base collection stats
+async fn get_rag_status(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+) -> AppResult<HttpResponse> {
+    let (embedding_engine, embedding_model) = {
+        let config = state.config.read().unwrap();
+        (
+            config.rag_embedding_engine.clone(),
+            config.rag_embedding_model.clone(),
+        )
+    };
+    let embedding = match &state.embedding_provider {
+        Some(provider) => json!({
+            "configured": true,
+            "engine": embedding_engine,
+            "model": provider.model_name(),
+            "dimension": provider.dimension(),
+            "providers": provider.provider_health(),
+        }),
+        None => json!({
+            "configured": false,
+            "engine": embedding_engine,
+            "model": embedding_model,
+            "dimension": null,
+            "providers": [],
+        }),
+    };
+
+    let Some(vector_db) = &state.vector_db else {
+        return Ok(HttpResponse::Ok().json(json!({
+            "status": "disabled",
+            "vector_db": {
+                "configured": false,
+                "reachable": false,
+            },
+            "embedding": embedding,
+            "collections": [],
+        })));
+    };
+
+    if let Err(e) = vector_db.heartbeat().await {
+        return Ok(HttpResponse::Ok().json(json!({
+            "status": "unreachable",
+            "vector_db": {
+                "configured": true,
+                "reachable": false,
+                "error": e.to_string(),
+            },
+            "embedding": embedding,
+            "collections": [],
+        })));
+    }
+
+    let knowledge_bases = KnowledgeService::new(&state.db).get_all_knowledge().await?;
+    let collections =
+        futures::future::join_all(knowledge_bases.iter().map(|knowledge| {
+            collection_status(vector_db.as_ref(), &knowledge.id, &knowledge.name)
+        }))
+        .await;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "vector_db": {
+            "configured": true,
+            "reachable": true,
+        },
+        "embedding": embedding,
+        "collections": collections,
+    })))
+}
+
+// POST /rag/prune-orphans - Delete vector collections whose knowledge base or file is gone
+//
+// Only reports what would be deleted unless called with `?dry_run=false`.
+async fn prune_orphan_collections(
+    state: web::Data<AppState>,
+    auth_user: AuthUser, // AdminMiddleware already checked
+    dry_run: web::Query<DryRunQuery>,
+) -> AppResult<HttpResponse> {
+    let vector_db = state
+        .vector_db
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest("Vector database is not configured".to_string()))?;
+
+    let report = VectorPruneService::new(&state.db)
+        .prune(vector_db.as_ref(), dry_run.is_dry_run(true))
+        .await?;
+    if !report.dry_run {
+        tracing::warn!(
+            "Admin {} pruned {} orphaned vector collections ({} failed)",
+            auth_user.user.id,
+            report.deleted.len(),
+            report.failed.len()
+        );
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Existence and vector count for a knowledge base's collection
+async fn collection_status(
+    vector_db: &dyn VectorDB,
+    knowledge_id: &str,
+    name: &str,
+) -> serde_json::Value {
+    let exists = match vector_db.has_collection(knowledge_id).await {
+        Ok(exists) => exists,
+        Err(e) => {
+            return json!({
+                "knowledge_id": knowledge_id,
+                "name": name,
+                "exists": false,
+                "count": null,
+                "error": e.to_string(),
+            })
+        }
+    };
+
+    let (count, error) = if exists {
+        match vector_db.count(knowledge_id).await {
+            Ok(count) => (Some(count), None),
+            Err(e) => (None, Some(e.to_string())),
+        }
+    } else {
+        (Some(0), None)
+    };
+
+    json!({
+        "knowledge_id": knowledge_id,
+        "name": name,
+        "exists": exists,
+        "count": count,
+        "error": error,
+    })
+}
@@ -256,7 +256,7 @@ async fn speech(
 
     // Forward to configured TTS engine (OpenAI-compatible endpoint)
     if config.tts_engine == "openai" {
-        let client = reqwest::Client::new();
+        let client = crate::utils::http::client();
 
         let tts_payload = json!({
             "model": payload.model.as_ref().unwrap_or(&config.tts_model),
@@ -342,7 +342,7 @@ async fn get_models(
                 .tts_openai_api_base_url
                 .starts_with("https://api.openai.com")
             {
-                let client = reqwest::Client::new();
+                let client = crate::utils::http::client();
                 if let Ok(response) = client
                     .get(format!("{}/audio/models", config.tts_openai_api_base_url))
                     .send()
@@ -380,7 +380,7 @@ async fn get_models(
         }
         "elevenlabs" => {
             // Fetch from Elevenlabs API
-            let client = reqwest::Client::new();
+            let client = crate::utils::http::client();
             if let Ok(response) = client
                 .get("https://api.elevenlabs.io/v1/models")
                 .header("xi-api-key", &config.tts_api_key)
@@ -427,7 +427,7 @@ async fn get_voices(
                 .tts_openai_api_base_url
                 .starts_with("https://api.openai.com")
             {
-                let client = reqwest::Client::new();
+                let client = crate::utils::http::client();
                 if let Ok(response) = client
                     .get(format!("{}/audio/voices", config.tts_openai_api_base_url))
                     .send()
@@ -481,7 +481,7 @@ async fn get_voices(
         }
         "elevenlabs" => {
             // Fetch from Elevenlabs API
-            let client = reqwest::Client::new();
+            let client = crate::utils::http::client();
             if let Ok(response) = client
                 .get("https://api.elevenlabs.io/v1/voices")
                 .header("xi-api-key", &config.tts_api_key)
@@ -517,7 +517,7 @@ async fn get_voices(
                 )
             };
 
-            let client = reqwest::Client::new();
+            let client = crate::utils::http::client();
             if let Ok(response) = client
                 .get(&url)
                 .header("Ocp-Apim-Subscription-Key", &config.tts_api_key)
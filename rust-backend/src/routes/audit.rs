@@ -0,0 +1,16 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    error::AppResult, middleware::AuthUser, models::audit::AuditQuery,
+    services::audit::AuditService, AppState,
+};
+
+// GET /api/audit - Filtered audit log with per-action counts
+pub async fn get_audit_logs(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<AuditQuery>,
+) -> AppResult<HttpResponse> {
+    let page = AuditService::new(&state.db).list(&query).await?;
+    Ok(HttpResponse::Ok().json(page))
+}
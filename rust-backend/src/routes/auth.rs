@@ -7,13 +7,34 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use validator::Validate;
 
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use crate::middleware::{AuthMiddleware, AuthUser};
-use crate::models::{SessionResponse, SigninRequest, SignupRequest};
-use crate::services::{AuthService, UserService};
-use crate::utils::auth::create_jwt;
+use crate::models::{SessionResponse, SigninRequest, SignupRequest, User};
+use crate::services::audit;
+use crate::services::mfa::MfaService;
+use crate::services::signin_throttle::SigninThrottle;
+use crate::services::{AuthService, ConfigService, UserService};
+use crate::utils::auth::{
+    create_jwt, create_mfa_challenge_jwt, create_mfa_setup_jwt, verify_mfa_challenge_jwt,
+};
+use crate::utils::captcha::{verify_captcha, CaptchaProvider};
+use crate::utils::webhook::{self, WebhookPayload};
 use crate::AppState;
 
+/// Audit action for successful password and OAuth sign-ins
+pub const SIGNIN_ACTION: &str = "user.signed_in";
+
+/// Audit action for a user turning on multi-factor sign-in
+pub const MFA_ENABLED_ACTION: &str = "user.mfa_enabled";
+
+/// Lifetime of the token an admin gets to set up MFA under REQUIRE_MFA_FOR_ADMINS
+const MFA_SETUP_TTL_SECS: i64 = 15 * 60;
+
+/// Cookie holding a sign-in that still owes an MFA code, and how long it lasts
+pub const MFA_CHALLENGE_COOKIE: &str = "mfa_challenge";
+const MFA_CHALLENGE_PATH: &str = "/api/v1/auths/mfa/challenge";
+const MFA_CHALLENGE_TTL_SECS: i64 = 5 * 60;
+
 // Helper function to create a cookie for clearing auth cookies
 fn create_clear_cookie() -> Cookie<'static> {
     let mut token_cookie = Cookie::new("token", "");
@@ -29,6 +50,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
         .route("/signup", web::post().to(signup))
         .route("/signout", web::get().to(signout))
         .route("/ldap", web::post().to(ldap_auth))
+        .route("/mfa/challenge", web::post().to(mfa_challenge))
         .service(
             web::resource("")
                 .wrap(AuthMiddleware)
@@ -49,6 +71,16 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
                 .wrap(AuthMiddleware)
                 .route(web::post().to(update_password)),
         )
+        .service(
+            web::resource("/mfa/setup")
+                .wrap(AuthMiddleware)
+                .route(web::post().to(mfa_setup)),
+        )
+        .service(
+            web::resource("/mfa/verify")
+                .wrap(AuthMiddleware)
+                .route(web::post().to(mfa_verify)),
+        )
         .service(
             web::resource("/add")
                 .wrap(AuthMiddleware)
@@ -86,24 +118,17 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
         );
 }
 
-async fn get_session_user(
-    state: web::Data<AppState>,
-    auth_user: AuthUser,
-    req: HttpRequest,
-) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
-
-    // Get token from Authorization header or cookie
-    let token = if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
-        if let Ok(auth_str) = auth_header.to_str() {
-            auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
-        } else {
-            None
-        }
-    } else {
-        None
-    }
-    .or_else(|| req.cookie("token").map(|c| c.value().to_string()));
+/// The token to keep using for `auth_user`'s session, and when it expires
+///
+/// Tokens within five minutes of expiry are swapped for a fresh one. Setup-only and
+/// impersonation tokens run out instead, so they can't be turned into a full session
+/// or outlive IMPERSONATION_TTL without an audit entry.
+fn session_token(
+    config: &crate::config::Config,
+    auth_user: &AuthUser,
+    token: Option<String>,
+) -> AppResult<(String, Option<i64>)> {
+    let renewable = !auth_user.mfa_setup_pending && auth_user.impersonated_by.is_none();
 
     // Validate token and check expiration
     let (token, expires_at, _should_refresh) = if let Some(existing_token) = token {
@@ -120,7 +145,7 @@ async fn get_session_user(
                     }
 
                     // Check if token is close to expiring (within 5 minutes) - refresh it
-                    let should_refresh = (exp - now) < 300; // 5 minutes = 300 seconds
+                    let should_refresh = renewable && (exp - now) < 300; // 5 minutes = 300 seconds
 
                     if should_refresh {
                         // Generate new token
@@ -146,6 +171,11 @@ async fn get_session_user(
                     (existing_token, None, false)
                 }
             }
+            Err(_) if !renewable => {
+                return Err(AppError::Unauthorized(
+                    "Session can't be renewed".to_string(),
+                ));
+            }
             Err(_) => {
                 // Token is invalid, generate new one
                 let new_token = create_jwt(
@@ -161,6 +191,10 @@ async fn get_session_user(
                 (new_token, new_expires_at, true)
             }
         }
+    } else if !renewable {
+        return Err(AppError::Unauthorized(
+            "Session can't be renewed".to_string(),
+        ));
     } else {
         // No token found, generate new one
         let new_token = create_jwt(
@@ -176,6 +210,30 @@ async fn get_session_user(
         (new_token, new_expires_at, true)
     };
 
+    Ok((token, expires_at))
+}
+
+async fn get_session_user(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    req: HttpRequest,
+) -> AppResult<HttpResponse> {
+    let config = state.config.read().unwrap();
+
+    // Get token from Authorization header or cookie
+    let token = if let Some(auth_header) = req.headers().get(header::AUTHORIZATION) {
+        if let Ok(auth_str) = auth_header.to_str() {
+            auth_str.strip_prefix("Bearer ").map(|s| s.to_string())
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+    .or_else(|| req.cookie("token").map(|c| c.value().to_string()));
+
+    let (token, expires_at) = session_token(&config, &auth_user, token)?;
+
     let response_json = json!({
         "token": token,
         "token_type": "Bearer",
@@ -210,18 +268,57 @@ async fn get_session_user(
 
 async fn signin(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<SigninRequest>,
 ) -> AppResult<HttpResponse> {
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    let oauth_providers = state.oauth_manager.get_available_providers().await;
+    ensure_login_form_enabled(&state.config.read().unwrap(), &oauth_providers)?;
+
+    req.validate()?;
 
     let auth_service = AuthService::new(&state.db);
     let user_service = UserService::new(&state.db);
 
-    let user_id = auth_service
+    // Hold the attempt before checking credentials, so dropping the connection early
+    // doesn't reveal the outcome
+    let client_ip = SigninThrottle::client_address(
+        &http_req.connection_info(),
+        &state.config.read().unwrap().trusted_proxies,
+    );
+    let throttle_keys = SigninThrottle::keys(&req.email, client_ip.as_deref().unwrap_or("unknown"));
+    let delay = state.signin_throttle.delay(&throttle_keys);
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let Some(user_id) = auth_service
         .authenticate(&req.email.to_lowercase(), &req.password)
         .await?
-        .ok_or(crate::error::AppError::InvalidCredentials)?;
+    else {
+        let failures = state.signin_throttle.record_failure(&throttle_keys);
+        tracing::debug!("Failed sign-in #{} for {}", failures, throttle_keys[0]);
+        let config = state.config.read().unwrap();
+        webhook::dispatch_auth_event(
+            &config,
+            WebhookPayload::auth_attempt_event(
+                webhook::AUTH_LOGIN_FAILURE,
+                &req.email,
+                client_ip.as_deref(),
+            ),
+        );
+        if state.signin_throttle.reaches_max_delay(failures) {
+            webhook::dispatch_auth_event(
+                &config,
+                WebhookPayload::auth_attempt_event(
+                    webhook::AUTH_ACCOUNT_LOCKED,
+                    &req.email,
+                    client_ip.as_deref(),
+                ),
+            );
+        }
+        return Err(crate::error::AppError::InvalidCredentials);
+    };
+    state.signin_throttle.reset(&throttle_keys);
 
     let user =
         user_service
@@ -230,13 +327,95 @@ async fn signin(
             .ok_or(crate::error::AppError::NotFound(
                 "User not found".to_string(),
             ))?;
+    let mfa_enabled = check_signin_mfa(&state, &user, req.mfa_code.as_deref()).await?;
+
+    audit::spawn_record(
+        state.clone(),
+        user.id.clone(),
+        SIGNIN_ACTION.to_string(),
+        "user",
+        user.id.clone(),
+        audit::with_region(Some(json!({ "method": "password" })), client_ip.as_deref()),
+    );
 
     let config = state.config.read().unwrap();
-    let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
+    webhook::dispatch_auth_event(
+        &config,
+        WebhookPayload::auth_user_event(
+            webhook::AUTH_LOGIN_SUCCESS,
+            &user.id,
+            client_ip.as_deref(),
+        ),
+    );
+    let mfa_setup_pending = !mfa_enabled && user.role == "admin" && config.require_mfa_for_admins;
+    session_response(&config, user, mfa_setup_pending)
+}
+
+/// Check the MFA code of a sign-in whose password was right, if the user has MFA
+///
+/// Returns whether MFA is enabled for the user.
+pub(crate) async fn check_signin_mfa(
+    state: &AppState,
+    user: &User,
+    code: Option<&str>,
+) -> AppResult<bool> {
+    let mfa = {
+        let config = state.config.read().unwrap();
+        MfaService::new(&state.db, &config)?
+    };
+    if !mfa.is_enabled(&user.id).await? {
+        return Ok(false);
+    }
 
-    let expires_at = chrono::Utc::now()
-        .checked_add_signed(crate::utils::auth::parse_duration(&config.jwt_expires_in)?)
-        .map(|dt| dt.timestamp());
+    let Some(code) = code.map(str::trim).filter(|code| !code.is_empty()) else {
+        return Err(AppError::Auth("MFA code required".to_string()));
+    };
+    state.mfa_attempts.check(&user.id)?;
+    if !mfa.verify(&user.id, code).await? {
+        state.mfa_attempts.record_failure(&user.id);
+        return Err(AppError::Auth("Invalid MFA code".to_string()));
+    }
+    state.mfa_attempts.reset(&user.id);
+    Ok(true)
+}
+
+/// Respond to a sign-in with a session for `user`
+fn session_response(
+    config: &crate::config::Config,
+    user: User,
+    mfa_setup_pending: bool,
+) -> AppResult<HttpResponse> {
+    let (session, cookie) = new_session(config, user, mfa_setup_pending)?;
+
+    // Return response with Set-Cookie header
+    Ok(HttpResponse::Ok()
+        .append_header((header::SET_COOKIE, cookie.to_string()))
+        .json(session))
+}
+
+/// Session for `user`, as JSON and the token cookie
+///
+/// An admin who still has to set up MFA gets a short-lived token that only reaches the
+/// MFA setup routes, and `mfa_setup_required` in the response.
+pub(crate) fn new_session(
+    config: &crate::config::Config,
+    user: User,
+    mfa_setup_pending: bool,
+) -> AppResult<(serde_json::Value, Cookie<'static>)> {
+    let (token, expires_at) = if mfa_setup_pending {
+        let (token, exp) = create_mfa_setup_jwt(
+            &user.id,
+            &config.webui_secret_key,
+            chrono::Duration::seconds(MFA_SETUP_TTL_SECS),
+        )?;
+        (token, Some(exp))
+    } else {
+        let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
+        let expires_at = chrono::Utc::now()
+            .checked_add_signed(crate::utils::auth::parse_duration(&config.jwt_expires_in)?)
+            .map(|dt| dt.timestamp());
+        (token, expires_at)
+    };
 
     let session_response = SessionResponse {
         token: token.clone(),
@@ -249,6 +428,10 @@ async fn signin(
         profile_image_url: user.profile_image_url,
         permissions: json!({}),
     };
+    let mut body = json!(session_response);
+    if mfa_setup_pending {
+        body["mfa_setup_required"] = json!(true);
+    }
 
     // Create cookie with token
     let mut cookie = Cookie::new("token", token);
@@ -261,26 +444,94 @@ async fn signin(
         cookie.set_expires(time::OffsetDateTime::from_unix_timestamp(exp).ok());
     }
 
-    // Return response with Set-Cookie header
-    Ok(HttpResponse::Ok()
-        .append_header((header::SET_COOKIE, cookie.to_string()))
-        .json(session_response))
+    Ok((body, cookie))
+}
+
+/// Reject email/password sign-in and signup while the login form is turned off
+///
+/// ENABLE_LOGIN_FORM=false only takes effect while an OAuth provider is available to
+/// sign in with instead, so losing the last provider can't lock everyone out.
+fn ensure_login_form_enabled(
+    config: &crate::config::Config,
+    oauth_providers: &[String],
+) -> AppResult<()> {
+    if config.enable_login_form || oauth_providers.is_empty() {
+        return Ok(());
+    }
+    Err(crate::error::AppError::Forbidden(
+        "Password sign-in is disabled; sign in with an OAuth provider".to_string(),
+    ))
+}
+
+/// Turning the login form off needs an OAuth provider to sign in with instead
+fn check_login_form_change(enable_login_form: bool, oauth_providers: &[String]) -> AppResult<()> {
+    if enable_login_form || !oauth_providers.is_empty() {
+        return Ok(());
+    }
+    Err(crate::error::AppError::BadRequest(
+        "Enable an OAuth provider before disabling the login form".to_string(),
+    ))
+}
+
+/// Add a newly created user to DEFAULT_USER_GROUPS; failures don't block account creation
+async fn add_to_default_groups(
+    state: &web::Data<AppState>,
+    user_id: &str,
+    config: &crate::config::Config,
+) {
+    // Failures are logged per group by the service
+    crate::services::group::GroupService::new(&state.db)
+        .add_user_to_default_groups(user_id, config)
+        .await;
 }
 
 async fn signup(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     req: web::Json<SignupRequest>,
 ) -> AppResult<HttpResponse> {
-    let config = state.config.read().unwrap();
+    let oauth_providers = state.oauth_manager.get_available_providers().await;
+    let captcha = {
+        let config = state.config.read().unwrap();
 
-    if !config.enable_signup {
-        return Err(crate::error::AppError::Forbidden(
-            "Signup is disabled".to_string(),
-        ));
+        if !config.enable_signup {
+            return Err(crate::error::AppError::Forbidden(
+                "Signup is disabled".to_string(),
+            ));
+        }
+        ensure_login_form_enabled(&config, &oauth_providers)?;
+
+        config
+            .signup_captcha_provider
+            .as_deref()
+            .and_then(CaptchaProvider::parse)
+            .map(|provider| (provider, config.captcha_secret.clone()))
+    };
+
+    req.validate()?;
+
+    if let Some((provider, secret)) = captcha {
+        // Peer addresses include the port; providers expect a bare IP
+        let remote_ip =
+            http_req
+                .connection_info()
+                .realip_remote_addr()
+                .map(|addr| match addr.parse::<std::net::SocketAddr>() {
+                    Ok(addr) => addr.ip().to_string(),
+                    Err(_) => addr.to_string(),
+                });
+        verify_captcha(
+            &state.http_client,
+            provider,
+            &secret,
+            req.captcha_token.as_deref(),
+            remote_ip.as_deref(),
+        )
+        .await?;
     }
 
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    // A snapshot, since record_first_run takes the write lock further down
+    let config = state.config.read().unwrap().clone();
 
     if let Some(confirmation) = &req.password_confirmation {
         if &req.password != confirmation {
@@ -329,39 +580,47 @@ async fn signup(
         .create_auth(&user_id, &req.email.to_lowercase(), &req.password)
         .await?;
 
-    let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
+    add_to_default_groups(&state, &user.id, &config).await;
 
-    let expires_at = chrono::Utc::now()
-        .checked_add_signed(crate::utils::auth::parse_duration(&config.jwt_expires_in)?)
-        .map(|dt| dt.timestamp());
+    let client_ip = http_req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    webhook::dispatch_auth_event(
+        &config,
+        WebhookPayload::auth_user_event(webhook::AUTH_SIGNUP, &user.id, client_ip.as_deref()),
+    );
 
-    let session_response = SessionResponse {
-        token: token.clone(),
-        token_type: "Bearer".to_string(),
-        expires_at,
-        id: user.id,
-        email: user.email,
-        name: user.name,
-        role: user.role,
-        profile_image_url: user.profile_image_url,
-        permissions: json!({}),
-    };
+    if user_count == 0 {
+        record_first_run(&state, &user).await;
+    }
 
-    // Create cookie with token
-    let mut cookie = Cookie::new("token", token);
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_path("/");
+    let mfa_setup_pending = user.role == "admin" && config.require_mfa_for_admins;
+    session_response(&config, user, mfa_setup_pending)
+}
 
-    // Set expiration if available
-    if let Some(exp) = expires_at {
-        cookie.set_expires(time::OffsetDateTime::from_unix_timestamp(exp).ok());
-    }
+/// Note the instance's first run and tell webhook subscribers, once per instance
+///
+/// The timestamp is persisted, so an admin created after every user was deleted does
+/// not trigger the event again.
+async fn record_first_run(state: &AppState, admin: &User) {
+    let section = {
+        let mut config = state.config.write().unwrap();
+        if config.first_run_at.is_some() {
+            return;
+        }
+        config.first_run_at = Some(chrono::Utc::now().timestamp());
+        webhook::dispatch_event(
+            &config,
+            WebhookPayload::first_run(&admin.id, &admin.email, &config.webui_url),
+        );
+        ConfigService::onboarding_section(&config)
+    };
 
-    // Return response with Set-Cookie header
-    Ok(HttpResponse::Ok()
-        .append_header((header::SET_COOKIE, cookie.to_string()))
-        .json(session_response))
+    tracing::info!("First admin {} created", admin.email);
+    if let Err(e) = ConfigService::update_section(&state.db, "onboarding", section).await {
+        tracing::warn!("Failed to persist first-run time: {}", e);
+    }
 }
 
 async fn signout(_state: web::Data<AppState>) -> HttpResponse {
@@ -419,6 +678,7 @@ async fn update_profile(
 
 async fn update_password(
     state: web::Data<AppState>,
+    http_req: HttpRequest,
     auth_user: AuthUser,
     req: web::Json<serde_json::Value>,
 ) -> AppResult<HttpResponse> {
@@ -429,7 +689,7 @@ async fn update_password(
                 "password is required".to_string(),
             ))?;
 
-    let _new_password = req.get("new_password").and_then(|v| v.as_str()).ok_or(
+    let new_password = req.get("new_password").and_then(|v| v.as_str()).ok_or(
         crate::error::AppError::BadRequest("new_password is required".to_string()),
     )?;
 
@@ -445,9 +705,23 @@ async fn update_password(
         return Err(crate::error::AppError::InvalidCredentials);
     }
 
-    // TODO: Update password (you'll need to implement this in AuthService)
-    // auth_service.update_password(&auth_user.user.id, new_password).await?;
-    // For now, return success
+    auth_service
+        .update_password(&auth_user.user.id, new_password)
+        .await?;
+
+    let client_ip = http_req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    webhook::dispatch_auth_event(
+        &state.config.read().unwrap(),
+        WebhookPayload::auth_user_event(
+            webhook::AUTH_PASSWORD_CHANGED,
+            &auth_user.user.id,
+            client_ip.as_deref(),
+        ),
+    );
+
     Ok(HttpResponse::Ok().json(json!({"status": true})))
 }
 
@@ -462,6 +736,134 @@ struct AddUserRequest {
     role: String,
 }
 
+/// Start MFA setup: a new secret and the `otpauth://` URL to enroll it with
+async fn mfa_setup(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
+    let (mfa, issuer) = {
+        let config = state.config.read().unwrap();
+        (
+            MfaService::new(&state.db, &config)?,
+            config.webui_name.clone(),
+        )
+    };
+    let setup = mfa
+        .begin_setup(&auth_user.user.id, &issuer, &auth_user.user.email)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "secret": setup.secret,
+        "otpauth_url": setup.otpauth_url,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaVerifyForm {
+    code: String,
+}
+
+/// Enable MFA with a code from the secret being set up; returns the recovery codes
+///
+/// An admin signed in only to set up MFA gets a full session in exchange.
+async fn mfa_verify(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    form: web::Json<MfaVerifyForm>,
+) -> AppResult<HttpResponse> {
+    let user = &auth_user.user;
+    state.mfa_attempts.check(&user.id)?;
+    let mfa = {
+        let config = state.config.read().unwrap();
+        MfaService::new(&state.db, &config)?
+    };
+    let Some(recovery_codes) = mfa.enable(&user.id, &form.code).await? else {
+        state.mfa_attempts.record_failure(&user.id);
+        return Err(AppError::Auth("Invalid MFA code".to_string()));
+    };
+    state.mfa_attempts.reset(&user.id);
+
+    audit::spawn_record(
+        state.clone(),
+        user.id.clone(),
+        MFA_ENABLED_ACTION.to_string(),
+        "user",
+        user.id.clone(),
+        None,
+    );
+
+    if !auth_user.mfa_setup_pending {
+        return Ok(HttpResponse::Ok().json(json!({
+            "enabled": true,
+            "recovery_codes": recovery_codes,
+        })));
+    }
+    let config = state.config.read().unwrap();
+    let (session, cookie) = new_session(&config, user.clone(), false)?;
+    Ok(HttpResponse::Ok()
+        .append_header((header::SET_COOKIE, cookie.to_string()))
+        .json(json!({
+            "enabled": true,
+            "recovery_codes": recovery_codes,
+            "session": session,
+        })))
+}
+
+#[derive(Debug, Deserialize)]
+struct MfaChallengeForm {
+    code: String,
+}
+
+/// Cookie carrying a sign-in that owes an MFA code to `/mfa/challenge`
+///
+/// Sign-ins that can't take a code up front (OAuth) set this and let the user finish
+/// with one.
+pub(crate) fn mfa_challenge_cookie(
+    config: &crate::config::Config,
+    user_id: &str,
+) -> AppResult<Cookie<'static>> {
+    let (token, exp) = create_mfa_challenge_jwt(
+        user_id,
+        &config.webui_secret_key,
+        chrono::Duration::seconds(MFA_CHALLENGE_TTL_SECS),
+    )?;
+    let mut cookie = Cookie::new(MFA_CHALLENGE_COOKIE, token);
+    cookie.set_http_only(true);
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_path(MFA_CHALLENGE_PATH);
+    cookie.set_expires(time::OffsetDateTime::from_unix_timestamp(exp).ok());
+    Ok(cookie)
+}
+
+/// Finish a sign-in waiting on an MFA code, in exchange for a session
+async fn mfa_challenge(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    form: web::Json<MfaChallengeForm>,
+) -> AppResult<HttpResponse> {
+    let challenge = http_req
+        .cookie(MFA_CHALLENGE_COOKIE)
+        .ok_or_else(|| AppError::Unauthorized("No sign-in is waiting for MFA".to_string()))?;
+    let secret = state.config.read().unwrap().webui_secret_key.clone();
+    let claims = verify_mfa_challenge_jwt(challenge.value(), &secret).map_err(|_| {
+        AppError::Unauthorized("MFA sign-in expired, please sign in again".to_string())
+    })?;
+    let user = UserService::new(&state.db)
+        .get_user_by_id(&claims.sub)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User not found".to_string()))?;
+
+    let mfa_enabled = check_signin_mfa(&state, &user, Some(&form.code)).await?;
+
+    let config = state.config.read().unwrap();
+    let mfa_setup_pending = !mfa_enabled && user.role == "admin" && config.require_mfa_for_admins;
+    let (session, cookie) = new_session(&config, user, mfa_setup_pending)?;
+    let mut spent = Cookie::new(MFA_CHALLENGE_COOKIE, "");
+    spent.set_path(MFA_CHALLENGE_PATH);
+    spent.make_removal();
+    Ok(HttpResponse::Ok()
+        .append_header((header::SET_COOKIE, cookie.to_string()))
+        .append_header((header::SET_COOKIE, spent.to_string()))
+        .json(session))
+}
+
 async fn add_user(
     state: web::Data<AppState>,
     auth_user: AuthUser,
@@ -474,8 +876,7 @@ async fn add_user(
         ));
     }
 
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
     let user_service = UserService::new(&state.db);
     let auth_service = AuthService::new(&state.db);
@@ -637,6 +1038,9 @@ struct AdminConfigResponse {
     webui_url: String,
     #[serde(rename = "ENABLE_SIGNUP")]
     enable_signup: bool,
+    /// Left unchanged when an update leaves it out
+    #[serde(rename = "ENABLE_LOGIN_FORM", default)]
+    enable_login_form: Option<bool>,
     #[serde(rename = "ENABLE_API_KEY")]
     enable_api_key: bool,
     #[serde(rename = "ENABLE_API_KEY_ENDPOINT_RESTRICTIONS")]
@@ -682,6 +1086,7 @@ async fn get_admin_config(
         show_admin_details: config.show_admin_details,
         webui_url: config.webui_url.clone(),
         enable_signup: config.enable_signup,
+        enable_login_form: Some(config.enable_login_form),
         enable_api_key: config.enable_api_key,
         enable_api_key_endpoint_restrictions: config.enable_api_key_endpoint_restrictions,
         api_key_allowed_endpoints: config.api_key_allowed_endpoints.clone(),
@@ -710,12 +1115,20 @@ async fn update_admin_config(
         ));
     }
 
+    if let Some(enable_login_form) = form_data.enable_login_form {
+        let oauth_providers = state.oauth_manager.get_available_providers().await;
+        check_login_form_change(enable_login_form, &oauth_providers)?;
+    }
+
     // Update config with write lock
     let mut config = state.config.write().unwrap();
 
     config.show_admin_details = form_data.show_admin_details;
     config.webui_url = form_data.webui_url.clone();
     config.enable_signup = form_data.enable_signup;
+    if let Some(enable_login_form) = form_data.enable_login_form {
+        config.enable_login_form = enable_login_form;
+    }
     config.enable_api_key = form_data.enable_api_key;
     config.enable_api_key_endpoint_restrictions = form_data.enable_api_key_endpoint_restrictions;
     config.api_key_allowed_endpoints = form_data.api_key_allowed_endpoints.clone();
@@ -745,6 +1158,7 @@ async fn update_admin_config(
         "show_admin_details": config.show_admin_details,
         "webui_url": config.webui_url,
         "enable_signup": config.enable_signup,
+        "enable_login_form": config.enable_login_form,
         "enable_api_key": config.enable_api_key,
         "enable_api_key_endpoint_restrictions": config.enable_api_key_endpoint_restrictions,
         "api_key_allowed_endpoints": config.api_key_allowed_endpoints,
@@ -776,6 +1190,7 @@ async fn update_admin_config(
         show_admin_details: config.show_admin_details,
         webui_url: config.webui_url.clone(),
         enable_signup: config.enable_signup,
+        enable_login_form: Some(config.enable_login_form),
         enable_api_key: config.enable_api_key,
         enable_api_key_endpoint_restrictions: config.enable_api_key_endpoint_restrictions,
         api_key_allowed_endpoints: config.api_key_allowed_endpoints.clone(),
@@ -962,16 +1377,18 @@ struct LdapAuthRequest {
     user: String,
     #[validate(length(min = 1))]
     password: String,
+    /// TOTP or recovery code, for users with MFA enabled
+    #[serde(default)]
+    mfa_code: Option<String>,
 }
 
 async fn ldap_auth(
     state: web::Data<AppState>,
     req: web::Json<LdapAuthRequest>,
 ) -> AppResult<HttpResponse> {
-    req.validate()
-        .map_err(|e| crate::error::AppError::Validation(e.to_string()))?;
+    req.validate()?;
 
-    let config = state.config.read().unwrap();
+    let config = state.config.read().unwrap().clone();
 
     // Check if LDAP is enabled
     if !config.enable_ldap {
@@ -1043,44 +1460,114 @@ async fn ldap_auth(
                 )
                 .await?,
         );
+        add_to_default_groups(&state, &user_id, &config).await;
     }
 
     let user = user.ok_or(crate::error::AppError::NotFound(
         "Failed to create user".to_string(),
     ))?;
 
-    // Generate JWT token
-    let token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
+    let mfa_enabled = check_signin_mfa(&state, &user, req.mfa_code.as_deref()).await?;
+    let mfa_setup_pending = !mfa_enabled && user.role == "admin" && config.require_mfa_for_admins;
+    session_response(&config, user, mfa_setup_pending)
+}
 
-    let expires_at = chrono::Utc::now()
-        .checked_add_signed(crate::utils::auth::parse_duration(&config.jwt_expires_in)?)
-        .map(|dt| dt.timestamp());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::utils::auth::create_impersonation_jwt;
 
-    let session_response = SessionResponse {
-        token: token.clone(),
-        token_type: "Bearer".to_string(),
-        expires_at,
-        id: user.id,
-        email: user.email,
-        name: user.name,
-        role: user.role,
-        profile_image_url: user.profile_image_url,
-        permissions: json!({}),
-    };
+    fn config(enable_login_form: bool) -> crate::config::Config {
+        let mut config = crate::config::Config::from_lookup(|_| None).unwrap();
+        config.enable_login_form = enable_login_form;
+        config
+    }
 
-    // Create cookie with token
-    let mut cookie = Cookie::new("token", token);
-    cookie.set_http_only(true);
-    cookie.set_same_site(SameSite::Lax);
-    cookie.set_path("/");
+    #[test]
+    fn test_signin_rejected_when_login_form_disabled() {
+        let google = vec!["google".to_string()];
 
-    // Set expiration if available
-    if let Some(exp) = expires_at {
-        cookie.set_expires(time::OffsetDateTime::from_unix_timestamp(exp).ok());
+        assert!(ensure_login_form_enabled(&config(true), &google).is_ok());
+        assert!(matches!(
+            ensure_login_form_enabled(&config(false), &google),
+            Err(AppError::Forbidden(_))
+        ));
+        // Without a provider to fall back on, password sign-in stays open
+        assert!(ensure_login_form_enabled(&config(false), &[]).is_ok());
     }
 
-    // Return response with Set-Cookie header
-    Ok(HttpResponse::Ok()
-        .append_header((header::SET_COOKIE, cookie.to_string()))
-        .json(session_response))
+    #[test]
+    fn test_login_form_cannot_be_disabled_without_oauth() {
+        assert!(matches!(
+            check_login_form_change(false, &[]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(check_login_form_change(false, &["google".to_string()]).is_ok());
+        assert!(check_login_form_change(true, &[]).is_ok());
+    }
+
+    fn admin() -> AuthUser {
+        AuthUser::new(
+            serde_json::from_value(json!({
+                "id": "admin-1",
+                "name": "Admin",
+                "email": "admin@example.com",
+                "role": "admin",
+                "profile_image_url": "/user.png",
+                "last_active_at": 0,
+                "updated_at": 0,
+                "created_at": 0,
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_restricted_tokens_are_not_renewed_into_full_sessions() {
+        let mut config = config(true);
+        config.webui_secret_key = "secret".to_string();
+
+        // Two minutes left puts the token inside the refresh window
+        let (token, exp) =
+            create_mfa_setup_jwt("admin-1", "secret", chrono::Duration::minutes(2)).unwrap();
+        let pending = AuthUser {
+            mfa_setup_pending: true,
+            ..admin()
+        };
+        let (kept, expires_at) = session_token(&config, &pending, Some(token.clone())).unwrap();
+        assert_eq!(kept, token);
+        assert_eq!(expires_at, Some(exp));
+        assert!(
+            crate::utils::auth::verify_jwt(&kept, "secret")
+                .unwrap()
+                .mfa_setup_pending
+        );
+        assert!(matches!(
+            session_token(&config, &pending, None),
+            Err(AppError::Unauthorized(_))
+        ));
+
+        // Nor is an impersonation token about to run out
+        let (token, exp) =
+            create_impersonation_jwt("user-1", "admin-1", "secret", chrono::Duration::minutes(2))
+                .unwrap();
+        let impersonated = AuthUser {
+            impersonated_by: Some("admin-1".to_string()),
+            ..admin()
+        };
+        let (kept, expires_at) =
+            session_token(&config, &impersonated, Some(token.clone())).unwrap();
+        assert_eq!(kept, token);
+        assert_eq!(expires_at, Some(exp));
+        assert!(matches!(
+            session_token(&config, &impersonated, None),
+            Err(AppError::Unauthorized(_))
+        ));
+
+        // A full session in the same window is refreshed as before
+        let token = create_jwt("admin-1", "secret", "2m").unwrap();
+        let (renewed, _) = session_token(&config, &admin(), Some(token.clone())).unwrap();
+        assert_ne!(renewed, token);
+    }
 }
@@ -196,8 +196,7 @@ async fn create_new_channel(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     let channel_service = ChannelService::new(&state.db);
 
@@ -293,8 +292,7 @@ async fn update_channel_by_id(
         .await?
         .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     let updated_channel = channel_service
         .update_channel(
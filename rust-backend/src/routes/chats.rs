@@ -1,13 +1,18 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{http::header, web, HttpResponse};
 use serde::Deserialize;
 use serde_json::json;
 
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use crate::middleware::{AuthMiddleware, AuthUser};
 use crate::models::chat::{ChatResponse, CreateChatRequest, UpdateChatRequest};
 use crate::services::chat::ChatService;
+use crate::utils::chat_export::{self, ExportFormat};
+use crate::utils::pagination::Cursor;
 use crate::AppState;
 
+/// Largest `limit` accepted for cursor-paginated lists
+const MAX_PAGE_SIZE: i64 = 200;
+
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("")
@@ -92,9 +97,8 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route(web::post().to(get_user_chat_list_by_tag_name)),
     )
     .service(
-        web::resource("/share/{share_id}")
-            .wrap(AuthMiddleware)
-            .route(web::get().to(get_shared_chat_by_id)),
+        // Route auth decides who may view shares (SHARE_REQUIRE_AUTH)
+        web::resource("/share/{share_id}").route(web::get().to(get_shared_chat_by_id)),
     )
     .service(
         web::resource("/archive/all")
@@ -128,6 +132,11 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(toggle_chat_archived)),
     )
+    .service(
+        web::resource("/{id}/export")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(export_chat)),
+    )
     .service(
         web::resource("/{id}/clone")
             .wrap(AuthMiddleware)
@@ -334,6 +343,38 @@ async fn get_chat(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ChatExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+// GET /{id}/export?format=json|markdown - Download one of the user's chats
+async fn export_chat(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+    query: web::Query<ChatExportQuery>,
+) -> AppResult<HttpResponse> {
+    let chat = ChatService::new(&state.db)
+        .get_chat_by_id_and_user_id(&id, &auth_user.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Chat not found".to_string()))?;
+
+    let format = query.format;
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"chat-{}.{}\"",
+                chat.id,
+                format.extension()
+            ),
+        ))
+        .streaming(chat_export::export_stream(chat, format)))
+}
+
 async fn get_chat_pinned_status(
     state: web::Data<AppState>,
     auth_user: AuthUser,
@@ -416,7 +457,8 @@ async fn share_chat(
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
     let share_id = service.create_shared_chat(&id).await?;
-    Ok(HttpResponse::Ok().json(json!({"share_id": share_id})))
+    let require_auth = state.config.read().unwrap().share_require_auth;
+    Ok(HttpResponse::Ok().json(json!({"share_id": share_id, "require_auth": require_auth})))
 }
 
 async fn delete_share_chat(
@@ -460,6 +502,9 @@ async fn delete_all_user_chats(
 pub struct ChatListQueryParams {
     pub page: Option<i64>,
     pub include_folders: Option<bool>,
+    /// Opts into keyset pagination; empty for the first page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
 }
 
 async fn get_chat_list(
@@ -468,6 +513,17 @@ async fn get_chat_list(
     query: web::Query<ChatListQueryParams>,
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
+
+    // Cursor paging returns {items, next_cursor}; `page` keeps the plain list
+    if query.cursor.is_some() {
+        let cursor = Cursor::from_param(query.cursor.as_deref())?;
+        let limit = query.limit.unwrap_or(60).clamp(1, MAX_PAGE_SIZE);
+        let page = service
+            .get_chat_title_id_page_by_user_id(&auth_user.id, cursor.as_ref(), limit)
+            .await?;
+        return Ok(HttpResponse::Ok().json(page));
+    }
+
     let page = query.page.unwrap_or(1);
     let limit = 60;
     let skip = (page - 1) * limit;
@@ -630,7 +686,6 @@ async fn get_user_chat_list_by_tag_name(
 
 async fn get_shared_chat_by_id(
     state: web::Data<AppState>,
-    _auth_user: AuthUser,
     share_id: web::Path<String>,
 ) -> AppResult<HttpResponse> {
     let service = ChatService::new(&state.db);
@@ -5,6 +5,7 @@ use serde_json::json;
 use crate::{
     error::AppError,
     middleware::{AuthMiddleware, AuthUser},
+    utils::app_config::Features,
     AppState,
 };
 
@@ -159,15 +160,20 @@ struct ImportConfigForm {
 async fn import_config(
     state: web::Data<AppState>,
     auth_user: AuthUser,
-    _form_data: web::Json<ImportConfigForm>,
+    form_data: web::Json<ImportConfigForm>,
 ) -> Result<HttpResponse, AppError> {
     // Only admins can import config
     if auth_user.user.role != "admin" {
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    // TODO: Implement config import from JSON
-    // For now, just return the current config
+    // Same import as /api/admin/config/import, answering with the resulting config
+    let current = state.config.read().unwrap().clone();
+    let (imported, report) = crate::services::ConfigService::import(&current, &form_data.config)?;
+    crate::services::ConfigService::persist_settings(&state.db, &imported, &report.applied).await?;
+    *state.config.write().unwrap() = imported;
+    state.models_cache.write().unwrap().clear();
+
     let config = state.config.read().unwrap();
     Ok(HttpResponse::Ok().json(serde_json::to_value(&*config).unwrap()))
 }
@@ -178,20 +184,8 @@ async fn get_features(
 ) -> Result<HttpResponse, AppError> {
     let config = state.config.read().unwrap();
 
-    Ok(HttpResponse::Ok().json(json!({
-        "enable_signup": config.enable_signup,
-        "enable_login_form": config.enable_login_form,
-        "enable_api_key": config.enable_api_key,
-        "enable_channels": config.enable_channels,
-        "enable_notes": config.enable_notes,
-        "enable_image_generation": config.enable_image_generation,
-        "enable_code_execution": config.enable_code_execution,
-        "enable_web_search": config.enable_web_search,
-        "enable_admin_chat_access": config.enable_admin_chat_access,
-        "enable_admin_export": config.enable_admin_export,
-        "enable_community_sharing": config.enable_community_sharing,
-        "enable_message_rating": config.enable_message_rating,
-    })))
+    // The same flags a signed-in caller gets from /api/config
+    Ok(HttpResponse::Ok().json(Features::new(&config, true, true)))
 }
 
 async fn get_banners(
@@ -6,6 +6,7 @@ use crate::{
     middleware::{AdminMiddleware, AuthMiddleware, AuthUser},
     models::feedback::{FeedbackForm, FeedbackModel},
     services::{FeedbackService, UserService},
+    utils::dry_run::{DryRunQuery, DryRunReport},
     AppState,
 };
 
@@ -135,12 +136,23 @@ async fn get_all_feedbacks(
     Ok(HttpResponse::Ok().json(feedback_list))
 }
 
-/// DELETE /feedbacks/all - Delete all feedbacks (admin only)
+/// DELETE /feedbacks/all - Delete all feedbacks (admin only); `?dry_run=true` lists them
+/// instead
 async fn delete_all_feedbacks(
     state: web::Data<AppState>,
     _auth_user: AuthUser, // AdminMiddleware already checked
+    query: web::Query<DryRunQuery>,
 ) -> AppResult<HttpResponse> {
     let feedback_service = FeedbackService::new(&state.db);
+    if query.is_dry_run(false) {
+        let ids = feedback_service
+            .get_all_feedbacks()
+            .await?
+            .into_iter()
+            .map(|feedback| feedback.id)
+            .collect();
+        return Ok(HttpResponse::Ok().json(DryRunReport::new(ids)));
+    }
     let success = feedback_service.delete_all_feedbacks().await?;
     Ok(HttpResponse::Ok().json(success))
 }
@@ -9,6 +9,10 @@ use crate::error::{AppError, AppResult};
 use crate::middleware::auth::{AdminMiddleware, AuthUser};
 use crate::models::file::FileResponse;
 use crate::services::file::FileService;
+use crate::utils::dry_run::{DryRunQuery, DryRunReport};
+
+/// Where uploads are stored, one file per file id
+pub const UPLOAD_DIR: &str = "./data/uploads";
 
 #[derive(Debug, Deserialize)]
 pub struct FileContentForm {
@@ -143,7 +147,7 @@ async fn upload_file(
     let file_id = uuid::Uuid::new_v4().to_string();
 
     // Create upload directory if it doesn't exist
-    let upload_dir = std::path::Path::new("./data/uploads");
+    let upload_dir = std::path::Path::new(UPLOAD_DIR);
     std::fs::create_dir_all(upload_dir)
         .map_err(|e| AppError::BadRequest(format!("Failed to create upload directory: {}", e)))?;
 
@@ -172,10 +176,24 @@ async fn upload_file(
     Ok(HttpResponse::Ok().json(FileResponse::from(file)))
 }
 
-// DELETE /all - Delete all files (admin only)
-async fn delete_all_files(db: web::Data<Database>, _user: AuthUser) -> AppResult<HttpResponse> {
+// DELETE /all - Delete all files (admin only); `?dry_run=true` lists them instead
+async fn delete_all_files(
+    db: web::Data<Database>,
+    _user: AuthUser,
+    query: web::Query<DryRunQuery>,
+) -> AppResult<HttpResponse> {
     let service = FileService::new(&db);
 
+    if query.is_dry_run(false) {
+        let ids = service
+            .get_all_files()
+            .await?
+            .into_iter()
+            .map(|file| file.id)
+            .collect();
+        return Ok(HttpResponse::Ok().json(DryRunReport::new(ids)));
+    }
+
     // TODO: Delete from storage
     // TODO: Reset vector DB
 
@@ -205,8 +223,7 @@ async fn get_file(
     let file = file.unwrap();
 
     // Check access: owner, admin, or has knowledge base access
-    if file.user_id != user.id && user.role != "admin" {
-        // TODO: Check knowledge base access via has_access_to_file
+    if !service.can_read(&file, &user).await? {
         return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "detail": "File not found"
         })));
@@ -216,6 +233,30 @@ async fn get_file(
     Ok(HttpResponse::Ok().json(response))
 }
 
+// GET /{id}/ingest-status - Embedding progress per knowledge base
+async fn get_file_ingest_status(
+    db: web::Data<Database>,
+    user: AuthUser,
+    file_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let service = FileService::new(&db);
+
+    let file = match service.get_file_by_id(&file_id).await? {
+        Some(file) if service.can_read(&file, &user).await? => file,
+        _ => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "detail": "File not found"
+            })))
+        }
+    };
+
+    let progress = service.get_ingest_progress_by_file(&file.id).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "file_id": file.id,
+        "knowledge": progress,
+    })))
+}
+
 // GET /{id}/process/status - Get file process status
 async fn get_file_process_status(
     db: web::Data<Database>,
@@ -234,8 +275,8 @@ async fn get_file_process_status(
 
     let mut file = file.unwrap();
 
-    // Check access
-    if file.user_id != user.id && user.role != "admin" {
+    // Check access: owner, admin, or has knowledge base access
+    if !service.can_read(&file, &user).await? {
         return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "detail": "File not found"
         })));
@@ -275,8 +316,8 @@ async fn get_file_data_content(
 
     let mut file = file.unwrap();
 
-    // Check access
-    if file.user_id != user.id && user.role != "admin" {
+    // Check access: owner, admin, or has knowledge base access
+    if !service.can_read(&file, &user).await? {
         return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "detail": "File not found"
         })));
@@ -369,8 +410,8 @@ async fn get_file_content(
 
     let file = file.unwrap();
 
-    // Check access
-    if file.user_id != user.id && user.role != "admin" {
+    // Check access: owner, admin, or has knowledge base access
+    if !service.can_read(&file, &user).await? {
         return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "detail": "File not found"
         })));
@@ -464,6 +505,7 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
                 "/{id}/process/status",
                 web::get().to(get_file_process_status),
             )
+            .route("/{id}/ingest-status", web::get().to(get_file_ingest_status))
             .route("/{id}/data/content", web::get().to(get_file_data_content))
             .route(
                 "/{id}/data/content/update",
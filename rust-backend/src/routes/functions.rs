@@ -223,7 +223,7 @@ async fn load_function_from_url(
     };
 
     // Fetch content from URL
-    let client = reqwest::Client::builder()
+    let client = crate::utils::http::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()
         .map_err(|e| AppError::BadRequest(format!("Failed to create HTTP client: {}", e)))?;
@@ -364,8 +364,7 @@ async fn create_new_function(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     // Validate ID contains only alphanumeric and underscores
     if !form.id.chars().all(|c| c.is_alphanumeric() || c == '_') {
@@ -1,9 +1,10 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::AppError,
     middleware::{AuthMiddleware, AuthUser},
+    utils::feature_flags::FeatureFlags,
     AppState,
 };
 
@@ -90,12 +91,15 @@ struct GenerateImageForm {
 /// POST /generations - Generate image
 async fn generate_image(
     state: web::Data<AppState>,
-    _auth_user: AuthUser,
+    auth_user: AuthUser,
+    req: HttpRequest,
     _form_data: web::Json<GenerateImageForm>,
 ) -> Result<HttpResponse, AppError> {
     let config = state.config.read().unwrap();
 
-    if !config.enable_image_generation {
+    if !FeatureFlags::from_request(&config, &auth_user.user, &req)
+        .enabled("enable_image_generation", config.enable_image_generation)
+    {
         return Err(AppError::Forbidden(
             "Image generation is not enabled".to_string(),
         ));
@@ -115,11 +119,14 @@ async fn generate_image(
 /// GET /models - Get available image generation models
 async fn get_models(
     state: web::Data<AppState>,
-    _auth_user: AuthUser,
+    auth_user: AuthUser,
+    req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
     let config = state.config.read().unwrap();
 
-    if !config.enable_image_generation {
+    if !FeatureFlags::from_request(&config, &auth_user.user, &req)
+        .enabled("enable_image_generation", config.enable_image_generation)
+    {
         return Err(AppError::Forbidden(
             "Image generation is not enabled".to_string(),
         ));
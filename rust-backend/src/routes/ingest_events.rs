@@ -0,0 +1,220 @@
+/// Live file ingestion progress over Socket.IO
+///
+/// While a file is embedded into a knowledge base, `file.ingest.progress` events carry
+/// the percentage and chunk reached, and `file.ingest.complete` reports how it ended.
+/// Events go only to the file owner's sessions. With WebSocket support disabled nothing
+/// is sent and clients poll GET /api/v1/files/{id}/ingest-status instead.
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicI16, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use crate::error::AppResult;
+use crate::routes::knowledge_vector::{Indexed, IngestUpdate};
+use crate::socketio::EventHandler;
+use crate::AppState;
+
+pub const PROGRESS_EVENT: &str = "file.ingest.progress";
+pub const COMPLETE_EVENT: &str = "file.ingest.complete";
+
+/// Where ingestion events are sent, if anywhere
+#[derive(Clone, Default)]
+pub struct IngestEvents {
+    handler: Option<Arc<EventHandler>>,
+}
+
+impl IngestEvents {
+    pub fn from_state(state: &AppState) -> Self {
+        Self {
+            handler: state
+                .socket_state
+                .as_ref()
+                .map(|socket| socket.native_handler.clone()),
+        }
+    }
+
+    /// Report the ingest of `file_id` into `knowledge_id` to `owner_id`
+    pub fn start(&self, owner_id: &str, file_id: &str, knowledge_id: &str) -> IngestReporter {
+        // Events are forwarded in order by one task, as progress is reported synchronously
+        let sender = self.handler.clone().map(|handler| {
+            let (tx, mut rx) = mpsc::unbounded_channel::<(&'static str, Value)>();
+            let owner_id = owner_id.to_string();
+            tokio::spawn(async move {
+                while let Some((event, data)) = rx.recv().await {
+                    if let Err(e) = handler.emit_to_user(&owner_id, event, data).await {
+                        tracing::debug!("Failed to emit {}: {}", event, e);
+                    }
+                }
+            });
+            tx
+        });
+
+        IngestReporter {
+            sender,
+            file_id: file_id.to_string(),
+            knowledge_id: knowledge_id.to_string(),
+            last_percent: AtomicI16::new(-1),
+        }
+    }
+}
+
+/// Sends one file's ingestion events
+pub struct IngestReporter {
+    sender: Option<mpsc::UnboundedSender<(&'static str, Value)>>,
+    file_id: String,
+    knowledge_id: String,
+    last_percent: AtomicI16,
+}
+
+impl IngestReporter {
+    /// Emit progress, at most once per percent
+    pub fn progress(&self, update: IngestUpdate) {
+        let percent = i16::from(update.percent);
+        if self.last_percent.swap(percent, Ordering::Relaxed) == percent {
+            return;
+        }
+        self.send(
+            PROGRESS_EVENT,
+            json!({
+                "file_id": self.file_id,
+                "knowledge_id": self.knowledge_id,
+                "percent": update.percent,
+                "chunk_index": update.chunk_index,
+                "chunks_total": update.chunks_total,
+            }),
+        );
+    }
+
+    /// Emit how the ingest ended
+    pub fn complete(&self, result: &AppResult<Indexed>) {
+        let data = match result {
+            Ok(indexed) => json!({
+                "file_id": self.file_id,
+                "knowledge_id": self.knowledge_id,
+                "status": "completed",
+                "chunks": indexed.chunks,
+                "truncated": indexed.truncated,
+            }),
+            Err(e) => json!({
+                "file_id": self.file_id,
+                "knowledge_id": self.knowledge_id,
+                "status": "failed",
+                "error": e.to_string(),
+            }),
+        };
+        self.send(COMPLETE_EVENT, data);
+    }
+
+    fn send(&self, event: &'static str, data: Value) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send((event, data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use crate::socketio::{
+        PresenceManager, RateLimitConfig, RateLimiter, RecoveryConfig, RecoveryManager,
+        SocketIOManager, SocketIOMetrics, YDocManager,
+    };
+
+    async fn connect(
+        handler: &EventHandler,
+        sid: &str,
+        user_id: &str,
+    ) -> mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        handler.manager().create_session(sid).await;
+        handler.register_connection(sid, tx).await;
+        handler
+            .manager()
+            .set_session_user(sid, json!({ "id": user_id }))
+            .await
+            .unwrap();
+        rx
+    }
+
+    /// Names and payloads of the Socket.IO events sent so far
+    fn received(rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<(String, Value)> {
+        let mut events = Vec::new();
+        while let Ok(packet) = rx.try_recv() {
+            // Engine.IO message ("4") carrying a Socket.IO event ("2")
+            let Some(payload) = packet.strip_prefix("42") else {
+                continue;
+            };
+            let payload: Value = serde_json::from_str(payload).unwrap();
+            events.push((payload[0].as_str().unwrap().to_string(), payload[1].clone()));
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_progress_events_reach_only_the_owner() {
+        let handler = Arc::new(EventHandler::new(
+            SocketIOManager::new(),
+            String::new(),
+            YDocManager::new(None),
+            None,
+            SocketIOMetrics::new(),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(PresenceManager::default()),
+            Arc::new(RecoveryManager::new(None, RecoveryConfig::default())),
+            Database::new_lazy_for_tests(),
+        ));
+        let mut owner = connect(&handler, "sid-owner", "owner").await;
+        let mut other = connect(&handler, "sid-other", "other").await;
+        let events = IngestEvents {
+            handler: Some(handler.clone()),
+        };
+
+        // Simulated ingest of 4 chunks, with a repeated percentage
+        let reporter = events.start("owner", "file-1", "kb-1");
+        for chunk_index in [1, 2, 2, 3, 4] {
+            reporter.progress(IngestUpdate {
+                percent: (chunk_index * 25) as u8,
+                chunk_index,
+                chunks_total: 4,
+            });
+        }
+        reporter.complete(&Ok(Indexed {
+            chunks: 4,
+            truncated: false,
+        }));
+        drop(reporter);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let events = received(&mut owner);
+        let progress: Vec<_> = events
+            .iter()
+            .filter(|(name, _)| name == PROGRESS_EVENT)
+            .map(|(_, data)| {
+                (
+                    data["percent"].as_u64().unwrap(),
+                    data["chunk_index"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(progress, vec![(25, 1), (50, 2), (75, 3), (100, 4)]);
+        let (name, done) = events.last().unwrap();
+        assert_eq!(name, COMPLETE_EVENT);
+        assert_eq!(done["status"], "completed");
+        assert_eq!(done["chunks"], 4);
+        assert_eq!(done["file_id"], "file-1");
+
+        assert!(received(&mut other).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_events_send_nothing() {
+        let reporter = IngestEvents::default().start("owner", "file-1", "kb-1");
+        reporter.progress(IngestUpdate {
+            percent: 50,
+            chunk_index: 1,
+            chunks_total: 2,
+        });
+        assert!(reporter.sender.is_none());
+    }
+}
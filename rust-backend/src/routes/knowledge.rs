@@ -1,19 +1,30 @@
 use actix_web::{web, HttpResponse};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::collections::{HashMap, HashSet};
 use tracing as log;
 use uuid::Uuid;
 
+use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::middleware::{AuthMiddleware, AuthUser};
-use crate::models::knowledge::{KnowledgeFilesResponse, KnowledgeResponse, KnowledgeUserResponse};
+use crate::middleware::{AuthMiddleware, AuthUser, ConcurrencyLimit};
+use crate::models::knowledge::{
+    Knowledge, KnowledgeFilesResponse, KnowledgeResponse, KnowledgeUserResponse,
+};
+use crate::models::User;
+use crate::retrieval::search::{self, ChunkTextStore, SearchOptions};
+use crate::retrieval::{ChunkingConfig, MetadataFilter};
+use crate::routes::ingest_events::IngestEvents;
 use crate::routes::knowledge_vector;
+use crate::services::audit::{self, AuditService};
 use crate::services::file::FileService;
 use crate::services::group::GroupService;
 use crate::services::knowledge::KnowledgeService;
+use crate::services::rag_chunk::RagChunkService;
 use crate::services::user::UserService;
+use crate::utils::dry_run::DryRunQuery;
 use crate::utils::misc::{has_access, has_permission};
+use crate::utils::webhook::{self, WebhookPayload};
 use crate::AppState;
 
 #[derive(Debug, Deserialize)]
@@ -26,11 +37,80 @@ pub struct KnowledgeForm {
     pub access_control: Option<serde_json::Value>,
 }
 
+/// What a copy of a knowledge base gets instead of the source's settings
+#[derive(Debug, Default, Deserialize)]
+pub struct KnowledgeCopyForm {
+    /// Defaults to the source's name with " (Copy)" appended
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub access_control: Option<serde_json::Value>,
+    /// Give the copy its own copies of the files instead of sharing the source's
+    #[serde(default)]
+    pub copy_files: bool,
+    /// Chunking for the copy's collection, in place of the source's
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+    #[serde(default)]
+    pub chunk_overlap: Option<usize>,
+}
+
+impl KnowledgeCopyForm {
+    /// The source's `chunking` settings with the form's in their place, or `None` when
+    /// neither sets any
+    fn chunking(&self, source: &Knowledge) -> AppResult<Option<serde_json::Value>> {
+        let mut chunking = source
+            .data
+            .as_ref()
+            .and_then(|data| data.get("chunking"))
+            .filter(|chunking| chunking.is_object())
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        if let Some(chunk_size) = self.chunk_size {
+            chunking["chunk_size"] = json!(chunk_size);
+        }
+        if let Some(chunk_overlap) = self.chunk_overlap {
+            chunking["chunk_overlap"] = json!(chunk_overlap);
+        }
+        if chunking.as_object().is_some_and(|c| c.is_empty()) {
+            return Ok(None);
+        }
+
+        let config = ChunkingConfig::for_knowledge(Some(&json!({ "chunking": chunking })));
+        if config.chunk_size == 0 || config.chunk_overlap >= config.chunk_size {
+            return Err(AppError::BadRequest(format!(
+                "Invalid chunking: chunk_overlap ({}) must be below chunk_size ({})",
+                config.chunk_overlap, config.chunk_size
+            )));
+        }
+        Ok(Some(chunking))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct KnowledgeFileIdForm {
     pub file_id: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct KnowledgeIdsForm {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KnowledgeQueryForm {
+    pub query: String,
+    #[serde(default)]
+    pub k: Option<usize>,
+    #[serde(default)]
+    pub score_threshold: Option<f32>,
+    /// Conditions on chunk payload fields, see [`MetadataFilter`]
+    #[serde(default)]
+    pub filter: Option<serde_json::Value>,
+}
+
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::resource("")
@@ -54,14 +134,25 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
     )
     .service(
         web::resource("/reindex")
+            .wrap(ConcurrencyLimit::Embeddings)
             .wrap(AuthMiddleware)
             .route(web::post().to(reindex_all_knowledge)),
     )
+    .service(
+        web::resource("/batch/delete")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(delete_knowledge_batch)),
+    )
     .service(
         web::resource("/{id}")
             .wrap(AuthMiddleware)
             .route(web::get().to(get_knowledge_by_id)),
     )
+    .service(
+        web::resource("/{id}/stats")
+            .wrap(AuthMiddleware)
+            .route(web::get().to(get_knowledge_stats)),
+    )
     .service(
         web::resource("/{id}/update")
             .wrap(AuthMiddleware)
@@ -72,13 +163,20 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::delete().to(delete_knowledge_by_id)),
     )
+    .service(
+        web::resource("/{id}/copy")
+            .wrap(AuthMiddleware)
+            .route(web::post().to(copy_knowledge_by_id)),
+    )
     .service(
         web::resource("/{id}/file/add")
+            .wrap(ConcurrencyLimit::Embeddings)
             .wrap(AuthMiddleware)
             .route(web::post().to(add_file_to_knowledge)),
     )
     .service(
         web::resource("/{id}/file/update")
+            .wrap(ConcurrencyLimit::Embeddings)
             .wrap(AuthMiddleware)
             .route(web::post().to(update_file_in_knowledge)),
     )
@@ -92,13 +190,227 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .wrap(AuthMiddleware)
             .route(web::post().to(reset_knowledge)),
     )
+    .service(
+        web::resource("/{id}/reindex")
+            .wrap(ConcurrencyLimit::Embeddings)
+            .wrap(AuthMiddleware)
+            .route(web::post().to(reindex_knowledge)),
+    )
     .service(
         web::resource("/{id}/files/batch/add")
+            .wrap(ConcurrencyLimit::Embeddings)
             .wrap(AuthMiddleware)
             .route(web::post().to(add_files_batch)),
+    )
+    .service(
+        web::resource("/{id}/query")
+            .wrap(ConcurrencyLimit::Embeddings)
+            .wrap(AuthMiddleware)
+            .route(web::post().to(query_knowledge)),
+    );
+}
+
+/// Access policy for a single knowledge base
+///
+/// Owners and admins have full access. Otherwise, a user without read access gets
+/// 404 as if the knowledge base did not exist, so its existence isn't revealed; a
+/// user who can read it but lacks the requested `write` access gets 403.
+fn knowledge_access_policy(
+    knowledge: &Knowledge,
+    user_id: &str,
+    is_admin: bool,
+    user_group_ids: &HashSet<String>,
+    access_type: &str,
+) -> AppResult<()> {
+    if is_admin || knowledge.user_id == user_id {
+        return Ok(());
+    }
+
+    if !has_access(user_id, "read", &knowledge.access_control, user_group_ids) {
+        return Err(AppError::NotFound("Knowledge not found".to_string()));
+    }
+
+    if access_type != "read"
+        && !has_access(
+            user_id,
+            access_type,
+            &knowledge.access_control,
+            user_group_ids,
+        )
+    {
+        return Err(AppError::Forbidden("Access prohibited".to_string()));
+    }
+
+    Ok(())
+}
+
+/// File ids recorded in a knowledge base's data
+fn knowledge_file_ids(knowledge: &Knowledge) -> Vec<String> {
+    knowledge
+        .data
+        .as_ref()
+        .and_then(|data| data.get("file_ids"))
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reject `data.file_ids` the caller can't read, other than those already in `current`
+///
+/// Listing a file shares it with the knowledge base's readers. Files already there stay
+/// editable by anyone with write access.
+async fn check_listed_files_readable(
+    file_service: &FileService<'_>,
+    user: &User,
+    data: Option<&serde_json::Value>,
+    current: &[String],
+) -> AppResult<()> {
+    let listed = data
+        .and_then(|data| data.get("file_ids"))
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|v| v.as_str());
+    for file_id in listed {
+        if current.iter().any(|id| id == file_id) {
+            continue;
+        }
+        if file_service
+            .get_readable_file(file_id, user)
+            .await?
+            .is_none()
+        {
+            return Err(AppError::NotFound(format!("File {} not found", file_id)));
+        }
+    }
+    Ok(())
+}
+
+/// Tell webhook subscribers about a knowledge base change and record it in the audit
+/// log (never blocks the handler)
+fn notify_knowledge_change(
+    state: &web::Data<AppState>,
+    event_type: &str,
+    knowledge_id: &str,
+    actor_id: &str,
+    file_ids: &[String],
+) {
+    let payload = WebhookPayload::knowledge_event(event_type, knowledge_id, actor_id, file_ids);
+    webhook::dispatch_event(&state.config.read().unwrap(), payload);
+
+    audit::spawn_record(
+        state.clone(),
+        actor_id.to_string(),
+        event_type.to_string(),
+        "knowledge",
+        knowledge_id.to_string(),
+        Some(json!({ "file_ids": file_ids })),
     );
 }
 
+/// Access control for a new knowledge base whose form didn't set one
+///
+/// Users who may share get DEFAULT_KNOWLEDGE_ACCESS, or a public knowledge base when it's
+/// unset; everyone else gets a private one.
+fn default_access_control(
+    can_share: bool,
+    default_access: Option<&serde_json::Value>,
+) -> Option<serde_json::Value> {
+    if !can_share {
+        return Some(json!({}));
+    }
+    default_access.cloned()
+}
+
+/// Creating knowledge bases needs the workspace.knowledge permission
+fn check_workspace_permission(state: &AppState, auth_user: &AuthUser) -> AppResult<()> {
+    if auth_user.user.role == "admin" {
+        return Ok(());
+    }
+    let config = state.config.read().unwrap();
+    if !has_permission(
+        &auth_user.user.id,
+        "workspace.knowledge",
+        &config.user_permissions,
+    ) {
+        return Err(AppError::Unauthorized("Unauthorized".to_string()));
+    }
+    Ok(())
+}
+
+/// [`default_access_control`] for a knowledge base the authenticated user creates
+fn new_access_control(state: &AppState, auth_user: &AuthUser) -> Option<serde_json::Value> {
+    let config = state.config.read().unwrap();
+    let can_share = auth_user.user.role == "admin"
+        || has_permission(
+            &auth_user.user.id,
+            "sharing.public_knowledge",
+            &config.user_permissions,
+        );
+    default_access_control(can_share, config.default_knowledge_access.as_ref())
+}
+
+/// Apply [`knowledge_access_policy`] for the authenticated user
+async fn check_knowledge_access(
+    state: &AppState,
+    auth_user: &AuthUser,
+    knowledge: &Knowledge,
+    access_type: &str,
+) -> AppResult<()> {
+    let audit_bypass = state.config.read().unwrap().audit_admin_bypass;
+    authorize_knowledge(
+        &state.db,
+        &auth_user.user,
+        knowledge,
+        access_type,
+        audit_bypass,
+    )
+    .await
+}
+
+/// With `audit_bypass`, an admin let in only by their role is recorded in the audit log
+async fn authorize_knowledge(
+    db: &Database,
+    user: &User,
+    knowledge: &Knowledge,
+    access_type: &str,
+    audit_bypass: bool,
+) -> AppResult<()> {
+    let is_admin = user.role == "admin";
+    if knowledge.user_id == user.id || (is_admin && !audit_bypass) {
+        return Ok(());
+    }
+
+    let group_service = GroupService::new(db);
+    let user_group_ids: HashSet<String> = group_service
+        .get_groups_by_member_id(&user.id)
+        .await?
+        .into_iter()
+        .map(|g| g.id)
+        .collect();
+
+    let access = knowledge_access_policy(knowledge, &user.id, false, &user_group_ids, access_type);
+    if !is_admin || access.is_ok() {
+        return access;
+    }
+
+    if let Err(e) = AuditService::new(db)
+        .record_admin_bypass(&user.id, "knowledge", &knowledge.id, access_type)
+        .await
+    {
+        log::warn!(
+            "Failed to audit admin bypass of knowledge {}: {}",
+            knowledge.id,
+            e
+        );
+    }
+    Ok(())
+}
+
 // GET / - Get knowledge bases with read access
 async fn get_knowledge_bases(
     state: web::Data<AppState>,
@@ -355,35 +667,21 @@ async fn create_knowledge(
     auth_user: AuthUser,
     form: web::Json<KnowledgeForm>,
 ) -> AppResult<HttpResponse> {
-    // Check workspace.knowledge permission
-    if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
-        let user_permissions = config.user_permissions.clone();
-        drop(config);
-
-        if !has_permission(&auth_user.user.id, "workspace.knowledge", &user_permissions) {
-            return Err(AppError::Unauthorized("Unauthorized".to_string()));
-        }
-    }
-
-    // Check if user can share publicly
-    let mut access_control = form.access_control.clone();
-    if auth_user.user.role != "admin" && access_control.is_none() {
-        let config = state.config.read().unwrap();
-        let user_permissions = config.user_permissions.clone();
-        drop(config);
-
-        if !has_permission(
-            &auth_user.user.id,
-            "sharing.public_knowledge",
-            &user_permissions,
-        ) {
-            access_control = Some(json!({}));
-        }
-    }
+    check_workspace_permission(&state, &auth_user)?;
+    let access_control = form
+        .access_control
+        .clone()
+        .or_else(|| new_access_control(&state, &auth_user));
 
     let knowledge_service = KnowledgeService::new(&state.db);
     let knowledge_id = Uuid::new_v4().to_string();
+    check_listed_files_readable(
+        &FileService::new(&state.db),
+        &auth_user.user,
+        form.data.as_ref(),
+        &[],
+    )
+    .await?;
 
     let mut data = form.data.clone().unwrap_or_else(|| json!({}));
     if data.get("file_ids").is_none() {
@@ -401,40 +699,171 @@ async fn create_knowledge(
         )
         .await?;
 
+    notify_knowledge_change(
+        &state,
+        webhook::KNOWLEDGE_CREATED,
+        &knowledge.id,
+        &auth_user.user.id,
+        &knowledge_file_ids(&knowledge),
+    );
+
     Ok(HttpResponse::Ok().json(KnowledgeResponse::from(knowledge)))
 }
 
-// GET /{id} - Get knowledge by ID
-async fn get_knowledge_by_id(
+// POST /{id}/copy - Copy a knowledge base for the caller
+//
+// The copy shares the source's files unless `copy_files` is set, and re-ingests them
+// into its own collection with the form's chunking settings.
+async fn copy_knowledge_by_id(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     knowledge_id: web::Path<String>,
+    form: web::Json<KnowledgeCopyForm>,
 ) -> AppResult<HttpResponse> {
+    check_workspace_permission(&state, &auth_user)?;
     let knowledge_service = KnowledgeService::new(&state.db);
     let file_service = FileService::new(&state.db);
 
-    let knowledge = knowledge_service
+    let source = knowledge_service
         .get_knowledge_by_id(&knowledge_id)
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
+    check_knowledge_access(&state, &auth_user, &source, "read").await?;
+
+    let access_control = form
+        .access_control
+        .clone()
+        .or_else(|| new_access_control(&state, &auth_user));
+    let (knowledge, file_ids) = copy_knowledge(
+        &state.db,
+        &source,
+        &auth_user.user.id,
+        &form,
+        access_control,
+    )
+    .await?;
+    log::info!(
+        "Copying knowledge base {} to {} with {} file(s)",
+        source.id,
+        knowledge.id,
+        file_ids.len()
+    );
 
-    // Check access: owner, admin, or has read access
-    if auth_user.user.role != "admin" && knowledge.user_id != auth_user.user.id {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "copy")?;
+    let results = ingest_files(&state, &file_service, &file_ids, &knowledge.id).await;
+    let (updated, _) = add_ingested_files(&knowledge_service, &knowledge, &results).await?;
+
+    let file_ids = knowledge_file_ids(&updated);
+    notify_knowledge_change(
+        &state,
+        webhook::KNOWLEDGE_CREATED,
+        &updated.id,
+        &auth_user.user.id,
+        &file_ids,
+    );
 
-        if !has_access(
-            &auth_user.user.id,
-            "read",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Unauthorized("Not found".to_string()));
+    let files = file_service.get_file_metadatas_by_ids(&file_ids).await?;
+    Ok(HttpResponse::Ok().json(KnowledgeBatchResponse {
+        knowledge: KnowledgeFilesResponse::from_knowledge_and_files(updated, files),
+        results,
+    }))
+}
+
+/// Create `owner_id`'s copy of `source`, returning it and the files to ingest into it
+///
+/// The copy lists no files until they're ingested into its own collection, and carries
+/// its chunking settings from the start so ingestion uses them.
+async fn copy_knowledge(
+    db: &Database,
+    source: &Knowledge,
+    owner_id: &str,
+    form: &KnowledgeCopyForm,
+    access_control: Option<serde_json::Value>,
+) -> AppResult<(Knowledge, Vec<String>)> {
+    let mut data = source
+        .data
+        .clone()
+        .filter(|data| data.is_object())
+        .unwrap_or_else(|| json!({}));
+    data["file_ids"] = json!([]);
+    if let Some(data) = data.as_object_mut() {
+        data.remove("chunking");
+    }
+    if let Some(chunking) = form.chunking(source)? {
+        data["chunking"] = chunking;
+    }
+
+    let name = form
+        .name
+        .clone()
+        .unwrap_or_else(|| format!("{} (Copy)", source.name));
+    let description = form
+        .description
+        .as_deref()
+        .or(source.description.as_deref());
+    let knowledge = KnowledgeService::new(db)
+        .create_knowledge_with_access_control(
+            &Uuid::new_v4().to_string(),
+            owner_id,
+            &name,
+            description,
+            Some(data),
+            access_control,
+        )
+        .await?;
+
+    let source_file_ids = knowledge_file_ids(source);
+    if !form.copy_files {
+        return Ok((knowledge, source_file_ids));
+    }
+
+    let file_service = FileService::new(db);
+    let mut file_ids = Vec::with_capacity(source_file_ids.len());
+    for file_id in source_file_ids {
+        let new_id = Uuid::new_v4().to_string();
+        match file_service.copy_file(&file_id, &new_id, owner_id).await {
+            Ok(_) => {}
+            Err(AppError::NotFound(_)) => {
+                log::warn!(
+                    "File {} of knowledge {} no longer exists, not copied",
+                    file_id,
+                    source.id
+                );
+                continue;
+            }
+            Err(e) => return Err(e),
         }
+        copy_upload(&file_id, &new_id).await;
+        file_ids.push(new_id);
     }
+    Ok((knowledge, file_ids))
+}
+
+/// Copy a file's stored upload, if it has one
+async fn copy_upload(file_id: &str, new_id: &str) {
+    let dir = std::path::Path::new(crate::routes::files::UPLOAD_DIR);
+    match tokio::fs::copy(dir.join(file_id), dir.join(new_id)).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::warn!("Failed to copy upload of file {}: {}", file_id, e),
+    }
+}
+
+// GET /{id} - Get knowledge by ID
+async fn get_knowledge_by_id(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    knowledge_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let knowledge_service = KnowledgeService::new(&state.db);
+    let file_service = FileService::new(&state.db);
+
+    let knowledge = knowledge_service
+        .get_knowledge_by_id(&knowledge_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
+
+    check_knowledge_access(&state, &auth_user, &knowledge, "read").await?;
 
     // Get files
     let mut files = Vec::new();
@@ -463,6 +892,103 @@ async fn get_knowledge_by_id(
     Ok(HttpResponse::Ok().json(response))
 }
 
+// GET /{id}/stats - File count, size and index coverage of a knowledge base
+async fn get_knowledge_stats(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    knowledge_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let knowledge_service = KnowledgeService::new(&state.db);
+
+    let knowledge = knowledge_service
+        .get_knowledge_by_id(&knowledge_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
+
+    check_knowledge_access(&state, &auth_user, &knowledge, "read").await?;
+
+    let mut stats = knowledge_service
+        .get_stats(&knowledge.id, &knowledge_file_ids(&knowledge))
+        .await?;
+    if let Some((vector_db, embedding_provider)) =
+        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
+    {
+        let chunk_count = knowledge_vector::count_knowledge_vectors(
+            &vector_db,
+            &embedding_provider,
+            &knowledge.id,
+        )
+        .await?;
+        stats.chunk_count = Some(chunk_count);
+    }
+
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+// POST /{id}/query - Semantic search over a knowledge base
+async fn query_knowledge(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    knowledge_id: web::Path<String>,
+    form_data: web::Json<KnowledgeQueryForm>,
+) -> AppResult<HttpResponse> {
+    let knowledge_service = KnowledgeService::new(&state.db);
+
+    let knowledge = knowledge_service
+        .get_knowledge_by_id(&knowledge_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
+
+    check_knowledge_access(&state, &auth_user, &knowledge, "read").await?;
+
+    let Some((vector_db, embedding_provider)) =
+        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
+    else {
+        knowledge_vector::log_rag_disabled("query_knowledge");
+        return Err(AppError::BadRequest("RAG is not enabled".to_string()));
+    };
+
+    let (mut options, chunk_store) = {
+        let config = state.config.read().unwrap();
+        let chunk_store = config
+            .enable_rag_chunk_store
+            .then(|| RagChunkService::new(&state.db));
+        (SearchOptions::from_config(&config), chunk_store)
+    };
+    if let Some(k) = form_data.k {
+        options.k = k;
+    }
+    if let Some(score_threshold) = form_data.score_threshold {
+        options.score_threshold = score_threshold;
+    }
+    if let Some(filter) = &form_data.filter {
+        options.filter = Some(
+            MetadataFilter::parse(filter)
+                .map_err(|e| AppError::BadRequest(format!("Invalid filter: {}", e)))?,
+        );
+    }
+
+    let results = search::search_collection(
+        &vector_db,
+        &embedding_provider,
+        &state.http_client,
+        &knowledge.id,
+        &form_data.query,
+        &options,
+        chunk_store
+            .as_ref()
+            .map(|store| store as &dyn ChunkTextStore),
+    )
+    .await
+    .map_err(|e| AppError::Internal(format!("Knowledge query failed: {}", e)))?;
+
+    let reranked = results.iter().any(|chunk| chunk.rerank_score.is_some());
+    Ok(HttpResponse::Ok().json(json!({
+        "results": results,
+        "reranked": reranked,
+    })))
+}
+
 // POST /{id}/update - Update knowledge
 async fn update_knowledge(
     state: web::Data<AppState>,
@@ -478,23 +1004,14 @@ async fn update_knowledge(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    check_listed_files_readable(
+        &file_service,
+        &auth_user.user,
+        form.data.as_ref(),
+        &knowledge_file_ids(&knowledge),
+    )
+    .await?;
 
     // Check if user can share publicly
     let mut access_control = form.access_control.clone();
@@ -563,91 +1080,29 @@ async fn delete_knowledge_by_id(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
 
     log::info!(
         "Deleting knowledge base: {} (name: {})",
         knowledge_id.as_str(),
         knowledge.name
     );
+    let batch_size = state.config.read().unwrap().knowledge_delete_batch_size;
 
-    // Get all models and update those that reference this knowledge base
-    if let Ok(models) = model_service.get_all_models().await {
-        log::info!(
-            "Found {} models to check for knowledge base {}",
-            models.len(),
-            knowledge_id.as_str()
-        );
-
-        for model in models {
-            if let Some(meta) = &model.meta {
-                if let Some(knowledge_list) = meta.get("knowledge").and_then(|k| k.as_array()) {
-                    // Filter out the deleted knowledge base
-                    let updated_knowledge: Vec<serde_json::Value> = knowledge_list
-                        .iter()
-                        .filter(|k| {
-                            k.get("id")
-                                .and_then(|id| id.as_str())
-                                .map(|id| id != knowledge_id.as_str())
-                                .unwrap_or(true)
-                        })
-                        .cloned()
-                        .collect();
-
-                    // If the knowledge list changed, update the model
-                    if updated_knowledge.len() != knowledge_list.len() {
-                        log::info!(
-                            "Updating model {} to remove knowledge base {}",
-                            model.id,
-                            knowledge_id.as_str()
-                        );
-
-                        let mut updated_meta = meta.clone();
-                        updated_meta["knowledge"] = json!(updated_knowledge);
-
-                        let model_form = crate::models::model::ModelForm {
-                            id: model.id.clone(),
-                            base_model_id: model.base_model_id.clone(),
-                            name: model.name.clone(),
-                            params: model.params.clone(),
-                            meta: updated_meta,
-                            access_control: model.access_control.clone(),
-                        };
-
-                        if let Err(e) = model_service
-                            .update_model_by_id(&model.id, model_form)
-                            .await
-                        {
-                            log::error!("Failed to update model {}: {}", model.id, e);
-                        }
-                    }
-                }
-            }
-        }
-    }
+    // Remove this knowledge base from any models that reference it
+    let knowledge_ids: HashSet<String> = [knowledge_id.to_string()].into_iter().collect();
+    remove_knowledge_from_models(&model_service, &knowledge_ids, batch_size).await;
 
     // Delete vector collection if RAG is enabled
-    if let Some((vector_db, _)) =
+    if let Some((vector_db, embedding_provider)) =
         knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
     {
-        if let Err(e) =
-            knowledge_vector::delete_knowledge_collection(&vector_db, &knowledge_id).await
+        if let Err(e) = knowledge_vector::delete_knowledge_collection(
+            &vector_db,
+            &embedding_provider,
+            &knowledge_id,
+        )
+        .await
         {
             log::warn!(
                 "Failed to delete vector collection for knowledge {}: {}",
@@ -660,11 +1115,156 @@ async fn delete_knowledge_by_id(
         knowledge_vector::log_rag_disabled("delete collection");
     }
 
-    knowledge_service.delete_knowledge(&knowledge_id).await?;
+    knowledge_service
+        .delete_knowledge(&knowledge_id, batch_size)
+        .await?;
+
+    notify_knowledge_change(
+        &state,
+        webhook::KNOWLEDGE_DELETED,
+        &knowledge_id,
+        &auth_user.user.id,
+        &knowledge_file_ids(&knowledge),
+    );
 
     Ok(HttpResponse::Ok().json(true))
 }
 
+/// Remove references to the given knowledge bases from every model's `meta.knowledge`.
+///
+/// Scans all models once regardless of how many knowledge bases are being removed,
+/// KNOWLEDGE_DELETE_BATCH_SIZE models per transaction.
+async fn remove_knowledge_from_models(
+    model_service: &crate::services::model::ModelService<'_>,
+    knowledge_ids: &HashSet<String>,
+    batch_size: usize,
+) {
+    match model_service
+        .remove_knowledge_references(knowledge_ids, batch_size)
+        .await
+    {
+        Ok(updated) => log::info!(
+            "Removed {} knowledge base(s) from {} model(s)",
+            knowledge_ids.len(),
+            updated
+        ),
+        Err(e) => log::error!("Failed to remove knowledge from models: {}", e),
+    }
+}
+
+// POST /batch/delete - Delete multiple knowledge bases
+async fn delete_knowledge_batch(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    form: web::Json<KnowledgeIdsForm>,
+) -> AppResult<HttpResponse> {
+    let knowledge_service = KnowledgeService::new(&state.db);
+    let model_service = crate::services::model::ModelService::new(&state.db);
+
+    let user_group_ids: HashSet<String> = if auth_user.user.role != "admin" {
+        let group_service = GroupService::new(&state.db);
+        group_service
+            .get_groups_by_member_id(&auth_user.user.id)
+            .await?
+            .into_iter()
+            .map(|g| g.id)
+            .collect()
+    } else {
+        HashSet::new()
+    };
+
+    let mut results: Vec<serde_json::Value> = Vec::new();
+    let mut deletable_ids: Vec<String> = Vec::new();
+    let mut deletable_file_ids: HashMap<String, Vec<String>> = HashMap::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for id in form.ids.iter() {
+        if !seen.insert(id.clone()) {
+            continue;
+        }
+
+        let knowledge = match knowledge_service.get_knowledge_by_id(id).await? {
+            Some(knowledge) => knowledge,
+            None => {
+                results.push(json!({"id": id, "status": false, "detail": "Knowledge not found"}));
+                continue;
+            }
+        };
+
+        if let Err(e) = knowledge_access_policy(
+            &knowledge,
+            &auth_user.user.id,
+            auth_user.user.role == "admin",
+            &user_group_ids,
+            "write",
+        ) {
+            let detail = match e {
+                AppError::NotFound(detail) | AppError::Forbidden(detail) => detail,
+                e => e.to_string(),
+            };
+            results.push(json!({"id": id, "status": false, "detail": detail}));
+            continue;
+        }
+
+        deletable_file_ids.insert(id.clone(), knowledge_file_ids(&knowledge));
+        deletable_ids.push(id.clone());
+    }
+
+    let batch_size = state.config.read().unwrap().knowledge_delete_batch_size;
+    if !deletable_ids.is_empty() {
+        log::info!("Batch deleting {} knowledge bases", deletable_ids.len());
+
+        // Prune model references once across all models
+        let knowledge_ids: HashSet<String> = deletable_ids.iter().cloned().collect();
+        remove_knowledge_from_models(&model_service, &knowledge_ids, batch_size).await;
+    }
+
+    let rag_components =
+        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider);
+    if rag_components.is_none() && !deletable_ids.is_empty() {
+        knowledge_vector::log_rag_disabled("batch delete collections");
+    }
+
+    for id in deletable_ids {
+        // Delete vector collection if RAG is enabled
+        if let Some((vector_db, embedding_provider)) = &rag_components {
+            if let Err(e) =
+                knowledge_vector::delete_knowledge_collection(vector_db, embedding_provider, &id)
+                    .await
+            {
+                log::warn!(
+                    "Failed to delete vector collection for knowledge {}: {}",
+                    id,
+                    e
+                );
+                // Continue with deletion even if vector DB fails
+            }
+        }
+
+        match knowledge_service.delete_knowledge(&id, batch_size).await {
+            Ok(()) => {
+                notify_knowledge_change(
+                    &state,
+                    webhook::KNOWLEDGE_DELETED,
+                    &id,
+                    &auth_user.user.id,
+                    deletable_file_ids
+                        .get(&id)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default(),
+                );
+                results.push(json!({"id": id, "status": true}));
+            }
+            Err(e) => {
+                log::error!("Failed to delete knowledge {}: {}", id, e);
+                results.push(json!({"id": id, "status": false, "detail": e.to_string()}));
+            }
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
 // POST /{id}/file/add - Add file to knowledge
 async fn add_file_to_knowledge(
     state: web::Data<AppState>,
@@ -680,27 +1280,12 @@ async fn add_file_to_knowledge(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "file add")?;
 
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
-
-    // Check if file exists
+    // Only files the caller can read may be shared through the knowledge base
     let file = file_service
-        .get_file_by_id(&form.file_id)
+        .get_readable_file(&form.file_id, &auth_user.user)
         .await?
         .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
 
@@ -719,15 +1304,17 @@ async fn add_file_to_knowledge(
             &file_service,
             &form.file_id,
             &knowledge_id,
+            &IngestEvents::from_state(&state),
         )
         .await
         {
-            Ok(chunk_count) => {
+            Ok(indexed) => {
                 log::info!(
-                    "Successfully indexed {} chunks from file {} to knowledge {}",
-                    chunk_count,
+                    "Successfully indexed {} chunks from file {} to knowledge {} (truncated: {})",
+                    indexed.chunks,
                     form.file_id,
-                    knowledge_id
+                    knowledge_id,
+                    indexed.truncated
                 );
             }
             Err(e) => {
@@ -759,6 +1346,14 @@ async fn add_file_to_knowledge(
             .update_knowledge_data(&knowledge_id, data)
             .await?;
 
+        notify_knowledge_change(
+            &state,
+            webhook::KNOWLEDGE_FILE_ADDED,
+            &knowledge_id,
+            &auth_user.user.id,
+            std::slice::from_ref(&form.file_id),
+        );
+
         // Get files
         let mut files = Vec::new();
         if let Some(data) = &updated.data {
@@ -806,27 +1401,12 @@ async fn update_file_in_knowledge(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "file update")?;
 
-    // Check if file exists
+    // Only files the caller can read may be shared through the knowledge base
     let _file = file_service
-        .get_file_by_id(&form.file_id)
+        .get_readable_file(&form.file_id, &auth_user.user)
         .await?
         .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
 
@@ -835,8 +1415,13 @@ async fn update_file_in_knowledge(
         knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
     {
         // Delete old vectors for this file
-        if let Err(e) =
-            knowledge_vector::delete_file_vectors(&vector_db, &knowledge_id, &form.file_id).await
+        if let Err(e) = knowledge_vector::delete_file_vectors(
+            &vector_db,
+            &embedding_provider,
+            &knowledge_id,
+            &form.file_id,
+        )
+        .await
         {
             log::warn!(
                 "Failed to delete old vectors for file {} in knowledge {}: {}",
@@ -853,15 +1438,17 @@ async fn update_file_in_knowledge(
             &file_service,
             &form.file_id,
             &knowledge_id,
+            &IngestEvents::from_state(&state),
         )
         .await
         {
-            Ok(chunk_count) => {
+            Ok(indexed) => {
                 log::info!(
-                    "Successfully re-indexed {} chunks from file {} in knowledge {}",
-                    chunk_count,
+                    "Successfully re-indexed {} chunks from file {} in knowledge {} (truncated: {})",
+                    indexed.chunks,
                     form.file_id,
-                    knowledge_id
+                    knowledge_id,
+                    indexed.truncated
                 );
             }
             Err(e) => {
@@ -921,30 +1508,20 @@ async fn remove_file_from_knowledge(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "file removal")?;
 
     // Remove file vectors from knowledge collection if RAG is enabled
-    if let Some((vector_db, _)) =
+    if let Some((vector_db, embedding_provider)) =
         knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
     {
-        if let Err(e) =
-            knowledge_vector::delete_file_vectors(&vector_db, &knowledge_id, &form.file_id).await
+        if let Err(e) = knowledge_vector::delete_file_vectors(
+            &vector_db,
+            &embedding_provider,
+            &knowledge_id,
+            &form.file_id,
+        )
+        .await
         {
             log::debug!(
                 "Failed to delete vectors for file {} from knowledge {}: {} (likely bypassed embedding processing)",
@@ -962,12 +1539,16 @@ async fn remove_file_from_knowledge(
     let delete_file = query.delete_file.unwrap_or(true);
     if delete_file {
         // Delete file's standalone collection if it exists
-        if let Some((vector_db, _)) =
+        if let Some((vector_db, embedding_provider)) =
             knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
         {
             let file_collection = format!("file-{}", form.file_id);
-            if let Err(e) =
-                knowledge_vector::delete_knowledge_collection(&vector_db, &file_collection).await
+            if let Err(e) = knowledge_vector::delete_knowledge_collection(
+                &vector_db,
+                &embedding_provider,
+                &file_collection,
+            )
+            .await
             {
                 log::debug!(
                     "Failed to delete file collection {}: {}",
@@ -1001,6 +1582,14 @@ async fn remove_file_from_knowledge(
             .update_knowledge_data(&knowledge_id, data)
             .await?;
 
+        notify_knowledge_change(
+            &state,
+            webhook::KNOWLEDGE_FILE_REMOVED,
+            &knowledge_id,
+            &auth_user.user.id,
+            std::slice::from_ref(&form.file_id),
+        );
+
         // Get files
         let mut files = Vec::new();
         if let Some(data) = &updated.data {
@@ -1046,29 +1635,20 @@ async fn reset_knowledge(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "reset")?;
 
     // Reset vector collection if RAG is enabled
-    if let Some((vector_db, _)) =
+    if let Some((vector_db, embedding_provider)) =
         knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
     {
-        if let Err(e) = knowledge_vector::reset_knowledge_vectors(&vector_db, &knowledge_id).await {
+        if let Err(e) = knowledge_vector::reset_knowledge_vectors(
+            &vector_db,
+            &embedding_provider,
+            &knowledge_id,
+        )
+        .await
+        {
             log::debug!(
                 "Failed to reset vector collection for knowledge {}: {}",
                 knowledge_id,
@@ -1086,22 +1666,140 @@ async fn reset_knowledge(
         .update_knowledge_data(&knowledge_id, data)
         .await?;
 
+    let removed_file_ids = knowledge_file_ids(&knowledge);
+    if !removed_file_ids.is_empty() {
+        notify_knowledge_change(
+            &state,
+            webhook::KNOWLEDGE_FILE_REMOVED,
+            &knowledge_id,
+            &auth_user.user.id,
+            &removed_file_ids,
+        );
+    }
+
     Ok(HttpResponse::Ok().json(updated))
 }
 
-// POST /reindex - Reindex all knowledge files (admin only)
+// POST /{id}/reindex - Rebuild a knowledge base's vectors, keeping its files
+async fn reindex_knowledge(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    knowledge_id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let knowledge_service = KnowledgeService::new(&state.db);
+    let file_service = FileService::new(&state.db);
+
+    let knowledge = knowledge_service
+        .get_knowledge_by_id(&knowledge_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
+
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "reindex")?;
+
+    let Some((vector_db, embedding_provider)) =
+        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
+    else {
+        knowledge_vector::log_rag_disabled("reindex knowledge");
+        return Err(AppError::BadRequest("RAG is not enabled".to_string()));
+    };
+
+    let file_ids = knowledge
+        .data
+        .as_ref()
+        .and_then(|data| data.get("file_ids"))
+        .and_then(|v| v.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+
+    // Stale chunks would mix with the rebuilt ones, so don't re-ingest on failure
+    knowledge_vector::reset_knowledge_vectors(&vector_db, &embedding_provider, &knowledge.id)
+        .await?;
+
+    let events = IngestEvents::from_state(&state);
+    let mut results = Vec::with_capacity(file_ids.len());
+    let mut failed_files = 0;
+    for file_id in &file_ids {
+        match knowledge_vector::process_and_index_file(
+            &vector_db,
+            &embedding_provider,
+            &file_service,
+            file_id,
+            &knowledge.id,
+            &events,
+        )
+        .await
+        {
+            Ok(indexed) => {
+                results.push(json!({
+                    "file_id": file_id,
+                    "status": "success",
+                    "chunks": indexed.chunks,
+                    "truncated": indexed.truncated,
+                }));
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to reindex file {} for knowledge {}: {}",
+                    file_id,
+                    knowledge.id,
+                    e
+                );
+                failed_files += 1;
+                results.push(json!({
+                    "file_id": file_id,
+                    "status": "failed",
+                    "error": e.to_string(),
+                }));
+            }
+        }
+    }
+
+    log::info!(
+        "Reindexed knowledge {}: {} files successful, {} failed",
+        knowledge.id,
+        file_ids.len() - failed_files,
+        failed_files
+    );
+
+    notify_knowledge_change(
+        &state,
+        webhook::KNOWLEDGE_REINDEXED,
+        &knowledge.id,
+        &auth_user.user.id,
+        &file_ids,
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "id": knowledge.id,
+        "files": results,
+    })))
+}
+
+// POST /reindex - Re-embed changed knowledge files (admin only)
+//
+// With `?dry_run=true`, lists the files that would be re-embedded and the invalid
+// knowledge bases that would be deleted, judged from file metadata without touching
+// the vector DB.
 async fn reindex_all_knowledge(
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    query: web::Query<DryRunQuery>,
 ) -> AppResult<HttpResponse> {
     if auth_user.user.role != "admin" {
         return Err(AppError::Unauthorized("Unauthorized".to_string()));
     }
+    let dry_run = query.is_dry_run(false);
 
     let knowledge_service = KnowledgeService::new(&state.db);
     let file_service = FileService::new(&state.db);
 
     let knowledge_bases = knowledge_service.get_all_knowledge().await?;
+    let batch_size = state.config.read().unwrap().knowledge_delete_batch_size;
 
     log::info!(
         "Starting reindexing for {} knowledge bases",
@@ -1109,34 +1807,27 @@ async fn reindex_all_knowledge(
     );
 
     let mut deleted_knowledge_bases = Vec::new();
+    let mut stale_files = Vec::new();
+    let mut total_reindexed = 0;
+    let mut total_skipped = 0;
+    let mut total_failed = 0;
 
     for knowledge_base in knowledge_bases {
         // Robust error handling for missing or invalid data
-        if knowledge_base.data.is_none() {
-            log::warn!(
-                "Knowledge base {} has no data. Deleting.",
-                knowledge_base.id
-            );
-            if let Err(e) = knowledge_service.delete_knowledge(&knowledge_base.id).await {
-                log::error!(
-                    "Failed to delete invalid knowledge base {}: {}",
-                    knowledge_base.id,
-                    e
-                );
-            } else {
+        let Some(data) = knowledge_base.data.as_ref().filter(|data| data.is_object()) else {
+            if dry_run {
                 deleted_knowledge_bases.push(knowledge_base.id.clone());
+                continue;
             }
-            continue;
-        }
-
-        let data = knowledge_base.data.as_ref().unwrap();
-        if !data.is_object() {
             log::warn!(
-                "Knowledge base {} has invalid data: {:?}. Deleting.",
+                "Knowledge base {} has missing or invalid data: {:?}. Deleting.",
                 knowledge_base.id,
-                data
+                knowledge_base.data
             );
-            if let Err(e) = knowledge_service.delete_knowledge(&knowledge_base.id).await {
+            if let Err(e) = knowledge_service
+                .delete_knowledge(&knowledge_base.id, batch_size)
+                .await
+            {
                 log::error!(
                     "Failed to delete invalid knowledge base {}: {}",
                     knowledge_base.id,
@@ -1146,7 +1837,20 @@ async fn reindex_all_knowledge(
                 deleted_knowledge_bases.push(knowledge_base.id.clone());
             }
             continue;
-        }
+        };
+
+        // A base another job is writing is left to that job
+        let _ingest = if dry_run {
+            None
+        } else {
+            match state.ingest_locks.try_lock(&knowledge_base.id, "reindex") {
+                Ok(guard) => Some(guard),
+                Err(e) => {
+                    log::warn!("Skipping knowledge base {}: {}", knowledge_base.id, e);
+                    continue;
+                }
+            }
+        };
 
         // Get file IDs from knowledge base
         let file_ids = data
@@ -1159,46 +1863,74 @@ async fn reindex_all_knowledge(
             })
             .unwrap_or_default();
 
+        if dry_run {
+            let Some(embedding_provider) = &state.embedding_provider else {
+                continue;
+            };
+            let distance = state
+                .vector_db
+                .as_ref()
+                .map(|vector_db| vector_db.distance())
+                .unwrap_or_default();
+            let chunking = ChunkingConfig::for_knowledge(knowledge_base.data.as_ref());
+            for mut file in file_service.get_files_by_ids(&file_ids).await? {
+                file.parse_json_fields();
+                if knowledge_vector::is_index_current(
+                    &file,
+                    embedding_provider.as_ref(),
+                    &chunking,
+                    distance,
+                    &knowledge_base.id,
+                ) {
+                    total_skipped += 1;
+                } else {
+                    stale_files.push(json!({
+                        "knowledge_id": knowledge_base.id,
+                        "file_id": file.id,
+                    }));
+                }
+            }
+            continue;
+        }
+
         // Get files by IDs
         if let Ok(files) = file_service.get_files_by_ids(&file_ids).await {
-            // Delete existing vector collection and reindex if RAG is enabled
+            // Re-embed changed or missing files if RAG is enabled
             if let Some((vector_db, embedding_provider)) =
                 knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
             {
-                // Delete existing collection
-                if let Err(e) =
-                    knowledge_vector::reset_knowledge_vectors(&vector_db, &knowledge_base.id).await
-                {
-                    log::error!(
-                        "Error deleting collection {}: {}. Skipping this knowledge base.",
-                        knowledge_base.id,
-                        e
-                    );
-                    continue;
-                }
-
-                // Process each file
                 let mut indexed_files = 0;
+                let mut skipped_files = 0;
                 let mut failed_files = 0;
                 for file in files {
-                    match knowledge_vector::process_and_index_file(
+                    match knowledge_vector::sync_file_with_progress(
                         &vector_db,
                         &embedding_provider,
                         &file_service,
                         &file.id,
                         &knowledge_base.id,
+                        &|update| {
+                            log::info!(
+                                "Re-indexing file {} for knowledge {}: {}% embedded",
+                                file.id,
+                                knowledge_base.id,
+                                update.percent
+                            )
+                        },
                     )
                     .await
                     {
-                        Ok(chunk_count) => {
+                        Ok(knowledge_vector::SyncOutcome::Reindexed(indexed)) => {
                             log::info!(
-                                "Successfully re-indexed file {} ({} chunks) for knowledge {}",
+                                "Successfully re-indexed file {} ({} chunks{}) for knowledge {}",
                                 file.id,
-                                chunk_count,
+                                indexed.chunks,
+                                if indexed.truncated { ", truncated" } else { "" },
                                 knowledge_base.id
                             );
                             indexed_files += 1;
                         }
+                        Ok(knowledge_vector::SyncOutcome::Skipped) => skipped_files += 1,
                         Err(e) => {
                             log::error!(
                                 "Error processing file {} (ID: {}): {}",
@@ -1211,24 +1943,53 @@ async fn reindex_all_knowledge(
                     }
                 }
                 log::info!(
-                    "Reindexed knowledge {}: {} files successful, {} failed",
+                    "Reindexed knowledge {}: {} files reprocessed, {} unchanged, {} failed",
                     knowledge_base.id,
                     indexed_files,
+                    skipped_files,
                     failed_files
                 );
+                total_reindexed += indexed_files;
+                total_skipped += skipped_files;
+                total_failed += failed_files;
+                notify_knowledge_change(
+                    &state,
+                    webhook::KNOWLEDGE_REINDEXED,
+                    &knowledge_base.id,
+                    &auth_user.user.id,
+                    &file_ids,
+                );
             } else {
                 knowledge_vector::log_rag_disabled("reindex");
             }
         }
     }
 
+    if dry_run {
+        return Ok(HttpResponse::Ok().json(json!({
+            "dry_run": true,
+            "reprocessed": stale_files.len(),
+            "skipped": total_skipped,
+            "files": stale_files,
+            "deleted_knowledge_bases": deleted_knowledge_bases,
+        })));
+    }
+
     log::info!(
-        "Reindexing completed. Deleted {} invalid knowledge bases: {:?}",
+        "Reindexing completed: {} files reprocessed, {} unchanged, {} failed. Deleted {} invalid knowledge bases: {:?}",
+        total_reindexed,
+        total_skipped,
+        total_failed,
         deleted_knowledge_bases.len(),
         deleted_knowledge_bases
     );
 
-    Ok(HttpResponse::Ok().json(true))
+    Ok(HttpResponse::Ok().json(json!({
+        "reprocessed": total_reindexed,
+        "skipped": total_skipped,
+        "failed": total_failed,
+        "deleted_knowledge_bases": deleted_knowledge_bases,
+    })))
 }
 
 // POST /{id}/files/batch/add - Add multiple files to knowledge
@@ -1246,29 +2007,14 @@ async fn add_files_batch(
         .await?
         .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))?;
 
-    // Check write access
-    if knowledge.user_id != auth_user.user.id && auth_user.user.role != "admin" {
-        let group_service = GroupService::new(&state.db);
-        let groups = group_service
-            .get_groups_by_member_id(&auth_user.user.id)
-            .await?;
-        let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
-
-        if !has_access(
-            &auth_user.user.id,
-            "write",
-            &knowledge.access_control,
-            &user_group_ids,
-        ) {
-            return Err(AppError::Forbidden("Access prohibited".to_string()));
-        }
-    }
+    check_knowledge_access(&state, &auth_user, &knowledge, "write").await?;
+    let _ingest = state.ingest_locks.try_lock(&knowledge.id, "batch add")?;
 
-    // Validate all files exist first
+    // Validate all files exist and are the caller's to share first
     let mut validated_file_ids = Vec::new();
     for file_form in form.iter() {
         let file = file_service
-            .get_file_by_id(&file_form.file_id)
+            .get_readable_file(&file_form.file_id, &auth_user.user)
             .await?
             .ok_or_else(|| AppError::NotFound(format!("File {} not found", file_form.file_id)))?;
 
@@ -1283,72 +2029,20 @@ async fn add_files_batch(
         validated_file_ids.push(file_form.file_id.clone());
     }
 
-    // Process files in batch if RAG is enabled
-    if let Some((vector_db, embedding_provider)) =
-        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
-    {
-        let mut successful_files = Vec::new();
-        let mut failed_files = Vec::new();
-
-        for file_id in &validated_file_ids {
-            match knowledge_vector::process_and_index_file(
-                &vector_db,
-                &embedding_provider,
-                &file_service,
-                file_id,
-                &knowledge_id,
-            )
-            .await
-            {
-                Ok(chunk_count) => {
-                    log::info!(
-                        "Successfully indexed {} chunks from file {} in batch",
-                        chunk_count,
-                        file_id
-                    );
-                    successful_files.push(file_id.clone());
-                }
-                Err(e) => {
-                    log::error!("Failed to index file {} in batch: {}", file_id, e);
-                    failed_files.push((file_id.clone(), e.to_string()));
-                }
-            }
-        }
-
-        if !failed_files.is_empty() {
-            log::warn!(
-                "Batch processing completed with {} failures: {:?}",
-                failed_files.len(),
-                failed_files
-            );
-        }
-    } else {
-        knowledge_vector::log_rag_disabled("batch index files");
-    }
-
-    // Add file IDs to knowledge data
-    let mut data = knowledge.data.clone().unwrap_or_else(|| json!({}));
-    let mut file_ids = data
-        .get("file_ids")
-        .and_then(|v| v.as_array())
-        .map(|arr| {
-            arr.iter()
-                .filter_map(|v| v.as_str().map(String::from))
-                .collect::<Vec<String>>()
-        })
-        .unwrap_or_default();
+    let results = ingest_files(&state, &file_service, &validated_file_ids, &knowledge_id).await;
+    let (updated, added_file_ids) =
+        add_ingested_files(&knowledge_service, &knowledge, &results).await?;
 
-    for file_form in form.iter() {
-        if !file_ids.contains(&file_form.file_id) {
-            file_ids.push(file_form.file_id.clone());
-        }
+    if !added_file_ids.is_empty() {
+        notify_knowledge_change(
+            &state,
+            webhook::KNOWLEDGE_FILE_ADDED,
+            &knowledge_id,
+            &auth_user.user.id,
+            &added_file_ids,
+        );
     }
 
-    data["file_ids"] = json!(file_ids);
-    let updated = knowledge_service
-        .update_knowledge_data(&knowledge_id, data)
-        .await?;
-
     // Get files
     let mut files = Vec::new();
     if let Some(data) = &updated.data {
@@ -1372,6 +2066,335 @@ async fn add_files_batch(
         }
     }
 
-    let response = KnowledgeFilesResponse::from_knowledge_and_files(updated, files);
-    Ok(HttpResponse::Ok().json(response))
+    Ok(HttpResponse::Ok().json(KnowledgeBatchResponse {
+        knowledge: KnowledgeFilesResponse::from_knowledge_and_files(updated, files),
+        results,
+    }))
+}
+
+/// Index files into a knowledge base, at most RAG_BATCH_CONCURRENCY at a time
+///
+/// With RAG disabled every file counts as ingested.
+async fn ingest_files(
+    state: &AppState,
+    file_service: &FileService<'_>,
+    file_ids: &[String],
+    knowledge_id: &str,
+) -> Vec<knowledge_vector::FileIngestResult> {
+    let Some((vector_db, embedding_provider)) =
+        knowledge_vector::get_rag_components(&state.vector_db, &state.embedding_provider)
+    else {
+        knowledge_vector::log_rag_disabled("batch index files");
+        return file_ids
+            .iter()
+            .map(|file_id| knowledge_vector::FileIngestResult {
+                file_id: file_id.clone(),
+                status: knowledge_vector::IngestStatus::Completed(Default::default()),
+            })
+            .collect();
+    };
+
+    let concurrency = state.config.read().unwrap().rag_batch_concurrency;
+    let results = knowledge_vector::index_files_concurrently(
+        &vector_db,
+        &embedding_provider,
+        file_service,
+        file_ids,
+        knowledge_id,
+        concurrency,
+        &IngestEvents::from_state(state),
+    )
+    .await;
+
+    let failed = results.iter().filter(|r| !r.is_completed()).count();
+    if failed > 0 {
+        log::warn!(
+            "Batch processing completed with {} of {} files failed",
+            failed,
+            results.len()
+        );
+    }
+    results
+}
+
+/// Add the files that ingested to the knowledge base's file list, returning it updated
+/// and the ids it didn't already list
+///
+/// Files that failed to ingest stay out of the knowledge base.
+async fn add_ingested_files(
+    knowledge_service: &KnowledgeService<'_>,
+    knowledge: &Knowledge,
+    results: &[knowledge_vector::FileIngestResult],
+) -> AppResult<(Knowledge, Vec<String>)> {
+    let mut data = knowledge.data.clone().unwrap_or_else(|| json!({}));
+    let mut file_ids = knowledge_file_ids(knowledge);
+
+    let mut added_file_ids = Vec::new();
+    for result in results.iter().filter(|result| result.is_completed()) {
+        if !file_ids.contains(&result.file_id) {
+            file_ids.push(result.file_id.clone());
+            added_file_ids.push(result.file_id.clone());
+        }
+    }
+
+    data["file_ids"] = json!(file_ids);
+    let updated = knowledge_service
+        .update_knowledge_data(&knowledge.id, data)
+        .await?;
+    Ok((updated, added_file_ids))
+}
+
+/// Knowledge base after a batch add, with how each file's ingestion went
+#[derive(Serialize)]
+struct KnowledgeBatchResponse {
+    #[serde(flatten)]
+    knowledge: KnowledgeFilesResponse,
+    results: Vec<knowledge_vector::FileIngestResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    fn knowledge(access_control: Option<serde_json::Value>) -> Knowledge {
+        Knowledge {
+            id: "kb-1".to_string(),
+            user_id: "owner".to_string(),
+            name: "Private".to_string(),
+            description: None,
+            data: None,
+            data_str: None,
+            meta: None,
+            meta_str: None,
+            access_control,
+            access_control_str: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn status(result: AppResult<()>) -> u16 {
+        result.unwrap_err().status_code().as_u16()
+    }
+
+    #[test]
+    fn test_unreadable_private_knowledge_is_not_found() {
+        let kb = knowledge(Some(json!({})));
+        let groups = HashSet::new();
+
+        for access_type in ["read", "write"] {
+            let result = knowledge_access_policy(&kb, "stranger", false, &groups, access_type);
+            assert_eq!(status(result), 404);
+        }
+    }
+
+    #[test]
+    fn test_read_only_user_gets_forbidden_on_write() {
+        let kb = knowledge(Some(json!({
+            "read": { "group_ids": ["readers"], "user_ids": [] },
+            "write": { "group_ids": [], "user_ids": [] },
+        })));
+        let groups: HashSet<String> = ["readers".to_string()].into_iter().collect();
+
+        assert!(knowledge_access_policy(&kb, "reader", false, &groups, "read").is_ok());
+        let result = knowledge_access_policy(&kb, "reader", false, &groups, "write");
+        assert_eq!(status(result), 403);
+    }
+
+    #[test]
+    fn test_default_access_applies_when_omitted() {
+        let config = crate::config::Config::from_lookup(|key| {
+            (key == "DEFAULT_KNOWLEDGE_ACCESS").then(|| {
+                r#"{"read":{"group_ids":["team"],"user_ids":[]},"write":{"group_ids":[],"user_ids":[]}}"#
+                    .to_string()
+            })
+        })
+        .unwrap();
+        let default = config.default_knowledge_access.as_ref();
+
+        let kb = knowledge(default_access_control(true, default));
+        let team: HashSet<String> = ["team".to_string()].into_iter().collect();
+        assert!(knowledge_access_policy(&kb, "member", false, &team, "read").is_ok());
+        let result = knowledge_access_policy(&kb, "member", false, &team, "write");
+        assert_eq!(status(result), 403);
+
+        // Without sharing permission the knowledge base stays private
+        assert_eq!(default_access_control(false, default), Some(json!({})));
+        // Unset keeps the public default for users who may share
+        assert_eq!(default_access_control(true, None), None);
+    }
+
+    #[test]
+    fn test_owner_and_admin_have_full_access() {
+        let kb = knowledge(Some(json!({})));
+        let groups = HashSet::new();
+
+        assert!(knowledge_access_policy(&kb, "owner", false, &groups, "write").is_ok());
+        assert!(knowledge_access_policy(&kb, "admin", true, &groups, "write").is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_admin_bypass_audited_but_owner_access_not() {
+        use crate::models::audit::AuditQuery;
+        use crate::services::audit::ADMIN_BYPASS_ACTION;
+        use crate::test_utils::{seed_user, test_db};
+
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let admin = seed_user(&db, "admin").await;
+        let kb = Knowledge {
+            id: Uuid::new_v4().to_string(),
+            user_id: owner.id.clone(),
+            ..knowledge(Some(json!({})))
+        };
+
+        authorize_knowledge(&db, &admin, &kb, "read", true)
+            .await
+            .unwrap();
+        authorize_knowledge(&db, &owner, &kb, "read", true)
+            .await
+            .unwrap();
+
+        let page = AuditService::new(&db)
+            .list(&AuditQuery {
+                action: Some(ADMIN_BYPASS_ACTION.to_string()),
+                target_id: Some(kb.id.clone()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].actor_id, admin.id);
+        assert_eq!(page.items[0].target_type, "knowledge");
+    }
+
+    #[test]
+    fn test_copy_chunking_overrides_source() {
+        let source = Knowledge {
+            data: Some(json!({
+                "file_ids": [],
+                "chunking": { "chunk_size": 1024, "chunk_overlap": 100 },
+            })),
+            ..knowledge(None)
+        };
+        let form = KnowledgeCopyForm {
+            chunk_overlap: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            form.chunking(&source).unwrap(),
+            Some(json!({ "chunk_size": 1024, "chunk_overlap": 0 }))
+        );
+        assert_eq!(
+            KnowledgeCopyForm::default()
+                .chunking(&knowledge(None))
+                .unwrap(),
+            None
+        );
+
+        let form = KnowledgeCopyForm {
+            chunk_size: Some(100),
+            chunk_overlap: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(status(form.chunking(&source).map(|_| ())), 400);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_copy_has_own_collection_and_file_list() {
+        use crate::retrieval::{EmbeddingProvider, VectorDB};
+        use crate::routes::knowledge_vector::tests::{FakeEmbeddings, MemoryVectorDb};
+        use crate::test_utils::{seed_user, test_db};
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let copier = seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+        let knowledge_service = KnowledgeService::new(&db);
+        let file_id = Uuid::new_v4().to_string();
+        file_service
+            .create_file(&file_id, &owner.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+        file_service
+            .update_file_data(&file_id, json!({ "content": "word ".repeat(400) }))
+            .await
+            .unwrap();
+        let source = knowledge_service
+            .create_knowledge(
+                &Uuid::new_v4().to_string(),
+                &owner.id,
+                "Docs",
+                None,
+                Some(json!({ "file_ids": [file_id] })),
+            )
+            .await
+            .unwrap();
+
+        let vector_db: Arc<dyn VectorDB> = Arc::new(MemoryVectorDb::default());
+        let embedding_provider: Arc<dyn EmbeddingProvider> = Arc::new(FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        });
+        let events = IngestEvents::default();
+        knowledge_vector::index_files_concurrently(
+            &vector_db,
+            &embedding_provider,
+            &file_service,
+            std::slice::from_ref(&file_id),
+            &source.id,
+            1,
+            &events,
+        )
+        .await;
+        let source_vectors = vector_db.count(&source.id).await.unwrap();
+
+        let form = KnowledgeCopyForm {
+            copy_files: true,
+            chunk_size: Some(256),
+            chunk_overlap: Some(0),
+            ..Default::default()
+        };
+        let (copy, file_ids) = copy_knowledge(&db, &source, &copier.id, &form, None)
+            .await
+            .unwrap();
+        assert_ne!(copy.id, source.id);
+        assert_eq!(copy.user_id, copier.id);
+        assert_eq!(copy.name, "Docs (Copy)");
+        assert!(knowledge_file_ids(&copy).is_empty());
+        assert_eq!(file_ids.len(), 1);
+        assert_ne!(file_ids[0], file_id);
+        let copied = file_service.get_file_by_id(&file_ids[0]).await.unwrap();
+        assert_eq!(copied.unwrap().user_id, copier.id);
+
+        let results = knowledge_vector::index_files_concurrently(
+            &vector_db,
+            &embedding_provider,
+            &file_service,
+            &file_ids,
+            &copy.id,
+            1,
+            &events,
+        )
+        .await;
+        let (copy, added) = add_ingested_files(&knowledge_service, &copy, &results)
+            .await
+            .unwrap();
+        assert_eq!(added, file_ids);
+        assert_eq!(knowledge_file_ids(&copy), file_ids);
+
+        // Smaller chunks, in a collection of its own
+        assert!(vector_db.count(&copy.id).await.unwrap() > source_vectors);
+        assert_eq!(vector_db.count(&source.id).await.unwrap(), source_vectors);
+        let source = knowledge_service
+            .get_knowledge_by_id(&source.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(knowledge_file_ids(&source), [file_id]);
+    }
 }
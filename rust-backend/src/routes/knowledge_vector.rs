@@ -1,11 +1,26 @@
 /// Helper functions for vector database operations in knowledge routes
 use crate::error::{AppError, AppResult};
-use crate::retrieval::{chunk_text, EmbeddingProvider, VectorDB, VectorError};
+use crate::models::file::{File, IngestProgress};
+use crate::retrieval::language::{collection_names, language_collection};
+use crate::retrieval::vector::filter::tag_key;
+use crate::retrieval::{
+    ChunkStrategy, Chunker, ChunkingConfig, DistanceMetric, EmbeddingProvider, LanguageRoute,
+    VectorDB,
+};
+use crate::routes::ingest_events::IngestEvents;
 use crate::services::file::FileService;
+use crate::services::rag_chunk::{chunk_id, RagChunkService};
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tracing::{debug, info, warn};
 
+/// Chunks embedded and upserted between progress checkpoints
+const INGEST_CHECKPOINT_CHUNKS: usize = 32;
+
 /// VectorItem structure for vector database operations
 #[derive(Debug, Clone)]
 pub struct VectorItem {
@@ -15,28 +30,506 @@ pub struct VectorItem {
     pub metadata: serde_json::Value,
 }
 
+/// What a file's vectors in one knowledge base were built from
+///
+/// Kept in the file meta under `vector_index.<knowledge_id>` so a reindex can tell
+/// whether the existing vectors are still current.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct IndexState {
+    content_hash: String,
+    embedding_fingerprint: String,
+    /// RAG_TEXT_NORMALIZE steps the chunks were cleaned with; absent when none were
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    normalization: String,
+}
+
+impl IndexState {
+    fn new(
+        content: &str,
+        embedding_provider: &dyn EmbeddingProvider,
+        chunking: &ChunkingConfig,
+        distance: DistanceMetric,
+    ) -> Self {
+        Self {
+            content_hash: format!("{:x}", Sha256::digest(content.as_bytes())),
+            // Chunking settings change the vectors as much as the model does, and vectors
+            // indexed under one metric can't be searched under another
+            embedding_fingerprint: format!(
+                "{}:{}:{}:{}:{}",
+                embedding_provider.model_name(),
+                embedding_provider.dimension(),
+                chunking.chunk_size,
+                chunking.chunk_overlap,
+                distance.as_str()
+            ),
+            normalization: chunking.normalization.to_string(),
+        }
+    }
+
+    /// Identifies the chunks and vectors an ingest produces, for resuming it
+    fn fingerprint(&self) -> String {
+        if self.normalization.is_empty() {
+            return format!("{}:{}", self.content_hash, self.embedding_fingerprint);
+        }
+        format!(
+            "{}:{}:{}",
+            self.content_hash, self.embedding_fingerprint, self.normalization
+        )
+    }
+
+    fn stored(meta: Option<&serde_json::Value>, knowledge_id: &str) -> Option<Self> {
+        let state = meta?.get("vector_index")?.get(knowledge_id)?;
+        serde_json::from_value(state.clone()).ok()
+    }
+
+    /// `meta` with this state recorded for `knowledge_id`
+    fn record(&self, meta: Option<serde_json::Value>, knowledge_id: &str) -> serde_json::Value {
+        let mut meta = meta
+            .filter(|meta| meta.is_object())
+            .unwrap_or_else(|| json!({}));
+        if !meta["vector_index"].is_object() {
+            meta["vector_index"] = json!({});
+        }
+        meta["vector_index"][knowledge_id] = json!(self);
+        meta
+    }
+}
+
+/// Whether chunk text is also kept in the rag_chunk table (ENABLE_RAG_CHUNK_STORE)
+fn chunk_store_from_env() -> bool {
+    std::env::var("ENABLE_RAG_CHUNK_STORE")
+        .map(|value| value.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(true)
+}
+
+/// Cap on the chunks one file is split into (RAG_MAX_CHUNKS_PER_FILE)
+///
+/// Keeps a pathological upload from eating the embedding budget and flooding the vector
+/// DB. Past the cap the file is rejected, or with RAG_MAX_CHUNKS_POLICY=truncate only its
+/// first chunks are indexed and the file meta records `chunks_truncated`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLimit {
+    /// 0 = unlimited
+    pub max_chunks: usize,
+    pub truncate: bool,
+}
+
+impl ChunkLimit {
+    pub fn from_env() -> Self {
+        Self {
+            max_chunks: std::env::var("RAG_MAX_CHUNKS_PER_FILE")
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(10000),
+            truncate: std::env::var("RAG_MAX_CHUNKS_POLICY")
+                .is_ok_and(|policy| policy.trim().eq_ignore_ascii_case("truncate")),
+        }
+    }
+
+    /// Hold `chunks` to the limit; returns whether any were dropped
+    fn apply(&self, chunks: &mut Vec<String>, filename: &str) -> AppResult<bool> {
+        if self.max_chunks == 0 || chunks.len() <= self.max_chunks {
+            return Ok(false);
+        }
+        if !self.truncate {
+            return Err(AppError::BadRequest(format!(
+                "{} would be split into {} chunks, more than the {} allowed per file",
+                filename,
+                chunks.len(),
+                self.max_chunks
+            )));
+        }
+        warn!(
+            "Truncating {} from {} to {} chunks (RAG_MAX_CHUNKS_PER_FILE)",
+            filename,
+            chunks.len(),
+            self.max_chunks
+        );
+        chunks.truncate(self.max_chunks);
+        Ok(true)
+    }
+
+    /// Whether a file truncated under an earlier, lower limit would now keep more chunks
+    fn raised_since(&self, meta: Option<&serde_json::Value>) -> bool {
+        let Some(kept) = meta
+            .and_then(|meta| meta.pointer("/chunks_truncated/kept"))
+            .and_then(|kept| kept.as_u64())
+        else {
+            return false;
+        };
+        self.max_chunks == 0 || kept < self.max_chunks as u64
+    }
+}
+
+/// `meta` with the truncation of the file's chunks recorded, or cleared if there was none
+fn record_truncation(
+    mut meta: serde_json::Value,
+    truncated: Option<(usize, usize)>,
+) -> serde_json::Value {
+    if let Some(meta) = meta.as_object_mut() {
+        match truncated {
+            Some((total, kept)) => {
+                meta.insert(
+                    "chunks_truncated".to_string(),
+                    json!({ "total": total, "kept": kept }),
+                );
+            }
+            None => {
+                meta.remove("chunks_truncated");
+            }
+        }
+    }
+    meta
+}
+
+/// File metadata copied into each chunk's payload so queries can filter on it
+///
+/// Read from RAG_VECTOR_METADATA_FIELDS (comma separated); `file_id` and `filename`
+/// are always stored.
+fn payload_fields_from_env() -> Vec<String> {
+    std::env::var("RAG_VECTOR_METADATA_FIELDS")
+        .unwrap_or_else(|_| "tags,created_at,mime".to_string())
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+/// Filterable payload entries for a file's chunks
+///
+/// Tags come from the file meta's `tags` list and are stored as one `tag:<name>` flag
+/// each, plus a comma-joined `tags` string for display.
+fn file_payload(file: &File, fields: &[String]) -> serde_json::Map<String, serde_json::Value> {
+    let meta = file.meta.as_ref();
+    let mut payload = serde_json::Map::new();
+
+    for field in fields {
+        match field.as_str() {
+            "created_at" => {
+                payload.insert("created_at".to_string(), json!(file.created_at));
+            }
+            "mime" => {
+                if let Some(mime) = meta
+                    .and_then(|meta| meta.get("content_type"))
+                    .and_then(|ct| ct.as_str())
+                {
+                    payload.insert("mime".to_string(), json!(mime));
+                }
+            }
+            "tags" => {
+                let tags: Vec<&str> = meta
+                    .and_then(|meta| meta.get("tags"))
+                    .and_then(|tags| tags.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|tag| tag.as_str().or_else(|| tag.get("name")?.as_str()))
+                    .collect();
+                if !tags.is_empty() {
+                    payload.insert("tags".to_string(), json!(tags.join(",")));
+                }
+                for tag in tags {
+                    payload.insert(tag_key(tag), json!(true));
+                }
+            }
+            other => warn!("Unknown RAG_VECTOR_METADATA_FIELDS entry: {}", other),
+        }
+    }
+
+    payload
+}
+
+/// What indexing one file wrote
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Indexed {
+    /// Chunks embedded and upserted
+    pub chunks: usize,
+    /// Whether chunks past RAG_MAX_CHUNKS_PER_FILE were dropped
+    pub truncated: bool,
+}
+
+/// How far embedding a file has got, reported while it is indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestUpdate {
+    /// 0-100
+    pub percent: u8,
+    /// Chunks embedded so far, i.e. the index of the next chunk
+    pub chunk_index: usize,
+    pub chunks_total: usize,
+}
+
+/// Result of syncing one file into a knowledge base
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// Content and embedding config unchanged and vectors present
+    Skipped,
+    /// Re-embedded
+    Reindexed(Indexed),
+}
+
+/// How ingesting one file of a batch went
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IngestStatus {
+    Completed(Indexed),
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileIngestResult {
+    pub file_id: String,
+    #[serde(flatten)]
+    pub status: IngestStatus,
+}
+
+impl FileIngestResult {
+    pub fn is_completed(&self) -> bool {
+        matches!(self.status, IngestStatus::Completed { .. })
+    }
+}
+
+/// Index several files into a knowledge base, at most `concurrency` at a time
+///
+/// One file failing doesn't stop the others; results come back in `file_ids` order.
+pub async fn index_files_concurrently(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    file_service: &FileService<'_>,
+    file_ids: &[String],
+    knowledge_id: &str,
+    concurrency: usize,
+    events: &IngestEvents,
+) -> Vec<FileIngestResult> {
+    stream::iter(file_ids)
+        .map(|file_id| async move {
+            let status = match process_and_index_file(
+                vector_db,
+                embedding_provider,
+                file_service,
+                file_id,
+                knowledge_id,
+                events,
+            )
+            .await
+            {
+                Ok(indexed) => IngestStatus::Completed(indexed),
+                Err(e) => {
+                    warn!("Failed to index file {} in batch: {}", file_id, e);
+                    IngestStatus::Failed {
+                        error: e.to_string(),
+                    }
+                }
+            };
+            FileIngestResult {
+                file_id: file_id.clone(),
+                status,
+            }
+        })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
 /// Process a file and add its embeddings to the vector database
+///
+/// Progress is reported to the file's owner through `events`.
 pub async fn process_and_index_file(
     vector_db: &Arc<dyn VectorDB>,
     embedding_provider: &Arc<dyn EmbeddingProvider>,
     file_service: &FileService<'_>,
     file_id: &str,
     knowledge_id: &str,
-) -> AppResult<usize> {
+    events: &IngestEvents,
+) -> AppResult<Indexed> {
     info!(
         "Processing file {} for knowledge base {}",
         file_id, knowledge_id
     );
 
-    // Get file
-    let file = file_service
+    let file = load_file(file_service, file_id).await?;
+    let reporter = events.start(&file.user_id, file_id, knowledge_id);
+    let result = index_file(
+        vector_db,
+        embedding_provider,
+        file_service,
+        file,
+        knowledge_id,
+        ChunkLimit::from_env(),
+        &|update| reporter.progress(update),
+    )
+    .await;
+    reporter.complete(&result);
+    result
+}
+
+/// Bring a file's vectors in a knowledge base up to date
+///
+/// Skips the file when its content and the embedding config match what its vectors were
+/// built from and the vectors still exist; otherwise replaces them.
+pub async fn sync_file_with_progress(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    file_service: &FileService<'_>,
+    file_id: &str,
+    knowledge_id: &str,
+    on_progress: &(dyn Fn(IngestUpdate) + Send + Sync),
+) -> AppResult<SyncOutcome> {
+    let file = load_file(file_service, file_id).await?;
+    let chunking = knowledge_chunking(file_service, knowledge_id).await?;
+    let current = current_index_state(
+        &file,
+        embedding_provider.as_ref(),
+        &chunking,
+        vector_db.distance(),
+    );
+    let limit = ChunkLimit::from_env();
+
+    if current.is_some()
+        && current == IndexState::stored(file.meta.as_ref(), knowledge_id)
+        && !limit.raised_since(file.meta.as_ref())
+        && has_file_vectors(vector_db, embedding_provider, knowledge_id, file_id).await?
+    {
+        debug!(
+            "File {} unchanged in knowledge base {}, skipping",
+            file_id, knowledge_id
+        );
+        return Ok(SyncOutcome::Skipped);
+    }
+
+    // An unfinished ingest of the same content keeps its vectors and resumes
+    let resumable = match &current {
+        Some(state) => file_service
+            .get_ingest_progress(file_id, knowledge_id)
+            .await?
+            .is_some_and(|progress| {
+                progress.status != IngestProgress::COMPLETED
+                    && progress.fingerprint == state.fingerprint()
+            }),
+        None => false,
+    };
+    if !resumable {
+        delete_file_vectors(vector_db, embedding_provider, knowledge_id, file_id).await?;
+    }
+    index_file(
+        vector_db,
+        embedding_provider,
+        file_service,
+        file,
+        knowledge_id,
+        limit,
+        on_progress,
+    )
+    .await
+    .map(SyncOutcome::Reindexed)
+}
+
+/// Whether the file meta says its vectors in the knowledge base were built from its
+/// current content and embedding config
+///
+/// Only the meta is consulted, so vectors deleted behind the index's back go unnoticed;
+/// [`sync_file_with_progress`] also checks the vector DB.
+pub fn is_index_current(
+    file: &File,
+    embedding_provider: &dyn EmbeddingProvider,
+    chunking: &ChunkingConfig,
+    distance: DistanceMetric,
+    knowledge_id: &str,
+) -> bool {
+    let Some(stored) = IndexState::stored(file.meta.as_ref(), knowledge_id) else {
+        return false;
+    };
+    if ChunkLimit::from_env().raised_since(file.meta.as_ref()) {
+        return false;
+    }
+    current_index_state(file, embedding_provider, chunking, distance)
+        .is_some_and(|state| state == stored)
+}
+
+/// What indexing the file now would build its vectors from
+fn current_index_state(
+    file: &File,
+    embedding_provider: &dyn EmbeddingProvider,
+    chunking: &ChunkingConfig,
+    distance: DistanceMetric,
+) -> Option<IndexState> {
+    let content = extract_content_from_file_data(file.data.as_ref()?).ok()?;
+    Some(IndexState::new(
+        &content,
+        embedding_provider,
+        chunking,
+        distance,
+    ))
+}
+
+/// Chunking settings of a knowledge base, see [`ChunkingConfig::for_knowledge`]
+async fn knowledge_chunking(
+    file_service: &FileService<'_>,
+    knowledge_id: &str,
+) -> AppResult<ChunkingConfig> {
+    let knowledge = file_service
+        .knowledge()
+        .get_knowledge_by_id(knowledge_id)
+        .await?;
+    Ok(ChunkingConfig::for_knowledge(
+        knowledge.as_ref().and_then(|k| k.data.as_ref()),
+    ))
+}
+
+async fn load_file(file_service: &FileService<'_>, file_id: &str) -> AppResult<File> {
+    let mut file = file_service
         .get_file_by_id(file_id)
         .await?
         .ok_or_else(|| AppError::NotFound(format!("File {} not found", file_id)))?;
+    file.parse_json_fields();
+    Ok(file)
+}
+
+/// Whether any vectors for `file_id` exist in the knowledge base's collections
+async fn has_file_vectors(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    knowledge_id: &str,
+    file_id: &str,
+) -> AppResult<bool> {
+    for collection in collection_names(embedding_provider.as_ref(), knowledge_id) {
+        let has_collection = vector_db
+            .has_collection(&collection)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+        if !has_collection {
+            continue;
+        }
+
+        let result = vector_db
+            .query(&collection, json!({ "file_id": file_id }), Some(1))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to query file vectors: {}", e)))?;
+        if result
+            .ids
+            .is_some_and(|ids| ids.iter().any(|ids| !ids.is_empty()))
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Chunk, embed and upsert a loaded file, then record what its vectors were built from
+async fn index_file(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    file_service: &FileService<'_>,
+    mut file: File,
+    knowledge_id: &str,
+    limit: ChunkLimit,
+    on_progress: &(dyn Fn(IngestUpdate) + Send + Sync),
+) -> AppResult<Indexed> {
+    let file_id = file.id.clone();
+    let file_id = file_id.as_str();
 
     // Check if file has data
     let file_data = file
         .data
+        .take()
         .ok_or_else(|| AppError::BadRequest("File has no processed data".to_string()))?;
 
     // Extract content from file data
@@ -45,80 +538,244 @@ pub async fn process_and_index_file(
 
     if content.trim().is_empty() {
         warn!("File {} has no extractable content", file_id);
-        return Ok(0);
+        return Ok(Indexed::default());
     }
 
-    // Chunk the content
-    let chunk_size = std::env::var("CHUNK_SIZE")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(512);
-    let chunk_overlap = std::env::var("CHUNK_OVERLAP")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(50);
+    // Chunk the content with a strategy matching the upload's detected type
+    let content_type = file
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.get("content_type"))
+        .and_then(|ct| ct.as_str());
+    let strategy = ChunkStrategy::detect(content_type, &file.filename);
+    let payload = file_payload(&file, &payload_fields_from_env());
+    let config = knowledge_chunking(file_service, knowledge_id).await?;
+    let index_state = IndexState::new(
+        &content,
+        embedding_provider.as_ref(),
+        &config,
+        vector_db.distance(),
+    );
 
     debug!(
-        "Chunking content with size={}, overlap={}",
-        chunk_size, chunk_overlap
+        "Chunking content with strategy={}, size={}, overlap={}",
+        strategy.as_str(),
+        config.chunk_size,
+        config.chunk_overlap
     );
-    let chunks = chunk_text(&content, chunk_size, chunk_overlap);
+    let mut chunks = Chunker::new(config).chunk(&content, strategy);
 
     if chunks.is_empty() {
         warn!("No chunks generated for file {}", file_id);
-        return Ok(0);
+        return Ok(Indexed::default());
     }
 
     info!("Generated {} chunks for file {}", chunks.len(), file_id);
+    let generated = chunks.len();
+    let truncated = limit.apply(&mut chunks, &file.filename)?;
 
-    // Generate embeddings
-    let texts: Vec<String> = chunks.iter().map(|s| s.to_string()).collect();
-    let embeddings = embedding_provider
-        .embed(texts)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to generate embeddings: {}", e)))?;
-
-    debug!("Generated {} embeddings", embeddings.len());
-
-    // Create vector items
-    let items: Vec<crate::retrieval::vector::types::VectorItem> = chunks
-        .into_iter()
-        .zip(embeddings)
-        .enumerate()
-        .map(
-            |(idx, (chunk, embedding))| crate::retrieval::vector::types::VectorItem {
-                id: format!("{}-chunk-{}", file_id, idx),
-                text: chunk,
-                vector: embedding,
-                metadata: json!({
-                    "file_id": file_id,
-                    "knowledge_id": knowledge_id,
-                    "chunk_index": idx,
-                    "filename": file.filename,
-                }),
-            },
-        )
-        .collect();
+    // Pick up after the last checkpoint of an unfinished ingest of the same content
+    let total = chunks.len();
+    let mut progress = IngestProgress {
+        file_id: file_id.to_string(),
+        knowledge_id: knowledge_id.to_string(),
+        fingerprint: index_state.fingerprint(),
+        chunks_total: total as i64,
+        chunks_done: 0,
+        status: IngestProgress::RUNNING.to_string(),
+        error: None,
+        updated_at: 0,
+    };
+    let mut resume_from = file_service
+        .get_ingest_progress(file_id, knowledge_id)
+        .await?
+        .map_or(0, |stored| {
+            stored.resume_point(&progress.fingerprint, total)
+        });
+    if resume_from > 0
+        && !has_file_vectors(vector_db, embedding_provider, knowledge_id, file_id).await?
+    {
+        resume_from = 0;
+    }
+    if resume_from > 0 {
+        info!(
+            "Resuming file {} at chunk {} of {}",
+            file_id, resume_from, total
+        );
+    }
+    progress.chunks_done = resume_from as i64;
+    file_service.save_ingest_progress(&progress).await?;
+    let chunk_store = chunk_store_from_env().then(|| file_service.rag_chunks());
+    if resume_from == 0 {
+        if let Some(store) = &chunk_store {
+            store.delete_file_chunks(knowledge_id, file_id).await?;
+        }
+    }
 
-    let item_count = items.len();
+    let result = embed_chunks(
+        vector_db,
+        embedding_provider,
+        file_service,
+        chunk_store.as_ref(),
+        &mut chunks,
+        resume_from,
+        &mut progress,
+        |idx, language| {
+            let mut metadata = json!({
+                "file_id": file_id,
+                "knowledge_id": knowledge_id,
+                "chunk_index": idx,
+                "chunk_strategy": strategy.as_str(),
+                "filename": file.filename,
+            });
+            if let Some(language) = language {
+                metadata["language"] = json!(language);
+            }
+            if let Some(metadata) = metadata.as_object_mut() {
+                metadata.extend(payload.clone());
+            }
+            metadata
+        },
+        on_progress,
+    )
+    .await;
 
-    // Insert into vector database (upsert to handle updates)
-    vector_db
-        .upsert(knowledge_id, items)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to index file: {}", e)))?;
+    if let Err(e) = result {
+        progress.status = IngestProgress::FAILED.to_string();
+        progress.error = Some(e.to_string());
+        if let Err(save_err) = file_service.save_ingest_progress(&progress).await {
+            warn!(
+                "Failed to record ingest failure for file {}: {}",
+                file_id, save_err
+            );
+        }
+        return Err(e);
+    }
 
     info!(
         "Successfully indexed {} chunks from file {} to knowledge base {}",
-        item_count, file_id, knowledge_id
+        total, file_id, knowledge_id
     );
 
-    Ok(item_count)
+    let meta = record_truncation(
+        index_state.record(file.meta, knowledge_id),
+        truncated.then_some((generated, total)),
+    );
+    file_service.update_file_metadata(file_id, meta).await?;
+    progress.status = IngestProgress::COMPLETED.to_string();
+    file_service.save_ingest_progress(&progress).await?;
+
+    Ok(Indexed {
+        chunks: total,
+        truncated,
+    })
 }
 
-/// Delete a file's vectors from the knowledge base
+/// Embed and upsert `chunks[resume_from..]` a window at a time, checkpointing after each
+///
+/// Only one window is in flight, so a slow embedding backend or vector DB holds the ingest
+/// back instead of piling up vectors in memory. Chunk ids are derived from the chunk index,
+/// so re-running a window after a crash overwrites rather than duplicates. With a chunk
+/// store, each window's text is saved there before it is embedded.
+#[allow(clippy::too_many_arguments)]
+async fn embed_chunks(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    file_service: &FileService<'_>,
+    chunk_store: Option<&RagChunkService<'_>>,
+    chunks: &mut [String],
+    resume_from: usize,
+    progress: &mut IngestProgress,
+    metadata: impl Fn(usize, Option<&str>) -> serde_json::Value,
+    on_progress: &(dyn Fn(IngestUpdate) + Send + Sync),
+) -> AppResult<()> {
+    let total = chunks.len();
+    let file_id = progress.file_id.clone();
+    let knowledge_id = progress.knowledge_id.clone();
+
+    let mut window_start = resume_from;
+    while window_start < total {
+        let window_end = (window_start + INGEST_CHECKPOINT_CHUNKS).min(total);
+
+        // Group chunks by the model (and so collection) their language routes them to
+        let mut groups: BTreeMap<Option<&str>, (LanguageRoute, Vec<usize>)> = BTreeMap::new();
+        let mut languages = BTreeMap::new();
+        for (idx, chunk) in chunks
+            .iter()
+            .enumerate()
+            .take(window_end)
+            .skip(window_start)
+        {
+            let route = embedding_provider.route_language(chunk);
+            languages.insert(idx, route.language);
+            groups
+                .entry(route.sub_collection())
+                .or_insert_with(|| (route, Vec::new()))
+                .1
+                .push(idx);
+        }
+
+        if let Some(store) = chunk_store {
+            let texts: Vec<(usize, &str)> = (window_start..window_end)
+                .map(|idx| (idx, chunks[idx].as_str()))
+                .collect();
+            store.save_chunks(&knowledge_id, &file_id, &texts).await?;
+        }
+
+        let mut embedded = window_start;
+        for (sub_collection, (route, indices)) in groups {
+            let provider = route.provider.as_ref().unwrap_or(embedding_provider);
+            let texts: Vec<String> = indices.iter().map(|&i| chunks[i].clone()).collect();
+            let embeddings = provider
+                .embed_with_progress(texts, &|done, _| {
+                    on_progress(IngestUpdate {
+                        percent: ((embedded + done) * 100 / total.max(1)) as u8,
+                        chunk_index: embedded + done,
+                        chunks_total: total,
+                    })
+                })
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to generate embeddings: {}", e)))?;
+            embedded += indices.len();
+
+            debug!(
+                "Generated {} embeddings with {}",
+                embeddings.len(),
+                provider.model_name()
+            );
+
+            let items: Vec<crate::retrieval::vector::types::VectorItem> = indices
+                .into_iter()
+                .zip(embeddings)
+                .map(
+                    |(idx, embedding)| crate::retrieval::vector::types::VectorItem {
+                        id: chunk_id(&file_id, idx),
+                        text: std::mem::take(&mut chunks[idx]),
+                        vector: embedding,
+                        metadata: metadata(idx, languages[&idx]),
+                    },
+                )
+                .collect();
+
+            // Upsert so a window replayed after an interruption stays idempotent
+            vector_db
+                .upsert(&language_collection(&knowledge_id, sub_collection), items)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to index file: {}", e)))?;
+        }
+
+        progress.chunks_done = window_end as i64;
+        file_service.save_ingest_progress(progress).await?;
+        window_start = window_end;
+    }
+
+    Ok(())
+}
+
+/// Delete a file's vectors from the knowledge base, including language sub-collections
 pub async fn delete_file_vectors(
     vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
     knowledge_id: &str,
     file_id: &str,
 ) -> AppResult<()> {
@@ -127,27 +784,29 @@ pub async fn delete_file_vectors(
         file_id, knowledge_id
     );
 
-    // Check if collection exists
-    let has_collection = vector_db
-        .has_collection(knowledge_id)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+    for collection in collection_names(embedding_provider.as_ref(), knowledge_id) {
+        // Check if collection exists
+        let has_collection = vector_db
+            .has_collection(&collection)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
 
-    if !has_collection {
-        debug!(
-            "Collection {} does not exist, nothing to delete",
-            knowledge_id
-        );
-        return Ok(());
-    }
+        if !has_collection {
+            debug!(
+                "Collection {} does not exist, nothing to delete",
+                collection
+            );
+            continue;
+        }
 
-    // Delete by metadata filter
-    let filter = json!({"file_id": file_id});
+        // Delete by metadata filter
+        let filter = json!({"file_id": file_id});
 
-    vector_db
-        .delete(knowledge_id, None, Some(filter))
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to delete file vectors: {}", e)))?;
+        vector_db
+            .delete(&collection, None, Some(filter))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to delete file vectors: {}", e)))?;
+    }
 
     info!(
         "Successfully deleted vectors for file {} from knowledge base {}",
@@ -157,31 +816,56 @@ pub async fn delete_file_vectors(
     Ok(())
 }
 
-/// Delete an entire knowledge base collection
+/// Vectors in a knowledge base's collection and its language sub-collections
+pub async fn count_knowledge_vectors(
+    vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
+    knowledge_id: &str,
+) -> AppResult<usize> {
+    let mut count = 0;
+    for collection in collection_names(embedding_provider.as_ref(), knowledge_id) {
+        let has_collection = vector_db
+            .has_collection(&collection)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+        if has_collection {
+            count += vector_db
+                .count(&collection)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to count vectors: {}", e)))?;
+        }
+    }
+    Ok(count)
+}
+
+/// Delete an entire knowledge base collection and its language sub-collections
 pub async fn delete_knowledge_collection(
     vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
     knowledge_id: &str,
 ) -> AppResult<()> {
     info!("Deleting collection for knowledge base {}", knowledge_id);
 
-    // Check if collection exists
-    let has_collection = vector_db
-        .has_collection(knowledge_id)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
+    for collection in collection_names(embedding_provider.as_ref(), knowledge_id) {
+        // Check if collection exists
+        let has_collection = vector_db
+            .has_collection(&collection)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to check collection: {}", e)))?;
 
-    if !has_collection {
-        debug!(
-            "Collection {} does not exist, nothing to delete",
-            knowledge_id
-        );
-        return Ok(());
-    }
+        if !has_collection {
+            debug!(
+                "Collection {} does not exist, nothing to delete",
+                collection
+            );
+            continue;
+        }
 
-    vector_db
-        .delete_collection(knowledge_id)
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to delete collection: {}", e)))?;
+        vector_db
+            .delete_collection(&collection)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to delete collection: {}", e)))?;
+    }
 
     info!(
         "Successfully deleted collection for knowledge base {}",
@@ -194,11 +878,12 @@ pub async fn delete_knowledge_collection(
 /// Reset a knowledge base (delete and recreate collection)
 pub async fn reset_knowledge_vectors(
     vector_db: &Arc<dyn VectorDB>,
+    embedding_provider: &Arc<dyn EmbeddingProvider>,
     knowledge_id: &str,
 ) -> AppResult<()> {
     info!("Resetting vectors for knowledge base {}", knowledge_id);
 
-    delete_knowledge_collection(vector_db, knowledge_id).await?;
+    delete_knowledge_collection(vector_db, embedding_provider, knowledge_id).await?;
 
     info!(
         "Successfully reset vectors for knowledge base {}",
@@ -265,3 +950,651 @@ pub fn log_rag_disabled(operation: &str) {
         operation
     );
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::retrieval::embeddings::EmbeddingError;
+    use crate::retrieval::normalize::TextNormalization;
+    use crate::retrieval::vector::{GetResult, SearchResult, VectorError};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    pub(crate) struct FakeEmbeddings {
+        pub(crate) calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for FakeEmbeddings {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(texts.iter().map(|_| vec![0.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "fake"
+        }
+    }
+
+    /// Fails the `fail_on`th embedding call, like a backend going away mid-ingest
+    struct FlakyEmbeddings {
+        calls: AtomicUsize,
+        fail_on: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl EmbeddingProvider for FlakyEmbeddings {
+        async fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) + 1 == self.fail_on {
+                return Err(EmbeddingError::ApiError("connection reset".to_string()));
+            }
+            Ok(texts.iter().map(|_| vec![0.0; 3]).collect())
+        }
+
+        fn dimension(&self) -> usize {
+            3
+        }
+
+        fn model_name(&self) -> &str {
+            "fake"
+        }
+    }
+
+    /// Collections of items, filtered on `file_id` only
+    #[derive(Default)]
+    pub(crate) struct MemoryVectorDb {
+        collections: Mutex<HashMap<String, Vec<crate::retrieval::vector::VectorItem>>>,
+    }
+
+    fn matches(item: &crate::retrieval::vector::VectorItem, filter: &serde_json::Value) -> bool {
+        match filter.get("file_id") {
+            Some(id) => item.metadata.get("file_id") == Some(id),
+            None => true,
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl VectorDB for MemoryVectorDb {
+        async fn has_collection(&self, name: &str) -> Result<bool, VectorError> {
+            Ok(self.collections.lock().unwrap().contains_key(name))
+        }
+
+        async fn count(&self, name: &str) -> Result<usize, VectorError> {
+            Ok(self
+                .collections
+                .lock()
+                .unwrap()
+                .get(name)
+                .map_or(0, Vec::len))
+        }
+
+        async fn list_collections(&self) -> Result<Vec<String>, VectorError> {
+            Ok(self.collections.lock().unwrap().keys().cloned().collect())
+        }
+
+        async fn delete_collection(&self, name: &str) -> Result<(), VectorError> {
+            self.collections.lock().unwrap().remove(name);
+            Ok(())
+        }
+
+        async fn insert(
+            &self,
+            name: &str,
+            items: Vec<crate::retrieval::vector::VectorItem>,
+        ) -> Result<(), VectorError> {
+            self.upsert(name, items).await
+        }
+
+        async fn upsert(
+            &self,
+            name: &str,
+            items: Vec<crate::retrieval::vector::VectorItem>,
+        ) -> Result<(), VectorError> {
+            let mut collections = self.collections.lock().unwrap();
+            let collection = collections.entry(name.to_string()).or_default();
+            for item in items {
+                collection.retain(|existing| existing.id != item.id);
+                collection.push(item);
+            }
+            Ok(())
+        }
+
+        async fn search(
+            &self,
+            _name: &str,
+            _vectors: Vec<Vec<f32>>,
+            _limit: usize,
+            _filter: Option<&crate::retrieval::MetadataFilter>,
+        ) -> Result<SearchResult, VectorError> {
+            unimplemented!()
+        }
+
+        async fn query(
+            &self,
+            name: &str,
+            filter: serde_json::Value,
+            limit: Option<usize>,
+        ) -> Result<GetResult, VectorError> {
+            let collections = self.collections.lock().unwrap();
+            let ids: Vec<String> = collections
+                .get(name)
+                .into_iter()
+                .flatten()
+                .filter(|item| matches(item, &filter))
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|item| item.id.clone())
+                .collect();
+            Ok(GetResult {
+                ids: Some(vec![ids]),
+                documents: None,
+                metadatas: None,
+            })
+        }
+
+        async fn get(&self, name: &str) -> Result<GetResult, VectorError> {
+            self.query(name, json!({}), None).await
+        }
+
+        async fn delete(
+            &self,
+            name: &str,
+            ids: Option<Vec<String>>,
+            filter: Option<serde_json::Value>,
+        ) -> Result<(), VectorError> {
+            if let Some(collection) = self.collections.lock().unwrap().get_mut(name) {
+                collection.retain(|item| {
+                    let by_id = ids.as_ref().is_some_and(|ids| ids.contains(&item.id));
+                    let by_filter = filter.as_ref().is_some_and(|f| matches(item, f));
+                    !(by_id || by_filter)
+                });
+            }
+            Ok(())
+        }
+
+        async fn reset(&self) -> Result<(), VectorError> {
+            self.collections.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    async fn sync(
+        vector_db: &Arc<dyn VectorDB>,
+        embedding_provider: &Arc<dyn EmbeddingProvider>,
+        file_service: &FileService<'_>,
+        file_id: &str,
+    ) -> SyncOutcome {
+        sync_file_with_progress(
+            vector_db,
+            embedding_provider,
+            file_service,
+            file_id,
+            "kb1",
+            &|_| {},
+        )
+        .await
+        .unwrap()
+    }
+
+    fn reindexed(chunks: usize) -> SyncOutcome {
+        SyncOutcome::Reindexed(Indexed {
+            chunks,
+            truncated: false,
+        })
+    }
+
+    #[test]
+    fn test_index_state_changes_with_content_and_model() {
+        let provider = FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        };
+        let chunking = ChunkingConfig::default();
+        let state = IndexState::new("hello", &provider, &chunking, DistanceMetric::Cosine);
+        let meta = state.record(Some(json!({ "content_type": "text/plain" })), "kb1");
+
+        assert_eq!(meta["content_type"], "text/plain");
+        assert_eq!(IndexState::stored(Some(&meta), "kb1"), Some(state.clone()));
+        assert_eq!(IndexState::stored(Some(&meta), "kb2"), None);
+        assert_ne!(
+            IndexState::new("hello!", &provider, &chunking, DistanceMetric::Cosine),
+            state
+        );
+        assert_ne!(
+            IndexState::new("hello", &provider, &chunking, DistanceMetric::Euclidean),
+            state
+        );
+
+        let rechunked = ChunkingConfig {
+            chunk_size: 1024,
+            ..ChunkingConfig::default()
+        };
+        assert_ne!(
+            IndexState::new("hello", &provider, &rechunked, DistanceMetric::Cosine),
+            state
+        );
+
+        // Cleaning the text differently makes other chunks; the steps are recorded
+        let normalized = ChunkingConfig {
+            normalization: TextNormalization::parse("whitespace").unwrap(),
+            ..ChunkingConfig::default()
+        };
+        let normalized = IndexState::new("hello", &provider, &normalized, DistanceMetric::Cosine);
+        assert_ne!(normalized, state);
+        let meta = normalized.record(None, "kb1");
+        assert_eq!(meta["vector_index"]["kb1"]["normalization"], "whitespace");
+        assert_eq!(IndexState::stored(Some(&meta), "kb1"), Some(normalized));
+    }
+
+    #[test]
+    fn test_index_current_judged_from_meta() {
+        let provider = FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        };
+        let state = IndexState::new(
+            "hello",
+            &provider,
+            &ChunkingConfig::from_env(),
+            DistanceMetric::Cosine,
+        );
+        let mut file = File {
+            id: "f1".to_string(),
+            user_id: "u1".to_string(),
+            filename: "notes.txt".to_string(),
+            path: None,
+            data: Some(json!({ "content": "hello" })),
+            data_str: None,
+            meta: Some(state.record(None, "kb1")),
+            meta_str: None,
+            access_control: None,
+            access_control_str: None,
+            hash: None,
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        assert!(is_index_current(
+            &file,
+            &provider,
+            &ChunkingConfig::from_env(),
+            DistanceMetric::Cosine,
+            "kb1"
+        ));
+        assert!(!is_index_current(
+            &file,
+            &provider,
+            &ChunkingConfig::from_env(),
+            DistanceMetric::Cosine,
+            "kb2"
+        ));
+        assert!(!is_index_current(
+            &file,
+            &provider,
+            &ChunkingConfig::from_env(),
+            DistanceMetric::Dot,
+            "kb1"
+        ));
+
+        file.data = Some(json!({ "content": "hello, edited" }));
+        assert!(!is_index_current(
+            &file,
+            &provider,
+            &ChunkingConfig::from_env(),
+            DistanceMetric::Cosine,
+            "kb1"
+        ));
+        // Judging staleness is read-only
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_resume_point_requires_matching_unfinished_ingest() {
+        let progress = IngestProgress {
+            file_id: "f1".to_string(),
+            knowledge_id: "kb1".to_string(),
+            fingerprint: "abc:fake".to_string(),
+            chunks_total: 100,
+            chunks_done: 64,
+            status: IngestProgress::INTERRUPTED.to_string(),
+            error: None,
+            updated_at: 0,
+        };
+        assert_eq!(progress.resume_point("abc:fake", 100), 64);
+        assert_eq!(progress.resume_point("def:fake", 100), 0);
+        assert_eq!(progress.resume_point("abc:fake", 99), 0);
+
+        let completed = IngestProgress {
+            status: IngestProgress::COMPLETED.to_string(),
+            ..progress
+        };
+        assert_eq!(completed.resume_point("abc:fake", 100), 0);
+    }
+
+    #[test]
+    fn test_file_payload_is_filterable() {
+        let file = File {
+            id: "f1".to_string(),
+            user_id: "u1".to_string(),
+            filename: "handbook.pdf".to_string(),
+            path: None,
+            data: None,
+            data_str: None,
+            meta: Some(json!({
+                "content_type": "application/pdf",
+                "tags": ["policy", {"name": "hr"}]
+            })),
+            meta_str: None,
+            access_control: None,
+            access_control_str: None,
+            hash: None,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_000,
+        };
+        let fields: Vec<String> = ["tags", "created_at", "mime"]
+            .iter()
+            .map(|field| field.to_string())
+            .collect();
+        let payload = serde_json::Value::Object(file_payload(&file, &fields));
+
+        assert_eq!(
+            payload,
+            json!({
+                "created_at": 1_700_000_000,
+                "mime": "application/pdf",
+                "tags": "policy,hr",
+                "tag:policy": true,
+                "tag:hr": true
+            })
+        );
+
+        let filter = crate::retrieval::MetadataFilter::parse(&json!({
+            "tags": "policy",
+            "created_at": {"gte": 1_690_000_000, "lt": 1_710_000_000}
+        }))
+        .unwrap();
+        assert!(filter.matches(&payload));
+        assert!(!file_payload(&file, &["mime".to_string()]).contains_key("tag:policy"));
+    }
+
+    #[test]
+    fn test_chunk_limit_rejects_or_truncates() {
+        let chunks = || vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let reject = ChunkLimit {
+            max_chunks: 2,
+            truncate: false,
+        };
+        let truncate = ChunkLimit {
+            truncate: true,
+            ..reject
+        };
+
+        let mut rejected = chunks();
+        assert!(matches!(
+            reject.apply(&mut rejected, "big.txt"),
+            Err(AppError::BadRequest(_))
+        ));
+        let mut truncated = chunks();
+        assert!(truncate.apply(&mut truncated, "big.txt").unwrap());
+        assert_eq!(truncated, vec!["a", "b"]);
+
+        let unlimited = ChunkLimit {
+            max_chunks: 0,
+            truncate: false,
+        };
+        let mut untouched = chunks();
+        assert!(!unlimited.apply(&mut untouched, "big.txt").unwrap());
+        assert_eq!(untouched.len(), 3);
+
+        // A file cut at 2 chunks is stale once the limit goes up, not when it stays put
+        let meta = record_truncation(json!({}), Some((3, 2)));
+        assert_eq!(meta["chunks_truncated"], json!({ "total": 3, "kept": 2 }));
+        assert!(!truncate.raised_since(Some(&meta)));
+        assert!(unlimited.raised_since(Some(&meta)));
+        assert!(record_truncation(meta, None)
+            .get("chunks_truncated")
+            .is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_oversized_file_handled_per_chunk_limit_policy() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+        let file_id = uuid::Uuid::new_v4().to_string();
+        file_service
+            .create_file(&file_id, &user.id, "rows.csv", "rows.csv", None)
+            .await
+            .unwrap();
+        let mut content = "id,description\n".to_string();
+        for row in 0..400 {
+            content.push_str(&format!("{},{}\n", row, "x".repeat(90)));
+        }
+        file_service
+            .update_file_data(&file_id, json!({ "content": content }))
+            .await
+            .unwrap();
+        let embedding_provider: Arc<dyn EmbeddingProvider> = Arc::new(FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        });
+        let vector_db: Arc<dyn VectorDB> = Arc::new(MemoryVectorDb::default());
+        let index = |limit| {
+            let (vector_db, embedding_provider, file_service) =
+                (&vector_db, &embedding_provider, &file_service);
+            let file_id = file_id.clone();
+            async move {
+                let file = load_file(file_service, &file_id).await.unwrap();
+                index_file(
+                    vector_db,
+                    embedding_provider,
+                    file_service,
+                    file,
+                    "kb1",
+                    limit,
+                    &|_| {},
+                )
+                .await
+            }
+        };
+
+        let reject = ChunkLimit {
+            max_chunks: 10,
+            truncate: false,
+        };
+        assert!(matches!(index(reject).await, Err(AppError::BadRequest(_))));
+        assert!(!vector_db.has_collection("kb1").await.unwrap());
+
+        let truncate = ChunkLimit {
+            truncate: true,
+            ..reject
+        };
+        assert_eq!(
+            index(truncate).await.unwrap(),
+            Indexed {
+                chunks: 10,
+                truncated: true
+            }
+        );
+        assert_eq!(vector_db.count("kb1").await.unwrap(), 10);
+        let file = load_file(&file_service, &file_id).await.unwrap();
+        let recorded = &file.meta.unwrap()["chunks_truncated"];
+        assert_eq!(recorded["kept"], 10);
+        assert!(recorded["total"].as_u64().unwrap() > 10);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_unchanged_file_skipped_on_second_reindex() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+        let file_id = uuid::Uuid::new_v4().to_string();
+        file_service
+            .create_file(&file_id, &user.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+        file_service
+            .update_file_data(&file_id, json!({ "content": "Some notes to index." }))
+            .await
+            .unwrap();
+
+        let provider = Arc::new(FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        });
+        let embedding_provider: Arc<dyn EmbeddingProvider> = provider.clone();
+        let vector_db: Arc<dyn VectorDB> = Arc::new(MemoryVectorDb::default());
+
+        assert_eq!(
+            sync(&vector_db, &embedding_provider, &file_service, &file_id).await,
+            reindexed(1)
+        );
+        assert_eq!(
+            sync(&vector_db, &embedding_provider, &file_service, &file_id).await,
+            SyncOutcome::Skipped
+        );
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        // Missing vectors are rebuilt even though the file is unchanged
+        vector_db.delete_collection("kb1").await.unwrap();
+        assert_eq!(
+            sync(&vector_db, &embedding_provider, &file_service, &file_id).await,
+            reindexed(1)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_batch_ingest_reports_failures_per_file() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+
+        // Two files with text and one whose data has nothing to extract
+        let mut file_ids = Vec::new();
+        for data in [
+            json!({ "content": "First document." }),
+            json!({ "pages": 3 }),
+            json!({ "content": "Second document." }),
+        ] {
+            let file_id = uuid::Uuid::new_v4().to_string();
+            file_service
+                .create_file(&file_id, &user.id, "doc.txt", "doc.txt", None)
+                .await
+                .unwrap();
+            file_service.update_file_data(&file_id, data).await.unwrap();
+            file_ids.push(file_id);
+        }
+
+        let embedding_provider: Arc<dyn EmbeddingProvider> = Arc::new(FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        });
+        let vector_db: Arc<dyn VectorDB> = Arc::new(MemoryVectorDb::default());
+        let results = index_files_concurrently(
+            &vector_db,
+            &embedding_provider,
+            &file_service,
+            &file_ids,
+            "kb1",
+            2,
+            &IngestEvents::default(),
+        )
+        .await;
+
+        let completed: Vec<&str> = results
+            .iter()
+            .filter(|result| result.is_completed())
+            .map(|result| result.file_id.as_str())
+            .collect();
+        assert_eq!(completed, vec![file_ids[0].as_str(), file_ids[2].as_str()]);
+        assert_eq!(
+            results[0].status,
+            IngestStatus::Completed(Indexed {
+                chunks: 1,
+                truncated: false
+            })
+        );
+        assert_eq!(results[1].file_id, file_ids[1]);
+        assert!(matches!(results[1].status, IngestStatus::Failed { .. }));
+        assert_eq!(
+            serde_json::to_value(&results[1]).unwrap()["status"],
+            "failed"
+        );
+        assert_eq!(vector_db.count("kb1").await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_interrupted_ingest_resumes_from_checkpoint() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+        let file_id = uuid::Uuid::new_v4().to_string();
+        file_service
+            .create_file(&file_id, &user.id, "rows.csv", "rows.csv", None)
+            .await
+            .unwrap();
+        let mut content = "id,description\n".to_string();
+        for row in 0..400 {
+            content.push_str(&format!("{},{}\n", row, "x".repeat(90)));
+        }
+        file_service
+            .update_file_data(&file_id, json!({ "content": content }))
+            .await
+            .unwrap();
+        let vector_db: Arc<dyn VectorDB> = Arc::new(MemoryVectorDb::default());
+
+        // The third window's embedding call fails
+        let flaky: Arc<dyn EmbeddingProvider> = Arc::new(FlakyEmbeddings {
+            calls: AtomicUsize::new(0),
+            fail_on: 3,
+        });
+        assert!(sync_file_with_progress(
+            &vector_db,
+            &flaky,
+            &file_service,
+            &file_id,
+            "kb1",
+            &|_| {}
+        )
+        .await
+        .is_err());
+        let progress = file_service
+            .get_ingest_progress(&file_id, "kb1")
+            .await
+            .unwrap()
+            .unwrap();
+        let total = progress.chunks_total as usize;
+        assert!(total > 3 * INGEST_CHECKPOINT_CHUNKS);
+        assert_eq!(progress.status, IngestProgress::FAILED);
+        assert_eq!(progress.chunks_done as usize, 2 * INGEST_CHECKPOINT_CHUNKS);
+        assert_eq!(
+            vector_db.count("kb1").await.unwrap(),
+            2 * INGEST_CHECKPOINT_CHUNKS
+        );
+
+        // Resubmitting picks up at the checkpoint instead of re-embedding everything
+        let provider = Arc::new(FakeEmbeddings {
+            calls: AtomicUsize::new(0),
+        });
+        let embedding_provider: Arc<dyn EmbeddingProvider> = provider.clone();
+        assert_eq!(
+            sync(&vector_db, &embedding_provider, &file_service, &file_id).await,
+            reindexed(total)
+        );
+        let remaining_windows =
+            (total - 2 * INGEST_CHECKPOINT_CHUNKS).div_ceil(INGEST_CHECKPOINT_CHUNKS);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), remaining_windows);
+        assert_eq!(vector_db.count("kb1").await.unwrap(), total);
+
+        let progress = file_service
+            .get_ingest_progress(&file_id, "kb1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(progress.status, IngestProgress::COMPLETED);
+        assert_eq!(progress.chunks_done as usize, total);
+    }
+}
@@ -0,0 +1,241 @@
+/// Application metrics endpoint (Prometheus text format)
+use actix_web::{web, HttpResponse};
+use std::fmt::Write;
+
+use crate::middleware::concurrency::Priority;
+use crate::utils::circuit_breaker::CircuitState;
+use crate::AppState;
+
+// GET /api/metrics - Export runtime metrics (admin only)
+pub async fn get_metrics(state: web::Data<AppState>) -> HttpResponse {
+    let mut output = String::new();
+
+    write_concurrency_metrics(&mut output, &state);
+    write_circuit_metrics(&mut output, &state);
+    write_redis_metrics(&mut output, &state);
+    write_oauth_refresh_metrics(&mut output, &state);
+    write_payload_metrics(&mut output, &state);
+    write_stream_metrics(&mut output, &state);
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(output)
+}
+
+fn write_concurrency_metrics(output: &mut String, state: &AppState) {
+    let limiters = state.concurrency_limits.all();
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_in_flight Requests currently holding a concurrency slot"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_in_flight gauge");
+    for limiter in limiters {
+        let _ = writeln!(
+            output,
+            "concurrency_in_flight{{limiter=\"{}\"}} {}",
+            limiter.name(),
+            limiter.in_flight()
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_queued Requests waiting for a concurrency slot, by priority"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_queued gauge");
+    for limiter in limiters {
+        for priority in [Priority::High, Priority::Low] {
+            let _ = writeln!(
+                output,
+                "concurrency_queued{{limiter=\"{}\",priority=\"{}\"}} {}",
+                limiter.name(),
+                priority.as_str(),
+                limiter.queued_with(priority)
+            );
+        }
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_saturated Whether every concurrency slot is taken"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_saturated gauge");
+    for limiter in limiters {
+        let saturated =
+            limiter.max_concurrent() > 0 && limiter.in_flight() >= limiter.max_concurrent();
+        let _ = writeln!(
+            output,
+            "concurrency_saturated{{limiter=\"{}\"}} {}",
+            limiter.name(),
+            u8::from(saturated)
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_shed_total Low-priority waiters dropped for high-priority requests"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_shed_total counter");
+    for limiter in limiters {
+        let _ = writeln!(
+            output,
+            "concurrency_shed_total{{limiter=\"{}\"}} {}",
+            limiter.name(),
+            limiter.shed()
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_rejected_total Requests turned away by a full wait queue"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_rejected_total counter");
+    for limiter in limiters {
+        let _ = writeln!(
+            output,
+            "concurrency_rejected_total{{limiter=\"{}\"}} {}",
+            limiter.name(),
+            limiter.rejected()
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP concurrency_limit Configured concurrency limit (0 = unlimited)"
+    );
+    let _ = writeln!(output, "# TYPE concurrency_limit gauge");
+    for limiter in limiters {
+        let _ = writeln!(
+            output,
+            "concurrency_limit{{limiter=\"{}\"}} {}",
+            limiter.name(),
+            limiter.max_concurrent()
+        );
+    }
+}
+
+fn write_redis_metrics(output: &mut String, state: &AppState) {
+    if state.redis.is_none() {
+        return;
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP redis_degraded Whether Redis is unreachable and its users are on fallbacks"
+    );
+    let _ = writeln!(output, "# TYPE redis_degraded gauge");
+    let _ = writeln!(
+        output,
+        "redis_degraded {}",
+        u8::from(state.redis_guard.is_degraded())
+    );
+}
+
+fn write_circuit_metrics(output: &mut String, state: &AppState) {
+    let statuses = state.circuit_breakers.statuses();
+
+    let _ = writeln!(
+        output,
+        "# HELP upstream_circuit_state Upstream circuit state (0 = closed, 1 = half-open, 2 = open)"
+    );
+    let _ = writeln!(output, "# TYPE upstream_circuit_state gauge");
+    for status in &statuses {
+        let value = match status.state {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        };
+        let _ = writeln!(
+            output,
+            "upstream_circuit_state{{connection=\"{}\",state=\"{}\"}} {}",
+            status.connection,
+            status.state.as_str(),
+            value
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP upstream_circuit_failures Consecutive failures recorded for an upstream connection"
+    );
+    let _ = writeln!(output, "# TYPE upstream_circuit_failures gauge");
+    for status in &statuses {
+        let _ = writeln!(
+            output,
+            "upstream_circuit_failures{{connection=\"{}\"}} {}",
+            status.connection, status.failures
+        );
+    }
+}
+
+fn write_oauth_refresh_metrics(output: &mut String, state: &AppState) {
+    let counts = state.oauth_refresher.counts();
+
+    let _ = writeln!(
+        output,
+        "# HELP oauth_refresh_total Proactive OAuth token refreshes by outcome"
+    );
+    let _ = writeln!(output, "# TYPE oauth_refresh_total counter");
+    for (result, count) in [
+        ("success", counts.succeeded),
+        ("failure", counts.failed),
+        ("skipped", counts.skipped),
+    ] {
+        let _ = writeln!(
+            output,
+            "oauth_refresh_total{{result=\"{}\"}} {}",
+            result, count
+        );
+    }
+}
+
+fn write_payload_metrics(output: &mut String, state: &AppState) {
+    for (name, help, histogram) in [
+        (
+            "http_request_size_bytes",
+            "Request body sizes in bytes",
+            &state.payload_metrics.requests,
+        ),
+        (
+            "http_response_size_bytes",
+            "Response body sizes in bytes",
+            &state.payload_metrics.responses,
+        ),
+    ] {
+        let _ = writeln!(output, "# HELP {} {}", name, help);
+        let _ = writeln!(output, "# TYPE {} histogram", name);
+        for (bound, count) in histogram.buckets() {
+            let le = bound.map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(output, "{}_bucket{{le=\"{}\"}} {}", name, le, count);
+        }
+        let _ = writeln!(output, "{}_sum {}", name, histogram.sum());
+        let _ = writeln!(output, "{}_count {}", name, histogram.count());
+    }
+}
+
+fn write_stream_metrics(output: &mut String, state: &AppState) {
+    let _ = writeln!(
+        output,
+        "# HELP chat_streams_open Streaming chat completions currently open, by user"
+    );
+    let _ = writeln!(output, "# TYPE chat_streams_open gauge");
+    for (user_id, count) in state.stream_limits.counts() {
+        let _ = writeln!(
+            output,
+            "chat_streams_open{{user=\"{}\"}} {}",
+            user_id, count
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "# HELP chat_streams_limit Configured streams per user (0 = unlimited)"
+    );
+    let _ = writeln!(output, "# TYPE chat_streams_limit gauge");
+    let _ = writeln!(
+        output,
+        "chat_streams_limit {}",
+        state.config.read().unwrap().max_concurrent_streams_per_user
+    );
+}
@@ -1,4 +1,6 @@
+pub mod admin;
 pub mod audio;
+pub mod audit;
 pub mod auth;
 pub mod cache;
 pub mod channels;
@@ -11,9 +13,11 @@ pub mod folders;
 pub mod functions;
 pub mod groups;
 pub mod images;
+pub mod ingest_events; // Live ingestion progress over Socket.IO
 pub mod knowledge;
 pub mod knowledge_vector; // Vector DB operations for knowledge
 pub mod memories;
+pub mod metrics;
 pub mod models;
 pub mod notes;
 pub mod oauth;
@@ -30,7 +34,8 @@ pub mod utils;
 use actix_web::web;
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
-    cfg.service(web::scope("/audio").configure(audio::create_routes))
+    cfg.service(web::scope("/admin").configure(admin::create_routes))
+        .service(web::scope("/audio").configure(audio::create_routes))
         .service(web::scope("/auths").configure(auth::create_routes))
         .service(web::scope("/api/v1").configure(cache::configure))
         .service(web::scope("/channels").configure(channels::create_routes))
@@ -6,9 +6,11 @@ use std::collections::{HashMap, HashSet};
 use crate::error::{AppError, AppResult};
 use crate::middleware::{AuthMiddleware, AuthUser};
 use crate::models::model::{Model, ModelForm, ModelResponse, ModelUserResponse};
+use crate::services::audit::AuditService;
 use crate::services::group::GroupService;
 use crate::services::model::ModelService;
 use crate::services::user::UserService;
+use crate::utils::dry_run::{DryRunQuery, DryRunReport};
 use crate::utils::misc::{has_access, has_permission};
 use crate::AppState;
 
@@ -35,6 +37,110 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
     );
 }
 
+/// Access policy for a single model
+///
+/// Owners and admins have full access. Otherwise, a user without read access gets
+/// 404 as if the model did not exist; a user who can read it but lacks the requested
+/// `write` access gets 403.
+fn model_access_policy(
+    model: &Model,
+    user_id: &str,
+    is_admin: bool,
+    user_group_ids: &HashSet<String>,
+    access_type: &str,
+) -> AppResult<()> {
+    if is_admin || model.user_id == user_id {
+        return Ok(());
+    }
+
+    if !has_access(user_id, "read", &model.access_control, user_group_ids) {
+        return Err(AppError::NotFound("Model not found".to_string()));
+    }
+
+    if access_type != "read"
+        && !has_access(user_id, access_type, &model.access_control, user_group_ids)
+    {
+        return Err(AppError::Forbidden("Access prohibited".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Look up a model and apply [`model_access_policy`] for the authenticated user
+async fn get_accessible_model(
+    state: &AppState,
+    auth_user: &AuthUser,
+    id: &str,
+    access_type: &str,
+) -> AppResult<Model> {
+    let model = ModelService::new(&state.db)
+        .get_model_by_id(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Model not found".to_string()))?;
+
+    let is_admin = auth_user.user.role == "admin";
+    if is_admin || model.user_id == auth_user.user.id {
+        return Ok(model);
+    }
+
+    let group_service = GroupService::new(&state.db);
+    let user_group_ids: HashSet<String> = group_service
+        .get_groups_by_member_id(&auth_user.user.id)
+        .await?
+        .into_iter()
+        .map(|g| g.id)
+        .collect();
+
+    model_access_policy(
+        &model,
+        &auth_user.user.id,
+        is_admin,
+        &user_group_ids,
+        access_type,
+    )?;
+    Ok(model)
+}
+
+// GET /api/models/{id} - Get a single model's full config
+pub async fn get_model(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    let model = get_accessible_model(&state, &auth_user, &id, "read").await?;
+
+    Ok(HttpResponse::Ok().json(ModelResponse::from(model)))
+}
+
+// POST /api/models/{id} - Update a model (owner, admin or write access)
+pub async fn update_model(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+    form_data: web::Json<ModelForm>,
+) -> AppResult<HttpResponse> {
+    get_accessible_model(&state, &auth_user, &id, "write").await?;
+
+    let updated = ModelService::new(&state.db)
+        .update_model_by_id(&id, form_data.into_inner())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ModelResponse::from(updated)))
+}
+
+// DELETE /api/models/{id} - Delete a model (owner, admin or write access)
+pub async fn delete_model(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    get_accessible_model(&state, &auth_user, &id, "write").await?;
+
+    let result = ModelService::new(&state.db).delete_model_by_id(&id).await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
 // GET / - Get models for current user
 async fn get_models(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
     let model_service = ModelService::new(&state.db);
@@ -119,38 +225,81 @@ async fn get_base_models(
     Ok(HttpResponse::Ok().json(response))
 }
 
+/// Check the shape of a submitted model before it's persisted
+fn validate_model_form(form: &ModelForm) -> AppResult<()> {
+    form.validate().map_err(AppError::BadRequest)
+}
+
+/// Whether `base_model_id` names a model served by a connection or function
+///
+/// Checks the routing cache first and only fetches the upstream lists on a miss.
+async fn base_model_exists(state: &AppState, base_model_id: &str) -> AppResult<bool> {
+    if state
+        .models_cache
+        .read()
+        .unwrap()
+        .get(base_model_id)
+        .is_some()
+    {
+        return Ok(true);
+    }
+
+    let config = state.config.read().unwrap().clone();
+    let base_models = crate::services::models::ModelService::new(config)
+        .get_all_base_models(&state.db)
+        .await?;
+    Ok(base_models.iter().any(|model| model.id == base_model_id))
+}
+
 // POST /create - Create a new model
-async fn create_model(
+pub async fn create_model(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     form_data: web::Json<ModelForm>,
 ) -> AppResult<HttpResponse> {
-    // Check user permissions
-    if auth_user.user.role != "admin" {
-        let config = state.config.read().unwrap();
-        let user_permissions = config.user_permissions.clone();
-        drop(config);
+    let user_permissions = state.config.read().unwrap().user_permissions.clone();
+    let is_admin = auth_user.user.role == "admin";
 
-        // Check if user has workspace.models permission
-        if !has_permission(&auth_user.user.id, "workspace.models", &user_permissions) {
-            return Err(AppError::Forbidden("Permission denied".to_string()));
-        }
+    // Check if user has workspace.models permission
+    if !is_admin && !has_permission(&auth_user.user.id, "workspace.models", &user_permissions) {
+        return Err(AppError::Forbidden("Permission denied".to_string()));
     }
 
+    let mut form = form_data.into_inner();
+    validate_model_form(&form)?;
+
     let model_service = ModelService::new(&state.db);
 
     // Check if model ID already exists
-    if model_service
-        .get_model_by_id(&form_data.id)
-        .await?
-        .is_some()
-    {
+    if model_service.get_model_by_id(&form.id).await?.is_some() {
         return Err(AppError::BadRequest("Model ID already taken".to_string()));
     }
 
-    let model = model_service
-        .insert_new_model(form_data.into_inner(), &auth_user.user.id)
-        .await?;
+    if let Some(base_model_id) = form.base_model_id.as_deref() {
+        if !base_model_exists(&state, base_model_id).await? {
+            return Err(AppError::BadRequest(format!(
+                "Base model {} not found",
+                base_model_id
+            )));
+        }
+    }
+
+    // Models are private unless the user may share them publicly
+    if form.access_control.is_none()
+        && !is_admin
+        && !has_permission(
+            &auth_user.user.id,
+            "sharing.public_models",
+            &user_permissions,
+        )
+    {
+        form.access_control = Some(json!({}));
+    }
+
+    let model = model_service.create_model(form, &auth_user.user.id).await?;
+
+    // Model lists now include the new model
+    state.models_cache.write().unwrap().clear();
 
     Ok(HttpResponse::Ok().json(ModelResponse::from(model)))
 }
@@ -275,9 +424,11 @@ async fn get_model_by_id(
     // Check access
     let config = state.config.read().unwrap();
     let bypass_admin_access_control = config.bypass_admin_access_control.unwrap_or(false);
+    let audit_admin_bypass = config.audit_admin_bypass;
     drop(config);
 
-    if auth_user.user.role == "admin" && bypass_admin_access_control {
+    let admin_bypass = auth_user.user.role == "admin" && bypass_admin_access_control;
+    if admin_bypass && !audit_admin_bypass {
         return Ok(HttpResponse::Ok().json(ModelResponse::from(model)));
     }
 
@@ -301,6 +452,16 @@ async fn get_model_by_id(
         return Ok(HttpResponse::Ok().json(ModelResponse::from(model)));
     }
 
+    if admin_bypass {
+        if let Err(e) = AuditService::new(&state.db)
+            .record_admin_bypass(&auth_user.user.id, "model", &model.id, "read")
+            .await
+        {
+            tracing::warn!("Failed to audit admin bypass of model {}: {}", model.id, e);
+        }
+        return Ok(HttpResponse::Ok().json(ModelResponse::from(model)));
+    }
+
     Err(AppError::Forbidden("Access denied".to_string()))
 }
 
@@ -466,17 +627,102 @@ async fn delete_model_by_id(
     Ok(HttpResponse::Ok().json(result))
 }
 
-// DELETE /delete/all - Delete all models (admin only)
+// DELETE /delete/all - Delete all models (admin only); `?dry_run=true` lists them instead
 async fn delete_all_models(
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    query: web::Query<DryRunQuery>,
 ) -> AppResult<HttpResponse> {
     if auth_user.user.role != "admin" {
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
     let model_service = ModelService::new(&state.db);
+    if query.is_dry_run(false) {
+        let ids = model_service.get_all_model_ids().await?;
+        return Ok(HttpResponse::Ok().json(DryRunReport::new(ids)));
+    }
     let result = model_service.delete_all_models().await?;
 
     Ok(HttpResponse::Ok().json(result))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    fn model(access_control: Option<serde_json::Value>) -> Model {
+        Model {
+            id: "custom-model".to_string(),
+            user_id: "owner".to_string(),
+            base_model_id: Some("gpt-4o".to_string()),
+            name: "Custom".to_string(),
+            params: json!({}),
+            meta: None,
+            access_control,
+            is_active: true,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    fn status(result: AppResult<()>) -> u16 {
+        result.unwrap_err().status_code().as_u16()
+    }
+
+    fn form(params: serde_json::Value, meta: serde_json::Value) -> ModelForm {
+        ModelForm {
+            id: "custom-model".to_string(),
+            base_model_id: Some("gpt-4o".to_string()),
+            name: "Custom".to_string(),
+            params,
+            meta,
+            access_control: None,
+        }
+    }
+
+    #[test]
+    fn test_validate_model_form() {
+        assert!(validate_model_form(&form(json!({"temperature": 0.2}), json!({}))).is_ok());
+        assert!(validate_model_form(&form(json!("temperature=0.2"), json!({}))).is_err());
+        assert!(validate_model_form(&form(json!({}), json!([1, 2]))).is_err());
+
+        let mut unnamed = form(json!({}), json!({}));
+        unnamed.name = "  ".to_string();
+        assert!(validate_model_form(&unnamed).is_err());
+    }
+
+    #[test]
+    fn test_inaccessible_model_is_not_found() {
+        let private = model(Some(json!({})));
+        let groups = HashSet::new();
+
+        for access_type in ["read", "write"] {
+            let result = model_access_policy(&private, "stranger", false, &groups, access_type);
+            assert_eq!(status(result), 404);
+        }
+    }
+
+    #[test]
+    fn test_readers_cannot_modify() {
+        let shared = model(Some(json!({
+            "read": { "group_ids": ["readers"], "user_ids": [] },
+            "write": { "group_ids": [], "user_ids": [] },
+        })));
+        let groups: HashSet<String> = ["readers".to_string()].into_iter().collect();
+
+        assert!(model_access_policy(&shared, "reader", false, &groups, "read").is_ok());
+        let result = model_access_policy(&shared, "reader", false, &groups, "write");
+        assert_eq!(status(result), 403);
+    }
+
+    #[test]
+    fn test_owner_and_admin_have_full_access() {
+        let private = model(Some(json!({})));
+        let groups = HashSet::new();
+
+        assert!(model_access_policy(&private, "owner", false, &groups, "write").is_ok());
+        assert!(model_access_policy(&private, "admin", true, &groups, "write").is_ok());
+    }
+}
@@ -1,12 +1,17 @@
 /// OAuth Routes
 /// Handles OAuth login and callback endpoints
 use crate::error::{AppError, AppResult};
+use crate::routes::auth::{self, SIGNIN_ACTION};
+use crate::services::audit;
+use crate::services::group::GroupSyncSummary;
+use crate::services::mfa::MfaService;
 use crate::services::oauth_provider::OAuthUserInfo;
-use crate::utils::auth::create_jwt;
+use crate::utils::redirect;
 use crate::AppState;
 use actix_web::{cookie::Cookie, web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info};
+use serde_json::json;
+use tracing::{debug, error, info, warn};
 
 /// Query parameters for OAuth login endpoint
 #[derive(Debug, Deserialize)]
@@ -60,17 +65,26 @@ async fn oauth_login(
         )));
     }
 
-    // Check if OAuth signup is enabled
-    let config = state.config.read().unwrap();
-    if !config.enable_oauth_signup {
-        return Err(AppError::Forbidden(
-            "OAuth authentication is disabled".to_string(),
-        ));
-    }
-    drop(config);
+    let redirect_path = {
+        // Check if OAuth signup is enabled
+        let config = state.config.read().unwrap();
+        if !config.enable_oauth_signup {
+            return Err(AppError::Forbidden(
+                "OAuth authentication is disabled".to_string(),
+            ));
+        }
+
+        // Only remember redirects the post-login redirect would follow
+        query.redirect_url.clone().filter(|target| {
+            let allowed = redirect::allowed_redirect(&config, target).is_some();
+            if !allowed {
+                warn!("Ignoring disallowed OAuth redirect_url: {}", target);
+            }
+            allowed
+        })
+    };
 
     // Initiate OAuth flow
-    let redirect_path = query.redirect_url.clone();
     let authorization_url = state
         .oauth_manager
         .initiate_login(&provider_name, redirect_path)
@@ -99,7 +113,7 @@ async fn oauth_callback(
     );
 
     // Handle OAuth callback
-    let (returned_provider, token_response, user_info) = state
+    let (returned_provider, token_response, user_info, redirect_path) = state
         .oauth_manager
         .handle_callback(&query.code, &query.state)
         .await
@@ -124,10 +138,15 @@ async fn oauth_callback(
     // Find or create user
     let user = find_or_create_user(&state, &provider_name, &user_info).await?;
 
-    // Sync user groups from OAuth (if enabled)
-    if let Err(e) = sync_user_groups_from_oauth(&state, &user.id, &user_info).await {
-        tracing::warn!("Failed to sync user groups from OAuth: {}", e);
-        // Non-fatal error, continue with authentication
+    // Sync user groups from OAuth (if enabled); failures don't block sign-in
+    let summary = sync_user_groups_from_oauth(&state, &user.id, &user_info).await;
+    if !summary.failed.is_empty() {
+        tracing::warn!(
+            "OAuth group sync for user {}: {} joined, {} failed",
+            user.id,
+            summary.joined.len(),
+            summary.failed.len()
+        );
     }
 
     // Store id_token for cookie (if available)
@@ -139,22 +158,41 @@ async fn oauth_callback(
         .create_session(&user.id, &provider_name, token_response)
         .await?;
 
-    // Generate JWT token
+    // The provider can't take an MFA code, so users with MFA finish signing in with one
+    // at /auths/mfa/challenge
+    let mfa_enabled = {
+        let mfa = {
+            let config = state.config.read().unwrap();
+            MfaService::new(&state.db, &config)?
+        };
+        mfa.is_enabled(&user.id).await?
+    };
+    if mfa_enabled {
+        let config = state.config.read().unwrap();
+        let mut challenge = auth::mfa_challenge_cookie(&config, &user.id)?;
+        challenge.set_secure(req.connection_info().scheme() == "https");
+        let frontend = config.frontend_base_url.trim_end_matches('/');
+        info!(
+            "OAuth login for user {} is waiting for an MFA code",
+            user.id
+        );
+        return Ok(HttpResponse::Found()
+            .cookie(challenge)
+            .append_header(("Location", format!("{}/auth?mfa_required=true", frontend)))
+            .finish());
+    }
+
+    // Admins owing MFA setup get a session that can only set it up
     let config = state.config.read().unwrap();
-    let jwt_token = create_jwt(&user.id, &config.webui_secret_key, &config.jwt_expires_in)?;
+    let mfa_setup_pending = user.role == "admin" && config.require_mfa_for_admins;
+    let (_, mut auth_cookie) = auth::new_session(&config, user.clone(), mfa_setup_pending)?;
     drop(config);
 
     // Create cookies
     let mut response = HttpResponse::Found();
 
     // Set auth cookie
-    let auth_cookie = Cookie::build("token", jwt_token.clone())
-        .path("/")
-        .http_only(true)
-        .secure(req.connection_info().scheme() == "https")
-        .same_site(actix_web::cookie::SameSite::Lax)
-        .finish();
-
+    auth_cookie.set_secure(req.connection_info().scheme() == "https");
     response.cookie(auth_cookie);
 
     // Set ID token cookie if enabled and available
@@ -174,8 +212,8 @@ async fn oauth_callback(
         }
     }
 
-    // Determine redirect URL
-    let redirect_url = format!("{}/", config.frontend_base_url);
+    // Re-check the stored redirect; the allowlist may have changed since login
+    let redirect_url = redirect::post_login_redirect(&config, redirect_path.as_deref());
     drop(config);
 
     response.append_header(("Location", redirect_url));
@@ -184,6 +222,21 @@ async fn oauth_callback(
         "OAuth login completed for user: {} ({})",
         user.name, user.email
     );
+    let client_ip = req
+        .connection_info()
+        .realip_remote_addr()
+        .map(str::to_string);
+    audit::spawn_record(
+        state.clone(),
+        user.id.clone(),
+        SIGNIN_ACTION.to_string(),
+        "user",
+        user.id.clone(),
+        audit::with_region(
+            Some(json!({ "method": "oauth", "provider": provider_name })),
+            client_ip.as_deref(),
+        ),
+    );
 
     Ok(response.finish())
 }
@@ -260,76 +313,94 @@ async fn find_or_create_user(
             "OAuth signup is disabled. Contact administrator.".to_string(),
         ));
     }
-
-    // Check if this is the first user
-    let user_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM \"user\"")
-        .fetch_one(state.db.pool())
-        .await?;
-
-    let is_first_user = user_count == 0;
-
-    // Determine user role
-    let role = state
-        .oauth_manager
-        .determine_user_role(user_info, is_first_user);
-
-    // If role is "pending", user is not allowed
-    if role == "pending" {
-        return Err(AppError::Forbidden(
-            "Your account does not have the required roles to access this application.".to_string(),
-        ));
-    }
+    let proxy_pictures = config.oauth_proxy_pictures;
+    drop(config);
 
     // Extract username
     let username = extract_username(user_info, email);
 
-    // Download and encode profile picture if available
-    let profile_image_url = if let Some(picture_url) = &user_info.picture {
-        match download_and_encode_profile_picture(state, picture_url).await {
-            Ok(data_url) => data_url,
-            Err(e) => {
-                tracing::warn!("Failed to download profile picture: {}", e);
-                String::new() // Empty string on failure
+    // Keep the remote URL when proxying, otherwise download and encode the picture
+    let profile_image_url = match &user_info.picture {
+        Some(picture_url) if proxy_pictures => picture_url.clone(),
+        Some(picture_url) => {
+            match crate::services::avatar::fetch_picture(picture_url).await {
+                Ok(picture) => picture.to_data_url(),
+                Err(e) => {
+                    tracing::warn!("Failed to download profile picture: {}", e);
+                    String::new() // Empty string on failure
+                }
             }
         }
-    } else {
-        String::new()
+        None => String::new(),
     };
 
     let user_id = uuid::Uuid::new_v4().to_string();
     let user_name = user_info.name.clone().unwrap_or_else(|| username.clone());
-    let current_time = chrono::Utc::now().timestamp();
 
-    drop(config);
-
-    // Create new user
-    let user = sqlx::query_as::<_, crate::models::user::User>(
-        r#"
-        INSERT INTO "user" (
-            id, name, email, role, profile_image_url, oauth_sub, 
-            last_active_at, created_at, updated_at
+    // Create new user; first-user (admin) determination is race-safe inside the service
+    let mut role_decision = None;
+    let (user, created) = crate::services::user::UserService::new(&state.db)
+        .create_user_with_first_user_role(
+            &user_id,
+            &user_name,
+            email,
+            &profile_image_url,
+            Some(&oauth_sub),
+            |is_first_user| {
+                let decision = state
+                    .oauth_manager
+                    .determine_user_role(user_info, is_first_user);
+
+                // If role is "pending", user is not allowed
+                if decision.role == "pending" {
+                    return Err(AppError::Forbidden(
+                        "Your account does not have the required roles to access this application."
+                            .to_string(),
+                    ));
+                }
+                let role = decision.role.clone();
+                role_decision = Some(decision);
+                Ok(role)
+            },
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-        RETURNING *
-        "#,
-    )
-    .bind(&user_id)
-    .bind(&user_name)
-    .bind(email)
-    .bind(&role)
-    .bind(&profile_image_url)
-    .bind(&oauth_sub)
-    .bind(current_time)
-    .bind(current_time)
-    .bind(current_time)
-    .fetch_one(state.db.pool())
-    .await?;
+        .await?;
+    if !created {
+        // A concurrent callback for the same identity created the user
+        debug!("Found existing user by oauth_sub: {}", user.id);
+        return Ok(user);
+    }
 
     info!(
         "Created new user from OAuth: {} ({}) with role: {}",
         user.name, user.email, user.role
     );
 
+    if let Some(decision) = &role_decision {
+        if let Err(e) = state
+            .oauth_manager
+            .record_role_decision(&state.db, &user, provider, decision)
+            .await
+        {
+            tracing::error!("Failed to audit OAuth role for {}: {}", user.id, e);
+        }
+    }
+
+    if proxy_pictures && !profile_image_url.is_empty() {
+        // Marks the URL as provider-supplied, the only kind the avatar proxy fetches
+        if let Err(e) = crate::services::user::UserService::new(&state.db)
+            .set_info_field(
+                &user.id,
+                crate::services::avatar::OAUTH_PICTURE_KEY,
+                &serde_json::json!(profile_image_url),
+            )
+            .await
+        {
+            tracing::error!("Failed to record OAuth picture for {}: {}", user.id, e);
+        }
+    }
+
+    add_to_default_groups(state, &user.id).await;
+
     // Send webhook notification
     send_oauth_user_signup_webhook(state, &user).await;
 
@@ -352,6 +423,15 @@ fn extract_username(user_info: &OAuthUserInfo, email: &str) -> String {
     email.split('@').next().unwrap_or(email).to_string()
 }
 
+/// Add a newly created OAuth user to DEFAULT_USER_GROUPS
+async fn add_to_default_groups(state: &web::Data<AppState>, user_id: &str) {
+    let config = state.config.read().unwrap().clone();
+    // Failures are logged per group by the service
+    crate::services::group::GroupService::new(&state.db)
+        .add_user_to_default_groups(user_id, &config)
+        .await;
+}
+
 /// Send webhook notification for OAuth user signup
 async fn send_oauth_user_signup_webhook(
     state: &web::Data<AppState>,
@@ -359,6 +439,7 @@ async fn send_oauth_user_signup_webhook(
 ) {
     let config = state.config.read().unwrap();
     let webhook_url = config.webhook_url.clone();
+    let format = crate::utils::webhook::WebhookFormat::configured(&config);
     drop(config);
 
     if webhook_url.is_none() || webhook_url.as_ref().unwrap().is_empty() {
@@ -378,185 +459,50 @@ async fn send_oauth_user_signup_webhook(
         }),
     );
 
-    if let Err(e) = crate::utils::webhook::post_webhook(&url, payload).await {
+    if let Err(e) = crate::utils::webhook::post_webhook(&url, &format, payload).await {
         tracing::warn!("Failed to send OAuth user signup webhook: {}", e);
     } else {
         debug!("Sent OAuth user signup webhook for user: {}", user.id);
     }
 }
 
-/// Download and encode profile picture to base64 data URL
-async fn download_and_encode_profile_picture(
-    state: &web::Data<AppState>,
-    picture_url: &str,
-) -> AppResult<String> {
-    // Download the image
-    let response = state
-        .http_client
-        .get(picture_url)
-        .timeout(std::time::Duration::from_secs(10))
-        .send()
-        .await
-        .map_err(|e| {
-            AppError::ExternalServiceError(format!("Failed to download profile picture: {}", e))
-        })?;
-
-    if !response.status().is_success() {
-        return Err(AppError::ExternalServiceError(format!(
-            "Failed to download profile picture: HTTP {}",
-            response.status()
-        )));
-    }
-
-    // Get content type
-    let content_type = response
-        .headers()
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("image/png")
-        .to_string();
-
-    // Read image bytes
-    let bytes = response.bytes().await.map_err(|e| {
-        AppError::ExternalServiceError(format!("Failed to read profile picture: {}", e))
-    })?;
-
-    // Limit image size to 5MB
-    if bytes.len() > 5 * 1024 * 1024 {
-        return Err(AppError::BadRequest(
-            "Profile picture is too large (max 5MB)".to_string(),
-        ));
-    }
-
-    // Encode to base64
-    use base64::Engine;
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&bytes);
-
-    // Create data URL
-    Ok(format!("data:{};base64,{}", content_type, base64_data))
-}
-
-/// Sync user groups from OAuth claims
+/// Sync user groups from OAuth claims, best effort; see the summary for what failed
 async fn sync_user_groups_from_oauth(
     state: &web::Data<AppState>,
     user_id: &str,
     user_info: &OAuthUserInfo,
-) -> AppResult<()> {
+) -> GroupSyncSummary {
     let config = state.config.read().unwrap();
 
     // Check if group management is enabled
     if !config.enable_oauth_group_management {
-        return Ok(());
+        return GroupSyncSummary::default();
     }
 
-    // Extract groups from OAuth claims
-    let oauth_groups = state.oauth_manager.extract_groups(user_info);
+    // Extract groups from OAuth claims, less blocked ones, up to the sync limit
+    let allowed_groups = state.oauth_manager.groups_to_sync(
+        user_info,
+        &config.oauth_blocked_groups,
+        config.oauth_max_synced_groups,
+    );
 
-    if oauth_groups.is_empty() {
-        debug!("No groups found in OAuth claims for user {}", user_id);
-        return Ok(());
+    if allowed_groups.is_empty() {
+        debug!("No groups to sync from OAuth claims for user {}", user_id);
+        return GroupSyncSummary::default();
     }
 
     info!(
         "Syncing {} OAuth groups for user {}",
-        oauth_groups.len(),
+        allowed_groups.len(),
         user_id
     );
 
-    // Filter out blocked groups
-    let blocked_groups = &config.oauth_blocked_groups;
-    let allowed_groups: Vec<String> = oauth_groups
-        .into_iter()
-        .filter(|g| !blocked_groups.contains(g))
-        .collect();
-
-    if allowed_groups.is_empty() {
-        debug!("All OAuth groups are blocked for user {}", user_id);
-        return Ok(());
-    }
-
+    let create_missing = config.enable_oauth_group_creation;
     drop(config);
 
-    // Get or create groups
-    for group_name in allowed_groups {
-        // Check if group already exists by name
-        let existing_group =
-            sqlx::query_scalar::<_, String>(r#"SELECT id FROM "group" WHERE name = $1"#)
-                .bind(&group_name)
-                .fetch_optional(state.db.pool())
-                .await?;
-
-        let group_id = if let Some(group_id) = existing_group {
-            group_id
-        } else {
-            // Create group if group creation is enabled
-            let config = state.config.read().unwrap();
-            if !config.enable_oauth_group_creation {
-                debug!("Skipping group creation for '{}' (disabled)", group_name);
-                drop(config);
-                continue;
-            }
-            drop(config);
-
-            let new_group_id = uuid::Uuid::new_v4().to_string();
-            let current_time = chrono::Utc::now().timestamp();
-
-            sqlx::query(
-                r#"
-                INSERT INTO "group" (id, user_id, name, description, meta, permissions, user_ids, created_at, updated_at)
-                VALUES ($1, $2, $3, $4, NULL, NULL, '[]'::jsonb, $5, $6)
-                ON CONFLICT (name) DO NOTHING
-                "#
-            )
-            .bind(&new_group_id)
-            .bind(user_id) // Creator is the OAuth user
-            .bind(&group_name)
-            .bind(format!("Auto-created from OAuth: {}", group_name))
-            .bind(current_time)
-            .bind(current_time)
-            .execute(state.db.pool())
-            .await?;
-
-            info!("Created new group from OAuth: {}", group_name);
-            new_group_id
-        };
-
-        // Add user to group if not already a member
-        let is_member = sqlx::query_scalar::<_, bool>(
-            r#"
-            SELECT EXISTS(
-                SELECT 1 FROM "group" 
-                WHERE id = $1 
-                AND user_ids::jsonb @> $2::jsonb
-            )
-            "#,
-        )
-        .bind(&group_id)
-        .bind(serde_json::json!([user_id]).to_string())
-        .fetch_one(state.db.pool())
-        .await?;
-
-        if !is_member {
-            // Add user to group's user_ids array
-            sqlx::query(
-                r#"
-                UPDATE "group"
-                SET user_ids = COALESCE(user_ids, '[]'::jsonb) || $2::jsonb,
-                    updated_at = $3
-                WHERE id = $1
-                "#,
-            )
-            .bind(&group_id)
-            .bind(serde_json::json!([user_id]).to_string())
-            .bind(chrono::Utc::now().timestamp())
-            .execute(state.db.pool())
-            .await?;
-
-            info!("Added user {} to group {}", user_id, group_name);
-        }
-    }
-
-    Ok(())
+    crate::services::group::GroupService::new(&state.db)
+        .add_user_to_groups_by_name(user_id, &allowed_groups, create_missing, "OAuth")
+        .await
 }
 
 #[cfg(test)]
@@ -1,11 +1,32 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use futures::stream::StreamExt;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Instant;
 
 use crate::{
     error::AppError,
     middleware::{AuthMiddleware, AuthUser},
+    retrieval::chunking::count_tokens_approx,
+    services::group::GroupService,
+    services::model::ModelService,
+    services::usage::{self, StreamUsageTracker, UsageService},
+    utils::auto_title,
+    utils::chat_cache::{self, ChatCache},
     utils::chat_completion::{self, StreamingContext},
+    utils::circuit_breaker::{self, CircuitBreakerSettings},
+    utils::forward_headers,
+    utils::history::HistoryLimit,
+    utils::image_policy::{self, ImagePolicy},
+    utils::model_access::ModelAccess,
+    utils::model_queue::ModelRateLimit,
+    utils::model_routing::{self, ModelRoutingRule},
+    utils::models_cache::{self, ModelRoute},
+    utils::moderation::{self, Moderator},
+    utils::param_policy::ParamPolicy,
+    utils::structured_output::{self, SchemaCheck},
+    utils::time::current_timestamp_seconds,
+    utils::upstream_retry::{self, RetrySettings},
     AppState,
 };
 
@@ -19,6 +40,12 @@ struct OpenAIConfigResponse {
     openai_api_keys: Vec<String>,
     #[serde(rename = "OPENAI_API_CONFIGS")]
     openai_api_configs: serde_json::Value,
+    /// Left unchanged on update when omitted
+    #[serde(rename = "MODEL_ALIASES", default)]
+    model_aliases: Option<BTreeMap<String, String>>,
+    /// Left unchanged on update when omitted
+    #[serde(rename = "MODEL_ROUTING_RULES", default)]
+    model_routing_rules: Option<Vec<ModelRoutingRule>>,
 }
 
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
@@ -53,6 +80,8 @@ async fn get_config(
         openai_api_base_urls: config.openai_api_base_urls.clone(),
         openai_api_keys: config.openai_api_keys.clone(),
         openai_api_configs: config.openai_api_configs.clone(),
+        model_aliases: Some(config.model_aliases.clone()),
+        model_routing_rules: Some(config.model_routing_rules.clone()),
     }))
 }
 
@@ -66,6 +95,18 @@ async fn update_config(
         return Err(AppError::Forbidden("Admin access required".to_string()));
     }
 
+    if let Some(rules) = &form_data.model_routing_rules {
+        // Targets are checked against the connections being saved
+        let mut config = state.config.read().unwrap().clone();
+        config.openai_api_base_urls = form_data.openai_api_base_urls.clone();
+        config.openai_api_keys = form_data.openai_api_keys.clone();
+        config.openai_api_configs = form_data.openai_api_configs.clone();
+        if let Some(model_aliases) = &form_data.model_aliases {
+            config.model_aliases = model_aliases.clone();
+        }
+        validate_routing_rules(&state, &config, rules).await?;
+    }
+
     // Update in-memory config
     {
         let mut config = state.config.write().unwrap();
@@ -89,6 +130,16 @@ async fn update_config(
         }
 
         config.openai_api_configs = form_data.openai_api_configs.clone();
+        if let Some(model_aliases) = &form_data.model_aliases {
+            config.model_aliases = model_aliases
+                .iter()
+                .map(|(alias, target)| (alias.trim().to_string(), target.trim().to_string()))
+                .filter(|(alias, target)| !alias.is_empty() && !target.is_empty())
+                .collect();
+        }
+        if let Some(model_routing_rules) = &form_data.model_routing_rules {
+            config.model_routing_rules = model_routing_rules.clone();
+        }
     }
 
     // Persist to database (best-effort, like Python)
@@ -97,7 +148,9 @@ async fn update_config(
         "enable": config.enable_openai_api,
         "api_base_urls": config.openai_api_base_urls,
         "api_keys": config.openai_api_keys,
-        "api_configs": config.openai_api_configs
+        "api_configs": config.openai_api_configs,
+        "model_aliases": config.model_aliases,
+        "model_routing_rules": config.model_routing_rules
     });
 
     let _ = crate::services::ConfigService::update_section(&state.db, "openai", openai_json).await;
@@ -107,24 +160,81 @@ async fn update_config(
         openai_api_base_urls: config.openai_api_base_urls.clone(),
         openai_api_keys: config.openai_api_keys.clone(),
         openai_api_configs: config.openai_api_configs.clone(),
+        model_aliases: Some(config.model_aliases.clone()),
+        model_routing_rules: Some(config.model_routing_rules.clone()),
     }))
 }
 
-// Get all OpenAI models from all configured endpoints
-async fn get_models(
-    state: web::Data<AppState>,
-    _auth_user: AuthUser,
-) -> Result<HttpResponse, AppError> {
-    let config = state.config.read().unwrap();
+/// Reject routing rules that are malformed or send chats to a model that doesn't exist
+///
+/// A target exists if a connection serves it (cached, by prefix, or in a fresh model
+/// list), it's an alias, or it's a custom model.
+async fn validate_routing_rules(
+    state: &web::Data<AppState>,
+    config: &crate::config::Config,
+    rules: &[ModelRoutingRule],
+) -> Result<(), AppError> {
+    let mut errors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (field, message) in model_routing::validate_rules(rules) {
+        errors
+            .entry(format!("MODEL_ROUTING_RULES{}", field))
+            .or_default()
+            .push(message);
+    }
 
-    if !config.enable_openai_api {
-        return Ok(HttpResponse::Ok().json(serde_json::json!({
-            "data": []
-        })));
+    let mut connection_models: Option<Vec<String>> = None;
+    for (i, rule) in rules.iter().enumerate() {
+        let model = rule.model.trim();
+        if model.is_empty() {
+            continue;
+        }
+        let served = {
+            let cache = state.models_cache.read().unwrap();
+            cache.get(model).is_some() || cache.resolve(model).is_some()
+        };
+        if served
+            || models_cache::route_by_prefix(config, model).is_some()
+            || config.model_aliases.contains_key(model)
+        {
+            continue;
+        }
+        if connection_models.is_none() {
+            connection_models = Some(
+                fetch_connection_models(config)
+                    .await
+                    .into_iter()
+                    .filter_map(|(model, _)| model.get("id")?.as_str().map(String::from))
+                    .collect(),
+            );
+        }
+        if connection_models
+            .as_ref()
+            .is_some_and(|ids| ids.iter().any(|id| id == model))
+            || ModelService::new(&state.db)
+                .get_model_by_id(model)
+                .await?
+                .is_some()
+        {
+            continue;
+        }
+        errors
+            .entry(format!("MODEL_ROUTING_RULES[{}].model", i))
+            .or_default()
+            .push(format!("Model '{}' not found", model));
     }
 
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::ValidationError(crate::error::FieldErrors(errors)))
+    }
+}
+
+/// Fetch and merge the model lists of every enabled OpenAI connection
+async fn fetch_connection_models(
+    config: &crate::config::Config,
+) -> Vec<(serde_json::Value, ModelRoute)> {
     let mut all_models = Vec::new();
-    let client = reqwest::Client::new();
 
     // Fetch models from each configured OpenAI endpoint
     for (idx, url) in config.openai_api_base_urls.iter().enumerate() {
@@ -148,6 +258,13 @@ async fn get_models(
                 continue;
             }
 
+            // Optional namespace keeping ids unique across connections (e.g. "openai.gpt-4o")
+            let prefix = config
+                .openai_api_configs
+                .get(idx.to_string())
+                .or_else(|| config.openai_api_configs.get(url))
+                .and_then(models_cache::connection_prefix);
+
             // Check if it's Azure
             let is_azure = api_config
                 .get("azure")
@@ -159,25 +276,33 @@ async fn get_models(
                 if let Some(model_ids) = api_config.get("model_ids").and_then(|v| v.as_array()) {
                     for model_id in model_ids {
                         if let Some(id_str) = model_id.as_str() {
-                            all_models.push(serde_json::json!({
-                                "id": id_str,
-                                "name": id_str,
-                                "object": "model",
-                                "owned_by": "azure",
-                                "urlIdx": idx,
-                                "connection_type": "external"
-                            }));
+                            all_models.push((
+                                serde_json::json!({
+                                    "id": models_cache::prefixed_model_id(prefix, id_str),
+                                    "name": id_str,
+                                    "object": "model",
+                                    "owned_by": "azure",
+                                    "urlIdx": idx,
+                                    "connection_type": "external"
+                                }),
+                                ModelRoute {
+                                    url_idx: idx,
+                                    upstream_id: id_str.to_string(),
+                                },
+                            ));
                         }
                     }
                 }
             } else {
                 // Fetch models from the endpoint
-                match client
-                    .get(format!("{}/models", url))
-                    .header("Authorization", format!("Bearer {}", key))
-                    .header("Content-Type", "application/json")
-                    .send()
-                    .await
+                match crate::utils::telemetry::send(
+                    crate::utils::http::client_for(url)
+                        .get(format!("{}/models", url))
+                        .header("Authorization", format!("Bearer {}", key))
+                        .header("Content-Type", "application/json"),
+                    "openai.models",
+                )
+                .await
                 {
                     Ok(response) if response.status().is_success() => {
                         if let Ok(models_response) = response.json::<serde_json::Value>().await {
@@ -205,15 +330,21 @@ async fn get_models(
                                             }
                                         }
 
-                                        all_models.push(serde_json::json!({
-                                            "id": model_id,
-                                            "name": model.get("name").and_then(|v| v.as_str()).unwrap_or(model_id),
-                                            "object": "model",
-                                            "owned_by": model.get("owned_by").and_then(|v| v.as_str()).unwrap_or("openai"),
-                                            "openai": model,
-                                            "connection_type": "external",
-                                            "urlIdx": idx
-                                        }));
+                                        all_models.push((
+                                            serde_json::json!({
+                                                "id": models_cache::prefixed_model_id(prefix, model_id),
+                                                "name": model.get("name").and_then(|v| v.as_str()).unwrap_or(model_id),
+                                                "object": "model",
+                                                "owned_by": model.get("owned_by").and_then(|v| v.as_str()).unwrap_or("openai"),
+                                                "openai": model,
+                                                "connection_type": "external",
+                                                "urlIdx": idx
+                                            }),
+                                            ModelRoute {
+                                                url_idx: idx,
+                                                upstream_id: model_id.to_string(),
+                                            },
+                                        ));
                                     }
                                 }
                             }
@@ -234,22 +365,58 @@ async fn get_models(
         }
     }
 
-    // Cache the models in app state (like Python's OPENAI_MODELS)
-    {
-        let mut cache = state.models_cache.write().unwrap();
-        cache.clear();
-        for model in &all_models {
-            if let Some(model_id) = model.get("id").and_then(|v| v.as_str()) {
-                cache.insert(model_id.to_string(), model.clone());
-            }
-        }
+    all_models
+}
+
+// Get all OpenAI models from all configured endpoints
+async fn get_models(
+    state: web::Data<AppState>,
+    _auth_user: AuthUser,
+) -> Result<HttpResponse, AppError> {
+    let config = state.config.read().unwrap().clone();
+
+    if !config.enable_openai_api {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "data": []
+        })));
     }
 
+    let merged_models = refresh_models_cache(&state, &config).await;
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "data": all_models
+        "data": merged_models
     })))
 }
 
+/// Fetch every connection's models into the models cache and return the merged list
+pub async fn refresh_models_cache(
+    state: &AppState,
+    config: &crate::config::Config,
+) -> Vec<serde_json::Value> {
+    // Concurrent refreshes against the same connections share one round of upstream calls
+    let signature = serde_json::json!([
+        config.openai_api_base_urls,
+        config.openai_api_keys,
+        config.openai_api_configs,
+    ])
+    .to_string();
+    let all_models = state
+        .model_list_flight
+        .run(signature, || fetch_connection_models(config))
+        .await;
+
+    // Cache the merged models in app state (like Python's OPENAI_MODELS); ids that
+    // collide across unprefixed connections are listed once, for the first connection
+    let mut merged_models = Vec::with_capacity(all_models.len());
+    let mut cache = state.models_cache.write().unwrap();
+    cache.clear();
+    for (model, route) in all_models {
+        if cache.insert(model.clone(), route) {
+            merged_models.push(model);
+        }
+    }
+    merged_models
+}
+
 // Get models from a specific OpenAI endpoint by index
 async fn get_models_by_idx(
     state: web::Data<AppState>,
@@ -300,7 +467,7 @@ async fn get_models_by_idx(
     }
 
     // Fetch models from the endpoint
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(url);
 
     match client
         .get(format!("{}/models", url))
@@ -428,15 +595,16 @@ fn get_endpoint_from_cache_or_config(
         .get("urlIdx")
         .and_then(|v| v.as_u64())
         .or_else(|| payload_obj.get("urlIdx").and_then(|v| v.as_u64()))
+        .map(|i| i as usize)
         .or_else(|| {
-            // Try to find model in cache and get its urlIdx
+            // Resolve (prefixed or legacy) model id through the cached reverse map,
+            // falling back to the configured connection prefixes
             let cache = state.models_cache.read().unwrap();
             cache
-                .get(model_id)
-                .and_then(|model| model.get("urlIdx"))
-                .and_then(|v| v.as_u64())
-        })
-        .map(|i| i as usize);
+                .resolve(model_id)
+                .map(|route| route.url_idx)
+                .or_else(|| models_cache::route_by_prefix(config, model_id).map(|r| r.url_idx))
+        });
 
     let selected_idx = if let Some(idx) = url_idx {
         // Use the provided urlIdx
@@ -520,7 +688,7 @@ async fn verify_connection(
         payload.config.clone()
     };
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(url);
 
     // Check if it's Azure
     let is_azure = api_config
@@ -661,7 +829,7 @@ async fn audio_speech(
     }
 
     // Make request to OpenAI
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(&url);
     let mut request_builder = client
         .post(format!("{}/audio/speech", url))
         .header("Content-Type", "application/json")
@@ -720,7 +888,13 @@ async fn embeddings_endpoint(
         ));
     }
 
-    let model_id = payload.get("model").and_then(|v| v.as_str()).unwrap_or("");
+    let mut payload = payload.into_inner();
+    let model_id = payload
+        .get("model")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+    let model_id = model_id.as_str();
 
     // Find the endpoint for this model
     let mut idx = 0;
@@ -728,10 +902,12 @@ async fn embeddings_endpoint(
     // Try to find in cache first
     {
         let cache = state.models_cache.read().unwrap();
-        if let Some(model) = cache.get(model_id) {
-            if let Some(url_idx) = model.get("urlIdx").and_then(|v| v.as_u64()) {
-                idx = url_idx as usize;
-            }
+        if let Some(route) = cache
+            .resolve(model_id)
+            .cloned()
+            .or_else(|| models_cache::route_by_prefix(&config, model_id))
+        {
+            idx = route.url_idx;
         }
     }
 
@@ -755,12 +931,18 @@ async fn embeddings_endpoint(
 
     drop(config);
 
+    // Forward the id the upstream connection knows (without our prefix)
+    let upstream_id = models_cache::upstream_model_id(&api_config, model_id);
+    if upstream_id != model_id {
+        payload["model"] = serde_json::json!(upstream_id);
+    }
+
     // Make request
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(&url);
     let mut request_builder = client
         .post(format!("{}/embeddings", url))
         .header("Content-Type", "application/json")
-        .json(&payload.into_inner());
+        .json(&payload);
 
     let auth_type = api_config
         .get("auth_type")
@@ -836,7 +1018,7 @@ async fn proxy_request(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(&url);
     let request_url = if is_azure {
         let api_version = api_config
             .get("api_version")
@@ -907,7 +1089,10 @@ async fn proxy_request(
             // Check if streaming
             if content_type.contains("text/event-stream") {
                 use bytes::Bytes;
-                let stream = response.bytes_stream().map(move |result| match result {
+                let stream = chat_completion::end_with_error_event(Box::pin(
+                    response.bytes_stream(),
+                ))
+                .map(move |result| match result {
                     Ok(bytes) => Ok::<Bytes, actix_web::Error>(bytes),
                     Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
                 });
@@ -933,11 +1118,99 @@ async fn proxy_request(
 
 // Chat completions endpoint - proxy to the appropriate OpenAI endpoint
 async fn chat_completions(
+    req: HttpRequest,
     state: web::Data<AppState>,
     auth_user: AuthUser,
     payload: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    handle_chat_completions(state, auth_user, payload).await
+    let (api_key_override, forwarded_headers) = {
+        let config = state.config.read().unwrap();
+        (
+            byok_key(&req, &config)?,
+            forward_headers::forwarded_headers(&req, &config.forward_headers),
+        )
+    };
+    handle_chat_completions(
+        state,
+        auth_user,
+        payload,
+        api_key_override,
+        forwarded_headers,
+    )
+    .await
+}
+
+/// Header carrying a caller-supplied upstream API key
+pub const BYOK_HEADER: &str = "X-OpenAI-Key";
+
+/// Upstream API key supplied by the caller for this request only (bring your own key)
+///
+/// The key replaces the configured connection key for the request and is never stored
+/// or logged. Returns `Forbidden` when `ALLOW_BYOK` is disabled.
+pub fn byok_key(
+    req: &HttpRequest,
+    config: &crate::config::Config,
+) -> Result<Option<String>, AppError> {
+    let Some(value) = req.headers().get(BYOK_HEADER) else {
+        return Ok(None);
+    };
+
+    if !config.allow_byok {
+        return Err(AppError::Forbidden(
+            "Bring-your-own API keys are disabled".to_string(),
+        ));
+    }
+
+    let key = value
+        .to_str()
+        .map_err(|_| AppError::BadRequest(format!("Invalid {} header", BYOK_HEADER)))?
+        .trim();
+
+    Ok((!key.is_empty()).then(|| key.to_string()))
+}
+
+/// Build the upstream chat completions request with auth for the connection and the
+/// client headers FORWARD_HEADERS passes through
+fn chat_completions_request(
+    client: &reqwest::Client,
+    url: &str,
+    key: &str,
+    api_config: &serde_json::Value,
+    forwarded_headers: &reqwest::header::HeaderMap,
+) -> reqwest::RequestBuilder {
+    let mut request_builder = client
+        .post(format!("{}/chat/completions", url))
+        .headers(forwarded_headers.clone())
+        .header("Content-Type", "application/json");
+
+    // Add authorization header based on auth_type
+    let auth_type = api_config
+        .get("auth_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("bearer");
+
+    match auth_type {
+        "none" => {
+            // No authentication
+        }
+        _ => {
+            // Default to bearer token for all other cases
+            if !key.is_empty() {
+                request_builder =
+                    request_builder.header("Authorization", format!("Bearer {}", key));
+            }
+        } // TODO: Add support for other auth types like "session", "system_oauth", "azure_ad"
+    }
+
+    request_builder
+}
+
+/// Mask a caller-supplied key if an upstream echoes it back in an error
+fn scrub_key(text: &str, key: Option<&str>) -> String {
+    match key {
+        Some(key) if !key.is_empty() => text.replace(key, "[REDACTED]"),
+        _ => text.to_string(),
+    }
 }
 
 /// Process streaming response and emit events via Socket.IO (wrapper function)
@@ -957,6 +1230,7 @@ async fn process_streaming_via_socketio(
     endpoint_key: String,
     tool_ids: Vec<String>,
     tool_specs: Vec<serde_json::Value>,
+    completion_log: Option<usage::CompletionLog>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Create streaming context
     let context = StreamingContext {
@@ -974,6 +1248,7 @@ async fn process_streaming_via_socketio(
         tool_ids,
         tool_specs,
         delta_chunk_size: None, // TODO: Extract from request params when frontend supports it
+        completion_log,
     };
 
     // Delegate to chat_completion module
@@ -989,11 +1264,63 @@ async fn process_streaming_via_socketio(
 // - Tool execution and multi-turn conversation logic
 // ============================================================================
 
+/// Fill in `DEFAULT_MODEL` when a chat payload doesn't name a model
+fn apply_default_model(payload: &mut serde_json::Value, default_model: Option<&str>) {
+    let unspecified = !matches!(
+        payload.get("model").and_then(|v| v.as_str()),
+        Some(model) if !model.trim().is_empty()
+    );
+    if let (true, Some(default_model), Some(obj)) =
+        (unspecified, default_model, payload.as_object_mut())
+    {
+        obj.insert("model".to_string(), serde_json::json!(default_model));
+    }
+}
+
+/// Model to reroute to when the provider for `model_id` is circuit-open
+///
+/// Only applies when the request opted in and a different `FALLBACK_MODEL` is
+/// configured; otherwise the open-circuit error is returned.
+fn fallback_model_for(
+    config: &crate::config::Config,
+    model_id: &str,
+    allow_fallback: bool,
+    retry_after: u64,
+) -> Result<String, AppError> {
+    config
+        .fallback_model
+        .clone()
+        .filter(|fallback| allow_fallback && fallback != model_id)
+        .ok_or_else(|| circuit_breaker::circuit_open_error(retry_after))
+}
+
+/// Rewrite an aliased `model` in a chat payload to its target and return the model id used
+///
+/// Upstream responses then report the real model rather than the alias.
+fn apply_model_alias(
+    payload: &mut serde_json::Value,
+    aliases: &BTreeMap<String, String>,
+) -> String {
+    let requested = payload
+        .get("model")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let model_id = models_cache::resolve_model_alias(aliases, &requested).to_string();
+    if model_id != requested {
+        tracing::debug!("Routing aliased model {} to {}", requested, model_id);
+        payload["model"] = serde_json::json!(model_id);
+    }
+    model_id
+}
+
 // Public handler for chat completions that can be called from main.rs
 pub async fn handle_chat_completions(
     state: web::Data<AppState>,
     auth_user: AuthUser,
     payload: web::Json<serde_json::Value>,
+    api_key_override: Option<String>,
+    forwarded_headers: reqwest::header::HeaderMap,
 ) -> Result<HttpResponse, AppError> {
     // Check if OpenAI API is enabled
     let enable_openai_api = {
@@ -1007,20 +1334,126 @@ pub async fn handle_chat_completions(
         ));
     }
 
-    // Get model ID from payload
-    let model_id = payload
+    let mut payload_obj = payload.into_inner();
+    apply_default_model(
+        &mut payload_obj,
+        state.config.read().unwrap().default_model.as_deref(),
+    );
+
+    // Get model ID from payload, routing legacy aliases to their replacement
+    let requested_model_id = payload_obj
         .get("model")
         .and_then(|v| v.as_str())
         .ok_or_else(|| AppError::BadRequest("Model ID is required".to_string()))?
         .to_string();
+    let mut model_id = apply_model_alias(
+        &mut payload_obj,
+        &state.config.read().unwrap().model_aliases,
+    );
+
+    // Tag and content routing rules, unless the request pins its model
+    let pin_model = payload_obj
+        .as_object_mut()
+        .and_then(|obj| obj.remove("pin_model"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let routing_rules = state.config.read().unwrap().model_routing_rules.clone();
+    let mut routed_rule = None;
+    if !pin_model && !routing_rules.is_empty() {
+        let chat_tags = match payload_obj.get("chat_id").and_then(|v| v.as_str()) {
+            Some(chat_id) if model_routing::uses_tags(&routing_rules) => {
+                crate::services::chat::ChatService::new(&state.db)
+                    .get_chat_by_id_and_user_id(chat_id, &auth_user.user.id)
+                    .await?
+                    .and_then(|chat| chat.meta?.get("tags").cloned())
+                    .and_then(|tags| serde_json::from_value::<Vec<String>>(tags).ok())
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        let text = model_routing::latest_user_text(&payload_obj);
+        if let Some(rule) = model_routing::match_rule(&routing_rules, &text, &chat_tags) {
+            tracing::debug!(
+                "Routing rule {} sends {} to {}",
+                rule.name,
+                model_id,
+                rule.model
+            );
+            payload_obj["model"] = serde_json::json!(rule.model);
+            model_id = apply_model_alias(
+                &mut payload_obj,
+                &state.config.read().unwrap().model_aliases,
+            );
+            routed_rule = Some(rule.name.clone());
+        }
+    }
+
+    // Guests may only use the configured guest models
+    if auth_user.user.role == crate::middleware::guest::GUEST_ROLE {
+        let allowed = {
+            let config = state.config.read().unwrap();
+            config.guest_models.contains(&requested_model_id)
+                || config.guest_models.contains(&model_id)
+        };
+        if !allowed {
+            return Err(AppError::Forbidden(
+                "Model is not available to guests".to_string(),
+            ));
+        }
+    }
+
+    // MODEL_ALLOWLIST/MODEL_DENYLIST, for the model the request ends up on
+    {
+        let config = state.config.read().unwrap().clone();
+        ModelAccess::for_user(&state.db, &config, &auth_user.user)
+            .await?
+            .check(&model_id)?;
+    }
+
+    // Enforce the monthly token quota (admins are exempt)
+    if auth_user.user.role != "admin" {
+        let quota = state.config.read().unwrap().usage_monthly_token_quota;
+        UsageService::new(&state.db)
+            .check_monthly_quota(&auth_user.user.id, quota)
+            .await?;
+    }
+
+    // Count the request against the daily limits (admins are exempt)
+    let daily_remaining = if auth_user.user.role != "admin" {
+        let by_group = !state
+            .config
+            .read()
+            .unwrap()
+            .daily_request_limit_groups
+            .is_empty();
+        let groups = if by_group {
+            GroupService::new(&state.db)
+                .get_groups_by_member_id(&auth_user.user.id)
+                .await?
+        } else {
+            Vec::new()
+        };
+        let limits = usage::daily_limits(&state.config.read().unwrap(), &model_id, &groups);
+        UsageService::new(&state.db)
+            .take_daily_request(&auth_user.user.id, &limits, current_timestamp_seconds())
+            .await?
+    } else {
+        None
+    };
 
     // Extract model_item from payload (matching Python's behavior exactly)
-    let mut payload_obj = payload.into_inner();
     let model_item = payload_obj
         .as_object_mut()
         .and_then(|obj| obj.remove("model_item"))
         .unwrap_or(serde_json::json!({}));
 
+    // Opt-in: reroute to FALLBACK_MODEL if the chosen provider is circuit-open
+    let allow_fallback = payload_obj
+        .as_object_mut()
+        .and_then(|obj| obj.remove("allow_fallback"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
     // Extract Socket.IO streaming metadata from top-level (frontend sends these at root level)
     let session_id = payload_obj
         .get("session_id")
@@ -1053,6 +1486,20 @@ pub async fn handle_chat_completions(
         .cloned()
         .unwrap_or_default();
 
+    // Clients asking for title generation themselves get the socket flow's title instead
+    let auto_title = chat_id
+        .clone()
+        .filter(|_| !should_generate_title)
+        .map(|chat_id| (chat_id, messages.clone()));
+
+    // Screen the prompt before it reaches the provider
+    let moderator = Moderator::from_config(&state.config.read().unwrap());
+    if let Some(moderator) = &moderator {
+        moderator
+            .check(&moderation::latest_user_prompt(&messages))
+            .await?;
+    }
+
     // Extract tool_ids BEFORE removing from payload
     let tool_ids = payload_obj
         .get("tool_ids")
@@ -1086,6 +1533,28 @@ pub async fn handle_chat_completions(
         obj.remove("model_item");
     }
 
+    // Enforce the admin parameter policy; params saved on a workspace model are locked
+    let workspace_model = ModelService::new(&state.db)
+        .get_model_by_id(&model_id)
+        .await?;
+    ParamPolicy::from_config(&state.config.read().unwrap()).apply(
+        &mut payload_obj,
+        workspace_model.as_ref().map(|model| &model.params),
+    )?;
+
+    // Check inline images; models are assumed vision-capable unless marked otherwise
+    let vision = workspace_model
+        .as_ref()
+        .and_then(|model| model.meta.as_ref())
+        .and_then(image_policy::vision_capability)
+        .or_else(|| {
+            model_item
+                .pointer("/info/meta")
+                .and_then(image_policy::vision_capability)
+        })
+        .unwrap_or(true);
+    ImagePolicy::from_config(&state.config.read().unwrap()).apply(&mut payload_obj, vision)?;
+
     // Prepare tool specs storage (moved outside if block for later use)
     let mut all_tool_specs = Vec::new();
 
@@ -1266,10 +1735,17 @@ pub async fn handle_chat_completions(
                     .unwrap_or_default();
                 let user_group_ids: HashSet<String> = groups.into_iter().map(|g| g.id).collect();
 
+                // The latest user message drives knowledge base search
+                let query = payload_obj
+                    .get("messages")
+                    .and_then(|m| m.as_array())
+                    .and_then(|messages| crate::utils::retrieval::get_last_user_message(messages));
+
                 // Extract sources from file items (notes, files, chats, etc.)
                 match crate::utils::retrieval::get_sources_from_items(
                     &state,
                     file_items.clone(),
+                    query.as_deref(),
                     &auth_user.user,
                     &user_group_ids,
                 )
@@ -1342,6 +1818,47 @@ pub async fn handle_chat_completions(
         serde_json::to_string(&model_item).unwrap_or_default()
     );
 
+    // Trim long histories before they go upstream (only when configured)
+    let (history_limit, summary_model) = {
+        let config = state.config.read().unwrap();
+        (
+            HistoryLimit::from_config(&config),
+            config.history_summary_model.clone(),
+        )
+    };
+    let forwarded = history_limit.and_then(|limit| {
+        payload_obj
+            .get_mut("messages")
+            .and_then(|m| m.as_array_mut())
+            .map(|messages| (limit, std::mem::take(messages)))
+    });
+    if let Some((limit, forwarded)) = forwarded {
+        let mut history = limit.truncate(forwarded);
+
+        if !history.dropped.is_empty() {
+            tracing::debug!(
+                "Dropping {} older message(s) from chat history",
+                history.dropped.len()
+            );
+            if let Some(summary_model) = &summary_model {
+                match crate::routes::tasks::summarize_history(
+                    &state,
+                    &auth_user,
+                    summary_model,
+                    &history.dropped,
+                )
+                .await
+                {
+                    Ok(summary) => history.insert_summary(&summary),
+                    // Plain truncation still keeps the request within limits
+                    Err(e) => tracing::warn!("Failed to summarize chat history: {}", e),
+                }
+            }
+        }
+
+        payload_obj["messages"] = serde_json::Value::Array(history.kept);
+    }
+
     // Check if this is a direct connection request (Python: if not model_item.get("direct", False))
     let is_direct = model_item
         .get("direct")
@@ -1601,34 +2118,136 @@ pub async fn handle_chat_completions(
         }
     };
 
-    // Prepare the request to the OpenAI-compatible endpoint
-    let client = reqwest::Client::new();
-    let mut request_builder = client
-        .post(format!("{}/chat/completions", url))
-        .header("Content-Type", "application/json");
+    // The schema is taken before response_format is fitted to the connection
+    let schema_check = SchemaCheck::from_request(&config, &payload_obj);
+    structured_output::adapt_response_format(&mut payload_obj, &api_config)?;
+
+    // Deterministic requests may be answered without calling the provider
+    let chat_cache = ChatCache::from_config(&config);
+    let cache_key = chat_cache
+        .as_ref()
+        .and_then(|cache| cache.key(&auth_user.user.id, &payload_obj));
+    // Worked out before the guard is dropped for the cache lookup and model queue
+    let max_streams = config.max_concurrent_streams_per_user;
+    let model_limit = ModelRateLimit::for_model(
+        &config,
+        &model_id,
+        workspace_model
+            .as_ref()
+            .and_then(|model| model.meta.as_ref()),
+    );
+    // The config guard isn't held while the cache backend is queried
+    drop(config);
+    if let (Some(cache), Some(cache_key)) = (&chat_cache, &cache_key) {
+        if let Some(cached) = cache.get(cache_key).await {
+            let mut response = HttpResponse::Ok();
+            response.insert_header((chat_cache::CACHE_HEADER, "HIT"));
+            for (name, value) in model_routing::route_headers(routed_rule.as_deref(), &model_id) {
+                response.insert_header((name, value));
+            }
+            if let Some(remaining) = daily_remaining {
+                response.insert_header((usage::DAILY_REMAINING_HEADER, remaining));
+            }
+            return Ok(response.json(cached));
+        }
+    }
 
-    // Add authorization header based on auth_type
-    let auth_type = api_config
-        .get("auth_type")
-        .and_then(|v| v.as_str())
-        .unwrap_or("bearer");
+    // Streams count against the user's MAX_CONCURRENT_STREAMS_PER_USER until the body
+    // ends or the client disconnects
+    let stream_guard = if payload_obj
+        .get("stream")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+    {
+        Some(
+            state
+                .stream_limits
+                .try_acquire(&auth_user.user.id, max_streams)?,
+        )
+    } else {
+        None
+    };
 
-    match auth_type {
-        "none" => {
-            // No authentication
+    // Models with a rate limit of their own take turns; the slot is held until the
+    // upstream has answered
+    let (_model_permit, queue_position) = match &model_limit {
+        Some(limit) => {
+            let (permit, position) = state
+                .model_queues
+                .acquire(&model_id, limit, &auth_user.user.role)
+                .await?;
+            (Some(permit), position)
         }
-        _ => {
-            // Default to bearer token for all other cases
-            if !key.is_empty() {
-                request_builder =
-                    request_builder.header("Authorization", format!("Bearer {}", key));
+        None => (None, None),
+    };
+    // A snapshot, so no guard is held through the upstream call below
+    let config = state.config.read().unwrap().clone();
+
+    // Fast-fail while this connection's circuit is open, unless the caller opted in
+    // to the fallback model
+    let breaker_settings = CircuitBreakerSettings::from_config(&config);
+    let mut fell_back = false;
+    let (url, key, api_config) =
+        match state
+            .circuit_breakers
+            .try_acquire(&url, &breaker_settings, Instant::now())
+        {
+            Ok(()) => (url, key, api_config),
+            Err(retry_after) => {
+                let fallback = fallback_model_for(&config, &model_id, allow_fallback, retry_after)?;
+                let endpoint = get_endpoint_from_cache_or_config(
+                    &state,
+                    &config,
+                    &fallback,
+                    &serde_json::json!({}),
+                    &serde_json::json!({}),
+                )?;
+                state
+                    .circuit_breakers
+                    .try_acquire(&endpoint.0, &breaker_settings, Instant::now())
+                    .map_err(circuit_breaker::circuit_open_error)?;
+
+                tracing::warn!(
+                    "Provider for {} is circuit-open, falling back to {}",
+                    model_id,
+                    fallback
+                );
+                payload_obj["model"] = serde_json::json!(fallback);
+                model_id = fallback;
+                fell_back = true;
+                endpoint
             }
-        } // TODO: Add support for other auth types like "session", "system_oauth", "azure_ad"
+        };
+
+    // A caller-supplied key applies to this request only
+    let key = api_key_override.clone().unwrap_or(key);
+
+    // Strip the connection's prefix_id so the upstream sees its own model id
+    let upstream_id = models_cache::upstream_model_id(&api_config, &model_id);
+    if upstream_id != model_id {
+        payload_obj["model"] = serde_json::json!(upstream_id);
     }
 
-    // Forward the modified payload (already extracted earlier)
+    // Prepare the request to the OpenAI-compatible endpoint
+    let client = crate::utils::http::client_for(&url);
+    let request_builder =
+        chat_completions_request(&client, &url, &key, &api_config, &forwarded_headers);
 
-    match request_builder.json(&payload_obj).send().await {
+    // Forward the modified payload (already extracted earlier)
+    let completion_log = usage::CompletionLog::for_request(&config, &payload_obj);
+    let keepalive_interval = std::time::Duration::from_secs(config.sse_keepalive_interval);
+    let retry_settings = RetrySettings::from_config(&config);
+    let upstream_result = upstream_retry::send_with_retry(
+        request_builder.json(&payload_obj),
+        "openai.chat_completions",
+        &retry_settings,
+        &state.circuit_breakers,
+        &url,
+        &breaker_settings,
+    )
+    .await;
+
+    let result = match upstream_result {
         Ok(response) if response.status().is_success() => {
             // Check if it's a streaming response
             let content_type = response
@@ -1681,6 +2300,7 @@ pub async fn handle_chat_completions(
                     let all_tool_specs_owned = all_tool_specs.clone();
 
                     tokio::spawn(async move {
+                        let _stream_guard = stream_guard;
                         if let Err(e) = process_streaming_via_socketio(
                             response,
                             &state_clone,
@@ -1696,6 +2316,7 @@ pub async fn handle_chat_completions(
                             key_owned,
                             tool_ids_owned,
                             all_tool_specs_owned,
+                            completion_log,
                         )
                         .await
                         {
@@ -1712,12 +2333,59 @@ pub async fn handle_chat_completions(
                 } else {
                     // Use traditional HTTP SSE streaming (no Socket.IO)
                     tracing::debug!("Using HTTP SSE streaming (no Socket.IO metadata)");
-                    chat_completion::create_sse_stream(response)
+                    let usage_tracker = StreamUsageTracker::new(
+                        state.clone(),
+                        auth_user.user.id.clone(),
+                        model_id.clone(),
+                        &messages,
+                        completion_log,
+                    );
+                    chat_completion::create_sse_stream(
+                        response,
+                        usage_tracker,
+                        keepalive_interval,
+                        moderator,
+                        stream_guard,
+                    )
                 }
             } else {
                 // Return JSON response
                 tracing::debug!("Returning JSON response");
                 if let Ok(json_response) = response.json::<serde_json::Value>().await {
+                    let completion_text = json_response
+                        .pointer("/choices/0/message/content")
+                        .and_then(|c| c.as_str())
+                        .unwrap_or("")
+                        .to_string();
+                    let moderation_result = match &moderator {
+                        Some(moderator) => moderator.check(&completion_text).await,
+                        None => Ok(()),
+                    };
+                    let schema_result = schema_check
+                        .as_ref()
+                        .map_or(Ok(()), |check| check.check(&completion_text));
+                    usage::spawn_record(
+                        state.clone(),
+                        auth_user.user.id.clone(),
+                        model_id.clone(),
+                        usage::extract_usage(&json_response),
+                        move || {
+                            (
+                                usage::estimate_prompt_tokens(&messages),
+                                count_tokens_approx(&completion_text) as i64,
+                            )
+                        },
+                        completion_log,
+                    );
+                    // Tokens were spent either way, so usage is recorded first
+                    moderation_result?;
+                    schema_result?;
+                    // A fallback model's answer isn't cached under the requested model
+                    if let (Some(cache), Some(cache_key), false) =
+                        (&chat_cache, &cache_key, fell_back)
+                    {
+                        cache.put(cache_key, &json_response).await;
+                    }
                     Ok(HttpResponse::Ok().json(json_response))
                 } else {
                     Err(AppError::InternalServerError(
@@ -1728,7 +2396,10 @@ pub async fn handle_chat_completions(
         }
         Ok(response) => {
             let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
+            let error_text = scrub_key(
+                &response.text().await.unwrap_or_default(),
+                api_key_override.as_deref(),
+            );
             tracing::error!("OpenAI API error: {} - {}", status, error_text);
             Err(AppError::InternalServerError(format!(
                 "OpenAI API error: {} - {}",
@@ -1736,11 +2407,238 @@ pub async fn handle_chat_completions(
             )))
         }
         Err(e) => {
-            tracing::error!("Error calling OpenAI API: {}", e);
+            let error_text = scrub_key(&e.to_string(), api_key_override.as_deref());
+            tracing::error!("Error calling OpenAI API: {}", error_text);
             Err(AppError::InternalServerError(format!(
                 "Error calling OpenAI API: {}",
-                e
+                error_text
             )))
         }
+    };
+
+    if let (Ok(_), Some((chat_id, messages))) = (&result, auto_title) {
+        auto_title::spawn(
+            state.clone(),
+            auth_user.clone(),
+            chat_id,
+            model_id.clone(),
+            messages,
+        );
+    }
+
+    result.map(|mut response| {
+        if fell_back {
+            response.headers_mut().insert(
+                actix_web::http::header::HeaderName::from_static("x-model-fallback"),
+                actix_web::http::header::HeaderValue::from_static("true"),
+            );
+        }
+        if cache_key.is_some() {
+            response.headers_mut().insert(
+                actix_web::http::header::HeaderName::from_static("x-cache"),
+                actix_web::http::header::HeaderValue::from_static("MISS"),
+            );
+        }
+        if let Some(position) = queue_position {
+            response.headers_mut().insert(
+                actix_web::http::header::HeaderName::from_static("x-queue-position"),
+                actix_web::http::header::HeaderValue::from(position),
+            );
+        }
+        if let Some(remaining) = daily_remaining {
+            response.headers_mut().insert(
+                actix_web::http::header::HeaderName::from_static("x-daily-requests-remaining"),
+                actix_web::http::header::HeaderValue::from(remaining),
+            );
+        }
+        for (name, value) in model_routing::route_headers(routed_rule.as_deref(), &model_id) {
+            response.headers_mut().insert(name, value);
+        }
+        response
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use actix_web::test::TestRequest;
+
+    fn config(allow_byok: bool) -> Config {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.allow_byok = allow_byok;
+        config.openai_api_base_urls = vec!["https://api.openai.com/v1".to_string()];
+        config.openai_api_keys = vec!["sk-server".to_string()];
+        config
+    }
+
+    #[test]
+    fn test_byok_key_used_and_not_persisted() {
+        let config = config(true);
+        let req = TestRequest::default()
+            .insert_header((BYOK_HEADER, "sk-user"))
+            .to_http_request();
+
+        let key = byok_key(&req, &config)
+            .unwrap()
+            .unwrap_or_else(|| config.openai_api_keys[0].clone());
+        let request = chat_completions_request(
+            &crate::utils::http::client(),
+            &config.openai_api_base_urls[0],
+            &key,
+            &serde_json::json!({}),
+            &reqwest::header::HeaderMap::new(),
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(request.headers()["Authorization"], "Bearer sk-user");
+        // The configured key is untouched
+        assert_eq!(config.openai_api_keys, vec!["sk-server".to_string()]);
+    }
+
+    #[test]
+    fn test_only_allowlisted_headers_reach_upstream() {
+        let mut config = config(false);
+        config.forward_headers = vec!["OpenAI-Organization".to_string()];
+        let req = TestRequest::default()
+            .insert_header(("openai-organization", "org-research"))
+            .insert_header(("Cookie", "session=secret"))
+            .insert_header(("Authorization", "Bearer webui-token"))
+            .insert_header(("X-Routing-Hint", "eu"))
+            .to_http_request();
+
+        let forwarded = forward_headers::forwarded_headers(&req, &config.forward_headers);
+        let request = chat_completions_request(
+            &crate::utils::http::client(),
+            &config.openai_api_base_urls[0],
+            &config.openai_api_keys[0],
+            &serde_json::json!({}),
+            &forwarded,
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(request.headers()["OpenAI-Organization"], "org-research");
+        assert!(!request.headers().contains_key("cookie"));
+        assert!(!request.headers().contains_key("x-routing-hint"));
+        // The connection's key, not the client's token
+        assert_eq!(request.headers()["Authorization"], "Bearer sk-server");
+
+        // Nothing is forwarded by default
+        let none = forward_headers::forwarded_headers(
+            &req,
+            &Config::from_lookup(|_| None).unwrap().forward_headers,
+        );
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn test_byok_forbidden_when_disabled() {
+        let req = TestRequest::default()
+            .insert_header((BYOK_HEADER, "sk-user"))
+            .to_http_request();
+        assert!(matches!(
+            byok_key(&req, &config(false)),
+            Err(AppError::Forbidden(_))
+        ));
+
+        // Requests without the header are unaffected
+        let req = TestRequest::default().to_http_request();
+        assert!(byok_key(&req, &config(false)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unspecified_model_uses_default() {
+        let mut missing = serde_json::json!({ "messages": [] });
+        apply_default_model(&mut missing, Some("gpt-4o-mini"));
+        assert_eq!(missing["model"], "gpt-4o-mini");
+
+        let mut blank = serde_json::json!({ "model": " " });
+        apply_default_model(&mut blank, Some("gpt-4o-mini"));
+        assert_eq!(blank["model"], "gpt-4o-mini");
+
+        let mut chosen = serde_json::json!({ "model": "gpt-4o" });
+        apply_default_model(&mut chosen, Some("gpt-4o-mini"));
+        assert_eq!(chosen["model"], "gpt-4o");
+
+        // Without a default the request is still rejected for lacking a model
+        let mut no_default = serde_json::json!({});
+        apply_default_model(&mut no_default, None);
+        assert!(no_default.get("model").is_none());
+    }
+
+    #[test]
+    fn test_circuit_open_provider_falls_back_when_opted_in() {
+        use actix_web::ResponseError;
+
+        let mut config = config(false);
+        config.fallback_model = Some("backup-model".to_string());
+        let settings = CircuitBreakerSettings::from_config(&config);
+
+        let breakers = circuit_breaker::CircuitBreakers::new();
+        let now = Instant::now();
+        for _ in 0..settings.failure_threshold {
+            breakers.record_failure("https://down.example.com/v1", &settings, now);
+        }
+        let retry_after = breakers
+            .try_acquire("https://down.example.com/v1", &settings, now)
+            .unwrap_err();
+
+        assert_eq!(
+            fallback_model_for(&config, "gpt-4o", true, retry_after).unwrap(),
+            "backup-model"
+        );
+
+        // Not opted in: the open-circuit error surfaces with Retry-After
+        let error = fallback_model_for(&config, "gpt-4o", false, retry_after).unwrap_err();
+        assert_eq!(error.status_code().as_u16(), 502);
+        assert!(error.error_response().headers().contains_key("retry-after"));
+
+        // The fallback's own provider being down isn't retried
+        assert!(fallback_model_for(&config, "backup-model", true, retry_after).is_err());
+    }
+
+    #[test]
+    fn test_aliased_model_reaches_mapped_upstream_model() {
+        let aliases = models_cache::parse_model_aliases("gpt-4 -> gpt-4o");
+        let mut payload = serde_json::json!({
+            "model": "gpt-4",
+            "messages": [{"role": "user", "content": "hi"}],
+        });
+
+        let model_id = apply_model_alias(&mut payload, &aliases);
+        assert_eq!(model_id, "gpt-4o");
+
+        let request = chat_completions_request(
+            &crate::utils::http::client(),
+            "https://api.openai.com/v1",
+            "sk-server",
+            &serde_json::json!({}),
+            &reqwest::header::HeaderMap::new(),
+        )
+        .json(&payload)
+        .build()
+        .unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(body["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_unaliased_model_untouched() {
+        let aliases = models_cache::parse_model_aliases("gpt-4 -> gpt-4o");
+        let mut payload = serde_json::json!({ "model": "gpt-4o-mini" });
+        assert_eq!(apply_model_alias(&mut payload, &aliases), "gpt-4o-mini");
+        assert_eq!(payload["model"], "gpt-4o-mini");
+    }
+
+    #[test]
+    fn test_scrub_key() {
+        assert_eq!(
+            scrub_key("Incorrect API key provided: sk-user", Some("sk-user")),
+            "Incorrect API key provided: [REDACTED]"
+        );
+        assert_eq!(scrub_key("upstream down", None), "upstream down");
     }
 }
@@ -44,7 +44,7 @@ async fn get_all_models_responses(state: &web::Data<AppState>) -> Vec<Option<ser
     let config = state.config.read().unwrap();
     let mut responses = Vec::new();
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     for (idx, url) in config.openai_api_base_urls.iter().enumerate() {
         let key = config
@@ -190,7 +190,7 @@ async fn upload_pipeline(
     };
 
     // Forward the file upload to the pipeline endpoint
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     let form = reqwest::multipart::Form::new().part(
         "file",
@@ -262,7 +262,7 @@ async fn add_pipeline(
     };
 
     // Forward the request to the pipeline endpoint
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .post(format!("{}/pipelines/add", endpoint_url))
@@ -328,7 +328,7 @@ async fn delete_pipeline(
     };
 
     // Forward the request to the pipeline endpoint
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .delete(format!("{}/pipelines/delete", endpoint_url))
@@ -398,7 +398,7 @@ async fn get_all_pipelines(
     };
 
     // Fetch pipelines from the endpoint
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .get(format!("{}/pipelines", endpoint_url))
@@ -465,7 +465,7 @@ async fn get_pipeline_valves(
         (endpoint_url, key)
     };
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .get(format!("{}/{}/valves", endpoint_url, pipeline_id))
@@ -532,7 +532,7 @@ async fn get_pipeline_valves_spec(
         (endpoint_url, key)
     };
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .get(format!("{}/{}/valves/spec", endpoint_url, pipeline_id))
@@ -600,7 +600,7 @@ async fn update_pipeline_valves(
         (endpoint_url, key)
     };
 
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     match client
         .post(format!("{}/{}/valves/update", endpoint_url, pipeline_id))
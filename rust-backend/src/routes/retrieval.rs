@@ -4,7 +4,7 @@ use serde_json::json;
 
 use crate::{
     error::{AppError, AppResult},
-    middleware::{AuthMiddleware, AuthUser},
+    middleware::{AuthMiddleware, AuthUser, ConcurrencyLimit},
     AppState,
 };
 
@@ -121,16 +121,46 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route("/config/update", web::post().to(update_rag_config))
             .route("/embedding", web::get().to(get_embedding_config))
             .route("/embedding/update", web::post().to(update_embedding_config))
-            .route("/process/file", web::post().to(process_file))
-            .route("/process/text", web::post().to(process_text))
-            .route("/process/youtube", web::post().to(process_youtube))
-            .route("/process/web", web::post().to(process_web))
-            .route("/process/web/search", web::post().to(process_web_search))
-            .route("/process/files/batch", web::post().to(process_files_batch))
-            .route("/query/doc", web::post().to(query_doc_handler))
-            .route(
-                "/query/collection",
-                web::post().to(query_collection_handler),
+            // Only ingest and query embed, so only they wait for an embeddings slot
+            .service(
+                web::resource("/process/file")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_file)),
+            )
+            .service(
+                web::resource("/process/text")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_text)),
+            )
+            .service(
+                web::resource("/process/youtube")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_youtube)),
+            )
+            .service(
+                web::resource("/process/web")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_web)),
+            )
+            .service(
+                web::resource("/process/web/search")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_web_search)),
+            )
+            .service(
+                web::resource("/process/files/batch")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(process_files_batch)),
+            )
+            .service(
+                web::resource("/query/doc")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(query_doc_handler)),
+            )
+            .service(
+                web::resource("/query/collection")
+                    .wrap(ConcurrencyLimit::Embeddings)
+                    .route(web::post().to(query_collection_handler)),
             )
             .route("/delete", web::post().to(delete_entries))
             .route("/reset/db", web::post().to(reset_vector_db))
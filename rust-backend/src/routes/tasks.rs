@@ -1,10 +1,11 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
     error::AppError,
     middleware::{AuthMiddleware, AuthUser},
+    utils::feature_flags::FeatureFlags,
     AppState,
 };
 
@@ -246,35 +247,7 @@ async fn generate_title(
         })));
     }
 
-    // Get the last 2 messages for title generation
-    let messages_for_title: Vec<_> = payload
-        .messages
-        .iter()
-        .rev()
-        .take(2)
-        .rev()
-        .cloned()
-        .collect();
-
-    // Build the title generation prompt
-    let template = if config.title_generation_prompt_template.is_empty() {
-        DEFAULT_TITLE_GENERATION_PROMPT_TEMPLATE.to_string()
-    } else {
-        config.title_generation_prompt_template.clone()
-    };
-
-    // Replace {{MESSAGES:END:2}} with the actual messages
-    let messages_text = messages_for_title
-        .iter()
-        .map(|m| {
-            let role = m.get("role").and_then(|v| v.as_str()).unwrap_or("user");
-            let content = m.get("content").and_then(|v| v.as_str()).unwrap_or("");
-            format!("{}: {}", role, content)
-        })
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    let prompt = template.replace("{{MESSAGES:END:2}}", &messages_text);
+    let prompt = title_prompt(&config, &payload.messages);
 
     drop(config); // Release lock before calling completion
 
@@ -432,11 +405,15 @@ async fn generate_queries(
 async fn generate_autocomplete(
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    req: HttpRequest,
     payload: web::Json<CompletionRequest>,
 ) -> Result<HttpResponse, AppError> {
     let config = state.config.read().unwrap();
 
-    if !config.enable_autocomplete_generation {
+    if !FeatureFlags::from_request(&config, &auth_user.user, &req).enabled(
+        "enable_autocomplete_generation",
+        config.enable_autocomplete_generation,
+    ) {
         return Ok(HttpResponse::Ok().json(json!({
             "detail": "Autocomplete generation is disabled"
         })));
@@ -546,6 +523,83 @@ async fn call_openai_completion(
     max_tokens: i32,
     temperature: f32,
 ) -> Result<HttpResponse, AppError> {
+    let json_response = request_completion(
+        state,
+        auth_user,
+        model,
+        model_item,
+        prompt,
+        max_tokens,
+        temperature,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json_response))
+}
+
+/// Title generation prompt for the last two of `messages`
+fn title_prompt(config: &crate::config::Config, messages: &[serde_json::Value]) -> String {
+    let template = if config.title_generation_prompt_template.is_empty() {
+        DEFAULT_TITLE_GENERATION_PROMPT_TEMPLATE.to_string()
+    } else {
+        config.title_generation_prompt_template.clone()
+    };
+    let last_two = &messages[messages.len().saturating_sub(2)..];
+    template.replace("{{MESSAGES:END:2}}", &format_messages(last_two))
+}
+
+/// Generate a title for a chat from its `messages`, using `model`
+///
+/// Returns the model's raw answer, which the default prompt asks to be `{"title": ...}`.
+pub async fn generate_chat_title(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    messages: &[serde_json::Value],
+) -> Result<String, AppError> {
+    let prompt = title_prompt(&state.config.read().unwrap(), messages);
+    let response = request_completion(state, auth_user, model, None, &prompt, 50, 0.1).await?;
+
+    response
+        .pointer("/choices/0/message/content")
+        .and_then(|c| c.as_str())
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| {
+            AppError::ExternalServiceError("Title model returned no content".to_string())
+        })
+}
+
+/// Summarize chat turns dropped by history truncation, using `model`
+pub async fn summarize_history(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    dropped: &[serde_json::Value],
+) -> Result<String, AppError> {
+    let prompt = crate::utils::history::summary_prompt(dropped);
+    let response = request_completion(state, auth_user, model, None, &prompt, 500, 0.2).await?;
+
+    response
+        .pointer("/choices/0/message/content")
+        .and_then(|c| c.as_str())
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .ok_or_else(|| {
+            AppError::ExternalServiceError("Summary model returned no content".to_string())
+        })
+}
+
+// Send a single-prompt, non-streaming completion and return the upstream JSON
+async fn request_completion(
+    state: &web::Data<AppState>,
+    auth_user: &AuthUser,
+    model: &str,
+    model_item: Option<&serde_json::Value>,
+    prompt: &str,
+    max_tokens: i32,
+    temperature: f32,
+) -> Result<serde_json::Value, AppError> {
     // Build the chat completion request payload
     let mut completion_payload = json!({
         "model": model,
@@ -585,7 +639,7 @@ async fn call_openai_completion(
     );
 
     // Make the API request
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
     let mut request_builder = client
         .post(format!("{}/chat/completions", url.trim_end_matches('/')))
         .header("Content-Type", "application/json");
@@ -614,13 +668,10 @@ async fn call_openai_completion(
     }
 
     match request_builder.json(&completion_payload).send().await {
-        Ok(response) if response.status().is_success() => {
-            let json_response = response.json::<serde_json::Value>().await.map_err(|e| {
-                AppError::InternalServerError(format!("Failed to parse response: {}", e))
-            })?;
-
-            Ok(HttpResponse::Ok().json(json_response))
-        }
+        Ok(response) if response.status().is_success() => response
+            .json::<serde_json::Value>()
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Failed to parse response: {}", e))),
         Ok(response) => {
             let status = response.status();
             let error_text = response
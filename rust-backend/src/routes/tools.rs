@@ -306,7 +306,7 @@ async fn load_tool_from_url(
     };
 
     // Fetch content from URL
-    let client = reqwest::Client::builder()
+    let client = crate::utils::http::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()
         .map_err(|e| AppError::BadRequest(format!("Failed to create HTTP client: {}", e)))?;
@@ -502,8 +502,7 @@ async fn create_new_tool(
         }
     }
 
-    form.validate()
-        .map_err(|e| AppError::Validation(e.to_string()))?;
+    form.validate()?;
 
     // Validate tool ID (alphanumeric and underscores only)
     if !form.id.chars().all(|c| c.is_alphanumeric() || c == '_') {
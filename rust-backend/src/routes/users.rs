@@ -1,13 +1,29 @@
-use actix_web::{web, HttpResponse};
+use actix_multipart::Multipart;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use crate::middleware::{AuthMiddleware, AuthUser};
-use crate::models::{UpdateUserRoleRequest, UserResponse};
+use crate::models::usage::UsageQuery;
+use crate::models::{UpdateUserRoleRequest, User, UserResponse};
+use crate::services::account_deletion::AccountDeletion;
+use crate::services::avatar::{self, AvatarStore, Picture};
+use crate::services::signin_throttle::SigninThrottle;
+use crate::services::usage::{start_of_current_month, UsageService};
+use crate::services::user::settings_etag;
 use crate::services::UserService;
+use crate::utils::feature_flags;
+use crate::utils::permissions::default_permissions;
+use crate::utils::time::current_timestamp_seconds;
+use crate::utils::webhook::{self, WebhookPayload};
 use crate::AppState;
 
+/// Profile image new accounts start with
+const DEFAULT_PROFILE_IMAGE_URL: &str = "/user.png";
+
 pub fn create_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("")
@@ -16,9 +32,18 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .service(web::resource("/").route(web::get().to(list_users)))
             .route("/all", web::get().to(get_all_users))
             .route("/active", web::get().to(get_active_users))
+            .route("/online", web::get().to(get_online_users))
             .route("/search", web::get().to(search_users))
             .route("/groups", web::get().to(get_user_groups))
             .route("/permissions", web::get().to(get_user_permissions))
+            .route("/me", web::delete().to(delete_my_account))
+            .route("/me/usage", web::get().to(get_my_usage))
+            .route("/me/export", web::get().to(export_my_data))
+            .service(
+                web::resource("/me/avatar")
+                    .route(web::post().to(upload_my_avatar))
+                    .route(web::delete().to(delete_my_avatar)),
+            )
             .service(
                 web::resource("/{id}")
                     .route(web::get().to(get_user_by_id))
@@ -27,6 +52,12 @@ pub fn create_routes(cfg: &mut web::ServiceConfig) {
             .route("/{id}/role", web::post().to(update_user_role))
             .route("/{id}/update", web::post().to(update_user_by_id))
             .route("/{id}/profile/image", web::get().to(get_user_profile_image))
+            .service(
+                web::resource("/{id}/avatar")
+                    .route(web::get().to(get_user_profile_image))
+                    .route(web::post().to(upload_user_avatar))
+                    .route(web::delete().to(delete_user_avatar)),
+            )
             .route("/{id}/active", web::get().to(get_user_active_status))
             .route("/{id}/groups", web::get().to(get_user_groups_by_id))
             .route(
@@ -105,14 +136,39 @@ async fn get_all_users(state: web::Data<AppState>, auth_user: AuthUser) -> AppRe
     })))
 }
 
+/// IDs of users with a live Socket.IO connection
+async fn online_user_ids(state: &AppState) -> Vec<String> {
+    match &state.socketio_handler {
+        Some(handler) => handler.presence_manager().online_user_ids().await,
+        None => Vec::new(),
+    }
+}
+
 // Get active users (returns list of active user IDs)
 async fn get_active_users(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     _auth_user: AuthUser,
 ) -> AppResult<HttpResponse> {
-    // TODO: Implement active users tracking via WebSocket/Socket.IO state
-    // For now, return empty list
-    Ok(HttpResponse::Ok().json(json!({ "user_ids": [] })))
+    let user_ids = online_user_ids(&state).await;
+    Ok(HttpResponse::Ok().json(json!({ "user_ids": user_ids })))
+}
+
+// Get currently connected users (admin only)
+async fn get_online_users(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> AppResult<HttpResponse> {
+    if auth_user.user.role != "admin" {
+        return Err(crate::error::AppError::Forbidden(
+            "Admin access required".to_string(),
+        ));
+    }
+
+    let user_ids = online_user_ids(&state).await;
+    Ok(HttpResponse::Ok().json(json!({
+        "user_ids": user_ids,
+        "total": user_ids.len(),
+    })))
 }
 
 // Search users by query
@@ -158,6 +214,129 @@ async fn get_user_permissions(
     Ok(HttpResponse::Ok().json(permissions))
 }
 
+// Get current user's token usage, plus month-to-date totals against any quota
+async fn get_my_usage(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    query: web::Query<UsageQuery>,
+) -> AppResult<HttpResponse> {
+    let mut query = query.into_inner();
+    query.user_id = Some(auth_user.user.id.clone());
+
+    let usage_service = UsageService::new(&state.db);
+    let usage = usage_service.get_usage_summary(&query).await?;
+    let month_tokens = usage_service
+        .get_user_total_tokens_since(&auth_user.user.id, start_of_current_month())
+        .await?;
+
+    let quota = state.config.read().unwrap().usage_monthly_token_quota;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "usage": usage,
+        "month_total_tokens": month_tokens,
+        "monthly_token_quota": if quota > 0 { Some(quota) } else { None },
+    })))
+}
+
+// GET /me/export - Download everything stored about the current user
+async fn export_my_data(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> AppResult<HttpResponse> {
+    // The export would be logged as the user's own; admins have an audited route
+    if auth_user.impersonated_by.is_some() {
+        return Err(AppError::Forbidden(
+            "Use /admin/users/{id}/export while impersonating".to_string(),
+        ));
+    }
+
+    let (user, stream) = state
+        .data_export
+        .start(&state.db, &auth_user.user, &auth_user.user.id)
+        .await?;
+    Ok(export_download(&user, stream))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct DeleteAccountRequest {
+    #[serde(default)]
+    password: Option<String>,
+}
+
+// DELETE /me - Delete the current user's account
+async fn delete_my_account(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    auth_user: AuthUser,
+    req: Option<web::Json<DeleteAccountRequest>>,
+) -> AppResult<HttpResponse> {
+    let req = req.map(web::Json::into_inner).unwrap_or_default();
+    let deletion = AccountDeletion::from_config(&state.config.read().unwrap()).with_rag(
+        crate::routes::knowledge_vector::get_rag_components(
+            &state.vector_db,
+            &state.embedding_provider,
+        ),
+    );
+
+    // The password prompt is throttled like sign-in, so a stolen session can't guess it
+    let client_ip = SigninThrottle::client_address(
+        &http_req.connection_info(),
+        &state.config.read().unwrap().trusted_proxies,
+    );
+    let throttle_keys = SigninThrottle::keys(
+        &auth_user.user.email,
+        client_ip.as_deref().unwrap_or("unknown"),
+    );
+    let delay = state.signin_throttle.delay(&throttle_keys);
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+
+    let result = deletion
+        .delete(
+            &state.db,
+            &auth_user.user,
+            auth_user.issued_at,
+            req.password.as_deref(),
+        )
+        .await;
+    if req.password.is_some() && matches!(result, Err(AppError::Unauthorized(_))) {
+        state.signin_throttle.record_failure(&throttle_keys);
+    }
+    let reassigned_to = result?;
+
+    webhook::dispatch_event(
+        &state.config.read().unwrap(),
+        WebhookPayload::new(
+            webhook::ACCOUNT_DELETED,
+            json!({
+                "user_id": auth_user.user.id,
+                "name": auth_user.user.name,
+                "email": auth_user.user.email,
+                "reassigned_to": reassigned_to,
+            }),
+        ),
+    );
+    Ok(HttpResponse::Ok().json(json!({ "success": true })))
+}
+
+/// Attachment response streaming a user's data export
+pub(crate) fn export_download(
+    user: &User,
+    stream: impl Stream<Item = AppResult<Bytes>> + 'static,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"open-webui-export-{}.json\"",
+                user.id
+            ),
+        ))
+        .streaming(stream)
+}
+
 async fn get_user_by_id(
     state: web::Data<AppState>,
     auth_user: AuthUser,
@@ -189,7 +368,7 @@ async fn get_user_by_id(
     })))
 }
 
-// Get user profile image
+// Get user profile image (also served as /avatar, proxying remote URLs when enabled)
 async fn get_user_profile_image(
     state: web::Data<AppState>,
     _auth_user: AuthUser,
@@ -203,34 +382,214 @@ async fn get_user_profile_image(
             "User not found".to_string(),
         ))?;
 
-    let profile_image_url = user.profile_image_url;
-    if !profile_image_url.is_empty() {
+    let profile_image_url = &user.profile_image_url;
+    if avatar::is_uploaded_avatar_url(profile_image_url) {
+        let upload_dir = state.config.read().unwrap().upload_dir.clone();
+        if let Some(picture) = AvatarStore::new(&upload_dir).load(&user.id).await? {
+            return Ok(HttpResponse::Ok()
+                .content_type(picture.content_type)
+                .append_header(("Cache-Control", "private, max-age=86400"))
+                .append_header(("X-Content-Type-Options", "nosniff"))
+                .body(picture.data));
+        }
+    } else if !profile_image_url.is_empty() {
         if profile_image_url.starts_with("http") {
+            let (proxy_pictures, ttl) = {
+                let config = state.config.read().unwrap();
+                (config.oauth_proxy_pictures, config.oauth_picture_cache_ttl)
+            };
+
+            // Serve provider-supplied avatars through the caching proxy
+            if proxy_pictures && avatar::is_provider_picture(&user) {
+                match state
+                    .avatar_cache
+                    .get_or_fetch(profile_image_url, std::time::Duration::from_secs(ttl))
+                    .await
+                {
+                    Ok(picture) => {
+                        return Ok(HttpResponse::Ok()
+                            .content_type(picture.content_type)
+                            .append_header(("Cache-Control", format!("private, max-age={}", ttl)))
+                            .append_header(("X-Content-Type-Options", "nosniff"))
+                            .body(picture.data));
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to proxy avatar for user {}: {}", user.id, e);
+                        return default_user_avatar();
+                    }
+                }
+            }
+
             // Redirect to external URL
             return Ok(HttpResponse::Found()
-                .append_header(("Location", profile_image_url))
+                .append_header(("Location", profile_image_url.as_str()))
                 .finish());
-        } else if profile_image_url.starts_with("data:image") {
-            // Return base64 encoded image
-            if let Some(comma_pos) = profile_image_url.find(',') {
-                let base64_data = &profile_image_url[comma_pos + 1..];
+        } else if let Some(data_url) = profile_image_url.strip_prefix("data:") {
+            // Return base64 encoded image, if it's one of the allowed raster types
+            if let Some((content_type, base64_data)) = data_url.split_once(',') {
+                let content_type = content_type.strip_suffix(";base64").unwrap_or(content_type);
                 use base64::{engine::general_purpose, Engine};
-                if let Ok(image_data) = general_purpose::STANDARD.decode(base64_data) {
+                if let (Ok(content_type), Ok(image_data)) = (
+                    avatar::check_content_type(content_type),
+                    general_purpose::STANDARD.decode(base64_data),
+                ) {
                     return Ok(HttpResponse::Ok()
-                        .content_type("image/png")
+                        .content_type(content_type)
+                        .append_header(("X-Content-Type-Options", "nosniff"))
                         .body(image_data));
                 }
             }
         }
     }
 
-    // Return default user avatar
+    default_user_avatar()
+}
+
+async fn upload_my_avatar(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    payload: Multipart,
+) -> AppResult<HttpResponse> {
+    set_uploaded_avatar(&state, &auth_user.user.id, payload).await
+}
+
+// Admins may set anyone's avatar; users only their own
+async fn upload_user_avatar(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+    payload: Multipart,
+) -> AppResult<HttpResponse> {
+    check_avatar_access(&auth_user, &id)?;
+    set_uploaded_avatar(&state, &id, payload).await
+}
+
+async fn delete_my_avatar(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+) -> AppResult<HttpResponse> {
+    reset_avatar(&state, &auth_user.user.id).await
+}
+
+async fn delete_user_avatar(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    id: web::Path<String>,
+) -> AppResult<HttpResponse> {
+    check_avatar_access(&auth_user, &id)?;
+    reset_avatar(&state, &id).await
+}
+
+fn check_avatar_access(auth_user: &AuthUser, user_id: &str) -> AppResult<()> {
+    if auth_user.user.id != user_id && auth_user.user.role != "admin" {
+        return Err(crate::error::AppError::Forbidden(
+            "Access denied".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Store the multipart `file` field as the user's avatar and point their profile at it
+async fn set_uploaded_avatar(
+    state: &web::Data<AppState>,
+    user_id: &str,
+    mut payload: Multipart,
+) -> AppResult<HttpResponse> {
+    let upload_dir = {
+        let config = state.config.read().unwrap();
+        if !config.enable_profile_image_upload {
+            return Err(crate::error::AppError::Forbidden(
+                "Profile image upload is disabled".to_string(),
+            ));
+        }
+        config.upload_dir.clone()
+    };
+
+    let user_service = UserService::new(&state.db);
+    user_service
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or(crate::error::AppError::NotFound(
+            "User not found".to_string(),
+        ))?;
+
+    let mut picture = None;
+    while let Some(field) = payload.next().await {
+        let mut field = field
+            .map_err(|e| crate::error::AppError::BadRequest(format!("Multipart error: {}", e)))?;
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let content_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_string())
+            .unwrap_or_default();
+        let content_type = avatar::check_content_type(&content_type)?;
+
+        // Enforce the size limit while reading rather than after buffering everything
+        let mut data = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| {
+                crate::error::AppError::BadRequest(format!("Chunk read error: {}", e))
+            })?;
+            data.extend_from_slice(&chunk);
+            avatar::check_size(data.len())?;
+        }
+        picture = Some(Picture {
+            content_type,
+            data: data.into(),
+        });
+    }
+
+    let picture = picture
+        .ok_or_else(|| crate::error::AppError::BadRequest("No file uploaded".to_string()))?;
+    AvatarStore::new(&upload_dir)
+        .save(user_id, &picture)
+        .await?;
+
+    let profile_image_url = avatar::uploaded_avatar_url(user_id, current_timestamp_seconds());
+    user_service
+        .update_user_profile(user_id, None, Some(&profile_image_url), None, None, None)
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "profile_image_url": profile_image_url })))
+}
+
+/// Remove an uploaded avatar and fall back to the default picture
+async fn reset_avatar(state: &web::Data<AppState>, user_id: &str) -> AppResult<HttpResponse> {
+    let upload_dir = state.config.read().unwrap().upload_dir.clone();
+    let user_service = UserService::new(&state.db);
+    user_service
+        .get_user_by_id(user_id)
+        .await?
+        .ok_or(crate::error::AppError::NotFound(
+            "User not found".to_string(),
+        ))?;
+
+    AvatarStore::new(&upload_dir).remove(user_id).await?;
+    user_service
+        .update_user_profile(
+            user_id,
+            None,
+            Some(DEFAULT_PROFILE_IMAGE_URL),
+            None,
+            None,
+            None,
+        )
+        .await?;
+
+    Ok(HttpResponse::Ok().json(json!({ "profile_image_url": DEFAULT_PROFILE_IMAGE_URL })))
+}
+
+fn default_user_avatar() -> AppResult<HttpResponse> {
     let static_dir = std::path::Path::new("../svelte-frontend/static/static");
     let user_avatar_path = static_dir.join("user.png");
 
     match std::fs::read(user_avatar_path) {
         Ok(image_data) => Ok(HttpResponse::Ok()
             .content_type("image/png")
+            .append_header(("X-Content-Type-Options", "nosniff"))
             .body(image_data)),
         Err(_) => Err(crate::error::AppError::NotFound(
             "Default avatar not found".to_string(),
@@ -240,12 +599,12 @@ async fn get_user_profile_image(
 
 // Get user active status
 async fn get_user_active_status(
-    _state: web::Data<AppState>,
+    state: web::Data<AppState>,
     _auth_user: AuthUser,
-    _id: web::Path<String>,
+    id: web::Path<String>,
 ) -> AppResult<HttpResponse> {
-    // TODO: Get from Socket.IO state
-    Ok(HttpResponse::Ok().json(json!({ "active": false })))
+    let active = online_user_ids(&state).await.contains(&id);
+    Ok(HttpResponse::Ok().json(json!({ "active": active })))
 }
 
 // Get user groups by ID (admin only)
@@ -335,6 +694,8 @@ async fn get_user_settings(
             "User not found".to_string(),
         ))?;
 
+    let etag = settings_etag(user.settings.as_ref());
+
     // Return settings from user model or default structure
     let mut settings = user.settings.unwrap_or_else(|| serde_json::json!({}));
 
@@ -343,19 +704,45 @@ async fn get_user_settings(
         settings["ui"] = serde_json::json!({});
     }
 
-    Ok(HttpResponse::Ok().json(settings))
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(settings))
 }
 
+/// Saving with `If-Match` fails with 409 if another tab changed the settings since
 async fn update_user_settings(
     state: web::Data<AppState>,
     auth_user: AuthUser,
+    req: HttpRequest,
     settings: web::Json<serde_json::Value>,
 ) -> AppResult<HttpResponse> {
     let user_service = UserService::new(&state.db);
+    let if_match = req
+        .headers()
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok());
+
+    // Feature flag overrides are admin-managed; keep whatever is stored
+    let mut settings = settings.into_inner();
+    if let Some(fields) = settings.as_object_mut() {
+        match auth_user
+            .user
+            .settings
+            .as_ref()
+            .and_then(|stored| stored.get(feature_flags::SETTINGS_KEY))
+        {
+            Some(flags) => {
+                fields.insert(feature_flags::SETTINGS_KEY.to_string(), flags.clone());
+            }
+            None => {
+                fields.remove(feature_flags::SETTINGS_KEY);
+            }
+        }
+    }
 
     // Update user settings in database
     user_service
-        .update_user_settings(&auth_user.user.id, &settings.into_inner())
+        .update_user_settings(&auth_user.user.id, &settings, if_match)
         .await?;
 
     // Retrieve and return updated settings
@@ -366,6 +753,7 @@ async fn update_user_settings(
             "User not found".to_string(),
         ))?;
 
+    let etag = settings_etag(user.settings.as_ref());
     let mut settings = user.settings.unwrap_or_else(|| serde_json::json!({}));
 
     // Ensure ui field exists
@@ -373,7 +761,9 @@ async fn update_user_settings(
         settings["ui"] = serde_json::json!({});
     }
 
-    Ok(HttpResponse::Ok().json(settings))
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(settings))
 }
 
 async fn get_user_info(state: web::Data<AppState>, auth_user: AuthUser) -> AppResult<HttpResponse> {
@@ -550,49 +940,7 @@ async fn get_default_user_permissions(
             "features": config.user_permissions.get("features").unwrap_or(&json!({})),
         })
     } else {
-        json!({
-            "workspace": {
-                "models": false,
-                "knowledge": false,
-                "prompts": false,
-                "tools": false
-            },
-            "sharing": {
-                "public_models": true,
-                "public_knowledge": true,
-                "public_prompts": true,
-                "public_tools": true,
-                "public_notes": true
-            },
-            "chat": {
-                "controls": true,
-                "valves": true,
-                "system_prompt": true,
-                "params": true,
-                "file_upload": true,
-                "delete": true,
-                "delete_message": true,
-                "continue_response": true,
-                "regenerate_response": true,
-                "rate_response": true,
-                "edit": true,
-                "share": true,
-                "export": true,
-                "stt": true,
-                "tts": true,
-                "call": true,
-                "multiple_models": true,
-                "temporary": true,
-                "temporary_enforced": false
-            },
-            "features": {
-                "direct_tool_servers": false,
-                "web_search": true,
-                "image_generation": true,
-                "code_interpreter": true,
-                "notes": true
-            }
-        })
+        default_permissions()
     };
 
     Ok(HttpResponse::Ok().json(permissions))
@@ -1,10 +1,11 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use crate::{
     error::{AppError, AppResult},
     middleware::{AdminMiddleware, AuthMiddleware, AuthUser},
+    utils::feature_flags::FeatureFlags,
     AppState,
 };
 
@@ -165,11 +166,14 @@ async fn format_code(
 async fn execute_code(
     state: web::Data<AppState>,
     auth_user: AuthUser, // AdminMiddleware already checked
+    req: HttpRequest,
     form_data: web::Json<CodeForm>,
 ) -> AppResult<HttpResponse> {
     let config = state.config.read().unwrap();
 
-    if !config.enable_code_execution {
+    if !FeatureFlags::from_request(&config, &auth_user.user, &req)
+        .enabled("enable_code_execution", config.enable_code_execution)
+    {
         return Err(AppError::BadRequest(
             "Code execution is not enabled".to_string(),
         ));
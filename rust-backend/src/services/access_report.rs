@@ -0,0 +1,262 @@
+/// Everything a user can reach, with the rule that grants each access
+///
+/// Mirrors the per-resource policies in the knowledge, model and channel routes:
+/// owners and admins always have full access, a missing `access_control` is public,
+/// and otherwise the user must be listed directly or through one of their groups.
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::models::group::Group;
+use crate::models::User;
+use crate::services::channel::ChannelService;
+use crate::services::group::GroupService;
+use crate::services::knowledge::KnowledgeService;
+use crate::services::model::ModelService;
+
+/// Why a user has a given access to a resource
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum AccessReason {
+    Owner,
+    AdminBypass,
+    /// No access control set on the resource
+    Public,
+    /// Listed in the resource's `user_ids`
+    User,
+    /// Through a group listed in the resource's `group_ids`
+    Group {
+        group_id: String,
+        group_name: String,
+    },
+    /// Member of the group itself
+    Member,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceAccess {
+    pub id: String,
+    pub name: String,
+    pub read: AccessReason,
+    pub write: Option<AccessReason>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UserAccessReport {
+    pub user_id: String,
+    pub role: String,
+    pub knowledge: Vec<ResourceAccess>,
+    pub models: Vec<ResourceAccess>,
+    pub groups: Vec<ResourceAccess>,
+    pub channels: Vec<ResourceAccess>,
+}
+
+/// The rule granting `user` `access_type` ("read" or "write"), if any
+pub fn access_reason(
+    user: &User,
+    owner_id: &str,
+    access_control: Option<&Value>,
+    access_type: &str,
+    user_groups: &[Group],
+) -> Option<AccessReason> {
+    if owner_id == user.id {
+        return Some(AccessReason::Owner);
+    }
+    if user.role == "admin" {
+        return Some(AccessReason::AdminBypass);
+    }
+    let Some(access_control) = access_control else {
+        return Some(AccessReason::Public);
+    };
+
+    let permission = access_control.get(access_type)?;
+    let listed = |key: &str, id: &str| {
+        permission
+            .get(key)
+            .and_then(Value::as_array)
+            .is_some_and(|ids| ids.iter().any(|v| v.as_str() == Some(id)))
+    };
+
+    if listed("user_ids", &user.id) {
+        return Some(AccessReason::User);
+    }
+    user_groups
+        .iter()
+        .find(|group| listed("group_ids", &group.id))
+        .map(|group| AccessReason::Group {
+            group_id: group.id.clone(),
+            group_name: group.name.clone(),
+        })
+}
+
+fn resource_access(
+    user: &User,
+    id: &str,
+    name: &str,
+    owner_id: &str,
+    access_control: Option<&Value>,
+    user_groups: &[Group],
+) -> Option<ResourceAccess> {
+    Some(ResourceAccess {
+        id: id.to_string(),
+        name: name.to_string(),
+        read: access_reason(user, owner_id, access_control, "read", user_groups)?,
+        write: access_reason(user, owner_id, access_control, "write", user_groups),
+    })
+}
+
+fn group_access(user: &User, group: &Group) -> Option<ResourceAccess> {
+    let write = if group.user_id == user.id {
+        Some(AccessReason::Owner)
+    } else if user.role == "admin" {
+        Some(AccessReason::AdminBypass)
+    } else {
+        None
+    };
+    let read = if group.user_ids.contains(&user.id) {
+        Some(AccessReason::Member)
+    } else {
+        write.clone()
+    };
+
+    Some(ResourceAccess {
+        id: group.id.clone(),
+        name: group.name.clone(),
+        read: read?,
+        write,
+    })
+}
+
+pub struct AccessReportService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> AccessReportService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        AccessReportService { db }
+    }
+
+    /// Every knowledge base, model, group and channel `user` can read
+    pub async fn for_user(&self, user: &User) -> AppResult<UserAccessReport> {
+        let user_groups = GroupService::new(self.db)
+            .get_groups_by_member_id(&user.id)
+            .await?;
+
+        let knowledge = KnowledgeService::new(self.db)
+            .get_all_knowledge()
+            .await?
+            .iter()
+            .filter_map(|k| {
+                resource_access(
+                    user,
+                    &k.id,
+                    &k.name,
+                    &k.user_id,
+                    k.access_control.as_ref(),
+                    &user_groups,
+                )
+            })
+            .collect();
+
+        let models = ModelService::new(self.db)
+            .get_models()
+            .await?
+            .iter()
+            .filter_map(|m| {
+                resource_access(
+                    user,
+                    &m.id,
+                    &m.name,
+                    &m.user_id,
+                    m.access_control.as_ref(),
+                    &user_groups,
+                )
+            })
+            .collect();
+
+        let groups = GroupService::new(self.db)
+            .get_all_groups()
+            .await?
+            .iter()
+            .filter_map(|group| group_access(user, group))
+            .collect();
+
+        let channels = ChannelService::new(self.db)
+            .get_all_channels()
+            .await?
+            .iter()
+            .filter_map(|c| {
+                resource_access(
+                    user,
+                    &c.id,
+                    &c.name,
+                    &c.user_id,
+                    c.access_control.as_ref(),
+                    &user_groups,
+                )
+            })
+            .collect();
+
+        Ok(UserAccessReport {
+            user_id: user.id.clone(),
+            role: user.role.clone(),
+            knowledge,
+            models,
+            groups,
+            channels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_group, seed_user, test_db};
+    use serde_json::json;
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_access_reasons() {
+        let db = test_db().await;
+        let admin = seed_user(&db, "admin").await;
+        let user = seed_user(&db, "user").await;
+        let group = seed_group(&db, &admin, &[&user]).await;
+        let groups = vec![group.clone()];
+
+        let reason = |owner: &str, access_control: Option<Value>, access_type: &str| {
+            access_reason(&user, owner, access_control.as_ref(), access_type, &groups)
+        };
+
+        assert_eq!(reason(&user.id, None, "write"), Some(AccessReason::Owner));
+        assert_eq!(reason(&admin.id, None, "write"), Some(AccessReason::Public));
+        assert_eq!(reason(&admin.id, Some(json!({})), "read"), None);
+        assert_eq!(
+            reason(
+                &admin.id,
+                Some(json!({"read": {"group_ids": [group.id], "user_ids": []}})),
+                "read"
+            ),
+            Some(AccessReason::Group {
+                group_id: group.id.clone(),
+                group_name: group.name.clone(),
+            })
+        );
+        assert_eq!(
+            reason(
+                &admin.id,
+                Some(json!({"read": {"user_ids": [user.id]}})),
+                "write"
+            ),
+            None
+        );
+        assert_eq!(
+            access_reason(&admin, &user.id, Some(&json!({})), "write", &[]),
+            Some(AccessReason::AdminBypass)
+        );
+
+        let access = group_access(&user, &group).unwrap();
+        assert_eq!(access.read, AccessReason::Member);
+        assert_eq!(access.write, None);
+    }
+}
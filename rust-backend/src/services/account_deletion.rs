@@ -0,0 +1,477 @@
+/// "Delete my account": users removing their own account
+///
+/// The user confirms with their password or, for accounts without one (OAuth, external
+/// IdP), with a session signed in less than ACCOUNT_DELETE_REAUTH_WINDOW seconds ago.
+/// The user row is then anonymized and marked `deleted_at` rather than removed, so audit
+/// entries and reassigned resources still resolve; user lookups skip deleted accounts,
+/// which also invalidates their tokens and API key. Personal rows are removed with the
+/// account, while workspace resources are deleted or handed to an admin per
+/// ACCOUNT_DELETE_POLICY. Once the database commits, the uploaded avatar and, for deleted
+/// files and knowledge bases, their stored uploads and vector collections are removed too.
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+use crate::retrieval::language::collection_names;
+use crate::retrieval::{EmbeddingProvider, VectorDB};
+use crate::services::audit::AuditService;
+use crate::services::avatar::AvatarStore;
+use crate::services::AuthService;
+use crate::utils::time::current_timestamp_seconds;
+
+pub const DELETE_ACTION: &str = "account.deleted";
+
+/// Rows only the user has a use for, removed with the account
+const PERSONAL_TABLES: &[&str] = &[
+    "oauth_session",
+    "chat",
+    "folder",
+    "memory",
+    "tag",
+    "feedback",
+    "channel_member",
+];
+
+/// Resources others may depend on, deleted or reassigned per ACCOUNT_DELETE_POLICY
+const WORKSPACE_TABLES: &[&str] = &[
+    "knowledge",
+    "model",
+    "prompt",
+    "tool",
+    "function",
+    "note",
+    "file",
+    "\"group\"",
+    "channel",
+];
+
+/// Ids of deleted rows whose data also lives outside the database
+#[derive(Debug, Default)]
+struct Leftovers {
+    file_ids: Vec<String>,
+    knowledge_ids: Vec<String>,
+}
+
+pub struct AccountDeletion {
+    reassign: bool,
+    reauth_window: Duration,
+    upload_dir: String,
+    /// Where file uploads are stored, one per file id
+    file_dir: PathBuf,
+    rag: Option<(Arc<dyn VectorDB>, Arc<dyn EmbeddingProvider>)>,
+}
+
+impl AccountDeletion {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            reassign: config.account_delete_policy == "reassign",
+            reauth_window: Duration::from_secs(config.account_delete_reauth_window),
+            upload_dir: config.upload_dir.clone(),
+            file_dir: PathBuf::from(crate::routes::files::UPLOAD_DIR),
+            rag: None,
+        }
+    }
+
+    /// Also drop the vector collections of deleted files and knowledge bases
+    pub fn with_rag(
+        mut self,
+        rag: Option<(Arc<dyn VectorDB>, Arc<dyn EmbeddingProvider>)>,
+    ) -> Self {
+        self.rag = rag;
+        self
+    }
+
+    /// Check the user really means it, then delete their account
+    ///
+    /// `issued_at` is when the caller's token was issued. Returns the admin the workspace
+    /// resources were reassigned to, if any.
+    pub async fn delete(
+        &self,
+        db: &Database,
+        user: &User,
+        issued_at: Option<i64>,
+        password: Option<&str>,
+    ) -> AppResult<Option<String>> {
+        self.confirm(db, user, issued_at, password).await?;
+
+        let mut tx = db.pool.begin().await?;
+
+        // Locking the admins serializes concurrent deletions (and admin role changes),
+        // so two admins can't each leave the other as the last one
+        let admins: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT id FROM "user"
+            WHERE role = 'admin' AND deleted_at IS NULL
+            ORDER BY created_at ASC
+            FOR UPDATE
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let other_admin = admins.into_iter().find(|id| *id != user.id);
+        if user.role == "admin" && other_admin.is_none() {
+            return Err(AppError::Forbidden(
+                "The last admin can't delete their account".to_string(),
+            ));
+        }
+
+        // Shared copies of the user's chats are stored as chats owned by "shared-<id>"
+        sqlx::query(
+            r#"
+            DELETE FROM chat
+            WHERE user_id IN (SELECT 'shared-' || id FROM chat WHERE user_id = $1)
+            "#,
+        )
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await?;
+        for table in PERSONAL_TABLES {
+            sqlx::query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+                .bind(&user.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut leftovers = Leftovers::default();
+        let reassigned_to = if self.reassign {
+            let admin_id = other_admin.ok_or_else(|| {
+                AppError::InternalServerError("No admin to reassign resources to".to_string())
+            })?;
+            for table in WORKSPACE_TABLES {
+                sqlx::query(&format!(
+                    "UPDATE {} SET user_id = $1 WHERE user_id = $2",
+                    table
+                ))
+                .bind(&admin_id)
+                .bind(&user.id)
+                .execute(&mut *tx)
+                .await?;
+            }
+            Some(admin_id)
+        } else {
+            leftovers.file_ids = sqlx::query_scalar("SELECT id FROM file WHERE user_id = $1")
+                .bind(&user.id)
+                .fetch_all(&mut *tx)
+                .await?;
+            leftovers.knowledge_ids =
+                sqlx::query_scalar("SELECT id FROM knowledge WHERE user_id = $1")
+                    .bind(&user.id)
+                    .fetch_all(&mut *tx)
+                    .await?;
+            for table in WORKSPACE_TABLES {
+                sqlx::query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+                    .bind(&user.id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            None
+        };
+
+        sqlx::query("DELETE FROM auth WHERE id = $1")
+            .bind(&user.id)
+            .execute(&mut *tx)
+            .await?;
+        let now = current_timestamp_seconds();
+        sqlx::query(
+            r#"
+            UPDATE "user"
+            SET name = 'Deleted user', email = $1, username = NULL, profile_image_url = '',
+                bio = NULL, gender = NULL, date_of_birth = NULL, info = NULL, settings = NULL,
+                api_key = NULL, oauth_sub = NULL, deleted_at = $2, updated_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(format!("deleted+{}@deleted.invalid", user.id))
+        .bind(now)
+        .bind(&user.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.remove_stored_data(&user.id, &leftovers).await;
+
+        AuditService::new(db)
+            .record(
+                &user.id,
+                DELETE_ACTION,
+                "user",
+                &user.id,
+                Some(json!({ "reassigned_to": reassigned_to })),
+            )
+            .await?;
+        tracing::warn!("User {} deleted their account", user.id);
+
+        Ok(reassigned_to)
+    }
+
+    /// Best-effort removal of what the committed deletion left outside the database
+    ///
+    /// Failures are only logged: the account is already gone, and vector collections
+    /// missed here are picked up by the orphan prune.
+    async fn remove_stored_data(&self, user_id: &str, leftovers: &Leftovers) {
+        if let Err(e) = AvatarStore::new(&self.upload_dir).remove(user_id).await {
+            tracing::warn!("Failed to remove avatar of deleted user {}: {}", user_id, e);
+        }
+
+        for file_id in &leftovers.file_ids {
+            match tokio::fs::remove_file(self.file_dir.join(file_id)).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("Failed to remove upload of file {}: {}", file_id, e),
+            }
+        }
+
+        let Some((vector_db, embedding_provider)) = &self.rag else {
+            return;
+        };
+        let bases = leftovers
+            .file_ids
+            .iter()
+            .map(|id| format!("file-{}", id))
+            .chain(leftovers.knowledge_ids.iter().cloned());
+        for base in bases {
+            for collection in collection_names(embedding_provider.as_ref(), &base) {
+                let result = match vector_db.has_collection(&collection).await {
+                    Ok(true) => vector_db.delete_collection(&collection).await,
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    tracing::warn!("Failed to delete vector collection {}: {}", collection, e);
+                }
+            }
+        }
+    }
+
+    async fn confirm(
+        &self,
+        db: &Database,
+        user: &User,
+        issued_at: Option<i64>,
+        password: Option<&str>,
+    ) -> AppResult<()> {
+        if let Some(password) = password {
+            let confirmed = AuthService::new(db)
+                .authenticate(&user.email.to_lowercase(), password)
+                .await?;
+            if confirmed.as_deref() != Some(user.id.as_str()) {
+                return Err(AppError::Unauthorized("Incorrect password".to_string()));
+            }
+            return Ok(());
+        }
+
+        let has_password = AuthService::new(db)
+            .get_auth_by_email(&user.email.to_lowercase())
+            .await?
+            .is_some_and(|auth| auth.id == user.id);
+        let signed_in_recently = issued_at.is_some_and(|iat| {
+            current_timestamp_seconds() - iat <= self.reauth_window.as_secs() as i64
+        });
+        if has_password || !signed_in_recently {
+            return Err(AppError::Unauthorized(
+                "Confirm with your password, or sign in again and retry".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audit::AuditQuery;
+    use crate::retrieval::vector::VectorItem;
+    use crate::routes::knowledge_vector::tests::{FakeEmbeddings, MemoryVectorDb};
+    use crate::services::avatar::Picture;
+    use crate::services::knowledge::KnowledgeService;
+    use crate::services::UserService;
+    use crate::test_utils::{seed_user, test_db};
+
+    fn deletion(policy: &str) -> AccountDeletion {
+        let policy = policy.to_string();
+        let config = Config::from_lookup(move |key| match key {
+            "ACCOUNT_DELETE_POLICY" => Some(policy.clone()),
+            _ => None,
+        })
+        .unwrap();
+        AccountDeletion::from_config(&config)
+    }
+
+    async fn seed_with_password(db: &Database, role: &str) -> User {
+        let user = seed_user(db, role).await;
+        AuthService::new(db)
+            .create_auth(&user.id, &user.email, "correct-password")
+            .await
+            .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_deletion_requires_confirmation() {
+        let db = test_db().await;
+        let _admin = seed_with_password(&db, "admin").await;
+        let user = seed_with_password(&db, "user").await;
+        let deletion = deletion("delete");
+        let just_now = Some(current_timestamp_seconds());
+
+        // A fresh session isn't enough for an account that has a password
+        for password in [None, Some("wrong-password")] {
+            assert!(matches!(
+                deletion.delete(&db, &user, just_now, password).await,
+                Err(AppError::Unauthorized(_))
+            ));
+        }
+        assert!(UserService::new(&db)
+            .get_user_by_id(&user.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        deletion
+            .delete(&db, &user, None, Some("correct-password"))
+            .await
+            .unwrap();
+        let users = UserService::new(&db);
+        assert!(users.get_user_by_id(&user.id).await.unwrap().is_none());
+        assert!(users
+            .get_user_by_email(&user.email)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(AuthService::new(&db)
+            .authenticate(&user.email, "correct-password")
+            .await
+            .unwrap()
+            .is_none());
+
+        // Without a password, only a recent sign-in confirms
+        let oauth_user = seed_user(&db, "user").await;
+        let an_hour_ago = Some(current_timestamp_seconds() - 3600);
+        assert!(matches!(
+            deletion.delete(&db, &oauth_user, an_hour_ago, None).await,
+            Err(AppError::Unauthorized(_))
+        ));
+        deletion
+            .delete(&db, &oauth_user, just_now, None)
+            .await
+            .unwrap();
+
+        let audit = AuditService::new(&db)
+            .list(&AuditQuery {
+                action: Some(DELETE_ACTION.to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.items.len(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_last_admin_cannot_delete_account() {
+        let db = test_db().await;
+        let first = seed_with_password(&db, "admin").await;
+        let second = seed_with_password(&db, "admin").await;
+        let deletion = deletion("reassign");
+
+        assert_eq!(
+            deletion
+                .delete(&db, &second, None, Some("correct-password"))
+                .await
+                .unwrap(),
+            Some(first.id.clone())
+        );
+        assert!(matches!(
+            deletion
+                .delete(&db, &first, None, Some("correct-password"))
+                .await,
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(UserService::new(&db)
+            .get_user_by_id(&first.id)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_stored_data_removed_with_account() {
+        let db = test_db().await;
+        let user = seed_with_password(&db, "user").await;
+        let dir = tempfile::tempdir().unwrap();
+        let vector_db = Arc::new(MemoryVectorDb::default());
+        let mut deletion = deletion("delete").with_rag(Some((
+            vector_db.clone() as Arc<dyn VectorDB>,
+            Arc::new(FakeEmbeddings {
+                calls: Default::default(),
+            }) as Arc<dyn EmbeddingProvider>,
+        )));
+        deletion.upload_dir = dir.path().to_str().unwrap().to_string();
+        deletion.file_dir = dir.path().join("uploads");
+
+        let knowledge_id = uuid::Uuid::new_v4().to_string();
+        KnowledgeService::new(&db)
+            .create_knowledge(&knowledge_id, &user.id, "Docs", None, None)
+            .await
+            .unwrap();
+        let file_id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO file (id, user_id, filename, path, created_at, updated_at) \
+             VALUES ($1, $2, 'notes.txt', 'notes.txt', 0, 0)",
+        )
+        .bind(&file_id)
+        .bind(&user.id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        std::fs::create_dir_all(&deletion.file_dir).unwrap();
+        std::fs::write(deletion.file_dir.join(&file_id), b"notes").unwrap();
+        let avatars = AvatarStore::new(&deletion.upload_dir);
+        avatars
+            .save(
+                &user.id,
+                &Picture {
+                    content_type: "image/png".to_string(),
+                    data: bytes::Bytes::from_static(b"png"),
+                },
+            )
+            .await
+            .unwrap();
+
+        let file_collection = format!("file-{}", file_id);
+        let other_collection = format!("file-{}", uuid::Uuid::new_v4());
+        for name in [&knowledge_id, &file_collection, &other_collection] {
+            vector_db
+                .insert(
+                    name,
+                    vec![VectorItem {
+                        id: "chunk-0".to_string(),
+                        text: "text".to_string(),
+                        vector: vec![0.0; 3],
+                        metadata: json!({}),
+                    }],
+                )
+                .await
+                .unwrap();
+        }
+
+        deletion
+            .delete(&db, &user, None, Some("correct-password"))
+            .await
+            .unwrap();
+
+        assert!(!deletion.file_dir.join(&file_id).exists());
+        assert!(avatars.load(&user.id).await.unwrap().is_none());
+        assert!(!vector_db.has_collection(&knowledge_id).await.unwrap());
+        assert!(!vector_db.has_collection(&file_collection).await.unwrap());
+        // Other users' collections are left alone
+        assert!(vector_db.has_collection(&other_collection).await.unwrap());
+    }
+}
@@ -26,7 +26,7 @@ pub struct AudioService {
 impl AudioService {
     pub fn new(config: Config) -> Self {
         AudioService {
-            client: Client::new(),
+            client: crate::utils::http::client(),
             config,
         }
     }
@@ -0,0 +1,374 @@
+use serde_json::{json, Value};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::audit::{AuditActionCount, AuditLog, AuditLogPage, AuditQuery};
+use crate::utils::geoip::{self, GeoIp};
+use crate::utils::pagination::{Cursor, CursorPage};
+use crate::utils::time::current_timestamp_seconds;
+
+/// Action recorded when an admin reaches a resource only through their role
+pub const ADMIN_BYPASS_ACTION: &str = "admin.access_bypass";
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub struct AuditService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> AuditService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        AuditService { db }
+    }
+
+    pub async fn record(
+        &self,
+        actor_id: &str,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        data: Option<Value>,
+    ) -> AppResult<AuditLog> {
+        self.insert(
+            actor_id,
+            action,
+            target_type,
+            target_id,
+            data,
+            current_timestamp_seconds(),
+        )
+        .await
+    }
+
+    /// Record that `admin_id` used `access_type` access they'd lack without the admin role
+    pub async fn record_admin_bypass(
+        &self,
+        admin_id: &str,
+        target_type: &str,
+        target_id: &str,
+        access_type: &str,
+    ) -> AppResult<AuditLog> {
+        self.record(
+            admin_id,
+            ADMIN_BYPASS_ACTION,
+            target_type,
+            target_id,
+            Some(json!({ "access": access_type })),
+        )
+        .await
+    }
+
+    async fn insert(
+        &self,
+        actor_id: &str,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        data: Option<Value>,
+        created_at: i64,
+    ) -> AppResult<AuditLog> {
+        let entry = sqlx::query_as::<_, AuditLog>(
+            r#"
+            INSERT INTO audit_log (id, actor_id, action, target_type, target_id, data, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, actor_id, action, target_type, target_id, data, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(actor_id)
+        .bind(action)
+        .bind(target_type)
+        .bind(target_id)
+        .bind(data)
+        .bind(created_at)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(entry)
+    }
+
+    /// Filtered, keyset-paginated entries, newest first
+    ///
+    /// The summary counts every entry matching the filters, not just this page.
+    pub async fn list(&self, query: &AuditQuery) -> AppResult<AuditLogPage> {
+        if let (Some(from), Some(to)) = (query.from, query.to) {
+            if from > to {
+                return Err(AppError::BadRequest(
+                    "from must not be after to".to_string(),
+                ));
+            }
+        }
+        let cursor = Cursor::from_param(query.cursor.as_deref())?;
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let mut builder = QueryBuilder::new(
+            "SELECT id, actor_id, action, target_type, target_id, data, created_at \
+             FROM audit_log WHERE 1 = 1",
+        );
+        push_filters(&mut builder, query);
+        if let Some(cursor) = &cursor {
+            builder
+                .push(" AND (created_at, id) < (")
+                .push_bind(cursor.created_at)
+                .push(", ")
+                .push_bind(&cursor.id)
+                .push(")");
+        }
+        builder
+            .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+            .push_bind(limit + 1);
+
+        let rows = builder
+            .build_query_as::<AuditLog>()
+            .fetch_all(self.db.read_conn())
+            .await?;
+        let page = CursorPage::from_rows(rows, limit as usize, |entry| Cursor {
+            created_at: entry.created_at,
+            id: entry.id.clone(),
+        });
+
+        let mut builder = QueryBuilder::new(
+            "SELECT action, COUNT(*)::BIGINT AS count FROM audit_log WHERE 1 = 1",
+        );
+        push_filters(&mut builder, query);
+        builder.push(" GROUP BY action ORDER BY count DESC, action");
+
+        let summary = builder
+            .build_query_as::<AuditActionCount>()
+            .fetch_all(self.db.read_conn())
+            .await?;
+
+        Ok(AuditLogPage {
+            items: page.items,
+            next_cursor: page.next_cursor,
+            summary,
+        })
+    }
+}
+
+fn push_filters<'q>(builder: &mut QueryBuilder<'q, Postgres>, query: &'q AuditQuery) {
+    if let Some(actor_id) = &query.actor_id {
+        builder.push(" AND actor_id = ").push_bind(actor_id);
+    }
+    if let Some(action) = &query.action {
+        builder
+            .push(" AND action LIKE ")
+            .push_bind(glob_to_like(action));
+    }
+    if let Some(target_type) = &query.target_type {
+        builder.push(" AND target_type = ").push_bind(target_type);
+    }
+    if let Some(target_id) = &query.target_id {
+        builder.push(" AND target_id = ").push_bind(target_id);
+    }
+    if let Some(from) = query.from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = query.to {
+        builder.push(" AND created_at <= ").push_bind(to);
+    }
+}
+
+/// Translate an action glob into a LIKE pattern, escaping LIKE's own wildcards
+fn glob_to_like(glob: &str) -> String {
+    let mut pattern = String::with_capacity(glob.len());
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push('%'),
+            '?' => pattern.push('_'),
+            '%' | '_' | '\\' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            c => pattern.push(c),
+        }
+    }
+    pattern
+}
+
+/// Record an entry in the background so auditing never delays or fails a request
+pub fn spawn_record(
+    state: actix_web::web::Data<crate::AppState>,
+    actor_id: String,
+    action: String,
+    target_type: &'static str,
+    target_id: String,
+    data: Option<Value>,
+) {
+    tokio::spawn(async move {
+        if let Err(e) = AuditService::new(&state.db)
+            .record(&actor_id, &action, target_type, &target_id, data)
+            .await
+        {
+            tracing::warn!("Failed to record audit entry {}: {}", action, e);
+        }
+    });
+}
+
+/// Add the client's approximate region to an entry's data under `region`
+///
+/// Unchanged when GEOIP_DB_PATH is unset or the address isn't in the database.
+pub fn with_region(data: Option<Value>, client_ip: Option<&str>) -> Option<Value> {
+    attach_region(geoip::get(), data, client_ip)
+}
+
+fn attach_region(
+    geoip: Option<&GeoIp>,
+    data: Option<Value>,
+    client_ip: Option<&str>,
+) -> Option<Value> {
+    let Some(region) = geoip.zip(client_ip).and_then(|(geoip, ip)| geoip.lookup(ip)) else {
+        return data;
+    };
+    let mut data = data.unwrap_or_else(|| json!({}));
+    if let Some(fields) = data.as_object_mut() {
+        fields.insert("region".to_string(), json!(region));
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_user, test_db};
+
+    #[test]
+    fn test_glob_to_like() {
+        assert_eq!(glob_to_like("knowledge.*"), "knowledge.%");
+        assert_eq!(glob_to_like("user.?ole"), "user._ole");
+        assert_eq!(glob_to_like("file_added%"), "file\\_added\\%");
+    }
+
+    #[test]
+    fn test_attach_region() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mmdb");
+        geoip::test_db::write(&path, "81.2.69.0".parse().unwrap(), 24, "GB", "ENG");
+        let geoip = GeoIp::open(path.to_str().unwrap()).unwrap();
+
+        let data = attach_region(
+            Some(&geoip),
+            Some(json!({ "provider": "google" })),
+            Some("81.2.69.160:52814"),
+        );
+        assert_eq!(
+            data,
+            Some(json!({
+                "provider": "google",
+                "region": { "country": "GB", "region": "ENG" },
+            }))
+        );
+        assert_eq!(
+            attach_region(Some(&geoip), None, Some("81.2.69.1")).unwrap()["region"]["country"],
+            "GB"
+        );
+
+        // Unknown addresses, missing peers and no database leave the data alone
+        assert_eq!(attach_region(Some(&geoip), None, Some("10.0.0.1")), None);
+        assert_eq!(attach_region(Some(&geoip), None, None), None);
+        assert_eq!(
+            attach_region(None, Some(json!({})), Some("81.2.69.160")),
+            Some(json!({}))
+        );
+    }
+
+    async fn seed(service: &AuditService<'_>, actor_id: &str, action: &str, created_at: i64) {
+        service
+            .insert(actor_id, action, "knowledge", "kb-1", None, created_at)
+            .await
+            .unwrap();
+    }
+
+    fn actions(page: &AuditLogPage) -> Vec<&str> {
+        page.items.iter().map(|e| e.action.as_str()).collect()
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_action_glob_filter() {
+        let db = test_db().await;
+        let user = seed_user(&db, "admin").await;
+        let service = AuditService::new(&db);
+        seed(&service, &user.id, "knowledge.created", 100).await;
+        seed(&service, &user.id, "knowledge.file_added", 200).await;
+        seed(&service, &user.id, "knowledge.file_added", 300).await;
+        seed(&service, &user.id, "knowledgebase.created", 400).await;
+        seed(&service, &user.id, "config.imported", 500).await;
+
+        let page = service
+            .list(&AuditQuery {
+                action: Some("knowledge.*".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            actions(&page),
+            vec![
+                "knowledge.file_added",
+                "knowledge.file_added",
+                "knowledge.created"
+            ]
+        );
+        let summary: Vec<(&str, i64)> = page
+            .summary
+            .iter()
+            .map(|c| (c.action.as_str(), c.count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("knowledge.file_added", 2), ("knowledge.created", 1)]
+        );
+
+        // `_` in the glob is literal, not LIKE's single-character wildcard
+        let page = service
+            .list(&AuditQuery {
+                action: Some("knowledge.file_adde_".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(page.items.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_date_range_bounds_are_inclusive() {
+        let db = test_db().await;
+        let user = seed_user(&db, "admin").await;
+        let service = AuditService::new(&db);
+        for ts in [99, 100, 150, 200, 201] {
+            seed(&service, &user.id, &format!("event.{}", ts), ts).await;
+        }
+
+        let mut query = AuditQuery {
+            from: Some(100),
+            to: Some(200),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = service.list(&query).await.unwrap();
+        assert_eq!(actions(&first), vec!["event.200", "event.150"]);
+        assert_eq!(first.summary.len(), 3);
+
+        query.cursor = first.next_cursor.clone();
+        let second = service.list(&query).await.unwrap();
+        assert_eq!(actions(&second), vec!["event.100"]);
+        assert!(second.next_cursor.is_none());
+
+        let inverted = service
+            .list(&AuditQuery {
+                from: Some(200),
+                to: Some(100),
+                ..Default::default()
+            })
+            .await;
+        assert!(matches!(inverted, Err(AppError::BadRequest(_))));
+    }
+}
@@ -1,7 +1,8 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::Auth;
-use crate::utils::password::{hash_password, verify_password};
+use crate::services::UserService;
+use crate::utils::password::{hash_password, needs_rehash, verify_dummy_password, verify_password};
 use crate::utils::time::current_timestamp_seconds;
 
 pub struct AuthService<'a> {
@@ -50,25 +51,69 @@ impl<'a> AuthService<'a> {
         Ok(result)
     }
 
+    /// Check credentials, returning the auth id when they match
+    ///
+    /// Unknown emails and wrong passwords both return `None` after a full hash
+    /// verification, so neither the response nor its latency reveals whether an
+    /// account exists. A hash made with other than the configured algorithm or parameters
+    /// is replaced while the password is at hand.
     pub async fn authenticate(&self, email: &str, password: &str) -> AppResult<Option<String>> {
         let auth = self.get_auth_by_email(email).await?;
+        let outdated = auth
+            .as_ref()
+            .is_some_and(|auth| needs_rehash(&auth.password));
 
-        if let Some(auth) = auth {
-            if !auth.active {
-                return Err(AppError::Unauthorized("Account is not active".to_string()));
+        let id = check_credentials(auth, password)?;
+        if let Some(id) = id.as_ref().filter(|_| outdated) {
+            // The old hash still works, so a failed upgrade doesn't fail the login
+            if let Err(e) = self.update_password(id, password).await {
+                tracing::warn!("Failed to rehash password for {}: {}", id, e);
             }
+        }
+        Ok(id)
+    }
 
-            if verify_password(password, &auth.password)? {
-                Ok(Some(auth.id))
-            } else {
-                Ok(None)
-            }
-        } else {
-            Ok(None)
+    /// Create the initial admin account if the database has no users
+    ///
+    /// Returns false without changes when any user already exists, so it is safe to run
+    /// on every startup. The first-user check shares the signup advisory lock, so
+    /// concurrently starting replicas create at most one admin.
+    pub async fn bootstrap_admin(&self, email: &str, password: &str) -> AppResult<bool> {
+        let user_service = UserService::new(self.db);
+        if user_service.count_users().await? > 0 {
+            return Ok(false);
+        }
+
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let name = email.split('@').next().unwrap_or(email);
+        let result = user_service
+            .create_user_with_first_user_role(
+                &user_id,
+                name,
+                email,
+                "/user.png",
+                None,
+                |is_first| {
+                    if is_first {
+                        Ok("admin".to_string())
+                    } else {
+                        Err(AppError::UserAlreadyExists)
+                    }
+                },
+            )
+            .await;
+
+        match result {
+            Ok(_) => {}
+            // Another replica created the first user between the count and the insert
+            Err(AppError::UserAlreadyExists) => return Ok(false),
+            Err(e) => return Err(e),
         }
+
+        self.create_auth(&user_id, email, password).await?;
+        Ok(true)
     }
 
-    #[allow(dead_code)]
     pub async fn update_password(&self, id: &str, new_password: &str) -> AppResult<()> {
         let password_hash = hash_password(new_password)?;
 
@@ -98,3 +143,160 @@ impl<'a> AuthService<'a> {
         Ok(())
     }
 }
+
+fn check_credentials(auth: Option<Auth>, password: &str) -> AppResult<Option<String>> {
+    let Some(auth) = auth else {
+        verify_dummy_password(password);
+        return Ok(None);
+    };
+
+    // A corrupt stored hash counts as a mismatch rather than a distinguishable error
+    if !verify_password(password, &auth.password).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    // Only reveal the account state to someone who knows the password
+    if !auth.active {
+        return Err(AppError::Unauthorized("Account is not active".to_string()));
+    }
+
+    Ok(Some(auth.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::password::VERIFICATIONS;
+
+    fn auth(password: &str, active: bool) -> Auth {
+        Auth {
+            id: "user-1".to_string(),
+            email: "user@example.com".to_string(),
+            password: hash_password(password).unwrap(),
+            active,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    /// Hash verifications performed by `f` on this thread
+    fn verifications<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = VERIFICATIONS.with(|n| n.get());
+        let result = f();
+        (result, VERIFICATIONS.with(|n| n.get()) - before)
+    }
+
+    #[test]
+    fn test_unknown_email_and_wrong_password_both_verify_hash() {
+        let (unknown, unknown_checks) = verifications(|| check_credentials(None, "guess"));
+        let (wrong, wrong_checks) =
+            verifications(|| check_credentials(Some(auth("secret", true)), "guess"));
+
+        assert!(unknown.unwrap().is_none());
+        assert!(wrong.unwrap().is_none());
+        assert_eq!(unknown_checks, 1);
+        assert_eq!(wrong_checks, 1);
+    }
+
+    #[test]
+    fn test_inactive_account_hidden_without_password() {
+        assert!(check_credentials(Some(auth("secret", false)), "guess")
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            check_credentials(Some(auth("secret", false)), "secret"),
+            Err(AppError::Unauthorized(_))
+        ));
+        assert_eq!(
+            check_credentials(Some(auth("secret", true)), "secret").unwrap(),
+            Some("user-1".to_string())
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_bootstrap_admin_is_idempotent() {
+        let db = crate::test_utils::test_db().await;
+        let auth_service = AuthService::new(&db);
+
+        assert!(auth_service
+            .bootstrap_admin("admin@example.com", "changeme")
+            .await
+            .unwrap());
+        assert!(!auth_service
+            .bootstrap_admin("admin@example.com", "changeme")
+            .await
+            .unwrap());
+
+        let user = UserService::new(&db)
+            .get_user_by_email("admin@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.role, "admin");
+        assert_eq!(UserService::new(&db).count_users().await.unwrap(), 1);
+        assert!(auth_service
+            .authenticate("admin@example.com", "changeme")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_legacy_bcrypt_hash_upgraded_on_login() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let auth_service = AuthService::new(&db);
+        auth_service
+            .create_auth(&user.id, &user.email, "secret")
+            .await
+            .unwrap();
+        sqlx::query("UPDATE auth SET password = $1 WHERE id = $2")
+            .bind(bcrypt::hash("secret", 4).unwrap())
+            .bind(&user.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        assert!(auth_service
+            .authenticate(&user.email, "wrong")
+            .await
+            .unwrap()
+            .is_none());
+        let stored = auth_service.get_auth_by_email(&user.email).await.unwrap();
+        assert!(stored.unwrap().password.starts_with("$2b$"));
+
+        assert_eq!(
+            auth_service
+                .authenticate(&user.email, "secret")
+                .await
+                .unwrap(),
+            Some(user.id.clone())
+        );
+        let stored = auth_service
+            .get_auth_by_email(&user.email)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(stored.password.starts_with("$argon2id$"));
+        assert!(!needs_rehash(&stored.password));
+        assert!(auth_service
+            .authenticate(&user.email, "secret")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_bootstrap_admin_skipped_when_users_exist() {
+        let db = crate::test_utils::test_db().await;
+        crate::test_utils::seed_user(&db, "user").await;
+
+        assert!(!AuthService::new(&db)
+            .bootstrap_admin("admin@example.com", "changeme")
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,546 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+
+/// Maximum accepted profile picture size
+pub const MAX_PICTURE_SIZE: usize = 5 * 1024 * 1024;
+
+/// Upper bound on cached avatars; expired entries are pruned first when reached
+const MAX_CACHE_ENTRIES: usize = 1000;
+
+/// Formats accepted for uploaded avatars; SVG is left out since it can carry script
+const UPLOAD_FORMATS: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+];
+
+/// A downloaded profile picture
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub content_type: String,
+    pub data: Bytes,
+}
+
+impl Picture {
+    /// Encode as a base64 data URL for storage in `profile_image_url`
+    pub fn to_data_url(&self) -> String {
+        use base64::Engine;
+        let base64_data = base64::engine::general_purpose::STANDARD.encode(&self.data);
+        format!("data:{};base64,{}", self.content_type, base64_data)
+    }
+}
+
+/// Redirects followed when downloading a picture; every hop is checked like the first
+const MAX_REDIRECTS: usize = 3;
+
+/// Download a remote profile picture, enforcing image content type and size limits
+///
+/// Only public http(s) hosts are fetched: the host is resolved up front, refused if
+/// any address is loopback, private or link-local, and the connection is pinned to
+/// the checked addresses so a second lookup can't point it elsewhere.
+pub async fn fetch_picture(url: &str) -> AppResult<Picture> {
+    let mut url = parse_picture_url(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = public_client(&url)
+            .await?
+            .get(url.as_str())
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::ExternalServiceError(format!("Failed to download profile picture: {}", e))
+            })?;
+
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| {
+                    AppError::ExternalServiceError(
+                        "Profile picture redirect has no location".to_string(),
+                    )
+                })?;
+            url = parse_picture_url(url.join(location).map_err(invalid_url)?.as_str())?;
+            continue;
+        }
+        return read_picture(response).await;
+    }
+
+    Err(AppError::ExternalServiceError(
+        "Too many redirects downloading profile picture".to_string(),
+    ))
+}
+
+async fn read_picture(response: reqwest::Response) -> AppResult<Picture> {
+    if !response.status().is_success() {
+        return Err(AppError::ExternalServiceError(format!(
+            "Failed to download profile picture: HTTP {}",
+            response.status()
+        )));
+    }
+
+    let content_type = check_content_type(
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("image/png"),
+    )?;
+    if let Some(len) = response.content_length() {
+        check_size(len as usize)?;
+    }
+
+    let data = response.bytes().await.map_err(|e| {
+        AppError::ExternalServiceError(format!("Failed to read profile picture: {}", e))
+    })?;
+    check_size(data.len())?;
+
+    Ok(Picture { content_type, data })
+}
+
+fn invalid_url(e: url::ParseError) -> AppError {
+    AppError::BadRequest(format!("Invalid profile picture URL: {}", e))
+}
+
+fn parse_picture_url(url: &str) -> AppResult<url::Url> {
+    let url = url::Url::parse(url).map_err(invalid_url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!(
+            "Profile picture URL must be http or https, not {}",
+            url.scheme()
+        )));
+    }
+    Ok(url)
+}
+
+/// A client for `url` that only connects to its host's checked public addresses
+async fn public_client(url: &url::Url) -> AppResult<reqwest::Client> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let (domain, addrs): (Option<&str>, Vec<SocketAddr>) = match url.host() {
+        Some(url::Host::Ipv4(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        Some(url::Host::Ipv6(ip)) => (None, vec![SocketAddr::new(ip.into(), port)]),
+        Some(url::Host::Domain(domain)) => {
+            let addrs = tokio::net::lookup_host((domain, port))
+                .await
+                .map_err(|e| {
+                    AppError::ExternalServiceError(format!(
+                        "Failed to resolve profile picture host {}: {}",
+                        domain, e
+                    ))
+                })?
+                .collect();
+            (Some(domain), addrs)
+        }
+        None => {
+            return Err(AppError::BadRequest(
+                "Profile picture URL has no host".to_string(),
+            ))
+        }
+    };
+
+    if addrs.is_empty() || !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+        return Err(AppError::Forbidden(format!(
+            "Profile picture host {} is not a public address",
+            url.host_str().unwrap_or_default()
+        )));
+    }
+
+    let mut builder =
+        crate::utils::http::builder_for(url.as_str()).redirect(reqwest::redirect::Policy::none());
+    if let Some(domain) = domain {
+        builder = builder.resolve_to_addrs(domain, &addrs);
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::InternalServerError(format!("Failed to build HTTP client: {}", e)))
+}
+
+/// Whether `ip` is routable on the public internet
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// The allowed raster type of a profile picture, without parameters
+///
+/// Only [`UPLOAD_FORMATS`] are accepted; SVG and other types are refused.
+pub fn check_content_type(content_type: &str) -> AppResult<String> {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if !UPLOAD_FORMATS
+        .iter()
+        .any(|(allowed, _)| *allowed == essence)
+    {
+        return Err(AppError::BadRequest(format!(
+            "Profile picture must be PNG, JPEG, GIF or WebP, not {}",
+            content_type
+        )));
+    }
+    Ok(essence)
+}
+
+/// Reject profile pictures over [`MAX_PICTURE_SIZE`]
+pub fn check_size(len: usize) -> AppResult<()> {
+    if len > MAX_PICTURE_SIZE {
+        return Err(AppError::BadRequest(
+            "Profile picture is too large (max 5MB)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// URL serving a user's uploaded avatar; `version` busts browser caches on change
+pub fn uploaded_avatar_url(user_id: &str, version: i64) -> String {
+    format!("/api/v1/users/{}/profile/image?v={}", user_id, version)
+}
+
+/// Whether `profile_image_url` points at an uploaded avatar
+pub fn is_uploaded_avatar_url(profile_image_url: &str) -> bool {
+    profile_image_url.starts_with("/api/v1/users/")
+}
+
+/// Key of `user.info` holding the picture URL the OAuth provider supplied at signup
+pub const OAUTH_PICTURE_KEY: &str = "oauth_picture_url";
+
+/// Whether the user's `profile_image_url` is still the one their OAuth provider supplied
+///
+/// Only these are proxied; a URL the user set themselves is never fetched server-side.
+pub fn is_provider_picture(user: &User) -> bool {
+    user.info
+        .as_ref()
+        .and_then(|info| info.get(OAUTH_PICTURE_KEY))
+        .and_then(|url| url.as_str())
+        .is_some_and(|url| !url.is_empty() && url == user.profile_image_url)
+}
+
+/// Uploaded avatars, one file per user under `{upload_dir}/avatars`
+pub struct AvatarStore {
+    dir: PathBuf,
+}
+
+impl AvatarStore {
+    pub fn new(upload_dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(upload_dir).join("avatars"),
+        }
+    }
+
+    /// Store a user's avatar, replacing any previous upload
+    pub async fn save(&self, user_id: &str, picture: &Picture) -> AppResult<()> {
+        check_size(picture.data.len())?;
+        let extension = UPLOAD_FORMATS
+            .iter()
+            .find(|(content_type, _)| *content_type == picture.content_type)
+            .map(|(_, extension)| *extension)
+            .ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Profile picture must be PNG, JPEG, GIF or WebP, not {}",
+                    picture.content_type
+                ))
+            })?;
+
+        self.remove(user_id).await?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.path(user_id, extension)?, &picture.data).await?;
+        Ok(())
+    }
+
+    /// The user's uploaded avatar, if any
+    pub async fn load(&self, user_id: &str) -> AppResult<Option<Picture>> {
+        for (content_type, extension) in UPLOAD_FORMATS {
+            match tokio::fs::read(self.path(user_id, extension)?).await {
+                Ok(data) => {
+                    return Ok(Some(Picture {
+                        content_type: content_type.to_string(),
+                        data: Bytes::from(data),
+                    }))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn remove(&self, user_id: &str) -> AppResult<()> {
+        for (_, extension) in UPLOAD_FORMATS {
+            if let Err(e) = tokio::fs::remove_file(self.path(user_id, extension)?).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, user_id: &str, extension: &str) -> AppResult<PathBuf> {
+        // User ids become file names, so refuse anything that could escape the directory
+        if user_id.is_empty() || user_id.contains(['/', '\\']) || user_id.starts_with('.') {
+            return Err(AppError::BadRequest("Invalid user id".to_string()));
+        }
+        Ok(self.dir.join(format!("{}.{}", user_id, extension)))
+    }
+}
+
+struct CachedPicture {
+    picture: Picture,
+    fetched_at: Instant,
+}
+
+/// In-memory TTL cache for proxied remote avatars, keyed by remote URL
+#[derive(Default)]
+pub struct AvatarCache {
+    entries: RwLock<HashMap<String, CachedPicture>>,
+}
+
+impl AvatarCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return a fresh cached picture, fetching (and caching) it when missing or expired
+    ///
+    /// If a refresh fails, a stale cached copy is served rather than failing the request.
+    pub async fn get_or_fetch(&self, url: &str, ttl: Duration) -> AppResult<Picture> {
+        let stale = {
+            let entries = self.entries.read().unwrap();
+            match entries.get(url) {
+                Some(entry) if entry.fetched_at.elapsed() < ttl => {
+                    return Ok(entry.picture.clone());
+                }
+                Some(entry) => Some(entry.picture.clone()),
+                None => None,
+            }
+        };
+
+        match fetch_picture(url).await {
+            Ok(picture) => {
+                self.insert(url, picture.clone(), ttl);
+                Ok(picture)
+            }
+            Err(e) => match stale {
+                Some(picture) => {
+                    tracing::warn!("Failed to refresh avatar, serving stale copy: {}", e);
+                    Ok(picture)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn insert(&self, url: &str, picture: Picture, ttl: Duration) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= MAX_CACHE_ENTRIES && !entries.contains_key(url) {
+            entries.retain(|_, entry| entry.fetched_at.elapsed() < ttl);
+            if entries.len() >= MAX_CACHE_ENTRIES {
+                // Still full of fresh entries: drop the oldest one
+                if let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.fetched_at)
+                    .map(|(key, _)| key.clone())
+                {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            url.to_string(),
+            CachedPicture {
+                picture,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picture() -> Picture {
+        Picture {
+            content_type: "image/png".to_string(),
+            data: Bytes::from_static(b"png"),
+        }
+    }
+
+    #[test]
+    fn test_data_url() {
+        assert_eq!(picture().to_data_url(), "data:image/png;base64,cG5n");
+    }
+
+    #[tokio::test]
+    async fn test_fresh_entry_served_from_cache() {
+        let cache = AvatarCache::new();
+        cache.insert(
+            "https://idp.example.com/a.png",
+            picture(),
+            Duration::from_secs(60),
+        );
+
+        // A fresh entry is returned without touching the network
+        let cached = cache
+            .get_or_fetch("https://idp.example.com/a.png", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(cached.data, Bytes::from_static(b"png"));
+    }
+
+    #[tokio::test]
+    async fn test_avatar_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AvatarStore::new(dir.path().to_str().unwrap());
+
+        store.save("user-1", &picture()).await.unwrap();
+        let jpeg = Picture {
+            content_type: "image/jpeg".to_string(),
+            data: Bytes::from_static(b"jpeg"),
+        };
+        // A new upload replaces the old one even when the format changes
+        store.save("user-1", &jpeg).await.unwrap();
+        let loaded = store.load("user-1").await.unwrap().unwrap();
+        assert_eq!(loaded.content_type, "image/jpeg");
+        assert_eq!(loaded.data, jpeg.data);
+
+        store.remove("user-1").await.unwrap();
+        assert!(store.load("user-1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_avatar_store_rejects_bad_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AvatarStore::new(dir.path().to_str().unwrap());
+
+        let svg = Picture {
+            content_type: "image/svg+xml".to_string(),
+            data: Bytes::from_static(b"<svg/>"),
+        };
+        assert!(store.save("user-1", &svg).await.is_err());
+        assert!(store.save("../escape", &picture()).await.is_err());
+        assert!(check_content_type("text/html").is_err());
+        assert!(check_content_type("image/svg+xml").is_err());
+        assert_eq!(
+            check_content_type("Image/PNG; charset=binary").unwrap(),
+            "image/png"
+        );
+        assert!(check_size(MAX_PICTURE_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn test_only_provider_pictures_are_proxied() {
+        let mut user: User = serde_json::from_value(serde_json::json!({
+            "id": "user-1",
+            "name": "Alice",
+            "email": "alice@example.com",
+            "role": "user",
+            "profile_image_url": "https://idp.example.com/a.png",
+            "last_active_at": 0,
+            "updated_at": 0,
+            "created_at": 0,
+        }))
+        .unwrap();
+        assert!(!is_provider_picture(&user));
+
+        user.info = Some(serde_json::json!({ OAUTH_PICTURE_KEY: "https://idp.example.com/a.png" }));
+        assert!(is_provider_picture(&user));
+
+        // Changed by the user afterwards
+        user.profile_image_url = "http://169.254.169.254/latest/meta-data".to_string();
+        assert!(!is_provider_picture(&user));
+    }
+
+    #[test]
+    fn test_only_public_hosts_are_fetched() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(
+                !is_public_ip(ip.parse().unwrap()),
+                "{} should be blocked",
+                ip
+            );
+        }
+        assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip("2606:2800:220:1::1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_internal_urls() {
+        for url in [
+            "http://127.0.0.1/avatar.png",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]:8080/avatar.png",
+            "http://localhost/avatar.png",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                fetch_picture(url).await.is_err(),
+                "{} should be refused",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_bounded() {
+        let cache = AvatarCache::new();
+        for i in 0..MAX_CACHE_ENTRIES + 5 {
+            cache.insert(&format!("u{}", i), picture(), Duration::from_secs(60));
+        }
+        assert_eq!(cache.len(), MAX_CACHE_ENTRIES);
+    }
+}
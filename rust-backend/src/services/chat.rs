@@ -1,11 +1,15 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::chat::{Chat, CreateChatRequest, UpdateChatRequest};
+use crate::utils::pagination::{Cursor, CursorPage};
 use crate::utils::time::current_timestamp_seconds;
 use sqlx::types::JsonValue;
 use sqlx::Row;
 use uuid::Uuid;
 
+/// Title of a chat nobody has named yet
+pub const DEFAULT_CHAT_TITLE: &str = "New Chat";
+
 pub struct ChatService<'a> {
     db: &'a Database,
 }
@@ -18,7 +22,7 @@ impl<'a> ChatService<'a> {
 
     pub async fn create_chat(&self, user_id: &str, req: CreateChatRequest) -> AppResult<Chat> {
         let now = current_timestamp_seconds();
-        let title = req.title.unwrap_or_else(|| "New Chat".to_string());
+        let title = req.title.unwrap_or_else(|| DEFAULT_CHAT_TITLE.to_string());
         let id = req.id;
 
         let meta_value: JsonValue = req.meta.unwrap_or_else(|| serde_json::json!({}));
@@ -116,12 +120,40 @@ impl<'a> ChatService<'a> {
             .bind(user_id)
             .bind(limit)
             .bind(skip)
-            .fetch_all(&self.db.pool)
+            .fetch_all(self.db.read_conn())
             .await?;
 
         Ok(chats)
     }
 
+    /// Page through all of a user's chats, archived included, in id order
+    ///
+    /// Keyed on id rather than an offset, so chats updated mid-way through neither
+    /// repeat nor go missing.
+    pub async fn get_chats_by_user_id_after(
+        &self,
+        user_id: &str,
+        after_id: Option<&str>,
+        limit: i64,
+    ) -> AppResult<Vec<Chat>> {
+        let chats = sqlx::query_as::<_, Chat>(
+            r#"
+            SELECT id, user_id, title, chat, folder_id, archived, pinned, share_id, meta, created_at, updated_at
+            FROM chat
+            WHERE user_id = $1 AND ($2::text IS NULL OR id > $2)
+            ORDER BY id
+            LIMIT $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(chats)
+    }
+
     pub async fn get_pinned_chats_by_user_id(&self, user_id: &str) -> AppResult<Vec<Chat>> {
         let chats = sqlx::query_as::<_, Chat>(
             r#"
@@ -201,7 +233,7 @@ impl<'a> ChatService<'a> {
             .bind(&search_pattern)
             .bind(limit)
             .bind(skip)
-            .fetch_all(&self.db.pool)
+            .fetch_all(self.db.read_conn())
             .await?;
 
         Ok(chats)
@@ -251,6 +283,27 @@ impl<'a> ChatService<'a> {
             .ok_or_else(|| AppError::NotFound("Chat not found after update".to_string()))
     }
 
+    /// Set the title of a chat still called [`DEFAULT_CHAT_TITLE`]; false when it has
+    /// been renamed meanwhile
+    pub async fn set_title_if_untitled(
+        &self,
+        id: &str,
+        user_id: &str,
+        title: &str,
+    ) -> AppResult<bool> {
+        let result = sqlx::query(
+            "UPDATE chat SET title = $1, updated_at = $2 WHERE id = $3 AND user_id = $4 AND title = $5",
+        )
+        .bind(title)
+        .bind(current_timestamp_seconds())
+        .bind(id)
+        .bind(user_id)
+        .bind(DEFAULT_CHAT_TITLE)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn toggle_chat_pinned(&self, id: &str, user_id: &str) -> AppResult<Chat> {
         let now = current_timestamp_seconds();
 
@@ -454,7 +507,7 @@ impl<'a> ChatService<'a> {
     pub async fn count_chats_by_user_id(&self, user_id: &str) -> AppResult<i64> {
         let count: i64 = sqlx::query("SELECT COUNT(*) as count FROM chat WHERE user_id = $1")
             .bind(user_id)
-            .fetch_one(&self.db.pool)
+            .fetch_one(self.db.read_conn())
             .await?
             .try_get("count")?;
 
@@ -572,7 +625,7 @@ impl<'a> ChatService<'a> {
         .bind(user_id)
         .bind(limit)
         .bind(skip)
-        .fetch_all(&self.db.pool)
+        .fetch_all(self.db.read_conn())
         .await?;
 
         let result: Vec<serde_json::Value> = rows
@@ -591,6 +644,54 @@ impl<'a> ChatService<'a> {
         Ok(result)
     }
 
+    /// Keyset-paginated variant of [`Self::get_chat_title_id_list_by_user_id`]
+    ///
+    /// Orders by creation time so chats created or updated while paging don't shift
+    /// later pages.
+    pub async fn get_chat_title_id_page_by_user_id(
+        &self,
+        user_id: &str,
+        cursor: Option<&Cursor>,
+        limit: i64,
+    ) -> AppResult<CursorPage<serde_json::Value>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, title, updated_at, created_at, folder_id
+            FROM chat
+            WHERE user_id = $1 AND archived = false
+                AND ($2::BIGINT IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(user_id)
+        .bind(cursor.map(|c| c.created_at))
+        .bind(cursor.map(|c| c.id.as_str()).unwrap_or_default())
+        .bind(limit + 1)
+        .fetch_all(self.db.read_conn())
+        .await?;
+
+        let items: Vec<serde_json::Value> = rows
+            .iter()
+            .map(|row| {
+                serde_json::json!({
+                    "id": row.get::<String, _>("id"),
+                    "title": row.get::<String, _>("title"),
+                    "updated_at": row.get::<i64, _>("updated_at"),
+                    "created_at": row.get::<i64, _>("created_at"),
+                    "folder_id": row.get::<Option<String>, _>("folder_id"),
+                })
+            })
+            .collect();
+
+        Ok(CursorPage::from_rows(items, limit as usize, |chat| {
+            Cursor {
+                created_at: chat["created_at"].as_i64().unwrap_or_default(),
+                id: chat["id"].as_str().unwrap_or_default().to_string(),
+            }
+        }))
+    }
+
     pub async fn get_chat_list_by_user_id(
         &self,
         user_id: &str,
@@ -624,7 +725,7 @@ impl<'a> ChatService<'a> {
         .bind(&search_pattern)
         .bind(limit)
         .bind(skip)
-        .fetch_all(&self.db.pool)
+        .fetch_all(self.db.read_conn())
         .await?;
 
         let result: Vec<serde_json::Value> = rows
@@ -1058,3 +1159,71 @@ impl<'a> ChatService<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    async fn create(service: &ChatService<'_>, user_id: &str) -> String {
+        let id = Uuid::new_v4().to_string();
+        service
+            .create_chat(
+                user_id,
+                CreateChatRequest {
+                    id: id.clone(),
+                    title: None,
+                    chat: serde_json::json!({}),
+                    folder_id: None,
+                    archived: None,
+                    pinned: None,
+                    share_id: None,
+                    meta: None,
+                },
+            )
+            .await
+            .unwrap();
+        id
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_cursor_paging_is_stable_under_concurrent_inserts() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let service = ChatService::new(&db);
+
+        let mut existing = HashSet::new();
+        for _ in 0..5 {
+            existing.insert(create(&service, &user.id).await);
+        }
+
+        let first = service
+            .get_chat_title_id_page_by_user_id(&user.id, None, 3)
+            .await
+            .unwrap();
+        assert_eq!(first.items.len(), 3);
+
+        // New chats land ahead of the cursor and must not shift the next page
+        for _ in 0..3 {
+            create(&service, &user.id).await;
+        }
+
+        let cursor = Cursor::decode(first.next_cursor.as_deref().unwrap()).unwrap();
+        let second = service
+            .get_chat_title_id_page_by_user_id(&user.id, Some(&cursor), 3)
+            .await
+            .unwrap();
+        assert!(second.next_cursor.is_none());
+
+        let seen: Vec<String> = first
+            .items
+            .iter()
+            .chain(second.items.iter())
+            .map(|chat| chat["id"].as_str().unwrap().to_string())
+            .collect();
+        let unique: HashSet<String> = seen.iter().cloned().collect();
+        assert_eq!(unique.len(), seen.len());
+        assert!(existing.is_subset(&unique));
+    }
+}
@@ -1,5 +1,88 @@
-use crate::{config::Config, db::Database, error::AppError, models::config::ConfigModel};
-use serde_json::json;
+use crate::{
+    config::Config,
+    db::Database,
+    error::{AppError, FieldErrors},
+    models::config::ConfigModel,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Settings that can change without a restart, with the DB config section each is
+/// persisted in
+///
+/// Everything else in `Config` comes from the environment at startup.
+pub const RUNTIME_SETTINGS: &[(&str, &str)] = &[
+    ("enable_direct_connections", "connections"),
+    ("enable_base_models_cache", "connections"),
+    ("enable_openai_api", "openai"),
+    ("openai_api_keys", "openai"),
+    ("openai_api_base_urls", "openai"),
+    ("openai_api_configs", "openai"),
+    ("model_aliases", "openai"),
+    ("model_routing_rules", "openai"),
+    ("show_admin_details", "admin"),
+    ("webui_url", "admin"),
+    ("enable_signup", "admin"),
+    ("enable_login_form", "admin"),
+    ("enable_api_key", "admin"),
+    ("enable_api_key_endpoint_restrictions", "admin"),
+    ("api_key_allowed_endpoints", "admin"),
+    ("default_user_role", "admin"),
+    ("jwt_expires_in", "admin"),
+    ("enable_user_webhooks", "admin"),
+    ("pending_user_overlay_title", "admin"),
+    ("pending_user_overlay_content", "admin"),
+    ("response_watermark", "admin"),
+    ("enable_channels", "admin"),
+    ("enable_notes", "admin"),
+    ("enable_community_sharing", "admin"),
+    ("enable_message_rating", "admin"),
+    ("enable_image_generation", "features"),
+    ("enable_code_execution", "features"),
+    ("enable_code_interpreter", "features"),
+    ("enable_web_search", "features"),
+    ("enable_admin_chat_access", "features"),
+    ("enable_admin_export", "features"),
+    ("default_models", "models"),
+    ("model_order_list", "models"),
+    ("code_execution_engine", "code_execution"),
+    ("code_execution_jupyter_url", "code_execution"),
+    ("code_execution_jupyter_auth", "code_execution"),
+    ("code_execution_jupyter_auth_token", "code_execution"),
+    ("code_execution_jupyter_auth_password", "code_execution"),
+    ("code_execution_jupyter_timeout", "code_execution"),
+    ("code_execution_sandbox_url", "code_execution"),
+    ("code_execution_sandbox_timeout", "code_execution"),
+    ("code_interpreter_engine", "code_interpreter"),
+    ("code_interpreter_prompt_template", "code_interpreter"),
+    ("code_interpreter_jupyter_url", "code_interpreter"),
+    ("code_interpreter_jupyter_auth", "code_interpreter"),
+    ("code_interpreter_jupyter_auth_token", "code_interpreter"),
+    ("code_interpreter_jupyter_auth_password", "code_interpreter"),
+    ("code_interpreter_jupyter_timeout", "code_interpreter"),
+    ("code_interpreter_sandbox_url", "code_interpreter"),
+    ("code_interpreter_sandbox_timeout", "code_interpreter"),
+    ("banners", "ui"),
+    ("default_prompt_suggestions", "ui"),
+    ("tool_server_connections", "tool_servers"),
+    ("oauth_disabled_providers", "oauth"),
+    ("maintenance_mode", "maintenance"),
+    ("maintenance_message", "maintenance"),
+    ("maintenance_until", "maintenance"),
+    ("onboarding_completed", "onboarding"),
+    ("first_run_at", "onboarding"),
+    ("permission_templates", "permissions"),
+];
+
+/// Outcome of importing a config snapshot
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigImport {
+    /// Runtime settings whose imported value was applied
+    pub applied: Vec<String>,
+    /// Settings left alone, with the reason
+    pub skipped: BTreeMap<String, String>,
+}
 
 /// Service for handling configuration persistence
 pub struct ConfigService;
@@ -31,6 +114,66 @@ impl ConfigService {
         }
     }
 
+    /// Re-read environment config, merge persisted DB config and validate the result
+    ///
+    /// Secrets that were generated at startup because their variable is unset are carried
+    /// over from `current`, so a reload never invalidates sessions or encrypted tokens.
+    pub async fn reload(db: &Database, current: &Config) -> Result<Config, AppError> {
+        // Pick up edits to the .env file without touching the process environment, which
+        // other threads may be reading
+        let dotenv = dotenv_vars();
+        let config = Self::from_env_with(
+            |key| dotenv.get(key).cloned().or_else(|| std::env::var(key).ok()),
+            current,
+        )?;
+
+        let config = Self::load_from_db(db, config).await?;
+        config
+            .validate()
+            .map_err(|e| AppError::Validation(e.to_string()))?;
+
+        Ok(config)
+    }
+
+    /// Config from `lookup`, keeping `current`'s generated secrets for unset variables
+    fn from_env_with<F>(lookup: F, current: &Config) -> Result<Config, AppError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut config =
+            Config::from_lookup(&lookup).map_err(|e| AppError::Validation(e.to_string()))?;
+
+        if lookup("WEBUI_SECRET_KEY").is_none() {
+            config.webui_secret_key = current.webui_secret_key.clone();
+        }
+        if lookup("OAUTH_SESSION_TOKEN_ENCRYPTION_KEY").is_none() {
+            config.oauth_session_token_encryption_key =
+                current.oauth_session_token_encryption_key.clone();
+        }
+        if lookup("OAUTH_CLIENT_INFO_ENCRYPTION_KEY").is_none() {
+            config.oauth_client_info_encryption_key =
+                current.oauth_client_info_encryption_key.clone();
+        }
+        Ok(config)
+    }
+
+    /// Names of top-level settings whose values differ between two configs
+    pub fn changed_settings(old: &Config, new: &Config) -> Vec<String> {
+        let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+            (serde_json::to_value(old), serde_json::to_value(new))
+        else {
+            return Vec::new();
+        };
+
+        let mut changed: Vec<String> = new
+            .iter()
+            .filter(|(key, value)| old.get(*key) != Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        changed.sort();
+        changed
+    }
+
     /// Get the latest configuration from database
     pub async fn get_latest_config(db: &Database) -> Result<Option<ConfigModel>, AppError> {
         let result = sqlx::query_as::<_, ConfigModel>(
@@ -119,6 +262,142 @@ impl ConfigService {
         Ok(())
     }
 
+    /// JSON Schema for a config snapshot accepted by [`Self::import`]
+    ///
+    /// Properties are the runtime settings, typed after their current values and nullable
+    /// when the setting is optional. Settings that are currently null accept any type and
+    /// are checked when the imported config is deserialized.
+    pub fn import_schema(config: &Config) -> Value {
+        let current = serde_json::to_value(config).unwrap_or_default();
+        let properties: Map<String, Value> = RUNTIME_SETTINGS
+            .iter()
+            .map(|(key, _)| {
+                let schema = match current.get(*key).map(json_type) {
+                    None | Some("null") => json!({}),
+                    Some(kind) => {
+                        let mut nulled = current.clone();
+                        nulled[*key] = Value::Null;
+                        if serde_json::from_value::<Config>(nulled).is_ok() {
+                            json!({ "type": [kind, "null"] })
+                        } else {
+                            json!({ "type": [kind] })
+                        }
+                    }
+                };
+                (key.to_string(), schema)
+            })
+            .collect();
+
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": properties,
+        })
+    }
+
+    /// Apply the runtime settings from an exported config snapshot
+    ///
+    /// Returns the updated config and which keys were applied or skipped; unknown keys,
+    /// startup-only settings and unchanged values are skipped. Any value that doesn't
+    /// match the schema, or a result that fails validation, rejects the whole import.
+    /// Nothing is persisted; see [`Self::persist_settings`].
+    pub fn import(current: &Config, snapshot: &Value) -> Result<(Config, ConfigImport), AppError> {
+        let Some(snapshot) = snapshot.as_object() else {
+            return Err(AppError::BadRequest(
+                "Config import must be a JSON object".to_string(),
+            ));
+        };
+
+        let schema = Self::import_schema(current);
+        let mut merged = serde_json::to_value(current)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize config: {}", e)))?;
+        let mut report = ConfigImport::default();
+        let mut errors = BTreeMap::new();
+
+        for (key, value) in snapshot {
+            let Some(current_value) = merged.get(key) else {
+                report
+                    .skipped
+                    .insert(key.clone(), "unknown setting".to_string());
+                continue;
+            };
+            if !RUNTIME_SETTINGS.iter().any(|(name, _)| name == key) {
+                report.skipped.insert(
+                    key.clone(),
+                    "not runtime-mutable; set it in the environment".to_string(),
+                );
+                continue;
+            }
+            if current_value == value {
+                report.skipped.insert(key.clone(), "unchanged".to_string());
+                continue;
+            }
+
+            if let Some(message) = schema_violation(&schema["properties"][key], value) {
+                errors.insert(key.clone(), vec![message]);
+                continue;
+            }
+            let mut candidate = merged.clone();
+            candidate[key] = value.clone();
+            if let Err(e) = serde_json::from_value::<Config>(candidate) {
+                errors.insert(key.clone(), vec![e.to_string()]);
+                continue;
+            }
+
+            merged[key] = value.clone();
+            report.applied.push(key.clone());
+        }
+
+        if !errors.is_empty() {
+            return Err(AppError::ValidationError(FieldErrors(errors)));
+        }
+
+        let config: Config =
+            serde_json::from_value(merged).map_err(|e| AppError::Validation(e.to_string()))?;
+        config
+            .validate()
+            .map_err(|e| AppError::Validation(e.to_string()))?;
+
+        Ok((config, report))
+    }
+
+    /// Persist the DB config sections holding `keys`
+    pub async fn persist_settings(
+        db: &Database,
+        config: &Config,
+        keys: &[String],
+    ) -> Result<(), AppError> {
+        let sections: BTreeSet<&str> = RUNTIME_SETTINGS
+            .iter()
+            .filter(|(name, _)| keys.iter().any(|key| key == name))
+            .map(|(_, section)| *section)
+            .collect();
+
+        let config_json = Self::config_to_json(config);
+        for section in sections {
+            Self::update_section(db, section, config_json[section].clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Persisted maintenance state, so an active window survives a restart
+    pub fn maintenance_section(config: &Config) -> serde_json::Value {
+        json!({
+            "enable": config.maintenance_mode,
+            "message": config.maintenance_message,
+            "until": config.maintenance_until
+        })
+    }
+
+    /// Persisted onboarding state, so it no longer depends on the user count
+    pub fn onboarding_section(config: &Config) -> serde_json::Value {
+        json!({
+            "completed": config.onboarding_completed,
+            "first_run_at": config.first_run_at
+        })
+    }
+
     /// Convert Config struct to JSON for database storage
     fn config_to_json(config: &Config) -> serde_json::Value {
         json!({
@@ -133,7 +412,9 @@ impl ConfigService {
                 "enable": config.enable_openai_api,
                 "api_keys": config.openai_api_keys,
                 "api_base_urls": config.openai_api_base_urls,
-                "api_configs": config.openai_api_configs
+                "api_configs": config.openai_api_configs,
+                "model_aliases": config.model_aliases,
+                "model_routing_rules": config.model_routing_rules
             },
             "features": {
                 "enable_channels": config.enable_channels,
@@ -178,6 +459,33 @@ impl ConfigService {
             },
             "tool_servers": {
                 "connections": config.tool_server_connections
+            },
+            "admin": {
+                "show_admin_details": config.show_admin_details,
+                "webui_url": config.webui_url,
+                "enable_signup": config.enable_signup,
+                "enable_login_form": config.enable_login_form,
+                "enable_api_key": config.enable_api_key,
+                "enable_api_key_endpoint_restrictions": config.enable_api_key_endpoint_restrictions,
+                "api_key_allowed_endpoints": config.api_key_allowed_endpoints,
+                "default_user_role": config.default_user_role,
+                "jwt_expires_in": config.jwt_expires_in,
+                "enable_community_sharing": config.enable_community_sharing,
+                "enable_message_rating": config.enable_message_rating,
+                "enable_channels": config.enable_channels,
+                "enable_notes": config.enable_notes,
+                "enable_user_webhooks": config.enable_user_webhooks,
+                "pending_user_overlay_title": config.pending_user_overlay_title,
+                "pending_user_overlay_content": config.pending_user_overlay_content,
+                "response_watermark": config.response_watermark
+            },
+            "oauth": {
+                "disabled_providers": config.oauth_disabled_providers
+            },
+            "maintenance": Self::maintenance_section(config),
+            "onboarding": Self::onboarding_section(config),
+            "permissions": {
+                "templates": config.permission_templates
             }
         })
     }
@@ -288,12 +596,24 @@ impl ConfigService {
             &["openai", "api_configs"],
             config.openai_api_configs.clone(),
         );
+        if let Ok(aliases) =
+            serde_json::from_value(get_json(&["openai", "model_aliases"], json!(null)))
+        {
+            config.model_aliases = aliases;
+        }
+        if let Ok(rules) =
+            serde_json::from_value(get_json(&["openai", "model_routing_rules"], json!(null)))
+        {
+            config.model_routing_rules = rules;
+        }
 
         // Merge Admin config
         config.show_admin_details =
             get_bool(&["admin", "show_admin_details"], config.show_admin_details);
         config.webui_url = get_string(&["admin", "webui_url"], config.webui_url.clone());
         config.enable_signup = get_bool(&["admin", "enable_signup"], config.enable_signup);
+        config.enable_login_form =
+            get_bool(&["admin", "enable_login_form"], config.enable_login_form);
         config.enable_api_key = get_bool(&["admin", "enable_api_key"], config.enable_api_key);
         config.enable_api_key_endpoint_restrictions = get_bool(
             &["admin", "enable_api_key_endpoint_restrictions"],
@@ -432,5 +752,155 @@ impl ConfigService {
             &["tool_servers", "connections"],
             config.tool_server_connections.clone(),
         );
+
+        // Merge OAuth
+        config.oauth_disabled_providers = get_vec_string(
+            &["oauth", "disabled_providers"],
+            config.oauth_disabled_providers.clone(),
+        );
+
+        // Merge maintenance mode
+        config.maintenance_mode = get_bool(&["maintenance", "enable"], config.maintenance_mode);
+        config.maintenance_message = get_string(
+            &["maintenance", "message"],
+            config.maintenance_message.clone(),
+        );
+        config.maintenance_until = get_json(&["maintenance", "until"], json!(null)).as_i64();
+
+        // Merge onboarding
+        config.onboarding_completed =
+            get_bool(&["onboarding", "completed"], config.onboarding_completed);
+        config.first_run_at =
+            get_json(&["onboarding", "first_run_at"], json!(config.first_run_at)).as_i64();
+
+        // Merge permission templates
+        if let Ok(templates) =
+            serde_json::from_value(get_json(&["permissions", "templates"], json!(null)))
+        {
+            config.permission_templates = templates;
+        }
+    }
+}
+
+/// JSON Schema type name of a value
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Check a value against a property schema from [`ConfigService::import_schema`]
+fn schema_violation(schema: &Value, value: &Value) -> Option<String> {
+    let allowed: Vec<&str> = schema
+        .get("type")?
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    let actual = json_type(value);
+    let matches = allowed.contains(&actual) || (actual == "integer" && allowed.contains(&"number"));
+    (!matches).then(|| format!("expected {}, got {}", allowed.join(" or "), actual))
+}
+
+/// Variables set in the .env file, empty when there is none
+fn dotenv_vars() -> HashMap<String, String> {
+    match dotenvy::dotenv_iter() {
+        Ok(vars) => vars
+            .filter_map(|var| match var {
+                Ok(var) => Some(var),
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable .env line: {}", e);
+                    None
+                }
+            })
+            .collect(),
+        Err(_) => HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_lookup(|_| None).unwrap()
+    }
+
+    #[test]
+    fn test_runtime_settings_are_config_fields() {
+        let current = serde_json::to_value(config()).unwrap();
+        for (key, section) in RUNTIME_SETTINGS {
+            assert!(current.get(*key).is_some(), "{} is not a config field", key);
+            assert!(
+                ConfigService::config_to_json(&config())
+                    .get(*section)
+                    .is_some(),
+                "{} is not persisted",
+                section
+            );
+        }
+    }
+
+    #[test]
+    fn test_reload_reads_lookup_and_keeps_generated_secrets() {
+        let current = config();
+        let dotenv = HashMap::from([("ENABLE_SIGNUP".to_string(), "false".to_string())]);
+        let reloaded =
+            ConfigService::from_env_with(|key| dotenv.get(key).cloned(), &current).unwrap();
+
+        assert!(!reloaded.enable_signup);
+        assert_eq!(reloaded.webui_secret_key, current.webui_secret_key);
+        assert_eq!(
+            reloaded.oauth_session_token_encryption_key,
+            current.oauth_session_token_encryption_key
+        );
+    }
+
+    #[test]
+    fn test_import_applies_runtime_settings_and_reports_skipped() {
+        let current = config();
+        let mut snapshot = serde_json::to_value(&current).unwrap();
+        snapshot["enable_signup"] = json!(!current.enable_signup);
+        snapshot["port"] = json!(9999);
+        snapshot["no_such_setting"] = json!(true);
+
+        let (imported, report) = ConfigService::import(&current, &snapshot).unwrap();
+
+        assert_eq!(imported.enable_signup, !current.enable_signup);
+        assert_eq!(imported.port, current.port);
+        assert_eq!(report.applied, vec!["enable_signup".to_string()]);
+        assert_eq!(report.skipped["no_such_setting"], "unknown setting");
+        assert!(report.skipped["port"].starts_with("not runtime-mutable"));
+        assert_eq!(report.skipped["enable_notes"], "unchanged");
+    }
+
+    #[test]
+    fn test_import_rejects_values_violating_schema() {
+        let current = config();
+        let snapshot = json!({
+            "enable_signup": "yes",
+            "model_order_list": "a,b",
+            "enable_notes": !current.enable_notes,
+        });
+
+        match ConfigService::import(&current, &snapshot) {
+            Err(AppError::ValidationError(FieldErrors(errors))) => {
+                assert_eq!(
+                    errors.keys().collect::<Vec<_>>(),
+                    vec!["enable_signup", "model_order_list"]
+                );
+                assert_eq!(
+                    errors["enable_signup"],
+                    vec!["expected boolean, got string"]
+                );
+            }
+            other => panic!("expected validation error, got {:?}", other.map(|(_, r)| r)),
+        }
     }
 }
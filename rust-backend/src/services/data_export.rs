@@ -0,0 +1,301 @@
+/// "Export my data": everything stored about one user as a JSON download
+///
+/// The export is a single JSON object streamed section by section (`exported_at`,
+/// `user`, `chats`, `notes`, `knowledge`, `feedback`, `oauth_sessions`), with chats
+/// fetched a page at a time so large histories are never held in memory. Secrets stay
+/// out: the profile has no API key and OAuth sessions carry metadata only. Each export
+/// is rate-limited per requester and audit-logged.
+use bytes::Bytes;
+use futures::Stream;
+use governor::{clock::DefaultClock, state::keyed::DefaultKeyedStateStore, Quota, RateLimiter};
+use serde::Serialize;
+use serde_json::json;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::feedback::FeedbackModel;
+use crate::models::knowledge::KnowledgeResponse;
+use crate::models::note::NoteModel;
+use crate::models::{User, UserResponse};
+use crate::services::audit::AuditService;
+use crate::services::chat::ChatService;
+use crate::services::knowledge::KnowledgeService;
+use crate::services::note::NoteService;
+use crate::services::{FeedbackService, UserService};
+use crate::utils::time::current_timestamp_seconds;
+
+pub const EXPORT_ACTION: &str = "user.data_exported";
+
+/// Chats fetched per query while streaming
+const CHAT_PAGE_SIZE: i64 = 50;
+
+type ExportRateLimiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
+
+pub struct DataExport {
+    // Exports per requester per day; None when exports are disabled
+    limiter: Option<ExportRateLimiter>,
+}
+
+impl DataExport {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            limiter: NonZeroU32::new(config.user_data_export_per_day)
+                .map(|limit| RateLimiter::keyed(per_day(limit))),
+        }
+    }
+
+    /// Rate-limit and audit-log `requester` exporting `user_id`'s data, then stream it
+    pub async fn start(
+        &self,
+        db: &Database,
+        requester: &User,
+        user_id: &str,
+    ) -> AppResult<(User, impl Stream<Item = AppResult<Bytes>>)> {
+        let Some(limiter) = &self.limiter else {
+            return Err(AppError::Forbidden("Data export is disabled".to_string()));
+        };
+        if limiter.check_key(&requester.id).is_err() {
+            return Err(AppError::TooManyRequests(
+                "Too many data exports, retry later".to_string(),
+            ));
+        }
+
+        let user = UserService::new(db)
+            .get_user_by_id(user_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+        // Recorded before any data leaves, so no export goes unlogged
+        AuditService::new(db)
+            .record(&requester.id, EXPORT_ACTION, "user", &user.id, None)
+            .await?;
+
+        Ok((user.clone(), export_stream(db.clone(), user)))
+    }
+}
+
+fn per_day(limit: NonZeroU32) -> Quota {
+    Quota::with_period(Duration::from_secs(24 * 3600) / limit.get())
+        .expect("a day split `limit` ways is never zero")
+        .allow_burst(limit)
+}
+
+/// What the stream writes next
+enum Step {
+    Open,
+    /// Chats after this id; `None` before the first page
+    Chats {
+        after: Option<String>,
+    },
+    Notes,
+    Knowledge,
+    Feedback,
+    OAuthSessions,
+    Done,
+}
+
+/// The export document for `user`, a chunk per section or page of chats
+pub fn export_stream(db: Database, user: User) -> impl Stream<Item = AppResult<Bytes>> {
+    futures::stream::unfold((db, user, Step::Open), |(db, user, step)| async move {
+        if matches!(step, Step::Done) {
+            return None;
+        }
+        match next_chunk(&db, &user, step).await {
+            Ok((chunk, next)) => Some((Ok(Bytes::from(chunk)), (db, user, next))),
+            // Ends the stream; the client sees a truncated download
+            Err(e) => Some((Err(e), (db, user, Step::Done))),
+        }
+    })
+}
+
+async fn next_chunk(db: &Database, user: &User, step: Step) -> AppResult<(String, Step)> {
+    match step {
+        Step::Open => {
+            let profile = UserResponse::from(user.clone());
+            let chunk = format!(
+                "{{\"exported_at\":{},\"user\":{},\"chats\":[",
+                current_timestamp_seconds(),
+                to_json(&profile)?
+            );
+            Ok((chunk, Step::Chats { after: None }))
+        }
+        Step::Chats { after } => {
+            let chats = ChatService::new(db)
+                .get_chats_by_user_id_after(&user.id, after.as_deref(), CHAT_PAGE_SIZE)
+                .await?;
+            let Some(last) = chats.last() else {
+                return Ok(("]".to_string(), Step::Notes));
+            };
+            let next = Step::Chats {
+                after: Some(last.id.clone()),
+            };
+
+            let mut chunk = String::new();
+            for (i, chat) in chats.iter().enumerate() {
+                if after.is_some() || i > 0 {
+                    chunk.push(',');
+                }
+                chunk.push_str(&to_json(chat)?);
+            }
+            Ok((chunk, next))
+        }
+        Step::Notes => {
+            let notes: Vec<NoteModel> = NoteService::new(db)
+                .get_notes_by_user_id(&user.id)
+                .await?
+                .into_iter()
+                .map(NoteModel::from)
+                .collect();
+            Ok((section("notes", &notes)?, Step::Knowledge))
+        }
+        Step::Knowledge => {
+            let knowledge: Vec<KnowledgeResponse> = KnowledgeService::new(db)
+                .get_knowledge_by_user_id(&user.id)
+                .await?
+                .into_iter()
+                .map(KnowledgeResponse::from)
+                .collect();
+            Ok((section("knowledge", &knowledge)?, Step::Feedback))
+        }
+        Step::Feedback => {
+            let feedback: Vec<FeedbackModel> = FeedbackService::new(db)
+                .get_feedbacks_by_user_id(&user.id)
+                .await?
+                .into_iter()
+                .map(FeedbackModel::from)
+                .collect();
+            Ok((section("feedback", &feedback)?, Step::OAuthSessions))
+        }
+        Step::OAuthSessions => {
+            let sessions = oauth_session_metadata(db, &user.id).await?;
+            let chunk = format!("{}}}", section("oauth_sessions", &sessions)?);
+            Ok((chunk, Step::Done))
+        }
+        Step::Done => Ok((String::new(), Step::Done)),
+    }
+}
+
+/// Provider and lifetime of each OAuth session, never the encrypted token
+async fn oauth_session_metadata(db: &Database, user_id: &str) -> AppResult<Vec<serde_json::Value>> {
+    let rows = sqlx::query_as::<_, (String, String, i64, i64, i64)>(
+        r#"
+        SELECT id, provider, expires_at, created_at, updated_at
+        FROM oauth_session
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(id, provider, expires_at, created_at, updated_at)| {
+            json!({
+                "id": id,
+                "provider": provider,
+                "expires_at": expires_at,
+                "created_at": created_at,
+                "updated_at": updated_at,
+            })
+        })
+        .collect())
+}
+
+fn section<T: Serialize>(name: &str, items: &[T]) -> AppResult<String> {
+    Ok(format!(",\"{}\":{}", name, to_json(&items)?))
+}
+
+fn to_json<T: Serialize>(value: &T) -> AppResult<String> {
+    serde_json::to_string(value)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to serialize export: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chat::CreateChatRequest;
+    use crate::models::note::NoteForm;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_export_contains_every_section() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+
+        let chat_service = ChatService::new(&db);
+        for title in ["First", "Second"] {
+            chat_service
+                .create_chat(
+                    &user.id,
+                    CreateChatRequest {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        title: Some(title.to_string()),
+                        chat: json!({ "messages": [{ "role": "user", "content": title }] }),
+                        folder_id: None,
+                        archived: None,
+                        pinned: None,
+                        share_id: None,
+                        meta: None,
+                    },
+                )
+                .await
+                .unwrap();
+        }
+        NoteService::new(&db)
+            .insert_new_note(
+                &user.id,
+                &NoteForm {
+                    title: "Groceries".to_string(),
+                    data: None,
+                    meta: None,
+                    access_control: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let exporter = DataExport {
+            limiter: Some(RateLimiter::keyed(per_day(NonZeroU32::new(1).unwrap()))),
+        };
+        let (_, stream) = exporter.start(&db, &user, &user.id).await.unwrap();
+        let chunks: Vec<Bytes> = stream.try_collect().await.unwrap();
+        let export: serde_json::Value = serde_json::from_slice(&chunks.concat()).unwrap();
+
+        for key in [
+            "exported_at",
+            "user",
+            "chats",
+            "notes",
+            "knowledge",
+            "feedback",
+            "oauth_sessions",
+        ] {
+            assert!(export.get(key).is_some(), "missing {}", key);
+        }
+        assert_eq!(export["user"]["id"], user.id.as_str());
+        assert!(export["user"].get("api_key").is_none());
+        assert_eq!(export["chats"].as_array().unwrap().len(), 2);
+        assert_eq!(export["notes"][0]["title"], "Groceries");
+
+        // One export per day, and the one that went out was logged
+        assert!(matches!(
+            exporter.start(&db, &user, &user.id).await,
+            Err(AppError::TooManyRequests(_))
+        ));
+        let audit = crate::services::audit::AuditService::new(&db)
+            .list(&crate::models::audit::AuditQuery {
+                action: Some(EXPORT_ACTION.to_string()),
+                target_id: Some(user.id.clone()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.items.len(), 1);
+    }
+}
@@ -1,7 +1,13 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::models::file::File;
+use crate::models::file::{File, IngestProgress};
+use crate::models::User;
+use crate::services::group::GroupService;
+use crate::services::knowledge::KnowledgeService;
+use crate::services::rag_chunk::RagChunkService;
+use crate::utils::misc::has_access;
 use crate::utils::time::current_timestamp_seconds;
+use std::collections::HashSet;
 
 #[allow(dead_code)]
 pub struct FileService<'a> {
@@ -14,6 +20,16 @@ impl<'a> FileService<'a> {
         FileService { db }
     }
 
+    /// Chunk text store sharing this service's database
+    pub fn rag_chunks(&self) -> RagChunkService<'a> {
+        RagChunkService::new(self.db)
+    }
+
+    /// Knowledge store sharing this service's database
+    pub fn knowledge(&self) -> KnowledgeService<'a> {
+        KnowledgeService::new(self.db)
+    }
+
     pub async fn create_file(
         &self,
         id: &str,
@@ -46,6 +62,36 @@ impl<'a> FileService<'a> {
             .ok_or_else(|| AppError::InternalServerError("Failed to create file".to_string()))
     }
 
+    /// Copy a file's record, extracted content included, to `new_id` owned by `user_id`
+    ///
+    /// The copy is private to its owner; the stored upload isn't touched.
+    pub async fn copy_file(&self, id: &str, new_id: &str, user_id: &str) -> AppResult<File> {
+        let now = current_timestamp_seconds();
+
+        let copied = sqlx::query(
+            r#"
+            INSERT INTO file (id, user_id, filename, path, data, meta, hash, created_at, updated_at)
+            SELECT $1, $2, filename, path, data, meta, hash, $3, $3
+            FROM file
+            WHERE id = $4
+            "#,
+        )
+        .bind(new_id)
+        .bind(user_id)
+        .bind(now)
+        .bind(id)
+        .execute(&self.db.pool)
+        .await?
+        .rows_affected();
+        if copied == 0 {
+            return Err(AppError::NotFound(format!("File {} not found", id)));
+        }
+
+        self.get_file_by_id(new_id)
+            .await?
+            .ok_or_else(|| AppError::InternalServerError("Failed to copy file".to_string()))
+    }
+
     pub async fn get_file_by_id(&self, id: &str) -> AppResult<Option<File>> {
         let result = sqlx::query_as::<_, File>(
             r#"
@@ -119,6 +165,46 @@ impl<'a> FileService<'a> {
         Ok(files)
     }
 
+    /// Whether `user` may read `file`
+    ///
+    /// Besides the owner and admins, anyone who can read a knowledge base containing the
+    /// file may read it, so sharing a knowledge base shares its files.
+    pub async fn can_read(&self, file: &File, user: &User) -> AppResult<bool> {
+        if file.user_id == user.id || user.role == "admin" {
+            return Ok(true);
+        }
+
+        let knowledge_bases = KnowledgeService::new(self.db)
+            .get_knowledge_by_file_id(&file.id)
+            .await?;
+        if knowledge_bases.is_empty() {
+            return Ok(false);
+        }
+
+        let user_group_ids: HashSet<String> = GroupService::new(self.db)
+            .get_groups_by_member_id(&user.id)
+            .await?
+            .into_iter()
+            .map(|g| g.id)
+            .collect();
+
+        Ok(knowledge_bases.iter().any(|knowledge| {
+            knowledge.user_id == user.id
+                || has_access(&user.id, "read", &knowledge.access_control, &user_group_ids)
+        }))
+    }
+
+    /// The file with `id`, if `user` may read it
+    ///
+    /// Use this before putting a file into a knowledge base: that shares it with the
+    /// knowledge base's readers, so it must be one the caller can read already.
+    pub async fn get_readable_file(&self, id: &str, user: &User) -> AppResult<Option<File>> {
+        match self.get_file_by_id(id).await? {
+            Some(file) if self.can_read(&file, user).await? => Ok(Some(file)),
+            _ => Ok(None),
+        }
+    }
+
     pub async fn update_file_metadata(&self, id: &str, meta: serde_json::Value) -> AppResult<File> {
         let now = current_timestamp_seconds();
         let meta_str = serde_json::to_string(&meta).unwrap_or_else(|_| "{}".to_string());
@@ -126,8 +212,8 @@ impl<'a> FileService<'a> {
         sqlx::query(
             r#"
             UPDATE file
-            SET meta = $6, updated_at = $5
-            WHERE id = $4
+            SET meta = $1, updated_at = $2
+            WHERE id = $3
             "#,
         )
         .bind(&meta_str)
@@ -146,6 +232,7 @@ impl<'a> FileService<'a> {
             .bind(id)
             .execute(&self.db.pool)
             .await?;
+        self.rag_chunks().delete_chunks_by_file_id(id).await?;
 
         Ok(())
     }
@@ -199,6 +286,94 @@ impl<'a> FileService<'a> {
             .ok_or_else(|| AppError::NotFound("File not found".to_string()))
     }
 
+    pub async fn get_ingest_progress(
+        &self,
+        file_id: &str,
+        knowledge_id: &str,
+    ) -> AppResult<Option<IngestProgress>> {
+        let progress = sqlx::query_as::<_, IngestProgress>(
+            "SELECT * FROM file_ingest_progress WHERE file_id = $1 AND knowledge_id = $2",
+        )
+        .bind(file_id)
+        .bind(knowledge_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Ingest progress of a file in every knowledge base it was added to
+    pub async fn get_ingest_progress_by_file(
+        &self,
+        file_id: &str,
+    ) -> AppResult<Vec<IngestProgress>> {
+        let progress = sqlx::query_as::<_, IngestProgress>(
+            "SELECT * FROM file_ingest_progress WHERE file_id = $1 ORDER BY updated_at DESC",
+        )
+        .bind(file_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(progress)
+    }
+
+    /// Record where an ingest stands; one row per file and knowledge base
+    pub async fn save_ingest_progress(&self, progress: &IngestProgress) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO file_ingest_progress
+                (file_id, knowledge_id, fingerprint, chunks_total, chunks_done, status, error, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (file_id, knowledge_id) DO UPDATE SET
+                fingerprint = EXCLUDED.fingerprint,
+                chunks_total = EXCLUDED.chunks_total,
+                chunks_done = EXCLUDED.chunks_done,
+                status = EXCLUDED.status,
+                error = EXCLUDED.error,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(&progress.file_id)
+        .bind(&progress.knowledge_id)
+        .bind(&progress.fingerprint)
+        .bind(progress.chunks_total)
+        .bind(progress.chunks_done)
+        .bind(&progress.status)
+        .bind(&progress.error)
+        .bind(current_timestamp_seconds())
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// When a file last finished indexing into a knowledge base
+    pub async fn get_last_indexed_at(&self, knowledge_id: &str) -> AppResult<Option<i64>> {
+        let last_indexed_at: Option<i64> = sqlx::query_scalar(
+            "SELECT MAX(updated_at) FROM file_ingest_progress WHERE knowledge_id = $1 AND status = $2",
+        )
+        .bind(knowledge_id)
+        .bind(IngestProgress::COMPLETED)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(last_indexed_at)
+    }
+
+    /// Mark ingests left running by a previous process as interrupted
+    pub async fn mark_interrupted_ingests(&self) -> AppResult<u64> {
+        let result = sqlx::query(
+            "UPDATE file_ingest_progress SET status = $1, updated_at = $2 WHERE status = $3",
+        )
+        .bind(IngestProgress::INTERRUPTED)
+        .bind(current_timestamp_seconds())
+        .bind(IngestProgress::RUNNING)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
     pub async fn search_files_by_pattern(
         &self,
         user_id: Option<&str>,
@@ -318,3 +493,88 @@ impl<'a> FileService<'a> {
         Ok(metadatas)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_user, test_db};
+    use serde_json::json;
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_knowledge_readers_can_read_its_files() {
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let reader = seed_user(&db, "user").await;
+        let outsider = seed_user(&db, "user").await;
+
+        let service = FileService::new(&db);
+        let file = service
+            .create_file("file-1", &owner.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+        KnowledgeService::new(&db)
+            .create_knowledge_with_access_control(
+                "kb-1",
+                &owner.id,
+                "Private",
+                None,
+                Some(json!({ "file_ids": ["file-1"] })),
+                Some(json!({ "read": { "user_ids": [reader.id], "group_ids": [] } })),
+            )
+            .await
+            .unwrap();
+
+        assert!(service.can_read(&file, &owner).await.unwrap());
+        assert!(service.can_read(&file, &reader).await.unwrap());
+        assert!(!service.can_read(&file, &outsider).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_file_outside_knowledge_is_owner_only() {
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let other = seed_user(&db, "user").await;
+        let admin = seed_user(&db, "admin").await;
+
+        let service = FileService::new(&db);
+        let file = service
+            .create_file("file-2", &owner.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+
+        assert!(!service.can_read(&file, &other).await.unwrap());
+        assert!(service.can_read(&file, &admin).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_other_users_file_not_readable_for_own_knowledge() {
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let other = seed_user(&db, "user").await;
+
+        let service = FileService::new(&db);
+        service
+            .create_file("file-3", &owner.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+        // A knowledge base of their own doesn't make someone else's file theirs to add
+        KnowledgeService::new(&db)
+            .create_knowledge("kb-3", &other.id, "Mine", None, None)
+            .await
+            .unwrap();
+
+        assert!(service
+            .get_readable_file("file-3", &other)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(service
+            .get_readable_file("file-3", &owner)
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,8 +1,39 @@
+use std::collections::HashMap;
+
+use crate::config::Config;
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::group::{Group, GroupForm, GroupUpdateForm};
+use crate::utils::permissions::apply_template;
 use crate::utils::time::current_timestamp_seconds;
 
+/// Outcome of adding a user to groups by name
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GroupSyncSummary {
+    /// Groups the user newly joined
+    pub joined: Vec<String>,
+    /// Groups the user was already in
+    pub unchanged: Vec<String>,
+    /// Missing groups left alone because creation is disabled
+    pub skipped: Vec<String>,
+    /// Groups that couldn't be created or joined, with the error
+    pub failed: Vec<(String, String)>,
+}
+
+impl GroupSyncSummary {
+    fn log_failures(&self, user_id: &str, origin: &str) {
+        for (name, error) in &self.failed {
+            tracing::warn!(
+                "Failed to add user {} to group {} from {}: {}",
+                user_id,
+                name,
+                origin,
+                error
+            );
+        }
+    }
+}
+
 pub struct GroupService<'a> {
     db: &'a Database,
 }
@@ -293,6 +324,209 @@ impl<'a> GroupService<'a> {
             .ok_or_else(|| AppError::NotFound("Group not found".to_string()))
     }
 
+    /// Overwrite the permissions a template sets on a group, keeping the rest
+    pub async fn apply_permission_template(
+        &self,
+        id: &str,
+        template: &serde_json::Value,
+    ) -> AppResult<Group> {
+        let group = self
+            .get_group_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Group not found".to_string()))?;
+
+        let permissions = apply_template(group.permissions.as_ref(), template);
+
+        sqlx::query(
+            r#"
+            UPDATE "group"
+            SET permissions = $1::jsonb, updated_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(permissions.to_string())
+        .bind(current_timestamp_seconds())
+        .bind(id)
+        .execute(&self.db.pool)
+        .await?;
+
+        self.get_group_by_id(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Group not found".to_string()))
+    }
+
+    /// Add a user to the named groups, creating missing ones when `create_missing` is set
+    ///
+    /// Created groups are owned by the user and described as coming from `origin`.
+    /// Existing groups are looked up and joined in one query each; a group that can't be
+    /// created or joined is logged and reported without stopping the others.
+    pub async fn add_user_to_groups_by_name(
+        &self,
+        user_id: &str,
+        group_names: &[String],
+        create_missing: bool,
+        origin: &str,
+    ) -> GroupSyncSummary {
+        let mut summary = GroupSyncSummary::default();
+        let mut names: Vec<String> = Vec::new();
+        for name in group_names {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        if names.is_empty() {
+            return summary;
+        }
+
+        let existing = sqlx::query_as::<_, (String, String)>(
+            r#"SELECT name, id FROM "group" WHERE name = ANY($1)"#,
+        )
+        .bind(&names)
+        .fetch_all(&self.db.pool)
+        .await;
+        let mut existing: HashMap<String, String> = match existing {
+            Ok(rows) => rows.into_iter().collect(),
+            Err(e) => {
+                let error = AppError::from(e).to_string();
+                summary.failed = names
+                    .into_iter()
+                    .map(|name| (name, error.clone()))
+                    .collect();
+                summary.log_failures(user_id, origin);
+                return summary;
+            }
+        };
+
+        // (name, id) of every group to join
+        let mut targets = Vec::new();
+        for name in names {
+            if let Some(group_id) = existing.remove(&name) {
+                targets.push((name, group_id));
+            } else if create_missing {
+                match self.create_group_by_name(user_id, &name, origin).await {
+                    Ok(group_id) => targets.push((name, group_id)),
+                    Err(e) => summary.failed.push((name, e.to_string())),
+                }
+            } else {
+                tracing::debug!("Skipping group creation for '{}' (disabled)", name);
+                summary.skipped.push(name);
+            }
+        }
+
+        let group_ids: Vec<String> = targets.iter().map(|(_, id)| id.clone()).collect();
+        match self.join_groups(user_id, &group_ids).await {
+            Ok(joined) => {
+                for (name, group_id) in targets {
+                    if joined.contains(&group_id) {
+                        summary.joined.push(name);
+                    } else {
+                        summary.unchanged.push(name);
+                    }
+                }
+            }
+            Err(e) => {
+                // Retry one by one so a single bad group doesn't keep the user out of the rest
+                tracing::warn!("Batch group join failed, retrying per group: {}", e);
+                for (name, group_id) in targets {
+                    match self
+                        .join_groups(user_id, std::slice::from_ref(&group_id))
+                        .await
+                    {
+                        Ok(joined) if joined.is_empty() => summary.unchanged.push(name),
+                        Ok(_) => summary.joined.push(name),
+                        Err(e) => summary.failed.push((name, e.to_string())),
+                    }
+                }
+            }
+        }
+
+        for name in &summary.joined {
+            tracing::info!("Added user {} to group {}", user_id, name);
+        }
+        summary.log_failures(user_id, origin);
+        summary
+    }
+
+    /// Create a group named `name` unless it exists, returning its id
+    async fn create_group_by_name(
+        &self,
+        user_id: &str,
+        name: &str,
+        origin: &str,
+    ) -> AppResult<String> {
+        let now = current_timestamp_seconds();
+        let result = sqlx::query(
+            r#"
+            INSERT INTO "group" (id, user_id, name, description, meta, permissions, user_ids, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, NULL, NULL, '[]'::jsonb, $5, $6)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(name)
+        .bind(format!("Auto-created from {}: {}", origin, name))
+        .bind(now)
+        .bind(now)
+        .execute(&self.db.pool)
+        .await?;
+
+        if result.rows_affected() > 0 {
+            tracing::info!("Created group {} from {}", name, origin);
+        }
+
+        // A concurrent creation may have won the insert
+        Ok(
+            sqlx::query_scalar::<_, String>(r#"SELECT id FROM "group" WHERE name = $1"#)
+                .bind(name)
+                .fetch_one(&self.db.pool)
+                .await?,
+        )
+    }
+
+    /// Append the user to each group they aren't in yet, returning the ids of those groups
+    async fn join_groups(&self, user_id: &str, group_ids: &[String]) -> AppResult<Vec<String>> {
+        if group_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Append atomically so concurrent joins don't overwrite each other
+        Ok(sqlx::query_scalar::<_, String>(
+            r#"
+            UPDATE "group"
+            SET user_ids = COALESCE(user_ids, '[]'::jsonb) || $2::jsonb,
+                updated_at = $3
+            WHERE id = ANY($1)
+            AND NOT COALESCE(user_ids, '[]'::jsonb) @> $2::jsonb
+            RETURNING id
+            "#,
+        )
+        .bind(group_ids)
+        .bind(serde_json::json!([user_id]).to_string())
+        .bind(current_timestamp_seconds())
+        .fetch_all(&self.db.pool)
+        .await?)
+    }
+
+    /// Add a newly created user to DEFAULT_USER_GROUPS
+    pub async fn add_user_to_default_groups(
+        &self,
+        user_id: &str,
+        config: &Config,
+    ) -> GroupSyncSummary {
+        if config.default_user_groups.is_empty() {
+            return GroupSyncSummary::default();
+        }
+
+        self.add_user_to_groups_by_name(
+            user_id,
+            &config.default_user_groups,
+            config.enable_default_group_creation,
+            "default user groups",
+        )
+        .await
+    }
+
     pub async fn delete_group_by_id(&self, id: &str) -> AppResult<bool> {
         let result = sqlx::query(r#"DELETE FROM "group" WHERE id = $1"#)
             .bind(id)
@@ -302,3 +536,121 @@ impl<'a> GroupService<'a> {
         Ok(result.rows_affected() > 0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_new_user_joins_default_groups() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let group_name = format!("everyone-{}", uuid::Uuid::new_v4());
+
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.default_user_groups = vec![group_name.clone()];
+        let service = GroupService::new(&db);
+
+        // Missing groups are only created when enabled
+        service.add_user_to_default_groups(&user.id, &config).await;
+        assert!(service
+            .get_groups_by_member_id(&user.id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        config.enable_default_group_creation = true;
+        service.add_user_to_default_groups(&user.id, &config).await;
+        // Joining again is a no-op
+        service.add_user_to_default_groups(&user.id, &config).await;
+
+        let groups = service.get_groups_by_member_id(&user.id).await.unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, group_name);
+        assert_eq!(
+            groups[0]
+                .user_ids
+                .iter()
+                .filter(|id| **id == user.id)
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_failed_group_does_not_block_others() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let service = GroupService::new(&db);
+
+        // Group names are VARCHAR(255), so creating this one fails
+        let names = vec![
+            "engineering".to_string(),
+            "x".repeat(300),
+            "sales".to_string(),
+        ];
+        let summary = service
+            .add_user_to_groups_by_name(&user.id, &names, true, "OAuth")
+            .await;
+
+        assert_eq!(summary.joined, vec!["engineering", "sales"]);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, names[1]);
+
+        let mut groups: Vec<String> = service
+            .get_groups_by_member_id(&user.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|group| group.name)
+            .collect();
+        groups.sort();
+        assert_eq!(groups, vec!["engineering", "sales"]);
+
+        // A second sync leaves existing memberships alone
+        let summary = service
+            .add_user_to_groups_by_name(&user.id, &names[..1], true, "OAuth")
+            .await;
+        assert_eq!(summary.unchanged, vec!["engineering"]);
+        assert!(summary.joined.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_apply_permission_template() {
+        use crate::utils::access_control::has_permission;
+        use serde_json::json;
+
+        let db = crate::test_utils::test_db().await;
+        let admin = crate::test_utils::seed_user(&db, "admin").await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let group = crate::test_utils::seed_group(&db, &admin, &[&user]).await;
+        let defaults = crate::utils::permissions::default_permissions();
+
+        assert!(
+            !has_permission(&db, &user.id, "workspace.models", &defaults)
+                .await
+                .unwrap()
+        );
+
+        let template = json!({"workspace": {"models": true, "knowledge": true}});
+        let group = GroupService::new(&db)
+            .apply_permission_template(&group.id, &template)
+            .await
+            .unwrap();
+        assert_eq!(group.permissions.unwrap()["workspace"]["knowledge"], true);
+
+        for key in ["workspace.models", "workspace.knowledge"] {
+            assert!(has_permission(&db, &user.id, key, &defaults).await.unwrap());
+        }
+        assert!(!has_permission(&db, &user.id, "workspace.tools", &defaults)
+            .await
+            .unwrap());
+        // Defaults still apply to keys the template doesn't set
+        assert!(has_permission(&db, &user.id, "chat.delete", &defaults)
+            .await
+            .unwrap());
+    }
+}
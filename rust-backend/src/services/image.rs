@@ -34,7 +34,7 @@ pub struct ImageService {
 impl ImageService {
     pub fn new(config: Config) -> Self {
         ImageService {
-            client: Client::new(),
+            client: crate::utils::http::client(),
             config,
         }
     }
@@ -0,0 +1,199 @@
+/// Admin impersonation of other users
+///
+/// An admin re-enters their password to receive a short-lived token acting as the
+/// target user. The token carries an `impersonated_by` claim, so the auth middleware
+/// refuses writes made with it. Every session start is audit-logged.
+use governor::{clock::DefaultClock, state::keyed::DefaultKeyedStateStore, Quota, RateLimiter};
+use serde::Serialize;
+use serde_json::json;
+use std::num::NonZeroU32;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+use crate::services::audit::AuditService;
+use crate::services::{AuthService, UserService};
+use crate::utils::auth::create_impersonation_jwt;
+
+pub const IMPERSONATION_ACTION: &str = "user.impersonated";
+
+type AdminRateLimiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
+
+#[derive(Debug, Serialize)]
+pub struct ImpersonationGrant {
+    pub token: String,
+    pub token_type: String,
+    pub expires_at: i64,
+    pub user_id: String,
+}
+
+pub struct Impersonation {
+    ttl: chrono::Duration,
+    // Sessions per admin per hour; None when impersonation is disabled
+    limiter: Option<AdminRateLimiter>,
+}
+
+impl Impersonation {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            ttl: chrono::Duration::seconds(config.impersonation_ttl as i64),
+            limiter: NonZeroU32::new(config.impersonation_rate_limit_per_hour)
+                .map(|limit| RateLimiter::keyed(Quota::per_hour(limit))),
+        }
+    }
+
+    /// Verify the admin's password and mint a token acting as `target_id`
+    pub async fn start(
+        &self,
+        db: &Database,
+        secret: &str,
+        admin: &User,
+        target_id: &str,
+        password: &str,
+    ) -> AppResult<ImpersonationGrant> {
+        let Some(limiter) = &self.limiter else {
+            return Err(AppError::Forbidden("Impersonation is disabled".to_string()));
+        };
+        // Checked first so the password prompt can't be used to guess either
+        if limiter.check_key(&admin.id).is_err() {
+            return Err(AppError::TooManyRequests(
+                "Too many impersonation sessions started, retry later".to_string(),
+            ));
+        }
+
+        let confirmed = AuthService::new(db)
+            .authenticate(&admin.email.to_lowercase(), password)
+            .await?;
+        if confirmed.as_deref() != Some(admin.id.as_str()) {
+            return Err(AppError::Unauthorized("Incorrect password".to_string()));
+        }
+
+        if target_id == admin.id {
+            return Err(AppError::BadRequest(
+                "Cannot impersonate yourself".to_string(),
+            ));
+        }
+        let target = UserService::new(db)
+            .get_user_by_id(target_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        if target.role == "admin" {
+            return Err(AppError::Forbidden(
+                "Admins cannot be impersonated".to_string(),
+            ));
+        }
+
+        let (token, expires_at) =
+            create_impersonation_jwt(&target.id, &admin.id, secret, self.ttl)?;
+
+        // Recorded before the token is handed out, so no session goes unlogged
+        AuditService::new(db)
+            .record(
+                &admin.id,
+                IMPERSONATION_ACTION,
+                "user",
+                &target.id,
+                Some(json!({ "expires_at": expires_at })),
+            )
+            .await?;
+
+        tracing::warn!(
+            "Admin {} started impersonating user {} until {}",
+            admin.email,
+            target.email,
+            expires_at
+        );
+
+        Ok(ImpersonationGrant {
+            token,
+            token_type: "Bearer".to_string(),
+            expires_at,
+            user_id: target.id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audit::AuditQuery;
+    use crate::test_utils::{seed_user, test_db};
+    use crate::utils::auth::verify_jwt;
+
+    fn impersonation(rate_limit: u32) -> Impersonation {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.impersonation_rate_limit_per_hour = rate_limit;
+        Impersonation::from_config(&config)
+    }
+
+    async fn seed_admin(db: &Database) -> User {
+        let admin = seed_user(db, "admin").await;
+        AuthService::new(db)
+            .create_auth(&admin.id, &admin.email, "admin-password")
+            .await
+            .unwrap();
+        admin
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_impersonation_token_and_audit_record() {
+        let db = test_db().await;
+        let admin = seed_admin(&db).await;
+        let target = seed_user(&db, "user").await;
+        let impersonation = impersonation(5);
+
+        let wrong = impersonation
+            .start(&db, "secret", &admin, &target.id, "guess")
+            .await;
+        assert!(matches!(wrong, Err(AppError::Unauthorized(_))));
+
+        let grant = impersonation
+            .start(&db, "secret", &admin, &target.id, "admin-password")
+            .await
+            .unwrap();
+        let claims = verify_jwt(&grant.token, "secret").unwrap();
+        assert_eq!(claims.sub, target.id);
+        assert_eq!(claims.impersonated_by, Some(admin.id.clone()));
+
+        let audit = AuditService::new(&db)
+            .list(&AuditQuery {
+                action: Some(IMPERSONATION_ACTION.to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(audit.items.len(), 1);
+        assert_eq!(audit.items[0].actor_id, admin.id);
+        assert_eq!(audit.items[0].target_id, target.id);
+        assert_eq!(
+            audit.items[0].data.as_ref().unwrap()["expires_at"],
+            grant.expires_at
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_impersonation_rate_limited_and_admins_protected() {
+        let db = test_db().await;
+        let admin = seed_admin(&db).await;
+        let other_admin = seed_user(&db, "admin").await;
+        let target = seed_user(&db, "user").await;
+        let impersonation = impersonation(2);
+
+        let refused = impersonation
+            .start(&db, "secret", &admin, &other_admin.id, "admin-password")
+            .await;
+        assert!(matches!(refused, Err(AppError::Forbidden(_))));
+
+        impersonation
+            .start(&db, "secret", &admin, &target.id, "admin-password")
+            .await
+            .unwrap();
+        let limited = impersonation
+            .start(&db, "secret", &admin, &target.id, "admin-password")
+            .await;
+        assert!(matches!(limited, Err(AppError::TooManyRequests(_))));
+    }
+}
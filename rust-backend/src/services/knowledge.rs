@@ -1,6 +1,8 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::models::knowledge::Knowledge;
+use crate::models::knowledge::{Knowledge, KnowledgeStats};
+use crate::services::file::FileService;
+use crate::services::rag_chunk::RagChunkService;
 use crate::utils::time::current_timestamp_seconds;
 
 #[allow(dead_code)]
@@ -129,6 +131,35 @@ impl<'a> KnowledgeService<'a> {
         Ok(knowledge)
     }
 
+    /// Knowledge bases whose `file_ids` include `file_id`
+    pub async fn get_knowledge_by_file_id(&self, file_id: &str) -> AppResult<Vec<Knowledge>> {
+        let mut knowledge = sqlx::query_as::<_, Knowledge>(
+            r#"
+            SELECT
+                id,
+                user_id,
+                name,
+                description,
+                CAST(data AS TEXT) as data_str,
+                CAST(meta AS TEXT) as meta_str,
+                CAST(access_control AS TEXT) as access_control_str,
+                created_at,
+                updated_at
+            FROM knowledge
+            WHERE data->'file_ids' ? $1
+            "#,
+        )
+        .bind(file_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        for k in &mut knowledge {
+            k.parse_json_fields();
+        }
+
+        Ok(knowledge)
+    }
+
     pub async fn create_knowledge_with_access_control(
         &self,
         id: &str,
@@ -293,7 +324,25 @@ impl<'a> KnowledgeService<'a> {
             .ok_or_else(|| AppError::NotFound("Knowledge not found".to_string()))
     }
 
-    pub async fn delete_knowledge(&self, id: &str) -> AppResult<()> {
+    /// Size of a knowledge base holding `file_ids`, leaving the vector count to the caller
+    pub async fn get_stats(&self, id: &str, file_ids: &[String]) -> AppResult<KnowledgeStats> {
+        let file_service = FileService::new(self.db);
+        let mut files = file_service.get_files_by_ids(file_ids).await?;
+        files.iter_mut().for_each(|file| file.parse_json_fields());
+
+        Ok(KnowledgeStats {
+            last_indexed_at: file_service.get_last_indexed_at(id).await?,
+            ..KnowledgeStats::from_files(&files)
+        })
+    }
+
+    /// Delete a knowledge base and its stored chunks, `batch_size` chunks at a time
+    ///
+    /// The knowledge row goes last, so a delete that fails partway can be retried.
+    pub async fn delete_knowledge(&self, id: &str, batch_size: usize) -> AppResult<()> {
+        RagChunkService::new(self.db)
+            .delete_collection_chunks(id, batch_size)
+            .await?;
         sqlx::query("DELETE FROM knowledge WHERE id = $1")
             .bind(id)
             .execute(&self.db.pool)
@@ -339,3 +388,98 @@ impl<'a> KnowledgeService<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::file::{File, IngestProgress};
+    use crate::test_utils::{seed_user, test_db};
+    use serde_json::json;
+
+    fn file(meta: Option<serde_json::Value>, content: Option<&str>) -> File {
+        File {
+            id: "f".to_string(),
+            user_id: "u".to_string(),
+            filename: "notes.txt".to_string(),
+            path: None,
+            data: content.map(|content| json!({ "content": content })),
+            data_str: None,
+            meta,
+            meta_str: None,
+            access_control: None,
+            access_control_str: None,
+            hash: None,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_stats_sum_sizes_and_tokens() {
+        let stats = KnowledgeStats::from_files(&[
+            file(Some(json!({ "size": 1000 })), Some(&"a".repeat(400))),
+            // Without an uploaded size the extracted text counts
+            file(None, Some("twelve bytes")),
+            file(Some(json!({ "size": 50 })), None),
+        ]);
+
+        assert_eq!(stats.file_count, 3);
+        assert_eq!(stats.total_bytes, 1062);
+        assert_eq!(stats.estimated_tokens, 103);
+        assert_eq!(stats.chunk_count, None);
+        assert_eq!(stats.last_indexed_at, None);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_stats_match_seeded_knowledge() {
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let file_service = FileService::new(&db);
+
+        for (id, size, content) in [("f1", 2048, "a".repeat(800)), ("f2", 512, "b".repeat(40))] {
+            file_service
+                .create_file(id, &owner.id, "doc.txt", id, Some(json!({ "size": size })))
+                .await
+                .unwrap();
+            file_service
+                .update_file_data(id, json!({ "content": content }))
+                .await
+                .unwrap();
+        }
+        let service = KnowledgeService::new(&db);
+        service
+            .create_knowledge(
+                "kb1",
+                &owner.id,
+                "Docs",
+                None,
+                Some(json!({ "file_ids": ["f1", "f2"] })),
+            )
+            .await
+            .unwrap();
+        file_service
+            .save_ingest_progress(&IngestProgress {
+                file_id: "f1".to_string(),
+                knowledge_id: "kb1".to_string(),
+                fingerprint: "x".to_string(),
+                chunks_total: 2,
+                chunks_done: 2,
+                status: IngestProgress::COMPLETED.to_string(),
+                error: None,
+                updated_at: 0,
+            })
+            .await
+            .unwrap();
+
+        let file_ids = vec!["f1".to_string(), "f2".to_string()];
+        let stats = service.get_stats("kb1", &file_ids).await.unwrap();
+
+        assert_eq!(stats.file_count, 2);
+        assert_eq!(stats.total_bytes, 2560);
+        assert_eq!(stats.estimated_tokens, 210);
+        assert!(stats.last_indexed_at.is_some());
+        let empty = service.get_stats("kb2", &[]).await.unwrap();
+        assert_eq!(empty, KnowledgeStats::default());
+    }
+}
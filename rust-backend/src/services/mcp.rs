@@ -45,7 +45,7 @@ impl McpClient {
     pub fn new(servers: Vec<McpServerConfig>) -> Self {
         Self {
             servers,
-            client: Client::new(),
+            client: crate::utils::http::client(),
         }
     }
 
@@ -0,0 +1,355 @@
+//! TOTP multi-factor sign-in
+//!
+//! A user starts setup to get a secret for their authenticator app; MFA is enabled once
+//! they confirm a code from it, and from then on signing in with a password also needs a
+//! code. Confirming hands out single-use recovery codes for when the app is lost. The
+//! secret is stored encrypted with MFA_ENCRYPTION_KEY and only hashes of the recovery
+//! codes are kept. REQUIRE_MFA_FOR_ADMINS makes admins set it up before doing anything
+//! else.
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::utils::fernet::MultiFernet;
+use crate::utils::time::current_timestamp_seconds;
+use crate::utils::totp;
+
+/// Recovery codes handed out when MFA is enabled
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Failed verifications are counted over this long
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Routes a token from a sign-in still owing MFA setup may reach
+pub const SETUP_ROUTES: &[&str] = &[
+    "/api/v1/auths",
+    "/api/v1/auths/",
+    "/api/v1/auths/mfa/setup",
+    "/api/v1/auths/mfa/verify",
+];
+
+#[derive(sqlx::FromRow)]
+struct MfaRow {
+    secret: String,
+    enabled: bool,
+    recovery_codes: String,
+    last_used_step: Option<i64>,
+}
+
+/// A secret to enroll in an authenticator app
+pub struct MfaSetup {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+pub struct MfaService<'a> {
+    db: &'a Database,
+    fernet: MultiFernet,
+}
+
+impl<'a> MfaService<'a> {
+    pub fn new(db: &'a Database, config: &Config) -> AppResult<Self> {
+        let fernet = MultiFernet::from_keys(&config.mfa_keys())?;
+        Ok(Self { db, fernet })
+    }
+
+    async fn get(&self, user_id: &str) -> AppResult<Option<MfaRow>> {
+        let row = sqlx::query_as::<_, MfaRow>(
+            "SELECT secret, enabled, recovery_codes, last_used_step FROM user_mfa WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        Ok(row)
+    }
+
+    pub async fn is_enabled(&self, user_id: &str) -> AppResult<bool> {
+        Ok(self.get(user_id).await?.is_some_and(|row| row.enabled))
+    }
+
+    /// Start (or restart) setup with a new secret; MFA stays off until it is confirmed
+    pub async fn begin_setup(
+        &self,
+        user_id: &str,
+        issuer: &str,
+        account: &str,
+    ) -> AppResult<MfaSetup> {
+        if self.is_enabled(user_id).await? {
+            return Err(AppError::Conflict("MFA is already enabled".to_string()));
+        }
+
+        let secret = totp::generate_secret();
+        let encrypted = self.fernet.encrypt(secret.as_bytes())?;
+        let now = current_timestamp_seconds();
+        sqlx::query(
+            r#"
+            INSERT INTO user_mfa (user_id, secret, enabled, recovery_codes, last_used_step, created_at, updated_at)
+            VALUES ($1, $2, FALSE, '[]', NULL, $3, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = NULL, updated_at = EXCLUDED.updated_at
+            WHERE user_mfa.enabled = FALSE
+            "#,
+        )
+        .bind(user_id)
+        .bind(&encrypted)
+        .bind(now)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(MfaSetup {
+            otpauth_url: totp::otpauth_url(issuer, account, &secret),
+            secret,
+        })
+    }
+
+    /// Enable MFA if `code` comes from the secret being set up
+    ///
+    /// Returns the recovery codes, which are not shown again, or `None` for a wrong code.
+    pub async fn enable(&self, user_id: &str, code: &str) -> AppResult<Option<Vec<String>>> {
+        let row = self
+            .get(user_id)
+            .await?
+            .ok_or_else(|| AppError::BadRequest("Start MFA setup first".to_string()))?;
+        if row.enabled {
+            return Err(AppError::Conflict("MFA is already enabled".to_string()));
+        }
+        let Some(step) = self.check_totp(&row, code)? else {
+            return Ok(None);
+        };
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let hashes: Vec<String> = codes.iter().map(|code| hash_recovery_code(code)).collect();
+        sqlx::query(
+            r#"
+            UPDATE user_mfa
+            SET enabled = TRUE, recovery_codes = $2, last_used_step = $3, updated_at = $4
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .bind(serde_json::to_string(&hashes).unwrap_or_default())
+        .bind(step)
+        .bind(current_timestamp_seconds())
+        .execute(&self.db.pool)
+        .await?;
+        Ok(Some(codes))
+    }
+
+    /// Check a sign-in's code: a current TOTP code or an unused recovery code, which is
+    /// used up
+    pub async fn verify(&self, user_id: &str, code: &str) -> AppResult<bool> {
+        let Some(row) = self.get(user_id).await?.filter(|row| row.enabled) else {
+            return Ok(false);
+        };
+
+        if let Some(step) = self.check_totp(&row, code)? {
+            // Conditional, so two sign-ins racing with one code can't both succeed
+            let result = sqlx::query(
+                r#"
+                UPDATE user_mfa SET last_used_step = $2, updated_at = $3
+                WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+                "#,
+            )
+            .bind(user_id)
+            .bind(step)
+            .bind(current_timestamp_seconds())
+            .execute(&self.db.pool)
+            .await?;
+            return Ok(result.rows_affected() == 1);
+        }
+
+        let hash = hash_recovery_code(code);
+        let mut hashes: Vec<String> = serde_json::from_str(&row.recovery_codes).unwrap_or_default();
+        let Some(index) = hashes.iter().position(|stored| *stored == hash) else {
+            return Ok(false);
+        };
+        hashes.remove(index);
+        let result = sqlx::query(
+            r#"
+            UPDATE user_mfa SET recovery_codes = $2, updated_at = $4
+            WHERE user_id = $1 AND recovery_codes = $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(serde_json::to_string(&hashes).unwrap_or_default())
+        .bind(&row.recovery_codes)
+        .bind(current_timestamp_seconds())
+        .execute(&self.db.pool)
+        .await?;
+        if result.rows_affected() == 1 {
+            tracing::info!(
+                "User {} signed in with a recovery code, {} left",
+                user_id,
+                hashes.len()
+            );
+        }
+        Ok(result.rows_affected() == 1)
+    }
+
+    fn check_totp(&self, row: &MfaRow, code: &str) -> AppResult<Option<i64>> {
+        let secret = String::from_utf8(self.fernet.decrypt(&row.secret)?)
+            .map_err(|_| AppError::InternalServerError("Corrupt MFA secret".to_string()))?;
+        Ok(totp::verify(
+            &secret,
+            code,
+            current_timestamp_seconds(),
+            row.last_used_step,
+        ))
+    }
+}
+
+/// Ten random base32 characters, as `xxxxx-xxxxx`
+fn generate_recovery_code() -> String {
+    let code = totp::generate_secret()[..10].to_lowercase();
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Recovery codes are compared ignoring case, spaces and dashes
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// Caps failed MFA codes per user, so six digits can't be guessed
+pub struct MfaAttempts {
+    max: u32,
+    failures: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl MfaAttempts {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max: config.mfa_max_attempts,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Refuse another attempt for `user_id` once it has used up its failures
+    pub fn check(&self, user_id: &str) -> AppResult<()> {
+        let failures = self.failures.lock().unwrap();
+        match failures.get(user_id) {
+            Some((count, since)) if *count >= self.max && since.elapsed() < ATTEMPT_WINDOW => {
+                Err(AppError::TooManyRequests(
+                    "Too many invalid MFA codes; try again later".to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, user_id: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, since)| since.elapsed() < ATTEMPT_WINDOW);
+        failures
+            .entry(user_id.to_string())
+            .or_insert((0, Instant::now()))
+            .0 += 1;
+    }
+
+    pub fn reset(&self, user_id: &str) {
+        self.failures.lock().unwrap().remove(user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_user, test_db};
+
+    fn config() -> Config {
+        Config::from_lookup(|key| match key {
+            "MFA_MAX_ATTEMPTS" => Some("3".to_string()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    /// The code for `offset` steps from now
+    fn code(secret: &str, offset: i64) -> String {
+        let secret = totp::base32_decode(secret).unwrap();
+        totp::code_at(
+            &secret,
+            totp::time_step(current_timestamp_seconds()) + offset,
+        )
+    }
+
+    #[test]
+    fn test_failed_codes_are_rate_limited() {
+        let attempts = MfaAttempts::from_config(&config());
+        for _ in 0..3 {
+            attempts.check("user-1").unwrap();
+            attempts.record_failure("user-1");
+        }
+        assert!(matches!(
+            attempts.check("user-1"),
+            Err(AppError::TooManyRequests(_))
+        ));
+        attempts.check("user-2").unwrap();
+
+        attempts.reset("user-1");
+        attempts.check("user-1").unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_setup_verify_and_recovery_codes() {
+        let db = test_db().await;
+        let user = seed_user(&db, "admin").await;
+        let config = config();
+        let service = MfaService::new(&db, &config).unwrap();
+
+        let setup = service
+            .begin_setup(&user.id, "Open WebUI", &user.email)
+            .await
+            .unwrap();
+        assert!(setup
+            .otpauth_url
+            .starts_with("otpauth://totp/Open%20WebUI:"));
+        assert!(!service.is_enabled(&user.id).await.unwrap());
+
+        // A wrong code doesn't enable it; the right one does
+        assert_eq!(service.enable(&user.id, "000000").await.unwrap(), None);
+        let codes = service
+            .enable(&user.id, &code(&setup.secret, 0))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(service.is_enabled(&user.id).await.unwrap());
+        assert!(matches!(
+            service
+                .begin_setup(&user.id, "Open WebUI", &user.email)
+                .await,
+            Err(AppError::Conflict(_))
+        ));
+
+        // The code that enabled MFA can't be replayed, and wrong codes fail
+        assert!(!service
+            .verify(&user.id, &code(&setup.secret, 0))
+            .await
+            .unwrap());
+        assert!(!service.verify(&user.id, "123456").await.unwrap());
+        assert!(service
+            .verify(&user.id, &code(&setup.secret, 1))
+            .await
+            .unwrap());
+
+        // Recovery codes work once, whatever their case
+        assert!(service
+            .verify(&user.id, &codes[0].to_uppercase())
+            .await
+            .unwrap());
+        assert!(!service.verify(&user.id, &codes[0]).await.unwrap());
+        assert!(service.verify(&user.id, &codes[1]).await.unwrap());
+    }
+}
@@ -1,17 +1,25 @@
+pub mod access_report;
+pub mod account_deletion;
 pub mod audio;
+pub mod audit;
 pub mod auth;
+pub mod avatar;
 pub mod channel;
 pub mod chat;
 pub mod config;
+pub mod data_export;
+pub mod external_jwt;
 pub mod feedback;
 pub mod file;
 pub mod folder;
 pub mod function;
 pub mod group;
 pub mod image;
+pub mod impersonation;
 pub mod knowledge;
 pub mod ldap;
 pub mod mcp;
+pub mod mfa;
 pub mod memory;
 pub mod message;
 pub mod model;
@@ -21,15 +29,24 @@ pub mod oauth;
 pub mod oauth_client;
 pub mod oauth_manager;
 pub mod oauth_provider;
+pub mod oauth_refresh;
 pub mod oauth_session;
+pub mod ownership_transfer;
 pub mod pipeline;
 pub mod prompt;
 pub mod rag;
+pub mod rag_chunk;
+pub mod retention;
 pub mod sandbox_executor;
+pub mod schema_check;
+pub mod session_store;
+pub mod signin_throttle;
 pub mod static_files;
 pub mod tool;
 pub mod tool_runtime;
+pub mod usage;
 pub mod user;
+pub mod vector_prune;
 
 pub use auth::*;
 pub use config::*;
@@ -1,8 +1,10 @@
+use crate::config::Config;
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::model::{Model, ModelForm};
 use crate::utils::time::current_timestamp_seconds;
 use sqlx::Row;
+use std::collections::HashSet;
 
 #[allow(dead_code)]
 pub struct ModelService<'a> {
@@ -15,40 +17,8 @@ impl<'a> ModelService<'a> {
         ModelService { db }
     }
 
-    pub async fn create_model(
-        &self,
-        id: &str,
-        user_id: &str,
-        base_model_id: Option<&str>,
-        name: &str,
-        params: serde_json::Value,
-        meta: serde_json::Value,
-    ) -> AppResult<Model> {
-        let now = current_timestamp_seconds();
-        let params_str = serde_json::to_string(&params).unwrap_or_else(|_| "{}".to_string());
-        let meta_str = serde_json::to_string(&meta).unwrap_or_else(|_| "{}".to_string());
-
-        sqlx::query(
-            r#"
-            INSERT INTO model (id, user_id, base_model_id, name, params, meta, created_at, updated_at, is_active)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-            "#,
-        )
-        .bind(id)
-        .bind(user_id)
-        .bind(base_model_id)
-        .bind(name)
-        .bind(&params_str)
-        .bind(&meta_str)
-        .bind(now)
-        .bind(now)
-        .bind(true)
-        .execute(&self.db.pool)
-        .await?;
-
-        self.get_model_by_id(id)
-            .await?
-            .ok_or_else(|| AppError::InternalServerError("Failed to create model".to_string()))
+    pub async fn create_model(&self, form: ModelForm, user_id: &str) -> AppResult<Model> {
+        self.insert_new_model(form, user_id).await
     }
 
     pub async fn get_model_by_id(&self, id: &str) -> AppResult<Option<Model>> {
@@ -81,6 +51,76 @@ impl<'a> ModelService<'a> {
         Ok(models)
     }
 
+    /// Remove the given knowledge bases from every model's `meta.knowledge`
+    ///
+    /// Models are paged through by id `batch_size` at a time and each page's updates are
+    /// committed together, so locks are held for one batch at most and a rerun after a
+    /// failure only touches models still referencing the knowledge. Returns the number
+    /// of models updated.
+    pub async fn remove_knowledge_references(
+        &self,
+        knowledge_ids: &HashSet<String>,
+        batch_size: usize,
+    ) -> AppResult<usize> {
+        let now = current_timestamp_seconds();
+        let mut after = String::new();
+        let mut batches = 0;
+        let mut updated = 0;
+        loop {
+            let models = sqlx::query_as::<_, Model>(
+                r#"
+                SELECT id, user_id, base_model_id, name, params, meta, access_control, created_at, updated_at, is_active
+                FROM model
+                WHERE base_model_id IS NOT NULL AND id > $1
+                ORDER BY id
+                LIMIT $2
+                "#,
+            )
+            .bind(&after)
+            .bind(batch_size as i64)
+            .fetch_all(&self.db.pool)
+            .await?;
+            let Some(last) = models.last() else {
+                break;
+            };
+            after = last.id.clone();
+            batches += 1;
+
+            let mut tx = self.db.pool.begin().await?;
+            let mut changed = 0;
+            for model in &models {
+                let Some(meta) = model
+                    .meta
+                    .as_ref()
+                    .and_then(|meta| without_knowledge(meta, knowledge_ids))
+                else {
+                    continue;
+                };
+                sqlx::query("UPDATE model SET meta = $1, updated_at = $2 WHERE id = $3")
+                    .bind(&meta)
+                    .bind(now)
+                    .bind(&model.id)
+                    .execute(&mut *tx)
+                    .await?;
+                changed += 1;
+            }
+            tx.commit().await?;
+            updated += changed;
+
+            tracing::info!(
+                "Knowledge cleanup batch {}: checked {} models, updated {}",
+                batches,
+                models.len(),
+                changed
+            );
+            if models.len() < batch_size {
+                break;
+            }
+        }
+
+        Ok(updated)
+    }
+
     pub async fn get_base_models(&self) -> AppResult<Vec<Model>> {
         let models = sqlx::query_as::<_, Model>(
             r#"
@@ -179,6 +219,15 @@ impl<'a> ModelService<'a> {
         Ok(())
     }
 
+    /// Every model row, including base model entries, as removed by [`Self::delete_all_models`]
+    pub async fn get_all_model_ids(&self) -> AppResult<Vec<String>> {
+        let ids = sqlx::query_scalar("SELECT id FROM model ORDER BY created_at DESC")
+            .fetch_all(&self.db.pool)
+            .await?;
+
+        Ok(ids)
+    }
+
     pub async fn delete_all_models(&self) -> AppResult<()> {
         sqlx::query("DELETE FROM model")
             .execute(&self.db.pool)
@@ -363,4 +412,225 @@ impl<'a> ModelService<'a> {
         self.delete_model(id).await?;
         Ok(true)
     }
+
+    /// Create the models in `forms` whose id is free, owned by `owner_id`
+    ///
+    /// A model is only created when what it builds on is in `available`: its base model,
+    /// or for one without a base model, the connection model it customizes. Existing
+    /// ids are left alone, edits included, so this is safe to run on every startup.
+    /// Returns the ids created.
+    pub async fn seed_models(
+        &self,
+        forms: &[ModelForm],
+        available: &HashSet<String>,
+        owner_id: &str,
+    ) -> AppResult<Vec<String>> {
+        let mut created = Vec::new();
+        for form in forms {
+            let base = form.base_model_id.as_deref().unwrap_or(&form.id);
+            if !available.contains(base) {
+                tracing::warn!(
+                    "Skipping default model {}: base model {} is not available",
+                    form.id,
+                    base
+                );
+                continue;
+            }
+
+            let now = current_timestamp_seconds();
+            let params = if form.params.is_null() {
+                serde_json::json!({})
+            } else {
+                form.params.clone()
+            };
+            let meta = if form.meta.is_null() {
+                serde_json::json!({})
+            } else {
+                form.meta.clone()
+            };
+            // Another replica may be seeding the same id at once
+            let result = sqlx::query(
+                r#"
+                INSERT INTO model (id, user_id, base_model_id, name, params, meta, access_control, created_at, updated_at, is_active)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, TRUE)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+            )
+            .bind(&form.id)
+            .bind(owner_id)
+            .bind(&form.base_model_id)
+            .bind(&form.name)
+            .bind(&params)
+            .bind(&meta)
+            .bind(&form.access_control)
+            .bind(now)
+            .execute(&self.db.pool)
+            .await?;
+            if result.rows_affected() == 1 {
+                created.push(form.id.clone());
+            }
+        }
+        Ok(created)
+    }
+}
+
+/// Create DEFAULT_MODEL_DEFINITIONS models that don't exist yet
+///
+/// They belong to the first user, the admin, so on a fresh instance without
+/// INITIAL_ADMIN_EMAIL they are created on the first startup after signup.
+pub async fn seed_default_models(db: &Database, config: &Config) -> AppResult<()> {
+    let Some(owner) = crate::services::UserService::new(db)
+        .get_first_user()
+        .await?
+    else {
+        tracing::info!("DEFAULT_MODEL_DEFINITIONS waits for an admin account before seeding");
+        return Ok(());
+    };
+
+    let available: HashSet<String> = crate::services::models::ModelService::new(config.clone())
+        .get_all_base_models(db)
+        .await?
+        .into_iter()
+        .map(|model| model.id)
+        .collect();
+    let created = ModelService::new(db)
+        .seed_models(&config.default_model_definitions, &available, &owner.id)
+        .await?;
+    if !created.is_empty() {
+        tracing::info!("Seeded default models: {}", created.join(", "));
+    }
+    Ok(())
+}
+
+/// `meta` without references to `knowledge_ids` in its `knowledge` list, or `None` when
+/// it has none
+fn without_knowledge(
+    meta: &serde_json::Value,
+    knowledge_ids: &HashSet<String>,
+) -> Option<serde_json::Value> {
+    let knowledge = meta.get("knowledge")?.as_array()?;
+    let kept: Vec<serde_json::Value> = knowledge
+        .iter()
+        .filter(|k| {
+            k.get("id")
+                .and_then(|id| id.as_str())
+                .is_none_or(|id| !knowledge_ids.contains(id))
+        })
+        .cloned()
+        .collect();
+    if kept.len() == knowledge.len() {
+        return None;
+    }
+    let mut meta = meta.clone();
+    meta["knowledge"] = serde_json::Value::Array(kept);
+    Some(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_user, test_db};
+
+    fn form(id: &str, base_model_id: Option<&str>) -> ModelForm {
+        ModelForm {
+            id: id.to_string(),
+            base_model_id: base_model_id.map(str::to_string),
+            name: id.to_string(),
+            params: serde_json::json!({"temperature": 0.2}),
+            meta: serde_json::Value::Null,
+            access_control: None,
+        }
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_seeded_models_are_not_duplicated() {
+        let db = test_db().await;
+        let admin = seed_user(&db, "admin").await;
+        let service = ModelService::new(&db);
+        let forms = [
+            form("support-bot", Some("gpt-4o")),
+            form("gpt-4o", None),
+            form("offline-bot", Some("missing-model")),
+        ];
+        let available = HashSet::from(["gpt-4o".to_string()]);
+
+        let created = service
+            .seed_models(&forms, &available, &admin.id)
+            .await
+            .unwrap();
+        assert_eq!(created, ["support-bot", "gpt-4o"]);
+        let model = service
+            .get_model_by_id("support-bot")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(model.user_id, admin.id);
+        assert_eq!(model.params["temperature"], 0.2);
+        assert!(service
+            .get_model_by_id("offline-bot")
+            .await
+            .unwrap()
+            .is_none());
+
+        // A restart doesn't create them again
+        let created = service
+            .seed_models(&forms, &available, &admin.id)
+            .await
+            .unwrap();
+        assert!(created.is_empty());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM model WHERE id = 'support-bot'")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_without_knowledge_drops_only_deleted_references() {
+        let ids = HashSet::from(["kb1".to_string()]);
+        let meta = serde_json::json!({
+            "description": "Support",
+            "knowledge": [{"id": "kb1"}, {"id": "kb2"}, {"name": "no id"}],
+        });
+
+        let pruned = without_knowledge(&meta, &ids).unwrap();
+        assert_eq!(
+            pruned["knowledge"],
+            serde_json::json!([{"id": "kb2"}, {"name": "no id"}])
+        );
+        assert_eq!(pruned["description"], "Support");
+        // Nothing to change
+        assert!(without_knowledge(&pruned, &ids).is_none());
+        assert!(without_knowledge(&serde_json::json!({}), &ids).is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_knowledge_references_removed_across_batches() {
+        let db = test_db().await;
+        let admin = seed_user(&db, "admin").await;
+        let service = ModelService::new(&db);
+        for i in 0..5 {
+            let mut form = form(&format!("kb-bot-{}", i), Some("gpt-4o"));
+            form.meta = serde_json::json!({"knowledge": [{"id": "kb1"}, {"id": "kb2"}]});
+            service.create_model(form, &admin.id).await.unwrap();
+        }
+
+        let ids = HashSet::from(["kb1".to_string()]);
+        assert_eq!(
+            service.remove_knowledge_references(&ids, 2).await.unwrap(),
+            5
+        );
+        let model = service.get_model_by_id("kb-bot-4").await.unwrap().unwrap();
+        assert_eq!(
+            model.meta.unwrap()["knowledge"],
+            serde_json::json!([{"id": "kb2"}])
+        );
+        // Rerunning finds nothing left to prune
+        assert_eq!(
+            service.remove_knowledge_references(&ids, 2).await.unwrap(),
+            0
+        );
+    }
 }
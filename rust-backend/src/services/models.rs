@@ -3,6 +3,10 @@ use crate::error::{AppError, AppResult};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +24,12 @@ pub struct Model {
     pub tags: Option<Vec<Tag>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arena: Option<bool>,
+    /// Standardized capabilities, filled in by `resolve_capabilities`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<ResolvedCapabilities>,
+    /// Context window in tokens, as reported upstream or filled in by `resolve_capabilities`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +60,66 @@ pub struct ModelCapabilities {
     pub vision: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub streaming: Option<bool>,
+}
+
+/// What a model supports, with every field decided, for clients to adapt their UI to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedCapabilities {
+    pub vision: bool,
+    pub tools: bool,
+    pub streaming: bool,
+}
+
+/// Context window assumed when neither the model, its upstream nor its family says
+pub const DEFAULT_CONTEXT_LENGTH: u64 = 4096;
+
+/// Defaults for a family of models, matched by a substring of the model id
+struct ModelFamily {
+    pattern: &'static str,
+    vision: bool,
+    tools: bool,
+    context_length: u64,
+}
+
+const fn family(
+    pattern: &'static str,
+    vision: bool,
+    tools: bool,
+    context_length: u64,
+) -> ModelFamily {
+    ModelFamily {
+        pattern,
+        vision,
+        tools,
+        context_length,
+    }
+}
+
+/// Known model families, more specific patterns first
+const MODEL_FAMILIES: &[ModelFamily] = &[
+    family("gpt-4o", true, true, 128_000),
+    family("gpt-4.1", true, true, 1_047_576),
+    family("gpt-4-turbo", true, true, 128_000),
+    family("gpt-4", false, true, 8_192),
+    family("gpt-3.5-turbo", false, true, 16_385),
+    family("claude", true, true, 200_000),
+    family("gemini", true, true, 1_048_576),
+    family("pixtral", true, true, 128_000),
+    family("mistral-large", false, true, 128_000),
+    family("llava", true, false, 4_096),
+    family("qwen2.5", false, true, 32_768),
+    family("deepseek", false, true, 65_536),
+];
+
+fn model_family(model_id: &str) -> Option<&'static ModelFamily> {
+    let id = model_id.to_lowercase();
+    MODEL_FAMILIES
+        .iter()
+        .find(|family| id.contains(family.pattern))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,29 +148,153 @@ pub struct Tag {
     pub name: String,
 }
 
+impl Model {
+    /// Fill in `capabilities` and `context_length`
+    ///
+    /// Admin-set `meta.capabilities` and `params` (`function_calling: "native"`,
+    /// `stream`, `num_ctx`/`context_length`) win, then what the upstream reported, then the
+    /// model family's defaults. Unknown models get the conservative defaults: no vision, no
+    /// tools, streaming, and a DEFAULT_CONTEXT_LENGTH window.
+    pub fn resolve_capabilities(&mut self) {
+        let meta = self
+            .info
+            .as_ref()
+            .and_then(|info| info.meta.as_ref())
+            .and_then(|meta| meta.capabilities.as_ref());
+        let params = self.info.as_ref().and_then(|info| info.params.as_ref());
+        let param = |key: &str| params.and_then(|params| params.get(key));
+        let family = model_family(&self.id);
+
+        let tools = meta.and_then(|meta| meta.tools).or_else(|| {
+            param("function_calling")
+                .and_then(Value::as_str)
+                .map(|mode| mode == "native")
+        });
+        self.capabilities = Some(ResolvedCapabilities {
+            vision: meta
+                .and_then(|meta| meta.vision)
+                .or(family.map(|family| family.vision))
+                .unwrap_or(false),
+            tools: tools.or(family.map(|family| family.tools)).unwrap_or(false),
+            streaming: meta
+                .and_then(|meta| meta.streaming)
+                .or_else(|| param("stream").and_then(Value::as_bool))
+                .unwrap_or(true),
+        });
+        self.context_length = param("num_ctx")
+            .or_else(|| param("context_length"))
+            .and_then(Value::as_u64)
+            .or(self.context_length)
+            .or(family.map(|family| family.context_length))
+            .or(Some(DEFAULT_CONTEXT_LENGTH));
+    }
+}
+
+/// Why a connection's models are missing from a list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamState {
+    /// Didn't answer within MODELS_FETCH_TIMEOUT
+    Timeout,
+    /// Answered with an error, or couldn't be reached
+    Error,
+}
+
+/// A connection left out of a model list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpstreamStatus {
+    #[serde(rename = "urlIdx")]
+    pub url_idx: usize,
+    pub status: UpstreamState,
+}
+
+/// Models gathered from the OpenAI connections
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamModels {
+    pub models: Vec<Model>,
+    /// Connections that timed out or failed
+    pub unavailable: Vec<UpstreamStatus>,
+}
+
+/// Last upstream model lists, shared across requests (ENABLE_BASE_MODELS_CACHE)
+///
+/// Requests arriving while one of them refreshes the list get the previous one rather
+/// than waiting on the same slow upstreams.
+#[derive(Default)]
+pub struct UpstreamModelsCache {
+    latest: RwLock<Option<UpstreamModels>>,
+    refreshing: AtomicBool,
+}
+
+/// Lets the next request refresh once the current refresh ends or is cancelled
+struct Refreshing<'a>(&'a AtomicBool);
+
+impl Drop for Refreshing<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+impl UpstreamModelsCache {
+    async fn get_or_refresh<F, Fut>(&self, refresh: F) -> UpstreamModels
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = UpstreamModels>,
+    {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            if let Some(latest) = self.latest.read().unwrap().clone() {
+                return latest;
+            }
+            // Nothing cached yet, so fetch alongside the refresh in progress
+            return refresh().await;
+        }
+
+        let _refreshing = Refreshing(&self.refreshing);
+        let fresh = refresh().await;
+        *self.latest.write().unwrap() = Some(fresh.clone());
+        fresh
+    }
+}
+
 pub struct ModelService {
     client: Client,
     config: Config,
+    cache: Option<Arc<UpstreamModelsCache>>,
 }
 
 impl ModelService {
     pub fn new(config: Config) -> Self {
         Self {
-            client: Client::new(),
+            client: crate::utils::http::client(),
             config,
+            cache: None,
         }
     }
 
+    /// Serve upstream model lists from `cache` while a refresh is in progress
+    pub fn with_cache(mut self, cache: Arc<UpstreamModelsCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
     /// Fetch all models from all configured backends
     pub async fn get_all_models(&self, db: &crate::db::Database) -> AppResult<Vec<Model>> {
+        Ok(self.get_all_models_with_status(db).await?.0)
+    }
+
+    /// Like [`Self::get_all_models`], also returning the connections that were left out
+    pub async fn get_all_models_with_status(
+        &self,
+        db: &crate::db::Database,
+    ) -> AppResult<(Vec<Model>, Vec<UpstreamStatus>)> {
         let mut all_models = Vec::new();
+        let mut unavailable = Vec::new();
 
         // Fetch OpenAI models
         if self.config.enable_openai_api {
-            match self.fetch_openai_models().await {
-                Ok(models) => all_models.extend(models),
-                Err(e) => warn!("Failed to fetch OpenAI models: {}", e),
-            }
+            let upstream = self.openai_models().await;
+            all_models.extend(upstream.models);
+            unavailable = upstream.unavailable;
         }
 
         // Fetch function/pipeline models
@@ -120,7 +314,7 @@ impl ModelService {
         // Attach global actions and filters
         all_models = self.attach_actions_and_filters(db, all_models).await?;
 
-        Ok(all_models)
+        Ok((all_models, unavailable))
     }
 
     /// Fetch base models (without applying filters or arena models)
@@ -129,10 +323,7 @@ impl ModelService {
 
         // Fetch OpenAI models
         if self.config.enable_openai_api {
-            match self.fetch_openai_models().await {
-                Ok(models) => base_models.extend(models),
-                Err(e) => warn!("Failed to fetch OpenAI models: {}", e),
-            }
+            base_models.extend(self.openai_models().await.models);
         }
 
         // Fetch function models
@@ -144,90 +335,137 @@ impl ModelService {
         Ok(base_models)
     }
 
-    /// Fetch models from OpenAI-compatible API endpoints
-    async fn fetch_openai_models(&self) -> AppResult<Vec<Model>> {
-        let mut all_models = Vec::new();
-
-        // Skip if no URLs are configured
-        if self.config.openai_api_base_urls.is_empty() {
-            return Ok(all_models);
+    /// OpenAI connections' models, from the cache while another request refreshes it
+    async fn openai_models(&self) -> UpstreamModels {
+        match &self.cache {
+            Some(cache) if self.config.enable_base_models_cache => {
+                cache.get_or_refresh(|| self.fetch_openai_models()).await
+            }
+            _ => self.fetch_openai_models().await,
         }
+    }
 
-        for (idx, base_url) in self.config.openai_api_base_urls.iter().enumerate() {
-            let api_key = self
-                .config
-                .openai_api_keys
-                .get(idx)
-                .cloned()
-                .unwrap_or_default();
-
-            let api_config = self
-                .config
-                .openai_api_configs
-                .get(&idx.to_string())
-                .or_else(|| self.config.openai_api_configs.get(base_url))
-                .and_then(|v| v.as_object())
-                .cloned();
-
-            // Check if this endpoint is explicitly disabled
-            let enabled = api_config
-                .as_ref()
-                .and_then(|cfg| cfg.get("enable"))
-                .and_then(|v| v.as_bool())
-                .unwrap_or(true);
-
-            if !enabled {
-                continue;
+    /// Fetch models from every OpenAI-compatible endpoint at once
+    ///
+    /// Each connection gets MODELS_FETCH_TIMEOUT to answer; ones that time out or fail
+    /// are reported as unavailable instead of holding up or failing the list.
+    async fn fetch_openai_models(&self) -> UpstreamModels {
+        let timeout = Duration::from_secs(self.config.models_fetch_timeout);
+        let fetches =
+            self.config
+                .openai_api_base_urls
+                .iter()
+                .enumerate()
+                .map(|(idx, base_url)| async move {
+                    let fetch = self.fetch_connection_models(idx, base_url);
+                    let result = match tokio::time::timeout(timeout, fetch).await {
+                        Ok(Ok(models)) => Ok(models),
+                        Ok(Err(e)) => {
+                            warn!("Failed to fetch models from {}: {}", base_url, e);
+                            Err(UpstreamState::Error)
+                        }
+                        Err(_) => {
+                            warn!(
+                                "Fetching models from {} timed out after {}s",
+                                base_url,
+                                timeout.as_secs()
+                            );
+                            Err(UpstreamState::Timeout)
+                        }
+                    };
+                    (idx, result)
+                });
+
+        let mut upstream = UpstreamModels::default();
+        for (idx, result) in futures::future::join_all(fetches).await {
+            match result {
+                Ok(models) => upstream.models.extend(models),
+                Err(status) => upstream.unavailable.push(UpstreamStatus {
+                    url_idx: idx,
+                    status,
+                }),
             }
+        }
+        upstream
+    }
 
-            // Skip if no API key is provided (unless it's explicitly configured as auth_type "none")
-            let auth_type = api_config
-                .as_ref()
-                .and_then(|cfg| cfg.get("auth_type"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("bearer");
+    /// Models of the connection at `idx`; none if it is disabled or has no key
+    async fn fetch_connection_models(&self, idx: usize, base_url: &str) -> AppResult<Vec<Model>> {
+        let api_key = self
+            .config
+            .openai_api_keys
+            .get(idx)
+            .cloned()
+            .unwrap_or_default();
 
-            if api_key.is_empty() && auth_type != "none" {
-                warn!(
-                    "Skipping OpenAI endpoint {} - no API key configured and auth_type is not 'none'",
-                    base_url
-                );
-                continue;
-            }
+        let api_config = self
+            .config
+            .openai_api_configs
+            .get(idx.to_string())
+            .or_else(|| self.config.openai_api_configs.get(base_url))
+            .and_then(|v| v.as_object())
+            .cloned();
+
+        // Check if this endpoint is explicitly disabled
+        let enabled = api_config
+            .as_ref()
+            .and_then(|cfg| cfg.get("enable"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
 
-            match self
-                .fetch_models_from_endpoint(base_url, &api_key, api_config.as_ref())
-                .await
-            {
-                Ok(mut models) => {
-                    // Add urlIdx to each model for backend routing
-                    for model in &mut models {
-                        if let Some(info) = &mut model.info {
-                            if let Some(_meta) = &mut info.meta {
-                                // Store the URL index for routing
-                            }
-                        } else {
-                            model.info = Some(ModelInfo {
-                                meta: Some(ModelMeta {
-                                    description: None,
-                                    capabilities: None,
-                                    tags: None,
-                                    knowledge: None,
-                                    profile_image_url: None,
-                                }),
-                                params: Some(json!({ "urlIdx": idx })),
-                            });
-                        }
-                    }
-                    all_models.extend(models);
-                }
-                Err(e) => {
-                    warn!("Failed to fetch models from {}: {}", base_url, e);
+        if !enabled {
+            return Ok(Vec::new());
+        }
+
+        // Skip if no API key is provided (unless it's explicitly configured as auth_type "none")
+        let auth_type = api_config
+            .as_ref()
+            .and_then(|cfg| cfg.get("auth_type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("bearer");
+
+        if api_key.is_empty() && auth_type != "none" {
+            warn!(
+                "Skipping OpenAI endpoint {} - no API key configured and auth_type is not 'none'",
+                base_url
+            );
+            return Ok(Vec::new());
+        }
+
+        let mut models = self
+            .fetch_models_from_endpoint(base_url, &api_key, api_config.as_ref())
+            .await?;
+
+        // Namespace ids with the connection's prefix_id, if configured
+        let prefix = self
+            .config
+            .openai_api_configs
+            .get(idx.to_string())
+            .or_else(|| self.config.openai_api_configs.get(base_url))
+            .and_then(crate::utils::models_cache::connection_prefix);
+
+        // Add urlIdx to each model for backend routing
+        for model in &mut models {
+            model.id = crate::utils::models_cache::prefixed_model_id(prefix, &model.id);
+            if let Some(info) = &mut model.info {
+                if let Some(_meta) = &mut info.meta {
+                    // Store the URL index for routing
                 }
+            } else {
+                model.info = Some(ModelInfo {
+                    meta: Some(ModelMeta {
+                        description: None,
+                        capabilities: None,
+                        tags: None,
+                        knowledge: None,
+                        profile_image_url: None,
+                    }),
+                    params: Some(json!({ "urlIdx": idx })),
+                });
             }
         }
 
-        Ok(all_models)
+        Ok(models)
     }
 
     /// Fetch models from a single OpenAI-compatible endpoint
@@ -278,7 +516,7 @@ impl ModelService {
             }
         }
 
-        let response = request.send().await?;
+        let response = crate::utils::telemetry::send(request, "openai.models").await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -320,6 +558,8 @@ impl ModelService {
                             pipeline: None,
                             tags: None,
                             arena: None,
+                            capabilities: None,
+                            context_length: v.get("context_length").and_then(Value::as_u64),
                         })
                     })
                     .collect()
@@ -379,6 +619,8 @@ impl ModelService {
                 }),
                 tags: None,
                 arena: None,
+                capabilities: None,
+                context_length: None,
             };
 
             pipe_models.push(model);
@@ -413,6 +655,9 @@ impl ModelService {
                         if let Some(ref mut meta) = info.meta {
                             // Merge tags from custom model
                             if let Some(custom_meta) = &custom.meta {
+                                if let Some(capabilities) = meta_capabilities(custom_meta) {
+                                    meta.capabilities = Some(capabilities);
+                                }
                                 if let Some(action_ids) = custom_meta.get("actionIds") {
                                     meta.tags = Some(vec![Tag {
                                         name: "custom".to_string(),
@@ -429,7 +674,7 @@ impl ModelService {
                                 description: m
                                     .get("description")
                                     .and_then(|d| d.as_str().map(|s| s.to_string())),
-                                capabilities: None,
+                                capabilities: meta_capabilities(m),
                                 tags: None,
                                 knowledge: None,
                                 profile_image_url: m
@@ -453,7 +698,7 @@ impl ModelService {
                             description: m
                                 .get("description")
                                 .and_then(|d| d.as_str().map(|s| s.to_string())),
-                            capabilities: None,
+                            capabilities: meta_capabilities(m),
                             tags: None,
                             knowledge: None,
                             profile_image_url: m
@@ -465,6 +710,8 @@ impl ModelService {
                     pipeline: None,
                     tags: None,
                     arena: None,
+                    capabilities: None,
+                    context_length: None,
                 });
             }
         }
@@ -512,6 +759,8 @@ impl ModelService {
                         pipeline: None,
                         tags: None,
                         arena: Some(true),
+                        capabilities: None,
+                        context_length: None,
                     })
                 })
                 .collect();
@@ -537,6 +786,8 @@ impl ModelService {
             pipeline: None,
             tags: None,
             arena: Some(true),
+            capabilities: None,
+            context_length: None,
         }]
     }
 
@@ -748,6 +999,11 @@ impl ModelService {
     }
 }
 
+/// `capabilities` of a custom model's stored meta
+fn meta_capabilities(meta: &Value) -> Option<ModelCapabilities> {
+    serde_json::from_value(meta.get("capabilities")?.clone()).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -769,4 +1025,169 @@ mod tests {
                 || !service.config.host.is_empty()
         );
     }
+
+    fn model(id: &str, meta: Option<ModelCapabilities>, params: Option<Value>) -> Model {
+        Model {
+            id: id.to_string(),
+            name: None,
+            object: "model".to_string(),
+            created: 0,
+            owned_by: "openai".to_string(),
+            info: Some(ModelInfo {
+                meta: Some(ModelMeta {
+                    description: None,
+                    capabilities: meta,
+                    tags: None,
+                    knowledge: None,
+                    profile_image_url: None,
+                }),
+                params,
+            }),
+            pipeline: None,
+            tags: None,
+            arena: None,
+            capabilities: None,
+            context_length: None,
+        }
+    }
+
+    #[test]
+    fn test_vision_model_flagged() {
+        let mut gpt4o = model("gpt-4o-mini", None, None);
+        gpt4o.resolve_capabilities();
+        assert_eq!(
+            gpt4o.capabilities,
+            Some(ResolvedCapabilities {
+                vision: true,
+                tools: true,
+                streaming: true
+            })
+        );
+        assert_eq!(gpt4o.context_length, Some(128_000));
+
+        // Admin-set meta and params win over the family
+        let mut custom = model(
+            "my-llava",
+            Some(ModelCapabilities {
+                vision: None,
+                usage: None,
+                tools: Some(true),
+                streaming: None,
+            }),
+            Some(json!({ "num_ctx": 8192 })),
+        );
+        custom.resolve_capabilities();
+        let capabilities = custom.capabilities.unwrap();
+        assert!(capabilities.vision && capabilities.tools);
+        assert_eq!(custom.context_length, Some(8192));
+    }
+
+    #[test]
+    fn test_unknown_model_gets_conservative_defaults() {
+        let mut unknown = model("acme-chat-7b", None, None);
+        unknown.resolve_capabilities();
+
+        assert_eq!(
+            unknown.capabilities,
+            Some(ResolvedCapabilities {
+                vision: false,
+                tools: false,
+                streaming: true
+            })
+        );
+        assert_eq!(unknown.context_length, Some(DEFAULT_CONTEXT_LENGTH));
+
+        // A window reported upstream is kept
+        let mut reported = model("acme-chat-7b", None, None);
+        reported.context_length = Some(32_000);
+        reported.resolve_capabilities();
+        assert_eq!(reported.context_length, Some(32_000));
+
+        let serialized = serde_json::to_value(&unknown).unwrap();
+        assert_eq!(serialized["capabilities"]["vision"], false);
+        assert_eq!(serialized["context_length"], DEFAULT_CONTEXT_LENGTH);
+    }
+
+    /// Accepts connections but never answers
+    async fn hanging_upstream() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Answers every request with a model list holding `id`
+    async fn listing_upstream(id: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let body = json!({ "data": [{ "id": id }] }).to_string();
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_upstream_does_not_hold_back_others() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.models_fetch_timeout = 1;
+        config.openai_api_base_urls = vec![
+            hanging_upstream().await,
+            listing_upstream("fast-model").await,
+        ];
+        config.openai_api_keys = vec!["sk-slow".to_string(), "sk-fast".to_string()];
+        config.openai_api_configs = json!({});
+
+        let service = ModelService::new(config);
+        let upstream = tokio::time::timeout(Duration::from_secs(5), service.openai_models())
+            .await
+            .expect("model list waited on the hanging upstream");
+
+        let ids: Vec<_> = upstream.models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["fast-model"]);
+        assert_eq!(
+            upstream.unavailable,
+            vec![UpstreamStatus {
+                url_idx: 0,
+                status: UpstreamState::Timeout,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_serves_latest_list_during_refresh() {
+        let cache = UpstreamModelsCache::default();
+        let first = cache
+            .get_or_refresh(|| async {
+                UpstreamModels {
+                    models: vec![model("cached", None, None)],
+                    unavailable: Vec::new(),
+                }
+            })
+            .await;
+        assert_eq!(first.models.len(), 1);
+
+        // While a refresh is running, other callers get the cached list
+        cache.refreshing.store(true, Ordering::Release);
+        let served = cache
+            .get_or_refresh(|| async { panic!("refreshed while a refresh was in progress") })
+            .await;
+        assert_eq!(served.models[0].id, "cached");
+    }
 }
@@ -75,6 +75,26 @@ impl<'a> NoteService<'a> {
         Ok(result)
     }
 
+    /// Notes the user owns, newest first
+    pub async fn get_notes_by_user_id(&self, user_id: &str) -> AppResult<Vec<Note>> {
+        let notes = sqlx::query_as::<_, Note>(
+            r#"
+            SELECT id, user_id, title, created_at, updated_at,
+                   CAST(data AS TEXT) as data_str,
+                   CAST(meta AS TEXT) as meta_str,
+                   CAST(access_control AS TEXT) as access_control_str
+            FROM note
+            WHERE user_id = $1
+            ORDER BY updated_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(notes)
+    }
+
     pub async fn get_notes_by_permission(
         &self,
         user_id: &str,
@@ -50,7 +50,7 @@ impl OAuthClient {
     pub fn new(config: OAuthConfig) -> Self {
         Self {
             config,
-            client: Client::new(),
+            client: crate::utils::http::client(),
         }
     }
 
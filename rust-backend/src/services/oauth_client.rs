@@ -42,7 +42,7 @@ impl OAuthClientManager {
         Self {
             clients: Arc::new(RwLock::new(HashMap::new())),
             tokens: Arc::new(RwLock::new(HashMap::new())),
-            client: Client::new(),
+            client: crate::utils::http::client(),
             db,
         }
     }
@@ -6,16 +6,52 @@ use super::oauth_provider::{
     OAuthTokenResponse, OAuthUserInfo, PKCEData,
 };
 use super::oauth_session::OAuthSessionService;
+use super::session_store::SessionStore;
 use crate::config::Config;
+use crate::db::Database;
 use crate::error::{AppError, AppResult};
-use crate::models::oauth_session::OAuthTokenData;
-use std::collections::HashMap;
+use crate::models::oauth_session::{OAuthSessionWithToken, OAuthTokenData};
+use crate::models::User;
+use crate::services::audit::AuditService;
+use crate::utils::time::current_timestamp_seconds;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
+/// Scope asking the provider for long-lived access, so re-authorizing can be offered
+const OFFLINE_ACCESS_SCOPE: &str = "offline_access";
+
+/// Audit action for admin granted from an OAuth roles claim
+pub const ADMIN_CLAIM_GRANTED_ACTION: &str = "oauth.admin_granted";
+/// Audit action for an admin claim held back by OAUTH_ADMIN_REQUIRES_APPROVAL
+pub const ADMIN_CLAIM_PENDING_ACTION: &str = "oauth.admin_pending_approval";
+
+/// Role for a new OAuth user, and the admin claim behind it if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleDecision {
+    pub role: String,
+    /// Configured admin role found in the user's roles claim
+    pub admin_claim: Option<String>,
+    /// Admin was claimed but not granted; an admin has to promote the user
+    pub awaiting_approval: bool,
+}
+
+impl RoleDecision {
+    fn plain(role: &str) -> Self {
+        RoleDecision {
+            role: role.to_string(),
+            admin_claim: None,
+            awaiting_approval: false,
+        }
+    }
+}
+
 /// OAuth state data stored temporarily during OAuth flow
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuthState {
     pub provider: String,
     pub pkce: Option<PKCEData>,
@@ -23,22 +59,47 @@ pub struct OAuthState {
     pub created_at: i64,
 }
 
+/// What happens to a session that can't be refreshed, per OAUTH_EXPIRED_SESSION_POLICY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiredSessionAction {
+    /// Not expired yet; the token stays usable until then
+    Keep,
+    /// Delete the session; the user has to sign in again
+    Delete,
+    /// Delete the session and tell the client to re-authorize with the provider
+    Reprompt,
+}
+
 /// OAuth Manager - coordinates all OAuth providers
 pub struct OAuthManager {
     providers: Arc<RwLock<HashMap<String, Arc<dyn OAuthProvider>>>>,
-    states: Arc<RwLock<HashMap<String, OAuthState>>>,
+    // Configured providers an admin switched off; hidden and refused at login
+    disabled: std::sync::RwLock<HashSet<String>>,
+    // Pending login states, shared across replicas via SESSION_STORE
+    states: Arc<dyn SessionStore>,
     session_service: Arc<OAuthSessionService>,
     config: Config,
+    // Shared pooled client handed to every provider
+    http_client: reqwest::Client,
 }
 
 impl OAuthManager {
     /// Create a new OAuth manager
-    pub async fn new(config: Config, session_service: Arc<OAuthSessionService>) -> AppResult<Self> {
+    pub async fn new(
+        config: Config,
+        session_service: Arc<OAuthSessionService>,
+        http_client: reqwest::Client,
+        states: Arc<dyn SessionStore>,
+    ) -> AppResult<Self> {
         let manager = Self {
             providers: Arc::new(RwLock::new(HashMap::new())),
-            states: Arc::new(RwLock::new(HashMap::new())),
+            disabled: std::sync::RwLock::new(
+                config.oauth_disabled_providers.iter().cloned().collect(),
+            ),
+            states,
             session_service,
             config: config.clone(),
+            http_client,
         };
 
         // Initialize providers
@@ -48,42 +109,42 @@ impl OAuthManager {
     }
 
     /// Initialize all configured OAuth providers
+    ///
+    /// Discovery for every provider runs concurrently, each bounded by
+    /// OIDC_DISCOVERY_TIMEOUT, so one slow IdP doesn't hold up the rest.
     async fn initialize_providers(&self, config: &Config) -> AppResult<()> {
+        let client = &self.http_client;
+        let (google, microsoft, github, oidc, feishu) = tokio::try_join!(
+            create_google_provider(config, client),
+            create_microsoft_provider(config, client),
+            create_github_provider(config, client),
+            create_oidc_provider(config, client),
+            create_feishu_provider(config, client),
+        )?;
+
         let mut providers = self.providers.write().await;
         let mut count = 0;
 
-        // Google
-        if let Some(provider) = create_google_provider(config).await? {
-            providers.insert("google".to_string(), Arc::new(provider));
-            count += 1;
-        }
-
-        // Microsoft
-        if let Some(provider) = create_microsoft_provider(config).await? {
-            providers.insert("microsoft".to_string(), Arc::new(provider));
-            count += 1;
-        }
-
-        // GitHub
-        if let Some(provider) = create_github_provider(config).await? {
-            providers.insert("github".to_string(), Arc::new(provider));
-            count += 1;
+        for (name, provider) in [
+            ("google", google),
+            ("microsoft", microsoft),
+            ("github", github),
+            ("feishu", feishu),
+        ] {
+            if let Some(provider) = provider {
+                providers.insert(name.to_string(), Arc::new(provider));
+                count += 1;
+            }
         }
 
         // Generic OIDC
-        if let Some(provider) = create_oidc_provider(config).await? {
+        if let Some(provider) = oidc {
             let name = provider.name().to_string();
             providers.insert(name.clone(), Arc::new(provider));
             info!("Generic OIDC provider registered as '{}'", name);
             count += 1;
         }
 
-        // Feishu
-        if let Some(provider) = create_feishu_provider(config).await? {
-            providers.insert("feishu".to_string(), Arc::new(provider));
-            count += 1;
-        }
-
         if count == 0 {
             warn!("No OAuth providers configured");
         } else {
@@ -93,10 +154,43 @@ impl OAuthManager {
         Ok(())
     }
 
-    /// Get list of available provider names
+    /// Get list of available provider names, leaving out disabled ones
     pub async fn get_available_providers(&self) -> Vec<String> {
         let providers = self.providers.read().await;
-        providers.keys().cloned().collect()
+        let disabled = self.disabled.read().unwrap();
+        let mut names: Vec<String> = providers
+            .keys()
+            .filter(|name| !disabled.contains(*name))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Enable or disable a configured provider; returns the full disabled list to persist
+    pub async fn set_provider_enabled(&self, name: &str, enabled: bool) -> AppResult<Vec<String>> {
+        if !self.providers.read().await.contains_key(name) {
+            return Err(AppError::NotFound(format!(
+                "OAuth provider '{}' is not configured",
+                name
+            )));
+        }
+
+        let mut disabled = self.disabled.write().unwrap();
+        if enabled {
+            disabled.remove(name);
+        } else {
+            disabled.insert(name.to_string());
+        }
+
+        let mut names: Vec<String> = disabled.iter().cloned().collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Replace the disabled set, e.g. after the config was reloaded or imported
+    pub fn set_disabled_providers(&self, names: &[String]) {
+        *self.disabled.write().unwrap() = names.iter().cloned().collect();
     }
 
     /// Get a specific provider
@@ -108,10 +202,10 @@ impl OAuthManager {
             .ok_or_else(|| AppError::NotFound(format!("OAuth provider '{}' not found", name)))
     }
 
-    /// Check if a provider is configured
+    /// Check if a provider is configured and not disabled
     pub async fn is_provider_configured(&self, name: &str) -> bool {
         let providers = self.providers.read().await;
-        providers.contains_key(name)
+        providers.contains_key(name) && !self.disabled.read().unwrap().contains(name)
     }
 
     /// Generate state parameter
@@ -123,24 +217,50 @@ impl OAuthManager {
         base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(random_bytes)
     }
 
-    /// Store OAuth state
-    async fn store_state(&self, state_id: String, state_data: OAuthState) {
-        let mut states = self.states.write().await;
-        states.insert(state_id, state_data);
-
-        // Clean up old states (older than 10 minutes)
-        let current_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+    fn state_key(state_id: &str) -> String {
+        format!("oauth_state:{}", state_id)
+    }
 
-        states.retain(|_, state| current_time - state.created_at < 600);
+    /// Store OAuth state; the store drops it after OAUTH_STATE_MAX_AGE
+    async fn store_state(&self, state_id: &str, state_data: &OAuthState) -> AppResult<()> {
+        let value = serde_json::to_string(state_data)
+            .map_err(|e| AppError::Internal(format!("Failed to serialize OAuth state: {}", e)))?;
+        self.states
+            .put(
+                &Self::state_key(state_id),
+                &value,
+                Duration::from_secs(self.config.oauth_state_max_age),
+            )
+            .await
     }
 
-    /// Retrieve and remove OAuth state
-    async fn retrieve_state(&self, state_id: &str) -> Option<OAuthState> {
-        let mut states = self.states.write().await;
-        states.remove(state_id)
+    /// Retrieve and remove OAuth state, so each state is accepted once
+    ///
+    /// States older than OAUTH_STATE_MAX_AGE are refused even if the store still has
+    /// them, whenever expired entries were last swept.
+    async fn retrieve_state(&self, state_id: &str) -> AppResult<Option<OAuthState>> {
+        let Some(value) = self.states.take(&Self::state_key(state_id)).await? else {
+            return Ok(None);
+        };
+        let state: OAuthState = match serde_json::from_str(&value) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Discarding unreadable OAuth state: {}", e);
+                return Ok(None);
+            }
+        };
+
+        let age = current_timestamp_seconds() - state.created_at;
+        if age > self.config.oauth_state_max_age as i64 {
+            warn!(
+                "Rejecting OAuth state for {} created {}s ago (OAUTH_STATE_MAX_AGE is {}s)",
+                state.provider, age, self.config.oauth_state_max_age
+            );
+            return Err(AppError::Auth(
+                "OAuth login took too long and expired, please sign in again".to_string(),
+            ));
+        }
+        Ok(Some(state))
     }
 
     /// Initiate OAuth login flow
@@ -168,15 +288,15 @@ impl OAuthManager {
             .as_secs() as i64;
 
         self.store_state(
-            state_id.clone(),
-            OAuthState {
+            &state_id,
+            &OAuthState {
                 provider: provider_name.to_string(),
                 pkce: pkce.clone(),
                 redirect_path,
                 created_at: current_time,
             },
         )
-        .await;
+        .await?;
 
         // Generate authorization URL
         let auth_url = provider
@@ -188,15 +308,17 @@ impl OAuthManager {
     }
 
     /// Handle OAuth callback
+    ///
+    /// Returns the provider, tokens, user info and the redirect stored at login.
     pub async fn handle_callback(
         &self,
         code: &str,
         state_id: &str,
-    ) -> AppResult<(String, OAuthTokenResponse, OAuthUserInfo)> {
+    ) -> AppResult<(String, OAuthTokenResponse, OAuthUserInfo, Option<String>)> {
         // Retrieve state
         let state = self
             .retrieve_state(state_id)
-            .await
+            .await?
             .ok_or_else(|| AppError::Auth("Invalid or expired OAuth state".to_string()))?;
 
         debug!("Handling callback for provider: {}", state.provider);
@@ -216,7 +338,12 @@ impl OAuthManager {
             state.provider, user_info.sub
         );
 
-        Ok((state.provider, token_response, user_info))
+        Ok((
+            state.provider,
+            token_response,
+            user_info,
+            state.redirect_path,
+        ))
     }
 
     /// Create OAuth session from token response
@@ -238,6 +365,19 @@ impl OAuthManager {
             current_time + 3600 // Default 1 hour
         };
 
+        let refreshable = token_response.refresh_token.is_some()
+            && !self
+                .config
+                .oauth_non_refreshable_providers
+                .iter()
+                .any(|name| name == provider);
+        if !refreshable {
+            debug!(
+                "OAuth session for user {} with provider {} is not refreshable",
+                user_id, provider
+            );
+        }
+
         let token_data = OAuthTokenData {
             access_token: token_response.access_token,
             token_type: token_response.token_type,
@@ -247,6 +387,7 @@ impl OAuthManager {
             expires_at,
             issued_at: current_time,
             scope: token_response.scope,
+            refreshable,
         };
 
         // Delete existing session for this provider/user
@@ -294,63 +435,40 @@ impl OAuthManager {
             user_id, provider_name
         );
 
-        // Check if we have a refresh token
-        let refresh_token = match &session.token.refresh_token {
-            Some(rt) => rt.clone(),
-            None => {
-                warn!(
-                    "No refresh token available for user {} provider {}",
-                    user_id, provider_name
-                );
-                return Ok(Some(session.token));
-            }
-        };
-
-        // Get provider
-        let provider = self.get_provider(provider_name).await?;
-
-        // Refresh token
-        match provider.refresh_token(&refresh_token).await {
-            Ok(new_token) => {
-                let current_time = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as i64;
-
-                let expires_at = if let Some(expires_in) = new_token.expires_in {
-                    current_time + expires_in
-                } else {
-                    current_time + 3600
-                };
-
-                let mut token_data = OAuthTokenData {
-                    access_token: new_token.access_token,
-                    token_type: new_token.token_type,
-                    refresh_token: new_token.refresh_token,
-                    id_token: new_token.id_token,
-                    expires_in: new_token.expires_in,
-                    expires_at,
-                    issued_at: current_time,
-                    scope: new_token.scope,
-                };
-
-                // Preserve old refresh token if not provided
-                if token_data.refresh_token.is_none() {
-                    token_data.refresh_token = Some(refresh_token);
+        if !session.token.can_refresh() {
+            let requested_scopes = match self.get_provider(provider_name).await {
+                Ok(provider) => provider.config().scopes.clone(),
+                Err(_) => Vec::new(),
+            };
+            return match self.expired_session_action(&session, &requested_scopes) {
+                ExpiredSessionAction::Keep => Ok(Some(session.token)),
+                ExpiredSessionAction::Delete => {
+                    info!(
+                        "OAuth session for user {} provider {} expired and can't be refreshed",
+                        user_id, provider_name
+                    );
+                    self.session_service
+                        .delete_session_by_id(&session.id)
+                        .await?;
+                    Ok(None)
                 }
+                ExpiredSessionAction::Reprompt => {
+                    self.session_service
+                        .delete_session_by_id(&session.id)
+                        .await?;
+                    Err(AppError::Unauthorized(format!(
+                        "OAuth session for {} expired; re-authorize at /oauth/{}/login",
+                        provider_name, provider_name
+                    )))
+                }
+            };
+        }
 
-                // Update session
-                self.session_service
-                    .update_session_by_id(&session.id, token_data.clone())
-                    .await?;
-
-                info!(
-                    "Refreshed token for user {} provider {}",
-                    user_id, provider_name
-                );
+        // An unknown provider is a configuration problem, not a reason to drop the session
+        self.get_provider(provider_name).await?;
 
-                Ok(Some(token_data))
-            }
+        match self.refresh_session(&session).await {
+            Ok(token_data) => Ok(Some(token_data)),
             Err(e) => {
                 error!(
                     "Failed to refresh token for user {} provider {}: {}",
@@ -365,6 +483,102 @@ impl OAuthManager {
         }
     }
 
+    /// How to handle `session`, which can't be refreshed
+    ///
+    /// Reprompting applies only when offline access was requested, either in the
+    /// provider's configured scopes or in the scope the token was granted.
+    pub fn expired_session_action(
+        &self,
+        session: &OAuthSessionWithToken,
+        requested_scopes: &[String],
+    ) -> ExpiredSessionAction {
+        if !self.session_service.is_session_expired(session) {
+            return ExpiredSessionAction::Keep;
+        }
+
+        let offline_access =
+            requested_scopes
+                .iter()
+                .any(|scope| scope == OFFLINE_ACCESS_SCOPE)
+                || session.token.scope.as_deref().is_some_and(|scope| {
+                    scope.split_whitespace().any(|s| s == OFFLINE_ACCESS_SCOPE)
+                });
+        if self.config.oauth_expired_session_policy == "reprompt" && offline_access {
+            ExpiredSessionAction::Reprompt
+        } else {
+            ExpiredSessionAction::Delete
+        }
+    }
+
+    /// Exchange a session's refresh token for a new token and store it
+    ///
+    /// Unlike [`Self::refresh_if_needed`], a failed refresh is returned as an error and
+    /// the session is kept, so the caller can retry later.
+    pub async fn refresh_session(
+        &self,
+        session: &OAuthSessionWithToken,
+    ) -> AppResult<OAuthTokenData> {
+        let refresh_token = session.token.refresh_token.clone().ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "No refresh token available for provider {}",
+                session.provider
+            ))
+        })?;
+
+        let provider = self.get_provider(&session.provider).await?;
+        let new_token = provider.refresh_token(&refresh_token).await?;
+
+        let current_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let expires_at = if let Some(expires_in) = new_token.expires_in {
+            current_time + expires_in
+        } else {
+            current_time + 3600
+        };
+
+        let mut token_data = OAuthTokenData {
+            access_token: new_token.access_token,
+            token_type: new_token.token_type,
+            refresh_token: new_token.refresh_token,
+            id_token: new_token.id_token,
+            expires_in: new_token.expires_in,
+            expires_at,
+            issued_at: current_time,
+            scope: new_token.scope,
+            refreshable: true,
+        };
+
+        // Preserve old refresh token if not provided
+        if token_data.refresh_token.is_none() {
+            token_data.refresh_token = Some(refresh_token);
+        }
+
+        // Update session
+        self.session_service
+            .update_session_by_id(&session.id, token_data.clone())
+            .await?;
+
+        info!(
+            "Refreshed token for user {} provider {}",
+            session.user_id, session.provider
+        );
+
+        Ok(token_data)
+    }
+
+    /// Sessions that expire within `minutes`, for proactive refresh
+    pub async fn sessions_expiring_soon(
+        &self,
+        minutes: i64,
+    ) -> AppResult<Vec<OAuthSessionWithToken>> {
+        self.session_service
+            .get_sessions_expiring_soon(minutes)
+            .await
+    }
+
     /// Get valid access token (with automatic refresh)
     pub async fn get_access_token(
         &self,
@@ -423,16 +637,47 @@ impl OAuthManager {
         }
     }
 
+    /// Groups from user info to sync: deduplicated, without `blocked`, at most `max`
+    /// (0 = unlimited)
+    pub fn groups_to_sync(
+        &self,
+        user_info: &OAuthUserInfo,
+        blocked: &[String],
+        max: usize,
+    ) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut groups: Vec<String> = self
+            .extract_groups(user_info)
+            .into_iter()
+            .filter(|g| !blocked.contains(g) && seen.insert(g.clone()))
+            .collect();
+        if max > 0 && groups.len() > max {
+            warn!(
+                "OAuth user {} has {} groups in claim '{}'; syncing only the first {} (OAUTH_MAX_SYNCED_GROUPS)",
+                user_info.sub,
+                groups.len(),
+                self.config.oauth_groups_claim,
+                max
+            );
+            groups.truncate(max);
+        }
+        groups
+    }
+
     /// Determine user role from OAuth claims
-    pub fn determine_user_role(&self, user_info: &OAuthUserInfo, is_first_user: bool) -> String {
+    pub fn determine_user_role(
+        &self,
+        user_info: &OAuthUserInfo,
+        is_first_user: bool,
+    ) -> RoleDecision {
         // First user is always admin
         if is_first_user {
-            return "admin".to_string();
+            return RoleDecision::plain("admin");
         }
 
         // If role management is disabled, use default role
         if !self.config.enable_oauth_role_management {
-            return self.config.default_user_role.clone();
+            return RoleDecision::plain(&self.config.default_user_role);
         }
 
         let roles = self.extract_roles(user_info);
@@ -440,19 +685,71 @@ impl OAuthManager {
         // Check for admin roles
         for admin_role in &self.config.oauth_admin_roles {
             if roles.contains(admin_role) {
-                return "admin".to_string();
+                let awaiting_approval = self.config.oauth_admin_requires_approval;
+                return RoleDecision {
+                    role: if awaiting_approval { "user" } else { "admin" }.to_string(),
+                    admin_claim: Some(admin_role.clone()),
+                    awaiting_approval,
+                };
             }
         }
 
         // Check for allowed roles
         for allowed_role in &self.config.oauth_allowed_roles {
             if roles.contains(allowed_role) {
-                return "user".to_string();
+                return RoleDecision::plain("user");
             }
         }
 
         // Default role
-        self.config.default_user_role.clone()
+        RoleDecision::plain(&self.config.default_user_role)
+    }
+
+    /// Audit a role decision that was made on an admin claim
+    ///
+    /// Claim-based admin grants are otherwise silent, so a misconfigured claim map could
+    /// hand out admin without anyone noticing.
+    pub async fn record_role_decision(
+        &self,
+        db: &Database,
+        user: &User,
+        provider: &str,
+        decision: &RoleDecision,
+    ) -> AppResult<()> {
+        let Some(matched_role) = &decision.admin_claim else {
+            return Ok(());
+        };
+        let action = if decision.awaiting_approval {
+            ADMIN_CLAIM_PENDING_ACTION
+        } else {
+            ADMIN_CLAIM_GRANTED_ACTION
+        };
+        AuditService::new(db)
+            .record(
+                &user.id,
+                action,
+                "user",
+                &user.id,
+                Some(json!({
+                    "provider": provider,
+                    "claim": self.config.oauth_roles_claim,
+                    "matched_role": matched_role,
+                    "role": decision.role,
+                })),
+            )
+            .await?;
+        if decision.awaiting_approval {
+            warn!(
+                "OAuth user {} claims admin via {} role '{}'; created as {} pending admin review",
+                user.id, provider, matched_role, decision.role
+            );
+        } else {
+            warn!(
+                "OAuth user {} granted admin via {} role '{}'",
+                user.id, provider, matched_role
+            );
+        }
+        Ok(())
     }
 
     /// Validate email domain
@@ -474,9 +771,109 @@ impl OAuthManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::session_store::DbSessionStore;
+
+    fn test_manager(config: Config, db: Database) -> OAuthManager {
+        let session_service = OAuthSessionService::new(db, &config.oauth_session_keys()).unwrap();
+        OAuthManager {
+            providers: Arc::new(RwLock::new(HashMap::new())),
+            disabled: std::sync::RwLock::new(HashSet::new()),
+            states: Arc::new(DbSessionStore::new(Database::new_lazy_for_tests())),
+            session_service: Arc::new(session_service),
+            config,
+            http_client: reqwest::Client::new(),
+        }
+    }
+
+    fn github_session(expires_at: i64) -> OAuthSessionWithToken {
+        OAuthSessionWithToken {
+            id: "session".to_string(),
+            user_id: "user".to_string(),
+            provider: "github".to_string(),
+            token: OAuthTokenData {
+                access_token: "access".to_string(),
+                token_type: "Bearer".to_string(),
+                refresh_token: None,
+                id_token: None,
+                expires_in: None,
+                expires_at,
+                issued_at: 0,
+                scope: None,
+                refreshable: false,
+            },
+            expires_at,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    /// Session store that never expires anything, like one that hasn't been swept
+    #[derive(Default)]
+    struct UnsweptStore {
+        values: std::sync::Mutex<HashMap<String, String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl SessionStore for UnsweptStore {
+        async fn put(&self, key: &str, value: &str, _ttl: Duration) -> AppResult<()> {
+            self.values
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+
+        async fn get(&self, key: &str) -> AppResult<Option<String>> {
+            Ok(self.values.lock().unwrap().get(key).cloned())
+        }
+
+        async fn take(&self, key: &str) -> AppResult<Option<String>> {
+            Ok(self.values.lock().unwrap().remove(key))
+        }
+
+        async fn delete(&self, key: &str) -> AppResult<()> {
+            self.values.lock().unwrap().remove(key);
+            Ok(())
+        }
 
-    #[test]
-    fn test_extract_nested_claim() {
+        fn backend(&self) -> &'static str {
+            "memory"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_state_older_than_max_age_is_rejected() {
+        let mut manager = test_manager(
+            Config::from_lookup(|key| match key {
+                "OAUTH_STATE_MAX_AGE" => Some("300".to_string()),
+                _ => None,
+            })
+            .unwrap(),
+            Database::new_lazy_for_tests(),
+        );
+        manager.states = Arc::new(UnsweptStore::default());
+        let state = |age: i64| OAuthState {
+            provider: "github".to_string(),
+            pkce: None,
+            redirect_path: None,
+            created_at: current_timestamp_seconds() - age,
+        };
+
+        manager.store_state("fresh", &state(60)).await.unwrap();
+        let fresh = manager.retrieve_state("fresh").await.unwrap();
+        assert_eq!(fresh.unwrap().provider, "github");
+
+        manager.store_state("stale", &state(301)).await.unwrap();
+        assert!(matches!(
+            manager.retrieve_state("stale").await,
+            Err(AppError::Auth(_))
+        ));
+        // Consumed either way
+        assert!(manager.retrieve_state("stale").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extract_nested_claim() {
         let user_info = OAuthUserInfo {
             sub: "123".to_string(),
             email: Some("test@example.com".to_string()),
@@ -496,16 +893,239 @@ mod tests {
             },
         };
 
-        let config = Config::from_env().unwrap();
-        let manager = OAuthManager {
-            providers: Arc::new(RwLock::new(HashMap::new())),
-            states: Arc::new(RwLock::new(HashMap::new())),
-            session_service: Arc::new(todo!()),
-            config,
-        };
+        let manager = test_manager(
+            Config::from_lookup(|_| None).unwrap(),
+            Database::new_lazy_for_tests(),
+        );
 
         let claim = manager.extract_claim(&user_info, "realm_access.roles");
         assert!(claim.is_some());
         assert_eq!(claim.unwrap(), serde_json::json!(["user", "admin"]));
     }
+
+    #[tokio::test]
+    async fn test_disabled_provider_hidden_and_refused() {
+        let config = Config::from_lookup(|key| match key {
+            "GITHUB_CLIENT_ID" => Some("client".to_string()),
+            "GITHUB_CLIENT_SECRET" => Some("secret".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let session_service =
+            OAuthSessionService::new(Database::new_lazy_for_tests(), &config.oauth_session_keys())
+                .unwrap();
+        let manager = OAuthManager::new(
+            config,
+            Arc::new(session_service),
+            reqwest::Client::new(),
+            Arc::new(DbSessionStore::new(Database::new_lazy_for_tests())),
+        )
+        .await
+        .unwrap();
+        assert!(manager.is_provider_configured("github").await);
+
+        let disabled = manager.set_provider_enabled("github", false).await.unwrap();
+        assert_eq!(disabled, vec!["github".to_string()]);
+        assert!(!manager.is_provider_configured("github").await);
+        assert!(manager.get_available_providers().await.is_empty());
+
+        manager.set_provider_enabled("github", true).await.unwrap();
+        assert_eq!(manager.get_available_providers().await, vec!["github"]);
+
+        assert!(matches!(
+            manager.set_provider_enabled("gitlab", false).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_non_refreshable_session_follows_policy() {
+        let delete = test_manager(
+            Config::from_lookup(|_| None).unwrap(),
+            Database::new_lazy_for_tests(),
+        );
+        let reprompt = test_manager(
+            Config::from_lookup(|key| match key {
+                "OAUTH_EXPIRED_SESSION_POLICY" => Some("reprompt".to_string()),
+                _ => None,
+            })
+            .unwrap(),
+            Database::new_lazy_for_tests(),
+        );
+        let offline = vec!["openid".to_string(), "offline_access".to_string()];
+
+        let valid = github_session(i64::MAX);
+        assert_eq!(
+            delete.expired_session_action(&valid, &offline),
+            ExpiredSessionAction::Keep
+        );
+
+        let expired = github_session(1);
+        assert_eq!(
+            delete.expired_session_action(&expired, &offline),
+            ExpiredSessionAction::Delete
+        );
+        assert_eq!(
+            reprompt.expired_session_action(&expired, &offline),
+            ExpiredSessionAction::Reprompt
+        );
+        // Without offline access there's nothing to re-prompt for
+        assert_eq!(
+            reprompt.expired_session_action(&expired, &["read:user".to_string()]),
+            ExpiredSessionAction::Delete
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_expired_github_session_deleted_on_use() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let manager = test_manager(Config::from_lookup(|_| None).unwrap(), db);
+
+        let token = OAuthTokenResponse {
+            access_token: "gho_access".to_string(),
+            token_type: "bearer".to_string(),
+            expires_in: Some(-60),
+            refresh_token: None,
+            id_token: None,
+            scope: Some("read:user user:email".to_string()),
+        };
+        manager
+            .create_session(&user.id, "github", token)
+            .await
+            .unwrap();
+
+        let sessions = manager
+            .session_service
+            .get_sessions_response_by_user_id(&user.id)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(!sessions[0].refreshable);
+
+        assert!(manager
+            .refresh_if_needed(&user.id, "github")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(manager
+            .session_service
+            .get_sessions_response_by_user_id(&user.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    fn claiming_roles(roles: serde_json::Value) -> OAuthUserInfo {
+        OAuthUserInfo {
+            sub: "123".to_string(),
+            email: Some("test@example.com".to_string()),
+            email_verified: Some(true),
+            name: None,
+            given_name: None,
+            family_name: None,
+            picture: None,
+            locale: None,
+            extra: HashMap::from([("roles".to_string(), roles)]),
+        }
+    }
+
+    fn role_managing(approval: bool) -> Config {
+        Config::from_lookup(|key| match key {
+            "ENABLE_OAUTH_ROLE_MANAGEMENT" => Some("true".to_string()),
+            "OAUTH_ADMIN_REQUIRES_APPROVAL" => Some(approval.to_string()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_claim_held_for_approval() {
+        let admin = claiming_roles(serde_json::json!(["user", "admin"]));
+        let granting = test_manager(role_managing(false), Database::new_lazy_for_tests());
+        let decision = granting.determine_user_role(&admin, false);
+        assert_eq!(decision.role, "admin");
+        assert_eq!(decision.admin_claim.as_deref(), Some("admin"));
+        assert!(!decision.awaiting_approval);
+
+        let reviewing = test_manager(role_managing(true), Database::new_lazy_for_tests());
+        let decision = reviewing.determine_user_role(&admin, false);
+        assert_eq!(decision.role, "user");
+        assert!(decision.awaiting_approval);
+
+        // Neither the first user nor plain users are claim grants
+        assert_eq!(reviewing.determine_user_role(&admin, true).role, "admin");
+        let user = claiming_roles(serde_json::json!("user"));
+        assert_eq!(
+            reviewing.determine_user_role(&user, false),
+            RoleDecision::plain("user")
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_claim_admin_grant_is_audited() {
+        use crate::models::audit::AuditQuery;
+        use crate::test_utils::{seed_user, test_db};
+
+        let db = test_db().await;
+        let manager = test_manager(role_managing(false), db.clone());
+        let user = seed_user(&db, "admin").await;
+        let decision =
+            manager.determine_user_role(&claiming_roles(serde_json::json!(["admin"])), false);
+        manager
+            .record_role_decision(&db, &user, "oidc", &decision)
+            .await
+            .unwrap();
+
+        let page = AuditService::new(&db)
+            .list(&AuditQuery {
+                target_id: Some(user.id.clone()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        let entry = &page.items[0];
+        assert_eq!(entry.action, ADMIN_CLAIM_GRANTED_ACTION);
+        let data = entry.data.as_ref().unwrap();
+        assert_eq!(data["provider"], "oidc");
+        assert_eq!(data["claim"], "roles");
+        assert_eq!(data["matched_role"], "admin");
+
+        // Plain decisions leave no record
+        manager
+            .record_role_decision(&db, &user, "oidc", &RoleDecision::plain("user"))
+            .await
+            .unwrap();
+        let page = AuditService::new(&db)
+            .list(&AuditQuery {
+                target_id: Some(user.id.clone()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_excessive_groups_are_capped() {
+        let manager = test_manager(
+            Config::from_lookup(|_| None).unwrap(),
+            Database::new_lazy_for_tests(),
+        );
+        let mut groups: Vec<String> = (0..1000).map(|i| format!("group-{}", i)).collect();
+        groups.insert(1, "group-0".to_string());
+        let mut user_info = claiming_roles(serde_json::json!([]));
+        user_info
+            .extra
+            .insert("groups".to_string(), serde_json::json!(groups));
+
+        let synced = manager.groups_to_sync(&user_info, &["group-2".to_string()], 200);
+        assert_eq!(synced.len(), 200);
+        assert_eq!(&synced[..3], ["group-0", "group-1", "group-3"]);
+
+        assert_eq!(manager.groups_to_sync(&user_info, &[], 0).len(), 1000);
+    }
 }
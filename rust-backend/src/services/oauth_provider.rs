@@ -9,7 +9,9 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use tracing::{debug, error, info};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
 /// OAuth provider configuration
 #[derive(Debug, Clone)]
@@ -25,6 +27,10 @@ pub struct OAuthProviderConfig {
     pub discovery_url: Option<String>,
     pub sub_claim: Option<String>,
     pub picture_url: Option<String>,
+    /// Largest userinfo response accepted (OAUTH_MAX_USERINFO_BYTES)
+    pub max_userinfo_bytes: usize,
+    /// How long a userinfo response is reused for its access token (OAUTH_CACHE_USERINFO)
+    pub userinfo_cache_ttl: Option<Duration>,
 }
 
 /// OAuth token response from provider
@@ -79,7 +85,7 @@ pub struct OIDCDiscovery {
 }
 
 /// PKCE (Proof Key for Code Exchange) data
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PKCEData {
     pub code_verifier: String,
     pub code_challenge: String,
@@ -143,35 +149,131 @@ pub trait OAuthProvider: Send + Sync {
     async fn refresh_token(&self, refresh_token: &str) -> AppResult<OAuthTokenResponse>;
 }
 
+/// Key of an access token in the userinfo cache, so tokens aren't held in memory
+fn token_key(access_token: &str) -> [u8; 32] {
+    Sha256::digest(access_token.as_bytes()).into()
+}
+
+#[derive(Default)]
+struct CachedToken {
+    /// When the provider said the token expires, if it did
+    expires_at: Option<Instant>,
+    user_info: Option<(OAuthUserInfo, Instant)>,
+}
+
+/// Userinfo responses by access token, so one login flow fetches them once
+///
+/// Each entry belongs to a single token, hence a single user, and lives for the TTL or
+/// until the token expires, whichever is sooner.
+struct UserInfoCache {
+    ttl: Duration,
+    tokens: Mutex<HashMap<[u8; 32], CachedToken>>,
+}
+
+impl UserInfoCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Remember when a newly issued token expires
+    fn token_issued(&self, token_response: &OAuthTokenResponse) {
+        let Some(expires_in) = token_response.expires_in else {
+            return;
+        };
+        let expires_at = Instant::now() + Duration::from_secs(expires_in.max(0) as u64);
+        let mut tokens = self.tokens.lock().unwrap();
+        Self::purge(&mut tokens, Instant::now());
+        tokens
+            .entry(token_key(&token_response.access_token))
+            .or_default()
+            .expires_at = Some(expires_at);
+    }
+
+    fn get(&self, access_token: &str) -> Option<OAuthUserInfo> {
+        let tokens = self.tokens.lock().unwrap();
+        let (user_info, until) = tokens.get(&token_key(access_token))?.user_info.as_ref()?;
+        (*until > Instant::now()).then(|| user_info.clone())
+    }
+
+    fn insert(&self, access_token: &str, user_info: &OAuthUserInfo) {
+        let now = Instant::now();
+        let key = token_key(access_token);
+        let mut tokens = self.tokens.lock().unwrap();
+        let expires_at = tokens.get(&key).and_then(|token| token.expires_at);
+        let until = expires_at.map_or(now + self.ttl, |at| at.min(now + self.ttl));
+        Self::purge(&mut tokens, now);
+        if until > now {
+            tokens.insert(
+                key,
+                CachedToken {
+                    expires_at,
+                    user_info: Some((user_info.clone(), until)),
+                },
+            );
+        }
+    }
+
+    /// Drop responses past their time and tokens past their expiry
+    fn purge(tokens: &mut HashMap<[u8; 32], CachedToken>, now: Instant) {
+        tokens.retain(|_, token| {
+            if token
+                .user_info
+                .as_ref()
+                .is_some_and(|(_, until)| *until <= now)
+            {
+                token.user_info = None;
+            }
+            token
+                .expires_at
+                .map_or(token.user_info.is_some(), |at| at > now)
+        });
+    }
+}
+
 /// Base OAuth provider implementation
 pub struct BaseOAuthProvider {
     config: OAuthProviderConfig,
     client: Client,
+    userinfo_cache: Option<UserInfoCache>,
 }
 
 impl BaseOAuthProvider {
-    pub fn new(config: OAuthProviderConfig) -> Self {
+    /// `client` is normally the shared pooled client from `AppState`
+    pub fn new(config: OAuthProviderConfig, client: Client) -> Self {
+        let userinfo_cache = config.userinfo_cache_ttl.map(UserInfoCache::new);
         Self {
             config,
-            client: Client::new(),
+            client,
+            userinfo_cache,
         }
     }
 
-    /// Discover OIDC endpoints
-    pub async fn discover_oidc(&mut self, discovery_url: &str) -> AppResult<()> {
+    /// Discover OIDC endpoints, giving up after `timeout`
+    pub async fn discover_oidc(&mut self, discovery_url: &str, timeout: Duration) -> AppResult<()> {
         debug!("Discovering OIDC endpoints from {}", discovery_url);
 
-        let discovery: OIDCDiscovery = self
-            .client
-            .get(discovery_url)
-            .send()
-            .await
-            .map_err(|e| AppError::ExternalServiceError(format!("OIDC discovery failed: {}", e)))?
-            .json()
-            .await
-            .map_err(|e| {
-                AppError::ExternalServiceError(format!("Failed to parse OIDC discovery: {}", e))
-            })?;
+        let fetch = async {
+            crate::utils::telemetry::send(self.client.get(discovery_url), "oauth.discovery")
+                .await
+                .map_err(|e| {
+                    AppError::ExternalServiceError(format!("OIDC discovery failed: {}", e))
+                })?
+                .json::<OIDCDiscovery>()
+                .await
+                .map_err(|e| {
+                    AppError::ExternalServiceError(format!("Failed to parse OIDC discovery: {}", e))
+                })
+        };
+        let discovery = tokio::time::timeout(timeout, fetch).await.map_err(|_| {
+            AppError::ExternalServiceError(format!(
+                "OIDC discovery from {} timed out after {}s",
+                discovery_url,
+                timeout.as_secs()
+            ))
+        })??;
 
         // Update configuration with discovered endpoints
         self.config.authorize_url = discovery.authorization_endpoint;
@@ -252,16 +354,15 @@ impl OAuthProvider for BaseOAuthProvider {
 
         debug!("Exchanging code for token with {}", self.config.name);
 
-        let response = self
-            .client
-            .post(&self.config.token_url)
-            .form(&params)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Token exchange failed: {}", e);
-                AppError::ExternalServiceError(format!("Token exchange failed: {}", e))
-            })?;
+        let response = crate::utils::telemetry::send(
+            self.client.post(&self.config.token_url).form(&params),
+            "oauth.token",
+        )
+        .await
+        .map_err(|e| {
+            error!("Token exchange failed: {}", e);
+            AppError::ExternalServiceError(format!("Token exchange failed: {}", e))
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -279,6 +380,9 @@ impl OAuthProvider for BaseOAuthProvider {
         })?;
 
         debug!("Token exchange successful for {}", self.config.name);
+        if let Some(cache) = &self.userinfo_cache {
+            cache.token_issued(&token_response);
+        }
         Ok(token_response)
     }
 
@@ -289,18 +393,26 @@ impl OAuthProvider for BaseOAuthProvider {
             .as_ref()
             .ok_or_else(|| AppError::BadRequest("No userinfo URL configured".to_string()))?;
 
+        if let Some(user_info) = self
+            .userinfo_cache
+            .as_ref()
+            .and_then(|cache| cache.get(access_token))
+        {
+            debug!("Reusing cached user info for sub: {}", user_info.sub);
+            return Ok(user_info);
+        }
+
         debug!("Fetching user info from {}", userinfo_url);
 
-        let response = self
-            .client
-            .get(userinfo_url)
-            .bearer_auth(access_token)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Failed to fetch user info: {}", e);
-                AppError::ExternalServiceError(format!("Failed to fetch user info: {}", e))
-            })?;
+        let response = crate::utils::telemetry::send(
+            self.client.get(userinfo_url).bearer_auth(access_token),
+            "oauth.userinfo",
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch user info: {}", e);
+            AppError::ExternalServiceError(format!("Failed to fetch user info: {}", e))
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -312,12 +424,21 @@ impl OAuthProvider for BaseOAuthProvider {
             )));
         }
 
-        let user_info: OAuthUserInfo = response.json().await.map_err(|e| {
+        let body = read_limited(response, self.config.max_userinfo_bytes)
+            .await
+            .map_err(|e| {
+                warn!("Refused user info from {}: {}", self.config.name, e);
+                e
+            })?;
+        let user_info: OAuthUserInfo = serde_json::from_slice(&body).map_err(|e| {
             error!("Failed to parse user info: {}", e);
             AppError::ExternalServiceError(format!("Failed to parse user info: {}", e))
         })?;
 
         debug!("User info fetched successfully for sub: {}", user_info.sub);
+        if let Some(cache) = &self.userinfo_cache {
+            cache.insert(access_token, &user_info);
+        }
         Ok(user_info)
     }
 
@@ -331,16 +452,15 @@ impl OAuthProvider for BaseOAuthProvider {
 
         debug!("Refreshing token for {}", self.config.name);
 
-        let response = self
-            .client
-            .post(&self.config.token_url)
-            .form(&params)
-            .send()
-            .await
-            .map_err(|e| {
-                error!("Token refresh failed: {}", e);
-                AppError::ExternalServiceError(format!("Token refresh failed: {}", e))
-            })?;
+        let response = crate::utils::telemetry::send(
+            self.client.post(&self.config.token_url).form(&params),
+            "oauth.token",
+        )
+        .await
+        .map_err(|e| {
+            error!("Token refresh failed: {}", e);
+            AppError::ExternalServiceError(format!("Token refresh failed: {}", e))
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -358,12 +478,57 @@ impl OAuthProvider for BaseOAuthProvider {
         })?;
 
         debug!("Token refresh successful for {}", self.config.name);
+        if let Some(cache) = &self.userinfo_cache {
+            cache.token_issued(&token_response);
+        }
         Ok(token_response)
     }
 }
 
+/// Read a response body of at most `max_bytes` (0 = unlimited)
+///
+/// Bodies are counted as they arrive, so an IdP can't exhaust memory with a huge or
+/// endless response even without a Content-Length.
+async fn read_limited(mut response: reqwest::Response, max_bytes: usize) -> AppResult<Vec<u8>> {
+    let too_large = || {
+        AppError::ExternalServiceError(format!(
+            "User info response exceeds {} bytes (OAUTH_MAX_USERINFO_BYTES)",
+            max_bytes
+        ))
+    };
+    if max_bytes > 0 && response.content_length().unwrap_or(0) > max_bytes as u64 {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| AppError::ExternalServiceError(format!("Failed to read user info: {}", e)))?
+    {
+        if max_bytes > 0 && body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+fn discovery_timeout(config: &Config) -> Duration {
+    Duration::from_secs(config.oidc_discovery_timeout)
+}
+
+fn userinfo_cache_ttl(config: &Config) -> Option<Duration> {
+    config
+        .oauth_cache_userinfo
+        .then(|| Duration::from_secs(config.oauth_userinfo_cache_ttl))
+}
+
 /// Create Google OAuth provider
-pub async fn create_google_provider(config: &Config) -> AppResult<Option<BaseOAuthProvider>> {
+pub async fn create_google_provider(
+    config: &Config,
+    client: &Client,
+) -> AppResult<Option<BaseOAuthProvider>> {
     if config.google_client_id.is_empty() || config.google_client_secret.is_empty() {
         return Ok(None);
     }
@@ -386,13 +551,24 @@ pub async fn create_google_provider(config: &Config) -> AppResult<Option<BaseOAu
         ),
         sub_claim: None,
         picture_url: None,
+        max_userinfo_bytes: config.oauth_max_userinfo_bytes,
+        userinfo_cache_ttl: userinfo_cache_ttl(config),
     };
 
-    let mut provider = BaseOAuthProvider::new(provider_config);
+    let mut provider = BaseOAuthProvider::new(provider_config, client.clone());
 
     // Perform OIDC discovery
     if let Some(discovery_url) = provider.config.discovery_url.clone() {
-        provider.discover_oidc(&discovery_url).await?;
+        if let Err(e) = provider
+            .discover_oidc(&discovery_url, discovery_timeout(config))
+            .await
+        {
+            // The endpoints above are Google's published ones, so carry on with them
+            warn!(
+                "Google OIDC discovery failed, using hardcoded endpoints: {}",
+                e
+            );
+        }
     }
 
     info!("Google OAuth provider configured");
@@ -400,7 +576,10 @@ pub async fn create_google_provider(config: &Config) -> AppResult<Option<BaseOAu
 }
 
 /// Create Microsoft OAuth provider
-pub async fn create_microsoft_provider(config: &Config) -> AppResult<Option<BaseOAuthProvider>> {
+pub async fn create_microsoft_provider(
+    config: &Config,
+    client: &Client,
+) -> AppResult<Option<BaseOAuthProvider>> {
     if config.microsoft_client_id.is_empty()
         || config.microsoft_client_secret.is_empty()
         || config.microsoft_client_tenant_id.is_empty()
@@ -430,13 +609,18 @@ pub async fn create_microsoft_provider(config: &Config) -> AppResult<Option<Base
         )),
         sub_claim: None,
         picture_url: Some(config.microsoft_client_picture_url.clone()),
+        max_userinfo_bytes: config.oauth_max_userinfo_bytes,
+        userinfo_cache_ttl: userinfo_cache_ttl(config),
     };
 
-    let mut provider = BaseOAuthProvider::new(provider_config);
+    let mut provider = BaseOAuthProvider::new(provider_config, client.clone());
 
     // Perform OIDC discovery
     if let Some(discovery_url) = provider.config.discovery_url.clone() {
-        if let Err(e) = provider.discover_oidc(&discovery_url).await {
+        if let Err(e) = provider
+            .discover_oidc(&discovery_url, discovery_timeout(config))
+            .await
+        {
             // Microsoft discovery can fail, use hardcoded endpoints as fallback
             debug!(
                 "Microsoft OIDC discovery failed, using hardcoded endpoints: {}",
@@ -450,7 +634,10 @@ pub async fn create_microsoft_provider(config: &Config) -> AppResult<Option<Base
 }
 
 /// Create GitHub OAuth provider
-pub async fn create_github_provider(config: &Config) -> AppResult<Option<BaseOAuthProvider>> {
+pub async fn create_github_provider(
+    config: &Config,
+    client: &Client,
+) -> AppResult<Option<BaseOAuthProvider>> {
     if config.github_client_id.is_empty() || config.github_client_secret.is_empty() {
         return Ok(None);
     }
@@ -471,16 +658,21 @@ pub async fn create_github_provider(config: &Config) -> AppResult<Option<BaseOAu
         discovery_url: None,
         sub_claim: Some("id".to_string()),
         picture_url: None,
+        max_userinfo_bytes: config.oauth_max_userinfo_bytes,
+        userinfo_cache_ttl: userinfo_cache_ttl(config),
     };
 
-    let provider = BaseOAuthProvider::new(provider_config);
+    let provider = BaseOAuthProvider::new(provider_config, client.clone());
 
     info!("GitHub OAuth provider configured");
     Ok(Some(provider))
 }
 
 /// Create generic OIDC provider
-pub async fn create_oidc_provider(config: &Config) -> AppResult<Option<BaseOAuthProvider>> {
+pub async fn create_oidc_provider(
+    config: &Config,
+    client: &Client,
+) -> AppResult<Option<BaseOAuthProvider>> {
     if config.oauth_client_id.is_empty()
         || config.oauth_client_secret.is_empty()
         || config.openid_provider_url.is_empty()
@@ -504,13 +696,24 @@ pub async fn create_oidc_provider(config: &Config) -> AppResult<Option<BaseOAuth
         discovery_url: Some(config.openid_provider_url.clone()),
         sub_claim: config.oauth_sub_claim.clone(),
         picture_url: None,
+        max_userinfo_bytes: config.oauth_max_userinfo_bytes,
+        userinfo_cache_ttl: userinfo_cache_ttl(config),
     };
 
-    let mut provider = BaseOAuthProvider::new(provider_config);
+    let client = crate::utils::http::upstream_client(client, &config.openid_provider_url);
+    let mut provider = BaseOAuthProvider::new(provider_config, client);
 
     // Perform OIDC discovery (required for generic OIDC)
     if let Some(discovery_url) = provider.config.discovery_url.clone() {
-        provider.discover_oidc(&discovery_url).await?;
+        provider
+            .discover_oidc(&discovery_url, discovery_timeout(config))
+            .await
+            .map_err(|e| {
+                AppError::ExternalServiceError(format!(
+                    "OIDC provider '{}' needs discovery to find its endpoints: {}",
+                    config.oauth_provider_name, e
+                ))
+            })?;
     }
 
     info!(
@@ -521,7 +724,10 @@ pub async fn create_oidc_provider(config: &Config) -> AppResult<Option<BaseOAuth
 }
 
 /// Create Feishu OAuth provider
-pub async fn create_feishu_provider(config: &Config) -> AppResult<Option<BaseOAuthProvider>> {
+pub async fn create_feishu_provider(
+    config: &Config,
+    client: &Client,
+) -> AppResult<Option<BaseOAuthProvider>> {
     if config.feishu_client_id.is_empty() || config.feishu_client_secret.is_empty() {
         return Ok(None);
     }
@@ -542,10 +748,183 @@ pub async fn create_feishu_provider(config: &Config) -> AppResult<Option<BaseOAu
         discovery_url: None,
         sub_claim: Some("user_id".to_string()),
         picture_url: None,
+        max_userinfo_bytes: config.oauth_max_userinfo_bytes,
+        userinfo_cache_ttl: userinfo_cache_ttl(config),
     };
 
-    let provider = BaseOAuthProvider::new(provider_config);
+    let provider = BaseOAuthProvider::new(provider_config, client.clone());
 
     info!("Feishu OAuth provider configured");
     Ok(Some(provider))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn provider() -> BaseOAuthProvider {
+        let config = OAuthProviderConfig {
+            name: "test".to_string(),
+            client_id: "client-id".to_string(),
+            client_secret: "secret".to_string(),
+            authorize_url: "https://idp.example.com/authorize".to_string(),
+            token_url: "https://idp.example.com/token".to_string(),
+            userinfo_url: None,
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            redirect_uri: "https://app.example.com/oauth/test/callback".to_string(),
+            discovery_url: None,
+            sub_claim: None,
+            picture_url: None,
+            max_userinfo_bytes: 1 << 20,
+            userinfo_cache_ttl: None,
+        };
+        // Standalone client; the app injects its shared one
+        BaseOAuthProvider::new(config, Client::new())
+    }
+
+    /// Base URL of a server that accepts connections but never answers
+    async fn hanging_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn discovery_config(url: &str) -> Config {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.oidc_discovery_timeout = 1;
+        config.microsoft_client_id = "ms-client".to_string();
+        config.microsoft_client_secret = "ms-secret".to_string();
+        config.microsoft_client_tenant_id = "tenant".to_string();
+        config.microsoft_client_login_base_url = url.to_string();
+        config.oauth_client_id = "oidc-client".to_string();
+        config.oauth_client_secret = "oidc-secret".to_string();
+        config.openid_provider_url = format!("{}/.well-known/openid-configuration", url);
+        config
+    }
+
+    #[tokio::test]
+    async fn test_hanging_discovery_times_out() {
+        let config = discovery_config(&hanging_server().await);
+        let client = Client::new();
+
+        let (microsoft, oidc) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(
+                create_microsoft_provider(&config, &client),
+                create_oidc_provider(&config, &client),
+            )
+        })
+        .await
+        .expect("discovery did not time out");
+
+        // Microsoft keeps its hardcoded endpoints
+        let microsoft = microsoft.unwrap().unwrap();
+        assert!(microsoft
+            .config
+            .token_url
+            .ends_with("/tenant/oauth2/v2.0/token"));
+
+        // Generic OIDC has no endpoints without discovery
+        let err = oidc.err().expect("generic OIDC should fail").to_string();
+        assert!(err.contains("timed out"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_url_includes_pkce() {
+        let pkce = PKCEData::generate();
+        let url = provider()
+            .get_authorization_url("state-1", Some(&pkce))
+            .await
+            .unwrap();
+        let url = reqwest::Url::parse(&url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(params["client_id"], "client-id");
+        assert_eq!(params["scope"], "openid email");
+        assert_eq!(params["state"], "state-1");
+        assert_eq!(params["code_challenge"], pkce.code_challenge);
+        assert_eq!(params["code_challenge_method"], "S256");
+    }
+
+    /// URL of a server answering every request with `body`, chunked, and how many
+    /// requests it has answered
+    async fn userinfo_server(body: String) -> (String, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                     transfer-encoding: chunked\r\nconnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{}/userinfo", addr), hits)
+    }
+
+    #[tokio::test]
+    async fn test_oversized_userinfo_is_refused() {
+        let groups: Vec<String> = (0..5000).map(|i| format!("group-{}", i)).collect();
+        let body = serde_json::json!({ "sub": "u1", "groups": groups }).to_string();
+        let (url, _) = userinfo_server(body.clone()).await;
+
+        let mut small = provider();
+        small.config.userinfo_url = Some(url.clone());
+        small.config.max_userinfo_bytes = 1024;
+        let err = small.get_user_info("token").await.unwrap_err().to_string();
+        assert!(err.contains("OAUTH_MAX_USERINFO_BYTES"), "{}", err);
+
+        let mut large = provider();
+        large.config.userinfo_url = Some(url);
+        large.config.max_userinfo_bytes = body.len();
+        assert_eq!(large.get_user_info("token").await.unwrap().sub, "u1");
+    }
+
+    #[tokio::test]
+    async fn test_userinfo_cached_per_token_within_ttl() {
+        let body = serde_json::json!({ "sub": "u1" }).to_string();
+        let (url, hits) = userinfo_server(body).await;
+        let mut cached = provider();
+        cached.config.userinfo_url = Some(url);
+        cached.userinfo_cache = Some(UserInfoCache::new(Duration::from_secs(60)));
+
+        assert_eq!(cached.get_user_info("token-a").await.unwrap().sub, "u1");
+        assert_eq!(cached.get_user_info("token-a").await.unwrap().sub, "u1");
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // Another token is another user's lookup
+        cached.get_user_info("token-b").await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Nothing outlives the token itself
+        let cache = cached.userinfo_cache.as_ref().unwrap();
+        cache.token_issued(&OAuthTokenResponse {
+            access_token: "token-c".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in: Some(0),
+            refresh_token: None,
+            id_token: None,
+            scope: None,
+        });
+        cached.get_user_info("token-c").await.unwrap();
+        cached.get_user_info("token-c").await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,304 @@
+/// Background refresh of OAuth tokens before they expire
+///
+/// Refreshes run with bounded concurrency and random spacing so a wave of sessions
+/// expiring together doesn't stampede a provider's token endpoint. A provider that
+/// keeps failing is skipped entirely until its backoff passes.
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use rand::Rng;
+
+use crate::config::Config;
+use crate::error::AppResult;
+use crate::models::oauth_session::OAuthSessionWithToken;
+use crate::services::oauth_manager::OAuthManager;
+
+/// Sessions expiring within this window are refreshed; matches the on-demand threshold
+const REFRESH_WINDOW_MINUTES: i64 = 5;
+
+/// Consecutive failures before a provider is backed off
+const BACKOFF_AFTER_FAILURES: u32 = 3;
+
+const BACKOFF_BASE: Duration = Duration::from_secs(60);
+const BACKOFF_MAX: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Default)]
+struct ProviderHealth {
+    consecutive_failures: u32,
+    backoff_until: Option<Instant>,
+}
+
+/// Success/failure counts since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefreshCounts {
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Sessions not attempted because their provider was backed off
+    pub skipped: u64,
+}
+
+pub struct OAuthRefresher {
+    interval: Duration,
+    concurrency: usize,
+    jitter: Duration,
+    providers: Mutex<HashMap<String, ProviderHealth>>,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    skipped: AtomicU64,
+}
+
+impl OAuthRefresher {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            interval: Duration::from_secs(config.oauth_refresh_interval),
+            concurrency: config.oauth_refresh_concurrency.max(1),
+            jitter: Duration::from_millis(config.oauth_refresh_jitter_ms),
+            providers: Mutex::new(HashMap::new()),
+            succeeded: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn counts(&self) -> RefreshCounts {
+        RefreshCounts {
+            succeeded: self.succeeded.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Start the periodic sweep; does nothing when OAUTH_REFRESH_INTERVAL is 0
+    pub fn spawn(self: &Arc<Self>, manager: Arc<OAuthManager>) {
+        if self.interval.is_zero() {
+            return;
+        }
+
+        let refresher = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(refresher.interval).await;
+                if let Err(e) = refresher.run_once(&manager).await {
+                    tracing::warn!("OAuth proactive refresh sweep failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Refresh every session that expires soon and can be refreshed
+    pub async fn run_once(&self, manager: &OAuthManager) -> AppResult<()> {
+        let sessions = manager
+            .sessions_expiring_soon(REFRESH_WINDOW_MINUTES)
+            .await?
+            .into_iter()
+            .filter(|session| session.token.can_refresh())
+            .collect();
+
+        self.refresh_all(sessions, |session| async move {
+            manager.refresh_session(&session).await.map(|_| ())
+        })
+        .await;
+        Ok(())
+    }
+
+    /// Run `refresh` over `sessions`, at most `concurrency` at a time
+    ///
+    /// Each user/provider pair is refreshed once per sweep.
+    async fn refresh_all<F, Fut>(&self, sessions: Vec<OAuthSessionWithToken>, refresh: F)
+    where
+        F: Fn(OAuthSessionWithToken) -> Fut,
+        Fut: Future<Output = AppResult<()>>,
+    {
+        let mut seen = HashSet::new();
+        let sessions: Vec<_> = sessions
+            .into_iter()
+            .filter(|session| seen.insert((session.user_id.clone(), session.provider.clone())))
+            .collect();
+        if sessions.is_empty() {
+            return;
+        }
+
+        tracing::debug!("Proactively refreshing {} OAuth sessions", sessions.len());
+
+        futures::stream::iter(sessions)
+            .for_each_concurrent(self.concurrency, |session| {
+                let refresh = &refresh;
+                async move {
+                    if !self.jitter.is_zero() {
+                        let delay = rand::rng().random_range(Duration::ZERO..=self.jitter);
+                        tokio::time::sleep(delay).await;
+                    }
+
+                    // Checked after the delay, so a backoff triggered meanwhile applies
+                    if self.is_backed_off(&session.provider) {
+                        self.skipped.fetch_add(1, Ordering::Relaxed);
+                        return;
+                    }
+
+                    let provider = session.provider.clone();
+                    match refresh(session).await {
+                        Ok(()) => self.record_success(&provider),
+                        Err(e) => {
+                            tracing::warn!(
+                                "Proactive refresh for provider {} failed: {}",
+                                provider,
+                                e
+                            );
+                            self.record_failure(&provider);
+                        }
+                    }
+                }
+            })
+            .await;
+    }
+
+    fn is_backed_off(&self, provider: &str) -> bool {
+        self.providers
+            .lock()
+            .unwrap()
+            .get(provider)
+            .and_then(|health| health.backoff_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn record_success(&self, provider: &str) {
+        self.succeeded.fetch_add(1, Ordering::Relaxed);
+        self.providers.lock().unwrap().remove(provider);
+    }
+
+    fn record_failure(&self, provider: &str) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+
+        let mut providers = self.providers.lock().unwrap();
+        let health = providers.entry(provider.to_string()).or_default();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= BACKOFF_AFTER_FAILURES {
+            // Doubles with every further failure
+            let exponent = (health.consecutive_failures - BACKOFF_AFTER_FAILURES).min(10);
+            let backoff = BACKOFF_BASE.saturating_mul(1 << exponent).min(BACKOFF_MAX);
+            health.backoff_until = Some(Instant::now() + backoff);
+            tracing::warn!(
+                "OAuth provider {} failed {} refreshes in a row, pausing refreshes for {}s",
+                provider,
+                health.consecutive_failures,
+                backoff.as_secs()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::models::oauth_session::OAuthTokenData;
+    use std::sync::atomic::AtomicUsize;
+
+    fn refresher(concurrency: usize) -> OAuthRefresher {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.oauth_refresh_concurrency = concurrency;
+        config.oauth_refresh_jitter_ms = 0;
+        OAuthRefresher::from_config(&config)
+    }
+
+    fn session(user_id: &str, provider: &str) -> OAuthSessionWithToken {
+        OAuthSessionWithToken {
+            id: format!("{}-{}", user_id, provider),
+            user_id: user_id.to_string(),
+            provider: provider.to_string(),
+            token: OAuthTokenData {
+                access_token: "access".to_string(),
+                token_type: "Bearer".to_string(),
+                refresh_token: Some("refresh".to_string()),
+                id_token: None,
+                expires_in: None,
+                expires_at: 0,
+                issued_at: 0,
+                scope: None,
+                refreshable: true,
+            },
+            expires_at: 0,
+            created_at: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_capped_at_configured_concurrency() {
+        let refresher = refresher(3);
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        let sessions = (0..20)
+            .map(|i| session(&format!("user-{}", i), "google"))
+            .collect();
+        refresher
+            .refresh_all(sessions, |_| async {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(refresher.counts().succeeded, 20);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_sessions_refreshed_once() {
+        let refresher = refresher(4);
+        let calls = AtomicUsize::new(0);
+
+        let sessions = vec![
+            session("alice", "google"),
+            session("alice", "google"),
+            session("alice", "github"),
+        ];
+        refresher
+            .refresh_all(sessions, |_| async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            })
+            .await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failing_provider_backed_off() {
+        let refresher = refresher(1);
+
+        let sessions = (0..5)
+            .map(|i| session(&format!("user-{}", i), "flaky"))
+            .chain([session("user-0", "google")])
+            .collect();
+        refresher
+            .refresh_all(sessions, |session| async move {
+                if session.provider == "flaky" {
+                    Err(AppError::ExternalServiceError(
+                        "token endpoint down".to_string(),
+                    ))
+                } else {
+                    Ok(())
+                }
+            })
+            .await;
+
+        assert_eq!(
+            refresher.counts(),
+            RefreshCounts {
+                succeeded: 1,
+                failed: BACKOFF_AFTER_FAILURES as u64,
+                skipped: 5 - BACKOFF_AFTER_FAILURES as u64,
+            }
+        );
+        assert!(refresher.is_backed_off("flaky"));
+        assert!(!refresher.is_backed_off("google"));
+    }
+}
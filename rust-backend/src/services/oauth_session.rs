@@ -4,10 +4,11 @@
 use crate::db::Database;
 use crate::error::{AppError, AppResult};
 use crate::models::oauth_session::{
-    OAuthSession, OAuthSessionResponse, OAuthSessionWithToken, OAuthTokenData,
+    KeyRotationReport, OAuthSessionResponse, OAuthSessionWithToken, OAuthTokenData,
 };
-use crate::utils::fernet::Fernet;
+use crate::utils::fernet::MultiFernet;
 use chrono::Utc;
+use sqlx::postgres::PgRow;
 use sqlx::Row;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, error, info, warn};
@@ -15,13 +16,14 @@ use uuid::Uuid;
 
 pub struct OAuthSessionService {
     db: Database,
-    fernet: Fernet,
+    fernet: MultiFernet,
 }
 
 impl OAuthSessionService {
     /// Create a new OAuth session service
-    pub fn new(db: Database, encryption_key: &str) -> AppResult<Self> {
-        let fernet = Fernet::new(encryption_key)?;
+    /// `encryption_keys` are ordered primary key first; the rest only decrypt
+    pub fn new(db: Database, encryption_keys: &[String]) -> AppResult<Self> {
+        let fernet = MultiFernet::from_keys(encryption_keys)?;
         Ok(Self { db, fernet })
     }
 
@@ -84,6 +86,37 @@ impl OAuthSessionService {
         })
     }
 
+    /// Decrypt a session row's token
+    ///
+    /// A token that no longer decrypts (e.g. its key was dropped from the key list) can
+    /// never be used again, so the session is deleted and treated as missing: the user
+    /// signs in again instead of hitting an error.
+    async fn session_from_row(&self, row: &PgRow) -> AppResult<Option<OAuthSessionWithToken>> {
+        let id: String = row.get("id");
+        let encrypted_token: String = row.get("token");
+        let token_data = match self.fernet.decrypt_json::<OAuthTokenData>(&encrypted_token) {
+            Ok(token_data) => token_data,
+            Err(e) => {
+                warn!(
+                    "Deleting OAuth session {} with undecryptable token: {}",
+                    id, e
+                );
+                self.delete_session_by_id(&id).await?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(OAuthSessionWithToken {
+            id,
+            user_id: row.get("user_id"),
+            provider: row.get("provider"),
+            token: token_data,
+            expires_at: row.get("expires_at"),
+            created_at: row.get("created_at"),
+            updated_at: row.get("updated_at"),
+        }))
+    }
+
     /// Get session by ID
     pub async fn get_session_by_id(
         &self,
@@ -105,20 +138,7 @@ impl OAuthSessionService {
             })?;
 
         match row {
-            Some(row) => {
-                let encrypted_token: String = row.get("token");
-                let token_data: OAuthTokenData = self.fernet.decrypt_json(&encrypted_token)?;
-
-                Ok(Some(OAuthSessionWithToken {
-                    id: row.get("id"),
-                    user_id: row.get("user_id"),
-                    provider: row.get("provider"),
-                    token: token_data,
-                    expires_at: row.get("expires_at"),
-                    created_at: row.get("created_at"),
-                    updated_at: row.get("updated_at"),
-                }))
-            }
+            Some(row) => self.session_from_row(&row).await,
             None => Ok(None),
         }
     }
@@ -146,20 +166,7 @@ impl OAuthSessionService {
             })?;
 
         match row {
-            Some(row) => {
-                let encrypted_token: String = row.get("token");
-                let token_data: OAuthTokenData = self.fernet.decrypt_json(&encrypted_token)?;
-
-                Ok(Some(OAuthSessionWithToken {
-                    id: row.get("id"),
-                    user_id: row.get("user_id"),
-                    provider: row.get("provider"),
-                    token: token_data,
-                    expires_at: row.get("expires_at"),
-                    created_at: row.get("created_at"),
-                    updated_at: row.get("updated_at"),
-                }))
-            }
+            Some(row) => self.session_from_row(&row).await,
             None => Ok(None),
         }
     }
@@ -187,20 +194,7 @@ impl OAuthSessionService {
             })?;
 
         match row {
-            Some(row) => {
-                let encrypted_token: String = row.get("token");
-                let token_data: OAuthTokenData = self.fernet.decrypt_json(&encrypted_token)?;
-
-                Ok(Some(OAuthSessionWithToken {
-                    id: row.get("id"),
-                    user_id: row.get("user_id"),
-                    provider: row.get("provider"),
-                    token: token_data,
-                    expires_at: row.get("expires_at"),
-                    created_at: row.get("created_at"),
-                    updated_at: row.get("updated_at"),
-                }))
-            }
+            Some(row) => self.session_from_row(&row).await,
             None => Ok(None),
         }
     }
@@ -257,7 +251,7 @@ impl OAuthSessionService {
         user_id: &str,
     ) -> AppResult<Vec<OAuthSessionResponse>> {
         let query = r#"
-            SELECT id, user_id, provider, expires_at
+            SELECT id, user_id, provider, token, expires_at
             FROM oauth_session
             WHERE user_id = $1
             ORDER BY created_at DESC
@@ -274,11 +268,20 @@ impl OAuthSessionService {
 
         let sessions = rows
             .iter()
-            .map(|row| OAuthSessionResponse {
-                id: row.get("id"),
-                user_id: row.get("user_id"),
-                provider: row.get("provider"),
-                expires_at: row.get("expires_at"),
+            .map(|row| {
+                // Only the refreshable flag is read from the token; it isn't returned
+                let encrypted_token: String = row.get("token");
+                let refreshable = self
+                    .fernet
+                    .decrypt_json::<OAuthTokenData>(&encrypted_token)
+                    .is_ok_and(|token| token.can_refresh());
+                OAuthSessionResponse {
+                    id: row.get("id"),
+                    user_id: row.get("user_id"),
+                    provider: row.get("provider"),
+                    expires_at: row.get("expires_at"),
+                    refreshable,
+                }
             })
             .collect();
 
@@ -505,19 +508,160 @@ impl OAuthSessionService {
 
         Ok(sessions)
     }
+
+    /// Re-encrypt every session token under the primary key
+    /// Sessions are processed in batches, each within its own transaction.
+    pub async fn rotate_encryption_key(&self, batch_size: i64) -> AppResult<KeyRotationReport> {
+        let mut report = KeyRotationReport {
+            key_count: self.fernet.key_count(),
+            ..Default::default()
+        };
+        let mut last_id = String::new();
+
+        loop {
+            let mut tx = self.db.pool.begin().await?;
+
+            let rows = sqlx::query(
+                r#"
+                SELECT id, token FROM oauth_session
+                WHERE id > $1
+                ORDER BY id
+                LIMIT $2
+                FOR UPDATE
+                "#,
+            )
+            .bind(&last_id)
+            .bind(batch_size)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let Some(last_row) = rows.last() else {
+                tx.commit().await?;
+                break;
+            };
+            last_id = last_row.get("id");
+
+            for row in &rows {
+                let session_id: String = row.get("id");
+                let encrypted_token: String = row.get("token");
+
+                match self.fernet.rotate(&encrypted_token) {
+                    Ok(rotated) => {
+                        sqlx::query("UPDATE oauth_session SET token = $1 WHERE id = $2")
+                            .bind(&rotated)
+                            .bind(&session_id)
+                            .execute(&mut *tx)
+                            .await?;
+                        report.migrated += 1;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to decrypt OAuth session {} during key rotation: {}",
+                            session_id, e
+                        );
+                        report.failed += 1;
+                        report.failed_session_ids.push(session_id);
+                    }
+                }
+            }
+
+            tx.commit().await?;
+            debug!(
+                "Key rotation batch committed ({} migrated, {} failed so far)",
+                report.migrated, report.failed
+            );
+        }
+
+        info!(
+            "OAuth session key rotation complete: {} migrated, {} failed",
+            report.migrated, report.failed
+        );
+
+        Ok(report)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // Note: These tests require a database connection
-    // Run with: cargo test --features test-db
+    use crate::test_utils::{seed_user, test_db};
+
+    fn token(access_token: &str) -> OAuthTokenData {
+        OAuthTokenData {
+            access_token: access_token.to_string(),
+            token_type: "Bearer".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            id_token: None,
+            expires_in: Some(3600),
+            expires_at: 4_000_000_000,
+            issued_at: 1_700_000_000,
+            scope: None,
+            refreshable: true,
+        }
+    }
 
     #[tokio::test]
-    #[ignore] // Requires database
+    #[ignore] // Requires TEST_DATABASE_URL
     async fn test_oauth_session_crud() {
-        // This would need database setup
-        // Left as example for integration tests
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        let service = OAuthSessionService::new(db, &["test-key".to_string()]).unwrap();
+
+        let session = service
+            .create_session(&user.id, "google", token("first"))
+            .await
+            .unwrap();
+        let fetched = service
+            .get_session_by_provider_and_user_id("google", &user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.id, session.id);
+        assert_eq!(fetched.token.access_token, "first");
+
+        let updated = service
+            .update_session_by_id(&session.id, token("second"))
+            .await
+            .unwrap();
+        assert_eq!(updated.token.access_token, "second");
+
+        assert!(service.delete_session_by_id(&session.id).await.unwrap());
+        assert!(service
+            .get_session_by_id(&session.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_undecryptable_session_is_treated_as_missing() {
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        let old_key = OAuthSessionService::new(db.clone(), &["old-key".to_string()]).unwrap();
+        let session = old_key
+            .create_session(&user.id, "google", token("first"))
+            .await
+            .unwrap();
+
+        // The key the token was encrypted with is gone
+        let service = OAuthSessionService::new(db, &["new-key".to_string()]).unwrap();
+        assert!(service
+            .get_valid_token(&user.id, "google")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(service
+            .get_session_by_id(&session.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // The session was deleted, so even the old key no longer finds it
+        assert!(old_key
+            .get_session_by_id(&session.id)
+            .await
+            .unwrap()
+            .is_none());
     }
 }
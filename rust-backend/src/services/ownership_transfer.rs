@@ -0,0 +1,415 @@
+/// Admin-driven ownership transfer, e.g. when someone leaves the organization
+///
+/// Moves one resource, or everything a user owns, to another user in a single
+/// transaction. The new owner is dropped from the resources' `access_control` user
+/// lists, where ownership now covers them; the previous owner isn't added, so they keep
+/// only the access the resource grants everyone else.
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::{Postgres, Transaction};
+use std::collections::BTreeMap;
+
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+use crate::services::audit::AuditService;
+use crate::services::UserService;
+
+pub const TRANSFER_ACTION: &str = "ownership.transferred";
+
+/// A transferable resource type and how its table is laid out
+struct ResourceTable {
+    name: &'static str,
+    table: &'static str,
+    key: &'static str,
+    has_access_control: bool,
+}
+
+const RESOURCES: &[ResourceTable] = &[
+    ResourceTable {
+        name: "knowledge",
+        table: "knowledge",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "model",
+        table: "model",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "prompt",
+        table: "prompt",
+        key: "command",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "tool",
+        table: "tool",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "function",
+        table: "function",
+        key: "id",
+        has_access_control: false,
+    },
+    ResourceTable {
+        name: "note",
+        table: "note",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "file",
+        table: "file",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "channel",
+        table: "channel",
+        key: "id",
+        has_access_control: true,
+    },
+    ResourceTable {
+        name: "chat",
+        table: "chat",
+        key: "id",
+        has_access_control: false,
+    },
+];
+
+/// Result of moving one resource
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceTransfer {
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub id: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Result of moving everything a user owns; counts by resource type
+#[derive(Debug, Clone, Serialize)]
+pub struct UserTransfer {
+    pub from: String,
+    pub to: String,
+    pub transferred: BTreeMap<&'static str, u64>,
+}
+
+pub struct OwnershipTransfer<'a> {
+    db: &'a Database,
+}
+
+impl<'a> OwnershipTransfer<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        OwnershipTransfer { db }
+    }
+
+    /// Make `to` the owner of the `resource_type` resource `id`
+    pub async fn transfer_resource(
+        &self,
+        admin: &User,
+        resource_type: &str,
+        id: &str,
+        to: &str,
+    ) -> AppResult<ResourceTransfer> {
+        let resource = RESOURCES
+            .iter()
+            .find(|r| r.name == resource_type)
+            .ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "Unknown resource type '{}': expected one of {}",
+                    resource_type,
+                    RESOURCES
+                        .iter()
+                        .map(|r| r.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+        let target = self.target_user(to).await?;
+
+        let mut tx = self.db.pool.begin().await?;
+        let from: String = sqlx::query_scalar(&format!(
+            "SELECT user_id FROM {} WHERE {} = $1 FOR UPDATE",
+            resource.table, resource.key
+        ))
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("{} not found", resource.name)))?;
+        if from == target.id {
+            return Err(AppError::BadRequest(
+                "The user already owns this resource".to_string(),
+            ));
+        }
+
+        if resource.has_access_control {
+            let access_control: Option<Value> = sqlx::query_scalar(&format!(
+                "SELECT access_control FROM {} WHERE {} = $1",
+                resource.table, resource.key
+            ))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+            set_owner(&mut tx, resource, id, &target.id, access_control).await?;
+        } else {
+            set_owner(&mut tx, resource, id, &target.id, None).await?;
+        }
+        if resource.name == "chat" {
+            // The chat's folder stays with the previous owner
+            sqlx::query("UPDATE chat SET folder_id = NULL WHERE id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        AuditService::new(self.db)
+            .record(
+                &admin.id,
+                TRANSFER_ACTION,
+                resource.name,
+                id,
+                Some(json!({ "from": from, "to": target.id })),
+            )
+            .await?;
+        tracing::info!(
+            "{} transferred {} {} from {} to {}",
+            admin.id,
+            resource.name,
+            id,
+            from,
+            target.id
+        );
+
+        Ok(ResourceTransfer {
+            resource_type: resource.name.to_string(),
+            id: id.to_string(),
+            from,
+            to: target.id,
+        })
+    }
+
+    /// Transfer everything `from` owns to `to`
+    pub async fn transfer_user(
+        &self,
+        admin: &User,
+        from: &str,
+        to: &str,
+    ) -> AppResult<UserTransfer> {
+        let source = UserService::new(self.db)
+            .get_user_by_id(from)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+        let target = self.target_user(to).await?;
+        if source.id == target.id {
+            return Err(AppError::BadRequest(
+                "Can't transfer resources to the same user".to_string(),
+            ));
+        }
+
+        let mut tx = self.db.pool.begin().await?;
+        let mut transferred = BTreeMap::new();
+        for resource in RESOURCES {
+            let rows: Vec<(String, Option<Value>)> = if resource.has_access_control {
+                sqlx::query_as(&format!(
+                    "SELECT {}, access_control FROM {} WHERE user_id = $1 FOR UPDATE",
+                    resource.key, resource.table
+                ))
+                .bind(&source.id)
+                .fetch_all(&mut *tx)
+                .await?
+            } else {
+                sqlx::query_scalar::<_, String>(&format!(
+                    "SELECT {} FROM {} WHERE user_id = $1 FOR UPDATE",
+                    resource.key, resource.table
+                ))
+                .bind(&source.id)
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|id| (id, None))
+                .collect()
+            };
+            for (id, access_control) in &rows {
+                set_owner(&mut tx, resource, id, &target.id, access_control.clone()).await?;
+            }
+            transferred.insert(resource.name, rows.len() as u64);
+        }
+        // Folders only move with the chats in them
+        let folders = sqlx::query("UPDATE folder SET user_id = $1 WHERE user_id = $2")
+            .bind(&target.id)
+            .bind(&source.id)
+            .execute(&mut *tx)
+            .await?;
+        transferred.insert("folder", folders.rows_affected());
+        tx.commit().await?;
+
+        AuditService::new(self.db)
+            .record(
+                &admin.id,
+                TRANSFER_ACTION,
+                "user",
+                &source.id,
+                Some(json!({ "to": target.id, "transferred": transferred })),
+            )
+            .await?;
+        tracing::info!(
+            "{} transferred all resources of {} to {}",
+            admin.id,
+            source.id,
+            target.id
+        );
+
+        Ok(UserTransfer {
+            from: source.id,
+            to: target.id,
+            transferred,
+        })
+    }
+
+    async fn target_user(&self, to: &str) -> AppResult<User> {
+        UserService::new(self.db)
+            .get_user_by_id(to)
+            .await?
+            .ok_or_else(|| AppError::BadRequest(format!("Target user '{}' not found", to)))
+    }
+}
+
+async fn set_owner(
+    tx: &mut Transaction<'_, Postgres>,
+    resource: &ResourceTable,
+    id: &str,
+    owner: &str,
+    access_control: Option<Value>,
+) -> AppResult<()> {
+    if resource.has_access_control {
+        sqlx::query(&format!(
+            "UPDATE {} SET user_id = $1, access_control = $2 WHERE {} = $3",
+            resource.table, resource.key
+        ))
+        .bind(owner)
+        .bind(access_control.map(|ac| without_user(ac, owner)))
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    } else {
+        sqlx::query(&format!(
+            "UPDATE {} SET user_id = $1 WHERE {} = $2",
+            resource.table, resource.key
+        ))
+        .bind(owner)
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// `access_control` with `user_id` removed from every `user_ids` list
+fn without_user(mut access_control: Value, user_id: &str) -> Value {
+    if let Some(access) = access_control.as_object_mut() {
+        for rule in access.values_mut() {
+            if let Some(user_ids) = rule.get_mut("user_ids").and_then(Value::as_array_mut) {
+                user_ids.retain(|id| id.as_str() != Some(user_id));
+            }
+        }
+    }
+    access_control
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::knowledge::KnowledgeService;
+    use crate::test_utils::{seed_user, test_db};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_without_user_drops_only_that_user() {
+        let access_control = json!({
+            "read": {"group_ids": ["g"], "user_ids": ["new", "other"]},
+            "write": {"group_ids": [], "user_ids": ["new"]},
+        });
+        assert_eq!(
+            without_user(access_control, "new"),
+            json!({
+                "read": {"group_ids": ["g"], "user_ids": ["other"]},
+                "write": {"group_ids": [], "user_ids": []},
+            })
+        );
+        assert_eq!(without_user(json!({}), "new"), json!({}));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_transferred_knowledge_has_new_owner() {
+        let db = test_db().await;
+        let admin = seed_user(&db, "admin").await;
+        let old = seed_user(&db, "user").await;
+        let new = seed_user(&db, "user").await;
+        let knowledge = KnowledgeService::new(&db);
+        let id = uuid::Uuid::new_v4().to_string();
+        knowledge
+            .create_knowledge_with_access_control(
+                &id,
+                &old.id,
+                "Handbook",
+                None,
+                None,
+                Some(json!({"read": {"group_ids": [], "user_ids": [new.id]}})),
+            )
+            .await
+            .unwrap();
+        let no_groups = HashSet::new();
+        assert!(knowledge
+            .check_access_by_user_id(&id, &old.id, "write", &no_groups)
+            .await
+            .unwrap());
+
+        let transfers = OwnershipTransfer::new(&db);
+        assert!(matches!(
+            transfers
+                .transfer_user(&admin, &old.id, "no-such-user")
+                .await,
+            Err(AppError::BadRequest(_))
+        ));
+
+        let result = transfers
+            .transfer_user(&admin, &old.id, &new.id)
+            .await
+            .unwrap();
+        assert_eq!(result.transferred["knowledge"], 1);
+
+        let moved = knowledge.get_knowledge_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(moved.user_id, new.id);
+        assert_eq!(
+            moved.access_control,
+            Some(json!({"read": {"group_ids": [], "user_ids": []}}))
+        );
+        for access in ["read", "write"] {
+            assert!(!knowledge
+                .check_access_by_user_id(&id, &old.id, access, &no_groups)
+                .await
+                .unwrap());
+        }
+        assert!(knowledge
+            .check_access_by_user_id(&id, &new.id, "write", &no_groups)
+            .await
+            .unwrap());
+
+        // A single resource can go back
+        let back = transfers
+            .transfer_resource(&admin, "knowledge", &id, &old.id)
+            .await
+            .unwrap();
+        assert_eq!(back.from, new.id);
+        let moved = knowledge.get_knowledge_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(moved.user_id, old.id);
+    }
+}
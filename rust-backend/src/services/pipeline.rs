@@ -80,7 +80,7 @@ pub async fn process_pipeline_inlet_filter(
     }
 
     let config = state.config.read().unwrap();
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     // Create user object
     let user_obj = serde_json::json!({
@@ -210,7 +210,7 @@ pub async fn process_pipeline_outlet_filter(
     }
 
     let config = state.config.read().unwrap();
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client();
 
     // Create user object
     let user_obj = serde_json::json!({
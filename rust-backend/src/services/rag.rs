@@ -34,7 +34,7 @@ pub struct RAGService {
 impl RAGService {
     pub async fn new(config: Config) -> AppResult<Self> {
         Ok(RAGService {
-            client: Client::new(),
+            client: crate::utils::http::client(),
             config,
         })
     }
@@ -0,0 +1,255 @@
+/// Chunk text stored at ingest time for citations
+///
+/// Rows are keyed by the knowledge base's base collection, file and chunk index, the
+/// same parts the vector ids (`{file_id}-chunk-{index}`) are built from.
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use sqlx::{Postgres, QueryBuilder, Row};
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::retrieval::search::ChunkTextStore;
+use crate::retrieval::VectorError;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Vector id of a file's chunk
+pub fn chunk_id(file_id: &str, chunk_index: usize) -> String {
+    format!("{}-chunk-{}", file_id, chunk_index)
+}
+
+/// File id and chunk index of a vector id built by [`chunk_id`]
+pub fn parse_chunk_id(id: &str) -> Option<(&str, i64)> {
+    let (file_id, chunk_index) = id.rsplit_once("-chunk-")?;
+    Some((file_id, chunk_index.parse().ok()?))
+}
+
+pub struct RagChunkService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> RagChunkService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        RagChunkService { db }
+    }
+
+    /// Store `(chunk_index, text)` pairs of a file, replacing earlier text for the same chunks
+    pub async fn save_chunks(
+        &self,
+        collection: &str,
+        file_id: &str,
+        chunks: &[(usize, &str)],
+    ) -> AppResult<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        let now = current_timestamp_seconds();
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO rag_chunk (collection, file_id, chunk_index, text, created_at) ",
+        );
+        builder.push_values(chunks, |mut row, (chunk_index, text)| {
+            row.push_bind(collection)
+                .push_bind(file_id)
+                .push_bind(*chunk_index as i64)
+                .push_bind(*text)
+                .push_bind(now);
+        });
+        builder.push(
+            " ON CONFLICT (collection, file_id, chunk_index) DO UPDATE SET \
+             text = EXCLUDED.text, created_at = EXCLUDED.created_at",
+        );
+        builder.build().execute(&self.db.pool).await?;
+
+        Ok(())
+    }
+
+    /// Text of the chunks with the given vector ids, keyed by id
+    ///
+    /// Ids that aren't chunk ids, or whose text wasn't stored, are left out.
+    pub async fn get_chunk_texts(
+        &self,
+        collection: &str,
+        ids: &[String],
+    ) -> AppResult<HashMap<String, String>> {
+        let keys: Vec<(&str, i64)> = ids.iter().filter_map(|id| parse_chunk_id(id)).collect();
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "SELECT file_id, chunk_index, text FROM rag_chunk WHERE collection = ",
+        );
+        builder.push_bind(collection);
+        builder.push(" AND (file_id, chunk_index) IN ");
+        builder.push_tuples(keys, |mut tuple, (file_id, chunk_index)| {
+            tuple.push_bind(file_id).push_bind(chunk_index);
+        });
+        let rows = builder.build().fetch_all(&self.db.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let file_id: String = row.get("file_id");
+                let chunk_index: i64 = row.get("chunk_index");
+                (chunk_id(&file_id, chunk_index as usize), row.get("text"))
+            })
+            .collect())
+    }
+
+    pub async fn delete_file_chunks(&self, collection: &str, file_id: &str) -> AppResult<()> {
+        sqlx::query("DELETE FROM rag_chunk WHERE collection = $1 AND file_id = $2")
+            .bind(collection)
+            .bind(file_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Drop a collection's chunks `batch_size` rows at a time
+    ///
+    /// Each batch is its own statement, so no lock is held across the whole delete and a
+    /// rerun after a failure picks up with the chunks still left. Returns the number of
+    /// batches that removed rows.
+    pub async fn delete_collection_chunks(
+        &self,
+        collection: &str,
+        batch_size: usize,
+    ) -> AppResult<usize> {
+        let mut batches = 0;
+        let mut deleted = 0;
+        loop {
+            let rows = sqlx::query(
+                "DELETE FROM rag_chunk WHERE ctid IN \
+                 (SELECT ctid FROM rag_chunk WHERE collection = $1 LIMIT $2)",
+            )
+            .bind(collection)
+            .bind(batch_size as i64)
+            .execute(&self.db.pool)
+            .await?
+            .rows_affected();
+            if rows == 0 {
+                break;
+            }
+            batches += 1;
+            deleted += rows;
+            tracing::info!(
+                "Deleted {} chunks of collection {} (batch {}, {} so far)",
+                rows,
+                collection,
+                batches,
+                deleted
+            );
+            if rows < batch_size as u64 {
+                break;
+            }
+        }
+
+        Ok(batches)
+    }
+
+    /// Drop a file's chunks from every collection
+    pub async fn delete_chunks_by_file_id(&self, file_id: &str) -> AppResult<()> {
+        sqlx::query("DELETE FROM rag_chunk WHERE file_id = $1")
+            .bind(file_id)
+            .execute(&self.db.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChunkTextStore for RagChunkService<'_> {
+    async fn chunk_texts(
+        &self,
+        collection: &str,
+        ids: &[String],
+    ) -> Result<HashMap<String, String>, VectorError> {
+        self.get_chunk_texts(collection, ids)
+            .await
+            .map_err(|e| VectorError::OperationError(format!("Failed to load chunk text: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_id_round_trip() {
+        let id = chunk_id("3f2a-chunk-notes", 12);
+        assert_eq!(parse_chunk_id(&id), Some(("3f2a-chunk-notes", 12)));
+        assert_eq!(parse_chunk_id("doc-1"), None);
+        assert_eq!(parse_chunk_id("f1-chunk-x"), None);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_saved_chunks_fetched_by_vector_id() {
+        let db = crate::test_utils::test_db().await;
+        let service = RagChunkService::new(&db);
+
+        service
+            .save_chunks("kb1", "f1", &[(0, "first"), (1, "second")])
+            .await
+            .unwrap();
+        // Re-ingesting a chunk replaces its text
+        service
+            .save_chunks("kb1", "f1", &[(1, "second, edited")])
+            .await
+            .unwrap();
+        service
+            .save_chunks("kb2", "f1", &[(0, "elsewhere")])
+            .await
+            .unwrap();
+
+        let ids = vec![chunk_id("f1", 1), chunk_id("f1", 0), chunk_id("f1", 7)];
+        let texts = service.get_chunk_texts("kb1", &ids).await.unwrap();
+        assert_eq!(texts.len(), 2);
+        assert_eq!(texts[&chunk_id("f1", 0)], "first");
+        assert_eq!(texts[&chunk_id("f1", 1)], "second, edited");
+
+        service.delete_collection_chunks("kb1", 100).await.unwrap();
+        assert!(service
+            .get_chunk_texts("kb1", &ids)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(service.get_chunk_texts("kb2", &ids).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_large_collection_deleted_in_batches() {
+        let db = crate::test_utils::test_db().await;
+        let service = RagChunkService::new(&db);
+
+        let chunks: Vec<(usize, String)> = (0..25).map(|i| (i, format!("chunk {}", i))).collect();
+        let chunks: Vec<(usize, &str)> = chunks.iter().map(|(i, t)| (*i, t.as_str())).collect();
+        service.save_chunks("kb1", "f1", &chunks).await.unwrap();
+        service
+            .save_chunks("kb2", "f1", &chunks[..3])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            service.delete_collection_chunks("kb1", 10).await.unwrap(),
+            3
+        );
+        let ids: Vec<String> = (0..25).map(|i| chunk_id("f1", i)).collect();
+        assert!(service
+            .get_chunk_texts("kb1", &ids)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(service.get_chunk_texts("kb2", &ids).await.unwrap().len(), 3);
+
+        // Nothing left to delete
+        assert_eq!(
+            service.delete_collection_chunks("kb1", 10).await.unwrap(),
+            0
+        );
+    }
+}
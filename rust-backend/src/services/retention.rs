@@ -0,0 +1,322 @@
+/// Age-based purging of chats and audit entries
+///
+/// Rows are removed in small batches, each its own statement, so a large backlog never
+/// holds locks on the chat or audit tables for long.
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::Row;
+
+use crate::config::MutableConfig;
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Rows deleted per statement
+const BATCH_SIZE: i64 = 500;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Rows removed by a purge, or that would be removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeCounts {
+    pub chats: i64,
+    pub messages: i64,
+    pub audit_logs: i64,
+}
+
+/// Timestamp (seconds) before which rows are purged; `None` keeps them forever
+pub fn cutoff(retention_days: u32, now: i64) -> Option<i64> {
+    (retention_days > 0).then(|| now - i64::from(retention_days) * SECONDS_PER_DAY)
+}
+
+pub struct RetentionService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> RetentionService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        RetentionService { db }
+    }
+
+    /// Count what a purge with these limits would remove, without deleting anything
+    pub async fn preview(&self, chat_days: u32, audit_days: u32) -> AppResult<PurgeCounts> {
+        let now = current_timestamp_seconds();
+        let mut counts = PurgeCounts::default();
+
+        if let Some(before) = cutoff(chat_days, now) {
+            let row = sqlx::query(
+                r#"
+                SELECT
+                    COUNT(*) AS chats,
+                    (SELECT COUNT(*) FROM message m JOIN chat c ON m.chat_id = c.id
+                     WHERE c.updated_at < $1 AND c.user_id NOT LIKE 'shared-%') AS messages
+                FROM chat
+                WHERE updated_at < $1 AND user_id NOT LIKE 'shared-%'
+                "#,
+            )
+            .bind(before)
+            .fetch_one(&self.db.pool)
+            .await?;
+            counts.chats = row.try_get("chats")?;
+            counts.messages = row.try_get("messages")?;
+        }
+
+        if let Some(before) = cutoff(audit_days, now) {
+            counts.audit_logs =
+                sqlx::query("SELECT COUNT(*) AS count FROM audit_log WHERE created_at < $1")
+                    .bind(before)
+                    .fetch_one(&self.db.pool)
+                    .await?
+                    .try_get("count")?;
+        }
+
+        Ok(counts)
+    }
+
+    /// Delete chats not updated within `chat_days` and audit entries older than `audit_days`
+    ///
+    /// A purged chat takes its messages and any shared snapshot of it along.
+    pub async fn purge(&self, chat_days: u32, audit_days: u32) -> AppResult<PurgeCounts> {
+        let now = current_timestamp_seconds();
+        let mut counts = PurgeCounts::default();
+
+        if let Some(before) = cutoff(chat_days, now) {
+            loop {
+                let (chats, messages) = self.purge_chat_batch(before).await?;
+                counts.chats += chats;
+                counts.messages += messages;
+                if chats < BATCH_SIZE {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        if let Some(before) = cutoff(audit_days, now) {
+            loop {
+                let deleted = self.purge_audit_batch(before).await?;
+                counts.audit_logs += deleted;
+                if deleted < BATCH_SIZE {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        }
+
+        Ok(counts)
+    }
+
+    async fn purge_chat_batch(&self, before: i64) -> AppResult<(i64, i64)> {
+        let row = sqlx::query(
+            r#"
+            WITH doomed AS (
+                SELECT id FROM chat
+                WHERE updated_at < $1 AND user_id NOT LIKE 'shared-%'
+                ORDER BY updated_at
+                LIMIT $2
+            ),
+            shared AS (
+                DELETE FROM chat WHERE user_id IN (SELECT 'shared-' || id FROM doomed)
+            ),
+            messages AS (
+                DELETE FROM message WHERE chat_id IN (SELECT id FROM doomed)
+                RETURNING 1
+            ),
+            chats AS (
+                DELETE FROM chat WHERE id IN (SELECT id FROM doomed)
+                RETURNING 1
+            )
+            SELECT
+                (SELECT COUNT(*) FROM chats) AS chats,
+                (SELECT COUNT(*) FROM messages) AS messages
+            "#,
+        )
+        .bind(before)
+        .bind(BATCH_SIZE)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok((row.try_get("chats")?, row.try_get("messages")?))
+    }
+
+    async fn purge_audit_batch(&self, before: i64) -> AppResult<i64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM audit_log
+            WHERE id IN (
+                SELECT id FROM audit_log WHERE created_at < $1 ORDER BY created_at LIMIT $2
+            )
+            "#,
+        )
+        .bind(before)
+        .bind(BATCH_SIZE)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(result.rows_affected() as i64)
+    }
+}
+
+/// Start the periodic purge; does nothing when RETENTION_PURGE_INTERVAL is 0
+///
+/// Retention days are re-read every sweep, so a config reload applies without restart.
+pub fn spawn(db: Database, config: MutableConfig) {
+    let interval = config.read().unwrap().retention_purge_interval;
+    if interval == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            let (chat_days, audit_days) = {
+                let config = config.read().unwrap();
+                (config.chat_retention_days, config.audit_retention_days)
+            };
+            if chat_days == 0 && audit_days == 0 {
+                continue;
+            }
+
+            match RetentionService::new(&db)
+                .purge(chat_days, audit_days)
+                .await
+            {
+                Ok(counts) => tracing::info!(
+                    "Retention purge removed {} chats, {} messages, {} audit entries",
+                    counts.chats,
+                    counts.messages,
+                    counts.audit_logs
+                ),
+                Err(e) => tracing::warn!("Retention purge failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{seed_user, test_db};
+
+    #[test]
+    fn test_cutoff() {
+        assert_eq!(cutoff(0, 1_000_000), None);
+        assert_eq!(cutoff(2, 1_000_000), Some(1_000_000 - 2 * 86_400));
+    }
+
+    async fn seed_chat(db: &Database, user_id: &str, id: &str, updated_at: i64) {
+        sqlx::query(
+            r#"
+            INSERT INTO chat (id, user_id, title, chat, created_at, updated_at)
+            VALUES ($1, $2, 'Test', '{}', $3, $3)
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(updated_at)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    async fn seed_audit(db: &Database, actor_id: &str, id: &str, created_at: i64) {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (id, actor_id, action, target_type, target_id, created_at)
+            VALUES ($1, $2, 'test.action', 'test', 'target', $3)
+            "#,
+        )
+        .bind(id)
+        .bind(actor_id)
+        .bind(created_at)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+    }
+
+    async fn ids(db: &Database, table: &str) -> Vec<String> {
+        sqlx::query(&format!("SELECT id FROM {} ORDER BY id", table))
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_rows_past_threshold_purged() {
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        let now = current_timestamp_seconds();
+        let old = now - 31 * SECONDS_PER_DAY;
+
+        seed_chat(&db, &user.id, "chat-old", old).await;
+        sqlx::query(
+            r#"
+            INSERT INTO message (id, chat_id, user_id, content, role, created_at, updated_at)
+            VALUES ('msg-old', 'chat-old', $1, 'hi', 'user', 0, 0)
+            "#,
+        )
+        .bind(&user.id)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        seed_chat(&db, &user.id, "chat-new", now).await;
+        seed_audit(&db, &user.id, "audit-old", old).await;
+        seed_audit(&db, &user.id, "audit-new", now).await;
+
+        let service = RetentionService::new(&db);
+        let expected = PurgeCounts {
+            chats: 1,
+            messages: 1,
+            audit_logs: 1,
+        };
+        assert_eq!(service.preview(30, 30).await.unwrap(), expected);
+        assert_eq!(service.purge(30, 30).await.unwrap(), expected);
+
+        assert_eq!(ids(&db, "chat").await, vec!["chat-new"]);
+        assert_eq!(ids(&db, "message").await, Vec::<String>::new());
+        assert_eq!(ids(&db, "audit_log").await, vec!["audit-new"]);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_dry_run_reports_without_deleting() {
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        let now = current_timestamp_seconds();
+        let old = now - 10 * SECONDS_PER_DAY;
+        let chat_id = format!("chat-{}", uuid::Uuid::new_v4());
+        seed_chat(&db, &user.id, &chat_id, old).await;
+        let chats_before = ids(&db, "chat").await;
+        let audit_before = ids(&db, "audit_log").await;
+
+        let service = RetentionService::new(&db);
+        let counts = service.preview(7, 0).await.unwrap();
+        assert!(counts.chats >= 1);
+        assert_eq!(counts.audit_logs, 0);
+        assert_eq!(ids(&db, "chat").await, chats_before);
+        assert_eq!(ids(&db, "audit_log").await, audit_before);
+
+        // The dry run reported exactly what the purge then removes
+        assert_eq!(service.purge(7, 0).await.unwrap(), counts);
+        assert!(!ids(&db, "chat").await.contains(&chat_id));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_zero_days_keeps_everything() {
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        seed_chat(&db, &user.id, "chat-ancient", 1).await;
+        seed_audit(&db, &user.id, "audit-ancient", 1).await;
+
+        let counts = RetentionService::new(&db).purge(0, 0).await.unwrap();
+        assert_eq!(counts, PurgeCounts::default());
+        assert_eq!(ids(&db, "chat").await, vec!["chat-ancient"]);
+    }
+}
@@ -37,7 +37,7 @@ pub struct SandboxExecutorClient {
 
 impl SandboxExecutorClient {
     pub fn new(base_url: String) -> Self {
-        let client = Client::builder()
+        let client = crate::utils::http::builder()
             .timeout(Duration::from_secs(300)) // 5 minutes max
             .build()
             .expect("Failed to create HTTP client");
@@ -0,0 +1,628 @@
+/// Startup check of the live database schema against what the queries expect
+///
+/// Migrations skip statements that fail with "already exists"/"does not exist", and
+/// databases shared with the Python backend may predate some of them, so a column can
+/// be missing or have another type without anything failing until the first query
+/// that touches it. Extra tables and columns are fine and not reported.
+use std::collections::HashMap;
+use std::fmt;
+
+use sqlx::Row;
+
+use crate::db::Database;
+use crate::error::AppResult;
+
+/// Column types as far as the queries care
+///
+/// VARCHAR and TEXT both decode to `String`, so they are not told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    Jsonb,
+    BigInt,
+    Integer,
+    Boolean,
+    Date,
+    Timestamp,
+}
+
+impl ColumnType {
+    /// Whether `data_type` from `information_schema.columns` is this type
+    fn matches(self, data_type: &str) -> bool {
+        match self {
+            ColumnType::Text => matches!(data_type, "text" | "character varying"),
+            ColumnType::Jsonb => data_type == "jsonb",
+            ColumnType::BigInt => data_type == "bigint",
+            ColumnType::Integer => data_type == "integer",
+            ColumnType::Boolean => data_type == "boolean",
+            ColumnType::Date => data_type == "date",
+            ColumnType::Timestamp => data_type.starts_with("timestamp"),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ColumnType::Text => "text",
+            ColumnType::Jsonb => "jsonb",
+            ColumnType::BigInt => "bigint",
+            ColumnType::Integer => "integer",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::Timestamp => "timestamp",
+        }
+    }
+}
+
+pub struct ExpectedTable {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnType)],
+}
+
+use ColumnType::{BigInt, Boolean, Date, Integer, Jsonb, Text, Timestamp};
+
+/// Tables and columns the queries rely on, as left by the migrations
+pub const EXPECTED_SCHEMA: &[ExpectedTable] = &[
+    ExpectedTable {
+        name: "user",
+        columns: &[
+            ("id", Text),
+            ("name", Text),
+            ("email", Text),
+            ("username", Text),
+            ("role", Text),
+            ("profile_image_url", Text),
+            ("bio", Text),
+            ("gender", Text),
+            ("date_of_birth", Date),
+            ("info", Jsonb),
+            ("settings", Jsonb),
+            ("api_key", Text),
+            ("oauth_sub", Text),
+            ("last_active_at", BigInt),
+            ("updated_at", BigInt),
+            ("created_at", BigInt),
+            ("deleted_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "auth",
+        columns: &[
+            ("id", Text),
+            ("email", Text),
+            ("password", Text),
+            ("active", Boolean),
+        ],
+    },
+    ExpectedTable {
+        name: "chat",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("title", Text),
+            ("chat", Jsonb),
+            ("folder_id", Text),
+            ("archived", Boolean),
+            ("pinned", Boolean),
+            ("share_id", Text),
+            ("meta", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "message",
+        columns: &[
+            ("id", Text),
+            ("chat_id", Text),
+            ("channel_id", Text),
+            ("user_id", Text),
+            ("reply_to_id", Text),
+            ("parent_id", Text),
+            ("content", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "message_reaction",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("message_id", Text),
+            ("name", Text),
+            ("created_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "model",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("base_model_id", Text),
+            ("name", Text),
+            ("params", Jsonb),
+            ("meta", Jsonb),
+            ("access_control", Jsonb),
+            ("is_active", Boolean),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "prompt",
+        columns: &[
+            ("command", Text),
+            ("user_id", Text),
+            ("title", Text),
+            ("content", Text),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "tool",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("content", Text),
+            ("specs", Jsonb),
+            ("meta", Jsonb),
+            ("valves", Jsonb),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "function",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("type", Text),
+            ("content", Text),
+            ("meta", Jsonb),
+            ("valves", Jsonb),
+            ("is_active", Boolean),
+            ("is_global", Boolean),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "file",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("hash", Text),
+            ("filename", Text),
+            ("path", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "folder",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("parent_id", Text),
+            ("is_expanded", Boolean),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "knowledge",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("description", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "memory",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("content", Text),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "note",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("title", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "group",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("name", Text),
+            ("description", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("permissions", Jsonb),
+            ("user_ids", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "channel",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("type", Text),
+            ("name", Text),
+            ("description", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("access_control", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "channel_member",
+        columns: &[
+            ("id", Text),
+            ("channel_id", Text),
+            ("user_id", Text),
+            ("created_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "tag",
+        columns: &[
+            ("id", Text),
+            ("name", Text),
+            ("user_id", Text),
+            ("data", Jsonb),
+        ],
+    },
+    ExpectedTable {
+        name: "feedback",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("version", BigInt),
+            ("type", Text),
+            ("data", Jsonb),
+            ("meta", Jsonb),
+            ("snapshot", Jsonb),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "config",
+        columns: &[
+            ("id", Integer),
+            ("data", Jsonb),
+            ("version", Integer),
+            ("created_at", Timestamp),
+            ("updated_at", Timestamp),
+        ],
+    },
+    ExpectedTable {
+        name: "oauth_session",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("provider", Text),
+            ("token", Text),
+            ("expires_at", BigInt),
+            ("created_at", BigInt),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "usage",
+        columns: &[
+            ("id", Text),
+            ("user_id", Text),
+            ("model", Text),
+            ("prompt_tokens", BigInt),
+            ("completion_tokens", BigInt),
+            ("total_tokens", BigInt),
+            ("estimated", Boolean),
+            ("created_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "session_store",
+        columns: &[("key", Text), ("value", Text), ("expires_at", BigInt)],
+    },
+    ExpectedTable {
+        name: "audit_log",
+        columns: &[
+            ("id", Text),
+            ("actor_id", Text),
+            ("action", Text),
+            ("target_type", Text),
+            ("target_id", Text),
+            ("data", Jsonb),
+            ("created_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "file_ingest_progress",
+        columns: &[
+            ("file_id", Text),
+            ("knowledge_id", Text),
+            ("fingerprint", Text),
+            ("chunks_total", BigInt),
+            ("chunks_done", BigInt),
+            ("status", Text),
+            ("error", Text),
+            ("updated_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "rag_chunk",
+        columns: &[
+            ("collection", Text),
+            ("file_id", Text),
+            ("chunk_index", BigInt),
+            ("text", Text),
+            ("created_at", BigInt),
+        ],
+    },
+    ExpectedTable {
+        name: "daily_request_count",
+        columns: &[
+            ("user_id", Text),
+            ("counter", Text),
+            ("day", BigInt),
+            ("count", BigInt),
+        ],
+    },
+];
+
+/// One difference between the expected and actual schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaDrift {
+    MissingTable(String),
+    MissingColumn {
+        table: String,
+        column: String,
+    },
+    WrongType {
+        table: String,
+        column: String,
+        expected: ColumnType,
+        actual: String,
+    },
+}
+
+impl fmt::Display for SchemaDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaDrift::MissingTable(table) => write!(f, "table \"{}\" is missing", table),
+            SchemaDrift::MissingColumn { table, column } => {
+                write!(f, "column \"{}\".{} is missing", table, column)
+            }
+            SchemaDrift::WrongType {
+                table,
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "column \"{}\".{} is {}, expected {}",
+                table,
+                column,
+                actual,
+                expected.as_str()
+            ),
+        }
+    }
+}
+
+/// Actual columns, as table -> column -> `information_schema` data type
+pub type ActualSchema = HashMap<String, HashMap<String, String>>;
+
+/// Every expected table or column that is missing or has another type
+pub fn compare(expected: &[ExpectedTable], actual: &ActualSchema) -> Vec<SchemaDrift> {
+    let mut drift = Vec::new();
+    for table in expected {
+        let Some(columns) = actual.get(table.name) else {
+            drift.push(SchemaDrift::MissingTable(table.name.to_string()));
+            continue;
+        };
+        for (column, expected_type) in table.columns {
+            match columns.get(*column) {
+                None => drift.push(SchemaDrift::MissingColumn {
+                    table: table.name.to_string(),
+                    column: column.to_string(),
+                }),
+                Some(actual_type) if !expected_type.matches(actual_type) => {
+                    drift.push(SchemaDrift::WrongType {
+                        table: table.name.to_string(),
+                        column: column.to_string(),
+                        expected: *expected_type,
+                        actual: actual_type.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    drift
+}
+
+/// Columns of every table in the connection's current schema
+pub async fn actual_schema(db: &Database) -> AppResult<ActualSchema> {
+    let rows = sqlx::query(
+        "SELECT table_name::text, column_name::text, data_type::text
+         FROM information_schema.columns
+         WHERE table_schema = current_schema()",
+    )
+    .fetch_all(db.pool())
+    .await?;
+
+    let mut schema = ActualSchema::new();
+    for row in rows {
+        schema
+            .entry(row.try_get("table_name")?)
+            .or_default()
+            .insert(row.try_get("column_name")?, row.try_get("data_type")?);
+    }
+    Ok(schema)
+}
+
+/// Compare the live schema with [`EXPECTED_SCHEMA`], logging every difference
+///
+/// With `strict` any drift fails startup; otherwise it is only logged.
+pub async fn verify(db: &Database, strict: bool) -> anyhow::Result<()> {
+    let drift = compare(EXPECTED_SCHEMA, &actual_schema(db).await?);
+    if drift.is_empty() {
+        tracing::info!("Database schema matches the expected schema");
+        return Ok(());
+    }
+
+    for difference in &drift {
+        tracing::warn!("Schema drift: {}", difference);
+    }
+    if strict {
+        anyhow::bail!(
+            "Database schema differs from what this version expects ({} difference(s)): {}. \
+             Fix the schema or set STRICT_SCHEMA_CHECK=false to start anyway",
+            drift.len(),
+            drift
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_db;
+
+    fn schema(tables: &[(&str, &[(&str, &str)])]) -> ActualSchema {
+        tables
+            .iter()
+            .map(|(table, columns)| {
+                let columns = columns
+                    .iter()
+                    .map(|(column, data_type)| (column.to_string(), data_type.to_string()))
+                    .collect();
+                (table.to_string(), columns)
+            })
+            .collect()
+    }
+
+    const SESSION_STORE: &[ExpectedTable] = &[ExpectedTable {
+        name: "session_store",
+        columns: &[("key", Text), ("value", Text), ("expires_at", BigInt)],
+    }];
+
+    #[test]
+    fn test_missing_column_flagged() {
+        let actual = schema(&[(
+            "session_store",
+            &[("key", "text"), ("value", "character varying")],
+        )]);
+
+        assert_eq!(
+            compare(SESSION_STORE, &actual),
+            vec![SchemaDrift::MissingColumn {
+                table: "session_store".to_string(),
+                column: "expires_at".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_wrong_type_and_missing_table_flagged() {
+        let actual = schema(&[(
+            "session_store",
+            &[
+                ("key", "text"),
+                ("value", "jsonb"),
+                ("expires_at", "bigint"),
+            ],
+        )]);
+        let drift = compare(SESSION_STORE, &actual);
+        assert_eq!(drift.len(), 1);
+        assert_eq!(
+            drift[0].to_string(),
+            "column \"session_store\".value is jsonb, expected text"
+        );
+
+        let drift = compare(SESSION_STORE, &ActualSchema::new());
+        assert_eq!(
+            drift,
+            vec![SchemaDrift::MissingTable("session_store".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_extra_columns_allowed() {
+        let actual = schema(&[(
+            "session_store",
+            &[
+                ("key", "text"),
+                ("value", "text"),
+                ("expires_at", "bigint"),
+                ("added_later", "text"),
+            ],
+        )]);
+        assert!(compare(SESSION_STORE, &actual).is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_migrated_schema_has_no_drift_until_column_dropped() {
+        let db = test_db().await;
+        assert!(verify(&db, true).await.is_ok());
+
+        sqlx::query("ALTER TABLE session_store DROP COLUMN expires_at")
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        let drift = compare(EXPECTED_SCHEMA, &actual_schema(&db).await.unwrap());
+        assert_eq!(
+            drift,
+            vec![SchemaDrift::MissingColumn {
+                table: "session_store".to_string(),
+                column: "expires_at".to_string(),
+            }]
+        );
+        assert!(verify(&db, true).await.is_err());
+        assert!(verify(&db, false).await.is_ok());
+    }
+}
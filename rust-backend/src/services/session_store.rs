@@ -0,0 +1,310 @@
+/// Short-lived key/value storage for auth flow state
+///
+/// Backs transient OAuth state (and anything else that must survive a hop between
+/// replicas) with either Postgres or Redis, selected by SESSION_STORE. The Redis store
+/// falls back to Postgres while Redis is unreachable.
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::utils::redis_guard::RedisGuard;
+use crate::utils::time::current_timestamp_seconds;
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Store `value` under `key`, replacing any previous value, until `ttl` passes
+    async fn put(&self, key: &str, value: &str, ttl: Duration) -> AppResult<()>;
+
+    /// Read an unexpired value
+    async fn get(&self, key: &str) -> AppResult<Option<String>>;
+
+    /// Read and delete an unexpired value atomically, so it can be consumed only once
+    async fn take(&self, key: &str) -> AppResult<Option<String>>;
+
+    async fn delete(&self, key: &str) -> AppResult<()>;
+
+    /// Backend name for logs
+    fn backend(&self) -> &'static str;
+}
+
+/// Build the store named by SESSION_STORE
+///
+/// Falls back to the database when Redis is selected but not available, so a missing
+/// Redis never breaks sign-in.
+pub fn from_config(
+    config: &Config,
+    db: &Database,
+    redis: Option<&deadpool_redis::Pool>,
+    redis_guard: Arc<RedisGuard>,
+) -> Arc<dyn SessionStore> {
+    match (config.session_store.as_str(), redis) {
+        ("redis", Some(pool)) => Arc::new(RedisSessionStore::new(
+            pool.clone(),
+            redis_guard,
+            Arc::new(DbSessionStore::new(db.clone())),
+        )),
+        ("redis", None) => {
+            tracing::warn!("SESSION_STORE=redis but Redis is not enabled, using the database");
+            Arc::new(DbSessionStore::new(db.clone()))
+        }
+        _ => Arc::new(DbSessionStore::new(db.clone())),
+    }
+}
+
+/// Session store in the `session_store` table
+pub struct DbSessionStore {
+    db: Database,
+}
+
+impl DbSessionStore {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl SessionStore for DbSessionStore {
+    async fn put(&self, key: &str, value: &str, ttl: Duration) -> AppResult<()> {
+        let now = current_timestamp_seconds();
+
+        // Writes are rare (one per login), so expired rows are swept here
+        sqlx::query("DELETE FROM session_store WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.db.pool)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO session_store (key, value, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, expires_at = EXCLUDED.expires_at
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .bind(now + ttl.as_secs() as i64)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> AppResult<Option<String>> {
+        let value = sqlx::query_scalar::<_, String>(
+            "SELECT value FROM session_store WHERE key = $1 AND expires_at > $2",
+        )
+        .bind(key)
+        .bind(current_timestamp_seconds())
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(value)
+    }
+
+    async fn take(&self, key: &str) -> AppResult<Option<String>> {
+        let row = sqlx::query_as::<_, (String, i64)>(
+            "DELETE FROM session_store WHERE key = $1 RETURNING value, expires_at",
+        )
+        .bind(key)
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(row
+            .filter(|(_, expires_at)| *expires_at > current_timestamp_seconds())
+            .map(|(value, _)| value))
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        sqlx::query("DELETE FROM session_store WHERE key = $1")
+            .bind(key)
+            .execute(&self.db.pool)
+            .await?;
+        Ok(())
+    }
+
+    fn backend(&self) -> &'static str {
+        "database"
+    }
+}
+
+/// Session store in Redis, relying on key expiry
+///
+/// While the Redis circuit is open, or when a call fails, `fallback` serves instead.
+/// Reads that miss in Redis also check `fallback`, so state written during an outage
+/// is still found after Redis recovers.
+pub struct RedisSessionStore {
+    pool: deadpool_redis::Pool,
+    prefix: String,
+    guard: Arc<RedisGuard>,
+    fallback: Arc<dyn SessionStore>,
+}
+
+impl RedisSessionStore {
+    pub fn new(
+        pool: deadpool_redis::Pool,
+        guard: Arc<RedisGuard>,
+        fallback: Arc<dyn SessionStore>,
+    ) -> Self {
+        Self {
+            pool,
+            prefix: "open-webui:session".to_string(),
+            guard,
+            fallback,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}:{}", self.prefix, key)
+    }
+
+    async fn connection(&self) -> AppResult<deadpool_redis::Connection> {
+        Ok(self.pool.get().await?)
+    }
+
+    /// `None` when Redis was skipped or failed and the fallback should serve
+    fn tracked<T>(&self, result: AppResult<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.guard.record_success();
+                Some(value)
+            }
+            Err(e) => {
+                self.guard.record_failure(&e);
+                None
+            }
+        }
+    }
+
+    async fn try_put(&self, key: &str, value: &str, ttl: Duration) -> AppResult<()> {
+        let mut conn = self.connection().await?;
+        conn.set_ex::<_, _, ()>(self.key(key), value, ttl.as_secs().max(1))
+            .await
+            .map_err(redis_error)
+    }
+
+    async fn try_get(&self, key: &str) -> AppResult<Option<String>> {
+        let mut conn = self.connection().await?;
+        conn.get(self.key(key)).await.map_err(redis_error)
+    }
+
+    async fn try_take(&self, key: &str) -> AppResult<Option<String>> {
+        let mut conn = self.connection().await?;
+        conn.get_del(self.key(key)).await.map_err(redis_error)
+    }
+
+    async fn try_delete(&self, key: &str) -> AppResult<()> {
+        let mut conn = self.connection().await?;
+        conn.del::<_, ()>(self.key(key)).await.map_err(redis_error)
+    }
+}
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::Redis(e.to_string())
+}
+
+#[async_trait]
+impl SessionStore for RedisSessionStore {
+    async fn put(&self, key: &str, value: &str, ttl: Duration) -> AppResult<()> {
+        if self.guard.allow() && self.tracked(self.try_put(key, value, ttl).await).is_some() {
+            return Ok(());
+        }
+        self.fallback.put(key, value, ttl).await
+    }
+
+    async fn get(&self, key: &str) -> AppResult<Option<String>> {
+        if self.guard.allow() {
+            if let Some(Some(value)) = self.tracked(self.try_get(key).await) {
+                return Ok(Some(value));
+            }
+        }
+        self.fallback.get(key).await
+    }
+
+    async fn take(&self, key: &str) -> AppResult<Option<String>> {
+        if self.guard.allow() {
+            if let Some(Some(value)) = self.tracked(self.try_take(key).await) {
+                return Ok(Some(value));
+            }
+        }
+        self.fallback.take(key).await
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        if self.guard.allow() {
+            self.tracked(self.try_delete(key).await);
+        }
+        self.fallback.delete(key).await
+    }
+
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_db_store_take_consumes_once() {
+        let db = crate::test_utils::test_db().await;
+        let store = DbSessionStore::new(db);
+
+        store
+            .put("oauth_state:abc", "{}", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get("oauth_state:abc").await.unwrap().as_deref(),
+            Some("{}")
+        );
+        assert_eq!(
+            store.take("oauth_state:abc").await.unwrap().as_deref(),
+            Some("{}")
+        );
+        assert!(store.take("oauth_state:abc").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_db_store_ignores_expired_values() {
+        let db = crate::test_utils::test_db().await;
+        let store = DbSessionStore::new(db);
+
+        store
+            .put("oauth_state:old", "{}", Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(store.get("oauth_state:old").await.unwrap().is_none());
+        assert!(store.take("oauth_state:old").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_redis_store_falls_back_when_redis_down() {
+        let db = crate::test_utils::test_db().await;
+        // Nothing listens on port 1, so every Redis call fails
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+        let guard = Arc::new(RedisGuard::default());
+        let store = RedisSessionStore::new(pool, guard.clone(), Arc::new(DbSessionStore::new(db)));
+
+        store
+            .put("oauth_state:degraded", "{}", Duration::from_secs(60))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.take("oauth_state:degraded").await.unwrap().as_deref(),
+            Some("{}")
+        );
+        store.delete("oauth_state:degraded").await.unwrap();
+        assert!(guard.is_degraded());
+    }
+}
@@ -0,0 +1,227 @@
+/// Progressive delay for repeated failed sign-ins
+///
+/// Unlike a rate limit this never rejects a request: each consecutive failure for an
+/// email/IP pair, and for the email from any address, makes the next attempt wait
+/// longer, up to a cap, which makes online password guessing slow without locking out
+/// a user who mistyped.
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::dev::ConnectionInfo;
+
+use crate::config::Config;
+
+/// Failures older than this are forgotten
+const FAILURE_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Tracked keys beyond which expired entries are pruned
+const PRUNE_THRESHOLD: usize = 1024;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+pub struct SigninThrottle {
+    base: Duration,
+    factor: f64,
+    max: Duration,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl SigninThrottle {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            base: Duration::from_millis(config.signin_throttle_base_ms),
+            factor: config.signin_throttle_factor.max(1.0),
+            max: Duration::from_millis(config.signin_throttle_max_ms),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Attempts are tracked per email and client address together, and per email alone
+    /// so spreading guesses over many addresses doesn't escape the delay
+    pub fn keys(email: &str, client: &str) -> [String; 2] {
+        let email = email.to_lowercase();
+        [format!("{}|{}", email, client), format!("{}|*", email)]
+    }
+
+    /// The client address attempts are keyed on
+    ///
+    /// X-Forwarded-For and Forwarded are whatever the client sent unless a proxy replaces
+    /// them, so they're only believed on connections from one of `trusted_proxies`.
+    pub fn client_address(conn: &ConnectionInfo, trusted_proxies: &[String]) -> Option<String> {
+        let peer = conn.peer_addr()?;
+        let trusted = peer.parse::<IpAddr>().is_ok_and(|peer| {
+            trusted_proxies
+                .iter()
+                .any(|proxy| proxy.parse::<IpAddr>().is_ok_and(|proxy| proxy == peer))
+        });
+        match trusted {
+            true => conn.realip_remote_addr().map(str::to_string),
+            false => Some(peer.to_string()),
+        }
+    }
+
+    /// How long to hold the next attempt for `keys` before checking credentials
+    pub fn delay(&self, keys: &[String]) -> Duration {
+        let failures = self.failures.lock().unwrap();
+        keys.iter()
+            .map(|key| match failures.get(key) {
+                Some(f) if f.last.elapsed() < FAILURE_TTL => self.delay_after(f.count),
+                _ => Duration::ZERO,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Count a failed attempt; returns the most consecutive failures of any key
+    pub fn record_failure(&self, keys: &[String]) -> u32 {
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= PRUNE_THRESHOLD {
+            failures.retain(|_, f| f.last.elapsed() < FAILURE_TTL);
+        }
+
+        keys.iter()
+            .map(|key| {
+                let entry = failures.entry(key.clone()).or_insert(Failures {
+                    count: 0,
+                    last: Instant::now(),
+                });
+                if entry.last.elapsed() >= FAILURE_TTL {
+                    entry.count = 0;
+                }
+                entry.count += 1;
+                entry.last = Instant::now();
+                entry.count
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Whether the `failures`th failure is the first held for the longest delay
+    pub fn reaches_max_delay(&self, failures: u32) -> bool {
+        failures > 0
+            && !self.max.is_zero()
+            && self.delay_after(failures) >= self.max
+            && self.delay_after(failures - 1) < self.max
+    }
+
+    pub fn reset(&self, keys: &[String]) {
+        let mut failures = self.failures.lock().unwrap();
+        for key in keys {
+            failures.remove(key);
+        }
+    }
+
+    /// `base * factor^(failures - 1)`, capped at `max`
+    fn delay_after(&self, failures: u32) -> Duration {
+        if failures == 0 || self.base.is_zero() {
+            return Duration::ZERO;
+        }
+        let exponent = (failures - 1).min(64) as i32;
+        let millis = self.base.as_millis() as f64 * self.factor.powi(exponent);
+        Duration::from_millis(millis.min(self.max.as_millis() as f64) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> SigninThrottle {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.signin_throttle_base_ms = 100;
+        config.signin_throttle_factor = 2.0;
+        config.signin_throttle_max_ms = 1_000;
+        SigninThrottle::from_config(&config)
+    }
+
+    #[test]
+    fn test_delay_grows_with_consecutive_failures() {
+        let throttle = throttle();
+        let keys = SigninThrottle::keys("User@Example.com", "10.0.0.1");
+
+        let mut delays = vec![throttle.delay(&keys)];
+        for _ in 0..6 {
+            throttle.record_failure(&keys);
+            delays.push(throttle.delay(&keys));
+        }
+
+        let millis: Vec<u128> = delays.iter().map(Duration::as_millis).collect();
+        assert_eq!(millis, vec![0, 100, 200, 400, 800, 1_000, 1_000]);
+        // Only the failure that first hits the cap counts as a lock
+        let locking: Vec<u32> = (1..=6).filter(|&f| throttle.reaches_max_delay(f)).collect();
+        assert_eq!(locking, vec![5]);
+
+        // Other users are unaffected
+        assert!(throttle
+            .delay(&SigninThrottle::keys("other@example.com", "10.0.0.1"))
+            .is_zero());
+    }
+
+    #[test]
+    fn test_delay_follows_email_across_addresses() {
+        let throttle = throttle();
+        for i in 0..4 {
+            throttle.record_failure(&SigninThrottle::keys(
+                "user@example.com",
+                &format!("10.0.0.{}", i),
+            ));
+        }
+
+        // Each address failed once, but the email has failed four times
+        let fresh = SigninThrottle::keys("user@example.com", "10.0.0.99");
+        assert_eq!(throttle.delay(&fresh).as_millis(), 800);
+        assert_eq!(throttle.record_failure(&fresh), 5);
+    }
+
+    #[test]
+    fn test_forwarded_address_needs_trusted_proxy() {
+        use actix_web::test::TestRequest;
+
+        let req = TestRequest::default()
+            .peer_addr("10.0.0.5:4000".parse().unwrap())
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_http_request();
+        let conn = req.connection_info();
+
+        assert_eq!(
+            SigninThrottle::client_address(&conn, &[]).as_deref(),
+            Some("10.0.0.5")
+        );
+        assert_eq!(
+            SigninThrottle::client_address(&conn, &["10.0.0.9".to_string()]).as_deref(),
+            Some("10.0.0.5")
+        );
+        assert_eq!(
+            SigninThrottle::client_address(&conn, &["10.0.0.5".to_string()]).as_deref(),
+            Some("203.0.113.7")
+        );
+    }
+
+    #[test]
+    fn test_success_resets_delay() {
+        let throttle = throttle();
+        let keys = SigninThrottle::keys("user@example.com", "10.0.0.1");
+        throttle.record_failure(&keys);
+        throttle.record_failure(&keys);
+
+        throttle.reset(&keys);
+        assert!(throttle.delay(&keys).is_zero());
+        assert_eq!(throttle.record_failure(&keys), 1);
+    }
+
+    #[test]
+    fn test_zero_base_disables_throttling() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.signin_throttle_base_ms = 0;
+        let throttle = SigninThrottle::from_config(&config);
+        let keys = SigninThrottle::keys("user@example.com", "10.0.0.1");
+
+        throttle.record_failure(&keys);
+        assert!(throttle.delay(&keys).is_zero());
+    }
+}
@@ -38,10 +38,10 @@ pub struct ToolRuntimeService {
 impl ToolRuntimeService {
     pub fn new() -> Self {
         ToolRuntimeService {
-            http_client: Client::builder()
+            http_client: crate::utils::http::builder()
                 .timeout(std::time::Duration::from_secs(30))
                 .build()
-                .unwrap_or_else(|_| Client::new()),
+                .unwrap_or_else(|_| crate::utils::http::client()),
             template_engine: TemplateEngine::new(),
             cache: Arc::new(RwLock::new(HashMap::new())),
             rate_limiters: Arc::new(RwLock::new(HashMap::new())),
@@ -0,0 +1,790 @@
+use chrono::{Datelike, TimeZone, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::group::Group;
+use crate::models::usage::{Usage, UsageGroupBy, UsageQuery, UsageSummary};
+use crate::retrieval::chunking::count_tokens_approx;
+use crate::utils::time::current_timestamp_seconds;
+
+/// Response header with the requests left today under the tightest daily limit
+pub const DAILY_REMAINING_HEADER: &str = "X-Daily-Requests-Remaining";
+
+/// Counter holding all of a user's chat requests
+const ALL_REQUESTS: &str = "all";
+
+const SECONDS_PER_DAY: i64 = 24 * 3600;
+
+/// A daily request cap and the counter it is enforced on
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyLimit {
+    pub counter: String,
+    pub limit: u64,
+}
+
+pub struct UsageService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> UsageService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        UsageService { db }
+    }
+
+    /// Record token usage for a single model call
+    pub async fn record(
+        &self,
+        user_id: &str,
+        model: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+    ) -> AppResult<Usage> {
+        self.insert(user_id, model, prompt_tokens, completion_tokens, false)
+            .await
+    }
+
+    /// Record usage whose token counts were estimated rather than reported upstream
+    pub async fn record_estimated(
+        &self,
+        user_id: &str,
+        model: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+    ) -> AppResult<Usage> {
+        self.insert(user_id, model, prompt_tokens, completion_tokens, true)
+            .await
+    }
+
+    async fn insert(
+        &self,
+        user_id: &str,
+        model: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+        estimated: bool,
+    ) -> AppResult<Usage> {
+        let usage = sqlx::query_as::<_, Usage>(
+            r#"
+            INSERT INTO usage (id, user_id, model, prompt_tokens, completion_tokens, total_tokens, estimated, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, user_id, model, prompt_tokens, completion_tokens, total_tokens, estimated, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(model)
+        .bind(prompt_tokens)
+        .bind(completion_tokens)
+        .bind(prompt_tokens + completion_tokens)
+        .bind(estimated)
+        .bind(current_timestamp_seconds())
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    /// Total tokens used by a user since the given timestamp
+    pub async fn get_user_total_tokens_since(&self, user_id: &str, since: i64) -> AppResult<i64> {
+        let total: Option<i64> = sqlx::query_scalar(
+            "SELECT SUM(total_tokens)::BIGINT FROM usage WHERE user_id = $1 AND created_at >= $2",
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_one(&self.db.pool)
+        .await?;
+
+        Ok(total.unwrap_or(0))
+    }
+
+    /// Aggregate usage, grouped by any combination of user, model and date
+    pub async fn get_usage_summary(&self, query: &UsageQuery) -> AppResult<Vec<UsageSummary>> {
+        let groups = query.group_by().map_err(AppError::BadRequest)?;
+        let has = |g: UsageGroupBy| groups.contains(&g);
+
+        let mut builder = sqlx::QueryBuilder::new("SELECT ");
+        builder.push(if has(UsageGroupBy::User) {
+            "user_id, "
+        } else {
+            "NULL::TEXT AS user_id, "
+        });
+        builder.push(if has(UsageGroupBy::Model) {
+            "model, "
+        } else {
+            "NULL::TEXT AS model, "
+        });
+        builder.push(if has(UsageGroupBy::Date) {
+            "to_char(to_timestamp(created_at) AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS date, "
+        } else {
+            "NULL::TEXT AS date, "
+        });
+        builder.push(
+            "COUNT(*)::BIGINT AS requests, \
+             COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens, \
+             COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens, \
+             COALESCE(SUM(total_tokens), 0)::BIGINT AS total_tokens \
+             FROM usage WHERE 1 = 1",
+        );
+
+        if let Some(user_id) = &query.user_id {
+            builder.push(" AND user_id = ").push_bind(user_id);
+        }
+        if let Some(model) = &query.model {
+            builder.push(" AND model = ").push_bind(model);
+        }
+        if let Some(start) = query.start {
+            builder.push(" AND created_at >= ").push_bind(start);
+        }
+        if let Some(end) = query.end {
+            builder.push(" AND created_at < ").push_bind(end);
+        }
+
+        // Positional references keep GROUP BY/ORDER BY in sync with the selected columns
+        let positions: Vec<&str> = [
+            (UsageGroupBy::User, "1"),
+            (UsageGroupBy::Model, "2"),
+            (UsageGroupBy::Date, "3"),
+        ]
+        .iter()
+        .filter(|(g, _)| has(*g))
+        .map(|(_, pos)| *pos)
+        .collect();
+
+        if !positions.is_empty() {
+            let columns = positions.join(", ");
+            builder.push(format!(" GROUP BY {} ORDER BY {}", columns, columns));
+        }
+
+        let rows = builder
+            .build_query_as::<UsageSummary>()
+            .fetch_all(self.db.read_conn())
+            .await?;
+
+        Ok(rows)
+    }
+
+    /// Fail with `TooManyRequests` when the user has exhausted their monthly token quota
+    pub async fn check_monthly_quota(&self, user_id: &str, quota: i64) -> AppResult<()> {
+        if quota <= 0 {
+            return Ok(());
+        }
+
+        let used = self
+            .get_user_total_tokens_since(user_id, start_of_current_month())
+            .await?;
+
+        if used >= quota {
+            return Err(AppError::TooManyRequests(format!(
+                "Monthly token quota exceeded ({} of {} tokens used)",
+                used, quota
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Count a chat request against each of `limits` for the UTC day containing `now`
+    ///
+    /// The counters move together in one transaction: when any is already at its limit,
+    /// none is incremented and the request fails with `TooManyRequests` giving the reset
+    /// time. Returns the requests left under the tightest limit, `None` without limits.
+    pub async fn take_daily_request(
+        &self,
+        user_id: &str,
+        limits: &[DailyLimit],
+        now: i64,
+    ) -> AppResult<Option<u64>> {
+        if limits.is_empty() {
+            return Ok(None);
+        }
+        let day = now - now.rem_euclid(SECONDS_PER_DAY);
+        let mut tx = self.db.pool.begin().await?;
+
+        // Counters from earlier days have served their purpose
+        sqlx::query("DELETE FROM daily_request_count WHERE user_id = $1 AND day < $2")
+            .bind(user_id)
+            .bind(day)
+            .execute(&mut *tx)
+            .await?;
+
+        let mut remaining = u64::MAX;
+        for limit in limits {
+            let count: Option<i64> = sqlx::query_scalar(
+                r#"
+                INSERT INTO daily_request_count (user_id, counter, day, count)
+                VALUES ($1, $2, $3, 1)
+                ON CONFLICT (user_id, counter, day) DO UPDATE
+                SET count = daily_request_count.count + 1
+                WHERE daily_request_count.count < $4
+                RETURNING count
+                "#,
+            )
+            .bind(user_id)
+            .bind(&limit.counter)
+            .bind(day)
+            .bind(limit.limit as i64)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            // Dropping the transaction rolls back the counters already taken
+            let Some(count) = count else {
+                let scope = match limit.counter.strip_prefix("model:") {
+                    Some(model) => format!(" for {}", model),
+                    None => String::new(),
+                };
+                let resets_at = Utc
+                    .timestamp_opt(day + SECONDS_PER_DAY, 0)
+                    .single()
+                    .map(|dt| dt.to_rfc3339())
+                    .unwrap_or_default();
+                return Err(AppError::TooManyRequests(format!(
+                    "Daily request limit{} reached ({} requests), resets at {}",
+                    scope, limit.limit, resets_at
+                )));
+            };
+            remaining = remaining.min(limit.limit.saturating_sub(count as u64));
+        }
+
+        tx.commit().await?;
+        Ok(Some(remaining))
+    }
+}
+
+/// Daily limits on a member of `groups` requesting `model_id`
+///
+/// The overall limit is the most generous DAILY_REQUEST_LIMIT_GROUPS entry among the
+/// user's groups, else DAILY_REQUEST_LIMIT; a DAILY_REQUEST_LIMIT_MODELS entry for the
+/// model is enforced alongside it. 0 means unlimited throughout.
+pub fn daily_limits(config: &Config, model_id: &str, groups: &[Group]) -> Vec<DailyLimit> {
+    let group_limits: Vec<u64> = groups
+        .iter()
+        .filter_map(|group| {
+            config
+                .daily_request_limit_groups
+                .get(&group.id)
+                .or_else(|| config.daily_request_limit_groups.get(&group.name))
+                .copied()
+        })
+        .collect();
+    let overall = if group_limits.is_empty() {
+        config.daily_request_limit
+    } else if group_limits.contains(&0) {
+        0
+    } else {
+        group_limits.into_iter().max().unwrap_or_default()
+    };
+
+    let mut limits = Vec::new();
+    if overall > 0 {
+        limits.push(DailyLimit {
+            counter: ALL_REQUESTS.to_string(),
+            limit: overall,
+        });
+    }
+    if let Some(&limit) = config
+        .daily_request_limit_models
+        .get(model_id)
+        .filter(|limit| **limit > 0)
+    {
+        limits.push(DailyLimit {
+            counter: format!("model:{}", model_id),
+            limit,
+        });
+    }
+    limits
+}
+
+/// Unix timestamp for the first second of the current UTC month
+pub fn start_of_current_month() -> i64 {
+    let now = Utc::now();
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .map(|dt| dt.timestamp())
+        .unwrap_or(0)
+}
+
+/// Read `(prompt_tokens, completion_tokens)` from an OpenAI-style `usage` object
+pub fn extract_usage(response: &Value) -> Option<(i64, i64)> {
+    let usage = response.get("usage").filter(|u| u.is_object())?;
+    let prompt = usage.get("prompt_tokens").and_then(|v| v.as_i64());
+    let completion = usage.get("completion_tokens").and_then(|v| v.as_i64());
+
+    match (prompt, completion) {
+        (None, None) => None,
+        (p, c) => Some((p.unwrap_or(0), c.unwrap_or(0))),
+    }
+}
+
+/// Approximate prompt tokens for a list of chat messages
+pub fn estimate_prompt_tokens(messages: &[Value]) -> i64 {
+    messages
+        .iter()
+        .map(|m| match m.get("content") {
+            Some(Value::String(text)) => count_tokens_approx(text),
+            Some(Value::Array(parts)) => parts
+                .iter()
+                .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                .map(count_tokens_approx)
+                .sum(),
+            _ => 0,
+        })
+        .sum::<usize>() as i64
+}
+
+/// Request parameters of a completion, logged with its usage under
+/// ENABLE_COMPLETION_LOGGING
+///
+/// Message content is never kept.
+#[derive(Debug, Clone)]
+pub struct CompletionLog {
+    stream: bool,
+    max_tokens: Option<i64>,
+    temperature: Option<f64>,
+    started: Instant,
+}
+
+/// The structured record logged for one completion
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompletionRecord {
+    pub user_id: String,
+    pub model: String,
+    pub stream: bool,
+    pub max_tokens: Option<i64>,
+    pub temperature: Option<f64>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Token counts were estimated because the upstream reported none
+    pub estimated: bool,
+    pub latency_ms: u64,
+}
+
+impl CompletionLog {
+    /// Start timing a completion for `payload`, if completion logging is on
+    pub fn for_request(config: &Config, payload: &Value) -> Option<Self> {
+        if !config.enable_completion_logging {
+            return None;
+        }
+        Some(Self {
+            stream: payload
+                .get("stream")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            max_tokens: payload
+                .get("max_tokens")
+                .or_else(|| payload.get("max_completion_tokens"))
+                .and_then(Value::as_i64),
+            temperature: payload.get("temperature").and_then(Value::as_f64),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(
+        &self,
+        user_id: &str,
+        model: &str,
+        prompt_tokens: i64,
+        completion_tokens: i64,
+        estimated: bool,
+    ) -> CompletionRecord {
+        CompletionRecord {
+            user_id: user_id.to_string(),
+            model: model.to_string(),
+            stream: self.stream,
+            max_tokens: self.max_tokens,
+            temperature: self.temperature,
+            prompt_tokens,
+            completion_tokens,
+            estimated,
+            latency_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+impl CompletionRecord {
+    /// Log the record as structured fields under the `completion` target
+    pub fn emit(&self) {
+        tracing::info!(
+            target: "completion",
+            user_id = %self.user_id,
+            model = %self.model,
+            stream = self.stream,
+            max_tokens = ?self.max_tokens,
+            temperature = ?self.temperature,
+            prompt_tokens = self.prompt_tokens,
+            completion_tokens = self.completion_tokens,
+            estimated = self.estimated,
+            latency_ms = self.latency_ms,
+            "completion"
+        );
+    }
+}
+
+/// Record usage in the background so accounting never delays or fails a response
+///
+/// With a `log`, the completion's structured record is logged from the same counts.
+pub fn spawn_record(
+    state: actix_web::web::Data<crate::AppState>,
+    user_id: String,
+    model: String,
+    reported: Option<(i64, i64)>,
+    estimate: impl FnOnce() -> (i64, i64) + Send + 'static,
+    log: Option<CompletionLog>,
+) {
+    tokio::spawn(async move {
+        let (prompt, completion, estimated) = match reported {
+            Some((prompt, completion)) => (prompt, completion, false),
+            None => {
+                let (prompt, completion) = estimate();
+                (prompt, completion, true)
+            }
+        };
+        if let Some(log) = log {
+            log.record(&user_id, &model, prompt, completion, estimated)
+                .emit();
+        }
+
+        let service = UsageService::new(&state.db);
+        let result = if estimated {
+            service
+                .record_estimated(&user_id, &model, prompt, completion)
+                .await
+        } else {
+            service.record(&user_id, &model, prompt, completion).await
+        };
+
+        if let Err(e) = result {
+            tracing::warn!("Failed to record usage for user {}: {}", user_id, e);
+        }
+    });
+}
+
+/// Token counts accumulated from a streamed completion
+///
+/// Upstream `usage` chunks are used when present; otherwise completion tokens
+/// are estimated from the streamed delta content.
+#[derive(Debug, Default)]
+pub struct StreamUsage {
+    prompt_estimate: i64,
+    completion_bytes: usize,
+    reported: Option<(i64, i64)>,
+    pending: String,
+}
+
+impl StreamUsage {
+    pub fn new(messages: &[Value]) -> Self {
+        Self {
+            prompt_estimate: estimate_prompt_tokens(messages),
+            ..Self::default()
+        }
+    }
+
+    /// Feed raw SSE bytes; partial lines are buffered until the next chunk
+    pub fn observe_bytes(&mut self, bytes: &[u8]) {
+        self.pending.push_str(&String::from_utf8_lossy(bytes));
+
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            if let Some(data) = line.trim().strip_prefix("data: ") {
+                if let Ok(event) = serde_json::from_str::<Value>(data) {
+                    self.observe_event(&event);
+                }
+            }
+        }
+    }
+
+    /// Feed an already parsed stream chunk
+    pub fn observe_event(&mut self, event: &Value) {
+        if let Some(usage) = extract_usage(event) {
+            self.reported = Some(usage);
+        }
+
+        if let Some(content) = event
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("delta"))
+            .and_then(|d| d.get("content"))
+            .and_then(|c| c.as_str())
+        {
+            self.completion_bytes += content.len();
+        }
+    }
+
+    /// Usage reported upstream, if any chunk carried it
+    pub fn reported(&self) -> Option<(i64, i64)> {
+        self.reported
+    }
+
+    /// Prompt and completion tokens estimated from the request and streamed content
+    pub fn estimate(&self) -> (i64, i64) {
+        (
+            self.prompt_estimate,
+            (self.completion_bytes as f64 / 4.0).ceil() as i64,
+        )
+    }
+}
+
+/// Accumulates usage from a streamed completion and records it when dropped
+///
+/// Used by the HTTP SSE, Socket.IO (including tool follow-up requests) and
+/// WebSocket chat streams.
+pub struct StreamUsageTracker {
+    state: actix_web::web::Data<crate::AppState>,
+    user_id: String,
+    model: String,
+    usage: StreamUsage,
+    log: Option<CompletionLog>,
+}
+
+impl StreamUsageTracker {
+    pub fn new(
+        state: actix_web::web::Data<crate::AppState>,
+        user_id: String,
+        model: String,
+        messages: &[Value],
+        log: Option<CompletionLog>,
+    ) -> Self {
+        Self {
+            state,
+            user_id,
+            model,
+            usage: StreamUsage::new(messages),
+            log,
+        }
+    }
+
+    /// Feed raw SSE bytes; partial lines are buffered until the next chunk
+    pub fn observe_bytes(&mut self, bytes: &[u8]) {
+        self.usage.observe_bytes(bytes);
+    }
+
+    /// Feed an already parsed stream chunk
+    pub fn observe_event(&mut self, event: &Value) {
+        self.usage.observe_event(event);
+    }
+}
+
+impl Drop for StreamUsageTracker {
+    fn drop(&mut self) {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return;
+        }
+
+        let estimate = self.usage.estimate();
+        spawn_record(
+            self.state.clone(),
+            std::mem::take(&mut self.user_id),
+            std::mem::take(&mut self.model),
+            self.usage.reported(),
+            move || estimate,
+            self.log.take(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract_usage() {
+        let response =
+            json!({"usage": {"prompt_tokens": 12, "completion_tokens": 30, "total_tokens": 42}});
+        assert_eq!(extract_usage(&response), Some((12, 30)));
+        assert_eq!(extract_usage(&json!({"usage": null})), None);
+        assert_eq!(extract_usage(&json!({})), None);
+    }
+
+    #[test]
+    fn test_estimate_prompt_tokens() {
+        let messages = vec![
+            json!({"role": "user", "content": "abcdefgh"}),
+            json!({"role": "user", "content": [{"type": "text", "text": "abcd"}]}),
+        ];
+        assert_eq!(estimate_prompt_tokens(&messages), 3);
+    }
+
+    #[test]
+    fn test_stream_usage_estimates_from_split_chunks() {
+        let messages = vec![json!({"role": "user", "content": "abcdefgh"})];
+        let mut usage = StreamUsage::new(&messages);
+
+        // WebSocket chat forwards raw chunks, which may split an SSE line
+        usage.observe_bytes(b"data: {\"choices\":[{\"delta\":{\"content\":\"hel");
+        usage.observe_bytes(
+            b"lo\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n",
+        );
+        usage.observe_bytes(b"data: [DONE]\n\n");
+
+        assert_eq!(usage.reported(), None);
+        assert_eq!(usage.estimate(), (2, 3));
+    }
+
+    #[test]
+    fn test_stream_usage_prefers_reported_usage() {
+        let mut usage = StreamUsage::new(&[]);
+
+        // Socket.IO streams feed parsed chunks
+        usage.observe_event(&json!({"choices": [{"delta": {"content": "hi"}}]}));
+        usage.observe_event(&json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 7, "completion_tokens": 5}
+        }));
+
+        assert_eq!(usage.reported(), Some((7, 5)));
+    }
+
+    #[test]
+    fn test_completion_record_has_params_and_usage_but_no_content() {
+        let payload = json!({
+            "model": "gpt-4o",
+            "stream": true,
+            "max_tokens": 256,
+            "temperature": 0.2,
+            "messages": [{"role": "user", "content": "my secret plans"}],
+        });
+        let disabled = Config::from_lookup(|_| None).unwrap();
+        assert!(CompletionLog::for_request(&disabled, &payload).is_none());
+
+        let config = Config::from_lookup(|key| match key {
+            "ENABLE_COMPLETION_LOGGING" => Some("true".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let log = CompletionLog::for_request(&config, &payload).unwrap();
+        let record = log.record("user-1", "gpt-4o", 12, 30, false);
+
+        assert_eq!(
+            record,
+            CompletionRecord {
+                user_id: "user-1".to_string(),
+                model: "gpt-4o".to_string(),
+                stream: true,
+                max_tokens: Some(256),
+                temperature: Some(0.2),
+                prompt_tokens: 12,
+                completion_tokens: 30,
+                estimated: false,
+                latency_ms: record.latency_ms,
+            }
+        );
+        assert!(!serde_json::to_string(&record).unwrap().contains("secret"));
+    }
+
+    fn group(name: &str) -> Group {
+        serde_json::from_value(json!({
+            "id": format!("{}-id", name),
+            "user_id": "owner",
+            "name": name,
+            "description": "",
+            "user_ids": [],
+            "created_at": 0,
+            "updated_at": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_daily_limits_by_group_and_model() {
+        let config = Config::from_lookup(|key| match key {
+            "DAILY_REQUEST_LIMIT" => Some("100".to_string()),
+            "DAILY_REQUEST_LIMIT_MODELS" => Some(r#"{"gpt-4o": 20}"#.to_string()),
+            "DAILY_REQUEST_LIMIT_GROUPS" => {
+                Some(r#"{"trial": 10, "research": 1000, "staff": 0}"#.to_string())
+            }
+            _ => None,
+        })
+        .unwrap();
+        let overall = |limits: Vec<DailyLimit>| {
+            limits
+                .into_iter()
+                .find(|limit| limit.counter == ALL_REQUESTS)
+                .map(|limit| limit.limit)
+        };
+
+        assert_eq!(
+            overall(daily_limits(&config, "gpt-4o-mini", &[])),
+            Some(100)
+        );
+        assert_eq!(
+            overall(daily_limits(
+                &config,
+                "gpt-4o-mini",
+                &[group("trial"), group("research")]
+            )),
+            Some(1000)
+        );
+        assert_eq!(
+            overall(daily_limits(&config, "gpt-4o-mini", &[group("staff")])),
+            None
+        );
+        assert_eq!(
+            daily_limits(&config, "gpt-4o", &[group("staff")]),
+            vec![DailyLimit {
+                counter: "model:gpt-4o".to_string(),
+                limit: 20,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_daily_limit_rejects_extra_request_until_next_day() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let service = UsageService::new(&db);
+        let limits = [
+            DailyLimit {
+                counter: ALL_REQUESTS.to_string(),
+                limit: 3,
+            },
+            DailyLimit {
+                counter: "model:gpt-4o".to_string(),
+                limit: 5,
+            },
+        ];
+        // 2026-10-17 15:00 UTC
+        let afternoon = 1_792_249_200;
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(
+                service
+                    .take_daily_request(&user.id, &limits, afternoon)
+                    .await
+                    .unwrap(),
+                Some(remaining)
+            );
+        }
+        let Err(AppError::TooManyRequests(message)) = service
+            .take_daily_request(&user.id, &limits, afternoon + 60)
+            .await
+        else {
+            panic!("expected the 4th request to be rejected");
+        };
+        assert!(message.contains("2026-10-18T00:00:00"), "{}", message);
+
+        // The rejected request took nothing from the model's counter either
+        let model_only = &limits[1..];
+        assert_eq!(
+            service
+                .take_daily_request(&user.id, model_only, afternoon)
+                .await
+                .unwrap(),
+            Some(1)
+        );
+
+        let next_morning = afternoon + 10 * 3600;
+        assert_eq!(
+            service
+                .take_daily_request(&user.id, &limits, next_morning)
+                .await
+                .unwrap(),
+            Some(2)
+        );
+    }
+}
@@ -5,6 +5,21 @@ use crate::utils::time::current_timestamp_seconds;
 use chrono::NaiveDate;
 use sqlx::Row;
 
+/// Advisory lock key serializing first-user (initial admin) determination
+const FIRST_USER_LOCK_KEY: i64 = 0x6f77_7569_6669_7273;
+
+/// The user linked to an OAuth identity ($1 = oauth_sub)
+const OAUTH_SUB_USER_QUERY: &str = r#"
+    SELECT id, name, email, username, role, profile_image_url, bio, gender,
+           date_of_birth,
+           COALESCE(info, '{}'::jsonb) as info,
+           COALESCE(settings, '{}'::jsonb) as settings,
+           api_key, oauth_sub,
+           last_active_at, updated_at, created_at
+    FROM "user"
+    WHERE oauth_sub = $1
+"#;
+
 pub struct UserService<'a> {
     db: &'a Database,
 }
@@ -24,7 +39,7 @@ impl<'a> UserService<'a> {
                    api_key, oauth_sub, 
                    last_active_at, updated_at, created_at
             FROM "user"
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
         )
         .bind(id)
@@ -44,7 +59,7 @@ impl<'a> UserService<'a> {
                    api_key, oauth_sub, 
                    last_active_at, updated_at, created_at
             FROM "user"
-            WHERE email = $1
+            WHERE email = $1 AND deleted_at IS NULL
             "#,
         )
         .bind(email)
@@ -54,6 +69,26 @@ impl<'a> UserService<'a> {
         Ok(result)
     }
 
+    pub async fn get_user_by_oauth_sub(&self, oauth_sub: &str) -> AppResult<Option<User>> {
+        let result = sqlx::query_as::<_, User>(
+            r#"
+            SELECT id, name, email, username, role, profile_image_url, bio, gender,
+                   date_of_birth,
+                   COALESCE(info, '{}'::jsonb) as info,
+                   COALESCE(settings, '{}'::jsonb) as settings,
+                   api_key, oauth_sub,
+                   last_active_at, updated_at, created_at
+            FROM "user"
+            WHERE oauth_sub = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(oauth_sub)
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(result)
+    }
+
     #[allow(dead_code)]
     pub async fn get_user_by_api_key(&self, api_key: &str) -> AppResult<Option<User>> {
         let result = sqlx::query_as::<_, User>(
@@ -65,7 +100,7 @@ impl<'a> UserService<'a> {
                    api_key, oauth_sub, 
                    last_active_at, updated_at, created_at
             FROM "user"
-            WHERE api_key = $1
+            WHERE api_key = $1 AND deleted_at IS NULL
             "#,
         )
         .bind(api_key)
@@ -127,6 +162,94 @@ impl<'a> UserService<'a> {
             .ok_or_else(|| AppError::InternalServerError("Failed to create user".to_string()))
     }
 
+    /// Create a user whose role depends on whether they are the first user
+    ///
+    /// The count and insert run in one transaction under an advisory lock, so concurrent
+    /// first-time signups can't both be treated as the first user. When `oauth_sub` is set
+    /// and a user with it already exists (e.g. a concurrent login won), that user is returned;
+    /// the insert itself is conditional on `oauth_sub`, so concurrent logins converge on one
+    /// user even without the lock. Also returns whether the user was created.
+    pub async fn create_user_with_first_user_role<F>(
+        &self,
+        id: &str,
+        name: &str,
+        email: &str,
+        profile_image_url: &str,
+        oauth_sub: Option<&str>,
+        role_for: F,
+    ) -> AppResult<(User, bool)>
+    where
+        F: FnOnce(bool) -> AppResult<String>,
+    {
+        let mut tx = self.db.pool.begin().await?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(FIRST_USER_LOCK_KEY)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(oauth_sub) = oauth_sub {
+            let existing = sqlx::query_as::<_, User>(OAUTH_SUB_USER_QUERY)
+                .bind(oauth_sub)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            if let Some(user) = existing {
+                tx.commit().await?;
+                return Ok((user, false));
+            }
+        }
+
+        let user_count: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM "user""#)
+            .fetch_one(&mut *tx)
+            .await?;
+        let role = role_for(user_count == 0)?;
+
+        let now = current_timestamp_seconds();
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            INSERT INTO "user" (
+                id, name, email, role, profile_image_url, oauth_sub,
+                last_active_at, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (oauth_sub) DO NOTHING
+            RETURNING id, name, email, username, role, profile_image_url, bio, gender,
+                      date_of_birth,
+                      COALESCE(info, '{}'::jsonb) as info,
+                      COALESCE(settings, '{}'::jsonb) as settings,
+                      api_key, oauth_sub,
+                      last_active_at, updated_at, created_at
+            "#,
+        )
+        .bind(id)
+        .bind(name)
+        .bind(email)
+        .bind(&role)
+        .bind(profile_image_url)
+        .bind(oauth_sub)
+        .bind(now)
+        .bind(now)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let created = user.is_some();
+        let user = match user {
+            Some(user) => user,
+            // Someone else linked or created a user with this oauth_sub meanwhile
+            None => {
+                sqlx::query_as::<_, User>(OAUTH_SUB_USER_QUERY)
+                    .bind(oauth_sub)
+                    .fetch_one(&mut *tx)
+                    .await?
+            }
+        };
+
+        tx.commit().await?;
+        Ok((user, created))
+    }
+
     #[allow(dead_code)]
     pub async fn update_user_last_active(&self, id: &str) -> AppResult<()> {
         let now = current_timestamp_seconds();
@@ -156,6 +279,7 @@ impl<'a> UserService<'a> {
                    api_key, oauth_sub, 
                    last_active_at, updated_at, created_at
             FROM "user"
+            WHERE deleted_at IS NULL
             ORDER BY created_at DESC
             LIMIT $1 OFFSET $2
             "#,
@@ -169,10 +293,11 @@ impl<'a> UserService<'a> {
     }
 
     pub async fn count_users(&self) -> AppResult<i64> {
-        let count: i64 = sqlx::query("SELECT COUNT(*) as count FROM \"user\"")
-            .fetch_one(&self.db.pool)
-            .await?
-            .try_get("count")?;
+        let count: i64 =
+            sqlx::query("SELECT COUNT(*) as count FROM \"user\" WHERE deleted_at IS NULL")
+                .fetch_one(&self.db.pool)
+                .await?
+                .try_get("count")?;
 
         Ok(count)
     }
@@ -203,21 +328,87 @@ impl<'a> UserService<'a> {
         Ok(())
     }
 
+    /// Replace a user's settings
+    ///
+    /// With `if_match`, the write only applies while the stored settings still carry one
+    /// of those ETags; otherwise it fails with `Conflict` so the client can merge and retry.
     pub async fn update_user_settings(
         &self,
         id: &str,
         settings: &serde_json::Value,
+        if_match: Option<&str>,
     ) -> AppResult<()> {
-        sqlx::query(
+        let Some(if_match) = if_match else {
+            sqlx::query(
+                r#"
+                UPDATE "user"
+                SET settings = $1, updated_at = $2
+                WHERE id = $3
+                "#,
+            )
+            .bind(settings)
+            .bind(current_timestamp_seconds())
+            .bind(id)
+            .execute(&self.db.pool)
+            .await?;
+
+            return Ok(());
+        };
+
+        let current: Option<serde_json::Value> =
+            sqlx::query_scalar(r#"SELECT settings FROM "user" WHERE id = $1"#)
+                .bind(id)
+                .fetch_optional(&self.db.pool)
+                .await?
+                .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+        let conflict = || {
+            AppError::Conflict(
+                "Settings were changed elsewhere; reload them and try again".to_string(),
+            )
+        };
+        if !etag_matches(if_match, &settings_etag(current.as_ref())) {
+            return Err(conflict());
+        }
+
+        // Compare-and-swap so a write landing between the read and here isn't lost
+        let result = sqlx::query(
             r#"
             UPDATE "user"
             SET settings = $1, updated_at = $2
-            WHERE id = $3
+            WHERE id = $3 AND settings IS NOT DISTINCT FROM $4::jsonb
             "#,
         )
         .bind(settings)
         .bind(current_timestamp_seconds())
         .bind(id)
+        .bind(&current)
+        .execute(&self.db.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(conflict());
+        }
+        Ok(())
+    }
+
+    /// Set one top-level key of a user's `info`
+    pub async fn set_info_field(
+        &self,
+        id: &str,
+        key: &str,
+        value: &serde_json::Value,
+    ) -> AppResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE "user"
+            SET info = jsonb_set(COALESCE(info, '{}'::jsonb), ARRAY[$1], $2)
+            WHERE id = $3
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .bind(id)
         .execute(&self.db.pool)
         .await?;
 
@@ -303,7 +494,7 @@ impl<'a> UserService<'a> {
     }
 
     pub async fn get_user_count(&self) -> AppResult<i64> {
-        let result = sqlx::query("SELECT COUNT(*) as count FROM \"user\"")
+        let result = sqlx::query("SELECT COUNT(*) as count FROM \"user\" WHERE deleted_at IS NULL")
             .fetch_one(&self.db.pool)
             .await?;
 
@@ -320,7 +511,7 @@ impl<'a> UserService<'a> {
             r#"
             SELECT id
             FROM "user"
-            WHERE id = ANY($1)
+            WHERE id = ANY($1) AND deleted_at IS NULL
             "#,
         )
         .bind(user_ids)
@@ -330,3 +521,135 @@ impl<'a> UserService<'a> {
         Ok(result.into_iter().map(|(id,)| id).collect())
     }
 }
+
+/// ETag for a user's stored settings
+pub fn settings_etag(settings: Option<&serde_json::Value>) -> String {
+    let serialized = settings.map(|s| s.to_string()).unwrap_or_default();
+    format!("\"{}\"", crate::utils::misc::sha256_hash(&serialized))
+}
+
+/// Whether an `If-Match` header value matches `etag` (weak tags compare by value)
+fn etag_matches(if_match: &str, etag: &str) -> bool {
+    if_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_matching() {
+        let etag = settings_etag(Some(&serde_json::json!({"ui": {}})));
+        assert_ne!(etag, settings_etag(None));
+        assert!(etag_matches(&etag, &etag));
+        assert!(etag_matches(&format!("W/{}, \"other\"", etag), &etag));
+        assert!(etag_matches("*", &etag));
+        assert!(!etag_matches("\"stale\"", &etag));
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_racing_settings_updates_conflict() {
+        let db = crate::test_utils::test_db().await;
+        let user = crate::test_utils::seed_user(&db, "user").await;
+        let etag = settings_etag(user.settings.as_ref());
+
+        // Two tabs loaded the same settings and save different changes
+        let service = UserService::new(&db);
+        let first = serde_json::json!({"ui": {"theme": "dark"}});
+        let second = serde_json::json!({"ui": {"language": "de"}});
+        let (a, b) = tokio::join!(
+            service.update_user_settings(&user.id, &first, Some(&etag)),
+            service.update_user_settings(&user.id, &second, Some(&etag)),
+        );
+
+        let results = [a, b];
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(AppError::Conflict(_)))));
+
+        // The loser retries against the fresh ETag
+        let stored = service.get_user_by_id(&user.id).await.unwrap().unwrap();
+        let fresh = settings_etag(stored.settings.as_ref());
+        service
+            .update_user_settings(&user.id, &second, Some(&fresh))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_concurrent_first_users_yield_one_admin() {
+        let db = crate::test_utils::test_db().await;
+
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    UserService::new(&db)
+                        .create_user_with_first_user_role(
+                            &uuid::Uuid::new_v4().to_string(),
+                            &format!("User {}", i),
+                            &format!("user{}@example.com", i),
+                            "",
+                            Some(&format!("test@{}", i)),
+                            |is_first| Ok(if is_first { "admin" } else { "user" }.to_string()),
+                        )
+                        .await
+                })
+            })
+            .collect();
+
+        let mut admins = 0;
+        for handle in handles {
+            if handle.await.unwrap().unwrap().0.role == "admin" {
+                admins += 1;
+            }
+        }
+        assert_eq!(admins, 1);
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_concurrent_oauth_callbacks_create_one_user() {
+        let db = crate::test_utils::test_db().await;
+        let oauth_sub = "google@same-identity";
+
+        // Two callbacks for one identity, each with its own freshly generated user id
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    UserService::new(&db)
+                        .create_user_with_first_user_role(
+                            &uuid::Uuid::new_v4().to_string(),
+                            "Same Person",
+                            "same@example.com",
+                            "",
+                            Some(oauth_sub),
+                            |_| Ok("user".to_string()),
+                        )
+                        .await
+                })
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap().unwrap());
+        }
+        assert_eq!(results[0].0.id, results[1].0.id);
+        assert_eq!(results.iter().filter(|(_, created)| *created).count(), 1);
+
+        let users: i64 = sqlx::query_scalar(r#"SELECT COUNT(*) FROM "user" WHERE oauth_sub = $1"#)
+            .bind(oauth_sub)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(users, 1);
+    }
+}
@@ -0,0 +1,194 @@
+/// Removal of vector collections left behind by deleted knowledge bases and files
+///
+/// Only collections named the way this backend names them are considered: a knowledge
+/// base's id, or `file-{id}`. Anything else in the vector database may belong to some
+/// other tool, so it is reported as unrecognized and left alone.
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::Row;
+
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::retrieval::VectorDB;
+
+const FILE_PREFIX: &str = "file-";
+
+/// Orphaned collections found by a scan, and whether they were deleted
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    /// Collections with no knowledge base or file behind them
+    pub orphans: Vec<String>,
+    /// Orphans actually deleted; empty on a dry run
+    pub deleted: Vec<String>,
+    /// Orphans whose deletion failed, with the error
+    pub failed: Vec<(String, String)>,
+    /// Collections not named like a knowledge base or file, left alone
+    pub unrecognized: Vec<String>,
+}
+
+/// What a collection name refers to
+enum Owner<'a> {
+    Knowledge(&'a str),
+    File(&'a str),
+}
+
+fn owner(collection: &str) -> Option<Owner<'_>> {
+    match collection.strip_prefix(FILE_PREFIX) {
+        Some(file_id) if !file_id.is_empty() => Some(Owner::File(file_id)),
+        Some(_) => None,
+        None => uuid::Uuid::parse_str(collection)
+            .is_ok()
+            .then_some(Owner::Knowledge(collection)),
+    }
+}
+
+pub struct VectorPruneService<'a> {
+    db: &'a Database,
+}
+
+impl<'a> VectorPruneService<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        VectorPruneService { db }
+    }
+
+    /// Find orphaned collections and, unless `dry_run`, delete them
+    pub async fn prune(&self, vector_db: &dyn VectorDB, dry_run: bool) -> AppResult<PruneReport> {
+        let collections = vector_db.list_collections().await.map_err(|e| {
+            AppError::ExternalServiceError(format!("Failed to list vector collections: {}", e))
+        })?;
+
+        let mut knowledge_ids = Vec::new();
+        let mut file_ids = Vec::new();
+        for collection in &collections {
+            match owner(collection) {
+                Some(Owner::Knowledge(id)) => knowledge_ids.push(id.to_string()),
+                Some(Owner::File(id)) => file_ids.push(id.to_string()),
+                None => {}
+            }
+        }
+        let knowledge = self.existing("knowledge", &knowledge_ids).await?;
+        let files = self.existing("file", &file_ids).await?;
+
+        let mut report = PruneReport {
+            dry_run,
+            ..Default::default()
+        };
+        for collection in collections {
+            let exists = match owner(&collection) {
+                Some(Owner::Knowledge(id)) => knowledge.contains(id),
+                Some(Owner::File(id)) => files.contains(id),
+                None => {
+                    report.unrecognized.push(collection);
+                    continue;
+                }
+            };
+            if !exists {
+                report.orphans.push(collection);
+            }
+        }
+        report.orphans.sort();
+        report.unrecognized.sort();
+        if dry_run {
+            return Ok(report);
+        }
+
+        for collection in &report.orphans {
+            match vector_db.delete_collection(collection).await {
+                Ok(()) => report.deleted.push(collection.clone()),
+                Err(e) => {
+                    tracing::warn!("Failed to delete orphaned collection {}: {}", collection, e);
+                    report.failed.push((collection.clone(), e.to_string()));
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Which of `ids` still have a row in `table`
+    async fn existing(&self, table: &str, ids: &[String]) -> AppResult<HashSet<String>> {
+        if ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let rows = sqlx::query(&format!("SELECT id FROM {} WHERE id = ANY($1)", table))
+            .bind(ids)
+            .fetch_all(&self.db.pool)
+            .await?;
+        rows.iter()
+            .map(|row| row.try_get("id").map_err(Into::into))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieval::vector::VectorItem;
+    use crate::routes::knowledge_vector::tests::MemoryVectorDb;
+    use crate::services::{file::FileService, knowledge::KnowledgeService};
+    use crate::test_utils::{seed_user, test_db};
+
+    async fn seed_collection(vector_db: &dyn VectorDB, name: &str) {
+        vector_db
+            .insert(
+                name,
+                vec![VectorItem {
+                    id: "chunk-0".to_string(),
+                    text: "text".to_string(),
+                    vector: vec![0.0; 3],
+                    metadata: serde_json::json!({}),
+                }],
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_orphan_collections_found_and_removed() {
+        let db = test_db().await;
+        let owner = seed_user(&db, "user").await;
+        let knowledge_id = uuid::Uuid::new_v4().to_string();
+        KnowledgeService::new(&db)
+            .create_knowledge(&knowledge_id, &owner.id, "Docs", None, None)
+            .await
+            .unwrap();
+        let file_id = uuid::Uuid::new_v4().to_string();
+        FileService::new(&db)
+            .create_file(&file_id, &owner.id, "notes.txt", "notes.txt", None)
+            .await
+            .unwrap();
+
+        let vector_db = MemoryVectorDb::default();
+        let orphan_knowledge = uuid::Uuid::new_v4().to_string();
+        let orphan_file = format!("file-{}", uuid::Uuid::new_v4());
+        let kept_file = format!("file-{}", file_id);
+        for name in [
+            knowledge_id.as_str(),
+            kept_file.as_str(),
+            orphan_knowledge.as_str(),
+            orphan_file.as_str(),
+            "web-search-cache",
+        ] {
+            seed_collection(&vector_db, name).await;
+        }
+        let mut orphans = vec![orphan_knowledge.clone(), orphan_file.clone()];
+        orphans.sort();
+
+        let service = VectorPruneService::new(&db);
+        let preview = service.prune(&vector_db, true).await.unwrap();
+        assert_eq!(preview.orphans, orphans);
+        assert!(preview.deleted.is_empty());
+        assert_eq!(preview.unrecognized, vec!["web-search-cache".to_string()]);
+        assert!(vector_db.has_collection(&orphan_knowledge).await.unwrap());
+
+        let pruned = service.prune(&vector_db, false).await.unwrap();
+        assert_eq!(pruned.deleted, orphans);
+        assert!(!vector_db.has_collection(&orphan_knowledge).await.unwrap());
+        assert!(!vector_db.has_collection(&orphan_file).await.unwrap());
+        assert!(vector_db.has_collection(&knowledge_id).await.unwrap());
+        assert!(vector_db.has_collection(&kept_file).await.unwrap());
+        assert!(vector_db.has_collection("web-search-cache").await.unwrap());
+    }
+}
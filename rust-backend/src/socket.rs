@@ -1,19 +1,34 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason};
+use serde::Serialize;
 use serde_json::json;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
 /// Socket.IO state for managing connections
 #[derive(Clone)]
 pub struct SocketState {
     // Reference to native Socket.IO handler
     pub native_handler: Arc<crate::socketio::EventHandler>,
+    /// Open WebSocket connections across Socket.IO and /api/ws/chat
+    pub connections: Arc<ConnectionLimits>,
 }
 
 impl SocketState {
-    pub fn new(handler: Arc<crate::socketio::EventHandler>) -> Self {
+    pub fn new(
+        handler: Arc<crate::socketio::EventHandler>,
+        connections: Arc<ConnectionLimits>,
+    ) -> Self {
         Self {
             native_handler: handler,
+            connections,
         }
     }
+
+    /// Register a streaming completion for a chat so it can be stopped over the socket
+    pub fn register_stream(&self, chat_id: &str, user_id: &str) -> crate::socketio::StreamHandle {
+        self.native_handler.streams().register(chat_id, user_id)
+    }
 }
 
 /// Create event emitter function for streaming chat completions
@@ -51,3 +66,196 @@ pub fn get_event_emitter(
         })
     }
 }
+
+/// Which cap turned a WebSocket upgrade away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// MAX_WS_CONNECTIONS_PER_USER
+    User,
+    /// MAX_WS_CONNECTIONS
+    Global,
+}
+
+impl LimitExceeded {
+    fn reason(self) -> &'static str {
+        match self {
+            Self::User => "Too many connections for this user",
+            Self::Global => "Too many connections",
+        }
+    }
+}
+
+#[derive(Default)]
+struct OpenConnections {
+    total: usize,
+    by_user: HashMap<String, usize>,
+}
+
+/// Counts open WebSocket connections against MAX_WS_CONNECTIONS_PER_USER and
+/// MAX_WS_CONNECTIONS (0 = unlimited)
+pub struct ConnectionLimits {
+    per_user: usize,
+    global: usize,
+    open: Mutex<OpenConnections>,
+}
+
+/// Connection counts for the admin stats endpoint
+#[derive(Debug, Serialize)]
+pub struct ConnectionCounts {
+    pub total: usize,
+    pub max_total: usize,
+    pub max_per_user: usize,
+    pub users: BTreeMap<String, usize>,
+}
+
+impl ConnectionLimits {
+    pub fn new(per_user: usize, global: usize) -> Self {
+        Self {
+            per_user,
+            global,
+            open: Mutex::new(OpenConnections::default()),
+        }
+    }
+
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Self::new(
+            config.max_ws_connections_per_user,
+            config.max_ws_connections,
+        )
+    }
+
+    /// Count a new connection, or refuse it when a cap is reached
+    ///
+    /// Connections whose user isn't known at upgrade only count toward the global cap.
+    /// The count is released when the returned guard drops.
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        user_id: Option<&str>,
+    ) -> Result<ConnectionGuard, LimitExceeded> {
+        let mut open = self.open.lock().unwrap();
+        if self.global > 0 && open.total >= self.global {
+            return Err(LimitExceeded::Global);
+        }
+        if let Some(user_id) = user_id {
+            let count = open.by_user.get(user_id).copied().unwrap_or(0);
+            if self.per_user > 0 && count >= self.per_user {
+                return Err(LimitExceeded::User);
+            }
+            open.by_user.insert(user_id.to_string(), count + 1);
+        }
+        open.total += 1;
+
+        Ok(ConnectionGuard {
+            limits: self.clone(),
+            user_id: user_id.map(str::to_string),
+        })
+    }
+
+    pub fn counts(&self) -> ConnectionCounts {
+        let open = self.open.lock().unwrap();
+        ConnectionCounts {
+            total: open.total,
+            max_total: self.global,
+            max_per_user: self.per_user,
+            users: open
+                .by_user
+                .iter()
+                .map(|(user_id, count)| (user_id.clone(), *count))
+                .collect(),
+        }
+    }
+
+    fn release(&self, user_id: Option<&str>) {
+        let mut open = self.open.lock().unwrap();
+        open.total = open.total.saturating_sub(1);
+        if let Some(user_id) = user_id {
+            if let Some(count) = open.by_user.get_mut(user_id) {
+                *count -= 1;
+                if *count == 0 {
+                    open.by_user.remove(user_id);
+                }
+            }
+        }
+    }
+}
+
+/// One counted connection; dropping it, however the socket ended, frees the slot
+pub struct ConnectionGuard {
+    limits: Arc<ConnectionLimits>,
+    user_id: Option<String>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.limits.release(self.user_id.as_deref());
+    }
+}
+
+/// Complete the upgrade, then close straight away with a policy-violation close code
+///
+/// Browsers don't expose HTTP status codes of failed upgrades, so the reason is sent as a
+/// WebSocket close frame instead.
+pub fn reject_upgrade(
+    req: &HttpRequest,
+    stream: web::Payload,
+    limit: LimitExceeded,
+) -> Result<HttpResponse, actix_web::Error> {
+    tracing::warn!(
+        "Rejecting WebSocket upgrade from {:?}: {}",
+        req.peer_addr(),
+        limit.reason()
+    );
+    let (response, session, _msg_stream) = actix_ws::handle(req, stream)?;
+    actix_web::rt::spawn(async move {
+        let _ = session
+            .close(Some(CloseReason {
+                code: CloseCode::Policy,
+                description: Some(limit.reason().to_string()),
+            }))
+            .await;
+    });
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_over_user_limit_rejected() {
+        let limits = Arc::new(ConnectionLimits::new(2, 0));
+        let first = limits.try_acquire(Some("u1")).unwrap();
+        let _second = limits.try_acquire(Some("u1")).unwrap();
+
+        assert_eq!(
+            limits.try_acquire(Some("u1")).err(),
+            Some(LimitExceeded::User)
+        );
+        // Other users and unauthenticated sockets aren't affected
+        let _other = limits.try_acquire(Some("u2")).unwrap();
+        let _anonymous = limits.try_acquire(None).unwrap();
+
+        let counts = limits.counts();
+        assert_eq!(counts.total, 4);
+        assert_eq!(counts.users.get("u1"), Some(&2));
+
+        // Closing a connection frees its slot
+        drop(first);
+        assert!(limits.try_acquire(Some("u1")).is_ok());
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let limits = Arc::new(ConnectionLimits::new(0, 2));
+        let guards: Vec<ConnectionGuard> = ["u1", "u2"]
+            .iter()
+            .map(|user_id| limits.try_acquire(Some(user_id)).unwrap())
+            .collect();
+
+        assert_eq!(limits.try_acquire(None).err(), Some(LimitExceeded::Global));
+        drop(guards);
+        let counts = limits.counts();
+        assert_eq!(counts.total, 0);
+        assert!(counts.users.is_empty());
+    }
+}
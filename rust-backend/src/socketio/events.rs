@@ -26,6 +26,7 @@ use crate::socketio::metrics::SocketIOMetrics;
 use crate::socketio::presence::PresenceManager;
 use crate::socketio::rate_limit::RateLimiter;
 use crate::socketio::recovery::RecoveryManager;
+use crate::socketio::streams::StreamRegistry;
 
 /// Event handler for Socket.IO events
 #[derive(Clone)]
@@ -39,6 +40,7 @@ pub struct EventHandler {
     rate_limiter: Arc<RateLimiter>,
     presence_manager: Arc<PresenceManager>,
     recovery_manager: Arc<RecoveryManager>,
+    streams: StreamRegistry,
     db: Database,
 }
 
@@ -64,6 +66,7 @@ impl EventHandler {
             rate_limiter,
             presence_manager,
             recovery_manager,
+            streams: StreamRegistry::new(),
             db,
         }
     }
@@ -88,6 +91,11 @@ impl EventHandler {
         &self.recovery_manager
     }
 
+    /// Get active stream registry reference
+    pub fn streams(&self) -> &StreamRegistry {
+        &self.streams
+    }
+
     /// Register a connection
     pub async fn register_connection(
         &self,
@@ -122,7 +130,9 @@ impl EventHandler {
 
         // Update presence if user was authenticated
         if let Some(uid) = &user_id {
-            self.presence_manager.user_offline(uid).await;
+            if self.presence_manager.user_offline(uid).await {
+                self.broadcast_presence(uid, "offline").await;
+            }
         }
 
         // Clean up rate limiter
@@ -136,6 +146,37 @@ impl EventHandler {
         tracing::info!("Unregistered connection: {}", sid);
     }
 
+    /// Drop sessions that stopped pinging, clearing their presence as on a clean disconnect
+    pub async fn cleanup_stale_sessions(&self, timeout_seconds: i64) {
+        for sid in self.manager.stale_session_ids(timeout_seconds).await {
+            tracing::warn!("Removing stale session: {}", sid);
+            self.unregister_connection(&sid).await;
+            self.manager.remove_session(&sid).await;
+        }
+    }
+
+    /// Tell every authenticated session that a user came online or went offline
+    async fn broadcast_presence(&self, user_id: &str, status: &str) {
+        let data = serde_json::json!({
+            "user_id": user_id,
+            "status": status,
+        });
+
+        let sids: Vec<String> = self.connections.read().await.keys().cloned().collect();
+        for sid in sids {
+            let authenticated = self
+                .manager
+                .get_session(&sid)
+                .await
+                .is_some_and(|session| session.user_id().is_some());
+            if authenticated {
+                let _ = self
+                    .emit_to_session(&sid, "user-presence", data.clone())
+                    .await;
+            }
+        }
+    }
+
     /// Emit event to a specific session
     pub async fn emit_to_session(
         &self,
@@ -265,7 +306,9 @@ impl EventHandler {
                 .ok_or("Missing user ID")?;
 
             // Update presence
-            self.presence_manager.user_online(user_id).await;
+            if self.presence_manager.user_online(user_id).await {
+                self.broadcast_presence(user_id, "online").await;
+            }
 
             // Record metric
             self.metrics.record_event_received("user-join").await;
@@ -425,6 +468,50 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Handle chat join (owner joins a chat room to receive typing indicators)
+    pub async fn handle_chat_join(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let session = self
+            .manager
+            .get_session(sid)
+            .await
+            .ok_or("Session not found")?;
+
+        let user_id = session.user_id().ok_or("User not authenticated")?;
+
+        let chat_id = data
+            .get("chat_id")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing chat_id")?;
+
+        use crate::services::chat::ChatService;
+        let chat_service = ChatService::new(&self.db);
+        chat_service
+            .get_chat_by_id_and_user_id(chat_id, &user_id)
+            .await
+            .map_err(|e| format!("Failed to load chat: {}", e))?
+            .ok_or("Chat not found")?;
+
+        let room = format!("chat:{}", chat_id);
+        self.manager.join_room(sid, &room).await?;
+
+        tracing::info!("Session {} joined chat room: {}", sid, chat_id);
+        Ok(())
+    }
+
+    /// Handle chat leave
+    pub async fn handle_chat_leave(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let chat_id = data
+            .get("chat_id")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing chat_id")?;
+
+        let room = format!("chat:{}", chat_id);
+        self.manager.leave_room(sid, &room).await?;
+
+        tracing::info!("Session {} left chat room: {}", sid, chat_id);
+        Ok(())
+    }
+
     /// Handle Yjs document join
     pub async fn handle_ydoc_join(&self, sid: &str, data: JsonValue) -> Result<(), String> {
         let doc_id = data
@@ -815,10 +902,8 @@ impl EventHandler {
             .and_then(|n| n.as_str())
             .unwrap_or("Unknown");
 
-        let room_id = data
-            .get("room_id")
-            .and_then(|r| r.as_str())
-            .ok_or("Missing room_id")?;
+        let room_id = typing_room(&data)?;
+        let room_id = room_id.as_str();
 
         self.presence_manager
             .start_typing(user_id, user_name, room_id)
@@ -848,10 +933,8 @@ impl EventHandler {
 
         let user_id = session.user_id().ok_or("User not authenticated")?;
 
-        let room_id = data
-            .get("room_id")
-            .and_then(|r| r.as_str())
-            .ok_or("Missing room_id")?;
+        let room_id = typing_room(&data)?;
+        let room_id = room_id.as_str();
 
         self.presence_manager.stop_typing(&user_id, room_id).await;
         self.metrics.record_event_received("typing:stop").await;
@@ -868,6 +951,31 @@ impl EventHandler {
         Ok(())
     }
 
+    /// Handle a stop-generation request, cancelling the chat's in-flight completion
+    pub async fn handle_stop_generation(&self, sid: &str, data: JsonValue) -> Result<(), String> {
+        let session = self
+            .manager
+            .get_session(sid)
+            .await
+            .ok_or("Session not found")?;
+
+        let user_id = session.user_id().ok_or("User not authenticated")?;
+
+        let chat_id = data
+            .get("chat_id")
+            .and_then(|c| c.as_str())
+            .ok_or("Missing chat_id")?;
+
+        self.metrics.record_event_received("stop_generation").await;
+
+        if self.streams.cancel(chat_id, &user_id) {
+            tracing::info!("User {} stopped generation for chat {}", user_id, chat_id);
+        } else {
+            tracing::debug!("No active generation to stop for chat {}", chat_id);
+        }
+        Ok(())
+    }
+
     /// Get presence for multiple users
     pub async fn handle_get_presences(
         &self,
@@ -892,6 +1000,18 @@ impl EventHandler {
     }
 }
 
+/// Room a typing event targets: an explicit `room_id`, or the chat's room for `chat_id`
+fn typing_room(data: &JsonValue) -> Result<String, String> {
+    if let Some(room_id) = data.get("room_id").and_then(|r| r.as_str()) {
+        return Ok(room_id.to_string());
+    }
+
+    data.get("chat_id")
+        .and_then(|c| c.as_str())
+        .map(|chat_id| format!("chat:{}", chat_id))
+        .ok_or_else(|| "Missing room_id".to_string())
+}
+
 /// HTTP endpoint for emitting events from Rust backend
 #[derive(Debug, Deserialize)]
 pub struct EmitRequest {
@@ -965,3 +1085,82 @@ pub async fn handle_health_check(
         rooms: *stats.get("rooms").unwrap_or(&0),
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socketio::{RateLimitConfig, RecoveryConfig};
+
+    fn test_handler() -> EventHandler {
+        EventHandler::new(
+            SocketIOManager::new(),
+            String::new(),
+            YDocManager::new(None),
+            None,
+            SocketIOMetrics::new(),
+            Arc::new(RateLimiter::new(RateLimitConfig::default())),
+            Arc::new(PresenceManager::default()),
+            Arc::new(RecoveryManager::new(None, RecoveryConfig::default())),
+            Database::new_lazy_for_tests(),
+        )
+    }
+
+    async fn connect(
+        handler: &EventHandler,
+        sid: &str,
+        user_id: &str,
+    ) -> tokio::sync::mpsc::UnboundedReceiver<String> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        handler.manager().create_session(sid).await;
+        handler.register_connection(sid, tx).await;
+        handler
+            .manager()
+            .set_session_user(sid, serde_json::json!({ "id": user_id }))
+            .await
+            .unwrap();
+        handler.presence_manager().user_online(user_id).await;
+        rx
+    }
+
+    #[tokio::test]
+    async fn test_presence_follows_connections() {
+        let handler = test_handler();
+        let presence = handler.presence_manager();
+        let _rx1 = connect(&handler, "sid-1", "user-1").await;
+        let _rx2 = connect(&handler, "sid-2", "user-1").await;
+        assert_eq!(
+            presence.get_presence("user-1").await.unwrap().session_count,
+            2
+        );
+
+        // One tab closes cleanly; the user stays online
+        handler.unregister_connection("sid-1").await;
+        handler.manager().remove_session("sid-1").await;
+        assert_eq!(
+            presence.get_presence("user-1").await.unwrap().session_count,
+            1
+        );
+        assert_eq!(presence.online_user_ids().await, vec!["user-1"]);
+
+        // The other stops pinging; stale cleanup clears the user
+        handler.cleanup_stale_sessions(-1).await;
+        assert_eq!(
+            presence.get_presence("user-1").await.unwrap().session_count,
+            0
+        );
+        assert!(presence.online_user_ids().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offline_broadcast_to_other_users() {
+        let handler = test_handler();
+        let mut observer = connect(&handler, "sid-observer", "user-2").await;
+        let _rx = connect(&handler, "sid-1", "user-1").await;
+
+        handler.unregister_connection("sid-1").await;
+
+        let message = observer.try_recv().unwrap();
+        assert!(message.contains("user-presence"));
+        assert!(message.contains("offline"));
+    }
+}
@@ -259,24 +259,15 @@ impl SocketIOManager {
         stats
     }
 
-    /// Clean up stale sessions (called periodically)
-    pub async fn cleanup_stale_sessions(&self, timeout_seconds: i64) {
+    /// Sessions that haven't pinged within `timeout_seconds`
+    pub async fn stale_session_ids(&self, timeout_seconds: i64) -> Vec<String> {
         let now = chrono::Utc::now().timestamp();
-        let mut sessions_to_remove = Vec::new();
-
-        {
-            let sessions = self.sessions.read().await;
-            for (sid, session) in sessions.iter() {
-                if now - session.last_ping > timeout_seconds {
-                    sessions_to_remove.push(sid.clone());
-                }
-            }
-        }
-
-        for sid in sessions_to_remove {
-            tracing::warn!("Removing stale session: {}", sid);
-            self.remove_session(&sid).await;
-        }
+        let sessions = self.sessions.read().await;
+        sessions
+            .iter()
+            .filter(|(_, session)| now - session.last_ping > timeout_seconds)
+            .map(|(sid, _)| sid.clone())
+            .collect()
     }
 }
 
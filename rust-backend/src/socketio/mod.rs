@@ -14,6 +14,7 @@
 /// - RateLimit: Rate limiting and backpressure control
 /// - Presence: User presence tracking and typing indicators
 /// - Recovery: Connection recovery and session persistence
+/// - Streams: Active streaming completions, cancellable per chat
 /// - Health: Connection health monitoring and heartbeat system
 /// - CircuitBreaker: Fault tolerance and graceful degradation
 /// - Prometheus: Metrics export for monitoring systems
@@ -29,6 +30,7 @@ pub mod protocol;
 pub mod rate_limit;
 pub mod recovery;
 pub mod redis_adapter;
+pub mod streams;
 pub mod transport;
 pub mod ydoc;
 
@@ -41,6 +43,7 @@ pub use presence::{PresenceConfig, PresenceManager};
 pub use prometheus::PrometheusExporter;
 pub use rate_limit::{RateLimitConfig, RateLimiter};
 pub use recovery::{RecoveryConfig, RecoveryManager};
+pub use streams::StreamHandle;
 pub use ydoc::YDocManager;
 
 // Logging utilities - available but not re-exported to avoid unused warnings
@@ -1,7 +1,9 @@
 /// Presence System for Socket.IO
 ///
 /// Tracks user online/offline status, typing indicators, and last seen timestamps
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -55,8 +57,21 @@ pub struct PresenceManager {
 
     /// Configuration
     config: PresenceConfig,
+
+    /// Redis pool for sharing online users across instances
+    redis: Option<deadpool_redis::Pool>,
+
+    /// Per-instance Redis hash of user_id -> connection count
+    redis_key: String,
 }
 
+/// Prefix of the per-instance presence hashes in Redis
+const REDIS_PRESENCE_PREFIX: &str = "socketio:presence";
+
+/// TTL of an instance's presence hash; refreshed by `sync_redis`, so a crashed
+/// instance's users drop out once it expires
+const REDIS_PRESENCE_TTL: i64 = 180;
+
 /// Presence configuration
 #[derive(Debug, Clone)]
 pub struct PresenceConfig {
@@ -86,9 +101,18 @@ impl PresenceManager {
             presences: Arc::new(RwLock::new(HashMap::new())),
             typing_indicators: Arc::new(RwLock::new(HashMap::new())),
             config,
+            redis: None,
+            redis_key: String::new(),
         }
     }
 
+    /// Mirror this instance's connection counts into Redis
+    pub fn with_redis(mut self, redis: Option<deadpool_redis::Pool>, server_id: &str) -> Self {
+        self.redis = redis;
+        self.redis_key = format!("{}:{}", REDIS_PRESENCE_PREFIX, server_id);
+        self
+    }
+
     /// Get current unix timestamp
     fn now_timestamp() -> u64 {
         SystemTime::now()
@@ -97,8 +121,8 @@ impl PresenceManager {
             .as_secs()
     }
 
-    /// Mark user as online (new session)
-    pub async fn user_online(&self, user_id: &str) {
+    /// Mark user as online (new session), returning true if this is their first session
+    pub async fn user_online(&self, user_id: &str) -> bool {
         let mut presences = self.presences.write().await;
 
         let presence = presences
@@ -122,32 +146,150 @@ impl PresenceManager {
             user_id,
             presence.session_count
         );
+
+        let session_count = presence.session_count;
+        drop(presences);
+        self.sync_redis_user(user_id, session_count).await;
+
+        session_count == 1
     }
 
     /// Mark user as offline (session ended)
     pub async fn user_offline(&self, user_id: &str) -> bool {
         let mut presences = self.presences.write().await;
 
-        if let Some(presence) = presences.get_mut(user_id) {
-            if presence.session_count > 0 {
-                presence.session_count -= 1;
+        let Some(presence) = presences.get_mut(user_id) else {
+            return false; // Not found
+        };
+
+        if presence.session_count > 0 {
+            presence.session_count -= 1;
+        }
+
+        let session_count = presence.session_count;
+        if session_count == 0 {
+            presence.status = PresenceStatus::Offline;
+            presence.last_seen = Self::now_timestamp();
+            tracing::debug!("User {} is offline", user_id);
+        } else {
+            tracing::debug!(
+                "User {} still has {} active sessions",
+                user_id,
+                session_count
+            );
+        }
+
+        drop(presences);
+        self.sync_redis_user(user_id, session_count).await;
+
+        session_count == 0 // User went fully offline
+    }
+
+    /// Write one user's connection count to this instance's Redis hash
+    async fn sync_redis_user(&self, user_id: &str, session_count: usize) {
+        let Some(redis) = &self.redis else {
+            return;
+        };
+        let Ok(mut conn) = redis.get().await else {
+            tracing::warn!("Failed to get Redis connection for presence update");
+            return;
+        };
+
+        let result = if session_count > 0 {
+            conn.hset::<_, _, _, ()>(&self.redis_key, user_id, session_count)
+                .await
+        } else {
+            conn.hdel::<_, _, ()>(&self.redis_key, user_id).await
+        };
+        let result = match result {
+            Ok(()) => {
+                conn.expire::<_, ()>(&self.redis_key, REDIS_PRESENCE_TTL)
+                    .await
             }
+            Err(e) => Err(e),
+        };
 
-            if presence.session_count == 0 {
-                presence.status = PresenceStatus::Offline;
-                presence.last_seen = Self::now_timestamp();
-                tracing::debug!("User {} is offline", user_id);
-                return true; // User went fully offline
-            } else {
-                tracing::debug!(
-                    "User {} still has {} active sessions",
-                    user_id,
-                    presence.session_count
-                );
+        if let Err(e) = result {
+            tracing::warn!("Failed to update presence in Redis: {}", e);
+        }
+    }
+
+    /// Rewrite this instance's Redis hash from local state and refresh its TTL
+    ///
+    /// Called periodically as a heartbeat so the hash outlives idle periods but not the instance.
+    pub async fn sync_redis(&self) {
+        let Some(redis) = &self.redis else {
+            return;
+        };
+
+        let counts: Vec<(String, usize)> = {
+            let presences = self.presences.read().await;
+            presences
+                .values()
+                .filter(|p| p.session_count > 0)
+                .map(|p| (p.user_id.clone(), p.session_count))
+                .collect()
+        };
+
+        let Ok(mut conn) = redis.get().await else {
+            tracing::warn!("Failed to get Redis connection for presence sync");
+            return;
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&self.redis_key).ignore();
+        if !counts.is_empty() {
+            pipe.hset_multiple(&self.redis_key, &counts)
+                .ignore()
+                .expire(&self.redis_key, REDIS_PRESENCE_TTL)
+                .ignore();
+        }
+
+        if let Err(e) = pipe.query_async::<()>(&mut conn).await {
+            tracing::warn!("Failed to sync presence to Redis: {}", e);
+        }
+    }
+
+    /// IDs of users with at least one live connection, across all instances when Redis is used
+    pub async fn online_user_ids(&self) -> Vec<String> {
+        if let Some(redis) = &self.redis {
+            match Self::redis_online_user_ids(redis).await {
+                Ok(user_ids) => return user_ids,
+                Err(e) => tracing::warn!("Failed to read presence from Redis: {}", e),
             }
         }
 
-        false // User still has active sessions or not found
+        let presences = self.presences.read().await;
+        let user_ids: BTreeSet<String> = presences
+            .values()
+            .filter(|p| p.session_count > 0)
+            .map(|p| p.user_id.clone())
+            .collect();
+        user_ids.into_iter().collect()
+    }
+
+    async fn redis_online_user_ids(
+        redis: &deadpool_redis::Pool,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut conn = redis.get().await?;
+
+        let keys: Vec<String> = {
+            let mut iter = conn
+                .scan_match::<_, String>(format!("{}:*", REDIS_PRESENCE_PREFIX))
+                .await?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+
+        let mut user_ids = BTreeSet::new();
+        for key in keys {
+            let ids: Vec<String> = conn.hkeys(&key).await?;
+            user_ids.extend(ids);
+        }
+        Ok(user_ids.into_iter().collect())
     }
 
     /// Update user activity (prevents away status)
@@ -419,6 +561,19 @@ mod tests {
         assert!(offline); // Now fully offline
     }
 
+    #[tokio::test]
+    async fn test_online_user_ids() {
+        let manager = PresenceManager::default();
+
+        assert!(manager.user_online("user-1").await);
+        assert!(!manager.user_online("user-1").await);
+        assert!(manager.user_online("user-2").await);
+        assert_eq!(manager.online_user_ids().await, vec!["user-1", "user-2"]);
+
+        manager.user_offline("user-2").await;
+        assert_eq!(manager.online_user_ids().await, vec!["user-1"]);
+    }
+
     #[tokio::test]
     async fn test_typing_indicators() {
         let manager = PresenceManager::default();
@@ -0,0 +1,145 @@
+/// Active Stream Registry
+///
+/// Associates in-flight streaming completions with their chat so a client can stop
+/// generation over the socket. Each stream holds a `CancellationToken`; cancelling it
+/// makes the streaming task stop reading (and drop) the upstream response.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+struct ActiveStream {
+    id: u64,
+    user_id: String,
+    token: CancellationToken,
+}
+
+/// Registry of active streaming completions, keyed by chat id
+#[derive(Clone, Default)]
+pub struct StreamRegistry {
+    streams: Arc<Mutex<HashMap<String, ActiveStream>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// Registration of one streaming completion; unregisters itself on drop
+pub struct StreamHandle {
+    registry: StreamRegistry,
+    chat_id: String,
+    id: u64,
+    token: CancellationToken,
+}
+
+impl StreamHandle {
+    /// Token cancelled when the client asks to stop generation
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        let mut streams = self.registry.streams.lock().unwrap();
+        // A newer stream for the same chat may have replaced this one
+        if streams.get(&self.chat_id).is_some_and(|s| s.id == self.id) {
+            streams.remove(&self.chat_id);
+        }
+    }
+}
+
+impl StreamRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a streaming completion for a chat, cancelling any previous one for it
+    pub fn register(&self, chat_id: &str, user_id: &str) -> StreamHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+
+        let previous = self.streams.lock().unwrap().insert(
+            chat_id.to_string(),
+            ActiveStream {
+                id,
+                user_id: user_id.to_string(),
+                token: token.clone(),
+            },
+        );
+        if let Some(previous) = previous {
+            previous.token.cancel();
+        }
+
+        StreamHandle {
+            registry: self.clone(),
+            chat_id: chat_id.to_string(),
+            id,
+            token,
+        }
+    }
+
+    /// Cancel the chat's active stream if it belongs to `user_id`
+    ///
+    /// Returns whether a stream was cancelled.
+    pub fn cancel(&self, chat_id: &str, user_id: &str) -> bool {
+        let streams = self.streams.lock().unwrap();
+        match streams.get(chat_id) {
+            Some(stream) if stream.user_id == user_id => {
+                stream.token.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a chat has a streaming completion in flight
+    pub fn is_active(&self, chat_id: &str) -> bool {
+        self.streams.lock().unwrap().contains_key(chat_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_stop_cancels_upstream_read_task() {
+        let registry = StreamRegistry::new();
+        let handle = registry.register("chat-1", "user-1");
+        let token = handle.token().clone();
+
+        // Stands in for the task reading the upstream response
+        let (_tx, mut upstream) = tokio::sync::mpsc::channel::<String>(1);
+        let reader = tokio::spawn(async move {
+            let _handle = handle;
+            tokio::select! {
+                _ = upstream.recv() => false,
+                _ = token.cancelled() => true,
+            }
+        });
+
+        // Only the chat's owner may stop it
+        assert!(!registry.cancel("chat-1", "user-2"));
+        assert!(registry.cancel("chat-1", "user-1"));
+
+        let cancelled = tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cancelled);
+        assert!(!registry.is_active("chat-1"));
+    }
+
+    #[test]
+    fn test_new_stream_replaces_previous() {
+        let registry = StreamRegistry::new();
+        let first = registry.register("chat-1", "user-1");
+        let second = registry.register("chat-1", "user-1");
+        assert!(first.token().is_cancelled());
+
+        // Dropping the stale handle must not unregister the new stream
+        drop(first);
+        assert!(registry.is_active("chat-1"));
+        drop(second);
+        assert!(!registry.is_active("chat-1"));
+    }
+}
@@ -15,6 +15,7 @@ use actix_ws::Message as WsMessage;
 use futures_util::StreamExt;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
 /// Polling response queue - stores messages to be sent to polling clients
@@ -41,10 +42,14 @@ async fn get_polling_responses(sid: &str) -> Vec<String> {
 }
 
 /// WebSocket transport handler
+///
+/// `connection` holds the socket's slot under the WebSocket connection limits until the
+/// socket closes, or until the client misses the heartbeat deadline.
 pub async fn websocket_handler(
     req: HttpRequest,
     stream: web::Payload,
     event_handler: web::Data<EventHandler>,
+    connection: Option<crate::socket::ConnectionGuard>,
 ) -> Result<HttpResponse, Error> {
     tracing::info!("WebSocket connection request from: {:?}", req.peer_addr());
     tracing::debug!("WebSocket headers: {:?}", req.headers());
@@ -102,9 +107,21 @@ pub async fn websocket_handler(
     actix_web::rt::spawn(async move {
         let event_handler = event_handler_clone;
         let sid = sid_clone;
-        let http_client = reqwest::Client::new();
-
-        while let Some(Ok(msg)) = msg_stream.next().await {
+        let http_client = crate::utils::http::client();
+        let _connection = connection;
+        // Clients that vanish without a close frame are dropped after the heartbeat deadline
+        let heartbeat_deadline =
+            Duration::from_millis(manager.ping_interval() + manager.ping_timeout());
+
+        loop {
+            let msg = match tokio::time::timeout(heartbeat_deadline, msg_stream.next()).await {
+                Ok(Some(Ok(msg))) => msg,
+                Ok(_) => break,
+                Err(_) => {
+                    tracing::info!("Client {} missed the heartbeat deadline", sid);
+                    break;
+                }
+            };
             match msg {
                 WsMessage::Text(text) => {
                     tracing::debug!("Received text message: {}", text);
@@ -216,6 +233,8 @@ async fn handle_socket_packet(
                     "channel-events" => event_handler.handle_channel_event(sid, data).await,
                     "channel:join" => event_handler.handle_channel_join(sid, data).await,
                     "channel:leave" => event_handler.handle_channel_leave(sid, data).await,
+                    "chat:join" => event_handler.handle_chat_join(sid, data).await,
+                    "chat:leave" => event_handler.handle_chat_leave(sid, data).await,
                     "ydoc:document:join" => event_handler.handle_ydoc_join(sid, data).await,
                     "ydoc:document:leave" => event_handler.handle_ydoc_leave(sid, data).await,
                     "ydoc:document:update" => event_handler.handle_ydoc_update(sid, data).await,
@@ -226,8 +245,11 @@ async fn handle_socket_packet(
                         event_handler.handle_ydoc_awareness_update(sid, data).await
                     }
                     "presence:status" => event_handler.handle_presence_status(sid, data).await,
-                    "typing:start" => event_handler.handle_typing_start(sid, data).await,
-                    "typing:stop" => event_handler.handle_typing_stop(sid, data).await,
+                    "typing:start" | "typing" => event_handler.handle_typing_start(sid, data).await,
+                    "typing:stop" | "stop_typing" => {
+                        event_handler.handle_typing_stop(sid, data).await
+                    }
+                    "stop_generation" => event_handler.handle_stop_generation(sid, data).await,
                     "presence:get" => {
                         match event_handler.handle_get_presences(sid, data).await {
                             Ok(response) => {
@@ -347,7 +369,7 @@ pub async fn polling_handler(
 
                 // Handle messages if event_handler is provided
                 if let Some(_handler) = event_handler {
-                    let _http_client = reqwest::Client::new();
+                    let _http_client = crate::utils::http::client();
 
                     // Split by packet separator
                     for packet_str in body_str.split('\x1e') {
@@ -0,0 +1,54 @@
+//! Shared fixtures for database-backed service tests
+//!
+//! Tests using these helpers need `TEST_DATABASE_URL` and are marked `#[ignore]`;
+//! run them with `cargo test -- --ignored`.
+
+use crate::db::Database;
+use crate::models::group::{Group, GroupForm};
+use crate::models::User;
+use crate::services::group::GroupService;
+use crate::services::UserService;
+
+/// Fresh, migrated database isolated from other tests
+pub async fn test_db() -> Database {
+    Database::new_for_tests()
+        .await
+        .expect("failed to set up test database")
+}
+
+/// Insert a user with the given role
+pub async fn seed_user(db: &Database, role: &str) -> User {
+    let id = uuid::Uuid::new_v4().to_string();
+    UserService::new(db)
+        .create_user(
+            &id,
+            &format!("Test {}", role),
+            &format!("{}@example.com", id),
+            role,
+            "",
+        )
+        .await
+        .expect("failed to seed user")
+}
+
+/// Insert a group owned by `owner` containing `members`
+pub async fn seed_group(db: &Database, owner: &User, members: &[&User]) -> Group {
+    let service = GroupService::new(db);
+    let group = service
+        .insert_new_group(
+            &owner.id,
+            &GroupForm {
+                name: "Test Group".to_string(),
+                description: String::new(),
+                permissions: None,
+            },
+        )
+        .await
+        .expect("failed to seed group");
+
+    let user_ids: Vec<String> = members.iter().map(|user| user.id.clone()).collect();
+    service
+        .add_users_to_group(&group.id, &user_ids)
+        .await
+        .expect("failed to add group members")
+}
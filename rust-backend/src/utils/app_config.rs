@@ -0,0 +1,500 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+use crate::middleware::maintenance::maintenance_retry_after;
+use crate::utils::permissions::{apply_template, default_permissions};
+
+/// Body of `GET /api/config`, in the shape the Open WebUI frontend reads
+///
+/// Sections a caller may not see are left out rather than nulled: anonymous callers get
+/// no session section, and with REQUIRE_AUTH_FOR_CONFIG only what the login page needs.
+#[derive(Debug, Clone, Serialize)]
+pub struct AppConfigResponse {
+    pub status: bool,
+    #[serde(flatten)]
+    pub instance: Option<InstanceInfo>,
+    pub features: Features,
+    pub oauth: OAuthConfig,
+    /// Only to anonymous callers before the first admin has signed up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub onboarding: Option<bool>,
+    /// While MAINTENANCE_MODE is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintenance: Option<MaintenanceNotice>,
+    /// When SIGNUP_CAPTCHA_PROVIDER is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captcha: Option<CaptchaConfig>,
+    /// When GUEST_MODE is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub guest: Option<GuestConfig>,
+    /// Only to signed-in callers
+    #[serde(flatten)]
+    pub session: Option<SessionConfig>,
+}
+
+/// Identifies the instance; hidden from anonymous callers under REQUIRE_AUTH_FOR_CONFIG
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceInfo {
+    /// WEBUI_NAME
+    pub name: String,
+    /// This build's crate version
+    pub version: &'static str,
+    /// Not configurable; the frontend falls back to the browser's language
+    pub default_locale: &'static str,
+    /// Set once the first admin finishes onboarding
+    pub onboarding_completed: bool,
+}
+
+/// `features` flags, in tiers by who may see them
+#[derive(Debug, Clone, Serialize)]
+pub struct Features {
+    #[serde(flatten)]
+    pub login: LoginFeatures,
+    #[serde(flatten)]
+    pub instance: Option<InstanceFeatures>,
+    #[serde(flatten)]
+    pub user: Option<UserFeatures>,
+}
+
+/// What the login page needs, shown to everyone
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginFeatures {
+    /// WEBUI_AUTH
+    pub auth: bool,
+    /// Trusted-header sign-in isn't implemented
+    pub auth_trusted_header: bool,
+    /// ENABLE_SIGNUP
+    pub enable_signup: bool,
+    /// ENABLE_LOGIN_FORM
+    pub enable_login_form: bool,
+    /// ENABLE_LDAP
+    pub enable_ldap: bool,
+    /// Signup doesn't ask for the password twice
+    pub enable_signup_password_confirmation: bool,
+    /// GUEST_MODE
+    pub enable_guest_mode: bool,
+}
+
+/// Instance-wide flags, hidden from anonymous callers under REQUIRE_AUTH_FOR_CONFIG
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceFeatures {
+    /// ENABLE_API_KEY
+    pub enable_api_key: bool,
+    /// ENABLE_WEBSOCKET_SUPPORT
+    pub enable_websocket: bool,
+    /// ENABLE_VERSION_UPDATE_CHECK
+    pub enable_version_update_check: bool,
+}
+
+/// Flags for signed-in users, each from the ENABLE_* setting of the same name
+#[derive(Debug, Clone, Serialize)]
+pub struct UserFeatures {
+    pub enable_direct_connections: bool,
+    pub enable_channels: bool,
+    pub enable_notes: bool,
+    pub enable_web_search: bool,
+    pub enable_code_execution: bool,
+    pub enable_code_interpreter: bool,
+    pub enable_image_generation: bool,
+    pub enable_autocomplete_generation: bool,
+    pub enable_community_sharing: bool,
+    pub enable_message_rating: bool,
+    pub enable_user_webhooks: bool,
+    pub enable_admin_export: bool,
+    pub enable_admin_chat_access: bool,
+    pub enable_google_drive_integration: bool,
+    pub enable_onedrive_integration: bool,
+}
+
+/// Sign-in providers that are configured and enabled, name -> display name
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuthConfig {
+    pub providers: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceNotice {
+    pub enabled: bool,
+    /// MAINTENANCE_MESSAGE
+    pub message: String,
+    /// Seconds until MAINTENANCE_UNTIL, when set
+    pub retry_after: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaptchaConfig {
+    /// SIGNUP_CAPTCHA_PROVIDER
+    pub provider: String,
+    /// CAPTCHA_SITE_KEY
+    pub site_key: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GuestConfig {
+    /// GUEST_MODELS
+    pub models: Vec<String>,
+}
+
+/// Settings the chat UI needs once signed in
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionConfig {
+    /// DEFAULT_MODELS as the comma-separated string it was set as, `null` when empty
+    pub default_models: Option<String>,
+    /// DEFAULT_PROMPT_SUGGESTIONS
+    pub default_prompt_suggestions: Value,
+    pub user_count: i64,
+    pub code: CodeConfig,
+    pub audio: AudioConfig,
+    pub file: FileConfig,
+    /// USER_PERMISSIONS over the defaults; group grants aren't folded in
+    pub permissions: Value,
+    /// DEFAULT_KNOWLEDGE_ACCESS, given to new knowledge bases created without access control
+    pub default_knowledge_access: Option<Value>,
+    pub google_drive: GoogleDriveConfig,
+    pub onedrive: OneDriveConfig,
+    pub ui: UiConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CodeConfig {
+    /// CODE_EXECUTION_ENGINE
+    pub engine: String,
+    /// CODE_INTERPRETER_ENGINE
+    pub interpreter_engine: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioConfig {
+    pub tts: TtsConfig,
+    pub stt: SttConfig,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TtsConfig {
+    /// TTS_ENGINE
+    pub engine: String,
+    /// TTS_VOICE
+    pub voice: String,
+    /// TTS_SPLIT_ON
+    pub split_on: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SttConfig {
+    /// STT_ENGINE
+    pub engine: String,
+}
+
+/// Upload limits the frontend enforces before sending; fixed in this backend
+#[derive(Debug, Clone, Serialize)]
+pub struct FileConfig {
+    pub max_size: u64,
+    pub max_count: u32,
+    pub image_compression: ImageCompression,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageCompression {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The Drive picker isn't wired up, so its credentials are always empty
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GoogleDriveConfig {
+    pub client_id: String,
+    pub api_key: String,
+}
+
+/// The OneDrive picker isn't wired up, so its settings are always empty
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OneDriveConfig {
+    pub client_id_personal: String,
+    pub client_id_business: String,
+    pub sharepoint_url: String,
+    pub sharepoint_tenant_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UiConfig {
+    /// PENDING_USER_OVERLAY_TITLE
+    pub pending_user_overlay_title: Option<String>,
+    /// PENDING_USER_OVERLAY_CONTENT
+    pub pending_user_overlay_content: Option<String>,
+    /// RESPONSE_WATERMARK
+    pub response_watermark: Option<String>,
+}
+
+impl Features {
+    /// Flags for a caller who may see instance details and/or is signed in
+    pub fn new(config: &Config, instance: bool, signed_in: bool) -> Self {
+        Self {
+            login: LoginFeatures {
+                auth: config.webui_auth,
+                auth_trusted_header: false,
+                enable_signup: config.enable_signup,
+                enable_login_form: config.enable_login_form,
+                enable_ldap: config.enable_ldap,
+                enable_signup_password_confirmation: false,
+                enable_guest_mode: config.guest_mode,
+            },
+            instance: instance.then_some(InstanceFeatures {
+                enable_api_key: config.enable_api_key,
+                enable_websocket: config.enable_websocket_support,
+                enable_version_update_check: config.enable_version_update_check,
+            }),
+            user: signed_in.then_some(UserFeatures {
+                enable_direct_connections: config.enable_direct_connections,
+                enable_channels: config.enable_channels,
+                enable_notes: config.enable_notes,
+                enable_web_search: config.enable_web_search,
+                enable_code_execution: config.enable_code_execution,
+                enable_code_interpreter: config.enable_code_interpreter,
+                enable_image_generation: config.enable_image_generation,
+                enable_autocomplete_generation: config.enable_autocomplete_generation,
+                enable_community_sharing: config.enable_community_sharing,
+                enable_message_rating: config.enable_message_rating,
+                enable_user_webhooks: config.enable_user_webhooks,
+                enable_admin_export: config.enable_admin_export,
+                enable_admin_chat_access: config.enable_admin_chat_access,
+                enable_google_drive_integration: config.enable_google_drive_integration,
+                enable_onedrive_integration: config.enable_onedrive_integration,
+            }),
+        }
+    }
+}
+
+impl SessionConfig {
+    fn new(config: &Config, user_count: i64) -> Self {
+        Self {
+            default_models: Some(config.default_models.clone()).filter(|models| !models.is_empty()),
+            default_prompt_suggestions: config.default_prompt_suggestions.clone(),
+            user_count,
+            code: CodeConfig {
+                engine: config.code_execution_engine.clone(),
+                interpreter_engine: config.code_interpreter_engine.clone(),
+            },
+            audio: AudioConfig {
+                tts: TtsConfig {
+                    engine: config.tts_engine.clone(),
+                    voice: config.tts_voice.clone(),
+                    split_on: config.tts_split_on.clone(),
+                },
+                stt: SttConfig {
+                    engine: config.stt_engine.clone(),
+                },
+            },
+            file: FileConfig {
+                max_size: 10 * 1024 * 1024,
+                max_count: 10,
+                image_compression: ImageCompression {
+                    width: 1024,
+                    height: 1024,
+                },
+            },
+            permissions: apply_template(Some(&default_permissions()), &config.user_permissions),
+            default_knowledge_access: config.default_knowledge_access.clone(),
+            google_drive: GoogleDriveConfig::default(),
+            onedrive: OneDriveConfig::default(),
+            ui: UiConfig {
+                pending_user_overlay_title: config.pending_user_overlay_title.clone(),
+                pending_user_overlay_content: config.pending_user_overlay_content.clone(),
+                response_watermark: config.response_watermark.clone(),
+            },
+        }
+    }
+}
+
+/// Body of `/api/config` for a caller that is or isn't signed in
+pub fn app_config_response(
+    config: &Config,
+    oauth_providers: Map<String, Value>,
+    authenticated: bool,
+    user_count: i64,
+) -> AppConfigResponse {
+    // Private deployments don't reveal the instance or its features before sign-in
+    let instance = authenticated || !config.require_auth_for_config;
+
+    AppConfigResponse {
+        status: true,
+        instance: instance.then(|| InstanceInfo {
+            name: config.webui_name.clone(),
+            version: env!("CARGO_PKG_VERSION"),
+            default_locale: "en-US",
+            onboarding_completed: config.onboarding_completed,
+        }),
+        features: Features::new(config, instance, authenticated),
+        oauth: OAuthConfig {
+            providers: oauth_providers,
+        },
+        // Once an admin has completed onboarding, deleting every user doesn't reopen it
+        onboarding: (!authenticated && user_count == 0 && !config.onboarding_completed)
+            .then_some(true),
+        maintenance: maintenance_retry_after(config, chrono::Utc::now().timestamp()).map(
+            |retry_after| MaintenanceNotice {
+                enabled: true,
+                message: config.maintenance_message.clone(),
+                retry_after,
+            },
+        ),
+        captcha: config
+            .signup_captcha_provider
+            .as_ref()
+            .map(|provider| CaptchaConfig {
+                provider: provider.clone(),
+                site_key: config.captcha_site_key.clone(),
+            }),
+        guest: config.guest_mode.then(|| GuestConfig {
+            models: config.guest_models.clone(),
+        }),
+        session: authenticated.then(|| SessionConfig::new(config, user_count)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn providers() -> Map<String, Value> {
+        [("google".to_string(), json!("google"))]
+            .into_iter()
+            .collect()
+    }
+
+    fn response(config: &Config, authenticated: bool) -> Value {
+        serde_json::to_value(app_config_response(config, providers(), authenticated, 3)).unwrap()
+    }
+
+    #[test]
+    fn test_config_open_by_default() {
+        let config = Config::from_lookup(|_| None).unwrap();
+
+        let anonymous = response(&config, false);
+        assert_eq!(anonymous["name"], json!(config.webui_name));
+        assert!(anonymous["version"].is_string());
+        assert!(anonymous["features"]["enable_websocket"].is_boolean());
+        assert!(anonymous.get("user_count").is_none());
+        assert!(anonymous["features"].get("enable_channels").is_none());
+    }
+
+    #[test]
+    fn test_require_auth_for_config_limits_anonymous_callers() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.require_auth_for_config = true;
+
+        let anonymous = response(&config, false);
+        assert!(anonymous.get("name").is_none());
+        assert!(anonymous.get("version").is_none());
+        assert!(anonymous["features"].get("enable_websocket").is_none());
+        // Still enough for the login page
+        assert_eq!(
+            anonymous["features"]["enable_signup"],
+            json!(config.enable_signup)
+        );
+        assert_eq!(
+            anonymous["features"]["enable_login_form"],
+            json!(config.enable_login_form)
+        );
+        assert_eq!(anonymous["oauth"]["providers"]["google"], json!("google"));
+
+        let signed_in = response(&config, true);
+        assert_eq!(signed_in["name"], json!(config.webui_name));
+        assert_eq!(signed_in["user_count"], json!(3));
+        assert!(signed_in["features"]["enable_channels"].is_boolean());
+    }
+
+    /// Replaces every leaf with its JSON type, keeping only the shape
+    fn shape(value: &Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), shape(value)))
+                    .collect(),
+            ),
+            Value::Null => json!("null"),
+            Value::Bool(_) => json!("bool"),
+            Value::Number(_) => json!("number"),
+            Value::String(_) => json!("string"),
+            Value::Array(_) => json!("array"),
+        }
+    }
+
+    #[test]
+    fn test_signed_in_response_shape() {
+        let config = Config::from_lookup(|_| None).unwrap();
+
+        assert_eq!(
+            shape(&response(&config, true)),
+            json!({
+                "status": "bool",
+                "name": "string",
+                "version": "string",
+                "default_locale": "string",
+                "onboarding_completed": "bool",
+                "features": {
+                    "auth": "bool",
+                    "auth_trusted_header": "bool",
+                    "enable_signup": "bool",
+                    "enable_login_form": "bool",
+                    "enable_ldap": "bool",
+                    "enable_signup_password_confirmation": "bool",
+                    "enable_guest_mode": "bool",
+                    "enable_api_key": "bool",
+                    "enable_websocket": "bool",
+                    "enable_version_update_check": "bool",
+                    "enable_direct_connections": "bool",
+                    "enable_channels": "bool",
+                    "enable_notes": "bool",
+                    "enable_web_search": "bool",
+                    "enable_code_execution": "bool",
+                    "enable_code_interpreter": "bool",
+                    "enable_image_generation": "bool",
+                    "enable_autocomplete_generation": "bool",
+                    "enable_community_sharing": "bool",
+                    "enable_message_rating": "bool",
+                    "enable_user_webhooks": "bool",
+                    "enable_admin_export": "bool",
+                    "enable_admin_chat_access": "bool",
+                    "enable_google_drive_integration": "bool",
+                    "enable_onedrive_integration": "bool"
+                },
+                "oauth": {"providers": {"google": "string"}},
+                "default_models": "null",
+                "default_prompt_suggestions": "array",
+                "user_count": "number",
+                "code": {"engine": "string", "interpreter_engine": "string"},
+                "audio": {
+                    "tts": {"engine": "string", "voice": "string", "split_on": "string"},
+                    "stt": {"engine": "string"}
+                },
+                "file": {
+                    "max_size": "number",
+                    "max_count": "number",
+                    "image_compression": {"width": "number", "height": "number"}
+                },
+                "permissions": {
+                    "workspace": shape(&default_permissions()["workspace"]),
+                    "sharing": shape(&default_permissions()["sharing"]),
+                    "chat": shape(&default_permissions()["chat"]),
+                    "features": shape(&default_permissions()["features"])
+                },
+                "default_knowledge_access": "null",
+                "google_drive": {"client_id": "string", "api_key": "string"},
+                "onedrive": {
+                    "client_id_personal": "string",
+                    "client_id_business": "string",
+                    "sharepoint_url": "string",
+                    "sharepoint_tenant_id": "string"
+                },
+                "ui": {
+                    "pending_user_overlay_title": "null",
+                    "pending_user_overlay_content": "null",
+                    "response_watermark": "null"
+                }
+            })
+        );
+    }
+}
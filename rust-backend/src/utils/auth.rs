@@ -1,7 +1,13 @@
 use crate::error::{AppError, AppResult};
 use crate::models::Claims;
+use base64::{engine::general_purpose, Engine as _};
 use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use sha2::Sha256;
+
+/// Purpose of the key MFA challenge tokens are signed with
+const MFA_CHALLENGE_PURPOSE: &str = "mfa-challenge";
 
 pub fn create_jwt(user_id: &str, secret: &str, expires_in: &str) -> AppResult<String> {
     let expiration = parse_duration(expires_in)?;
@@ -14,6 +20,8 @@ pub fn create_jwt(user_id: &str, secret: &str, expires_in: &str) -> AppResult<St
         sub: user_id.to_string(),
         exp: Some(exp),
         iat: Some(Utc::now().timestamp()),
+        impersonated_by: None,
+        mfa_setup_pending: false,
     };
 
     let token = encode(
@@ -25,6 +33,111 @@ pub fn create_jwt(user_id: &str, secret: &str, expires_in: &str) -> AppResult<St
     Ok(token)
 }
 
+/// Short-lived token acting as `user_id`, marked with the admin who requested it
+pub fn create_impersonation_jwt(
+    user_id: &str,
+    admin_id: &str,
+    secret: &str,
+    ttl: Duration,
+) -> AppResult<(String, i64)> {
+    let now = Utc::now();
+    let exp = now
+        .checked_add_signed(ttl)
+        .ok_or_else(|| AppError::InternalServerError("Invalid expiration time".to_string()))?
+        .timestamp();
+
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: Some(exp),
+        iat: Some(now.timestamp()),
+        impersonated_by: Some(admin_id.to_string()),
+        mfa_setup_pending: false,
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?;
+
+    Ok((token, exp))
+}
+
+/// Short-lived token for an admin who must set up MFA, good for nothing else
+pub fn create_mfa_setup_jwt(
+    user_id: &str,
+    secret: &str,
+    ttl: Duration,
+) -> AppResult<(String, i64)> {
+    let now = Utc::now();
+    let exp = now
+        .checked_add_signed(ttl)
+        .ok_or_else(|| AppError::InternalServerError("Invalid expiration time".to_string()))?
+        .timestamp();
+
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: Some(exp),
+        iat: Some(now.timestamp()),
+        impersonated_by: None,
+        mfa_setup_pending: true,
+    };
+
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?;
+
+    Ok((token, exp))
+}
+
+/// Token for a sign-in whose first factor passed and which still owes an MFA code
+///
+/// It is signed with a key derived for the purpose, so it never passes as a session token.
+pub fn create_mfa_challenge_jwt(
+    user_id: &str,
+    secret: &str,
+    ttl: Duration,
+) -> AppResult<(String, i64)> {
+    let now = Utc::now();
+    let exp = now
+        .checked_add_signed(ttl)
+        .ok_or_else(|| AppError::InternalServerError("Invalid expiration time".to_string()))?
+        .timestamp();
+
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: Some(exp),
+        iat: Some(now.timestamp()),
+        impersonated_by: None,
+        mfa_setup_pending: false,
+    };
+
+    let key = derive_key(secret, MFA_CHALLENGE_PURPOSE);
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(key.as_bytes()),
+    )?;
+
+    Ok((token, exp))
+}
+
+pub fn verify_mfa_challenge_jwt(token: &str, secret: &str) -> AppResult<Claims> {
+    verify_jwt(token, &derive_key(secret, MFA_CHALLENGE_PURPOSE))
+}
+
+/// Key for signing something other than sessions, derived from WEBUI_SECRET_KEY
+///
+/// Tokens signed with it can't be replayed as session tokens, or the other way round.
+pub fn derive_key(secret: &str, purpose: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(purpose.as_bytes());
+    general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+}
+
 pub fn verify_jwt(token: &str, secret: &str) -> AppResult<Claims> {
     let token_data = decode::<Claims>(
         token,
@@ -62,7 +175,6 @@ pub fn parse_duration(duration_str: &str) -> AppResult<Duration> {
     }
 }
 
-#[allow(dead_code)]
 pub fn extract_bearer_token(auth_header: &str) -> Option<String> {
     if auth_header.starts_with("Bearer ") {
         Some(auth_header[7..].to_string())
@@ -70,3 +182,38 @@ pub fn extract_bearer_token(auth_header: &str) -> Option<String> {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impersonation_claim_round_trip() {
+        let (token, exp) =
+            create_impersonation_jwt("user-1", "admin-1", "secret", Duration::minutes(15)).unwrap();
+
+        let claims = verify_jwt(&token, "secret").unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.impersonated_by.as_deref(), Some("admin-1"));
+        assert_eq!(claims.exp, Some(exp));
+        assert!(exp - Utc::now().timestamp() <= 15 * 60);
+
+        // Regular session tokens carry no impersonation claim
+        let token = create_jwt("user-1", "secret", "1h").unwrap();
+        assert_eq!(verify_jwt(&token, "secret").unwrap().impersonated_by, None);
+    }
+
+    #[test]
+    fn test_mfa_challenge_is_not_a_session_token() {
+        let (challenge, _) =
+            create_mfa_challenge_jwt("user-1", "secret", Duration::minutes(5)).unwrap();
+        assert!(verify_jwt(&challenge, "secret").is_err());
+        assert_eq!(
+            verify_mfa_challenge_jwt(&challenge, "secret").unwrap().sub,
+            "user-1"
+        );
+
+        let session = create_jwt("user-1", "secret", "1h").unwrap();
+        assert!(verify_mfa_challenge_jwt(&session, "secret").is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Server-side titles for new chats
+//!
+//! With ENABLE_AUTO_TITLE on, a chat completion answering a chat's first user message
+//! also names the chat, in the background so the response isn't delayed. The title comes
+//! from TITLE_GENERATION_MODEL (or TASK_MODEL, or the chat's own model) and is announced
+//! to the owner's sessions with a `chat.title.updated` event. Chats the user has already
+//! named keep their title.
+use actix_web::web;
+use serde_json::{json, Value};
+use std::future::Future;
+
+use crate::db::Database;
+use crate::error::AppResult;
+use crate::middleware::AuthUser;
+use crate::services::chat::{ChatService, DEFAULT_CHAT_TITLE};
+use crate::AppState;
+
+pub const TITLE_UPDATED_EVENT: &str = "chat.title.updated";
+
+/// Longest title kept from the model's answer, in characters
+const MAX_TITLE_CHARS: usize = 100;
+
+/// Whether `messages` start a chat: no assistant turn has happened yet
+pub fn is_first_exchange(messages: &[Value]) -> bool {
+    let has = |role: &str| {
+        messages
+            .iter()
+            .any(|m| m.get("role").and_then(Value::as_str) == Some(role))
+    };
+    has("user") && !has("assistant")
+}
+
+/// The title in a model's answer: `{"title": ...}` when it followed the prompt, else
+/// the answer's first line
+pub fn parse_title(answer: &str) -> Option<String> {
+    let from_json = match (answer.find('{'), answer.rfind('}')) {
+        (Some(start), Some(end)) if start < end => {
+            serde_json::from_str::<Value>(&answer[start..=end])
+                .ok()
+                .and_then(|v| v.get("title").and_then(Value::as_str).map(String::from))
+        }
+        _ => None,
+    };
+    let title = from_json.unwrap_or_else(|| answer.lines().next().unwrap_or("").to_string());
+    let title = title.trim().trim_matches('"').trim();
+    (!title.is_empty()).then(|| title.chars().take(MAX_TITLE_CHARS).collect())
+}
+
+/// Name `chat_id` with the title `generate` produces, unless the user has named it
+///
+/// `generate` is only called for an untitled chat. Returns the title that was set.
+pub async fn title_untitled_chat<F, Fut>(
+    db: &Database,
+    user_id: &str,
+    chat_id: &str,
+    generate: F,
+) -> AppResult<Option<String>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = AppResult<String>>,
+{
+    let service = ChatService::new(db);
+    let untitled = service
+        .get_chat_by_id_and_user_id(chat_id, user_id)
+        .await?
+        .is_some_and(|chat| chat.title == DEFAULT_CHAT_TITLE);
+    if !untitled {
+        return Ok(None);
+    }
+
+    let Some(title) = parse_title(&generate().await?) else {
+        return Ok(None);
+    };
+    // The user may have renamed the chat while the title was generated
+    let updated = service
+        .set_title_if_untitled(chat_id, user_id, &title)
+        .await?;
+    Ok(updated.then_some(title))
+}
+
+/// Title a new chat in the background, after a completion for `messages` succeeded
+pub fn spawn(
+    state: web::Data<AppState>,
+    auth_user: AuthUser,
+    chat_id: String,
+    chat_model: String,
+    messages: Vec<Value>,
+) {
+    let model = {
+        let config = state.config.read().unwrap();
+        if !config.enable_auto_title || !is_first_exchange(&messages) {
+            return;
+        }
+        config
+            .title_generation_model
+            .clone()
+            .or_else(|| config.task_model.clone())
+            .unwrap_or(chat_model)
+    };
+
+    tokio::spawn(async move {
+        let user_id = auth_user.user.id.clone();
+        let result = title_untitled_chat(&state.db, &user_id, &chat_id, || async {
+            crate::routes::tasks::generate_chat_title(&state, &auth_user, &model, &messages).await
+        })
+        .await;
+
+        match result {
+            Ok(Some(title)) => {
+                tracing::debug!("Titled chat {}: {}", chat_id, title);
+                if let Some(socket_state) = &state.socket_state {
+                    let data = json!({ "chat_id": chat_id, "title": title });
+                    if let Err(e) = socket_state
+                        .native_handler
+                        .emit_to_user(&user_id, TITLE_UPDATED_EVENT, data)
+                        .await
+                    {
+                        tracing::debug!("Failed to emit {}: {}", TITLE_UPDATED_EVENT, e);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to title chat {}: {}", chat_id, e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use crate::models::chat::CreateChatRequest;
+    use crate::test_utils::{seed_user, test_db};
+
+    #[test]
+    fn test_first_exchange_and_title_parsing() {
+        let user = json!({ "role": "user", "content": "How do I bake cookies?" });
+        let system = json!({ "role": "system", "content": "Be brief" });
+        let assistant = json!({ "role": "assistant", "content": "Preheat the oven" });
+        assert!(is_first_exchange(&[system.clone(), user.clone()]));
+        assert!(!is_first_exchange(&[user.clone(), assistant, user]));
+        assert!(!is_first_exchange(&[system]));
+
+        assert_eq!(
+            parse_title("Sure! { \"title\": \"🍪 Cookie Baking\" }").as_deref(),
+            Some("🍪 Cookie Baking")
+        );
+        assert_eq!(
+            parse_title("\"Cookie Baking\"\nmore").as_deref(),
+            Some("Cookie Baking")
+        );
+        assert_eq!(parse_title("   "), None);
+    }
+
+    async fn create_chat(db: &Database, user_id: &str, title: Option<&str>) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        ChatService::new(db)
+            .create_chat(
+                user_id,
+                CreateChatRequest {
+                    id: id.clone(),
+                    title: title.map(String::from),
+                    chat: json!({}),
+                    folder_id: None,
+                    archived: None,
+                    pinned: None,
+                    share_id: None,
+                    meta: None,
+                },
+            )
+            .await
+            .unwrap();
+        id
+    }
+
+    #[tokio::test]
+    #[ignore] // Requires TEST_DATABASE_URL
+    async fn test_new_chat_gets_generated_title() {
+        let db = test_db().await;
+        let user = seed_user(&db, "user").await;
+        let chat_id = create_chat(&db, &user.id, None).await;
+
+        let title = title_untitled_chat(&db, &user.id, &chat_id, || async {
+            Ok(r#"{ "title": "🍪 Cookie Baking" }"#.to_string())
+        })
+        .await
+        .unwrap();
+        assert_eq!(title.as_deref(), Some("🍪 Cookie Baking"));
+        let chat = ChatService::new(&db)
+            .get_chat_by_id(&chat_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chat.title, "🍪 Cookie Baking");
+
+        // A chat the user named is left alone, without asking the model
+        let named = create_chat(&db, &user.id, Some("My recipes")).await;
+        let title = title_untitled_chat(&db, &user.id, &named, || async {
+            Err::<String, _>(AppError::InternalServerError("not called".to_string()))
+        })
+        .await
+        .unwrap();
+        assert_eq!(title, None);
+    }
+}
@@ -26,6 +26,8 @@ use std::{
 use tokio::sync::{RwLock, Semaphore};
 use tracing::{debug, error, info, warn};
 
+use crate::utils::redis_guard::RedisGuard;
+
 // ============================================================================
 // Core Types and Traits
 // ============================================================================
@@ -644,7 +646,7 @@ impl Cache for RedisCache {
             .map_err(|e| CacheError::Redis(e.to_string()))?;
 
         if !keys.is_empty() {
-            conn.del(keys).await?;
+            let _: () = conn.del(keys).await?;
         }
 
         Ok(())
@@ -729,20 +731,29 @@ impl Cache for RedisCache {
 
 /// Multi-tier cache that uses both in-memory and Redis caches
 /// Provides L1 (memory) and L2 (Redis) caching with automatic promotion
+///
+/// L2 calls go through the shared [`RedisGuard`]; while Redis is down the cache keeps
+/// working from L1 alone.
 pub struct MultiTierCache {
     l1_cache: Arc<MemoryCache<String, Vec<u8>>>,
     l2_cache: Option<Arc<RedisCache>>,
+    redis_guard: Arc<RedisGuard>,
     stats: Arc<RwLock<CacheStats>>,
 }
 
 impl MultiTierCache {
     /// Create a new multi-tier cache with both L1 and L2
-    pub fn new(l1_config: CacheConfig, redis_pool: Option<RedisPool>) -> Self {
+    pub fn new(
+        l1_config: CacheConfig,
+        redis_pool: Option<RedisPool>,
+        redis_guard: Arc<RedisGuard>,
+    ) -> Self {
         let l2_cache = redis_pool.map(|pool| Arc::new(RedisCache::new(pool, l1_config.clone())));
 
         Self {
             l1_cache: Arc::new(MemoryCache::new(l1_config)),
             l2_cache,
+            redis_guard,
             stats: Arc::new(RwLock::new(CacheStats::default())),
         }
     }
@@ -752,10 +763,30 @@ impl MultiTierCache {
         Self {
             l1_cache: Arc::new(MemoryCache::new(config)),
             l2_cache: None,
+            redis_guard: Arc::new(RedisGuard::default()),
             stats: Arc::new(RwLock::new(CacheStats::default())),
         }
     }
 
+    /// The Redis tier, unless there is none or the Redis circuit is open
+    fn l2(&self) -> Option<&RedisCache> {
+        self.l2_cache
+            .as_deref()
+            .filter(|_| self.redis_guard.allow())
+    }
+
+    /// Report an L2 call's outcome to the Redis circuit
+    ///
+    /// Only Redis errors count as failures; a value that fails to deserialize still
+    /// means Redis answered.
+    fn track<T>(&self, result: CacheResult<T>) -> CacheResult<T> {
+        match &result {
+            Err(CacheError::Redis(e)) => self.redis_guard.record_failure(e),
+            _ => self.redis_guard.record_success(),
+        }
+        result
+    }
+
     /// Promote a value from L2 to L1
     async fn promote_to_l1<V>(&self, key: &str, value: &V, ttl: Option<Duration>) -> CacheResult<()>
     where
@@ -784,8 +815,8 @@ impl Cache for MultiTierCache {
         }
 
         // Try L2 if available
-        if let Some(l2) = &self.l2_cache {
-            if let Ok(Some(value)) = l2.get::<K, V>(key).await {
+        if let Some(l2) = self.l2() {
+            if let Ok(Some(value)) = self.track(l2.get::<K, V>(key).await) {
                 debug!("L2 cache hit for key: {}, promoting to L1", key_str);
                 // Note: Promotion requires Serialize, skip if not needed for correctness
                 // In production, you might want to use a different approach
@@ -805,8 +836,8 @@ impl Cache for MultiTierCache {
         // Set in both L1 and L2
         let l1_result = self.l1_cache.set(&key, &value, ttl).await;
 
-        if let Some(l2) = &self.l2_cache {
-            let l2_result = l2.set(&key, &value, ttl).await;
+        if let Some(l2) = self.l2() {
+            let l2_result = self.track(l2.set(&key, &value, ttl).await);
             if let Err(e) = l2_result {
                 warn!("Failed to set key '{}' in L2 cache: {}", key.as_ref(), e);
             }
@@ -821,8 +852,8 @@ impl Cache for MultiTierCache {
     {
         let l1_deleted = self.l1_cache.delete(key).await?;
 
-        if let Some(l2) = &self.l2_cache {
-            let _ = l2.delete(key).await;
+        if let Some(l2) = self.l2() {
+            let _ = self.track(l2.delete(key).await);
         }
 
         Ok(l1_deleted)
@@ -836,8 +867,9 @@ impl Cache for MultiTierCache {
             return Ok(true);
         }
 
-        if let Some(l2) = &self.l2_cache {
-            return l2.exists(key).await;
+        if let Some(l2) = self.l2() {
+            // Without Redis only L1 can answer
+            return Ok(self.track(l2.exists(key).await).unwrap_or(false));
         }
 
         Ok(false)
@@ -846,8 +878,10 @@ impl Cache for MultiTierCache {
     async fn clear(&self) -> CacheResult<()> {
         self.l1_cache.clear().await?;
 
-        if let Some(l2) = &self.l2_cache {
-            l2.clear().await?;
+        if let Some(l2) = self.l2() {
+            if let Err(e) = self.track(l2.clear().await) {
+                warn!("Failed to clear L2 cache: {}", e);
+            }
         }
 
         Ok(())
@@ -1172,4 +1206,27 @@ mod tests {
         let hashed = make_cache_key_hashed("session", "very_long_session_data_here");
         assert!(hashed.starts_with("session:"));
     }
+
+    #[tokio::test]
+    async fn test_multi_tier_cache_degrades_when_redis_down() {
+        // Nothing listens on port 1, so every Redis call fails
+        let pool = deadpool_redis::Config::from_url("redis://127.0.0.1:1")
+            .create_pool(Some(deadpool_redis::Runtime::Tokio1))
+            .unwrap();
+        let guard = Arc::new(RedisGuard::default());
+        let cache = MultiTierCache::new(CacheConfig::default(), Some(pool), guard.clone());
+
+        for i in 0..5 {
+            let key = format!("key{}", i);
+            cache.set(&key, "value", None).await.unwrap();
+            let value: Option<String> = cache.get(&key).await.unwrap();
+            assert_eq!(value.as_deref(), Some("value"));
+        }
+        assert!(!cache.exists(&"missing").await.unwrap());
+        cache.clear().await.unwrap();
+
+        assert!(guard.is_degraded());
+        // The circuit is open, so Redis is no longer tried
+        assert!(!guard.allow());
+    }
 }
@@ -0,0 +1,192 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Bumped when the shape of the capabilities response changes
+pub const CAPABILITIES_VERSION: u32 = 1;
+
+/// One backend capability and the version of its API
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    /// Implemented and initialized on this instance right now
+    pub available: bool,
+    /// Bumped when clients have to change how they use the capability
+    pub version: u32,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+impl Capability {
+    fn new(available: bool, details: Value) -> Self {
+        Self {
+            available,
+            version: 1,
+            details,
+        }
+    }
+}
+
+/// Response of `GET /api/capabilities`
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: u32,
+    pub server_version: &'static str,
+    pub capabilities: BTreeMap<&'static str, Capability>,
+}
+
+/// Runtime facts the capability map is derived from
+///
+/// Config flags say what an admin turned on; these say what actually started.
+pub struct RuntimeState<'a> {
+    pub config: &'a Config,
+    pub vector_db: bool,
+    pub embedding_model: Option<&'a str>,
+    pub websocket: bool,
+    pub socketio: bool,
+    pub code_sandbox: bool,
+    pub oauth_providers: Vec<String>,
+}
+
+/// What this backend can do, for clients to degrade gracefully against
+pub fn capabilities(state: &RuntimeState) -> Capabilities {
+    let config = state.config;
+    let rag = state.vector_db && state.embedding_model.is_some();
+
+    let capabilities = BTreeMap::from([
+        (
+            "chat_streaming",
+            Capability::new(true, json!({ "formats": ["sse"] })),
+        ),
+        (
+            "rag",
+            Capability::new(
+                rag,
+                json!({
+                    "vector_db": state.vector_db,
+                    "embedding_model": state.embedding_model,
+                    "reranking": rag && config.rag_rerank_url.is_some(),
+                    "language_routing": rag && config.rag_language_detection,
+                }),
+            ),
+        ),
+        (
+            "websockets",
+            Capability::new(state.websocket, json!({ "socketio": state.socketio })),
+        ),
+        (
+            "code_execution",
+            Capability::new(state.code_sandbox, json!({ "engine": "sandbox" })),
+        ),
+        (
+            "oauth",
+            Capability::new(
+                !state.oauth_providers.is_empty(),
+                json!({ "providers": state.oauth_providers }),
+            ),
+        ),
+        (
+            // Tools can be managed and passed through to models, but aren't run server-side
+            "tools",
+            Capability::new(
+                true,
+                json!({ "server_execution": false, "tool_servers": false }),
+            ),
+        ),
+        (
+            // Only the OpenAI-compatible TTS engine is implemented
+            "tts",
+            Capability::new(config.tts_engine == "openai", Value::Null),
+        ),
+        ("stt", Capability::new(false, Value::Null)),
+        ("image_generation", Capability::new(false, Value::Null)),
+    ]);
+
+    Capabilities {
+        version: CAPABILITIES_VERSION,
+        server_version: env!("CARGO_PKG_VERSION"),
+        capabilities,
+    }
+}
+
+/// Rejects anonymous callers while `REQUIRE_AUTH_FOR_CONFIG` is on
+pub fn check_access(config: &Config, signed_in: bool) -> Result<(), AppError> {
+    if config.require_auth_for_config && !signed_in {
+        return Err(AppError::Unauthorized("Not authenticated".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(config: &Config) -> RuntimeState<'_> {
+        RuntimeState {
+            config,
+            vector_db: false,
+            embedding_model: None,
+            websocket: false,
+            socketio: false,
+            code_sandbox: false,
+            oauth_providers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_capabilities_follow_runtime_state() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.rag_language_detection = true;
+
+        // Config alone doesn't make RAG available
+        let caps = capabilities(&state(&config)).capabilities;
+        assert!(caps["chat_streaming"].available);
+        assert!(!caps["rag"].available);
+        assert_eq!(caps["rag"].details["language_routing"], json!(false));
+        assert!(!caps["oauth"].available);
+
+        let caps = capabilities(&RuntimeState {
+            vector_db: true,
+            embedding_model: Some("text-embedding-3-small"),
+            oauth_providers: vec!["google".to_string()],
+            ..state(&config)
+        })
+        .capabilities;
+        assert!(caps["rag"].available);
+        assert_eq!(caps["rag"].details["language_routing"], json!(true));
+        assert_eq!(
+            caps["rag"].details["embedding_model"],
+            "text-embedding-3-small"
+        );
+        assert!(caps["oauth"].available);
+        assert_eq!(caps["oauth"].details["providers"], json!(["google"]));
+    }
+
+    #[test]
+    fn test_vector_db_without_embeddings_is_not_rag() {
+        let config = Config::from_lookup(|_| None).unwrap();
+        let caps = capabilities(&RuntimeState {
+            vector_db: true,
+            ..state(&config)
+        })
+        .capabilities;
+
+        assert!(!caps["rag"].available);
+        assert_eq!(caps["rag"].details["vector_db"], json!(true));
+    }
+
+    #[test]
+    fn test_signed_in_callers_pass_auth_requirement() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        assert!(check_access(&config, false).is_ok());
+
+        config.require_auth_for_config = true;
+        assert!(check_access(&config, true).is_ok());
+        assert!(matches!(
+            check_access(&config, false),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+}
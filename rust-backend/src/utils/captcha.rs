@@ -0,0 +1,159 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::error::{AppError, AppResult};
+
+/// Captcha services supported for signup verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptchaProvider {
+    HCaptcha,
+    ReCaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    /// Parse a `SIGNUP_CAPTCHA_PROVIDER` value (case-insensitive)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "hcaptcha" => Some(Self::HCaptcha),
+            "recaptcha" => Some(Self::ReCaptcha),
+            "turnstile" => Some(Self::Turnstile),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HCaptcha => "hcaptcha",
+            Self::ReCaptcha => "recaptcha",
+            Self::Turnstile => "turnstile",
+        }
+    }
+
+    /// Server-side token verification endpoint
+    pub fn siteverify_url(&self) -> &'static str {
+        match self {
+            Self::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            Self::ReCaptcha => "https://www.google.com/recaptcha/api/siteverify",
+            Self::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        }
+    }
+}
+
+/// Response shared by the hCaptcha, reCAPTCHA and Turnstile siteverify endpoints
+#[derive(Debug, Deserialize)]
+pub struct SiteverifyResponse {
+    pub success: bool,
+    #[serde(rename = "error-codes", default)]
+    pub error_codes: Vec<String>,
+}
+
+impl SiteverifyResponse {
+    fn into_result(self) -> AppResult<()> {
+        if self.success {
+            return Ok(());
+        }
+
+        let reason = if self.error_codes.is_empty() {
+            "verification failed".to_string()
+        } else {
+            self.error_codes.join(", ")
+        };
+        Err(AppError::Validation(format!(
+            "Captcha rejected: {}",
+            reason
+        )))
+    }
+}
+
+/// Verify a captcha token with the provider
+///
+/// Missing or rejected tokens are validation errors; an unreachable provider is an
+/// external service error so that outages aren't reported as bad input.
+pub async fn verify_captcha(
+    client: &reqwest::Client,
+    provider: CaptchaProvider,
+    secret: &str,
+    token: Option<&str>,
+    remote_ip: Option<&str>,
+) -> AppResult<()> {
+    let token = token
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| AppError::Validation("captcha_token is required".to_string()))?;
+
+    let mut params = vec![("secret", secret), ("response", token)];
+    if let Some(remote_ip) = remote_ip {
+        params.push(("remoteip", remote_ip));
+    }
+
+    let response: SiteverifyResponse = client
+        .post(provider.siteverify_url())
+        .timeout(Duration::from_secs(10))
+        .form(&params)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| {
+            AppError::ExternalServiceError(format!(
+                "Captcha verification with {} failed: {}",
+                provider.as_str(),
+                e
+            ))
+        })?
+        .json()
+        .await
+        .map_err(|e| {
+            AppError::ExternalServiceError(format!("Invalid captcha verification response: {}", e))
+        })?;
+
+    response.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!(
+            CaptchaProvider::parse("hCaptcha"),
+            Some(CaptchaProvider::HCaptcha)
+        );
+        assert_eq!(
+            CaptchaProvider::parse("turnstile"),
+            Some(CaptchaProvider::Turnstile)
+        );
+        assert_eq!(CaptchaProvider::parse("friendlycaptcha"), None);
+    }
+
+    #[test]
+    fn test_siteverify_response() {
+        let ok: SiteverifyResponse = serde_json::from_str(r#"{"success": true}"#).unwrap();
+        assert!(ok.into_result().is_ok());
+
+        let rejected: SiteverifyResponse = serde_json::from_str(
+            r#"{"success": false, "error-codes": ["invalid-input-response"]}"#,
+        )
+        .unwrap();
+        match rejected.into_result() {
+            Err(AppError::Validation(message)) => {
+                assert!(message.contains("invalid-input-response"))
+            }
+            other => panic!("expected validation error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_token_rejected_without_request() {
+        let result = verify_captcha(
+            &crate::utils::http::client(),
+            CaptchaProvider::HCaptcha,
+            "secret",
+            Some("  "),
+            None,
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,163 @@
+/// Response cache for deterministic chat completions
+///
+/// With ENABLE_CHAT_CACHE set, non-streaming requests at `temperature: 0` without tools
+/// are answered from the model cache (Redis when configured, memory otherwise) for
+/// CHAT_CACHE_TTL seconds. Entries are per user unless CHAT_CACHE_SHARED is set.
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cache_manager::CacheManager;
+use crate::config::Config;
+use crate::utils::cache::{make_cache_key_hashed, Cache, MultiTierCache};
+
+/// Response header reporting `HIT` or `MISS` for cacheable requests
+pub const CACHE_HEADER: &str = "X-Cache";
+
+#[derive(Clone)]
+pub struct ChatCache {
+    cache: Arc<MultiTierCache>,
+    ttl: Duration,
+    shared: bool,
+}
+
+impl ChatCache {
+    /// `None` when the cache is disabled
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.enable_chat_cache {
+            return None;
+        }
+        Some(Self::new(
+            CacheManager::get_or_init().model_cache.clone(),
+            config,
+        ))
+    }
+
+    fn new(cache: Arc<MultiTierCache>, config: &Config) -> Self {
+        Self {
+            cache,
+            ttl: Duration::from_secs(config.chat_cache_ttl),
+            shared: config.chat_cache_shared,
+        }
+    }
+
+    /// Cache key for `payload`, or `None` when the request isn't deterministic
+    ///
+    /// The key covers the whole forwarded payload: model, messages and parameters.
+    pub fn key(&self, user_id: &str, payload: &Value) -> Option<String> {
+        if !is_deterministic(payload) {
+            return None;
+        }
+        let scope = if self.shared {
+            "chat_cache:shared".to_string()
+        } else {
+            format!("chat_cache:user:{}", user_id)
+        };
+        Some(make_cache_key_hashed(&scope, &payload.to_string()))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Value> {
+        match self.cache.get(&key).await {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Chat cache lookup failed: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, response: &Value) {
+        if let Err(e) = self.cache.set(key, response, Some(self.ttl)).await {
+            tracing::warn!("Failed to cache chat response: {}", e);
+        }
+    }
+}
+
+/// Non-streaming, `temperature: 0` and no tools or functions
+fn is_deterministic(payload: &Value) -> bool {
+    let streaming = payload
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let zero_temperature = payload
+        .get("temperature")
+        .and_then(Value::as_f64)
+        .is_some_and(|temperature| temperature == 0.0);
+    let has_tools = ["tools", "functions"].iter().any(|field| {
+        payload
+            .get(*field)
+            .and_then(Value::as_array)
+            .is_some_and(|tools| !tools.is_empty())
+    });
+    !streaming && zero_temperature && !has_tools
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::cache::CacheConfig;
+    use serde_json::json;
+
+    fn chat_cache(shared: bool) -> ChatCache {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.chat_cache_shared = shared;
+        ChatCache::new(
+            Arc::new(MultiTierCache::memory_only(CacheConfig::default())),
+            &config,
+        )
+    }
+
+    fn payload() -> Value {
+        json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "Capital of France?" }],
+            "temperature": 0,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_identical_deterministic_request_hits_cache() {
+        let cache = chat_cache(false);
+        let response = json!({ "choices": [{ "message": { "content": "Paris" } }] });
+
+        let first = cache.key("alice", &payload()).unwrap();
+        assert!(cache.get(&first).await.is_none());
+        cache.put(&first, &response).await;
+
+        let second = cache.key("alice", &payload()).unwrap();
+        assert_eq!(cache.get(&second).await, Some(response));
+
+        // Other users don't see it unless the cache is shared
+        let other = cache.key("bob", &payload()).unwrap();
+        assert!(cache.get(&other).await.is_none());
+        let shared = chat_cache(true);
+        assert_eq!(
+            shared.key("alice", &payload()),
+            shared.key("bob", &payload())
+        );
+    }
+
+    #[test]
+    fn test_only_deterministic_requests_cacheable() {
+        let cache = chat_cache(false);
+        let mut streaming = payload();
+        streaming["stream"] = json!(true);
+        let mut sampled = payload();
+        sampled["temperature"] = json!(0.7);
+        let mut default_temperature = payload();
+        default_temperature
+            .as_object_mut()
+            .unwrap()
+            .remove("temperature");
+        let mut tools = payload();
+        tools["tools"] = json!([{ "type": "function", "function": { "name": "lookup" } }]);
+
+        for payload in [streaming, sampled, default_temperature, tools] {
+            assert!(cache.key("alice", &payload).is_none(), "{}", payload);
+        }
+        let mut no_tools = payload();
+        no_tools["tools"] = json!([]);
+        assert!(cache.key("alice", &no_tools).is_some());
+    }
+}
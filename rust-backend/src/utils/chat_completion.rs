@@ -4,9 +4,10 @@
 
 use actix_web::{web, HttpResponse};
 use bytes::Bytes;
-use futures::stream::StreamExt;
+use futures::stream::{Stream, StreamExt};
 use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::time::Duration;
 
 use crate::{
     error::AppError,
@@ -14,6 +15,9 @@ use crate::{
         execute_code_block, format_execution_result, get_code_interpreter_timeout,
         get_sandbox_client, is_code_interpreter_enabled, CodeBlockDetector,
     },
+    services::usage::StreamUsageTracker,
+    utils::moderation::{self, Moderator},
+    utils::stream_limits::StreamGuard,
     AppState,
 };
 
@@ -62,23 +66,114 @@ pub struct StreamingContext {
     pub tool_ids: Vec<String>,
     pub tool_specs: Vec<Value>,
     pub delta_chunk_size: Option<usize>,
+    pub completion_log: Option<crate::services::usage::CompletionLog>,
+}
+
+/// SSE comment sent while waiting for the first upstream bytes
+const SSE_KEEPALIVE: &[u8] = b": ping\n\n";
+
+/// Emit SSE keepalive comments every `interval` until the first upstream chunk arrives
+///
+/// Keeps proxies from timing out during slow model warmup. Comments are ignored by SSE
+/// clients, so no content is injected; once data flows the stream passes through as is.
+fn keepalive_until_first_chunk<S, E>(
+    stream: S,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let ticker = (!interval.is_zero())
+        .then(|| tokio::time::interval_at(tokio::time::Instant::now() + interval, interval));
+
+    futures::stream::unfold((stream, ticker), |(mut stream, mut ticker)| async move {
+        let item = match ticker.as_mut() {
+            Some(ticker_ref) => tokio::select! {
+                item = stream.next() => item,
+                _ = ticker_ref.tick() => {
+                    return Some((Ok(Bytes::from_static(SSE_KEEPALIVE)), (stream, ticker)));
+                }
+            },
+            None => stream.next().await,
+        };
+
+        // Real data has arrived (or the stream ended): stop pinging
+        item.map(|item| (item, (stream, None)))
+    })
+}
+
+/// End an SSE stream with an error event and `[DONE]` when the upstream fails
+///
+/// Without this a stream that breaks mid-generation just cuts the client off. The event
+/// is `data: {"error": {"message", "type"}}`. A client that disconnects drops the
+/// stream instead, so it never produces one.
+pub fn end_with_error_event<S, E>(stream: S) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    // Tracks whether the last chunk finished an event, so the error isn't glued onto a
+    // partial line
+    futures::stream::unfold(Some((stream, true)), |state| async move {
+        let (mut stream, at_boundary) = state?;
+        match stream.next().await {
+            Some(Ok(bytes)) => {
+                let at_boundary = if bytes.is_empty() {
+                    at_boundary
+                } else {
+                    bytes.ends_with(b"\n\n")
+                };
+                Some((Ok(bytes), Some((stream, at_boundary))))
+            }
+            Some(Err(e)) => {
+                tracing::error!("SSE stream error: {}", e);
+                let event = json!({
+                    "error": {
+                        "message": format!("Upstream stream failed: {}", e),
+                        "type": "upstream_error",
+                    }
+                });
+                let separator = if at_boundary { "" } else { "\n\n" };
+                let bytes = format!("{}data: {}\n\ndata: [DONE]\n\n", separator, event);
+                Some((Ok(Bytes::from(bytes)), None))
+            }
+            None => None,
+        }
+    })
 }
 
 /// Create an HTTP SSE streaming response
 /// This is used when Socket.IO metadata is not present (API calls, integrations, etc.)
-pub fn create_sse_stream(response: reqwest::Response) -> Result<HttpResponse, AppError> {
+/// Usage is recorded by the tracker once the stream is dropped.
+pub fn create_sse_stream(
+    response: reqwest::Response,
+    mut usage_tracker: StreamUsageTracker,
+    keepalive_interval: Duration,
+    moderator: Option<Moderator>,
+    stream_guard: Option<StreamGuard>,
+) -> Result<HttpResponse, AppError> {
     tracing::debug!("Creating HTTP SSE streaming response");
 
-    let stream = response.bytes_stream().map(move |result| match result {
-        Ok(bytes) => {
-            // Forward immediately without ANY processing
-            Ok::<Bytes, actix_web::Error>(bytes)
-        }
-        Err(e) => {
-            tracing::error!("SSE stream error: {}", e);
-            Err(actix_web::error::ErrorInternalServerError(e))
+    let upstream = Box::pin(end_with_error_event(Box::pin(
+        moderation::moderate_sse_stream(response.bytes_stream(), moderator),
+    )));
+    // The body owns the guard, so the stream stays counted until it ends or the client
+    // goes away
+    let stream = upstream.map(move |result| {
+        let _ = &stream_guard;
+        match result {
+            Ok(bytes) => {
+                // Forward immediately, only observing the bytes for usage accounting
+                usage_tracker.observe_bytes(&bytes);
+                Ok::<Bytes, actix_web::Error>(bytes)
+            }
+            Err(e) => {
+                tracing::error!("SSE stream error: {}", e);
+                Err(actix_web::error::ErrorInternalServerError(e))
+            }
         }
     });
+    let stream = keepalive_until_first_chunk(stream, keepalive_interval);
 
     Ok(HttpResponse::Ok()
         .content_type("text/event-stream; charset=utf-8")
@@ -105,6 +200,16 @@ pub async fn process_streaming_via_socketio(
         }
     };
 
+    // Let the client stop this chat's generation with a `stop_generation` event
+    let stream_handle = context
+        .chat_id
+        .as_deref()
+        .map(|chat_id| socket_state.register_stream(chat_id, &context.user_id));
+    let cancel_token = stream_handle
+        .as_ref()
+        .map(|handle| handle.token().clone())
+        .unwrap_or_default();
+
     // Create event emitter
     let event_emitter = crate::socket::get_event_emitter(
         socket_state,
@@ -114,8 +219,12 @@ pub async fn process_streaming_via_socketio(
         context.session_id.clone(),
     );
 
-    // Stream the response with batching like Python backend
-    let mut stream = response.bytes_stream();
+    // Stream the response with batching like Python backend, cut off if moderation flags it
+    let moderator = Moderator::from_config(&context.state.config.read().unwrap());
+    let mut stream = Box::pin(moderation::moderate_sse_stream(
+        response.bytes_stream(),
+        moderator,
+    ));
     let mut content = String::new();
 
     // Delta batching to prevent flooding frontend
@@ -144,13 +253,34 @@ pub async fn process_streaming_via_socketio(
         None
     };
 
+    let mut usage_tracker = StreamUsageTracker::new(
+        context.state.clone(),
+        context.user_id.clone(),
+        context.model_id.clone(),
+        &context.messages,
+        context.completion_log.clone(),
+    );
+
     tracing::info!(
         "🔴 Socket.IO STREAMING STARTED for user {} (code_interpreter: {})",
         context.user_id,
         code_interpreter_enabled
     );
 
-    while let Some(chunk_result) = stream.next().await {
+    let mut cancelled = false;
+
+    loop {
+        let chunk_result = tokio::select! {
+            chunk = stream.next() => match chunk {
+                Some(chunk) => chunk,
+                None => break,
+            },
+            _ = cancel_token.cancelled() => {
+                cancelled = true;
+                break;
+            }
+        };
+
         match chunk_result {
             Ok(chunk) => {
                 // Convert bytes to text
@@ -187,6 +317,8 @@ pub async fn process_streaming_via_socketio(
 
                             // Parse JSON data
                             if let Ok(mut data) = serde_json::from_str::<Value>(data_str) {
+                                usage_tracker.observe_event(&data);
+
                                 // Extract delta content
                                 if let Some(choices) =
                                     data.get("choices").and_then(|c| c.as_array())
@@ -408,6 +540,48 @@ pub async fn process_streaming_via_socketio(
         }
     }
 
+    // Record usage for this completion before any tool follow-up requests
+    drop(usage_tracker);
+
+    if cancelled {
+        // Dropping the response stream closes the upstream connection
+        drop(stream);
+        tracing::info!("⏹️  Streaming stopped by user {}", context.user_id);
+
+        if let Some(pending_data) = last_delta_data.take() {
+            let completion_event = json!({
+                "type": "chat:completion",
+                "data": pending_data
+            });
+            event_emitter(completion_event).await;
+        }
+
+        let completion_event = json!({
+            "type": "chat:completion",
+            "data": {
+                "content": content.clone(),
+                "done": true,
+            }
+        });
+        event_emitter(completion_event).await;
+
+        if let (Some(cid), Some(mid)) = (context.chat_id.as_ref(), context.message_id.as_ref()) {
+            let _ = upsert_chat_message(
+                &context.state.db,
+                cid,
+                mid,
+                json!({
+                    "role": "assistant",
+                    "content": content,
+                    "done": true,
+                    "model": context.model_id.clone(),
+                }),
+            )
+            .await;
+        }
+        return Ok(());
+    }
+
     // Execute tools if tool_calls were detected
     if has_tool_calls && !collected_tool_calls.is_empty() {
         execute_tools_and_continue(
@@ -550,9 +724,19 @@ async fn execute_tools_and_continue(
     )
     .await?;
 
+    // The follow-up request is a separate model call and is recorded on its own
+    let usage_tracker = StreamUsageTracker::new(
+        context.state.clone(),
+        context.user_id.clone(),
+        context.model_id.clone(),
+        &new_messages,
+        context.completion_log.clone(),
+    );
+
     // Stream the second response
     stream_second_response(
         second_response,
+        usage_tracker,
         event_emitter,
         delta_chunk_size,
         &context.state,
@@ -729,6 +913,7 @@ async fn make_tool_response_request(
 /// Stream the second response from tool execution
 async fn stream_second_response(
     response: reqwest::Response,
+    mut usage_tracker: StreamUsageTracker,
     event_emitter: impl Fn(Value) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
         + Send,
     delta_chunk_size: usize,
@@ -770,6 +955,8 @@ async fn stream_second_response(
                             }
 
                             if let Ok(mut data) = serde_json::from_str::<Value>(data_str) {
+                                usage_tracker.observe_event(&data);
+
                                 if let Some(choices) =
                                     data.get("choices").and_then(|c| c.as_array())
                                 {
@@ -1186,3 +1373,106 @@ async fn generate_and_update_title(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keepalive_until_first_chunk() {
+        // Upstream that is slow to produce its first token
+        let upstream = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            Ok::<Bytes, ()>(Bytes::from_static(b"data: {}\n\n"))
+        })
+        .chain(futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            Ok::<Bytes, ()>(Bytes::from_static(b"data: [DONE]\n\n"))
+        }));
+
+        let items: Vec<Bytes> =
+            keepalive_until_first_chunk(Box::pin(upstream), Duration::from_millis(100))
+                .map(|item| item.unwrap())
+                .collect()
+                .await;
+
+        let first_data = items
+            .iter()
+            .position(|item| item.as_ref() != SSE_KEEPALIVE)
+            .unwrap();
+        assert!(
+            first_data >= 1,
+            "expected keepalives before the first token"
+        );
+        assert!(items[..first_data]
+            .iter()
+            .all(|item| item.as_ref() == SSE_KEEPALIVE));
+
+        // No keepalives once data is flowing
+        assert_eq!(
+            &items[first_data..],
+            &[
+                Bytes::from_static(b"data: {}\n\n"),
+                Bytes::from_static(b"data: [DONE]\n\n")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upstream_failure_ends_with_error_event() {
+        let upstream = futures::stream::iter(vec![
+            Ok(Bytes::from_static(b"data: {\"choices\":[]}\n\n")),
+            Ok(Bytes::from_static(b"data: {\"cho")),
+            Err("connection reset by peer"),
+            Ok(Bytes::from_static(b"data: never sent\n\n")),
+        ]);
+
+        let items: Vec<Bytes> = end_with_error_event(upstream)
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 3);
+        let tail = std::str::from_utf8(&items[2]).unwrap();
+        // The partial event is terminated before the error event starts
+        assert!(tail.starts_with("\n\ndata: "));
+        assert!(tail.ends_with("\n\ndata: [DONE]\n\n"));
+
+        let event: Value = serde_json::from_str(
+            tail.trim()
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("data: "))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(event["error"]["type"], "upstream_error");
+        assert!(event["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("connection reset by peer"));
+    }
+
+    #[tokio::test]
+    async fn test_completed_stream_has_no_error_event() {
+        let chunks = vec![
+            Bytes::from_static(b"data: {}\n\n"),
+            Bytes::from_static(b"data: [DONE]\n\n"),
+        ];
+        let upstream = futures::stream::iter(chunks.clone().into_iter().map(Ok::<_, &str>));
+        let items: Vec<Bytes> = end_with_error_event(upstream)
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+        assert_eq!(items, chunks);
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_disabled() {
+        let upstream = futures::stream::iter(vec![Ok::<Bytes, ()>(Bytes::from_static(b"a"))]);
+        let items: Vec<_> = keepalive_until_first_chunk(upstream, Duration::ZERO)
+            .collect()
+            .await;
+        assert_eq!(items, vec![Ok(Bytes::from_static(b"a"))]);
+    }
+}
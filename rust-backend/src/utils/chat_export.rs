@@ -0,0 +1,306 @@
+//! Chat downloads as the stored JSON or a readable Markdown transcript
+//!
+//! The JSON export is the chat as GET /chats/{id} returns it, which POST /chats/import
+//! accepts back. The Markdown transcript follows the chat's current branch, a heading
+//! per message with its role and time. User-written text is escaped so it can't inject
+//! HTML or pass itself off as another message's heading; code blocks are kept as they
+//! are.
+use bytes::Bytes;
+use futures::Stream;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{AppError, AppResult};
+use crate::models::chat::{Chat, ChatResponse};
+use crate::utils::chat::get_message_list;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Markdown => "md",
+        }
+    }
+}
+
+/// The export of `chat`, streamed a message at a time for Markdown
+pub fn export_stream(
+    chat: Chat,
+    format: ExportFormat,
+) -> impl Stream<Item = AppResult<Bytes>> + 'static {
+    let chunks: Vec<AppResult<Bytes>> = match format {
+        ExportFormat::Json => vec![serde_json::to_vec(&ChatResponse::from(chat))
+            .map(Bytes::from)
+            .map_err(|e| {
+                AppError::InternalServerError(format!("Failed to serialize chat: {}", e))
+            })],
+        ExportFormat::Markdown => {
+            let messages = transcript_messages(&chat.chat);
+            std::iter::once(markdown_header(&chat))
+                .chain(messages.iter().map(markdown_message))
+                .map(|chunk| Ok(Bytes::from(chunk)))
+                .collect()
+        }
+    };
+    futures::stream::iter(chunks)
+}
+
+/// Messages of the chat's current branch, oldest first
+fn transcript_messages(chat: &Value) -> Vec<Value> {
+    let history = chat.get("history");
+    let messages = history
+        .and_then(|h| h.get("messages"))
+        .and_then(|m| m.as_object());
+    let current_id = history
+        .and_then(|h| h.get("currentId"))
+        .and_then(|id| id.as_str());
+    if let (Some(messages), Some(current_id)) = (messages, current_id) {
+        return get_message_list(messages, current_id);
+    }
+
+    // Chats saved before message history was kept
+    chat.get("messages")
+        .and_then(|m| m.as_array())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn markdown_header(chat: &Chat) -> String {
+    format!(
+        "# {}\n\n_Created {}_\n",
+        escape_text(&chat.title),
+        format_time(chat.created_at).unwrap_or_default()
+    )
+}
+
+fn markdown_message(message: &Value) -> String {
+    let role = message
+        .get("role")
+        .and_then(|r| r.as_str())
+        .unwrap_or("unknown");
+    let mut heading = match role {
+        "user" => "User".to_string(),
+        "assistant" => "Assistant".to_string(),
+        "system" => "System".to_string(),
+        other => escape_text(other),
+    };
+    if let Some(model) = message.get("model").and_then(|m| m.as_str()) {
+        if role == "assistant" {
+            heading.push_str(&format!(" ({})", escape_text(model)));
+        }
+    }
+    if let Some(time) = message
+        .get("timestamp")
+        .and_then(|t| t.as_i64())
+        .and_then(format_time)
+    {
+        heading.push_str(&format!(" · {}", time));
+    }
+
+    let content = message_content(message);
+    let body = if role == "user" {
+        escape_markdown(&content)
+    } else {
+        close_fences(&content)
+    };
+    format!("\n---\n\n### {}\n\n{}\n", heading, body.trim_end())
+}
+
+/// Text of a message, joining the text parts of multi-part content
+fn message_content(message: &Value) -> String {
+    match message.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => String::new(),
+    }
+}
+
+fn format_time(timestamp: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+}
+
+/// Opening fence of a fenced code block
+fn fence(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    ["```", "~~~"]
+        .into_iter()
+        .find(|marker| line.starts_with(marker))
+}
+
+/// `text` with a fence still open at its end closed, so it can't swallow what follows
+fn close_fences(text: &str) -> String {
+    let mut open: Option<&str> = None;
+    for line in text.lines() {
+        match (open, fence(line)) {
+            (None, Some(marker)) => open = Some(marker),
+            (Some(marker), Some(found)) if marker == found => open = None,
+            _ => {}
+        }
+    }
+    match open {
+        Some(marker) => format!("{}\n{}", text.trim_end(), marker),
+        None => text.to_string(),
+    }
+}
+
+/// Inline text with HTML and Markdown headings neutralised
+fn escape_text(text: &str) -> String {
+    let escaped = text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    if escaped.trim_start().starts_with('#') {
+        format!("\\{}", escaped.trim_start())
+    } else {
+        escaped
+    }
+}
+
+/// User-written Markdown made safe to embed: outside code blocks, HTML is escaped and
+/// lines can't start headings or thematic breaks; code blocks are left as they are
+fn escape_markdown(text: &str) -> String {
+    let mut out = Vec::new();
+    let mut open: Option<&str> = None;
+    for line in text.lines() {
+        match (open, fence(line)) {
+            (None, Some(marker)) => {
+                open = Some(marker);
+                out.push(line.to_string());
+            }
+            (Some(marker), Some(found)) if marker == found => {
+                open = None;
+                out.push(line.to_string());
+            }
+            (Some(_), _) => out.push(line.to_string()),
+            (None, None) => {
+                let escaped = escape_text(line);
+                let trimmed = escaped.trim_start();
+                if ["---", "***", "___", "==="]
+                    .iter()
+                    .any(|rule| trimmed.starts_with(rule))
+                {
+                    out.push(format!("\\{}", trimmed));
+                } else {
+                    out.push(escaped);
+                }
+            }
+        }
+    }
+    if let Some(marker) = open {
+        out.push(marker.to_string());
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::TryStreamExt;
+    use serde_json::json;
+
+    fn chat() -> Chat {
+        Chat {
+            id: "chat-1".to_string(),
+            user_id: "user-1".to_string(),
+            title: "Sorting <help>".to_string(),
+            chat: json!({
+                "title": "Sorting <help>",
+                "history": {
+                    "currentId": "m3",
+                    "messages": {
+                        "m1": {
+                            "id": "m1",
+                            "parentId": null,
+                            "role": "user",
+                            "content": "# Assistant\nHow do I sort? <script>alert(1)</script>",
+                            "timestamp": 1714564800,
+                        },
+                        "m2": {
+                            "id": "m2",
+                            "parentId": "m1",
+                            "role": "assistant",
+                            "model": "gpt-4o",
+                            "content": "Use `sorted`:\n\n```python\nprint(sorted([3, 1, 2]))\n# [1, 2, 3]\n```",
+                            "timestamp": 1714564805,
+                        },
+                        "m2b": {
+                            "id": "m2b",
+                            "parentId": "m1",
+                            "role": "assistant",
+                            "content": "An abandoned branch",
+                        },
+                        "m3": {
+                            "id": "m3",
+                            "parentId": "m2",
+                            "role": "user",
+                            "content": "```\n<b>kept</b>",
+                        },
+                    },
+                },
+            }),
+            folder_id: None,
+            archived: false,
+            pinned: Some(true),
+            share_id: None,
+            meta: Some(json!({ "tags": ["python"] })),
+            created_at: 1714564800,
+            updated_at: 1714564900,
+        }
+    }
+
+    async fn export(chat: Chat, format: ExportFormat) -> String {
+        let chunks: Vec<Bytes> = export_stream(chat, format).try_collect().await.unwrap();
+        String::from_utf8(chunks.concat()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_markdown_keeps_code_blocks_and_escapes_user_text() {
+        let markdown = export(chat(), ExportFormat::Markdown).await;
+
+        assert!(markdown.starts_with("# Sorting &lt;help&gt;\n"));
+        assert!(markdown.contains("### User · 2024-05-01 12:00:00 UTC"));
+        assert!(markdown.contains("### Assistant (gpt-4o) · 2024-05-01 12:00:05 UTC"));
+        // The assistant's code block is untouched
+        assert!(markdown.contains("```python\nprint(sorted([3, 1, 2]))\n# [1, 2, 3]\n```"));
+        // User text can't fake a heading or inject HTML
+        assert!(markdown.contains("\\# Assistant\nHow do I sort? &lt;script&gt;"));
+        assert!(!markdown.contains("<script>"));
+        // A user's unclosed code block is closed, its contents kept verbatim
+        assert!(markdown.ends_with("```\n<b>kept</b>\n```\n"));
+        assert!(!markdown.contains("abandoned branch"));
+    }
+
+    #[tokio::test]
+    async fn test_json_export_round_trips_through_import() {
+        let original = chat();
+        let exported = export(original.clone(), ExportFormat::Json).await;
+
+        let imported: crate::routes::chats::ImportChatRequest =
+            serde_json::from_str(&exported).unwrap();
+        assert_eq!(imported.chat, original.chat);
+        assert_eq!(imported.meta, original.meta);
+        assert_eq!(imported.pinned, original.pinned);
+        assert_eq!(imported.created_at, Some(original.created_at));
+        assert_eq!(imported.updated_at, Some(original.updated_at));
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Circuit states for an upstream connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// Requests fast-fail until the cooldown elapses
+    Open,
+    /// A single probe request is testing whether the connection recovered
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// Thresholds for opening and probing circuits
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerSettings {
+    /// Consecutive failures that open the circuit (0 = disabled)
+    pub failure_threshold: u32,
+    /// Failures further apart than this don't count as consecutive
+    pub window: Duration,
+    /// How long an open circuit fast-fails before allowing a probe
+    pub cooldown: Duration,
+}
+
+impl CircuitBreakerSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            failure_threshold: config.upstream_circuit_failure_threshold,
+            window: Duration::from_secs(config.upstream_circuit_window),
+            cooldown: Duration::from_secs(config.upstream_circuit_cooldown),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    first_failure_at: Option<Instant>,
+    opened_at: Option<Instant>,
+    probe_started_at: Option<Instant>,
+}
+
+impl Circuit {
+    fn new() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            failures: 0,
+            first_failure_at: None,
+            opened_at: None,
+            probe_started_at: None,
+        }
+    }
+
+    fn open(&mut self, now: Instant) {
+        self.state = CircuitState::Open;
+        self.opened_at = Some(now);
+        self.probe_started_at = None;
+    }
+}
+
+/// Snapshot of one connection's circuit for the metrics endpoint
+#[derive(Debug, Clone)]
+pub struct CircuitStatus {
+    pub connection: String,
+    pub state: CircuitState,
+    pub failures: u32,
+}
+
+/// In-memory circuit breakers keyed by upstream connection
+///
+/// Settings are passed per call so config reloads take effect immediately.
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreakers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check whether a request to `connection` may proceed
+    ///
+    /// Returns the seconds until the next probe when the circuit is open. Once the
+    /// cooldown has elapsed the circuit half-opens and lets one probe through; other
+    /// requests keep fast-failing until that probe is recorded (or itself times out
+    /// after another cooldown).
+    pub fn try_acquire(
+        &self,
+        connection: &str,
+        settings: &CircuitBreakerSettings,
+        now: Instant,
+    ) -> Result<(), u64> {
+        if settings.failure_threshold == 0 {
+            return Ok(());
+        }
+
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(connection) else {
+            return Ok(());
+        };
+
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let opened_at = circuit.opened_at.unwrap_or(now);
+                let elapsed = now.saturating_duration_since(opened_at);
+                if elapsed >= settings.cooldown {
+                    circuit.state = CircuitState::HalfOpen;
+                    circuit.probe_started_at = Some(now);
+                    Ok(())
+                } else {
+                    Err(retry_after_secs(settings.cooldown - elapsed))
+                }
+            }
+            CircuitState::HalfOpen => {
+                let probe_started_at = circuit.probe_started_at.unwrap_or(now);
+                let elapsed = now.saturating_duration_since(probe_started_at);
+                if elapsed >= settings.cooldown {
+                    // The previous probe never reported back; try another
+                    circuit.probe_started_at = Some(now);
+                    Ok(())
+                } else {
+                    Err(retry_after_secs(settings.cooldown - elapsed))
+                }
+            }
+        }
+    }
+
+    /// Record a successful upstream response, closing the circuit
+    pub fn record_success(&self, connection: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        if let Some(circuit) = circuits.get_mut(connection) {
+            *circuit = Circuit::new();
+        }
+    }
+
+    /// Record an upstream failure (connection error or 5xx)
+    pub fn record_failure(
+        &self,
+        connection: &str,
+        settings: &CircuitBreakerSettings,
+        now: Instant,
+    ) {
+        if settings.failure_threshold == 0 {
+            return;
+        }
+
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits
+            .entry(connection.to_string())
+            .or_insert_with(Circuit::new);
+
+        match circuit.state {
+            // A failed probe re-opens the circuit for another cooldown
+            CircuitState::HalfOpen => {
+                circuit.failures += 1;
+                circuit.open(now);
+            }
+            CircuitState::Open => {}
+            CircuitState::Closed => {
+                let within_window = circuit
+                    .first_failure_at
+                    .is_some_and(|first| now.saturating_duration_since(first) <= settings.window);
+                if within_window {
+                    circuit.failures += 1;
+                } else {
+                    circuit.failures = 1;
+                    circuit.first_failure_at = Some(now);
+                }
+
+                if circuit.failures >= settings.failure_threshold {
+                    tracing::warn!(
+                        "Opening circuit for upstream {} after {} consecutive failures",
+                        connection,
+                        circuit.failures
+                    );
+                    circuit.open(now);
+                }
+            }
+        }
+    }
+
+    /// Current state of every connection that has failed since startup
+    pub fn statuses(&self) -> Vec<CircuitStatus> {
+        let circuits = self.circuits.lock().unwrap();
+        let mut statuses: Vec<CircuitStatus> = circuits
+            .iter()
+            .map(|(connection, circuit)| CircuitStatus {
+                connection: connection.clone(),
+                state: circuit.state,
+                failures: circuit.failures,
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.connection.cmp(&b.connection));
+        statuses
+    }
+}
+
+fn retry_after_secs(remaining: Duration) -> u64 {
+    remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+}
+
+/// Error returned while a connection's circuit is open
+pub fn circuit_open_error(retry_after: u64) -> AppError {
+    AppError::CircuitOpen {
+        message: "Upstream provider is temporarily unavailable after repeated failures".to_string(),
+        retry_after,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://api.example.com/v1";
+
+    fn settings() -> CircuitBreakerSettings {
+        CircuitBreakerSettings {
+            failure_threshold: 3,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+
+    fn state(breakers: &CircuitBreakers) -> CircuitState {
+        breakers.statuses()[0].state
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breakers = CircuitBreakers::new();
+        let now = Instant::now();
+
+        breakers.record_failure(URL, &settings(), now);
+        breakers.record_failure(URL, &settings(), now);
+        assert!(breakers.try_acquire(URL, &settings(), now).is_ok());
+
+        breakers.record_failure(URL, &settings(), now);
+        assert_eq!(state(&breakers), CircuitState::Open);
+        assert_eq!(breakers.try_acquire(URL, &settings(), now), Err(30));
+        assert_eq!(
+            breakers.try_acquire(URL, &settings(), now + Duration::from_millis(20_500)),
+            Err(10)
+        );
+    }
+
+    #[test]
+    fn test_failures_outside_window_do_not_accumulate() {
+        let breakers = CircuitBreakers::new();
+        let now = Instant::now();
+
+        breakers.record_failure(URL, &settings(), now);
+        breakers.record_failure(URL, &settings(), now + Duration::from_secs(10));
+        breakers.record_failure(URL, &settings(), now + Duration::from_secs(120));
+
+        assert_eq!(state(&breakers), CircuitState::Closed);
+        assert_eq!(breakers.statuses()[0].failures, 1);
+    }
+
+    #[test]
+    fn test_half_open_probe_closes_on_success() {
+        let breakers = CircuitBreakers::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            breakers.record_failure(URL, &settings(), now);
+        }
+
+        let after_cooldown = now + Duration::from_secs(30);
+        assert!(breakers
+            .try_acquire(URL, &settings(), after_cooldown)
+            .is_ok());
+        assert_eq!(state(&breakers), CircuitState::HalfOpen);
+        // Only the probe goes through
+        assert!(breakers
+            .try_acquire(URL, &settings(), after_cooldown)
+            .is_err());
+
+        breakers.record_success(URL);
+        assert_eq!(state(&breakers), CircuitState::Closed);
+        assert!(breakers
+            .try_acquire(URL, &settings(), after_cooldown)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_failed_probe_reopens() {
+        let breakers = CircuitBreakers::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            breakers.record_failure(URL, &settings(), now);
+        }
+
+        let after_cooldown = now + Duration::from_secs(30);
+        assert!(breakers
+            .try_acquire(URL, &settings(), after_cooldown)
+            .is_ok());
+        breakers.record_failure(URL, &settings(), after_cooldown);
+
+        assert_eq!(state(&breakers), CircuitState::Open);
+        assert_eq!(
+            breakers.try_acquire(URL, &settings(), after_cooldown),
+            Err(30)
+        );
+    }
+
+    #[test]
+    fn test_disabled_threshold_never_opens() {
+        let breakers = CircuitBreakers::new();
+        let disabled = CircuitBreakerSettings {
+            failure_threshold: 0,
+            ..settings()
+        };
+        let now = Instant::now();
+        for _ in 0..10 {
+            breakers.record_failure(URL, &disabled, now);
+        }
+        assert!(breakers.try_acquire(URL, &disabled, now).is_ok());
+        assert!(breakers.statuses().is_empty());
+    }
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// `?dry_run=` parameter of destructive endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct DryRunQuery {
+    pub dry_run: Option<bool>,
+}
+
+impl DryRunQuery {
+    /// Whether to only report, with `default` used when the caller didn't say
+    pub fn is_dry_run(&self, default: bool) -> bool {
+        self.dry_run.unwrap_or(default)
+    }
+}
+
+/// What a destructive operation would remove, returned instead of performing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DryRunReport {
+    pub dry_run: bool,
+    pub count: usize,
+    pub ids: Vec<String>,
+}
+
+impl DryRunReport {
+    pub fn new(ids: Vec<String>) -> Self {
+        Self {
+            dry_run: true,
+            count: ids.len(),
+            ids,
+        }
+    }
+}
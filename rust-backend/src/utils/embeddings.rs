@@ -139,7 +139,7 @@ pub async fn generate_openai_embeddings(
     texts: Vec<String>,
     dimension: Option<i32>,
 ) -> Result<Vec<Vec<f32>>, AppError> {
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(base_url);
 
     let mut payload = json!({
         "model": model,
@@ -150,13 +150,15 @@ pub async fn generate_openai_embeddings(
         payload["dimensions"] = json!(dim);
     }
 
-    let response = client
-        .post(format!("{}/embeddings", base_url))
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+    let response = crate::utils::telemetry::send(
+        client
+            .post(format!("{}/embeddings", base_url))
+            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Content-Type", "application/json")
+            .json(&payload),
+        "embeddings",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -186,7 +188,7 @@ pub async fn generate_azure_openai_embeddings(
     api_version: &str,
     dimension: Option<i32>,
 ) -> Result<Vec<Vec<f32>>, AppError> {
-    let client = reqwest::Client::new();
+    let client = crate::utils::http::client_for(base_url);
 
     let url = format!(
         "{}/openai/deployments/{}/embeddings?api-version={}",
@@ -201,13 +203,15 @@ pub async fn generate_azure_openai_embeddings(
         payload["dimensions"] = json!(dim);
     }
 
-    let response = client
-        .post(&url)
-        .header("api-key", api_key)
-        .header("Content-Type", "application/json")
-        .json(&payload)
-        .send()
-        .await?;
+    let response = crate::utils::telemetry::send(
+        client
+            .post(&url)
+            .header("api-key", api_key)
+            .header("Content-Type", "application/json")
+            .json(&payload),
+        "embeddings",
+    )
+    .await?;
 
     if !response.status().is_success() {
         let status = response.status();
@@ -0,0 +1,299 @@
+/// Per-user and per-request feature flag overrides
+///
+/// Flags listed in FEATURE_FLAG_OVERRIDES can be switched for one user through
+/// `settings.feature_flags` (set by an admin) or for one request through an
+/// `X-Feature-Flags` token an admin signed. The header wins over the user's settings;
+/// anything not marked overridable keeps its global value. Only flags whose server-side
+/// check goes through [`FeatureFlags::enabled`] can be overridden, so an override
+/// changes what the server allows and not just what /api/config reports.
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+use crate::utils::auth::derive_key;
+
+pub const FEATURE_FLAGS_HEADER: &str = "X-Feature-Flags";
+
+/// Key in `user.settings` holding that user's overrides
+pub const SETTINGS_KEY: &str = "feature_flags";
+
+/// Flag tokens are signed with a key derived for this purpose, never the session key
+const TOKEN_PURPOSE: &str = "feature-flags";
+
+type FlagField = fn(&mut Config) -> &mut bool;
+
+/// Flags that can ever be overridden; FEATURE_FLAG_OVERRIDES picks from these
+///
+/// Each must be checked through [`FeatureFlags::enabled`] wherever the server enforces it.
+const OVERRIDABLE: &[(&str, FlagField)] = &[
+    ("enable_code_execution", |c| &mut c.enable_code_execution),
+    ("enable_image_generation", |c| &mut c.enable_image_generation),
+    ("enable_autocomplete_generation", |c| {
+        &mut c.enable_autocomplete_generation
+    }),
+];
+
+pub fn is_known_flag(flag: &str) -> bool {
+    OVERRIDABLE.iter().any(|(name, _)| *name == flag)
+}
+
+/// Reject flags the deployment hasn't marked overridable
+pub fn check_overridable(config: &Config, flags: &BTreeMap<String, bool>) -> AppResult<()> {
+    let rejected: Vec<&str> = flags
+        .keys()
+        .filter(|flag| !config.feature_flag_overrides.contains(flag))
+        .map(String::as_str)
+        .collect();
+    if rejected.is_empty() {
+        return Ok(());
+    }
+    Err(AppError::BadRequest(format!(
+        "Feature flags not overridable: {}",
+        rejected.join(", ")
+    )))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FlagClaims {
+    feature_flags: BTreeMap<String, bool>,
+    /// Only this user's requests may use the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+    exp: i64,
+    iat: i64,
+}
+
+/// Sign overrides for the `X-Feature-Flags` header; returns the token and its expiry
+///
+/// The key is derived from `secret` for flag tokens only, and the claims carry no `id`,
+/// so the token can't pass as a session token or the other way round.
+pub fn sign(
+    flags: &BTreeMap<String, bool>,
+    user_id: Option<&str>,
+    secret: &str,
+    ttl_seconds: i64,
+) -> AppResult<(String, i64)> {
+    let iat = Utc::now().timestamp();
+    let claims = FlagClaims {
+        feature_flags: flags.clone(),
+        user_id: user_id.map(str::to_string),
+        exp: iat + ttl_seconds,
+        iat,
+    };
+    let token = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(derive_key(secret, TOKEN_PURPOSE).as_bytes()),
+    )?;
+    Ok((token, claims.exp))
+}
+
+/// Overrides in effect for one request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeatureFlags {
+    overrides: BTreeMap<String, bool>,
+}
+
+impl FeatureFlags {
+    /// Overrides for `user`, with a signed header token on top
+    ///
+    /// Invalid or expired tokens, and tokens minted for another user, are ignored.
+    pub fn resolve(config: &Config, user: &User, header: Option<&str>) -> Self {
+        let mut overrides = BTreeMap::new();
+        if config.feature_flag_overrides.is_empty() {
+            return Self { overrides };
+        }
+
+        if let Some(Value::Object(flags)) = user
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.get(SETTINGS_KEY))
+        {
+            for (flag, value) in flags {
+                if let Some(value) = value.as_bool() {
+                    overrides.insert(flag.clone(), value);
+                }
+            }
+        }
+
+        if let Some(token) = header {
+            match decode::<FlagClaims>(
+                token,
+                &DecodingKey::from_secret(
+                    derive_key(&config.webui_secret_key, TOKEN_PURPOSE).as_bytes(),
+                ),
+                &Validation::default(),
+            ) {
+                Ok(data)
+                    if data
+                        .claims
+                        .user_id
+                        .as_deref()
+                        .is_none_or(|id| id == user.id) =>
+                {
+                    overrides.extend(data.claims.feature_flags);
+                }
+                Ok(_) => tracing::debug!("Ignoring feature flag token for another user"),
+                Err(e) => tracing::debug!("Ignoring invalid feature flag token: {}", e),
+            }
+        }
+
+        overrides.retain(|flag, _| config.feature_flag_overrides.contains(flag));
+        Self { overrides }
+    }
+
+    /// Overrides for the signed-in user of `req`
+    pub fn from_request(config: &Config, user: &User, req: &actix_web::HttpRequest) -> Self {
+        let header = req
+            .headers()
+            .get(FEATURE_FLAGS_HEADER)
+            .and_then(|value| value.to_str().ok());
+        Self::resolve(config, user, header)
+    }
+
+    /// `flag` for this request, or `default` when it isn't overridden
+    pub fn enabled(&self, flag: &str, default: bool) -> bool {
+        self.overrides.get(flag).copied().unwrap_or(default)
+    }
+
+    /// Write the overrides into a copy of the config, e.g. to report effective features
+    pub fn apply(&self, config: &mut Config) {
+        for (name, field) in OVERRIDABLE {
+            if let Some(value) = self.overrides.get(*name) {
+                *field(config) = *value;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Config {
+        Config::from_lookup(|key| match key {
+            "WEBUI_SECRET_KEY" => Some("secret".to_string()),
+            "FEATURE_FLAG_OVERRIDES" => Some("enable_image_generation".to_string()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    fn user(id: &str, settings: Option<Value>) -> User {
+        User {
+            id: id.to_string(),
+            name: id.to_string(),
+            email: format!("{}@example.com", id),
+            username: None,
+            role: "user".to_string(),
+            profile_image_url: String::new(),
+            bio: None,
+            gender: None,
+            date_of_birth: None,
+            info: None,
+            settings,
+            api_key: None,
+            oauth_sub: None,
+            last_active_at: 0,
+            updated_at: 0,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_user_override_enables_feature_for_that_user_only() {
+        let config = config();
+        assert!(!config.enable_image_generation);
+        let beta = user(
+            "beta",
+            Some(json!({ "feature_flags": {
+                "enable_image_generation": true,
+                "enable_code_execution": !config.enable_code_execution,
+            } })),
+        );
+        let other = user("other", None);
+
+        let flags = FeatureFlags::resolve(&config, &beta, None);
+        assert!(flags.enabled("enable_image_generation", config.enable_image_generation));
+        // Not marked overridable, so the global value stands
+        assert_eq!(
+            flags.enabled("enable_code_execution", config.enable_code_execution),
+            config.enable_code_execution
+        );
+        let mut effective = config.clone();
+        flags.apply(&mut effective);
+        assert!(effective.enable_image_generation);
+        assert_eq!(
+            effective.enable_code_execution,
+            config.enable_code_execution
+        );
+
+        let flags = FeatureFlags::resolve(&config, &other, None);
+        assert!(flags.is_empty());
+        assert!(!flags.enabled("enable_image_generation", config.enable_image_generation));
+    }
+
+    #[test]
+    fn test_only_enforced_flags_are_overridable() {
+        for flag in ["enable_web_search", "enable_channels", "enable_notes"] {
+            assert!(!is_known_flag(flag), "{} isn't enforced server-side", flag);
+        }
+        assert!(is_known_flag("enable_image_generation"));
+    }
+
+    #[test]
+    fn test_signed_header_bound_to_user() {
+        let config = config();
+        let flags = BTreeMap::from([("enable_image_generation".to_string(), true)]);
+        let (token, _) = sign(&flags, Some("beta"), "secret", 60).unwrap();
+
+        let beta = FeatureFlags::resolve(&config, &user("beta", None), Some(&token));
+        assert!(beta.enabled("enable_image_generation", false));
+        let other = FeatureFlags::resolve(&config, &user("other", None), Some(&token));
+        assert!(other.is_empty());
+
+        // Forged, expired and session tokens are ignored, as is a token signed with the
+        // session key itself
+        let (forged, _) = sign(&flags, None, "wrong-secret", 60).unwrap();
+        let (expired, _) = sign(&flags, None, "secret", -120).unwrap();
+        let session = crate::utils::auth::create_jwt("beta", "secret", "1h").unwrap();
+        let session_keyed = encode(
+            &Header::default(),
+            &FlagClaims {
+                feature_flags: flags.clone(),
+                user_id: None,
+                exp: Utc::now().timestamp() + 60,
+                iat: Utc::now().timestamp(),
+            },
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        for token in [forged, expired, session, session_keyed] {
+            assert!(FeatureFlags::resolve(&config, &user("beta", None), Some(&token)).is_empty());
+        }
+        assert!(crate::utils::auth::verify_jwt(&token, "secret").is_err());
+    }
+
+    #[test]
+    fn test_check_overridable() {
+        let config = config();
+        let allowed = BTreeMap::from([("enable_image_generation".to_string(), true)]);
+        assert!(check_overridable(&config, &allowed).is_ok());
+        let denied = BTreeMap::from([("enable_code_execution".to_string(), false)]);
+        assert!(matches!(
+            check_overridable(&config, &denied),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
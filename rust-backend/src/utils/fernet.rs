@@ -1,6 +1,10 @@
 use crate::error::{AppError, AppResult};
 use aes::Aes128;
-use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine as _,
+};
 /// Fernet-compatible encryption/decryption for OAuth tokens
 /// Compatible with Python's cryptography.fernet.Fernet
 use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
@@ -12,6 +16,15 @@ type HmacSha256 = Hmac<Sha256>;
 
 const FERNET_VERSION: u8 = 0x80;
 
+/// Python emits padded base64url keys and tokens; unpadded input is still accepted
+/// for tokens written by earlier versions of this backend
+const FERNET_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
 pub struct Fernet {
     signing_key: [u8; 16],
     encryption_key: [u8; 16],
@@ -23,7 +36,7 @@ impl Fernet {
     pub fn new(key: &str) -> AppResult<Self> {
         let key_bytes = if key.len() == 44 {
             // Properly formatted Fernet key
-            URL_SAFE_NO_PAD
+            FERNET_BASE64
                 .decode(key.as_bytes())
                 .map_err(|e| AppError::Auth(format!("Invalid Fernet key format: {}", e)))?
         } else {
@@ -62,13 +75,18 @@ impl Fernet {
         use rand::RngCore;
         rand::rng().fill_bytes(&mut iv);
 
+        self.encrypt_from_parts(data, timestamp, &iv)
+    }
+
+    /// Encrypt with a fixed timestamp and IV, mirroring Python's `_encrypt_from_parts`
+    fn encrypt_from_parts(&self, data: &[u8], timestamp: u64, iv: &[u8; 16]) -> AppResult<String> {
         // Encrypt data with AES-128-CBC
         let mut buffer = data.to_vec();
         // Add PKCS7 padding
         let padding_len = 16 - (buffer.len() % 16);
         buffer.extend(vec![padding_len as u8; padding_len]);
 
-        let mut cipher = cbc::Encryptor::<Aes128>::new(&self.encryption_key.into(), &iv.into());
+        let mut cipher = cbc::Encryptor::<Aes128>::new(&self.encryption_key.into(), iv.into());
 
         // Encrypt in place, block by block
         use aes::cipher::generic_array::{typenum::U16, GenericArray};
@@ -83,7 +101,7 @@ impl Fernet {
         let mut token = Vec::new();
         token.push(FERNET_VERSION);
         token.extend_from_slice(&timestamp.to_be_bytes());
-        token.extend_from_slice(&iv);
+        token.extend_from_slice(iv);
         token.extend_from_slice(&ciphertext);
 
         // Calculate HMAC-SHA256 over version | timestamp | iv | ciphertext
@@ -96,13 +114,13 @@ impl Fernet {
         token.extend_from_slice(&hmac_result);
 
         // Base64url encode the entire token
-        Ok(URL_SAFE_NO_PAD.encode(&token))
+        Ok(FERNET_BASE64.encode(&token))
     }
 
     /// Decrypt a base64url-encoded Fernet token
     pub fn decrypt(&self, token: &str) -> AppResult<Vec<u8>> {
         // Decode base64url
-        let token_bytes = URL_SAFE_NO_PAD
+        let token_bytes = FERNET_BASE64
             .decode(token.as_bytes())
             .map_err(|e| AppError::Auth(format!("Invalid Fernet token encoding: {}", e)))?;
 
@@ -184,6 +202,78 @@ impl Fernet {
     }
 }
 
+/// Fernet with multiple keys, compatible with Python's cryptography.fernet.MultiFernet
+/// Encrypts with the first (primary) key and decrypts with any configured key,
+/// allowing old keys to be retired once all tokens are re-encrypted.
+pub struct MultiFernet {
+    fernets: Vec<Fernet>,
+}
+
+impl MultiFernet {
+    /// Create from keys ordered newest first
+    pub fn new(keys: &[&str]) -> AppResult<Self> {
+        if keys.is_empty() {
+            return Err(AppError::Auth(
+                "MultiFernet requires at least one key".to_string(),
+            ));
+        }
+
+        let fernets = keys
+            .iter()
+            .map(|key| Fernet::new(key))
+            .collect::<AppResult<Vec<_>>>()?;
+
+        Ok(Self { fernets })
+    }
+
+    /// Create from owned keys ordered newest first
+    pub fn from_keys(keys: &[String]) -> AppResult<Self> {
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        Self::new(&keys)
+    }
+
+    /// Number of configured keys (primary plus retired keys)
+    pub fn key_count(&self) -> usize {
+        self.fernets.len()
+    }
+
+    /// Encrypt data with the primary key
+    pub fn encrypt(&self, data: &[u8]) -> AppResult<String> {
+        self.fernets[0].encrypt(data)
+    }
+
+    /// Decrypt a token with the first key that verifies it
+    pub fn decrypt(&self, token: &str) -> AppResult<Vec<u8>> {
+        let mut last_error = None;
+        for fernet in &self.fernets {
+            match fernet.decrypt(token) {
+                Ok(plaintext) => return Ok(plaintext),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| AppError::Auth("No Fernet keys configured".to_string())))
+    }
+
+    /// Re-encrypt a token under the primary key
+    pub fn rotate(&self, token: &str) -> AppResult<String> {
+        let plaintext = self.decrypt(token)?;
+        self.encrypt(&plaintext)
+    }
+
+    /// Encrypt JSON data
+    pub fn encrypt_json<T: serde::Serialize>(&self, data: &T) -> AppResult<String> {
+        self.fernets[0].encrypt_json(data)
+    }
+
+    /// Decrypt JSON data
+    pub fn decrypt_json<T: serde::de::DeserializeOwned>(&self, token: &str) -> AppResult<T> {
+        let plaintext = self.decrypt(token)?;
+        let data = serde_json::from_slice(&plaintext)
+            .map_err(|e| AppError::Auth(format!("JSON deserialization error: {}", e)))?;
+        Ok(data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,4 +325,69 @@ mod tests {
 
         assert_eq!(data, decrypted);
     }
+
+    #[test]
+    fn test_multi_fernet_rotation() {
+        let old_key = "old_key";
+        let new_key = "new_key";
+
+        let old = MultiFernet::new(&[old_key]).unwrap();
+        let token = old.encrypt(b"secret").unwrap();
+
+        let rotated = MultiFernet::from_keys(&[new_key.to_string(), old_key.to_string()]).unwrap();
+        assert_eq!(rotated.key_count(), 2);
+        assert_eq!(rotated.decrypt(&token).unwrap(), b"secret".to_vec());
+
+        let new_token = rotated.rotate(&token).unwrap();
+        let new_only = MultiFernet::new(&[new_key]).unwrap();
+        assert_eq!(new_only.decrypt(&new_token).unwrap(), b"secret".to_vec());
+        assert!(new_only.decrypt(&token).is_err());
+    }
+
+    /// Known-answer vectors generated with Python's `cryptography.fernet.Fernet`
+    const PYTHON_VECTORS: &str = include_str!("../../tests/data/fernet_vectors.json");
+
+    fn python_vectors() -> Vec<serde_json::Value> {
+        serde_json::from_str(PYTHON_VECTORS).unwrap()
+    }
+
+    fn iv_from_hex(hex: &str) -> [u8; 16] {
+        let mut iv = [0u8; 16];
+        for (i, byte) in iv.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        iv
+    }
+
+    #[test]
+    fn test_decrypt_python_tokens() {
+        for vector in python_vectors() {
+            let fernet = Fernet::new(vector["key"].as_str().unwrap()).unwrap();
+            let plaintext = fernet.decrypt(vector["token"].as_str().unwrap()).unwrap();
+            assert_eq!(plaintext, vector["plaintext"].as_str().unwrap().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_encrypt_matches_python_tokens() {
+        for vector in python_vectors() {
+            let fernet = Fernet::new(vector["key"].as_str().unwrap()).unwrap();
+            let token = fernet
+                .encrypt_from_parts(
+                    vector["plaintext"].as_str().unwrap().as_bytes(),
+                    vector["timestamp"].as_u64().unwrap(),
+                    &iv_from_hex(vector["iv"].as_str().unwrap()),
+                )
+                .unwrap();
+            assert_eq!(token, vector["token"].as_str().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_decrypt_unpadded_token() {
+        let vector = &python_vectors()[0];
+        let fernet = Fernet::new(vector["key"].as_str().unwrap()).unwrap();
+        let unpadded = vector["token"].as_str().unwrap().trim_end_matches('=');
+        assert_eq!(fernet.decrypt(unpadded).unwrap(), b"hello".to_vec());
+    }
 }
@@ -0,0 +1,90 @@
+//! Client request headers passed through to upstream chat completions
+//!
+//! Only headers named in FORWARD_HEADERS reach the upstream, none by default, so
+//! cookies and credentials sent to this server stay here. Headers carrying credentials
+//! or describing the connection can't be allowlisted at all.
+use actix_web::HttpRequest;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Credentials for this server or the upstream, and headers the upstream request sets
+/// itself
+const PROTECTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-openai-key",
+    "x-api-key",
+    "api-key",
+    "host",
+    "content-length",
+    "content-type",
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "te",
+    "trailer",
+    "upgrade",
+    "expect",
+];
+
+/// Problems with the FORWARD_HEADERS names
+pub fn validate(names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter_map(|name| {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                Some(format!(
+                    "Invalid FORWARD_HEADERS entry '{}': not a header name",
+                    name
+                ))
+            } else if PROTECTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                Some(format!(
+                    "Invalid FORWARD_HEADERS entry '{}': this header is never forwarded",
+                    name
+                ))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The allowlisted headers of the client's request, for the upstream request
+pub fn forwarded_headers(req: &HttpRequest, allowlist: &[String]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in req.headers() {
+        if !allowlist
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(name.as_str()))
+            || PROTECTED_HEADERS.contains(&name.as_str())
+        {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_str().as_bytes()),
+            HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protected_headers_cannot_be_allowlisted() {
+        let names = vec![
+            "OpenAI-Organization".to_string(),
+            "Cookie".to_string(),
+            "bad header".to_string(),
+        ];
+        let errors = validate(&names);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("Cookie"));
+        assert!(errors[1].contains("bad header"));
+    }
+}
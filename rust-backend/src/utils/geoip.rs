@@ -0,0 +1,205 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::OnceLock;
+
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+
+use crate::config::Config;
+
+/// Approximate location of a client IP
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Region {
+    /// ISO 3166-1 country code
+    pub country: String,
+    /// ISO 3166-2 subdivision code, when the database has one (City databases)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+/// A MaxMind GeoIP2 or GeoLite2 Country/City database
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| anyhow::anyhow!("Failed to open GeoIP database {}: {}", path, e))?;
+        Ok(Self { reader })
+    }
+
+    /// Region of `client_ip`, which may carry a port; `None` when the address doesn't
+    /// parse or isn't in the database
+    pub fn lookup(&self, client_ip: &str) -> Option<Region> {
+        let ip = client_ip
+            .parse::<IpAddr>()
+            .or_else(|_| client_ip.parse::<SocketAddr>().map(|addr| addr.ip()))
+            .ok()?
+            .to_canonical();
+
+        let city: geoip2::City = match self.reader.lookup(ip) {
+            Ok(city) => city,
+            Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => return None,
+            Err(e) => {
+                tracing::debug!("GeoIP lookup for {} failed: {}", ip, e);
+                return None;
+            }
+        };
+
+        Some(Region {
+            country: city.country?.iso_code?.to_string(),
+            region: city
+                .subdivisions
+                .and_then(|subdivisions| subdivisions.into_iter().next())
+                .and_then(|subdivision| subdivision.iso_code)
+                .map(str::to_string),
+        })
+    }
+}
+
+/// Database opened from GEOIP_DB_PATH, set once at startup
+static GEOIP: OnceLock<GeoIp> = OnceLock::new();
+
+/// Open the GEOIP_DB_PATH database, if configured
+pub fn init(config: &Config) -> anyhow::Result<()> {
+    let Some(path) = config.geoip_db_path.as_deref().filter(|path| !path.is_empty()) else {
+        return Ok(());
+    };
+
+    let geoip = GeoIp::open(path)?;
+    tracing::info!("GeoIP region lookup enabled ({})", path);
+    let _ = GEOIP.set(geoip);
+    Ok(())
+}
+
+/// The configured database; `None` when GEOIP_DB_PATH is unset
+pub fn get() -> Option<&'static GeoIp> {
+    GEOIP.get()
+}
+
+/// Minimal MaxMind DB writer for tests
+#[cfg(test)]
+pub(crate) mod test_db {
+    use std::net::Ipv4Addr;
+    use std::path::Path;
+
+    fn control(kind: u8, size: usize) -> Vec<u8> {
+        assert!(size < 29);
+        if kind <= 7 {
+            vec![(kind << 5) | size as u8]
+        } else {
+            vec![size as u8, kind - 7]
+        }
+    }
+
+    fn string(s: &str) -> Vec<u8> {
+        [control(2, s.len()), s.as_bytes().to_vec()].concat()
+    }
+
+    fn uint(kind: u8, value: u64) -> Vec<u8> {
+        let bytes: Vec<u8> = value
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        [control(kind, bytes.len()), bytes].concat()
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = control(7, entries.len());
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    fn array(items: &[Vec<u8>]) -> Vec<u8> {
+        [control(11, items.len()), items.concat()].concat()
+    }
+
+    /// Write an IPv4 City database holding one network
+    pub fn write(path: &Path, network: Ipv4Addr, prefix_len: u32, country: &str, region: &str) {
+        // One node per prefix bit; the other branch of each is empty
+        let node_count = prefix_len;
+        let address = u32::from(network);
+        let mut tree = Vec::new();
+        for i in 0..prefix_len {
+            let bit = (address >> (31 - i)) & 1;
+            let next = if i + 1 < prefix_len {
+                i + 1
+            } else {
+                // Data pointer to offset 0 of the data section
+                node_count + 16
+            };
+            let records = if bit == 0 {
+                [next, node_count]
+            } else {
+                [node_count, next]
+            };
+            for record in records {
+                tree.extend(&record.to_be_bytes()[1..]);
+            }
+        }
+
+        let data = map(&[
+            ("country", map(&[("iso_code", string(country))])),
+            (
+                "subdivisions",
+                array(&[map(&[("iso_code", string(region))])]),
+            ),
+        ]);
+        let metadata = map(&[
+            ("binary_format_major_version", uint(5, 2)),
+            ("binary_format_minor_version", uint(5, 0)),
+            ("build_epoch", uint(9, 1_700_000_000)),
+            ("database_type", string("Test-City")),
+            ("description", map(&[])),
+            ("ip_version", uint(5, 4)),
+            ("languages", array(&[])),
+            ("node_count", uint(6, node_count as u64)),
+            ("record_size", uint(5, 24)),
+        ]);
+
+        let file = [
+            tree,
+            vec![0; 16],
+            data,
+            b"\xab\xcd\xefMaxMind.com".to_vec(),
+            metadata,
+        ]
+        .concat();
+        std::fs::write(path, file).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_resolves_known_ip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.mmdb");
+        test_db::write(&path, "81.2.69.0".parse().unwrap(), 24, "GB", "ENG");
+        let geoip = GeoIp::open(path.to_str().unwrap()).unwrap();
+
+        let expected = Some(Region {
+            country: "GB".to_string(),
+            region: Some("ENG".to_string()),
+        });
+        assert_eq!(geoip.lookup("81.2.69.160"), expected);
+        // Peer addresses carry a port; IPv4-mapped IPv6 is looked up as IPv4
+        assert_eq!(geoip.lookup("81.2.69.160:52814"), expected);
+        assert_eq!(geoip.lookup("::ffff:81.2.69.160"), expected);
+
+        assert_eq!(geoip.lookup("81.2.70.1"), None);
+        assert_eq!(geoip.lookup("unknown"), None);
+    }
+
+    #[test]
+    fn test_unconfigured_lookup_is_noop() {
+        assert!(init(&Config::from_lookup(|_| None).unwrap()).is_ok());
+        assert!(get().is_none());
+    }
+}
@@ -0,0 +1,214 @@
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::services::usage::estimate_prompt_tokens;
+
+/// Limits on the chat history forwarded upstream; both unset leaves history untouched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryLimit {
+    pub max_messages: Option<usize>,
+    pub max_tokens: Option<usize>,
+}
+
+/// Chat messages split into those to send and the older turns left out
+#[derive(Debug, Default, PartialEq)]
+pub struct TruncatedHistory {
+    pub kept: Vec<Value>,
+    pub dropped: Vec<Value>,
+}
+
+impl HistoryLimit {
+    /// `None` when neither MAX_HISTORY_MESSAGES nor MAX_HISTORY_TOKENS is set
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let limit = Self {
+            max_messages: config.max_history_messages,
+            max_tokens: config.max_history_tokens,
+        };
+        (limit != Self::default()).then_some(limit)
+    }
+
+    /// Keep leading system messages plus the newest turns that fit both limits
+    ///
+    /// System messages don't count towards the limits. The latest message is always
+    /// kept, and the kept window never starts with a tool result whose call was dropped.
+    pub fn truncate(&self, messages: Vec<Value>) -> TruncatedHistory {
+        let system_count = messages.iter().take_while(|m| role(m) == "system").count();
+
+        let mut budget_messages = self.max_messages.unwrap_or(usize::MAX);
+        let mut budget_tokens = self.max_tokens.unwrap_or(usize::MAX);
+        let mut start = messages.len();
+        while start > system_count {
+            let tokens =
+                estimate_prompt_tokens(std::slice::from_ref(&messages[start - 1])) as usize;
+            let is_latest = start == messages.len();
+            if !is_latest && (budget_messages == 0 || tokens > budget_tokens) {
+                break;
+            }
+            budget_messages = budget_messages.saturating_sub(1);
+            budget_tokens = budget_tokens.saturating_sub(tokens);
+            start -= 1;
+        }
+        while start + 1 < messages.len() && role(&messages[start]) == "tool" {
+            start += 1;
+        }
+
+        let mut messages = messages;
+        let recent = messages.split_off(start);
+        let dropped = messages.split_off(system_count);
+        messages.extend(recent);
+
+        TruncatedHistory {
+            kept: messages,
+            dropped,
+        }
+    }
+}
+
+impl TruncatedHistory {
+    /// Insert a summary of the dropped turns right after the system messages
+    pub fn insert_summary(&mut self, summary: &str) {
+        let position = self.kept.iter().take_while(|m| role(m) == "system").count();
+        self.kept.insert(
+            position,
+            json!({
+                "role": "system",
+                "content": format!("Summary of the earlier conversation:\n{}", summary.trim()),
+            }),
+        );
+    }
+}
+
+/// Prompt asking a model to summarize the dropped turns
+pub fn summary_prompt(dropped: &[Value]) -> String {
+    let transcript = dropped
+        .iter()
+        .map(|m| {
+            let content = match m.get("content") {
+                Some(Value::String(text)) => text.clone(),
+                Some(Value::Array(parts)) => parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                _ => String::new(),
+            };
+            format!("{}: {}", role(m), content)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "Summarize the following conversation in a few sentences. Keep names, facts, \
+         decisions and open questions the assistant will need to continue it. Reply with \
+         the summary only.\n\n<conversation>\n{}\n</conversation>",
+        transcript
+    )
+}
+
+fn role(message: &Value) -> &str {
+    message.get("role").and_then(|r| r.as_str()).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Value {
+        json!({ "role": role, "content": content })
+    }
+
+    fn conversation(turns: usize) -> Vec<Value> {
+        let mut messages = vec![message("system", "You are helpful.")];
+        for i in 0..turns {
+            messages.push(message("user", &format!("question {}", i)));
+            messages.push(message("assistant", &format!("answer {}", i)));
+        }
+        messages.push(message("user", "latest question"));
+        messages
+    }
+
+    fn contents(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|m| m["content"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_unset_limits_disable_truncation() {
+        let config = Config::from_lookup(|_| None).unwrap();
+        assert_eq!(HistoryLimit::from_config(&config), None);
+    }
+
+    #[test]
+    fn test_message_limit_keeps_system_and_recent_turns() {
+        let limit = HistoryLimit {
+            max_messages: Some(3),
+            max_tokens: None,
+        };
+        let history = limit.truncate(conversation(4));
+
+        assert_eq!(
+            contents(&history.kept),
+            vec![
+                "You are helpful.",
+                "question 3",
+                "answer 3",
+                "latest question"
+            ]
+        );
+        assert_eq!(history.dropped.len(), 6);
+        assert_eq!(history.dropped[0]["content"], "question 0");
+    }
+
+    #[test]
+    fn test_token_limit_keeps_latest_message() {
+        let limit = HistoryLimit {
+            max_messages: None,
+            max_tokens: Some(1),
+        };
+        let mut messages = conversation(2);
+        messages.push(message("user", &"long ".repeat(100)));
+        let history = limit.truncate(messages);
+
+        // The latest message is sent even when it alone exceeds the budget
+        assert_eq!(history.kept.len(), 2);
+        assert_eq!(history.kept[0]["role"], "system");
+        assert_eq!(history.dropped.len(), 5);
+    }
+
+    #[test]
+    fn test_window_does_not_start_with_orphaned_tool_result() {
+        let limit = HistoryLimit {
+            max_messages: Some(2),
+            max_tokens: None,
+        };
+        let messages = vec![
+            message("system", "sys"),
+            json!({ "role": "assistant", "content": "", "tool_calls": [] }),
+            message("tool", "result"),
+            message("user", "next"),
+        ];
+        let history = limit.truncate(messages);
+        assert_eq!(contents(&history.kept), vec!["sys", "next"]);
+        assert_eq!(history.dropped.len(), 2);
+    }
+
+    #[test]
+    fn test_summary_inserted_after_system_messages() {
+        let limit = HistoryLimit {
+            max_messages: Some(1),
+            max_tokens: None,
+        };
+        let mut history = limit.truncate(conversation(1));
+        history.insert_summary("User asked question 0.");
+
+        assert_eq!(history.kept.len(), 3);
+        assert_eq!(history.kept[1]["role"], "system");
+        assert!(history.kept[1]["content"]
+            .as_str()
+            .unwrap()
+            .ends_with("User asked question 0."));
+        assert_eq!(history.kept[2]["content"], "latest question");
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Proxy applied to every client built through this module, set once at startup
+static OUTBOUND_PROXY: OnceLock<Proxy> = OnceLock::new();
+
+/// Certificate settings for every client built through this module, set once at startup
+static TLS: OnceLock<TlsSettings> = OnceLock::new();
+
+/// Certificate verification overrides for upstreams under one URL prefix
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpstreamTls {
+    /// PEM bundle trusted in addition to EXTRA_CA_CERTS and the system roots
+    pub extra_ca_certs: Option<String>,
+    /// Skip verification entirely; for development against self-signed certs only
+    pub danger_accept_invalid_certs: bool,
+}
+
+struct TlsSettings {
+    extra_ca_certs: Vec<Certificate>,
+    accept_invalid_certs: bool,
+    /// Keyed by URL prefix; the longest matching prefix applies
+    upstreams: BTreeMap<String, (Vec<Certificate>, bool)>,
+}
+
+/// Route outbound HTTP through the configured proxy and trust the configured CAs
+///
+/// Must run before any client is built; clients created earlier connect directly
+/// and only trust the system roots.
+pub fn init(config: &Config) -> anyhow::Result<()> {
+    let _ = TLS.set(load_tls(config)?);
+
+    let Some(url) = &config.outbound_proxy_url else {
+        return Ok(());
+    };
+
+    let proxy = Proxy::all(url)
+        .map_err(|e| anyhow::anyhow!("Invalid outbound proxy URL: {}", e))?
+        .no_proxy(NoProxy::from_string(&config.no_proxy));
+    let _ = OUTBOUND_PROXY.set(proxy);
+    Ok(())
+}
+
+impl TlsSettings {
+    /// The UPSTREAM_TLS_OVERRIDES entry with the longest prefix of `url`
+    fn upstream(&self, url: &str) -> Option<&(Vec<Certificate>, bool)> {
+        self.upstreams
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, upstream)| upstream)
+    }
+}
+
+fn load_tls(config: &Config) -> anyhow::Result<TlsSettings> {
+    let mut tls = TlsSettings {
+        extra_ca_certs: match &config.extra_ca_certs {
+            Some(path) => load_ca_certs(path)?,
+            None => Vec::new(),
+        },
+        accept_invalid_certs: config.danger_accept_invalid_certs,
+        upstreams: BTreeMap::new(),
+    };
+    if tls.accept_invalid_certs {
+        tracing::error!(
+            "🚨 DANGER_ACCEPT_INVALID_CERTS is set: TLS certificates of ALL upstreams are not \
+             verified, so any of them can be impersonated. Never use this in production"
+        );
+    }
+
+    for (prefix, upstream) in &config.upstream_tls_overrides {
+        let certs = match &upstream.extra_ca_certs {
+            Some(path) => load_ca_certs(path)?,
+            None => Vec::new(),
+        };
+        if upstream.danger_accept_invalid_certs {
+            tracing::error!(
+                "🚨 TLS certificate verification is DISABLED for upstreams under {}; \
+                 they can be impersonated. Never use this in production",
+                prefix
+            );
+        }
+        tls.upstreams.insert(
+            prefix.clone(),
+            (certs, upstream.danger_accept_invalid_certs),
+        );
+    }
+    Ok(tls)
+}
+
+/// Read every certificate in the PEM bundle at `path`
+pub fn load_ca_certs(path: &str) -> anyhow::Result<Vec<Certificate>> {
+    let pem = std::fs::read(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read CA bundle {}: {}", path, e))?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| anyhow::anyhow!("Invalid CA bundle {}: {}", path, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("CA bundle {} contains no certificates", path);
+    }
+    tracing::info!(
+        "Trusting {} extra CA certificate(s) from {}",
+        certs.len(),
+        path
+    );
+    Ok(certs)
+}
+
+/// Trust `certs` on top of the system roots, and stop verifying at all if `accept_invalid`
+fn with_tls(builder: ClientBuilder, certs: &[Certificate], accept_invalid: bool) -> ClientBuilder {
+    let builder = certs.iter().fold(builder, |builder, cert| {
+        builder.add_root_certificate(cert.clone())
+    });
+    if accept_invalid {
+        builder.danger_accept_invalid_certs(true)
+    } else {
+        builder
+    }
+}
+
+/// Client builder with the outbound proxy and CA certs applied; use instead of `Client::builder()`
+pub fn builder() -> ClientBuilder {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = OUTBOUND_PROXY.get() {
+        builder = builder.proxy(proxy.clone());
+    }
+    match TLS.get() {
+        Some(tls) => with_tls(builder, &tls.extra_ca_certs, tls.accept_invalid_certs),
+        None => builder,
+    }
+}
+
+fn upstream_tls(url: &str) -> Option<&'static (Vec<Certificate>, bool)> {
+    TLS.get()?.upstream(url)
+}
+
+/// Like [`builder`], plus the UPSTREAM_TLS_OVERRIDES entry matching `url`, if any
+pub fn builder_for(url: &str) -> ClientBuilder {
+    match upstream_tls(url) {
+        Some((certs, accept_invalid)) => with_tls(builder(), certs, *accept_invalid),
+        None => builder(),
+    }
+}
+
+/// Default client with the outbound proxy and CA certs applied; use instead of `Client::new()`
+pub fn client() -> reqwest::Client {
+    builder().build().expect("Failed to build HTTP client")
+}
+
+/// [`client`] for calls to `url`, honouring its UPSTREAM_TLS_OVERRIDES entry
+pub fn client_for(url: &str) -> reqwest::Client {
+    builder_for(url)
+        .build()
+        .expect("Failed to build HTTP client")
+}
+
+/// `shared` for calls to `url`, unless UPSTREAM_TLS_OVERRIDES needs a client of its own
+pub fn upstream_client(shared: &reqwest::Client, url: &str) -> reqwest::Client {
+    match upstream_tls(url) {
+        Some(_) => client_for(url),
+        None => shared.clone(),
+    }
+}
+
+/// Whether a NO_PROXY list makes requests to `url` bypass the proxy
+///
+/// Follows the usual conventions: `*` matches everything, domains match themselves and
+/// their subdomains (with or without a leading dot) and IPs match exactly.
+pub fn bypasses_proxy(no_proxy: &str, url: &str) -> bool {
+    let Some(host) = reqwest::Url::parse(url).ok().and_then(|url| {
+        url.host_str()
+            .map(|h| h.trim_matches(['[', ']']).to_lowercase())
+    }) else {
+        return false;
+    };
+
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            if let Ok(ip) = entry.parse::<IpAddr>() {
+                return host.parse::<IpAddr>() == Ok(ip);
+            }
+            let domain = entry.trim_start_matches('.');
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed "Test Private CA" certificate
+    const TEST_CA_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBjDCCATGgAwIBAgIUG5ChfswypMIT0mmAeo2SjYse3cQwCgYIKoZIzj0EAwIw
+GjEYMBYGA1UEAwwPVGVzdCBQcml2YXRlIENBMCAXDTI2MTAxNzA0NDUwMVoYDzIx
+MjYwOTIzMDQ0NTAxWjAaMRgwFgYDVQQDDA9UZXN0IFByaXZhdGUgQ0EwWTATBgcq
+hkjOPQIBBggqhkjOPQMBBwNCAAQAVEYUOPUtZJdVqIpG1S2W48NLA/3KIkfed+FH
+BtoQ3OW+n9sda+Joy7ZhRAnjMRgolqnwH0MsfvpK8GaYoHg1o1MwUTAdBgNVHQ4E
+FgQUm35yzkKH32GGOjVbli/VXKp5EwUwHwYDVR0jBBgwFoAUm35yzkKH32GGOjVb
+li/VXKp5EwUwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNJADBGAiEArBqD
+TUyM+S88FPpJ89wllvCU1qiFY1LB9KG5COH4W68CIQCC7mgs1qGlefigYXbHivfk
+S2IUukXoKG79bHhT8ihkLw==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_custom_ca_loaded_into_client_builder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("private-ca.pem");
+        std::fs::write(&path, TEST_CA_PEM).unwrap();
+        let path = path.to_str().unwrap().to_string();
+
+        let overrides = format!(
+            r#"{{"https://llm.internal/": {{"extra_ca_certs": "{}"}},
+                "https://llm.internal/dev/": {{"danger_accept_invalid_certs": true}}}}"#,
+            path
+        );
+        let config = Config::from_lookup(move |key| match key {
+            "EXTRA_CA_CERTS" => Some(path.clone()),
+            "UPSTREAM_TLS_OVERRIDES" => Some(overrides.clone()),
+            _ => None,
+        })
+        .unwrap();
+        let tls = load_tls(&config).unwrap();
+
+        assert_eq!(tls.extra_ca_certs.len(), 1);
+        assert!(!tls.accept_invalid_certs);
+        let (certs, accept_invalid) = tls.upstream("https://llm.internal/v1").unwrap();
+        assert_eq!((certs.len(), *accept_invalid), (1, false));
+        let (certs, accept_invalid) = tls.upstream("https://llm.internal/dev/v1").unwrap();
+        assert_eq!((certs.len(), *accept_invalid), (0, true));
+        assert!(tls.upstream("https://api.openai.com/v1").is_none());
+
+        let builder = with_tls(reqwest::Client::builder(), &tls.extra_ca_certs, false);
+        assert!(builder.build().is_ok());
+
+        assert!(load_ca_certs("/nonexistent/ca.pem").is_err());
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+        assert!(load_ca_certs(empty.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_bypasses_proxy() {
+        let no_proxy = "localhost, .internal.example.com,10.0.0.5";
+        assert!(bypasses_proxy(no_proxy, "http://localhost:11434"));
+        assert!(bypasses_proxy(
+            no_proxy,
+            "https://llm.internal.example.com/v1"
+        ));
+        assert!(bypasses_proxy(no_proxy, "https://internal.example.com"));
+        assert!(bypasses_proxy(no_proxy, "http://10.0.0.5:8000/v1"));
+        assert!(!bypasses_proxy(no_proxy, "https://api.openai.com/v1"));
+        assert!(!bypasses_proxy(no_proxy, "https://notinternal.example.com"));
+        assert!(bypasses_proxy("*", "https://api.openai.com/v1"));
+        assert!(!bypasses_proxy("", "https://api.openai.com/v1"));
+    }
+}
@@ -0,0 +1,234 @@
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+/// Admin limits on images embedded in chat requests
+///
+/// Only inline `data:` URLs are inspected; remote image URLs are forwarded as-is since
+/// their size and type aren't known without fetching them.
+#[derive(Debug, Clone, Default)]
+pub struct ImagePolicy {
+    /// Largest decoded image in bytes (0 = none)
+    pub max_size: u64,
+    /// Largest decoded total across the request in bytes (0 = none)
+    pub max_total_size: u64,
+    /// Accepted MIME types, lowercase (empty = any)
+    pub allowed_types: Vec<String>,
+    /// Reject images sent to a non-vision model instead of stripping them
+    pub reject_non_vision: bool,
+}
+
+impl ImagePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_size: config.max_image_size,
+            max_total_size: config.max_image_total_size,
+            allowed_types: config.allowed_image_types.clone(),
+            reject_non_vision: config.non_vision_image_handling == "error",
+        }
+    }
+
+    /// Check every `image_url` part of a chat payload against the policy
+    ///
+    /// With `vision` false the images are stripped (or rejected, if configured) instead.
+    /// Violations are returned as 400s so nothing oversized reaches the provider.
+    pub fn apply(&self, payload: &mut Value, vision: bool) -> AppResult<()> {
+        let Some(messages) = payload.get_mut("messages").and_then(Value::as_array_mut) else {
+            return Ok(());
+        };
+
+        let mut total: u64 = 0;
+        for message in messages {
+            let Some(parts) = message.get_mut("content").and_then(Value::as_array_mut) else {
+                continue;
+            };
+
+            if !vision {
+                let before = parts.len();
+                parts.retain(|part| !is_image_part(part));
+                if parts.len() < before {
+                    if self.reject_non_vision {
+                        return Err(AppError::BadRequest(
+                            "The selected model does not accept images".to_string(),
+                        ));
+                    }
+                    tracing::debug!(
+                        "Stripped {} image(s) sent to a non-vision model",
+                        before - parts.len()
+                    );
+                }
+                continue;
+            }
+
+            for part in parts.iter().filter(|part| is_image_part(part)) {
+                if let Some(size) = self.check_image(part)? {
+                    total += size;
+                }
+            }
+        }
+
+        if self.max_total_size > 0 && total > self.max_total_size {
+            return Err(AppError::BadRequest(format!(
+                "Images total {} bytes, more than the {} byte limit per request",
+                total, self.max_total_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Validate one image part; returns its decoded size when it is inline
+    fn check_image(&self, part: &Value) -> AppResult<Option<u64>> {
+        let url = match &part["image_url"] {
+            Value::String(url) => url.as_str(),
+            image_url => image_url["url"].as_str().unwrap_or_default(),
+        };
+        let Some(data_url) = url.strip_prefix("data:") else {
+            return Ok(None);
+        };
+        let Some((header, data)) = data_url.split_once(',') else {
+            return Err(AppError::BadRequest("Malformed image data URL".to_string()));
+        };
+
+        let mime = header
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !self.allowed_types.is_empty() && !self.allowed_types.contains(&mime) {
+            return Err(AppError::BadRequest(format!(
+                "Image type '{}' is not allowed; accepted types: {}",
+                mime,
+                self.allowed_types.join(", ")
+            )));
+        }
+
+        let size = if header.ends_with(";base64") {
+            decoded_len(data)
+        } else {
+            data.len() as u64
+        };
+        if self.max_size > 0 && size > self.max_size {
+            return Err(AppError::BadRequest(format!(
+                "Image is {} bytes, larger than the {} byte limit",
+                size, self.max_size
+            )));
+        }
+
+        Ok(Some(size))
+    }
+}
+
+fn is_image_part(part: &Value) -> bool {
+    part.get("type").and_then(Value::as_str) == Some("image_url")
+}
+
+/// Size of base64 data once decoded, without decoding it
+fn decoded_len(data: &str) -> u64 {
+    let data = data.trim_end();
+    let padding = data.bytes().rev().take_while(|b| *b == b'=').count() as u64;
+    (data.len() as u64 * 3 / 4).saturating_sub(padding)
+}
+
+/// Vision flag from a model's `meta.capabilities`; `None` when the model doesn't say
+pub fn vision_capability(meta: &Value) -> Option<bool> {
+    meta.pointer("/capabilities/vision")
+        .and_then(Value::as_bool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy() -> ImagePolicy {
+        ImagePolicy {
+            max_size: 8,
+            max_total_size: 12,
+            allowed_types: vec!["image/png".to_string()],
+            reject_non_vision: false,
+        }
+    }
+
+    /// Payload with one user message holding text and the given image URLs
+    fn payload(urls: &[&str]) -> Value {
+        let mut content = vec![json!({"type": "text", "text": "What is this?"})];
+        content.extend(
+            urls.iter()
+                .map(|url| json!({"type": "image_url", "image_url": {"url": url}})),
+        );
+        json!({"model": "m", "messages": [{"role": "user", "content": content}]})
+    }
+
+    #[test]
+    fn test_decoded_len() {
+        assert_eq!(decoded_len("aGVsbG8="), 5);
+        assert_eq!(decoded_len("aGk="), 2);
+        assert_eq!(decoded_len("aGV5"), 3);
+    }
+
+    #[test]
+    fn test_images_within_limits_pass() {
+        // 6 bytes each, 12 in total
+        let mut body = payload(&[
+            "data:image/png;base64,aGVsbG8h",
+            "data:image/png;base64,aGVsbG8h",
+            "https://example.com/remote.jpg",
+        ]);
+        policy().apply(&mut body, true).unwrap();
+        assert_eq!(body["messages"][0]["content"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_oversize_image_rejected() {
+        // 9 bytes decoded
+        let mut body = payload(&["data:image/png;base64,aGVsbG8gOTkh"]);
+        let err = policy().apply(&mut body, true).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("8 byte limit")));
+    }
+
+    #[test]
+    fn test_total_image_size_rejected() {
+        let mut body = payload(&[
+            "data:image/png;base64,aGVsbG8h",
+            "data:image/png;base64,aGVsbG8h",
+            "data:image/png;base64,aGk=",
+        ]);
+        let err = policy().apply(&mut body, true).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("per request")));
+    }
+
+    #[test]
+    fn test_disallowed_type_rejected() {
+        let mut body = payload(&["data:image/svg+xml;base64,aGk="]);
+        let err = policy().apply(&mut body, true).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg.contains("image/svg+xml")));
+    }
+
+    #[test]
+    fn test_non_vision_model_images_stripped_or_rejected() {
+        let mut body = payload(&["data:image/png;base64,aGk="]);
+        policy().apply(&mut body, false).unwrap();
+        assert_eq!(
+            body["messages"][0]["content"],
+            json!([{"type": "text", "text": "What is this?"}])
+        );
+
+        let strict = ImagePolicy {
+            reject_non_vision: true,
+            ..policy()
+        };
+        let mut body = payload(&["data:image/png;base64,aGk="]);
+        assert!(strict.apply(&mut body, false).is_err());
+    }
+
+    #[test]
+    fn test_vision_capability() {
+        assert_eq!(
+            vision_capability(&json!({"capabilities": {"vision": false}})),
+            Some(false)
+        );
+        assert_eq!(vision_capability(&json!({})), None);
+    }
+}
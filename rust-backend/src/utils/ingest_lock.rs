@@ -0,0 +1,144 @@
+//! One ingestion job per knowledge base at a time
+//!
+//! A reindex deletes a knowledge base's vectors while a batch add upserts into them; run
+//! together they can leave the collection half rebuilt. Jobs that write a base's vectors
+//! take its lock first, and a job arriving while another holds it is rejected with a
+//! conflict. The lock is released when the job's guard drops, whether the job completed
+//! or failed. A lock held longer than KNOWLEDGE_INGEST_LOCK_TIMEOUT is treated as
+//! abandoned and can be taken over. Locks are per instance.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, AppResult};
+
+struct Held {
+    token: u64,
+    job: &'static str,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct LockState {
+    held: HashMap<String, Held>,
+    next_token: u64,
+}
+
+#[derive(Clone)]
+pub struct IngestLocks {
+    state: Arc<Mutex<LockState>>,
+    timeout: Duration,
+}
+
+/// Holds a knowledge base's lock until dropped
+pub struct IngestGuard {
+    state: Arc<Mutex<LockState>>,
+    knowledge_id: String,
+    token: u64,
+}
+
+impl IngestLocks {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(LockState::default())),
+            timeout,
+        }
+    }
+
+    /// Lock `knowledge_id` for `job`, or fail with a conflict naming the running job
+    pub fn try_lock(&self, knowledge_id: &str, job: &'static str) -> AppResult<IngestGuard> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(held) = state.held.get(knowledge_id) {
+            if held.since.elapsed() < self.timeout {
+                return Err(AppError::Conflict(format!(
+                    "Knowledge base {} is busy with a {} job; try again when it finishes",
+                    knowledge_id, held.job
+                )));
+            }
+            tracing::warn!(
+                "Taking over knowledge base {} from a {} job held for over {}s",
+                knowledge_id,
+                held.job,
+                self.timeout.as_secs()
+            );
+        }
+
+        state.next_token += 1;
+        let token = state.next_token;
+        state.held.insert(
+            knowledge_id.to_string(),
+            Held {
+                token,
+                job,
+                since: Instant::now(),
+            },
+        );
+        Ok(IngestGuard {
+            state: self.state.clone(),
+            knowledge_id: knowledge_id.to_string(),
+            token,
+        })
+    }
+}
+
+impl Drop for IngestGuard {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        // A lock taken over after the timeout belongs to the new job
+        if state
+            .held
+            .get(&self.knowledge_id)
+            .is_some_and(|held| held.token == self.token)
+        {
+            state.held.remove(&self.knowledge_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_second_ingestion_rejected_while_first_runs() {
+        let locks = IngestLocks::new(Duration::from_secs(60));
+        let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+        let (finish_tx, finish_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let first = tokio::spawn({
+            let locks = locks.clone();
+            async move {
+                let _guard = locks.try_lock("kb-1", "reindex").unwrap();
+                started_tx.send(()).unwrap();
+                let _ = finish_rx.await;
+            }
+        });
+        started_rx.await.unwrap();
+
+        let err = locks.try_lock("kb-1", "batch add").err().unwrap();
+        assert!(matches!(err, AppError::Conflict(ref message) if message.contains("reindex")));
+        // Other knowledge bases are unaffected
+        assert!(locks.try_lock("kb-2", "batch add").is_ok());
+
+        finish_tx.send(()).unwrap();
+        first.await.unwrap();
+        assert!(locks.try_lock("kb-1", "batch add").is_ok());
+    }
+
+    #[test]
+    fn test_abandoned_lock_taken_over_after_timeout() {
+        let locks = IngestLocks::new(Duration::ZERO);
+        let stale = locks.try_lock("kb-1", "reindex").unwrap();
+        let current = locks.try_lock("kb-1", "batch add").unwrap();
+
+        // The stale holder finishing doesn't release the new job's lock
+        drop(stale);
+        let locks = IngestLocks {
+            timeout: Duration::from_secs(60),
+            ..locks
+        };
+        assert!(locks.try_lock("kb-1", "reindex").is_err());
+        drop(current);
+        assert!(locks.try_lock("kb-1", "reindex").is_ok());
+    }
+}
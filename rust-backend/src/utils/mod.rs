@@ -1,17 +1,49 @@
 pub mod access_control;
+pub mod app_config;
 pub mod auth;
+pub mod auto_title;
 pub mod cache;
+pub mod capabilities;
+pub mod captcha;
 pub mod chat;
+pub mod chat_cache;
 pub mod chat_completion;
+pub mod chat_export;
 pub mod chat_middleware;
+pub mod circuit_breaker;
+pub mod dry_run;
 pub mod embeddings;
+pub mod feature_flags;
 pub mod fernet;
+pub mod forward_headers;
+pub mod geoip;
+pub mod history;
+pub mod http;
+pub mod image_policy;
+pub mod ingest_lock;
 pub mod misc;
+pub mod model_access;
+pub mod model_queue;
+pub mod model_routing;
+pub mod models_cache;
+pub mod moderation;
+pub mod pagination;
+pub mod param_policy;
 pub mod password;
+pub mod permissions;
 pub mod pipeline;
+pub mod readiness;
+pub mod redis_guard;
+pub mod redirect;
 pub mod retrieval;
+pub mod single_flight;
+pub mod stream_limits;
+pub mod structured_output;
 pub mod tasks;
+pub mod telemetry;
 pub mod template;
 pub mod time;
+pub mod totp;
+pub mod upstream_retry;
 pub mod version;
 pub mod webhook;
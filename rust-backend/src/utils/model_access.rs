@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::error::{AppError, AppResult};
+use crate::models::group::Group;
+use crate::models::user::User;
+use crate::services::group::GroupService;
+
+/// Models a user may call under MODEL_ALLOWLIST and MODEL_DENYLIST
+///
+/// Both map `user:<id or email>` and `group:<id or name>` to model ids, where a trailing
+/// `*` matches any suffix. A model denied by any entry that applies to the user is
+/// refused, even if another allows it; when any applicable entry allows models, only
+/// those are permitted. Admins aren't restricted. This narrows what a user can reach on
+/// top of each model's own access control.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelAccess {
+    /// `None` when no allowlist applies, so every model not denied is allowed
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+}
+
+/// Keys of the user's and their groups' entries
+fn keys(user: &User, groups: &[Group]) -> Vec<String> {
+    let mut keys = vec![format!("user:{}", user.id), format!("user:{}", user.email)];
+    for group in groups {
+        keys.push(format!("group:{}", group.id));
+        keys.push(format!("group:{}", group.name));
+    }
+    keys
+}
+
+fn entries(lists: &BTreeMap<String, Vec<String>>, keys: &[String]) -> Option<Vec<String>> {
+    let mut matched = keys.iter().filter_map(|key| lists.get(key)).peekable();
+    matched.peek()?;
+    Some(matched.flatten().cloned().collect())
+}
+
+fn matches(pattern: &str, model_id: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model_id.starts_with(prefix),
+        None => pattern == model_id,
+    }
+}
+
+impl ModelAccess {
+    /// The lists that apply to `user` as a member of `groups`
+    pub fn resolve(config: &Config, user: &User, groups: &[Group]) -> Self {
+        if user.role == "admin" {
+            return Self::default();
+        }
+        let keys = keys(user, groups);
+        Self {
+            allow: entries(&config.model_allowlist, &keys),
+            deny: entries(&config.model_denylist, &keys).unwrap_or_default(),
+        }
+    }
+
+    /// [`Self::resolve`], looking up the user's groups only when lists are configured
+    pub async fn for_user(db: &Database, config: &Config, user: &User) -> AppResult<Self> {
+        if config.model_allowlist.is_empty() && config.model_denylist.is_empty() {
+            return Ok(Self::default());
+        }
+        let groups = GroupService::new(db)
+            .get_groups_by_member_id(&user.id)
+            .await?;
+        Ok(Self::resolve(config, user, &groups))
+    }
+
+    pub fn allows(&self, model_id: &str) -> bool {
+        if self.deny.iter().any(|pattern| matches(pattern, model_id)) {
+            return false;
+        }
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|pattern| matches(pattern, model_id)))
+    }
+
+    /// 403 unless the user may call `model_id`
+    pub fn check(&self, model_id: &str) -> AppResult<()> {
+        if self.allows(model_id) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(format!(
+                "Model {} is not available to you",
+                model_id
+            )))
+        }
+    }
+}
+
+/// Check the keys of MODEL_ALLOWLIST or MODEL_DENYLIST
+pub fn validate_lists(name: &str, lists: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    lists
+        .keys()
+        .filter(|key| {
+            key.strip_prefix("user:")
+                .or_else(|| key.strip_prefix("group:"))
+                .is_none_or(str::is_empty)
+        })
+        .map(|key| {
+            format!(
+                "Invalid {} key '{}': expected user:<id or email> or group:<id or name>",
+                name, key
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(allowlist: &str, denylist: &str) -> Config {
+        let (allowlist, denylist) = (allowlist.to_string(), denylist.to_string());
+        Config::from_lookup(move |key| match key {
+            "MODEL_ALLOWLIST" => Some(allowlist.clone()),
+            "MODEL_DENYLIST" => Some(denylist.clone()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    fn user(role: &str) -> User {
+        serde_json::from_value(json!({
+            "id": "user-1",
+            "name": "Alice",
+            "email": "alice@example.com",
+            "role": role,
+            "profile_image_url": "/user.png",
+            "last_active_at": 0,
+            "updated_at": 0,
+            "created_at": 0,
+        }))
+        .unwrap()
+    }
+
+    fn group(name: &str) -> Group {
+        serde_json::from_value(json!({
+            "id": format!("{}-id", name),
+            "user_id": "owner",
+            "name": name,
+            "description": "",
+            "user_ids": [],
+            "created_at": 0,
+            "updated_at": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_denylist_overrides_allowlist() {
+        let config = config(
+            r#"{"group:research": ["gpt-4o", "claude-*"]}"#,
+            r#"{"user:alice@example.com": ["claude-3-opus"]}"#,
+        );
+        let access = ModelAccess::resolve(&config, &user("user"), &[group("research")]);
+
+        assert!(access.allows("gpt-4o"));
+        assert!(access.allows("claude-3-haiku"));
+        assert!(!access.allows("claude-3-opus"));
+        // Not on the group's allowlist
+        assert!(!access.allows("llama3"));
+        assert!(matches!(
+            access.check("claude-3-opus"),
+            Err(AppError::Forbidden(_))
+        ));
+
+        // Without the group only the denylist applies
+        let access = ModelAccess::resolve(&config, &user("user"), &[]);
+        assert!(access.allows("llama3"));
+        assert!(!access.allows("claude-3-opus"));
+
+        // Admins aren't restricted
+        let access = ModelAccess::resolve(&config, &user("admin"), &[group("research")]);
+        assert!(access.allows("llama3"));
+    }
+
+    #[test]
+    fn test_denied_model_filtered_from_list() {
+        let config = config("", r#"{"group:interns-id": ["gpt-4o"]}"#);
+        let access = ModelAccess::resolve(&config, &user("user"), &[group("interns")]);
+
+        let mut models = vec!["gpt-4o", "gpt-4o-mini", "llama3"];
+        models.retain(|id| access.allows(id));
+        assert_eq!(models, ["gpt-4o-mini", "llama3"]);
+    }
+
+    #[test]
+    fn test_keys_must_name_a_user_or_group() {
+        let config = config(r#"{"research": ["gpt-4o"], "group:": []}"#, "");
+        assert_eq!(
+            validate_lists("MODEL_ALLOWLIST", &config.model_allowlist).len(),
+            2
+        );
+        assert!(validate_lists("MODEL_DENYLIST", &config.model_denylist).is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+/// Per-model throttling for the chat proxy
+///
+/// A model with a limit, from the `rate_limit` object in its meta or its entry in
+/// MODEL_RATE_LIMITS, gets a gate: at most `requests_per_minute` requests start per
+/// minute and at most `max_concurrent` run at once, with up to `max_queue` more waiting
+/// for a slot. Waiters are served first come, first served, or with `priority` set,
+/// admins before users before everyone else. Throttled requests get 429 with Retry-After;
+/// a request that had to wait reports its place in the queue in X-Queue-Position.
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+
+use governor::{
+    clock::{Clock, DefaultClock},
+    state::{direct::NotKeyed, InMemoryState},
+    Quota, RateLimiter,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+/// Seconds a request turned away by a full queue is told to wait
+const QUEUE_FULL_RETRY_AFTER: u64 = 5;
+
+type DirectRateLimiter = RateLimiter<NotKeyed, InMemoryState, DefaultClock>;
+
+/// Waiters in serving order: highest priority first, then arrival
+type WaiterKey = (Reverse<u8>, u64);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelRateLimit {
+    /// Requests running at once (0 = unlimited)
+    pub max_concurrent: usize,
+    /// Requests waiting for a slot before more are turned away
+    pub max_queue: usize,
+    /// Requests started per minute (0 = unlimited)
+    pub requests_per_minute: u32,
+    /// Serve waiting admins, then users, ahead of other roles
+    pub priority: bool,
+}
+
+impl ModelRateLimit {
+    /// The limit for `model_id`: its meta's `rate_limit`, else its MODEL_RATE_LIMITS entry
+    pub fn for_model(config: &Config, model_id: &str, meta: Option<&Value>) -> Option<Self> {
+        meta.and_then(|meta| meta.get("rate_limit"))
+            .and_then(|limit| match Self::deserialize(limit) {
+                Ok(limit) => Some(limit),
+                Err(e) => {
+                    tracing::warn!("Ignoring invalid rate_limit on model {}: {}", model_id, e);
+                    None
+                }
+            })
+            .or_else(|| config.model_rate_limits.get(model_id).cloned())
+            .filter(|limit| limit.max_concurrent > 0 || limit.requests_per_minute > 0)
+    }
+}
+
+/// Held while a request to a throttled model runs; dropping it lets the next one in
+pub struct ModelPermit {
+    gate: Option<Arc<Gate>>,
+}
+
+impl Drop for ModelPermit {
+    fn drop(&mut self) {
+        let Some(gate) = self.gate.take() else {
+            return;
+        };
+        let next = {
+            let mut state = gate.state.lock().unwrap();
+            loop {
+                match state.waiters.pop_first() {
+                    Some((_, waiter)) if waiter.is_closed() => continue,
+                    Some((_, waiter)) => break Some(waiter),
+                    None => {
+                        state.available += 1;
+                        break None;
+                    }
+                }
+            }
+        };
+        if let Some(waiter) = next {
+            // A waiter that gave up meanwhile drops the permit, which hands it on again
+            let _ = waiter.send(ModelPermit { gate: Some(gate) });
+        }
+    }
+}
+
+struct Gate {
+    limit: ModelRateLimit,
+    rate: Option<DirectRateLimiter>,
+    state: Mutex<GateState>,
+}
+
+struct GateState {
+    available: usize,
+    next_seq: u64,
+    waiters: BTreeMap<WaiterKey, oneshot::Sender<ModelPermit>>,
+}
+
+impl Gate {
+    fn new(limit: ModelRateLimit) -> Self {
+        Self {
+            rate: NonZeroU32::new(limit.requests_per_minute)
+                .map(|rpm| RateLimiter::direct(Quota::per_minute(rpm))),
+            state: Mutex::new(GateState {
+                available: limit.max_concurrent,
+                next_seq: 0,
+                waiters: BTreeMap::new(),
+            }),
+            limit,
+        }
+    }
+
+    async fn acquire(
+        self: &Arc<Self>,
+        model_id: &str,
+        role: &str,
+    ) -> AppResult<(ModelPermit, Option<usize>)> {
+        if let Some(rate) = &self.rate {
+            if let Err(not_until) = rate.check() {
+                let wait = not_until.wait_time_from(DefaultClock::default().now());
+                return Err(AppError::RateLimited {
+                    message: format!("Rate limit reached for {}, retry later", model_id),
+                    retry_after: wait.as_secs_f64().ceil().max(1.0) as u64,
+                });
+            }
+        }
+        if self.limit.max_concurrent == 0 {
+            return Ok((ModelPermit { gate: None }, None));
+        }
+
+        let (position, slot) = {
+            let mut state = self.state.lock().unwrap();
+            state.waiters.retain(|_, waiter| !waiter.is_closed());
+            if state.available > 0 && state.waiters.is_empty() {
+                state.available -= 1;
+                return Ok((
+                    ModelPermit {
+                        gate: Some(self.clone()),
+                    },
+                    None,
+                ));
+            }
+            if state.waiters.len() >= self.limit.max_queue {
+                tracing::warn!(
+                    "Queue for {} is full ({} waiting)",
+                    model_id,
+                    state.waiters.len()
+                );
+                return Err(AppError::RateLimited {
+                    message: format!("{} is busy, retry later", model_id),
+                    retry_after: QUEUE_FULL_RETRY_AFTER,
+                });
+            }
+
+            let priority = if self.limit.priority {
+                role_priority(role)
+            } else {
+                0
+            };
+            let key = (Reverse(priority), state.next_seq);
+            state.next_seq += 1;
+            let (tx, rx) = oneshot::channel();
+            state.waiters.insert(key, tx);
+            (state.waiters.range(..key).count() + 1, rx)
+        };
+
+        let permit = slot.await.map_err(|_| {
+            AppError::InternalServerError(format!("Queue for {} was dropped", model_id))
+        })?;
+        Ok((permit, Some(position)))
+    }
+}
+
+fn role_priority(role: &str) -> u8 {
+    match role {
+        "admin" => 2,
+        "user" => 1,
+        _ => 0,
+    }
+}
+
+/// Gates for the throttled models, created on first use
+#[derive(Default)]
+pub struct ModelQueues {
+    gates: Mutex<HashMap<String, Arc<Gate>>>,
+}
+
+impl ModelQueues {
+    /// Wait for a slot on `model_id` under `limit`
+    ///
+    /// Also returns the queue position the request waited at, if it had to. A changed
+    /// limit starts a fresh gate; requests already running keep their slots on the old one.
+    pub async fn acquire(
+        &self,
+        model_id: &str,
+        limit: &ModelRateLimit,
+        role: &str,
+    ) -> AppResult<(ModelPermit, Option<usize>)> {
+        let gate = {
+            let mut gates = self.gates.lock().unwrap();
+            match gates.get(model_id) {
+                Some(gate) if gate.limit == *limit => gate.clone(),
+                _ => {
+                    let gate = Arc::new(Gate::new(limit.clone()));
+                    gates.insert(model_id.to_string(), gate.clone());
+                    gate
+                }
+            }
+        };
+        gate.acquire(model_id, role).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limit(max_concurrent: usize, max_queue: usize, priority: bool) -> ModelRateLimit {
+        ModelRateLimit {
+            max_concurrent,
+            max_queue,
+            requests_per_minute: 0,
+            priority,
+        }
+    }
+
+    async fn wait_for_waiters(queues: &ModelQueues, model_id: &str, count: usize) {
+        for _ in 0..100 {
+            let waiting = queues.gates.lock().unwrap()[model_id]
+                .state
+                .lock()
+                .unwrap()
+                .waiters
+                .len();
+            if waiting == count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("expected {} waiters", count);
+    }
+
+    #[tokio::test]
+    async fn test_requests_queue_and_higher_priority_goes_first() {
+        let queues = Arc::new(ModelQueues::default());
+        let limit = limit(1, 2, true);
+        let (running, position) = queues.acquire("gpt-4", &limit, "user").await.unwrap();
+        assert_eq!(position, None);
+
+        let served = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for (expected, role) in [(1, "user"), (1, "admin")] {
+            let (task_queues, task_limit, task_served) =
+                (queues.clone(), limit.clone(), served.clone());
+            waiters.push(tokio::spawn(async move {
+                let (permit, position) = task_queues
+                    .acquire("gpt-4", &task_limit, role)
+                    .await
+                    .unwrap();
+                task_served.lock().unwrap().push(role);
+                drop(permit);
+                assert_eq!(position, Some(expected));
+            }));
+            wait_for_waiters(&queues, "gpt-4", waiters.len()).await;
+        }
+
+        // The queue is full now
+        assert!(matches!(
+            queues.acquire("gpt-4", &limit, "admin").await,
+            Err(AppError::RateLimited { .. })
+        ));
+
+        drop(running);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*served.lock().unwrap(), vec!["admin", "user"]);
+    }
+
+    #[tokio::test]
+    async fn test_requests_per_minute_sets_retry_after() {
+        let queues = ModelQueues::default();
+        let limit = ModelRateLimit {
+            requests_per_minute: 1,
+            ..Default::default()
+        };
+
+        let _first = queues.acquire("gpt-4", &limit, "admin").await.unwrap();
+        let Err(AppError::RateLimited { retry_after, .. }) =
+            queues.acquire("gpt-4", &limit, "admin").await
+        else {
+            panic!("expected the second request to be throttled");
+        };
+        assert!((1..=60).contains(&retry_after));
+    }
+
+    #[test]
+    fn test_model_meta_overrides_config() {
+        let config = Config::from_lookup(|key| match key {
+            "MODEL_RATE_LIMITS" => Some(r#"{"gpt-4": {"max_concurrent": 2}}"#.to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        assert_eq!(
+            ModelRateLimit::for_model(&config, "gpt-4", None),
+            Some(limit(2, 0, false))
+        );
+        let meta = serde_json::json!({ "rate_limit": { "max_concurrent": 1, "priority": true } });
+        assert_eq!(
+            ModelRateLimit::for_model(&config, "gpt-4", Some(&meta)),
+            Some(limit(1, 0, true))
+        );
+        assert_eq!(
+            ModelRateLimit::for_model(&config, "gpt-4o-mini", None),
+            None
+        );
+    }
+}
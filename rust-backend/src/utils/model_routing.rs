@@ -0,0 +1,190 @@
+//! Rule-based routing of chat requests to models
+//!
+//! MODEL_ROUTING_RULES (editable with the OpenAI connection settings) sends a chat to
+//! another model when its latest user message carries one of a rule's tags (`#code`),
+//! the chat itself is tagged with it, or the message matches the rule's pattern. Rules
+//! are tried in order and the first match wins. A request with `"pin_model": true`
+//! keeps the model it asked for.
+use actix_web::http::header::{HeaderName, HeaderValue};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const ROUTE_HEADER: &str = "x-model-route";
+pub const EFFECTIVE_MODEL_HEADER: &str = "x-effective-model";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelRoutingRule {
+    /// Reported in `X-Model-Route` when the rule applies
+    pub name: String,
+    /// Message hashtags or chat tags that select the rule
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Regex matched against the latest user message
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Model the chat is sent to
+    pub model: String,
+}
+
+/// Tag as chat tags are stored: lowercase, `_` for spaces, no leading `#`
+fn normalize_tag(tag: &str) -> String {
+    tag.trim()
+        .trim_start_matches('#')
+        .replace(' ', "_")
+        .to_lowercase()
+}
+
+/// Problems with `rules`, as `(field, message)` with the field relative to the rule list
+pub fn validate_rules(rules: &[ModelRoutingRule]) -> Vec<(String, String)> {
+    let mut errors = Vec::new();
+    for (i, rule) in rules.iter().enumerate() {
+        if rule.name.trim().is_empty() {
+            errors.push((format!("[{}].name", i), "must not be empty".to_string()));
+        }
+        if rule.model.trim().is_empty() {
+            errors.push((format!("[{}].model", i), "must not be empty".to_string()));
+        }
+        if rule.tags.iter().all(|tag| normalize_tag(tag).is_empty()) && rule.pattern.is_none() {
+            errors.push((
+                format!("[{}]", i),
+                "needs tags or a pattern to match".to_string(),
+            ));
+        }
+        if let Some(Err(e)) = rule.pattern.as_deref().map(Regex::new) {
+            errors.push((format!("[{}].pattern", i), e.to_string()));
+        }
+    }
+    errors
+}
+
+/// Whether any rule matches on tags, and so needs the chat's tags looked up
+pub fn uses_tags(rules: &[ModelRoutingRule]) -> bool {
+    rules.iter().any(|rule| !rule.tags.is_empty())
+}
+
+/// Text of the last user message in a chat payload
+pub fn latest_user_text(payload: &Value) -> String {
+    let Some(message) = payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .and_then(|messages| {
+            messages
+                .iter()
+                .rev()
+                .find(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+        })
+    else {
+        return String::new();
+    };
+
+    match message.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        // Multimodal content: the text parts
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// `#hashtags` in a message, normalized
+fn message_tags(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '#' || c == '_' || c == '-'))
+        .filter_map(|word| word.strip_prefix('#'))
+        .map(normalize_tag)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// First rule matching the latest user message `text` or the chat's `chat_tags`
+pub fn match_rule<'a>(
+    rules: &'a [ModelRoutingRule],
+    text: &str,
+    chat_tags: &[String],
+) -> Option<&'a ModelRoutingRule> {
+    let mut tags = message_tags(text);
+    tags.extend(chat_tags.iter().map(|tag| normalize_tag(tag)));
+
+    rules.iter().find(|rule| {
+        rule.tags
+            .iter()
+            .any(|tag| tags.contains(&normalize_tag(tag)))
+            || rule
+                .pattern
+                .as_deref()
+                .and_then(|pattern| Regex::new(pattern).ok())
+                .is_some_and(|pattern| pattern.is_match(text))
+    })
+}
+
+/// Headers telling the client which rule routed a request and the model that answered
+pub fn route_headers(rule: Option<&str>, model_id: &str) -> Vec<(HeaderName, HeaderValue)> {
+    let Some(rule) = rule else {
+        return Vec::new();
+    };
+    [(ROUTE_HEADER, rule), (EFFECTIVE_MODEL_HEADER, model_id)]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            Some((
+                HeaderName::from_static(name),
+                HeaderValue::from_str(value).ok()?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules() -> Vec<ModelRoutingRule> {
+        serde_json::from_value(json!([
+            { "name": "code", "tags": ["#code"], "model": "qwen-coder" },
+            { "name": "sql", "pattern": "(?i)\\bselect\\b.+\\bfrom\\b", "model": "sql-model" },
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tagged_message_routes_to_configured_model() {
+        let rules = rules();
+        let payload = json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "user", "content": "Hi" },
+                { "role": "assistant", "content": "Hello!" },
+                { "role": "user", "content": "#code write a binary search in Rust" },
+            ],
+        });
+        let rule = match_rule(&rules, &latest_user_text(&payload), &[]).unwrap();
+        assert_eq!(rule.name, "code");
+        assert_eq!(rule.model, "qwen-coder");
+
+        // A chat tagged "Code" matches too, as do patterns
+        let rule = match_rule(&rules, "explain lifetimes", &["Code".to_string()]).unwrap();
+        assert_eq!(rule.model, "qwen-coder");
+        let rule = match_rule(&rules, "SELECT name FROM users", &[]).unwrap();
+        assert_eq!(rule.model, "sql-model");
+
+        assert!(match_rule(&rules, "what's the weather? #codex", &[]).is_none());
+    }
+
+    #[test]
+    fn test_invalid_rules_are_reported() {
+        let invalid: Vec<ModelRoutingRule> = serde_json::from_value(json!([
+            { "name": "", "tags": [], "model": "x" },
+            { "name": "bad", "pattern": "(", "model": "" },
+        ]))
+        .unwrap();
+        let fields: Vec<String> = validate_rules(&invalid)
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(fields, vec!["[0].name", "[0]", "[1].model", "[1].pattern"]);
+        assert!(validate_rules(&rules()).is_empty());
+    }
+}
@@ -0,0 +1,267 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::Config;
+
+/// Where a (possibly prefixed) model id is served from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelRoute {
+    /// Index of the OpenAI connection in `openai_api_base_urls`
+    pub url_idx: usize,
+    /// Model id as the upstream connection knows it (prefix stripped)
+    pub upstream_id: String,
+}
+
+/// Merged model list across OpenAI connections, with a reverse map for routing
+///
+/// Connections may set `prefix_id` in their API config so that models with the same
+/// upstream id stay distinct (`openai.gpt-4o` vs `azure.gpt-4o`). Unprefixed upstream
+/// ids remain routable as legacy aliases (first connection wins) so existing chats
+/// keep working after a prefix is configured.
+#[derive(Debug, Default)]
+pub struct ModelsCache {
+    models: HashMap<String, Value>,
+    routes: HashMap<String, ModelRoute>,
+}
+
+impl ModelsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.models.clear();
+        self.routes.clear();
+    }
+
+    pub fn get(&self, model_id: &str) -> Option<&Value> {
+        self.models.get(model_id)
+    }
+
+    /// Add a model served by `route`, keyed by its (already prefixed) `id`
+    ///
+    /// Returns false if another connection already exposes the same id; the first one
+    /// is kept.
+    pub fn insert(&mut self, model: Value, route: ModelRoute) -> bool {
+        let Some(model_id) = model.get("id").and_then(|v| v.as_str()).map(String::from) else {
+            return false;
+        };
+
+        if self.models.contains_key(&model_id) {
+            tracing::warn!(
+                "Model id {} is exposed by multiple connections; set prefix_id to disambiguate",
+                model_id
+            );
+            return false;
+        }
+
+        if route.upstream_id != model_id && !self.models.contains_key(&route.upstream_id) {
+            self.routes
+                .entry(route.upstream_id.clone())
+                .or_insert_with(|| route.clone());
+        }
+        self.routes.insert(model_id.clone(), route);
+        self.models.insert(model_id, model);
+        true
+    }
+
+    /// Resolve a model id (prefixed or legacy unprefixed) to its connection
+    pub fn resolve(&self, model_id: &str) -> Option<&ModelRoute> {
+        self.routes.get(model_id)
+    }
+}
+
+/// The `prefix_id` configured for a connection, if any
+pub fn connection_prefix(api_config: &Value) -> Option<&str> {
+    api_config
+        .get("prefix_id")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|prefix| !prefix.is_empty())
+}
+
+/// Model id as exposed to clients for a connection with the given prefix
+pub fn prefixed_model_id(prefix: Option<&str>, model_id: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}.{}", prefix, model_id),
+        None => model_id.to_string(),
+    }
+}
+
+/// Model id to send upstream: strips the connection's prefix when present
+pub fn upstream_model_id<'a>(api_config: &Value, model_id: &'a str) -> &'a str {
+    connection_prefix(api_config)
+        .and_then(|prefix| model_id.strip_prefix(prefix)?.strip_prefix('.'))
+        .unwrap_or(model_id)
+}
+
+/// Route a prefixed model id by matching it against configured connection prefixes
+///
+/// Used when the model list has not been cached yet.
+pub fn route_by_prefix(config: &Config, model_id: &str) -> Option<ModelRoute> {
+    config
+        .openai_api_base_urls
+        .iter()
+        .enumerate()
+        .find_map(|(idx, url)| {
+            let api_config = config
+                .openai_api_configs
+                .get(idx.to_string())
+                .or_else(|| config.openai_api_configs.get(url))?;
+            let upstream_id = upstream_model_id(api_config, model_id);
+            (upstream_id != model_id).then(|| ModelRoute {
+                url_idx: idx,
+                upstream_id: upstream_id.to_string(),
+            })
+        })
+}
+
+/// Parse `MODEL_ALIASES`: comma-separated `alias -> target` (or `alias=target`) pairs
+///
+/// Malformed entries are skipped with a warning.
+pub fn parse_model_aliases(value: &str) -> BTreeMap<String, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let pair = entry.split_once("->").or_else(|| entry.split_once('='));
+            match pair.map(|(alias, target)| (alias.trim(), target.trim())) {
+                Some((alias, target)) if !alias.is_empty() && !target.is_empty() => {
+                    Some((alias.to_string(), target.to_string()))
+                }
+                _ => {
+                    tracing::warn!("Ignoring invalid MODEL_ALIASES entry: {}", entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Model id an alias points at, or the id itself
+///
+/// Aliases resolve a single hop so a misconfigured cycle can't loop.
+pub fn resolve_model_alias<'a>(
+    aliases: &'a BTreeMap<String, String>,
+    model_id: &'a str,
+) -> &'a str {
+    aliases
+        .get(model_id)
+        .map(String::as_str)
+        .unwrap_or(model_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn add(cache: &mut ModelsCache, prefix: Option<&str>, url_idx: usize, id: &str) -> bool {
+        cache.insert(
+            json!({ "id": prefixed_model_id(prefix, id), "urlIdx": url_idx }),
+            ModelRoute {
+                url_idx,
+                upstream_id: id.to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_prefixes_resolve_collisions() {
+        let mut cache = ModelsCache::new();
+        assert!(add(&mut cache, Some("openai"), 0, "gpt-4o"));
+        assert!(add(&mut cache, Some("azure"), 1, "gpt-4o"));
+
+        let openai = cache.resolve("openai.gpt-4o").unwrap();
+        assert_eq!(openai.url_idx, 0);
+        assert_eq!(openai.upstream_id, "gpt-4o");
+
+        let azure = cache.resolve("azure.gpt-4o").unwrap();
+        assert_eq!(azure.url_idx, 1);
+        assert_eq!(azure.upstream_id, "gpt-4o");
+
+        assert!(cache.get("openai.gpt-4o").is_some());
+        assert!(cache.get("azure.gpt-4o").is_some());
+    }
+
+    #[test]
+    fn test_unprefixed_collision_keeps_first() {
+        let mut cache = ModelsCache::new();
+        assert!(add(&mut cache, None, 0, "gpt-4o"));
+        assert!(!add(&mut cache, None, 1, "gpt-4o"));
+        assert_eq!(cache.resolve("gpt-4o").unwrap().url_idx, 0);
+    }
+
+    #[test]
+    fn test_legacy_unprefixed_id_routes_to_first_connection() {
+        let mut cache = ModelsCache::new();
+        add(&mut cache, Some("azure"), 1, "gpt-4o");
+        add(&mut cache, Some("openai"), 0, "gpt-4o");
+
+        let legacy = cache.resolve("gpt-4o").unwrap();
+        assert_eq!(legacy.url_idx, 1);
+        assert_eq!(legacy.upstream_id, "gpt-4o");
+        // Aliases are routable but not listed
+        assert!(cache.get("gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_real_id_overrides_legacy_alias() {
+        let mut cache = ModelsCache::new();
+        add(&mut cache, Some("azure"), 0, "gpt-4o");
+        add(&mut cache, None, 1, "gpt-4o");
+        assert_eq!(cache.resolve("gpt-4o").unwrap().url_idx, 1);
+    }
+
+    #[test]
+    fn test_upstream_model_id_strips_prefix() {
+        let api_config = json!({ "prefix_id": "openai" });
+        assert_eq!(upstream_model_id(&api_config, "openai.gpt-4o"), "gpt-4o");
+        assert_eq!(upstream_model_id(&api_config, "gpt-4o"), "gpt-4o");
+        assert_eq!(
+            upstream_model_id(&api_config, "openaix.gpt-4o"),
+            "openaix.gpt-4o"
+        );
+        assert_eq!(
+            upstream_model_id(&json!({}), "openai.gpt-4o"),
+            "openai.gpt-4o"
+        );
+    }
+
+    #[test]
+    fn test_parse_model_aliases() {
+        let aliases = parse_model_aliases("gpt-4 -> gpt-4o, gpt-3.5-turbo=gpt-4o-mini, broken");
+        assert_eq!(aliases.len(), 2);
+        assert_eq!(resolve_model_alias(&aliases, "gpt-4"), "gpt-4o");
+        assert_eq!(
+            resolve_model_alias(&aliases, "gpt-3.5-turbo"),
+            "gpt-4o-mini"
+        );
+        assert_eq!(resolve_model_alias(&aliases, "gpt-4o"), "gpt-4o");
+    }
+
+    #[test]
+    fn test_alias_resolves_single_hop() {
+        let aliases = parse_model_aliases("a -> b, b -> a");
+        assert_eq!(resolve_model_alias(&aliases, "a"), "b");
+    }
+
+    #[test]
+    fn test_route_by_prefix() {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.openai_api_base_urls = vec![
+            "https://api.openai.com/v1".to_string(),
+            "https://example.openai.azure.com".to_string(),
+        ];
+        config.openai_api_configs = json!({
+            "0": { "prefix_id": "openai" },
+            "1": { "prefix_id": "azure" },
+        });
+
+        let route = route_by_prefix(&config, "azure.gpt-4o").unwrap();
+        assert_eq!(route.url_idx, 1);
+        assert_eq!(route.upstream_id, "gpt-4o");
+        assert!(route_by_prefix(&config, "gpt-4o").is_none());
+    }
+}
@@ -0,0 +1,403 @@
+use std::future::Future;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+/// Outcome of screening a piece of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allowed,
+    /// Carries the reason shown to the user
+    Flagged(String),
+}
+
+/// Client for the optional MODERATION_URL endpoint
+#[derive(Debug, Clone)]
+pub struct Moderator {
+    client: reqwest::Client,
+    url: String,
+    api_key: String,
+    fail_closed: bool,
+    timeout: Duration,
+    stream_interval: usize,
+}
+
+impl Moderator {
+    /// `None` when moderation is not configured
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.moderation_url.clone()?;
+        Some(Self {
+            client: crate::utils::http::client(),
+            url,
+            api_key: config.moderation_api_key.clone(),
+            fail_closed: config.moderation_fail_closed,
+            timeout: Duration::from_secs(config.moderation_timeout),
+            stream_interval: config.moderation_stream_interval,
+        })
+    }
+
+    /// Screen `text`, returning `Forbidden` with the reason when it is flagged
+    pub async fn check(&self, text: &str) -> AppResult<()> {
+        match self.verdict(text).await {
+            Verdict::Allowed => Ok(()),
+            Verdict::Flagged(reason) => Err(AppError::Forbidden(reason)),
+        }
+    }
+
+    /// Screen `text`, applying the fail-open/fail-closed policy when the call fails
+    pub async fn verdict(&self, text: &str) -> Verdict {
+        if text.trim().is_empty() {
+            return Verdict::Allowed;
+        }
+
+        match self.request(text).await {
+            Ok(verdict) => verdict,
+            Err(e) if self.fail_closed => {
+                tracing::warn!("Moderation failed, blocking content: {}", e);
+                Verdict::Flagged("Content could not be moderated".to_string())
+            }
+            Err(e) => {
+                tracing::warn!("Moderation failed, allowing content: {}", e);
+                Verdict::Allowed
+            }
+        }
+    }
+
+    async fn request(&self, text: &str) -> Result<Verdict, String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .json(&json!({ "input": text }));
+        if !self.api_key.is_empty() {
+            request = request.bearer_auth(&self.api_key);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "moderation endpoint returned {}",
+                response.status()
+            ));
+        }
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        parse_verdict(&body).ok_or_else(|| "unrecognised moderation response".to_string())
+    }
+}
+
+/// Read a moderation response
+///
+/// Accepts the OpenAI shape (`results[].flagged` with `categories`) as well as a plain
+/// `{"flagged": bool, "reason": "..."}` object.
+fn parse_verdict(body: &Value) -> Option<Verdict> {
+    let results: Vec<&Value> = match body.get("results").and_then(Value::as_array) {
+        Some(results) => results.iter().collect(),
+        None => vec![body],
+    };
+
+    let mut flagged = false;
+    let mut reasons = Vec::new();
+    for result in results {
+        if !result.get("flagged")?.as_bool()? {
+            continue;
+        }
+        flagged = true;
+
+        if let Some(reason) = result.get("reason").and_then(Value::as_str) {
+            reasons.push(reason.to_string());
+        }
+        if let Some(categories) = result.get("categories").and_then(Value::as_object) {
+            reasons.extend(
+                categories
+                    .iter()
+                    .filter(|(_, hit)| hit.as_bool() == Some(true))
+                    .map(|(category, _)| category.clone()),
+            );
+        }
+    }
+
+    Some(match (flagged, reasons.is_empty()) {
+        (false, _) => Verdict::Allowed,
+        (true, true) => Verdict::Flagged("Content flagged by moderation".to_string()),
+        (true, false) => Verdict::Flagged(format!(
+            "Content flagged by moderation: {}",
+            reasons.join(", ")
+        )),
+    })
+}
+
+/// Text of the latest user message, including the text parts of multimodal content
+pub fn latest_user_prompt(messages: &[Value]) -> String {
+    let Some(message) = messages
+        .iter()
+        .rev()
+        .find(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+    else {
+        return String::new();
+    };
+
+    match message.get("content") {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Accumulates completion text from raw SSE bytes
+#[derive(Default)]
+struct SseScanner {
+    pending: String,
+    content: String,
+    checked_len: usize,
+}
+
+impl SseScanner {
+    /// Feed a chunk; returns true once the upstream signals the end of the completion
+    fn feed(&mut self, bytes: &[u8]) -> bool {
+        self.pending.push_str(&String::from_utf8_lossy(bytes));
+
+        let mut finished = false;
+        while let Some(pos) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=pos).collect();
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                finished = true;
+                continue;
+            }
+            let Ok(event) = serde_json::from_str::<Value>(data) else {
+                continue;
+            };
+            let choice = event.pointer("/choices/0");
+            if let Some(delta) = choice
+                .and_then(|c| c.pointer("/delta/content"))
+                .and_then(Value::as_str)
+            {
+                self.content.push_str(delta);
+            }
+            if choice
+                .and_then(|c| c.get("finish_reason"))
+                .is_some_and(|reason| !reason.is_null())
+            {
+                finished = true;
+            }
+        }
+        finished
+    }
+
+    fn unchecked(&self) -> usize {
+        self.content.len() - self.checked_len
+    }
+
+    fn mark_checked(&mut self) -> String {
+        self.checked_len = self.content.len();
+        self.content.clone()
+    }
+}
+
+/// Final chunk sent in place of a flagged completion
+fn blocked_chunk(reason: &str) -> Bytes {
+    let event = json!({
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "content_filter" }],
+        "error": { "message": reason, "type": "moderation" },
+    });
+    Bytes::from(format!("data: {}\n\ndata: [DONE]\n\n", event))
+}
+
+/// Screen a streamed completion as it arrives
+///
+/// The accumulated text is re-checked every `stream_interval` characters and once more
+/// when the upstream finishes. A flagged stream is cut off: the chunk that tipped it over
+/// is withheld and replaced by a `content_filter` chunk carrying the reason. Passes the
+/// stream through untouched when moderation is off.
+pub fn moderate_sse_stream<S, E>(
+    stream: S,
+    moderator: Option<Moderator>,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let interval = moderator.as_ref().map(|m| m.stream_interval);
+    moderate_sse_stream_with(stream, interval, move |text| {
+        let moderator = moderator.clone();
+        async move {
+            match moderator {
+                Some(moderator) => moderator.verdict(&text).await,
+                None => Verdict::Allowed,
+            }
+        }
+    })
+}
+
+fn moderate_sse_stream_with<S, E, F, Fut>(
+    stream: S,
+    interval: Option<usize>,
+    check: F,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Verdict>,
+{
+    struct State<S, F> {
+        stream: S,
+        scanner: SseScanner,
+        check: F,
+        blocked: bool,
+    }
+
+    let state = State {
+        stream,
+        scanner: SseScanner::default(),
+        check,
+        blocked: false,
+    };
+
+    futures::stream::unfold(state, move |mut state| async move {
+        if state.blocked {
+            return None;
+        }
+        let Some(interval) = interval else {
+            let item = state.stream.next().await?;
+            return Some((item, state));
+        };
+
+        let bytes = match state.stream.next().await {
+            Some(Ok(bytes)) => Some(bytes),
+            Some(Err(e)) => return Some((Err(e), state)),
+            None => None,
+        };
+
+        let finished = match &bytes {
+            Some(bytes) => state.scanner.feed(bytes),
+            None => true,
+        };
+        let due = interval > 0 && state.scanner.unchecked() >= interval;
+        if state.scanner.unchecked() > 0 && (finished || due) {
+            let text = state.scanner.mark_checked();
+            if let Verdict::Flagged(reason) = (state.check)(text).await {
+                tracing::warn!("Streamed completion flagged by moderation: {}", reason);
+                state.blocked = true;
+                return Some((Ok(blocked_chunk(&reason)), state));
+            }
+        }
+
+        bytes.map(|bytes| (Ok(bytes), state))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(content: &str) -> Result<Bytes, ()> {
+        let event = json!({ "choices": [{ "index": 0, "delta": { "content": content } }] });
+        Ok(Bytes::from(format!("data: {}\n\n", event)))
+    }
+
+    async fn collect(chunks: Vec<Result<Bytes, ()>>, interval: usize) -> String {
+        let stream = moderate_sse_stream_with(
+            futures::stream::iter(chunks),
+            Some(interval),
+            |text| async move {
+                if text.contains("forbidden") {
+                    Verdict::Flagged("Content flagged by moderation: test".to_string())
+                } else {
+                    Verdict::Allowed
+                }
+            },
+        );
+        stream
+            .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat()
+    }
+
+    #[test]
+    fn test_parse_openai_and_plain_verdicts() {
+        let openai = json!({
+            "results": [{ "flagged": true, "categories": { "violence": true, "hate": false } }]
+        });
+        assert_eq!(
+            parse_verdict(&openai),
+            Some(Verdict::Flagged(
+                "Content flagged by moderation: violence".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_verdict(&json!({ "flagged": false })),
+            Some(Verdict::Allowed)
+        );
+        assert_eq!(parse_verdict(&json!({ "status": "ok" })), None);
+    }
+
+    #[test]
+    fn test_latest_user_prompt() {
+        let messages = vec![
+            json!({ "role": "user", "content": "first" }),
+            json!({ "role": "assistant", "content": "reply" }),
+            json!({ "role": "user", "content": [
+                { "type": "text", "text": "describe" },
+                { "type": "image_url", "image_url": { "url": "data:..." } }
+            ] }),
+        ];
+        assert_eq!(latest_user_prompt(&messages), "describe");
+        assert_eq!(latest_user_prompt(&[]), "");
+    }
+
+    #[tokio::test]
+    async fn test_clean_stream_passes_through() {
+        let output = collect(
+            vec![
+                delta("hello "),
+                delta("world"),
+                Ok(Bytes::from("data: [DONE]\n\n")),
+            ],
+            5,
+        )
+        .await;
+        assert!(output.contains("hello ") && output.contains("world"));
+        assert!(!output.contains("content_filter"));
+    }
+
+    #[tokio::test]
+    async fn test_flagged_stream_is_terminated_mid_generation() {
+        let output = collect(
+            vec![
+                delta("this is fine "),
+                delta("now something forbidden"),
+                delta("more text"),
+                Ok(Bytes::from("data: [DONE]\n\n")),
+            ],
+            10,
+        )
+        .await;
+        assert!(output.contains("this is fine"));
+        assert!(!output.contains("forbidden"));
+        assert!(!output.contains("more text"));
+        assert!(output.contains("content_filter"));
+        assert!(output.ends_with("data: [DONE]\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_remaining_text_checked_when_stream_finishes() {
+        // Too short to reach the interval, so only the final check catches it
+        let output = collect(
+            vec![delta("forbidden"), Ok(Bytes::from("data: [DONE]\n\n"))],
+            1000,
+        )
+        .await;
+        assert!(output.contains("content_filter"));
+    }
+}
@@ -0,0 +1,111 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+
+use crate::error::{AppError, AppResult};
+
+/// Keyset position: the last row seen, ordered by `created_at DESC, id DESC`
+///
+/// Unlike offsets, a cursor stays valid while rows are inserted ahead of it, so paging
+/// never skips or repeats rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    /// Opaque token handed to clients
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.created_at, self.id))
+    }
+
+    pub fn decode(token: &str) -> AppResult<Self> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+        let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (created_at, id) = decoded.split_once(':').ok_or_else(invalid)?;
+
+        Ok(Self {
+            created_at: created_at.parse().map_err(|_| invalid())?,
+            id: id.to_string(),
+        })
+    }
+
+    /// Parse an optional `cursor` query parameter; empty means the first page
+    pub fn from_param(param: Option<&str>) -> AppResult<Option<Self>> {
+        match param.map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(token) => Self::decode(token).map(Some),
+        }
+    }
+}
+
+/// One page of keyset-paginated results
+#[derive(Debug, Serialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    /// Pass back as `cursor` for the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> CursorPage<T> {
+    /// Build a page from up to `limit + 1` rows fetched after the cursor
+    ///
+    /// The extra row only signals that another page exists and is dropped.
+    pub fn from_rows(mut rows: Vec<T>, limit: usize, cursor_of: impl Fn(&T) -> Cursor) -> Self {
+        let has_more = rows.len() > limit;
+        rows.truncate(limit);
+        let next_cursor = if has_more {
+            rows.last().map(|row| cursor_of(row).encode())
+        } else {
+            None
+        };
+
+        Self {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor {
+            created_at: 1_700_000_000,
+            id: "chat:with:colons".to_string(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_invalid_cursor_rejected() {
+        assert!(Cursor::decode("not base64!").is_err());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("no-separator")).is_err());
+        assert!(Cursor::decode(&URL_SAFE_NO_PAD.encode("abc:id")).is_err());
+        assert_eq!(Cursor::from_param(Some("")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_page_has_next_cursor_only_when_more_rows() {
+        let cursor_of = |n: &i64| Cursor {
+            created_at: *n,
+            id: n.to_string(),
+        };
+
+        let page = CursorPage::from_rows(vec![5, 4, 3], 2, cursor_of);
+        assert_eq!(page.items, vec![5, 4]);
+        assert_eq!(
+            Cursor::decode(page.next_cursor.as_deref().unwrap()).unwrap(),
+            cursor_of(&4)
+        );
+
+        let last = CursorPage::from_rows(vec![2, 1], 2, cursor_of);
+        assert_eq!(last.items, vec![2, 1]);
+        assert!(last.next_cursor.is_none());
+    }
+}
@@ -0,0 +1,249 @@
+use serde_json::{json, Map, Value};
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult};
+
+/// Request keys the proxy itself needs; never stripped by the allowlist
+const ALWAYS_ALLOWED: &[&str] = &["model", "messages", "stream", "files", "metadata"];
+
+/// Model params that are forwarded as request parameters and override the client's
+const LOCKABLE_PARAMS: &[&str] = &[
+    "temperature",
+    "top_p",
+    "min_p",
+    "max_tokens",
+    "max_completion_tokens",
+    "frequency_penalty",
+    "presence_penalty",
+    "reasoning_effort",
+    "seed",
+    "stop",
+    "logit_bias",
+    "response_format",
+];
+
+/// Most stop sequences OpenAI-compatible APIs accept
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Admin limits on the parameters clients may send to the chat proxy
+#[derive(Debug, Clone, Default)]
+pub struct ParamPolicy {
+    /// Ceiling for `max_tokens`/`max_completion_tokens` (0 = none)
+    pub max_tokens_cap: u64,
+    /// Client-settable parameters (empty = all)
+    pub allowlist: Vec<String>,
+    /// Added to every request's `stop`
+    pub stop_sequences: Vec<String>,
+}
+
+impl ParamPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_tokens_cap: config.param_max_tokens_cap,
+            allowlist: config.param_allowlist.clone(),
+            stop_sequences: config.param_stop_sequences.clone(),
+        }
+    }
+
+    /// Enforce the policy on a chat payload
+    ///
+    /// Disallowed client params are stripped and the rest validated against hard limits
+    /// (400 on violation). The model's locked params then override the client's values,
+    /// the configured stop sequences are merged in, and `max_tokens` is clamped to the cap.
+    pub fn apply(&self, payload: &mut Value, locked_params: Option<&Value>) -> AppResult<()> {
+        let Some(obj) = payload.as_object_mut() else {
+            return Ok(());
+        };
+
+        if !self.allowlist.is_empty() {
+            obj.retain(|key, _| {
+                let allowed = ALWAYS_ALLOWED.contains(&key.as_str())
+                    || self.allowlist.iter().any(|param| param == key);
+                if !allowed {
+                    tracing::debug!("Stripping disallowed chat parameter {}", key);
+                }
+                allowed
+            });
+        }
+
+        validate_hard_limits(obj)?;
+
+        if let Some(locked) = locked_params.and_then(Value::as_object) {
+            for (key, value) in locked {
+                if LOCKABLE_PARAMS.contains(&key.as_str()) && !value.is_null() {
+                    obj.insert(key.clone(), value.clone());
+                }
+            }
+        }
+
+        self.merge_stop_sequences(obj);
+
+        if self.max_tokens_cap > 0 {
+            for key in ["max_tokens", "max_completion_tokens"] {
+                if let Some(max_tokens) = obj.get(key).and_then(Value::as_u64) {
+                    if max_tokens > self.max_tokens_cap {
+                        obj.insert(key.to_string(), json!(self.max_tokens_cap));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Configured sequences come first so they survive the upstream limit
+    fn merge_stop_sequences(&self, obj: &mut Map<String, Value>) {
+        if self.stop_sequences.is_empty() {
+            return;
+        }
+
+        let mut stops = self.stop_sequences.clone();
+        for stop in stop_values(obj.get("stop")) {
+            if !stops.contains(&stop) {
+                stops.push(stop);
+            }
+        }
+        stops.truncate(MAX_STOP_SEQUENCES);
+        obj.insert("stop".to_string(), json!(stops));
+    }
+}
+
+fn stop_values(stop: Option<&Value>) -> Vec<String> {
+    match stop {
+        Some(Value::String(stop)) => vec![stop.clone()],
+        Some(Value::Array(stops)) => stops
+            .iter()
+            .filter_map(|s| s.as_str().map(String::from))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Reject parameter values no upstream would accept
+fn validate_hard_limits(obj: &Map<String, Value>) -> AppResult<()> {
+    let ranges: [(&str, f64, f64); 4] = [
+        ("temperature", 0.0, 2.0),
+        ("top_p", 0.0, 1.0),
+        ("frequency_penalty", -2.0, 2.0),
+        ("presence_penalty", -2.0, 2.0),
+    ];
+    for (key, min, max) in ranges {
+        let Some(value) = obj.get(key).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        match value.as_f64() {
+            Some(n) if (min..=max).contains(&n) => {}
+            _ => {
+                return Err(AppError::BadRequest(format!(
+                    "{} must be a number between {} and {}",
+                    key, min, max
+                )))
+            }
+        }
+    }
+
+    for key in ["max_tokens", "max_completion_tokens"] {
+        let Some(value) = obj.get(key).filter(|v| !v.is_null()) else {
+            continue;
+        };
+        if value.as_u64().is_none_or(|n| n == 0) {
+            return Err(AppError::BadRequest(format!(
+                "{} must be a positive integer",
+                key
+            )));
+        }
+    }
+
+    match obj.get("stop") {
+        None | Some(Value::Null) | Some(Value::String(_)) => {}
+        Some(Value::Array(stops))
+            if stops.len() <= MAX_STOP_SEQUENCES && stops.iter().all(Value::is_string) => {}
+        Some(_) => {
+            return Err(AppError::BadRequest(format!(
+                "stop must be a string or a list of at most {} strings",
+                MAX_STOP_SEQUENCES
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ParamPolicy {
+        ParamPolicy {
+            max_tokens_cap: 1000,
+            allowlist: vec!["temperature".to_string(), "max_tokens".to_string()],
+            stop_sequences: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_max_tokens_clamped_to_cap() {
+        let mut payload = json!({ "model": "gpt-4o", "max_tokens": 5000 });
+        policy().apply(&mut payload, None).unwrap();
+        assert_eq!(payload["max_tokens"], 1000);
+
+        let mut payload = json!({ "model": "gpt-4o", "max_tokens": 200 });
+        policy().apply(&mut payload, None).unwrap();
+        assert_eq!(payload["max_tokens"], 200);
+    }
+
+    #[test]
+    fn test_disallowed_params_stripped() {
+        let mut payload = json!({
+            "model": "gpt-4o",
+            "messages": [],
+            "stream": true,
+            "temperature": 0.5,
+            "logit_bias": { "50256": -100 },
+            "n": 8
+        });
+        policy().apply(&mut payload, None).unwrap();
+        assert_eq!(
+            payload,
+            json!({ "model": "gpt-4o", "messages": [], "stream": true, "temperature": 0.5 })
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_values_rejected() {
+        for payload in [
+            json!({ "temperature": 3.5 }),
+            json!({ "max_tokens": 0 }),
+            json!({ "max_tokens": "lots" }),
+            json!({ "stop": ["a", "b", "c", "d", "e"] }),
+        ] {
+            let mut payload = payload;
+            assert!(matches!(
+                ParamPolicy::default().apply(&mut payload, None),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_locked_model_params_override_client_and_respect_cap() {
+        let mut payload = json!({ "model": "m", "temperature": 1.5 });
+        let locked = json!({ "temperature": 0.2, "max_tokens": 4000, "system": "Be brief" });
+        policy().apply(&mut payload, Some(&locked)).unwrap();
+
+        assert_eq!(payload["temperature"], 0.2);
+        assert_eq!(payload["max_tokens"], 1000);
+        assert!(payload.get("system").is_none());
+    }
+
+    #[test]
+    fn test_configured_stop_sequences_merged_first() {
+        let policy = ParamPolicy {
+            stop_sequences: vec!["<|end|>".to_string()],
+            ..ParamPolicy::default()
+        };
+        let mut payload = json!({ "stop": ["a", "b", "c", "<|end|>"] });
+        policy.apply(&mut payload, None).unwrap();
+        assert_eq!(payload["stop"], json!(["<|end|>", "a", "b", "c"]));
+    }
+}
@@ -1,26 +1,260 @@
+use std::sync::OnceLock;
+
+use crate::config::Config;
 use crate::error::{AppError, AppResult};
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
+    Algorithm, Argon2, Params, Version,
 };
 
+/// Algorithm new password hashes are made with, set with PASSWORD_HASH_ALGO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHashAlgo {
+    Bcrypt,
+    Argon2id,
+}
+
+impl PasswordHashAlgo {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bcrypt" => Some(Self::Bcrypt),
+            "argon2id" => Some(Self::Argon2id),
+            _ => None,
+        }
+    }
+
+    /// The algorithm a stored hash was made with, from its prefix
+    ///
+    /// bcrypt hashes start with `$2a$`, `$2b$` or `$2y$`, argon2id ones with `$argon2id$`.
+    fn of_hash(hash: &str) -> Option<Self> {
+        if ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            Some(Self::Bcrypt)
+        } else if hash.starts_with("$argon2id$") {
+            Some(Self::Argon2id)
+        } else {
+            None
+        }
+    }
+}
+
+/// How new password hashes are made
+#[derive(Debug, Clone)]
+pub struct PasswordHashing {
+    pub algo: PasswordHashAlgo,
+    pub argon2: Params,
+    pub bcrypt_cost: u32,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        Self {
+            algo: PasswordHashAlgo::Argon2id,
+            argon2: Params::default(),
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+        }
+    }
+}
+
+impl PasswordHashing {
+    /// Settings from PASSWORD_HASH_ALGO and PASSWORD_ARGON2_*; errors name the variable
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        let algo = PasswordHashAlgo::parse(&config.password_hash_algo).ok_or_else(|| {
+            format!(
+                "Invalid PASSWORD_HASH_ALGO '{}': expected bcrypt or argon2id",
+                config.password_hash_algo
+            )
+        })?;
+        let argon2 = Params::new(
+            config.password_argon2_memory_kib,
+            config.password_argon2_time_cost,
+            config.password_argon2_parallelism,
+            None,
+        )
+        .map_err(|e| format!("Invalid PASSWORD_ARGON2_* parameters: {}", e))?;
+
+        Ok(Self {
+            algo,
+            argon2,
+            ..Self::default()
+        })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.argon2.clone())
+    }
+
+    pub fn hash(&self, password: &str) -> AppResult<String> {
+        let hash = match self.algo {
+            PasswordHashAlgo::Bcrypt => {
+                bcrypt::hash(password, self.bcrypt_cost).map_err(|e| e.to_string())
+            }
+            PasswordHashAlgo::Argon2id => self
+                .argon2()
+                .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+                .map(|hash| hash.to_string())
+                .map_err(|e| e.to_string()),
+        };
+        hash.map_err(|e| AppError::InternalServerError(format!("Failed to hash password: {}", e)))
+    }
+
+    /// Whether a stored hash was made with another algorithm or other parameters
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match PasswordHashAlgo::of_hash(hash) {
+            Some(algo) if algo != self.algo => true,
+            Some(PasswordHashAlgo::Bcrypt) => {
+                hash.parse::<bcrypt::HashParts>()
+                    .ok()
+                    .map(|parts| parts.get_cost())
+                    != Some(self.bcrypt_cost)
+            }
+            Some(PasswordHashAlgo::Argon2id) => {
+                let costs = |params: &Params| (params.m_cost(), params.t_cost(), params.p_cost());
+                PasswordHash::new(hash)
+                    .ok()
+                    .and_then(|hash| Params::try_from(&hash).ok())
+                    .map(|params| costs(&params))
+                    != Some(costs(&self.argon2))
+            }
+            None => true,
+        }
+    }
+}
+
+/// Hashing settings, set once at startup
+static HASHING: OnceLock<PasswordHashing> = OnceLock::new();
+
+/// Hash new passwords as configured
+///
+/// Must run before the first password is hashed; until then the argon2id defaults apply.
+pub fn init(config: &Config) -> anyhow::Result<()> {
+    let hashing = PasswordHashing::from_config(config).map_err(|e| anyhow::anyhow!(e))?;
+    let _ = HASHING.set(hashing);
+    Ok(())
+}
+
+fn hashing() -> &'static PasswordHashing {
+    HASHING.get_or_init(PasswordHashing::default)
+}
+
+/// Hash a password with the configured algorithm
 pub fn hash_password(password: &str) -> AppResult<String> {
-    let salt = SaltString::generate(&mut OsRng);
-    let argon2 = Argon2::default();
+    hashing().hash(password)
+}
 
-    let password_hash = argon2
-        .hash_password(password.as_bytes(), &salt)
-        .map_err(|e| AppError::InternalServerError(format!("Failed to hash password: {}", e)))?
-        .to_string();
+/// Whether a stored hash should be replaced by one made with the current settings
+pub fn needs_rehash(password_hash: &str) -> bool {
+    hashing().needs_rehash(password_hash)
+}
+
+lazy_static::lazy_static! {
+    /// Hash of a random password with the same parameters as real ones, so verifying
+    /// against it costs as much as a genuine check
+    static ref DUMMY_HASH: String =
+        hash_password(&uuid::Uuid::new_v4().to_string()).expect("hashing a random password");
+}
 
-    Ok(password_hash)
+#[cfg(test)]
+thread_local! {
+    /// Hash verifications run on this thread, for asserting timing-sensitive paths
+    pub static VERIFICATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
 }
 
+/// Verify `password` against a stored hash; the digest comparison is constant-time
+///
+/// The algorithm is taken from the hash itself, so hashes made before a change of
+/// PASSWORD_HASH_ALGO keep working.
 pub fn verify_password(password: &str, password_hash: &str) -> AppResult<bool> {
-    let parsed_hash = PasswordHash::new(password_hash)
-        .map_err(|e| AppError::InternalServerError(format!("Invalid password hash: {}", e)))?;
+    #[cfg(test)]
+    VERIFICATIONS.with(|n| n.set(n.get() + 1));
+
+    match PasswordHashAlgo::of_hash(password_hash) {
+        Some(PasswordHashAlgo::Bcrypt) => bcrypt::verify(password, password_hash)
+            .map_err(|e| AppError::InternalServerError(format!("Invalid password hash: {}", e))),
+        _ => {
+            let parsed_hash = PasswordHash::new(password_hash).map_err(|e| {
+                AppError::InternalServerError(format!("Invalid password hash: {}", e))
+            })?;
+
+            // Parameters are read from the hash, so any argon2 settings verify
+            Ok(Argon2::default()
+                .verify_password(password.as_bytes(), &parsed_hash)
+                .is_ok())
+        }
+    }
+}
+
+/// Run a full verification that always fails
+///
+/// Used when there is no account to check against, so unknown emails take as long
+/// as wrong passwords and cannot be told apart by timing.
+pub fn verify_dummy_password(password: &str) -> bool {
+    let _ = verify_password(password, &DUMMY_HASH);
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bcrypt_hashing() -> PasswordHashing {
+        PasswordHashing {
+            algo: PasswordHashAlgo::Bcrypt,
+            bcrypt_cost: 4,
+            ..PasswordHashing::default()
+        }
+    }
+
+    fn argon2_hashing(m_cost: u32) -> PasswordHashing {
+        PasswordHashing {
+            argon2: Params::new(m_cost, 1, 1, None).unwrap(),
+            ..PasswordHashing::default()
+        }
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let hash = hash_password("correct horse").unwrap();
+        assert!(verify_password("correct horse", &hash).unwrap());
+        assert!(!verify_password("battery staple", &hash).unwrap());
+    }
+
+    #[test]
+    fn test_verify_dispatches_on_hash_prefix() {
+        let bcrypt_hash = bcrypt_hashing().hash("correct horse").unwrap();
+        let argon2_hash = argon2_hashing(1024).hash("correct horse").unwrap();
+        assert!(bcrypt_hash.starts_with("$2b$"));
+        assert!(argon2_hash.starts_with("$argon2id$"));
+
+        // Whatever is configured, both verify
+        for hash in [&bcrypt_hash, &argon2_hash] {
+            assert!(verify_password("correct horse", hash).unwrap());
+            assert!(!verify_password("battery staple", hash).unwrap());
+        }
+        assert!(verify_password("correct horse", "plaintext").is_err());
+    }
+
+    #[test]
+    fn test_hashes_upgraded_to_configured_algorithm() {
+        let legacy = bcrypt_hashing().hash("correct horse").unwrap();
+        let argon2 = argon2_hashing(1024);
+        assert!(argon2.needs_rehash(&legacy));
+        assert!(!bcrypt_hashing().needs_rehash(&legacy));
+
+        let upgraded = argon2.hash("correct horse").unwrap();
+        assert!(!argon2.needs_rehash(&upgraded));
+        assert!(verify_password("correct horse", &upgraded).unwrap());
+
+        // Changed parameters also call for a new hash, as does moving back to bcrypt
+        assert!(argon2_hashing(2048).needs_rehash(&upgraded));
+        assert!(bcrypt_hashing().needs_rehash(&upgraded));
+    }
 
-    Ok(Argon2::default()
-        .verify_password(password.as_bytes(), &parsed_hash)
-        .is_ok())
+    #[test]
+    fn test_dummy_verification_never_matches() {
+        assert!(!verify_dummy_password(""));
+        assert!(!verify_dummy_password("anything"));
+    }
 }
@@ -0,0 +1,155 @@
+use serde_json::{json, Map, Value};
+
+/// Every user permission with its default value
+///
+/// This is also the schema permission templates are checked against: a template may
+/// only set keys that exist here, and only to booleans.
+pub fn default_permissions() -> Value {
+    json!({
+        "workspace": {
+            "models": false,
+            "knowledge": false,
+            "prompts": false,
+            "tools": false
+        },
+        "sharing": {
+            "public_models": true,
+            "public_knowledge": true,
+            "public_prompts": true,
+            "public_tools": true,
+            "public_notes": true
+        },
+        "chat": {
+            "controls": true,
+            "valves": true,
+            "system_prompt": true,
+            "params": true,
+            "file_upload": true,
+            "delete": true,
+            "delete_message": true,
+            "continue_response": true,
+            "regenerate_response": true,
+            "rate_response": true,
+            "edit": true,
+            "share": true,
+            "export": true,
+            "stt": true,
+            "tts": true,
+            "call": true,
+            "multiple_models": true,
+            "temporary": true,
+            "temporary_enforced": false
+        },
+        "features": {
+            "direct_tool_servers": false,
+            "web_search": true,
+            "image_generation": true,
+            "code_interpreter": true,
+            "notes": true
+        }
+    })
+}
+
+/// Problems with a permission set, as `category.key: reason`; empty when it is valid
+///
+/// Templates may be partial, so missing categories and keys are fine.
+pub fn permission_errors(permissions: &Value) -> Vec<String> {
+    let schema = default_permissions();
+    let Some(categories) = permissions.as_object() else {
+        return vec!["expected a JSON object of permission categories".to_string()];
+    };
+
+    let mut errors = Vec::new();
+    for (category, keys) in categories {
+        let Some(known) = schema.get(category).and_then(Value::as_object) else {
+            errors.push(format!("{}: unknown permission category", category));
+            continue;
+        };
+        let Some(keys) = keys.as_object() else {
+            errors.push(format!("{}: expected an object", category));
+            continue;
+        };
+        for (key, value) in keys {
+            if !known.contains_key(key) {
+                errors.push(format!("{}.{}: unknown permission", category, key));
+            } else if !value.is_boolean() {
+                errors.push(format!("{}.{}: expected a boolean", category, key));
+            }
+        }
+    }
+    errors
+}
+
+/// A group's permissions with `template` applied on top
+///
+/// Keys the template sets are overwritten; everything else the group had is kept.
+pub fn apply_template(permissions: Option<&Value>, template: &Value) -> Value {
+    let mut merged = permissions
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+
+    for (category, keys) in template.as_object().into_iter().flatten() {
+        let entry = merged
+            .entry(category.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !entry.is_object() {
+            *entry = Value::Object(Map::new());
+        }
+        if let (Some(target), Some(keys)) = (entry.as_object_mut(), keys.as_object()) {
+            for (key, value) in keys {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    Value::Object(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permission_errors() {
+        assert!(permission_errors(&default_permissions()).is_empty());
+        assert!(permission_errors(&json!({"workspace": {"models": true}})).is_empty());
+
+        assert_eq!(
+            permission_errors(&json!({
+                "workspace": {"models": "yes", "gardening": true},
+                "admin": {"all": true},
+                "chat": true
+            })),
+            vec![
+                "admin: unknown permission category",
+                "chat: expected an object",
+                "workspace.gardening: unknown permission",
+                "workspace.models: expected a boolean",
+            ]
+        );
+        assert_eq!(permission_errors(&json!([])).len(), 1);
+    }
+
+    #[test]
+    fn test_apply_template_keeps_unset_keys() {
+        let group = json!({
+            "workspace": {"models": false, "tools": true},
+            "chat": {"delete": false}
+        });
+        let template = json!({
+            "workspace": {"models": true},
+            "features": {"web_search": false}
+        });
+
+        assert_eq!(
+            apply_template(Some(&group), &template),
+            json!({
+                "workspace": {"models": true, "tools": true},
+                "chat": {"delete": false},
+                "features": {"web_search": false}
+            })
+        );
+        assert_eq!(apply_template(None, &template), template);
+    }
+}
@@ -1,4 +1,3 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -44,7 +43,7 @@ pub async fn process_pipeline_inlet_filter(
         model_id
     );
 
-    let client = Client::builder()
+    let client = crate::utils::http::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()?;
 
@@ -147,7 +146,7 @@ pub async fn process_pipeline_outlet_filter(
         model_id
     );
 
-    let client = Client::builder()
+    let client = crate::utils::http::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()?;
 
@@ -0,0 +1,114 @@
+//! Whether this instance has finished starting up and should receive traffic
+//!
+//! Each startup task is a named check. `/health/ready` answers 503 until every check
+//! has completed, so a load balancer only routes to warm instances during a rolling
+//! deploy. A check completes when its task finishes, whether or not it succeeded; a
+//! failed warmup is logged by the task and shouldn't keep the instance out of rotation.
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+pub const MIGRATIONS: &str = "migrations";
+pub const CONFIG: &str = "config";
+pub const MODELS_CACHE: &str = "models_cache";
+pub const EMBEDDINGS: &str = "embeddings";
+pub const VECTOR_DB: &str = "vector_db";
+
+/// Seconds a caller is told to wait while the instance is still warming up
+pub const RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    /// Each startup task and whether it has completed
+    pub checks: BTreeMap<&'static str, bool>,
+}
+
+#[derive(Debug)]
+pub struct Readiness {
+    checks: Mutex<BTreeMap<&'static str, bool>>,
+}
+
+impl Readiness {
+    /// Not ready until every one of `checks` completes
+    pub fn new(checks: &[&'static str]) -> Self {
+        Self {
+            checks: Mutex::new(checks.iter().map(|check| (*check, false)).collect()),
+        }
+    }
+
+    pub fn complete(&self, check: &'static str) {
+        self.checks.lock().unwrap().insert(check, true);
+        if self.is_ready() {
+            tracing::info!("✅ Startup complete, ready for traffic");
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.checks.lock().unwrap().values().all(|done| *done)
+    }
+
+    pub fn report(&self) -> ReadinessReport {
+        let checks = self.checks.lock().unwrap().clone();
+        ReadinessReport {
+            ready: checks.values().all(|done| *done),
+            checks,
+        }
+    }
+
+    /// Run `task` in the background and complete `check` once it finishes
+    pub fn spawn<F>(self: &Arc<Self>, check: &'static str, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let readiness = self.clone();
+        tokio::spawn(async move {
+            task.await;
+            readiness.complete(check);
+        });
+    }
+}
+
+/// Whether a request reaches its handler while BLOCK_UNTIL_READY holds traffic back
+pub fn is_exempt(path: &str) -> bool {
+    path == "/health" || path.starts_with("/health/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_ready_once_warmup_completes() {
+        let readiness = Arc::new(Readiness::new(&[MIGRATIONS, MODELS_CACHE]));
+        readiness.complete(MIGRATIONS);
+
+        let (warmed, warm) = tokio::sync::oneshot::channel::<()>();
+        readiness.spawn(MODELS_CACHE, async move {
+            let _ = warm.await;
+        });
+        tokio::task::yield_now().await;
+
+        let report = readiness.report();
+        assert!(!report.ready);
+        assert!(report.checks[MIGRATIONS]);
+        assert!(!report.checks[MODELS_CACHE]);
+
+        warmed.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !readiness.is_ready() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("readiness never flipped after warmup");
+        assert!(readiness.report().checks.values().all(|done| *done));
+
+        assert!(is_exempt("/health/ready"));
+        assert!(!is_exempt("/healthz-spoof"));
+        assert!(!is_exempt("/api/models"));
+    }
+}
@@ -0,0 +1,115 @@
+/// Post-login redirect validation
+///
+/// A redirect is followed only when it is a path on the frontend or an absolute URL
+/// whose origin is the frontend's or listed in OAUTH_ALLOWED_REDIRECTS.
+use url::{Origin, Url};
+
+use crate::config::Config;
+
+/// Origin of an http(s) URL
+pub fn origin(url: &str) -> Option<Origin> {
+    let url = Url::parse(url).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return None;
+    }
+    Some(url.origin())
+}
+
+/// Where to send a user after login, or `None` when `target` isn't allowed
+///
+/// Paths are resolved against FRONTEND_BASE_URL. Scheme-relative (`//host`) and
+/// backslash paths are rejected, since browsers treat them as other hosts.
+pub fn allowed_redirect(config: &Config, target: &str) -> Option<String> {
+    let base = config.frontend_base_url.trim_end_matches('/');
+    if target.chars().any(char::is_control) {
+        return None;
+    }
+    if target.starts_with('/') {
+        if target.starts_with("//") || target.contains('\\') {
+            return None;
+        }
+        return Some(format!("{}{}", base, target));
+    }
+
+    let target_origin = origin(target)?;
+    let allowed = origin(base).is_some_and(|base| base == target_origin)
+        || config
+            .oauth_allowed_redirects
+            .iter()
+            .any(|allowed| origin(allowed).is_some_and(|allowed| allowed == target_origin));
+    allowed.then(|| target.to_string())
+}
+
+/// `target` if allowed, otherwise the frontend root
+pub fn post_login_redirect(config: &Config, target: Option<&str>) -> String {
+    target
+        .and_then(|target| allowed_redirect(config, target))
+        .unwrap_or_else(|| format!("{}/", config.frontend_base_url.trim_end_matches('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allowed: &str) -> Config {
+        let allowed = allowed.to_string();
+        Config::from_lookup(move |key| match key {
+            "FRONTEND_BASE_URL" => Some("https://chat.example.com".to_string()),
+            "OAUTH_ALLOWED_REDIRECTS" => Some(allowed.clone()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_origin_allowed_by_default() {
+        let config = config("");
+        assert_eq!(
+            post_login_redirect(&config, Some("/c/123?tab=files")),
+            "https://chat.example.com/c/123?tab=files"
+        );
+        assert_eq!(
+            post_login_redirect(&config, Some("https://chat.example.com/workspace")),
+            "https://chat.example.com/workspace"
+        );
+        assert_eq!(
+            post_login_redirect(&config, None),
+            "https://chat.example.com/"
+        );
+    }
+
+    #[test]
+    fn test_external_targets_rejected() {
+        let config = config("");
+        for target in [
+            "https://evil.example.net/login",
+            "//evil.example.net",
+            "/\\evil.example.net",
+            "https://chat.example.com.evil.net/",
+            "http://chat.example.com/",
+            "https://chat.example.com:8443/",
+            "javascript:alert(1)",
+            "evil.example.net",
+            "/\r\nLocation: https://evil.example.net",
+        ] {
+            assert_eq!(allowed_redirect(&config, target), None, "{}", target);
+            assert_eq!(
+                post_login_redirect(&config, Some(target)),
+                "https://chat.example.com/"
+            );
+        }
+    }
+
+    #[test]
+    fn test_allowlisted_origin_accepted() {
+        let config = config("https://docs.example.com, http://localhost:5173");
+        assert_eq!(
+            allowed_redirect(&config, "https://docs.example.com/guide").as_deref(),
+            Some("https://docs.example.com/guide")
+        );
+        assert!(allowed_redirect(&config, "http://localhost:5173/").is_some());
+        assert_eq!(allowed_redirect(&config, "https://docs.example.org/"), None);
+        assert!(config.validate().is_ok());
+        assert!(self::config("docs.example.com").validate().is_err());
+    }
+}
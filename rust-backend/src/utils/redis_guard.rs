@@ -0,0 +1,111 @@
+/// Circuit breaker shared by the Redis-backed components
+///
+/// Once REDIS_CIRCUIT_FAILURE_THRESHOLD Redis calls in a row fail, callers stop
+/// touching Redis for REDIS_CIRCUIT_COOLDOWN seconds and run degraded: caches serve
+/// from memory only and the session store uses the database. After the cooldown one
+/// call probes Redis again, and a success puts everything back on Redis.
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::utils::circuit_breaker::{CircuitBreakerSettings, CircuitBreakers};
+
+/// Circuit key for Redis in the breaker map
+const REDIS_CIRCUIT: &str = "redis";
+
+/// Failures further apart than this don't count as consecutive
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct RedisGuard {
+    breakers: CircuitBreakers,
+    settings: CircuitBreakerSettings,
+    degraded: AtomicBool,
+}
+
+impl Default for RedisGuard {
+    fn default() -> Self {
+        Self::new(CircuitBreakerSettings {
+            failure_threshold: 3,
+            window: FAILURE_WINDOW,
+            cooldown: Duration::from_secs(15),
+        })
+    }
+}
+
+impl RedisGuard {
+    pub fn new(settings: CircuitBreakerSettings) -> Self {
+        Self {
+            breakers: CircuitBreakers::new(),
+            settings,
+            degraded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(CircuitBreakerSettings {
+            failure_threshold: config.redis_circuit_failure_threshold,
+            window: FAILURE_WINDOW,
+            cooldown: Duration::from_secs(config.redis_circuit_cooldown),
+        })
+    }
+
+    /// Whether a Redis call should be attempted now; `false` means use the fallback
+    pub fn allow(&self) -> bool {
+        self.breakers
+            .try_acquire(REDIS_CIRCUIT, &self.settings, Instant::now())
+            .is_ok()
+    }
+
+    pub fn record_success(&self) {
+        self.breakers.record_success(REDIS_CIRCUIT);
+        if self.degraded.swap(false, Ordering::Relaxed) {
+            tracing::info!("Redis is reachable again, leaving degraded mode");
+        }
+    }
+
+    pub fn record_failure(&self, error: &dyn Display) {
+        self.breakers
+            .record_failure(REDIS_CIRCUIT, &self.settings, Instant::now());
+        if !self.degraded.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                "Redis call failed, running degraded until it recovers: {}",
+                error
+            );
+        } else {
+            tracing::debug!("Redis call failed while degraded: {}", error);
+        }
+    }
+
+    /// Whether the last Redis call failed
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_then_recovers() {
+        let guard = RedisGuard::new(CircuitBreakerSettings {
+            failure_threshold: 2,
+            window: FAILURE_WINDOW,
+            cooldown: Duration::from_secs(60),
+        });
+        assert!(guard.allow());
+
+        guard.record_failure(&"connection refused");
+        assert!(guard.is_degraded());
+        assert!(guard.allow());
+        guard.record_failure(&"connection refused");
+        assert!(!guard.allow());
+
+        // A successful probe closes the circuit
+        guard.record_success();
+        assert!(!guard.is_degraded());
+        assert!(guard.allow());
+    }
+}
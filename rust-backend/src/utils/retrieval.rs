@@ -5,7 +5,11 @@
 use crate::{
     error::{AppError, AppResult},
     models::{chat::Chat, file::File, note::Note, user::User},
-    services::{chat::ChatService, file::FileService, note::NoteService},
+    retrieval::search::{search_collection, ChunkTextStore, SearchOptions},
+    services::{
+        chat::ChatService, file::FileService, knowledge::KnowledgeService, note::NoteService,
+        rag_chunk::RagChunkService,
+    },
     utils::misc::{get_message_list, has_access},
     AppState,
 };
@@ -135,6 +139,7 @@ pub fn rag_template(template: &str, context: &str, query: &str) -> String {
 pub async fn get_sources_from_items(
     state: &AppState,
     items: Vec<FileItem>,
+    query: Option<&str>,
     user: &User,
     user_group_ids: &HashSet<String>,
 ) -> AppResult<Vec<Source>> {
@@ -385,6 +390,20 @@ pub async fn get_sources_from_items(
                 // TODO: Implement collection-based retrieval with embeddings
             }
 
+            "collection" => {
+                // Knowledge base attachment - semantic search over its collection
+                if let (Some(knowledge_id), Some(query)) = (&item.id, query) {
+                    query_result = query_knowledge_collection(
+                        state,
+                        knowledge_id,
+                        query,
+                        user,
+                        user_group_ids,
+                    )
+                    .await?;
+                }
+            }
+
             _ => {
                 tracing::warn!("Unknown item type: {}", item.item_type);
             }
@@ -411,6 +430,101 @@ pub async fn get_sources_from_items(
     Ok(sources)
 }
 
+/// Retrieve the chunks of a knowledge base most relevant to `query`
+///
+/// Applies the configured score threshold and re-ranking; each chunk's metadata carries
+/// its `score` (and `rerank_score` when re-ranked).
+async fn query_knowledge_collection(
+    state: &AppState,
+    knowledge_id: &str,
+    query: &str,
+    user: &User,
+    user_group_ids: &HashSet<String>,
+) -> AppResult<Option<(Vec<String>, Vec<Value>)>> {
+    let Some(knowledge) = KnowledgeService::new(&state.db)
+        .get_knowledge_by_id(knowledge_id)
+        .await?
+    else {
+        tracing::warn!("⚠️ Knowledge base {} not found", knowledge_id);
+        return Ok(None);
+    };
+
+    if user.role != "admin"
+        && knowledge.user_id != user.id
+        && !has_access(&user.id, "read", &knowledge.access_control, user_group_ids)
+    {
+        tracing::warn!(
+            "⚠️ User {} has no access to knowledge base {}",
+            user.id,
+            knowledge_id
+        );
+        return Ok(None);
+    }
+
+    let (Some(vector_db), Some(embedding_provider)) = (&state.vector_db, &state.embedding_provider)
+    else {
+        tracing::debug!("RAG is disabled, skipping knowledge base {}", knowledge_id);
+        return Ok(None);
+    };
+
+    let (options, chunk_store) = {
+        let config = state.config.read().unwrap();
+        let chunk_store = config
+            .enable_rag_chunk_store
+            .then(|| RagChunkService::new(&state.db));
+        (SearchOptions::from_config(&config), chunk_store)
+    };
+    let chunks = match search_collection(
+        vector_db,
+        embedding_provider,
+        &state.http_client,
+        knowledge_id,
+        query,
+        &options,
+        chunk_store
+            .as_ref()
+            .map(|store| store as &dyn ChunkTextStore),
+    )
+    .await
+    {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            tracing::warn!(
+                "⚠️ Search failed for knowledge base {}: {}",
+                knowledge_id,
+                e
+            );
+            return Ok(None);
+        }
+    };
+
+    tracing::info!(
+        "✅ Knowledge base '{}' returned {} relevant chunk(s)",
+        knowledge.name,
+        chunks.len()
+    );
+
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+
+    let (documents, metadatas) = chunks
+        .into_iter()
+        .map(|chunk| {
+            let mut metadata = chunk.metadata;
+            if let Some(obj) = metadata.as_object_mut() {
+                obj.insert("score".to_string(), json!(chunk.score));
+                if let Some(rerank_score) = chunk.rerank_score {
+                    obj.insert("rerank_score".to_string(), json!(rerank_score));
+                }
+            }
+            (chunk.text, metadata)
+        })
+        .unzip();
+
+    Ok(Some((documents, metadatas)))
+}
+
 /// Process sources and inject them into messages as RAG context
 pub fn inject_sources_into_messages(
     sources: Vec<Source>,
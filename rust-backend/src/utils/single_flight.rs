@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use tokio::sync::watch;
+
+/// Collapses concurrent identical calls into one
+///
+/// The first caller for a key runs the work; callers arriving while it is in flight
+/// wait for and share its result. Nothing is cached once the call completes. If the
+/// leading caller is cancelled, waiting callers run the work themselves.
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, watch::Receiver<Option<V>>>>,
+}
+
+impl<K, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Forgets the in-flight call when the leader finishes or is dropped
+struct Leader<'a, K: Eq + Hash, V> {
+    flight: &'a SingleFlight<K, V>,
+    key: &'a K,
+}
+
+impl<K: Eq + Hash, V> Drop for Leader<'_, K, V> {
+    fn drop(&mut self) {
+        self.flight.calls.lock().unwrap().remove(self.key);
+    }
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `work` for `key`, or share the result of an identical call in flight
+    pub async fn run<F, Fut>(&self, key: K, work: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        let waiting = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    calls.insert(key.clone(), receiver);
+                    Ok(sender)
+                }
+            }
+        };
+
+        match waiting {
+            Ok(sender) => {
+                let _leader = Leader {
+                    flight: self,
+                    key: &key,
+                };
+                let value = work().await;
+                let _ = sender.send(Some(value.clone()));
+                value
+            }
+            Err(mut receiver) => {
+                // Bound first so the watch guard isn't held across `work()`
+                let shared = receiver
+                    .wait_for(|value| value.is_some())
+                    .await
+                    .ok()
+                    .map(|value| value.clone().expect("waited for a value"));
+                match shared {
+                    Some(value) => value,
+                    // The leader was cancelled before finishing
+                    None => work().await,
+                }
+            }
+        }
+    }
+
+    /// Number of distinct calls currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_identical_calls_share_one_upstream_call() {
+        let flight = Arc::new(SingleFlight::<String, usize>::new());
+        let upstream_calls = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..10)
+            .map(|_| {
+                let flight = flight.clone();
+                let upstream_calls = upstream_calls.clone();
+                tokio::spawn(async move {
+                    flight
+                        .run("models".to_string(), || async {
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            upstream_calls.fetch_add(1, Ordering::SeqCst) + 42
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), 42);
+        }
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(flight.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_distinct_keys_and_later_calls_run_separately() {
+        let flight = SingleFlight::<u32, u32>::new();
+        let upstream_calls = AtomicUsize::new(0);
+        let work = |value: u32| {
+            let upstream_calls = &upstream_calls;
+            move || async move {
+                upstream_calls.fetch_add(1, Ordering::SeqCst);
+                value
+            }
+        };
+
+        let (a, b) = tokio::join!(flight.run(1, work(1)), flight.run(2, work(2)));
+        assert_eq!((a, b), (1, 2));
+        // Completed calls aren't cached
+        assert_eq!(flight.run(1, work(1)).await, 1);
+        assert_eq!(upstream_calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_followers_recover_from_cancelled_leader() {
+        let flight = Arc::new(SingleFlight::<&'static str, u32>::new());
+
+        let leader = {
+            let flight = flight.clone();
+            tokio::spawn(async move {
+                flight
+                    .run("key", || async {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                        1
+                    })
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let follower = {
+            let flight = flight.clone();
+            tokio::spawn(async move { flight.run("key", || async { 2 }).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        leader.abort();
+
+        assert_eq!(follower.await.unwrap(), 2);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use crate::error::AppError;
+
+/// Seconds a user is told to wait when all their streams are taken
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// Streaming chat completions open per user, against MAX_CONCURRENT_STREAMS_PER_USER
+///
+/// The limit is passed per call so config reloads take effect immediately.
+#[derive(Debug, Default)]
+pub struct StreamLimits {
+    open: Mutex<HashMap<String, usize>>,
+}
+
+impl StreamLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a new stream for `user_id`, or refuse it when `limit` are already open
+    /// (0 = unlimited)
+    ///
+    /// The stream is counted until the returned guard drops.
+    pub fn try_acquire(
+        self: &Arc<Self>,
+        user_id: &str,
+        limit: usize,
+    ) -> Result<StreamGuard, AppError> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.get(user_id).copied().unwrap_or(0);
+        if limit > 0 && count >= limit {
+            return Err(AppError::RateLimited {
+                message: format!(
+                    "Too many concurrent streams: at most {} per user, retry when one ends",
+                    limit
+                ),
+                retry_after: RETRY_AFTER_SECS,
+            });
+        }
+        open.insert(user_id.to_string(), count + 1);
+
+        Ok(StreamGuard {
+            limits: self.clone(),
+            user_id: user_id.to_string(),
+        })
+    }
+
+    /// Open streams by user, for the metrics endpoint
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        self.open
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(user_id, count)| (user_id.clone(), *count))
+            .collect()
+    }
+
+    fn release(&self, user_id: &str) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(user_id) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(user_id);
+            }
+        }
+    }
+}
+
+/// One counted stream; dropping it, however the stream ended, frees the slot
+pub struct StreamGuard {
+    limits: Arc<StreamLimits>,
+    user_id: String,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.limits.release(&self.user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_extra_stream_rejected_until_one_ends() {
+        let limits = Arc::new(StreamLimits::new());
+        let first = limits.try_acquire("alice", 2).unwrap();
+        let second = limits.try_acquire("alice", 2).unwrap();
+        assert!(matches!(
+            limits.try_acquire("alice", 2),
+            Err(AppError::RateLimited { retry_after, .. }) if retry_after == RETRY_AFTER_SECS
+        ));
+        // Other users have their own allowance
+        let _bob = limits.try_acquire("bob", 2).unwrap();
+
+        drop(first);
+        let third = limits.try_acquire("alice", 2).unwrap();
+        assert_eq!(limits.counts()["alice"], 2);
+
+        // A body dropped mid-stream, as on a client disconnect, frees its slot too
+        let mut body = Box::pin(futures::stream::iter([1, 2, 3]).map(move |chunk| {
+            let _ = &second;
+            chunk
+        }));
+        assert_eq!(body.next().await, Some(1));
+        drop(body);
+        drop(third);
+        assert_eq!(limits.counts().get("alice"), None);
+        assert_eq!(limits.counts()["bob"], 1);
+    }
+}
@@ -0,0 +1,349 @@
+/// Structured output (`response_format`) handling for the chat proxy
+///
+/// `response_format` is forwarded as sent unless the connection's config says the
+/// upstream can't take it (`structured_outputs` in OPENAI_API_CONFIGS):
+/// - `json_schema` (default): forwarded unchanged
+/// - `json_object`: a `json_schema` is sent as `json_object` with the schema in a system message
+/// - `none`: JSON modes are rejected with 400 and `text` is dropped
+///
+/// With STRUCTURED_OUTPUT_VALIDATION set, non-streaming completions are checked against
+/// the request's `json_schema`. Only the keywords structured outputs use are understood:
+/// `type`, `properties`, `required`, `additionalProperties`, `items`, `enum`, `const`,
+/// `anyOf`/`oneOf` and local `$ref`s.
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::error::{AppError, AppResult, FieldErrors};
+
+/// Root of the field paths in validation errors
+const ROOT_PATH: &str = "response";
+
+/// Checks a completion against the request's JSON schema
+#[derive(Debug, Clone)]
+pub struct SchemaCheck {
+    schema: Value,
+    strict: bool,
+}
+
+impl SchemaCheck {
+    /// `None` when validation is off or the request carries no `json_schema`
+    pub fn from_request(config: &Config, payload: &Value) -> Option<Self> {
+        let strict = match config.structured_output_validation.as_str() {
+            "strict" => true,
+            "warn" => false,
+            _ => return None,
+        };
+        let format = payload.get("response_format")?;
+        if format.get("type").and_then(Value::as_str) != Some("json_schema") {
+            return None;
+        }
+        let schema = format.pointer("/json_schema/schema")?.clone();
+        Some(Self { schema, strict })
+    }
+
+    /// In strict mode a non-conforming `content` is a 400 listing each violation;
+    /// otherwise it is logged and let through
+    pub fn check(&self, content: &str) -> AppResult<()> {
+        match validate(&self.schema, content) {
+            Ok(()) => Ok(()),
+            Err(errors) if self.strict => Err(AppError::ValidationError(errors)),
+            Err(errors) => {
+                tracing::warn!("Completion doesn't match the requested schema: {}", errors);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Fit `response_format` to what the connection supports
+pub fn adapt_response_format(payload: &mut Value, api_config: &Value) -> AppResult<()> {
+    let support = api_config
+        .get("structured_outputs")
+        .and_then(Value::as_str)
+        .unwrap_or("json_schema");
+    let Some(format_type) = payload
+        .pointer("/response_format/type")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+    else {
+        return Ok(());
+    };
+
+    match (support, format_type.as_str()) {
+        ("none", "text") => {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.remove("response_format");
+            }
+            Ok(())
+        }
+        ("none", _) => Err(AppError::BadRequest(format!(
+            "This model's connection doesn't support response_format '{}'",
+            format_type
+        ))),
+        ("json_object", "json_schema") => {
+            let schema = payload
+                .pointer("/response_format/json_schema/schema")
+                .cloned()
+                .unwrap_or_else(|| json!({}));
+            payload["response_format"] = json!({ "type": "json_object" });
+            let instruction = json!({
+                "role": "system",
+                "content": format!(
+                    "Respond only with a JSON object that conforms to this JSON schema:\n{}",
+                    schema
+                ),
+            });
+            if let Some(messages) = payload.get_mut("messages").and_then(Value::as_array_mut) {
+                messages.insert(0, instruction);
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Parse `content` as JSON and check it against `schema`
+pub fn validate(schema: &Value, content: &str) -> Result<(), FieldErrors> {
+    let mut errors = BTreeMap::new();
+    match serde_json::from_str::<Value>(content) {
+        Ok(value) => check(schema, schema, &value, ROOT_PATH, &mut errors),
+        Err(e) => add_error(&mut errors, ROOT_PATH, format!("is not valid JSON: {}", e)),
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(FieldErrors(errors))
+    }
+}
+
+fn add_error(errors: &mut BTreeMap<String, Vec<String>>, path: &str, message: String) {
+    errors.entry(path.to_string()).or_default().push(message);
+}
+
+fn check(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    path: &str,
+    errors: &mut BTreeMap<String, Vec<String>>,
+) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => return add_error(errors, path, "is not allowed".to_string()),
+        Value::Object(_) => schema,
+        _ => return,
+    };
+
+    if let Some(target) = schema.get("$ref").and_then(Value::as_str) {
+        // Only local references ("#/$defs/name") can be resolved
+        if let Some(resolved) = target.strip_prefix('#').and_then(|ptr| root.pointer(ptr)) {
+            check(root, resolved, value, path, errors);
+        }
+        return;
+    }
+
+    for keyword in ["anyOf", "oneOf"] {
+        if let Some(branches) = schema.get(keyword).and_then(Value::as_array) {
+            let matches = branches.iter().any(|branch| {
+                let mut branch_errors = BTreeMap::new();
+                check(root, branch, value, path, &mut branch_errors);
+                branch_errors.is_empty()
+            });
+            if !matches {
+                add_error(
+                    errors,
+                    path,
+                    format!("matches none of the {} schemas", keyword),
+                );
+            }
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            return add_error(errors, path, format!("expected {}", types.join(" or ")));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            add_error(errors, path, "is not one of the allowed values".to_string());
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            add_error(errors, path, format!("expected {}", expected));
+        }
+    }
+
+    match value {
+        Value::Object(fields) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for name in schema
+                .get("required")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                if !fields.contains_key(name) {
+                    add_error(
+                        errors,
+                        &format!("{}.{}", path, name),
+                        "is required".to_string(),
+                    );
+                }
+            }
+            for (name, field) in fields {
+                let field_path = format!("{}.{}", path, name);
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(field_schema) => check(root, field_schema, field, &field_path, errors),
+                    None => {
+                        if let Some(additional) = schema.get("additionalProperties") {
+                            check(root, additional, field, &field_path, errors);
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check(
+                        root,
+                        item_schema,
+                        item,
+                        &format!("{}[{}]", path, index),
+                        errors,
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Value {
+        json!({
+            "model": "gpt-4o",
+            "messages": [{ "role": "user", "content": "Describe Paris" }],
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "city",
+                    "strict": true,
+                    "schema": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "string" },
+                            "population": { "type": "integer" },
+                            "landmarks": { "type": "array", "items": { "$ref": "#/$defs/landmark" } }
+                        },
+                        "required": ["name", "population"],
+                        "additionalProperties": false,
+                        "$defs": {
+                            "landmark": { "type": "string", "enum": ["Louvre", "Eiffel Tower"] }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn schema_check(mode: &str) -> Option<SchemaCheck> {
+        let mode = mode.to_string();
+        let config = Config::from_lookup(move |key| match key {
+            "STRUCTURED_OUTPUT_VALIDATION" => Some(mode.clone()),
+            _ => None,
+        })
+        .unwrap();
+        SchemaCheck::from_request(&config, &payload())
+    }
+
+    #[test]
+    fn test_outputs_checked_per_strict_setting() {
+        let valid = r#"{"name": "Paris", "population": 2102650, "landmarks": ["Louvre"]}"#;
+        let wrong_shape = r#"{"name": "Paris", "landmarks": ["Notre-Dame"], "mayor": "?"}"#;
+        let not_json = "Paris is the capital of France.";
+
+        assert!(schema_check("off").is_none());
+
+        let strict = schema_check("strict").unwrap();
+        assert!(strict.check(valid).is_ok());
+        let Err(AppError::ValidationError(errors)) = strict.check(wrong_shape) else {
+            panic!("expected a validation error");
+        };
+        assert_eq!(
+            errors.0.keys().collect::<Vec<_>>(),
+            vec![
+                "response.landmarks[0]",
+                "response.mayor",
+                "response.population"
+            ]
+        );
+        assert!(matches!(
+            strict.check(not_json),
+            Err(AppError::ValidationError(_))
+        ));
+
+        let warn = schema_check("warn").unwrap();
+        for output in [valid, wrong_shape, not_json] {
+            assert!(warn.check(output).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_response_format_adapted_to_connection() {
+        let mut forwarded = payload();
+        adapt_response_format(&mut forwarded, &json!({})).unwrap();
+        assert_eq!(forwarded, payload());
+
+        let mut translated = payload();
+        adapt_response_format(
+            &mut translated,
+            &json!({ "structured_outputs": "json_object" }),
+        )
+        .unwrap();
+        assert_eq!(
+            translated["response_format"],
+            json!({ "type": "json_object" })
+        );
+        let instruction = translated["messages"][0]["content"].as_str().unwrap();
+        assert!(instruction.contains("\"population\""));
+
+        let unsupported = json!({ "structured_outputs": "none" });
+        assert!(matches!(
+            adapt_response_format(&mut payload(), &unsupported),
+            Err(AppError::BadRequest(_))
+        ));
+        let mut text = json!({ "response_format": { "type": "text" } });
+        adapt_response_format(&mut text, &unsupported).unwrap();
+        assert!(text.get("response_format").is_none());
+    }
+}
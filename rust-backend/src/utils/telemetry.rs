@@ -0,0 +1,142 @@
+/// Tracing subscriber setup with optional OpenTelemetry (OTLP) export
+///
+/// OTLP export is compiled only with the `otel` cargo feature and activated at runtime
+/// when `OTEL_EXPORTER_OTLP_ENDPOINT` is set. Otherwise the plain `FmtSubscriber` is used.
+use tracing::{Instrument, Level};
+use tracing_subscriber::FmtSubscriber;
+
+#[cfg(feature = "otel")]
+use once_cell::sync::OnceCell;
+
+#[cfg(feature = "otel")]
+static TRACER_PROVIDER: OnceCell<opentelemetry_sdk::trace::SdkTracerProvider> = OnceCell::new();
+
+/// Initialize the global tracing subscriber
+pub fn init_tracing(log_level: Level) -> anyhow::Result<()> {
+    #[cfg(feature = "otel")]
+    {
+        if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .map(|v| !v.trim().is_empty())
+            .unwrap_or(false)
+        {
+            return init_otel_tracing(log_level);
+        }
+    }
+
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(log_level)
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_file(true)
+        .with_line_number(true)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(())
+}
+
+#[cfg(feature = "otel")]
+fn init_otel_tracing(log_level: Level) -> anyhow::Result<()> {
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    // Endpoint, headers and protocol are read from the standard OTEL_EXPORTER_OTLP_* variables
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()?;
+
+    let service_name =
+        std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "open-webui-rust".to_string());
+
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(service_name)
+                .build(),
+        )
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(
+        opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+    );
+    opentelemetry::global::set_tracer_provider(provider.clone());
+
+    let tracer = provider.tracer("open-webui-rust");
+    let _ = TRACER_PROVIDER.set(provider);
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::from_level(
+            log_level,
+        ))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true),
+        )
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    tracing::info!("OpenTelemetry OTLP trace export enabled");
+    Ok(())
+}
+
+/// Flush pending spans before the process exits
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    {
+        if let Some(provider) = TRACER_PROVIDER.get() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to shut down OpenTelemetry tracer provider: {}", e);
+            }
+        }
+    }
+}
+
+/// Inject the current trace context (`traceparent`) into an outbound request
+pub fn inject_trace_context(builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+        impl opentelemetry::propagation::Injector for HeaderInjector<'_> {
+            fn set(&mut self, key: &str, value: String) {
+                if let (Ok(name), Ok(value)) = (
+                    reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+                    reqwest::header::HeaderValue::from_str(&value),
+                ) {
+                    self.0.insert(name, value);
+                }
+            }
+        }
+
+        let context = tracing::Span::current().context();
+        let mut headers = reqwest::header::HeaderMap::new();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+        });
+        builder.headers(headers)
+    }
+
+    #[cfg(not(feature = "otel"))]
+    {
+        builder
+    }
+}
+
+/// Send an upstream request inside a client span, propagating trace context
+pub async fn send(
+    builder: reqwest::RequestBuilder,
+    upstream: &'static str,
+) -> reqwest::Result<reqwest::Response> {
+    let span = tracing::info_span!("upstream_request", otel.kind = "client", upstream);
+
+    async move { inject_trace_context(builder).send().await }
+        .instrument(span)
+        .await
+}
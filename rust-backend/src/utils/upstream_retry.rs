@@ -0,0 +1,221 @@
+//! Bounded retry of transient upstream failures
+//!
+//! A request is retried only when it never got an answer worth passing on: a timeout,
+//! a refused connection, or a 408/429/502/503/504 status. Retries happen before the
+//! response is handed back, so a stream is never retried once its first byte could
+//! have reached the client. Every attempt is fed into the connection's circuit
+//! breaker, and retrying stops as soon as the circuit opens.
+use std::time::{Duration, Instant};
+
+use reqwest::StatusCode;
+
+use crate::config::Config;
+use crate::utils::circuit_breaker::{CircuitBreakerSettings, CircuitBreakers};
+
+/// Longest wait between two attempts, however many retries are allowed
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+const RETRYABLE_STATUSES: &[StatusCode] = &[
+    StatusCode::REQUEST_TIMEOUT,
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetrySettings {
+    /// Attempts after the first (0 = no retry)
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+}
+
+impl RetrySettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_retries: config.upstream_max_retries,
+            backoff: Duration::from_millis(config.upstream_retry_backoff_ms),
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(MAX_BACKOFF)
+    }
+}
+
+/// Whether another attempt could get a different answer
+pub fn is_retryable(result: &reqwest::Result<reqwest::Response>) -> bool {
+    match result {
+        Ok(response) => RETRYABLE_STATUSES.contains(&response.status()),
+        Err(e) => e.is_timeout() || e.is_connect(),
+    }
+}
+
+/// Feed an upstream response into the connection's circuit breaker
+///
+/// Connection errors and 5xx responses count as failures; 4xx are the caller's fault
+/// and close the circuit like any other answer from a healthy upstream.
+pub fn record_outcome(
+    breakers: &CircuitBreakers,
+    connection: &str,
+    settings: &CircuitBreakerSettings,
+    result: &reqwest::Result<reqwest::Response>,
+) {
+    match result {
+        Ok(response) if !response.status().is_server_error() => breakers.record_success(connection),
+        _ => breakers.record_failure(connection, settings, Instant::now()),
+    }
+}
+
+/// Send `builder` to `connection`, retrying transient failures
+///
+/// Returns the last attempt's outcome once it succeeds, fails for good, runs out of
+/// retries or the circuit opens. Requests whose body can't be replayed are sent once.
+pub async fn send_with_retry(
+    builder: reqwest::RequestBuilder,
+    upstream: &'static str,
+    settings: &RetrySettings,
+    breakers: &CircuitBreakers,
+    connection: &str,
+    breaker_settings: &CircuitBreakerSettings,
+) -> reqwest::Result<reqwest::Response> {
+    let mut builder = builder;
+    let mut retry = 0;
+    loop {
+        let replay = if retry < settings.max_retries {
+            builder.try_clone()
+        } else {
+            None
+        };
+
+        let result = crate::utils::telemetry::send(builder, upstream).await;
+        record_outcome(breakers, connection, breaker_settings, &result);
+        let Some(replay) = replay.filter(|_| is_retryable(&result)) else {
+            return result;
+        };
+        if let Err(retry_after) = breakers.try_acquire(connection, breaker_settings, Instant::now())
+        {
+            tracing::warn!(
+                "Not retrying {}: circuit open for another {}s",
+                connection,
+                retry_after
+            );
+            return result;
+        }
+
+        let delay = settings.delay(retry);
+        retry += 1;
+        tracing::warn!(
+            "Upstream {} failed ({}), retry {}/{} in {:?}",
+            connection,
+            match &result {
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            },
+            retry,
+            settings.max_retries,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        builder = replay;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Answers 502 to the first `failures` requests and 200 after that
+    async fn flaky_upstream(failures: usize) -> (String, Arc<AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = socket.read(&mut buf).await;
+                let response = if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    "HTTP/1.1 502 Bad Gateway\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}"
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{}/chat/completions", addr), hits)
+    }
+
+    fn settings(max_retries: u32) -> RetrySettings {
+        RetrySettings {
+            max_retries,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn breaker_settings(failure_threshold: u32) -> CircuitBreakerSettings {
+        CircuitBreakerSettings {
+            failure_threshold,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(60),
+        }
+    }
+
+    async fn send(
+        url: &str,
+        settings: &RetrySettings,
+        breakers: &CircuitBreakers,
+        breaker_settings: &CircuitBreakerSettings,
+    ) -> reqwest::Response {
+        let builder = crate::utils::http::client()
+            .post(url)
+            .json(&serde_json::json!({ "model": "gpt-4o" }));
+        send_with_retry(builder, "test", settings, breakers, url, breaker_settings)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success_or_exhausted() {
+        let breakers = CircuitBreakers::new();
+
+        // Succeeds on the second attempt
+        let (url, hits) = flaky_upstream(1).await;
+        let response = send(&url, &settings(2), &breakers, &breaker_settings(0)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Out of retries: the upstream's own error comes back
+        let (url, hits) = flaky_upstream(usize::MAX).await;
+        let response = send(&url, &settings(2), &breakers, &breaker_settings(0)).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Off by default
+        let (url, hits) = flaky_upstream(1).await;
+        let defaults = RetrySettings::from_config(&Config::from_lookup(|_| None).unwrap());
+        let response = send(&url, &defaults, &breakers, &breaker_settings(0)).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_stops_retries() {
+        let breakers = CircuitBreakers::new();
+        let (url, hits) = flaky_upstream(usize::MAX).await;
+
+        let response = send(&url, &settings(5), &breakers, &breaker_settings(2)).await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(breakers
+            .try_acquire(&url, &breaker_settings(2), Instant::now())
+            .is_err());
+    }
+}
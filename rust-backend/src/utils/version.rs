@@ -1,4 +1,3 @@
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use tracing::{error, info};
 
@@ -29,7 +28,7 @@ pub struct VersionInfo {
 /// Check for updates from GitHub releases
 #[allow(dead_code)]
 pub async fn check_for_updates(current_version: &str) -> AppResult<VersionInfo> {
-    let client = Client::builder()
+    let client = crate::utils::http::builder()
         .user_agent("open-webui-rust")
         .timeout(std::time::Duration::from_secs(10))
         .build()?;
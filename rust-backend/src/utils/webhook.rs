@@ -1,9 +1,90 @@
-use reqwest::Client;
+use governor::{clock::DefaultClock, state::keyed::DefaultKeyedStateStore, Quota, RateLimiter};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value};
+use std::num::NonZeroU32;
+use std::time::{Duration, Instant};
 use tracing::{debug, error, warn};
 
+use crate::config::Config;
 use crate::error::AppError;
+use crate::utils::misc::sha256_hash;
+
+/// Knowledge base lifecycle events
+pub const KNOWLEDGE_CREATED: &str = "knowledge.created";
+pub const KNOWLEDGE_FILE_ADDED: &str = "knowledge.file_added";
+pub const KNOWLEDGE_FILE_REMOVED: &str = "knowledge.file_removed";
+pub const KNOWLEDGE_DELETED: &str = "knowledge.deleted";
+pub const KNOWLEDGE_REINDEXED: &str = "knowledge.reindexed";
+
+/// Sent once, when the first admin account is created
+pub const INSTANCE_FIRST_RUN: &str = "instance.first_run";
+
+/// Sent when a user deletes their own account
+pub const ACCOUNT_DELETED: &str = "account.deleted";
+
+/// Authentication events for security monitoring, sent with WEBHOOK_AUTH_EVENTS
+pub const AUTH_LOGIN_SUCCESS: &str = "auth.login.success";
+pub const AUTH_LOGIN_FAILURE: &str = "auth.login.failure";
+pub const AUTH_SIGNUP: &str = "auth.signup";
+pub const AUTH_PASSWORD_CHANGED: &str = "auth.password_changed";
+/// Sign-ins for an email and address are held for the longest throttle delay
+pub const AUTH_ACCOUNT_LOCKED: &str = "auth.account_locked";
+
+/// Sample event sent by POST /api/v1/admin/webhook/test
+pub const WEBHOOK_TEST: &str = "webhook.test";
+
+/// How long a receiver has to answer
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+lazy_static::lazy_static! {
+    static ref PLACEHOLDER: regex::Regex = regex::Regex::new(r"\{\{\s*([^{}]+?)\s*\}\}").unwrap();
+}
+
+/// Body shape posted to WEBHOOK_URL, set with WEBHOOK_FORMAT
+#[derive(Debug, Clone, PartialEq)]
+pub enum WebhookFormat {
+    /// The payload as-is: `type`, `data` and `timestamp`
+    OpenWebUI,
+    /// Incoming webhook message with the event summary as `text`
+    Slack,
+    /// Webhook message with the event summary as `content`
+    Discord,
+    /// WEBHOOK_TEMPLATE, with `{{field}}` placeholders in its strings
+    Custom(Value),
+}
+
+impl WebhookFormat {
+    /// Format from WEBHOOK_FORMAT and WEBHOOK_TEMPLATE; errors name the variable
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        match config.webhook_format.trim().to_ascii_lowercase().as_str() {
+            "openwebui" => Ok(Self::OpenWebUI),
+            "slack" => Ok(Self::Slack),
+            "discord" => Ok(Self::Discord),
+            "custom" => {
+                let template = config
+                    .webhook_template
+                    .as_deref()
+                    .filter(|template| !template.trim().is_empty())
+                    .ok_or("WEBHOOK_TEMPLATE is required with WEBHOOK_FORMAT=custom")?;
+                serde_json::from_str(template)
+                    .map(Self::Custom)
+                    .map_err(|e| format!("Invalid WEBHOOK_TEMPLATE: {}", e))
+            }
+            other => Err(format!(
+                "Invalid WEBHOOK_FORMAT '{}': expected openwebui, slack, discord or custom",
+                other
+            )),
+        }
+    }
+
+    /// The configured format, falling back to the default if the settings don't parse
+    pub fn configured(config: &Config) -> Self {
+        Self::from_config(config).unwrap_or_else(|e| {
+            warn!("{}; posting webhooks in the default format", e);
+            Self::OpenWebUI
+        })
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebhookPayload {
@@ -66,11 +147,313 @@ impl WebhookPayload {
             }),
         )
     }
+
+    pub fn first_run(admin_id: &str, email: &str, webui_url: &str) -> Self {
+        Self::new(
+            INSTANCE_FIRST_RUN,
+            json!({
+                "admin_id": admin_id,
+                "email": email,
+                "webui_url": webui_url,
+            }),
+        )
+    }
+
+    pub fn knowledge_event(
+        event_type: &str,
+        knowledge_id: &str,
+        actor_id: &str,
+        file_ids: &[String],
+    ) -> Self {
+        Self::new(
+            event_type,
+            json!({
+                "knowledge_id": knowledge_id,
+                "actor_id": actor_id,
+                "file_ids": file_ids,
+            }),
+        )
+    }
+
+    /// Authentication event for a known user
+    pub fn auth_user_event(event_type: &str, user_id: &str, ip: Option<&str>) -> Self {
+        Self::new(
+            event_type,
+            json!({
+                "user_id": user_id,
+                "ip": ip,
+            }),
+        )
+    }
+
+    /// Authentication event for an attempt that didn't identify a user
+    ///
+    /// Only a hash of the attempted email is sent, so receivers can correlate attempts
+    /// without collecting the addresses people typed.
+    pub fn test_event(admin_id: &str) -> Self {
+        Self::new(WEBHOOK_TEST, json!({ "user_id": admin_id }))
+    }
+
+    pub fn auth_attempt_event(event_type: &str, email: &str, ip: Option<&str>) -> Self {
+        Self::new(
+            event_type,
+            json!({
+                "email_hash": sha256_hash(&email.trim().to_lowercase()),
+                "ip": ip,
+            }),
+        )
+    }
+
+    fn data_str(&self, key: &str) -> Option<&str> {
+        self.data.get(key).and_then(Value::as_str)
+    }
+
+    /// One-line, human readable description of the event, for chat receivers
+    pub fn message(&self) -> String {
+        let user = || {
+            let name = self
+                .data_str("username")
+                .or_else(|| self.data_str("name"))
+                .unwrap_or("unknown");
+            match self.data_str("email") {
+                Some(email) if email != name => format!("{} ({})", name, email),
+                _ => name.to_string(),
+            }
+        };
+        let knowledge = || self.data_str("knowledge_id").unwrap_or("unknown");
+        let client = || self.data_str("ip").unwrap_or("an unknown address");
+        let files = || {
+            let count = self
+                .data
+                .get("file_ids")
+                .and_then(Value::as_array)
+                .map_or(0, Vec::len);
+            format!("{} file{}", count, if count == 1 { "" } else { "s" })
+        };
+
+        match self.event_type.as_str() {
+            "user.signup" | "oauth.user.signup" => format!("New user signed up: {}", user()),
+            "user.signin" => format!("User signed in: {}", user()),
+            "chat.created" => format!(
+                "New chat created: {}",
+                self.data_str("title").unwrap_or("Untitled")
+            ),
+            "message.created" => format!(
+                "New message in chat {}",
+                self.data_str("chat_id").unwrap_or("unknown")
+            ),
+            INSTANCE_FIRST_RUN => format!(
+                "Instance set up at {} with first admin {}",
+                self.data_str("webui_url").unwrap_or("unknown URL"),
+                self.data_str("email").unwrap_or("unknown")
+            ),
+            KNOWLEDGE_CREATED => format!("Knowledge base {} created", knowledge()),
+            KNOWLEDGE_FILE_ADDED => format!("{} added to knowledge base {}", files(), knowledge()),
+            KNOWLEDGE_FILE_REMOVED => {
+                format!("{} removed from knowledge base {}", files(), knowledge())
+            }
+            KNOWLEDGE_DELETED => format!("Knowledge base {} deleted", knowledge()),
+            KNOWLEDGE_REINDEXED => format!("Knowledge base {} reindexed", knowledge()),
+            ACCOUNT_DELETED => format!("User deleted their account: {}", user()),
+            AUTH_LOGIN_SUCCESS | AUTH_SIGNUP | AUTH_PASSWORD_CHANGED => format!(
+                "{} for user {} from {}",
+                self.event_type,
+                self.data_str("user_id").unwrap_or("unknown"),
+                client()
+            ),
+            AUTH_LOGIN_FAILURE | AUTH_ACCOUNT_LOCKED => {
+                format!("{} from {}", self.event_type, client())
+            }
+            WEBHOOK_TEST => "Test event from Open WebUI: webhook delivery works".to_string(),
+            other => format!("Event: {}", other),
+        }
+    }
+
+    /// Value for a template placeholder
+    ///
+    /// `type`, `timestamp` and `message` describe the event; anything else is a path into
+    /// its data, with or without a leading `data.`.
+    fn field(&self, path: &str) -> Option<Value> {
+        match path {
+            "type" => return Some(Value::String(self.event_type.clone())),
+            "timestamp" => return self.timestamp.map(Value::from),
+            "message" => return Some(Value::String(self.message())),
+            _ => {}
+        }
+        path.strip_prefix("data.")
+            .unwrap_or(path)
+            .split('.')
+            .try_fold(&self.data, |value, key| match value {
+                Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => value.get(key),
+            })
+            .cloned()
+    }
+
+    /// Substitute placeholders in the strings of a template
+    ///
+    /// A string that is only a placeholder takes the field's JSON value, so numbers and
+    /// lists keep their type; inside longer strings fields are spliced in as text.
+    /// Unknown fields become null or an empty string respectively.
+    fn render_template(&self, template: &Value) -> Value {
+        match template {
+            Value::String(text) => {
+                if let Some(caps) = PLACEHOLDER.captures(text.trim()) {
+                    if caps[0].len() == text.trim().len() {
+                        return self.field(&caps[1]).unwrap_or(Value::Null);
+                    }
+                }
+                let rendered = PLACEHOLDER.replace_all(text, |caps: &regex::Captures| {
+                    match self.field(&caps[1]) {
+                        Some(Value::String(s)) => s,
+                        Some(Value::Null) | None => String::new(),
+                        Some(value) => value.to_string(),
+                    }
+                });
+                Value::String(rendered.into_owned())
+            }
+            Value::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| self.render_template(item))
+                    .collect(),
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), self.render_template(value)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Request body for a receiver expecting `format`
+    pub fn render(&self, format: &WebhookFormat) -> Value {
+        match format {
+            WebhookFormat::OpenWebUI => json!(self),
+            WebhookFormat::Slack => json!({ "text": self.message() }),
+            WebhookFormat::Discord => json!({ "content": self.message() }),
+            WebhookFormat::Custom(template) => self.render_template(template),
+        }
+    }
+}
+
+/// Whether `event_type` passes the WEBHOOK_EVENTS filter
+///
+/// An empty filter allows everything; `family.*` matches every event in a family.
+pub fn event_enabled(filter: &[String], event_type: &str) -> bool {
+    filter.is_empty()
+        || filter
+            .iter()
+            .any(|pattern| match pattern.strip_suffix(".*") {
+                Some(family) => event_type
+                    .strip_prefix(family)
+                    .is_some_and(|rest| rest.starts_with('.')),
+                None => pattern == event_type,
+            })
+}
+
+/// Post an event to WEBHOOK_URL in the background
+///
+/// Delivery runs on its own task so a slow or failing receiver never delays or fails
+/// the operation that raised the event.
+pub fn dispatch_event(config: &Config, payload: WebhookPayload) {
+    let Some(url) = config.webhook_url.clone().filter(|url| !url.is_empty()) else {
+        return;
+    };
+    if !event_enabled(&config.webhook_events, &payload.event_type) {
+        debug!("Webhook event {} filtered out", payload.event_type);
+        return;
+    }
+    let format = WebhookFormat::configured(config);
+
+    tokio::spawn(async move {
+        let _ = post_webhook(&url, &format, payload).await;
+    });
+}
+
+/// Post an authentication event to WEBHOOK_URL, if WEBHOOK_AUTH_EVENTS is on
+pub fn dispatch_auth_event(config: &Config, payload: WebhookPayload) {
+    if config.webhook_auth_events {
+        dispatch_event(config, payload);
+    }
+}
+
+/// Outcome of posting one webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryReport {
+    pub delivered: bool,
+    /// Status the receiver answered with, if it answered
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Post `payload` to `webhook_url` with the body shaped for `format`, reporting the outcome
+pub async fn deliver(
+    webhook_url: &str,
+    format: &WebhookFormat,
+    payload: &WebhookPayload,
+) -> DeliveryReport {
+    let started = Instant::now();
+    let result = match crate::utils::http::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client
+            .post(webhook_url)
+            .json(&payload.render(format))
+            .send()
+            .await
+            .map_err(|e| delivery_error(webhook_url, &e)),
+        Err(e) => Err(format!("Failed to create HTTP client: {}", e)),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            DeliveryReport {
+                delivered: status.is_success(),
+                status: Some(status.as_u16()),
+                latency_ms,
+                error: (!status.is_success())
+                    .then(|| format!("Receiver answered with status {}", status)),
+            }
+        }
+        Err(error) => DeliveryReport {
+            delivered: false,
+            status: None,
+            latency_ms,
+            error: Some(error),
+        },
+    }
+}
+
+fn delivery_error(webhook_url: &str, e: &reqwest::Error) -> String {
+    if e.is_timeout() {
+        format!(
+            "No answer from {} within {}s",
+            webhook_url,
+            DELIVERY_TIMEOUT.as_secs()
+        )
+    } else if e.is_connect() {
+        format!("Could not connect to {}: {}", webhook_url, e)
+    } else {
+        format!("Failed to post to {}: {}", webhook_url, e)
+    }
 }
 
-/// Post webhook to configured URL
+/// Post webhook to configured URL, with the body shaped for `format`
+///
+/// Delivery failures are logged, never returned, so they can't fail the caller.
 #[allow(dead_code)]
-pub async fn post_webhook(webhook_url: &str, payload: WebhookPayload) -> Result<(), AppError> {
+pub async fn post_webhook(
+    webhook_url: &str,
+    format: &WebhookFormat,
+    payload: WebhookPayload,
+) -> Result<(), AppError> {
     if webhook_url.is_empty() {
         debug!("Webhook URL is empty, skipping webhook post");
         return Ok(());
@@ -81,30 +464,64 @@ pub async fn post_webhook(webhook_url: &str, payload: WebhookPayload) -> Result<
         webhook_url, payload
     );
 
-    let client = Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .build()
-        .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+    let report = deliver(webhook_url, format, &payload).await;
+    match (report.status, report.error) {
+        (_, None) => debug!("Webhook posted successfully in {}ms", report.latency_ms),
+        (Some(status), Some(_)) => {
+            warn!("Webhook post returned non-success status: {}", status)
+        }
+        (None, Some(e)) => error!("Failed to post webhook: {}", e),
+    }
+    Ok(())
+}
 
-    match client.post(webhook_url).json(&payload).send().await {
-        Ok(response) => {
-            if response.status().is_success() {
-                debug!("Webhook posted successfully: {}", response.status());
-                Ok(())
-            } else {
-                warn!(
-                    "Webhook post returned non-success status: {}",
-                    response.status()
-                );
-                // Don't fail the request if webhook fails
-                Ok(())
-            }
+type WebhookTestLimiter = RateLimiter<String, DefaultKeyedStateStore<String>, DefaultClock>;
+
+/// Sample deliveries admins send to check a webhook, rate-limited per admin
+pub struct WebhookTester {
+    // Tests per admin per minute; None when testing is disabled
+    limiter: Option<WebhookTestLimiter>,
+}
+
+impl WebhookTester {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            limiter: NonZeroU32::new(config.webhook_test_rate_limit_per_minute)
+                .map(|limit| RateLimiter::keyed(Quota::per_minute(limit))),
         }
-        Err(e) => {
-            error!("Failed to post webhook: {}", e);
-            // Don't fail the request if webhook fails
-            Ok(())
+    }
+
+    /// Send a sample event to `url`, or WEBHOOK_URL when none is given
+    pub async fn run(
+        &self,
+        config: &Config,
+        admin_id: &str,
+        url: Option<&str>,
+    ) -> Result<DeliveryReport, AppError> {
+        let Some(limiter) = &self.limiter else {
+            return Err(AppError::Forbidden(
+                "Webhook testing is disabled".to_string(),
+            ));
+        };
+        let url = url
+            .or(config.webhook_url.as_deref())
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| AppError::BadRequest("No webhook URL configured".to_string()))?;
+        if !reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid webhook URL '{}': expected an http(s) URL",
+                url
+            )));
         }
+        if limiter.check_key(&admin_id.to_string()).is_err() {
+            return Err(AppError::TooManyRequests(
+                "Too many webhook tests, retry later".to_string(),
+            ));
+        }
+
+        let format = WebhookFormat::configured(config);
+        Ok(deliver(url, &format, &WebhookPayload::test_event(admin_id)).await)
     }
 }
 
@@ -126,7 +543,8 @@ pub async fn post_user_webhook(
         data_obj.insert("user_id".to_string(), json!(user_id));
     }
 
-    post_webhook(webhook_url, enriched_payload).await
+    // Users point these at their own receivers, which get the payload as-is
+    post_webhook(webhook_url, &WebhookFormat::OpenWebUI, enriched_payload).await
 }
 
 #[cfg(test)]
@@ -152,4 +570,207 @@ mod tests {
         assert_eq!(payload.data["user_id"], "user456");
         assert_eq!(payload.data["title"], "Test Chat");
     }
+
+    #[test]
+    fn test_knowledge_event_payload() {
+        let payload = WebhookPayload::knowledge_event(
+            KNOWLEDGE_FILE_ADDED,
+            "kb1",
+            "user456",
+            &["file1".to_string()],
+        );
+
+        assert_eq!(payload.event_type, "knowledge.file_added");
+        assert_eq!(payload.data["knowledge_id"], "kb1");
+        assert_eq!(payload.data["actor_id"], "user456");
+        assert_eq!(payload.data["file_ids"], json!(["file1"]));
+    }
+
+    #[test]
+    fn test_first_run_payload() {
+        let payload = WebhookPayload::first_run("admin1", "admin@example.com", "https://chat");
+
+        assert_eq!(payload.event_type, INSTANCE_FIRST_RUN);
+        assert_eq!(payload.data["admin_id"], "admin1");
+        assert_eq!(payload.data["webui_url"], "https://chat");
+    }
+
+    #[test]
+    fn test_event_filter() {
+        assert!(event_enabled(&[], KNOWLEDGE_CREATED));
+
+        let filter = vec!["knowledge.*".to_string(), "user.signup".to_string()];
+        assert!(event_enabled(&filter, KNOWLEDGE_DELETED));
+        assert!(event_enabled(&filter, "user.signup"));
+        assert!(!event_enabled(&filter, "user.signin"));
+        assert!(!event_enabled(&filter, "knowledgebase.created"));
+    }
+
+    fn format(name: &str, template: Option<&str>) -> Result<WebhookFormat, String> {
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.webhook_format = name.to_string();
+        config.webhook_template = template.map(str::to_string);
+        WebhookFormat::from_config(&config)
+    }
+
+    #[test]
+    fn test_signup_rendered_for_slack_and_discord() {
+        let payload = WebhookPayload::user_signup("testuser", Some("test@example.com"));
+
+        assert_eq!(
+            payload.render(&format("slack", None).unwrap()),
+            json!({"text": "New user signed up: testuser (test@example.com)"})
+        );
+        assert_eq!(
+            payload.render(&format("Discord", None).unwrap()),
+            json!({"content": "New user signed up: testuser (test@example.com)"})
+        );
+
+        // The default keeps the original shape
+        let body = payload.render(&format("openwebui", None).unwrap());
+        assert_eq!(body["type"], "user.signup");
+        assert_eq!(body["data"]["username"], "testuser");
+    }
+
+    #[test]
+    fn test_knowledge_event_message() {
+        let payload = WebhookPayload::knowledge_event(
+            KNOWLEDGE_FILE_ADDED,
+            "kb1",
+            "user456",
+            &["file1".to_string(), "file2".to_string()],
+        );
+        assert_eq!(payload.message(), "2 files added to knowledge base kb1");
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let template = r#"{"event": "{{type}}", "who": "{{ username }} <{{data.email}}>",
+            "files": "{{file_ids}}", "missing": "{{nope}}", "fixed": 1}"#;
+        let payload = WebhookPayload::user_signup("testuser", Some("test@example.com"));
+        let body = payload.render(&format("custom", Some(template)).unwrap());
+
+        assert_eq!(
+            body,
+            json!({
+                "event": "user.signup",
+                "who": "testuser <test@example.com>",
+                "files": null,
+                "missing": null,
+                "fixed": 1
+            })
+        );
+
+        let payload =
+            WebhookPayload::knowledge_event(KNOWLEDGE_CREATED, "kb1", "u1", &["f1".to_string()]);
+        let body = payload.render(&format("custom", Some(template)).unwrap());
+        assert_eq!(body["files"], json!(["f1"]));
+    }
+
+    #[test]
+    fn test_invalid_format_rejected() {
+        assert!(format("teams", None).is_err());
+        assert!(format("custom", None).is_err());
+        assert!(format("custom", Some("{not json")).is_err());
+    }
+
+    /// URL of a receiver that answers 200 and forwards each request body
+    async fn capturing_receiver() -> (String, tokio::sync::mpsc::UnboundedReceiver<Value>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buf = [0u8; 4096];
+                // Read until the whole JSON body has arrived
+                let body = loop {
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    if n == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some((_, body)) = text.split_once("\r\n\r\n") {
+                        if let Ok(body) = serde_json::from_str::<Value>(body) {
+                            break Some(body);
+                        }
+                    }
+                };
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .await;
+                if let Some(body) = body {
+                    let _ = tx.send(body);
+                }
+            }
+        });
+        (format!("http://{}/hook", addr), rx)
+    }
+
+    #[tokio::test]
+    async fn test_failed_login_fires_event_with_hashed_email() {
+        let (url, mut received) = capturing_receiver().await;
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.webhook_url = Some(url);
+
+        // Off by default
+        let failure = || {
+            WebhookPayload::auth_attempt_event(
+                AUTH_LOGIN_FAILURE,
+                " Alice@Example.com",
+                Some("203.0.113.7"),
+            )
+        };
+        dispatch_auth_event(&config, failure());
+
+        config.webhook_auth_events = true;
+        dispatch_auth_event(&config, failure());
+        let body = tokio::time::timeout(std::time::Duration::from_secs(5), received.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(body["type"], AUTH_LOGIN_FAILURE);
+        assert_eq!(body["data"]["email_hash"], sha256_hash("alice@example.com"));
+        assert_eq!(body["data"]["ip"], "203.0.113.7");
+        assert!(body["timestamp"].is_i64());
+        assert!(!body.to_string().to_lowercase().contains("alice"));
+        // Only the event sent with WEBHOOK_AUTH_EVENTS on arrived
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_test_reports_delivery() {
+        let (url, mut received) = capturing_receiver().await;
+        let mut config = Config::from_lookup(|_| None).unwrap();
+        config.webhook_url = Some(url);
+        config.webhook_test_rate_limit_per_minute = 2;
+        let tester = WebhookTester::from_config(&config);
+
+        let report = tester.run(&config, "admin-1", None).await.unwrap();
+        assert!(report.delivered, "{:?}", report.error);
+        assert_eq!(report.status, Some(200));
+        assert!(report.error.is_none());
+        let body = received.recv().await.unwrap();
+        assert_eq!(body["type"], WEBHOOK_TEST);
+
+        // A port nothing listens on
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/hook", listener.local_addr().unwrap())
+        };
+        let report = tester.run(&config, "admin-1", Some(&closed)).await.unwrap();
+        assert!(!report.delivered);
+        assert_eq!(report.status, None);
+        let error = report.error.unwrap();
+        assert!(error.starts_with("Could not connect to"), "{}", error);
+
+        assert!(matches!(
+            tester.run(&config, "admin-1", None).await,
+            Err(AppError::TooManyRequests(_))
+        ));
+    }
 }
@@ -1,25 +1,150 @@
-use actix_web::{web, Error, HttpRequest, HttpResponse};
-use actix_ws::Message as WsMessage;
+use actix_web::{http::header, web, Error, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message as WsMessage};
 use futures::stream::StreamExt;
-
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, AppResult};
+use crate::middleware::authenticate_token;
+use crate::models::User;
+use crate::retrieval::chunking::count_tokens_approx;
+use crate::services::usage::{self, CompletionLog, StreamUsageTracker};
+use crate::utils::auth::extract_bearer_token;
 use crate::AppState;
 
+/// Subprotocol marker a browser client sends alongside its token (`["bearer", token]`)
+const BEARER_PROTOCOL: &str = "bearer";
+
+/// How often the server pings, and how long a silent client is kept
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// Extract the auth token from a WebSocket upgrade request
+///
+/// Browsers can't set headers on upgrades, so besides the `Authorization` header and `token`
+/// cookie the token may come from the `token` query parameter or `Sec-WebSocket-Protocol`.
+pub(crate) fn upgrade_token(req: &HttpRequest) -> AppResult<(String, bool)> {
+    if let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(extract_bearer_token)
+    {
+        return Ok((token, false));
+    }
+
+    if let Some(token) = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|protocols| {
+            let mut protocols = protocols.split(',').map(str::trim);
+            protocols.find(|p| p.eq_ignore_ascii_case(BEARER_PROTOCOL))?;
+            protocols.next().filter(|t| !t.is_empty()).map(String::from)
+        })
+    {
+        return Ok((token, true));
+    }
+
+    if let Ok(query) = web::Query::<HashMap<String, String>>::from_query(req.query_string()) {
+        if let Some(token) = query.get("token").filter(|t| !t.is_empty()) {
+            return Ok((token.clone(), false));
+        }
+    }
+
+    if let Some(cookie) = req.cookie("token") {
+        return Ok((cookie.value().to_string(), false));
+    }
+
+    Err(AppError::Unauthorized("Not authenticated".to_string()))
+}
+
 /// WebSocket handler for real-time chat streaming
+///
+/// The upgrade is rejected with 401 unless it carries a valid token, and the socket is
+/// closed once a JWT expires. Upgrades past the WebSocket connection limits are closed
+/// with a policy-violation code.
 pub async fn websocket_chat_handler(
     req: HttpRequest,
     stream: web::Payload,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+    let (token, via_protocol) = upgrade_token(&req)?;
+    let (auth_user, expires_at) = authenticate_token(&state, &token).await?;
+    // Chatting writes to the user's history, which impersonation sessions may not do
+    if auth_user.impersonated_by.is_some() {
+        return Err(AppError::Forbidden(
+            "Write actions are not allowed while impersonating a user".to_string(),
+        )
+        .into());
+    }
+    let user = auth_user.user;
+
+    let connection = match &state.socket_state {
+        Some(socket_state) => match socket_state.connections.try_acquire(Some(&user.id)) {
+            Ok(guard) => Some(guard),
+            Err(limit) => return crate::socket::reject_upgrade(&req, stream, limit),
+        },
+        None => None,
+    };
 
-    tracing::info!("WebSocket connection established");
+    let (mut response, mut session, mut msg_stream) = actix_ws::handle(&req, stream)?;
+
+    // Browsers fail the handshake unless the server echoes a subprotocol they offered
+    if via_protocol {
+        response.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            header::HeaderValue::from_static(BEARER_PROTOCOL),
+        );
+    }
+
+    tracing::info!("WebSocket connection established for user {}", user.id);
 
     // Clone state for spawned task
     let state_clone = state.clone();
 
     // Spawn task to handle WebSocket messages
     actix_web::rt::spawn(async move {
-        while let Some(Ok(msg)) = msg_stream.next().await {
+        let _connection = connection;
+        let expiry = token_expiry(expires_at);
+        tokio::pin!(expiry);
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut last_seen = Instant::now();
+
+        loop {
+            let msg = tokio::select! {
+                msg = msg_stream.next() => match msg {
+                    Some(Ok(msg)) => {
+                        last_seen = Instant::now();
+                        msg
+                    }
+                    _ => break,
+                },
+                _ = heartbeat.tick() => {
+                    // Sockets that drop without a close frame would otherwise hold their
+                    // connection slot forever
+                    if last_seen.elapsed() > HEARTBEAT_TIMEOUT {
+                        tracing::info!("WebSocket heartbeat timed out for user {}", user.id);
+                        let _ = session.close(None).await;
+                        break;
+                    }
+                    if session.ping(b"").await.is_err() {
+                        break;
+                    }
+                    continue;
+                },
+                _ = &mut expiry => {
+                    tracing::info!("WebSocket token expired for user {}", user.id);
+                    let _ = session
+                        .close(Some(CloseReason {
+                            code: CloseCode::Policy,
+                            description: Some("Token expired".to_string()),
+                        }))
+                        .await;
+                    return;
+                }
+            };
+
             match msg {
                 WsMessage::Text(text) => {
                     tracing::debug!("Received WebSocket text: {}", text);
@@ -29,7 +154,8 @@ pub async fn websocket_chat_handler(
                         Ok(payload) => {
                             // Process chat completion in real-time
                             if let Err(e) =
-                                process_chat_stream(&state_clone, payload, &mut session).await
+                                process_chat_stream(&state_clone, &user, payload, &mut session)
+                                    .await
                             {
                                 tracing::error!("Error processing chat: {}", e);
                                 let error_msg = serde_json::json!({
@@ -67,9 +193,21 @@ pub async fn websocket_chat_handler(
     Ok(response)
 }
 
+/// Resolves when the token expires; never resolves for tokens without an expiry
+async fn token_expiry(expires_at: Option<i64>) {
+    match expires_at {
+        Some(exp) => {
+            let remaining = (exp - chrono::Utc::now().timestamp()).max(0) as u64;
+            tokio::time::sleep(std::time::Duration::from_secs(remaining)).await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
 /// Process chat completion and stream results in real-time
 async fn process_chat_stream(
     state: &web::Data<AppState>,
+    user: &User,
     payload: serde_json::Value,
     session: &mut actix_ws::Session,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -87,6 +225,14 @@ async fn process_chat_stream(
         .and_then(|v| v.as_str())
         .map(String::from);
 
+    // Only the chat's owner may stream into it
+    if let Some(ch_id) = &chat_id {
+        ChatService::new(&state.db)
+            .get_chat_by_id_and_user_id(ch_id, &user.id)
+            .await?
+            .ok_or("Chat not found")?;
+    }
+
     // Extract model
     let model_id = payload
         .get("model")
@@ -106,7 +252,7 @@ async fn process_chat_stream(
     };
 
     // Make request to OpenAI API with streaming - ZERO BUFFERING
-    let client = reqwest::Client::builder()
+    let client = crate::utils::http::builder()
         .tcp_nodelay(true) // Disable Nagle's algorithm for real-time streaming
         .timeout(std::time::Duration::from_secs(300)) // 5 min timeout
         .http2_keep_alive_interval(Some(std::time::Duration::from_secs(5)))
@@ -142,6 +288,14 @@ async fn process_chat_stream(
         .and_then(|v| v.as_bool())
         .unwrap_or(false);
 
+    // Usage is recorded for both reply styles, the same way as the HTTP endpoint
+    let messages = payload
+        .get("messages")
+        .and_then(|m| m.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let completion_log = CompletionLog::for_request(&state.config.read().unwrap(), &payload);
+
     if is_stream {
         // REAL-TIME STREAMING - ZERO BUFFERING APPROACH
         // Use chunk_completion_stream to get smallest possible chunks
@@ -149,11 +303,22 @@ async fn process_chat_stream(
         let mut accumulated_content = String::new();
         let mut buffer = String::new();
 
+        // Recorded once the tracker is dropped at the end of the stream
+        let mut usage_tracker = StreamUsageTracker::new(
+            state.clone(),
+            user.id.clone(),
+            model_id.clone(),
+            &messages,
+            completion_log,
+        );
+
         tracing::info!("🔴 LIVE STREAMING STARTED - forwarding chunks in real-time");
 
         while let Some(chunk_result) = stream.next().await {
             match chunk_result {
                 Ok(chunk) => {
+                    usage_tracker.observe_bytes(&chunk);
+
                     // Convert bytes to text
                     if let Ok(text) = std::str::from_utf8(&chunk) {
                         tracing::debug!("⚡ Received chunk: {} bytes", text.len());
@@ -317,8 +482,64 @@ async fn process_chat_stream(
     } else {
         // Non-streaming response
         let json_response = response.json::<serde_json::Value>().await?;
+        let completion_text = json_response
+            .pointer("/choices/0/message/content")
+            .and_then(|c| c.as_str())
+            .unwrap_or("")
+            .to_string();
+        usage::spawn_record(
+            state.clone(),
+            user.id.clone(),
+            model_id,
+            usage::extract_usage(&json_response),
+            move || {
+                (
+                    usage::estimate_prompt_tokens(&messages),
+                    count_tokens_approx(&completion_text) as i64,
+                )
+            },
+            completion_log,
+        );
         session.text(serde_json::to_string(&json_response)?).await?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn upgrade_request() -> TestRequest {
+        TestRequest::get()
+            .uri("/api/ws/chat")
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::CONNECTION, "upgrade"))
+            .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+            .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+    }
+
+    #[test]
+    fn test_unauthenticated_upgrade_rejected() {
+        let req = upgrade_request().to_http_request();
+        let err = upgrade_token(&req).unwrap_err();
+        assert_eq!(
+            actix_web::ResponseError::status_code(&err),
+            actix_web::http::StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn test_upgrade_token_sources() {
+        let req = upgrade_request()
+            .uri("/api/ws/chat?token=abc")
+            .to_http_request();
+        assert_eq!(upgrade_token(&req).unwrap(), ("abc".to_string(), false));
+
+        let req = upgrade_request()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "bearer, xyz"))
+            .to_http_request();
+        assert_eq!(upgrade_token(&req).unwrap(), ("xyz".to_string(), true));
+    }
+}